@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "short_url" TEXT;
@@ -0,0 +1,8 @@
+-- CreateTable
+CREATE TABLE "global_settings" (
+    "key" TEXT NOT NULL,
+    "value" TEXT,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "global_settings_pkey" PRIMARY KEY ("key")
+);
@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "provider_errors" (
+    "id" TEXT NOT NULL,
+    "item_id" TEXT,
+    "endpoint" TEXT NOT NULL,
+    "http_status" INTEGER,
+    "message" TEXT NOT NULL,
+    "response_body" TEXT,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "provider_errors_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "provider_errors_item_id_idx" ON "provider_errors"("item_id");
+
+-- CreateIndex
+CREATE INDEX "provider_errors_created_at_idx" ON "provider_errors"("created_at");
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "locked" BOOLEAN NOT NULL DEFAULT false;
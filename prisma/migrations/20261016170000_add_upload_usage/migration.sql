@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "upload_usage" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "item_id" TEXT,
+    "provider" TEXT NOT NULL,
+    "bytes" BIGINT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "upload_usage_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "upload_usage_user_id_provider_created_at_idx" ON "upload_usage"("user_id", "provider", "created_at");
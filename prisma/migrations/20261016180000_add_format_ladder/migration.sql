@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "format_rung" INTEGER,
+ADD COLUMN "format_used" TEXT;
@@ -0,0 +1,15 @@
+-- CreateTable
+CREATE TABLE "provenance" (
+    "item_id" TEXT NOT NULL,
+    "webpage_url" TEXT,
+    "extractor" TEXT,
+    "video_id" TEXT,
+    "captured_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "ytdlp_version" TEXT,
+    "format" TEXT,
+    "file_name" TEXT,
+    "file_size" BIGINT,
+    "sha256" TEXT,
+
+    CONSTRAINT "provenance_pkey" PRIMARY KEY ("item_id")
+);
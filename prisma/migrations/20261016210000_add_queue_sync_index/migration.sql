@@ -0,0 +1,2 @@
+-- CreateIndex
+CREATE INDEX "queue_user_id_updated_at_id_idx" ON "queue"("user_id", "updated_at", "id");
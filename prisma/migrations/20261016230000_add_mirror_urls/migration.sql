@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "mirror_urls" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[],
+ADD COLUMN "source_index" INTEGER;
@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "priority" INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN "failure_count" INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "language" TEXT,
+ADD COLUMN "caption_languages" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
+
+-- CreateIndex
+CREATE INDEX "queue_language_idx" ON "queue"("language");
@@ -0,0 +1,14 @@
+-- CreateTable
+CREATE TABLE "bandwidth_usage" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "item_id" TEXT,
+    "direction" TEXT NOT NULL,
+    "bytes" BIGINT NOT NULL,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "bandwidth_usage_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "bandwidth_usage_user_id_created_at_idx" ON "bandwidth_usage"("user_id", "created_at");
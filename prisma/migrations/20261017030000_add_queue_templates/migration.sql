@@ -0,0 +1,26 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "format_override" TEXT;
+ALTER TABLE "queue" ADD COLUMN "tags" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
+ALTER TABLE "queue" ADD COLUMN "template_id" TEXT;
+
+-- CreateTable
+CREATE TABLE "queue_templates" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "name" TEXT NOT NULL,
+    "url_pattern" TEXT NOT NULL,
+    "format" TEXT,
+    "upload_target" TEXT,
+    "tags" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[],
+    "download_sections" TEXT,
+    "priority" INTEGER NOT NULL DEFAULT 0,
+    "schedule" TEXT,
+    "enabled" BOOLEAN NOT NULL DEFAULT true,
+    "last_run_at" TIMESTAMPTZ,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "queue_templates_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "queue_templates_user_id_idx" ON "queue_templates"("user_id");
@@ -0,0 +1,13 @@
+-- CreateTable
+CREATE TABLE "item_events" (
+    "id" TEXT NOT NULL,
+    "item_id" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "message" TEXT,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "item_events_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "item_events_item_id_created_at_idx" ON "item_events"("item_id", "created_at");
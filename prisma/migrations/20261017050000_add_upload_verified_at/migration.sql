@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "upload_verified_at" TIMESTAMPTZ;
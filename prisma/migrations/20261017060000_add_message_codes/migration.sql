@@ -0,0 +1,7 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "message_code" TEXT,
+ADD COLUMN "message_params" TEXT;
+
+-- AlterTable
+ALTER TABLE "item_events" ADD COLUMN "message_code" TEXT,
+ADD COLUMN "message_params" TEXT;
@@ -0,0 +1,18 @@
+-- CreateTable
+CREATE TABLE "hook_runs" (
+    "id" TEXT NOT NULL,
+    "item_id" TEXT NOT NULL,
+    "event" TEXT NOT NULL,
+    "command" TEXT NOT NULL,
+    "exit_code" INTEGER,
+    "stdout" TEXT NOT NULL DEFAULT '',
+    "stderr" TEXT NOT NULL DEFAULT '',
+    "error" TEXT,
+    "started_at" TIMESTAMPTZ NOT NULL,
+    "finished_at" TIMESTAMPTZ NOT NULL,
+
+    CONSTRAINT "hook_runs_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE INDEX "hook_runs_item_id_started_at_idx" ON "hook_runs"("item_id", "started_at");
@@ -0,0 +1,21 @@
+-- CreateTable
+CREATE TABLE "playlists" (
+    "id" TEXT NOT NULL,
+    "user_id" TEXT NOT NULL,
+    "url" TEXT NOT NULL,
+    "title" TEXT,
+    "source_id" TEXT,
+    "entry_count" INTEGER NOT NULL DEFAULT 0,
+    "added_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "playlists_pkey" PRIMARY KEY ("id")
+);
+
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "playlist_id" TEXT;
+
+-- CreateIndex
+CREATE INDEX "playlists_user_id_added_at_idx" ON "playlists"("user_id", "added_at");
+
+-- CreateIndex
+CREATE INDEX "queue_playlist_id_idx" ON "queue"("playlist_id");
@@ -0,0 +1,11 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "video_key" TEXT;
+
+-- CreateIndex
+CREATE INDEX "queue_user_id_status_added_at_idx" ON "queue"("user_id", "status", "added_at");
+
+-- CreateIndex
+CREATE INDEX "queue_status_updated_at_idx" ON "queue"("status", "updated_at");
+
+-- CreateIndex
+CREATE UNIQUE INDEX "queue_video_key_key" ON "queue"("video_key");
@@ -0,0 +1,2 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "subtitle_paths" TEXT[] NOT NULL DEFAULT ARRAY[]::TEXT[];
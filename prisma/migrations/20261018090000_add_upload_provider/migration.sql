@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "upload_provider" TEXT,
+ADD COLUMN "upload_url" TEXT;
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "collection" TEXT;
+
+-- CreateIndex
+CREATE INDEX "queue_user_id_collection_idx" ON "queue"("user_id", "collection");
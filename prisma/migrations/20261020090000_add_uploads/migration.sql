@@ -0,0 +1,16 @@
+-- CreateTable
+CREATE TABLE "uploads" (
+    "id" TEXT NOT NULL,
+    "item_id" TEXT NOT NULL,
+    "target" TEXT NOT NULL,
+    "status" TEXT NOT NULL,
+    "url" TEXT,
+    "error" TEXT,
+    "created_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    "updated_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "uploads_pkey" PRIMARY KEY ("id")
+);
+
+-- CreateIndex
+CREATE UNIQUE INDEX "uploads_item_id_target_key" ON "uploads"("item_id", "target");
@@ -0,0 +1,7 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "retry_count" INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN "max_retries" INTEGER,
+ADD COLUMN "next_retry_at" TIMESTAMPTZ;
+
+-- CreateIndex
+CREATE INDEX "queue_status_next_retry_at_idx" ON "queue"("status", "next_retry_at");
@@ -0,0 +1,13 @@
+-- CreateTable
+-- Older databases were set up with this table by hand, hence IF NOT EXISTS
+CREATE TABLE IF NOT EXISTS "videos" (
+    "id" BIGSERIAL NOT NULL,
+    "title" TEXT NOT NULL,
+    "url" TEXT NOT NULL,
+    "local_path" TEXT,
+    "thumbnail" TEXT,
+    "status" TEXT NOT NULL,
+    "created_at" TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "videos_pkey" PRIMARY KEY ("id")
+);
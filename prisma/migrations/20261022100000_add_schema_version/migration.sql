@@ -0,0 +1,11 @@
+-- CreateTable
+-- The app creates this table itself before it applies migrations (see
+-- tauri/src/migrations.rs); this keeps Prisma's view of the schema in step
+CREATE TABLE IF NOT EXISTS "schema_version" (
+    "version" TEXT NOT NULL,
+    "checksum" TEXT NOT NULL,
+    "source" TEXT NOT NULL,
+    "applied_at" TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+
+    CONSTRAINT "schema_version_pkey" PRIMARY KEY ("version")
+);
@@ -0,0 +1,5 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "total_bytes" BIGINT,
+ADD COLUMN "downloaded_bytes" BIGINT,
+ADD COLUMN "speed_bps" BIGINT,
+ADD COLUMN "eta_seconds" INTEGER;
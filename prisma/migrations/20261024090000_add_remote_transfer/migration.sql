@@ -0,0 +1,3 @@
+-- AlterTable
+ALTER TABLE "queue" ADD COLUMN "transfer_mode" TEXT,
+ADD COLUMN "remote_filecode" TEXT;
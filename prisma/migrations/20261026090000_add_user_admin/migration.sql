@@ -0,0 +1,6 @@
+-- AlterTable
+ALTER TABLE "users" ADD COLUMN "is_admin" BOOLEAN NOT NULL DEFAULT false;
+
+-- The oldest account of an existing install becomes its admin
+UPDATE "users" SET "is_admin" = true
+WHERE "id" = (SELECT "id" FROM "users" ORDER BY "created_at", "id" LIMIT 1);
@@ -0,0 +1,54 @@
+import { NextRequest, NextResponse } from "next/server";
+import { getChangesSince, SYNC_MAX_PAGE_SIZE } from "@/lib/queue";
+
+/**
+ * API endpoint for incremental index sync (HTTP equivalent of the
+ * get_changes_since Tauri command).
+ *
+ * Query params:
+ *   userId  - owner of the items (required)
+ *   cursor  - next_cursor from the previous call; omit for a full first sync
+ *   limit   - page size, capped at SYNC_MAX_PAGE_SIZE
+ */
+export async function GET(request: NextRequest) {
+  try {
+    const { searchParams } = new URL(request.url);
+    const userId = searchParams.get("userId");
+    const cursor = searchParams.get("cursor");
+    const limitParam = searchParams.get("limit");
+
+    if (!userId) {
+      return NextResponse.json(
+        { success: false, error: "userId is required" },
+        { status: 400 },
+      );
+    }
+
+    const limit = limitParam ? Number(limitParam) : undefined;
+    if (limit !== undefined && (!Number.isInteger(limit) || limit < 1)) {
+      return NextResponse.json(
+        {
+          success: false,
+          error: `limit must be an integer between 1 and ${SYNC_MAX_PAGE_SIZE}`,
+        },
+        { status: 400 },
+      );
+    }
+
+    const changes = await getChangesSince(userId, cursor, limit);
+    if (!changes) {
+      return NextResponse.json(
+        { success: false, error: `Invalid sync cursor '${cursor}'` },
+        { status: 400 },
+      );
+    }
+
+    return NextResponse.json({ success: true, ...changes });
+  } catch (error) {
+    console.error("Error fetching changes:", error);
+    return NextResponse.json(
+      { success: false, error: "Failed to fetch changes" },
+      { status: 500 },
+    );
+  }
+}
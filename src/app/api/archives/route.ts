@@ -2,12 +2,14 @@ import { NextRequest, NextResponse } from 'next/server';
 import { getEncodedItems } from '@/lib/queue';
 
 /**
- * API endpoint to get all archives from the database
+ * API endpoint to get all archives from the database.
+ * Optional ?language=<code> limits results to videos in that language.
  */
 export async function GET(request: NextRequest) {
   try {
     // Get all encoded items from the database
-    const archives = await getEncodedItems();
+    const language = request.nextUrl.searchParams.get('language') || undefined;
+    const archives = await getEncodedItems(language);
     
     // Return the archives
     return NextResponse.json({ 
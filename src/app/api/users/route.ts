@@ -32,14 +32,19 @@ export async function POST(request: NextRequest) {
       });
     }
 
-    // Create new user with auto-generated UUID
-    const newUser = await prisma.user.create({
-      data: {
-        username: email, // Use email as username for Google auth users
-        email,
-        displayName: name,
-        lastLogin: new Date(),
-      },
+    // Create new user with auto-generated UUID. The first account of a new
+    // database becomes its admin, so someone can change the global settings.
+    const newUser = await prisma.$transaction(async (tx) => {
+      const admins = await tx.user.count({ where: { isAdmin: true } });
+      return tx.user.create({
+        data: {
+          username: email, // Use email as username for Google auth users
+          email,
+          displayName: name,
+          lastLogin: new Date(),
+          isAdmin: admins === 0,
+        },
+      });
     });
 
     // Create default settings for new user
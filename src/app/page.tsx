@@ -32,7 +32,18 @@ import {
 import { invoke } from "@tauri-apps/api/tauri"; // Import invoke
 import { open } from "@tauri-apps/api/shell"; // Import open for external links
 import { useTauri } from "@/app/tauri-integration"; // Corrected import path
-import { QueueItem, AppSettings, openExternalLink } from "@/lib/tauri-api"; // <-- Import types
+import {
+  QueueItem,
+  AppSettings,
+  DiskUsage,
+  getDiskUsage,
+  openExternalLink,
+  YtdlpVersion,
+  checkYtdlpVersion,
+  updateYtdlp,
+  testWebhook,
+  getDashboardUrl,
+} from "@/lib/tauri-api"; // <-- Import types
 import { createEmptySettings } from "@/lib/settings-helper"; // Import factory function
 import { listen } from "@tauri-apps/api/event"; // <-- Import listen
 import { toast } from "react-hot-toast";
@@ -58,6 +69,42 @@ const icons: { [key: string]: React.JSX.Element } = {
   all: <Bars3Icon className={iconClass + " text-gray-500"} />,
 };
 
+// Bytes as gigabytes with one decimal, for the disk usage line in settings
+const formatGb = (bytes: number) => (bytes / 1024 ** 3).toFixed(1);
+
+// Bytes in the largest unit that keeps the number above 1, for download sizes
+const formatBytes = (bytes: number) => {
+  const units = ["B", "KiB", "MiB", "GiB", "TiB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit++;
+  }
+  return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+};
+
+// Seconds as m:ss, or h:mm:ss for an hour or more
+const formatEta = (seconds: number) => {
+  const h = Math.floor(seconds / 3600);
+  const m = Math.floor((seconds % 3600) / 60);
+  const s = String(seconds % 60).padStart(2, "0");
+  return h > 0 ? `${h}:${String(m).padStart(2, "0")}:${s}` : `${m}:${s}`;
+};
+
+// "120.5 MiB of 1.2 GiB · 3.4 MiB/s · ETA 5:12", from what yt-dlp reported
+const downloadStats = (item: QueueItem) =>
+  [
+    item.total_bytes != null &&
+      (item.downloaded_bytes != null
+        ? `${formatBytes(item.downloaded_bytes)} of ${formatBytes(item.total_bytes)}`
+        : formatBytes(item.total_bytes)),
+    item.speed_bps != null && `${formatBytes(item.speed_bps)}/s`,
+    item.eta_seconds != null && `ETA ${formatEta(item.eta_seconds)}`,
+  ]
+    .filter(Boolean)
+    .join(" · ");
+
 // --- Color mapping for badges/progress ---
 const statusColors: {
   [key: string]: { bg: string; text: string; progress: string };
@@ -73,12 +120,42 @@ const statusColors: {
     text: "text-green-700",
     progress: "bg-green-500",
   },
+  pending_review: {
+    bg: "bg-yellow-100",
+    text: "text-yellow-700",
+    progress: "bg-yellow-500",
+  },
   uploading: {
     bg: "bg-purple-100",
     text: "text-purple-700",
     progress: "bg-purple-500",
   },
+  provider_unavailable: {
+    bg: "bg-amber-100",
+    text: "text-amber-700",
+    progress: "bg-amber-400",
+  },
   failed: { bg: "bg-red-100", text: "text-red-700", progress: "bg-red-500" },
+  unsupported: {
+    bg: "bg-orange-100",
+    text: "text-orange-700",
+    progress: "bg-orange-400",
+  },
+  auth_required: {
+    bg: "bg-pink-100",
+    text: "text-pink-700",
+    progress: "bg-pink-400",
+  },
+  blocked_missing_ffmpeg: {
+    bg: "bg-yellow-100",
+    text: "text-yellow-800",
+    progress: "bg-yellow-400",
+  },
+  blocked_disk_space: {
+    bg: "bg-yellow-100",
+    text: "text-yellow-800",
+    progress: "bg-yellow-400",
+  },
   cancelled: {
     bg: "bg-gray-100",
     text: "text-gray-500",
@@ -102,6 +179,12 @@ declare global {
   }
 }
 
+// Backend timestamps are ISO-8601 strings; unparseable or missing ones sort first
+const timestampOf = (value?: string): number => {
+  const parsed = value ? Date.parse(value) : NaN;
+  return Number.isNaN(parsed) ? 0 : parsed;
+};
+
 // --- Helper Component for Queue Item ---
 interface QueueItemProps {
   item: QueueItem;
@@ -244,6 +327,9 @@ const QueueListItem: React.FC<QueueItemProps> = ({
                   </span>
                 </div>
               )}
+            {item.status === "downloading" && downloadStats(item) && (
+              <p className="text-gray-500 mt-0.5">{downloadStats(item)}</p>
+            )}
             {/* Display message if not a progress message */}
             {!(
               item.status === "downloading" &&
@@ -300,7 +386,10 @@ const QueueListItem: React.FC<QueueItemProps> = ({
                 <LinkIcon className="h-3.5 w-3.5" />,
               )}
             {/* Retry Download/Upload Button */}
-            {item.status === "failed" &&
+            {(item.status === "failed" ||
+              item.status === "auth_required" ||
+              item.status === "blocked_missing_ffmpeg" ||
+              item.status === "blocked_disk_space") &&
               !item.filemoon_url &&
               renderButton(
                 // Show "Retry Upload" if there's a local path or upload-related error message
@@ -376,6 +465,8 @@ export default function Home() {
     | "title_desc"
     | "status";
   const [filterStatus, setFilterStatus] = useState<FilterStatus>("all");
+  // Video language to show, or "all"
+  const [filterLanguage, setFilterLanguage] = useState<string>("all");
   const [sortKey, setSortKey] = useState<SortKey>("added_at_desc");
   const [showFilterDropdown, setShowFilterDropdown] = useState(false);
   const [showSortDropdown, setShowSortDropdown] = useState(false);
@@ -592,7 +683,17 @@ export default function Home() {
         `[DEBUG] About to clear items with statusTypes:`,
         statusTypes,
       );
-      await tauriClearItems(statusTypes);
+      // The backend only clears what it described to the user first
+      const preview = await tauriClearItems(statusTypes);
+      const confirmation = preview?.confirmation;
+      if (!confirmation || confirmation.item_count === 0) {
+        setMessage(`No ${type} items to clear.`);
+        return;
+      }
+      if (!window.confirm(`${confirmation.summary}?`)) {
+        return;
+      }
+      await tauriClearItems(statusTypes, confirmation.token);
       console.log(`[DEBUG] tauriClearItems completed, refreshing queue...`);
 
       // Force refresh the queue items after clearing
@@ -854,17 +955,33 @@ export default function Home() {
     }
   };
 
+  // Languages captured from downloaded items, offered as filters
+  const availableLanguages = useMemo(
+    () =>
+      Array.from(
+        new Set(
+          contextQueue
+            .map((item) => item.language)
+            .filter((language): language is string => !!language),
+        ),
+      ).sort(),
+    [contextQueue],
+  );
+
   const displayedQueueItems = contextQueue
     .filter((item) => {
+      if (filterLanguage !== "all" && item.language !== filterLanguage) {
+        return false;
+      }
       if (filterStatus === "all") return true;
       return item.status === filterStatus;
     })
     .sort((a, b) => {
       switch (sortKey) {
         case "added_at_asc":
-          return (a.added_at || 0) - (b.added_at || 0);
+          return timestampOf(a.added_at) - timestampOf(b.added_at);
         case "added_at_desc":
-          return (b.added_at || 0) - (a.added_at || 0);
+          return timestampOf(b.added_at) - timestampOf(a.added_at);
         case "title_asc":
           return (a.title || a.url || "").localeCompare(b.title || b.url || "");
         case "title_desc":
@@ -884,10 +1001,15 @@ export default function Home() {
     );
     // Modal-specific state for the contribution checkbox - no longer needed
     const [isLoadingSettings, setIsLoadingSettings] = useState(true);
+    const [diskUsage, setDiskUsage] = useState<DiskUsage | null>(null);
+    const [ytdlp, setYtdlp] = useState<YtdlpVersion | null>(null);
+    const [isUpdatingYtdlp, setIsUpdatingYtdlp] = useState(false);
 
     // Load initial values into modal state when modal opens
     useEffect(() => {
       setIsLoadingSettings(true);
+      getDiskUsage().then(setDiskUsage);
+      checkYtdlpVersion().then(setYtdlp);
       tauriGetAppSettings()
         .then((fetchedSettings) => {
           setModalSettings(fetchedSettings || createEmptySettings());
@@ -900,6 +1022,41 @@ export default function Home() {
         });
     }, []); // Run only when modal mounts
 
+    const handleUpdateYtdlp = () => {
+      setIsUpdatingYtdlp(true);
+      updateYtdlp()
+        .then((status) => {
+          setYtdlp(status);
+          toast.success(`yt-dlp ${status?.managed_version ?? ""} installed`);
+        })
+        .catch((err) => toast.error(`yt-dlp update failed: ${err}`))
+        .finally(() => setIsUpdatingYtdlp(false));
+    };
+
+    const handleTestWebhook = () => {
+      testWebhook()
+        .then((result) =>
+          toast.success(
+            `Webhook answered HTTP ${result?.status}${result?.signed ? " (signed)" : ""}`,
+          ),
+        )
+        .catch((err) => toast.error(`Webhook test failed: ${err}`));
+    };
+
+    // The link opens the dashboard only; the API token never goes into a URL
+    const handleCopyDashboardUrl = () => {
+      getDashboardUrl().then((url) => {
+        if (!url) {
+          toast.error("Save the settings with the local API on first");
+          return;
+        }
+        navigator.clipboard
+          .writeText(url)
+          .then(() => toast.success("Dashboard link copied"))
+          .catch((err) => toast.error(`Could not copy the link: ${err}`));
+      });
+    };
+
     // Handle form submission in the modal
     const handleModalSubmit = (e: React.FormEvent<HTMLFormElement>) => {
       e.preventDefault();
@@ -910,6 +1067,16 @@ export default function Home() {
         delete_after_upload,
         auto_upload,
         upload_target,
+        desktop_notifications,
+        clipboard_watch,
+        clipboard_sites,
+        webhook_url,
+        webhook_secret,
+        local_api_enabled,
+        local_api_port,
+        local_api_token,
+        local_api_lan,
+        max_auto_retries,
       } = modalSettings;
 
       const settingsToSave: AppSettings = {
@@ -918,6 +1085,16 @@ export default function Home() {
         delete_after_upload,
         auto_upload,
         upload_target,
+        desktop_notifications,
+        clipboard_watch,
+        clipboard_sites,
+        webhook_url,
+        webhook_secret,
+        local_api_enabled,
+        local_api_port,
+        local_api_token,
+        local_api_lan,
+        max_auto_retries,
       };
 
       // Call the main save handler with just the settings
@@ -1044,6 +1221,39 @@ export default function Home() {
                 "text",
                 "Leave blank to use system default download folder.",
               )}
+              {diskUsage && (
+                <p className="-mt-3 mb-4 text-xs text-gray-500">
+                  {formatGb(diskUsage.free_bytes)} GB free of{" "}
+                  {formatGb(diskUsage.total_bytes)} GB; downloads take{" "}
+                  {formatGb(diskUsage.download_dir_bytes)} GB
+                  {diskUsage.blocked_items > 0 &&
+                    `. ${diskUsage.blocked_items} item(s) wait for more space`}
+                </p>
+              )}
+
+              {ytdlp && (
+                <div className="mb-4 flex items-center justify-between text-xs text-gray-500">
+                  <span>
+                    yt-dlp {ytdlp.version ?? "not found"}
+                    {ytdlp.source === "managed" && " (managed)"}
+                    {ytdlp.message && `. ${ytdlp.message}`}
+                  </span>
+                  {(ytdlp.update_available || !ytdlp.version) && (
+                    <button
+                      type="button"
+                      onClick={handleUpdateYtdlp}
+                      disabled={isUpdatingYtdlp}
+                      className="ml-3 py-1 px-2 border border-gray-300 rounded text-xs text-gray-700 hover:bg-gray-50 disabled:opacity-50"
+                    >
+                      {isUpdatingYtdlp
+                        ? "Installing..."
+                        : ytdlp.managed_version
+                          ? `Update to ${ytdlp.latest_version}`
+                          : "Install yt-dlp"}
+                    </button>
+                  )}
+                </div>
+              )}
 
               <div className="mb-4">
                 {" "}
@@ -1068,8 +1278,174 @@ export default function Home() {
                       delete_after_upload: checked ? "true" : "false",
                     })),
                 )}
+                {renderCheckbox(
+                  "desktopNotifications",
+                  "Show desktop notifications when items finish or fail",
+                  modalSettings.desktop_notifications === "true",
+                  (checked) =>
+                    setModalSettings((prev) => ({
+                      ...prev,
+                      desktop_notifications: checked ? "true" : "false",
+                    })),
+                )}
               </div>
 
+              <div className="mb-4">
+                <label
+                  htmlFor="clipboardWatch"
+                  className="block text-sm font-medium text-gray-700 mb-1"
+                >
+                  Copied video links
+                </label>
+                <select
+                  id="clipboardWatch"
+                  value={modalSettings.clipboard_watch || "off"}
+                  onChange={(e) =>
+                    setModalSettings((prev) => ({
+                      ...prev,
+                      clipboard_watch: e.target.value,
+                    }))
+                  }
+                  className="mt-1 block w-full px-3 py-2 border border-gray-300 rounded-md shadow-sm focus:outline-none focus:ring-indigo-500 focus:border-indigo-500 sm:text-sm text-gray-900"
+                >
+                  <option value="off">Ignore the clipboard</option>
+                  <option value="confirm">Ask before queuing them</option>
+                  <option value="enqueue">Queue them straight away</option>
+                </select>
+              </div>
+              {modalSettings.clipboard_watch &&
+                modalSettings.clipboard_watch !== "off" &&
+                renderInput(
+                  "clipboardSites",
+                  "Clipboard sites",
+                  "e.g., youtube, vimeo.com",
+                  modalSettings.clipboard_sites,
+                  (val) =>
+                    setModalSettings((prev) => ({
+                      ...prev,
+                      clipboard_sites: val,
+                    })),
+                  "text",
+                  "Only links from these sites; empty for every supported site",
+                )}
+
+              {renderInput(
+                "webhookUrl",
+                "Webhook URL",
+                "e.g., https://n8n.example.com/webhook/permavid",
+                modalSettings.webhook_url,
+                (val) =>
+                  setModalSettings((prev) => ({
+                    ...prev,
+                    webhook_url: val,
+                  })),
+                "text",
+                "Receives a JSON POST whenever an item changes status",
+              )}
+              {modalSettings.webhook_url && (
+                <>
+                  {renderInput(
+                    "webhookSecret",
+                    "Webhook secret",
+                    "Optional",
+                    modalSettings.webhook_secret,
+                    (val) =>
+                      setModalSettings((prev) => ({
+                        ...prev,
+                        webhook_secret: val,
+                      })),
+                    "password",
+                    "Signs each request with HMAC-SHA256 in X-PermaVid-Signature",
+                  )}
+                  <button
+                    type="button"
+                    onClick={handleTestWebhook}
+                    className="mb-4 py-1 px-2 border border-gray-300 rounded text-xs text-gray-700 hover:bg-gray-50"
+                  >
+                    Send a test event to the saved URL
+                  </button>
+                </>
+              )}
+
+              <div className="mb-4">
+                {renderCheckbox(
+                  "localApiEnabled",
+                  "Accept links from browser extensions (local API)",
+                  modalSettings.local_api_enabled === "true",
+                  (checked) =>
+                    setModalSettings((prev) => ({
+                      ...prev,
+                      local_api_enabled: checked ? "true" : "false",
+                      // A token is required; generate one the first time
+                      local_api_token:
+                        checked && !prev.local_api_token
+                          ? crypto.randomUUID().replace(/-/g, "")
+                          : prev.local_api_token,
+                    })),
+                )}
+              </div>
+              {modalSettings.local_api_enabled === "true" && (
+                <>
+                  {renderInput(
+                    "localApiPort",
+                    "Local API port",
+                    "47615",
+                    modalSettings.local_api_port,
+                    (val) =>
+                      setModalSettings((prev) => ({
+                        ...prev,
+                        local_api_port: val,
+                      })),
+                  )}
+                  {renderInput(
+                    "localApiToken",
+                    "Local API token",
+                    "At least 16 characters",
+                    modalSettings.local_api_token,
+                    (val) =>
+                      setModalSettings((prev) => ({
+                        ...prev,
+                        local_api_token: val,
+                      })),
+                    "text",
+                    "Send as Authorization: Bearer <token> to http://127.0.0.1:<port>/api/queue",
+                  )}
+                  <div className="mb-4">
+                    {renderCheckbox(
+                      "localApiLan",
+                      "Also answer on the local network, e.g. the dashboard on a phone",
+                      modalSettings.local_api_lan === "true",
+                      (checked) =>
+                        setModalSettings((prev) => ({
+                          ...prev,
+                          local_api_lan: checked ? "true" : "false",
+                        })),
+                    )}
+                  </div>
+                  <button
+                    type="button"
+                    onClick={handleCopyDashboardUrl}
+                    className="mb-4 py-1 px-2 border border-gray-300 rounded text-xs text-gray-700 hover:bg-gray-50"
+                  >
+                    Copy the dashboard link (read-only) for the saved settings
+                  </button>
+                </>
+              )}
+
+              {renderInput(
+                "maxAutoRetries",
+                "Automatic retries",
+                "3",
+                modalSettings.max_auto_retries,
+                (val) =>
+                  setModalSettings((prev) => ({
+                    ...prev,
+                    max_auto_retries: val,
+                  })),
+                "number",
+                "Network errors and Filemoon server errors are retried with a growing delay; 0 turns this off",
+              )}
+
               {/* Action buttons (submit now triggers handleModalSubmit) */}
               <div className="flex justify-end space-x-3 pt-4 border-t border-gray-200">
                 <button
@@ -1330,6 +1706,9 @@ export default function Home() {
                     <span className="capitalize font-semibold">
                       {filterStatus}
                     </span>
+                    {filterLanguage !== "all" && (
+                      <span className="font-semibold"> · {filterLanguage}</span>
+                    )}
                   </span>
                   <ChevronDownIcon className="h-3 w-3 ml-1" />
                 </button>
@@ -1344,10 +1723,17 @@ export default function Home() {
                         "all",
                         "queued",
                         "downloading",
+                        "transcoding",
                         "downloaded",
+                        "pending_review",
                         "uploading",
+                        "provider_unavailable",
                         "uploaded",
                         "failed",
+                        "unsupported",
+                        "auth_required",
+                        "blocked_missing_ffmpeg",
+                        "blocked_disk_space",
                         "cancelled",
                       ] as FilterStatus[]
                     ).map((status) => (
@@ -1367,6 +1753,25 @@ export default function Home() {
                         </div>
                       </DropdownItem>
                     ))}
+                    {availableLanguages.length > 0 && (
+                      <>
+                        <div className="px-4 pt-2 pb-1 text-[10px] font-semibold uppercase tracking-wide text-gray-400 dark:text-gray-500 border-t border-gray-200 dark:border-gray-700">
+                          Language
+                        </div>
+                        {["all", ...availableLanguages].map((language) => (
+                          <DropdownItem
+                            key={`language-${language}`}
+                            onClick={() => {
+                              setFilterLanguage(language);
+                              setShowFilterDropdown(false);
+                            }}
+                            isActive={filterLanguage === language}
+                          >
+                            <span className="capitalize">{language}</span>
+                          </DropdownItem>
+                        ))}
+                      </>
+                    )}
                   </DropdownMenu>
                 )}
               </div>
@@ -1469,6 +1874,9 @@ export default function Home() {
                 <li className="px-4 py-10 sm:px-6 text-center text-gray-500 dark:text-gray-400">
                   No items match the current filter (
                   <span className="capitalize font-medium">{filterStatus}</span>
+                  {filterLanguage !== "all" && (
+                    <span className="font-medium">, {filterLanguage}</span>
+                  )}
                   ).
                 </li>
               ) : (
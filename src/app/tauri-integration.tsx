@@ -26,10 +26,17 @@ import {
   retryItem,
   triggerUpload,
   cancelItem,
+  DownloadProgress,
+  StartupReport,
+  ClearResult,
+  Confirmed,
+  ClipboardUrlDetected,
+  addQueueItemsBulk,
 } from "@/lib/tauri-api";
 import { createEmptySettings } from "@/lib/settings-helper";
 import { fetch as tauriFetch, Body } from "@tauri-apps/api/http"; // Import Tauri fetch AND Body
 import { listen } from "@tauri-apps/api/event"; // Import event listener
+import { toast } from "react-hot-toast";
 
 // Define context value type
 interface TauriContextType {
@@ -40,7 +47,10 @@ interface TauriContextType {
   addToQueue: (item: QueueItem) => Promise<string>;
   updateItem: (item: QueueItem) => Promise<void>;
   updateStatus: (id: string, status: string, message?: string) => Promise<void>;
-  clearItems: (statusTypes: string[]) => Promise<void>;
+  clearItems: (
+    statusTypes: string[],
+    confirmToken?: string,
+  ) => Promise<Confirmed<ClearResult> | null>;
   getAppSettings: () => Promise<AppSettings>;
   saveAppSettings: (settings: AppSettings) => Promise<void>;
   openLink: (url: string) => Promise<void>;
@@ -66,7 +76,7 @@ const TauriContext = createContext<TauriContextType>({
   addToQueue: async () => "",
   updateItem: async () => {},
   updateStatus: async () => {},
-  clearItems: async () => {},
+  clearItems: async () => null,
   getAppSettings: async () => createEmptySettings(),
   saveAppSettings: async () => {},
   openLink: async () => {},
@@ -237,6 +247,10 @@ export function TauriProvider({ children }: { children: ReactNode }) {
     if (!isTauriEnvironment) return;
 
     let unlistenFn: (() => void) | undefined;
+    let unlistenProviderFns: (() => void)[] = [];
+    let unlistenProgressFn: (() => void) | undefined;
+    let unlistenStartupFn: (() => void) | undefined;
+    let unlistenClipboardFn: (() => void) | undefined;
 
     const setupListeners = async () => {
       try {
@@ -269,6 +283,101 @@ export function TauriProvider({ children }: { children: ReactNode }) {
           // Also fetch the latest queue data from the backend
           fetchQueueItems();
         });
+
+        // Live download progress; the database row is only updated every few seconds
+        unlistenProgressFn = await listen<DownloadProgress>(
+          "queue://progress",
+          (event) => {
+            const {
+              item_id,
+              percent,
+              speed,
+              eta,
+              total_bytes,
+              downloaded_bytes,
+              speed_bps,
+              eta_seconds,
+            } = event.payload;
+            const details = [speed, eta && `ETA ${eta}`].filter(Boolean).join(", ");
+            setQueueItems((prevItems) =>
+              prevItems.map((item) =>
+                item.id === item_id
+                  ? {
+                      ...item,
+                      status: "downloading",
+                      message: `Downloading: ${percent.toFixed(1)}%${
+                        details ? ` (${details})` : ""
+                      }`,
+                      total_bytes,
+                      downloaded_bytes,
+                      speed_bps,
+                      eta_seconds,
+                    }
+                  : item,
+              ),
+            );
+          },
+        );
+
+        // One notification per Filemoon maintenance window, not one per held item
+        unlistenProviderFns = await Promise.all(
+          ["provider_unavailable", "provider_available"].map((eventName) =>
+            listen<{ provider: string; message: string }>(eventName, (event) => {
+              if (eventName === "provider_unavailable") {
+                toast.error(event.payload.message);
+              } else {
+                toast.success(event.payload.message);
+              }
+              fetchQueueItems();
+            }),
+          ),
+        );
+
+        // Items the last session left mid-download are queued again at startup
+        unlistenStartupFn = await listen<StartupReport>(
+          "startup_report",
+          (event) => {
+            const report = event.payload;
+            if (!report.database_ok) {
+              toast.error(report.database_message || "Database unreachable");
+              return;
+            }
+            const requeued =
+              report.downloads_requeued + report.uploads_requeued;
+            if (requeued > 0) {
+              toast.success(
+                `Resumed ${requeued} item(s) interrupted when the app last closed`,
+              );
+              fetchQueueItems();
+            }
+          },
+        );
+
+        // Video links copied while clipboard_watch is on
+        unlistenClipboardFn = await listen<ClipboardUrlDetected>(
+          "clipboard://url-detected",
+          async (event) => {
+            const { mode, urls, results } = event.payload;
+            let added = results;
+            if (mode === "confirm" || !results) {
+              const ok = window.confirm(
+                `Queue ${urls.length} copied link(s)?\n\n${urls.slice(0, 10).join("\n")}`,
+              );
+              if (!ok) return;
+              try {
+                added = await addQueueItemsBulk(urls);
+              } catch (err) {
+                toast.error(`Failed to queue copied links: ${err}`);
+                return;
+              }
+            }
+            const count = (added ?? []).filter((r) => r.result === "added").length;
+            if (count > 0) {
+              toast.success(`Queued ${count} copied link(s)`);
+              fetchQueueItems();
+            }
+          },
+        );
       } catch (err) {
         console.error("Error setting up event listeners:", err);
       }
@@ -279,6 +388,10 @@ export function TauriProvider({ children }: { children: ReactNode }) {
     // Return cleanup function
     return () => {
       if (unlistenFn) unlistenFn();
+      unlistenProviderFns.forEach((unlisten) => unlisten());
+      if (unlistenProgressFn) unlistenProgressFn();
+      if (unlistenStartupFn) unlistenStartupFn();
+      if (unlistenClipboardFn) unlistenClipboardFn();
     };
   }, [isTauriEnvironment, fetchQueueItems]);
 
@@ -307,16 +420,20 @@ export function TauriProvider({ children }: { children: ReactNode }) {
     [fetchQueueItems],
   );
 
+  // Without confirmToken this only asks what would be cleared
   const clearItems = useCallback(
-    async (statusTypes: string[]) => {
+    async (statusTypes: string[], confirmToken?: string) => {
       console.log("[DEBUG] clearItems called with statusTypes:", statusTypes);
 
       try {
-        await clearCompletedItems(statusTypes);
+        const response = await clearCompletedItems(statusTypes, confirmToken);
         console.log("[DEBUG] clearCompletedItems completed, fetching queue...");
 
-        await fetchQueueItems();
-        console.log("[DEBUG] Queue items refreshed");
+        if (confirmToken) {
+          await fetchQueueItems();
+          console.log("[DEBUG] Queue items refreshed");
+        }
+        return response?.data ?? null;
       } catch (error) {
         console.error("[ERROR] clearItems failed:", error);
         throw error; // Re-throw so the calling code can handle it
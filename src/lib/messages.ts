@@ -0,0 +1,36 @@
+import type { MessageParams } from "./tauri-api";
+
+// Localization of backend status messages. Next to the English text, the backend
+// stores a message code and its parameters (see tauri/src/messages.rs); a
+// translation table maps codes to templates with the same {name} placeholders.
+export type MessageTranslations = Record<string, string>;
+
+interface CodedMessage {
+  message?: string | null;
+  message_code?: string;
+  message_params?: MessageParams;
+}
+
+export function renderTemplate(
+  template: string,
+  params: MessageParams = {},
+): string {
+  return template.replace(/\{([a-z_]+)\}/g, (placeholder, name: string) =>
+    name in params ? String(params[name] ?? "none") : placeholder,
+  );
+}
+
+// Text to show for a queue item or timeline event: its code's template from the
+// translations when there is one, otherwise the English text stored by the backend
+export function localizeMessage(
+  source: CodedMessage,
+  translations: MessageTranslations = {},
+): string | undefined {
+  const template = source.message_code
+    ? translations[source.message_code]
+    : undefined;
+  if (template === undefined) {
+    return source.message ?? undefined;
+  }
+  return renderTemplate(template, source.message_params);
+}
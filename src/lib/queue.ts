@@ -12,9 +12,16 @@ export interface QueueItem {
   status:
     | "queued"
     | "downloading"
+    | "transcoding"
     | "completed"
+    | "pending_review"
     | "failed"
+    | "unsupported"
+    | "auth_required"
+    | "blocked_missing_ffmpeg"
+    | "blocked_disk_space"
     | "uploading"
+    | "provider_unavailable"
     | "uploaded"
     | "cancelled"
     | "encoding"
@@ -31,6 +38,8 @@ export interface QueueItem {
   added_at: number;
   updated_at: number;
   user_id?: string | null;
+  language?: string | null;
+  caption_languages?: string[];
 }
 
 // Helper function to convert Prisma QueueItem to our interface
@@ -50,6 +59,8 @@ function convertPrismaQueueItem(item: any): QueueItem {
     added_at: Number(item.addedAt),
     updated_at: Number(item.updatedAt),
     user_id: item.userId,
+    language: item.language,
+    caption_languages: item.captionLanguages ?? [],
   };
 }
 
@@ -130,20 +141,31 @@ export async function getQueue(): Promise<QueueItem[]> {
   }
 }
 
+// Scheduling policy, kept in step with tauri/src/scheduler.rs: one point of boost per
+// 30 minutes waited (at most 10), minus 3 points per failed download attempt.
+const AGING_STEP_SECONDS = 30 * 60;
+const MAX_AGING_BOOST = 10;
+const FAILURE_PENALTY = 3;
+
 /**
- * Gets the next queued item for processing.
+ * Gets the next queued item for processing, highest effective priority first.
  * @returns The next queued item or undefined if none.
  */
 export async function getNextQueuedItem(): Promise<QueueItem | undefined> {
   try {
-    const item = await prisma.queueItem.findFirst({
-      where: {
-        status: "queued",
-      },
-      orderBy: {
-        addedAt: "asc",
-      },
-    });
+    const [next] = await prisma.$queryRaw<{ id: string }[]>`
+      SELECT id FROM queue
+      WHERE status = 'queued' AND NOT locked
+      ORDER BY (priority
+                + LEAST(FLOOR(EXTRACT(EPOCH FROM (NOW() - added_at)) / ${AGING_STEP_SECONDS}),
+                        ${MAX_AGING_BOOST})
+                - failure_count * ${FAILURE_PENALTY}) DESC,
+               added_at ASC
+      LIMIT 1
+    `;
+    const item = next
+      ? await prisma.queueItem.findUnique({ where: { id: next.id } })
+      : null;
 
     return item ? convertPrismaQueueItem(item) : undefined;
   } catch (error) {
@@ -464,11 +486,12 @@ export async function clearItemsByStatus(
  * Gets only the successfully encoded items for the gallery.
  * @returns Array of encoded queue items.
  */
-export async function getEncodedItems(): Promise<QueueItem[]> {
+export async function getEncodedItems(language?: string): Promise<QueueItem[]> {
   try {
     const items = await prisma.queueItem.findMany({
       where: {
         status: "encoded",
+        ...(language ? { language } : {}),
       },
       orderBy: {
         updatedAt: "desc",
@@ -481,6 +504,98 @@ export async function getEncodedItems(): Promise<QueueItem[]> {
   }
 }
 
+// --- Incremental Sync ---
+// Same cursor format as the Tauri get_changes_since command:
+// "<updated_at, RFC 3339 with microseconds>|<item id>", or a bare timestamp.
+
+export const SYNC_DEFAULT_PAGE_SIZE = 200;
+export const SYNC_MAX_PAGE_SIZE = 1000;
+
+export interface SyncItem {
+  id: string;
+  url: string;
+  status: string;
+  message: string | null;
+  title: string | null;
+  filemoon_url: string | null;
+  encoding_progress: number | null;
+  thumbnail_url: string | null;
+  added_at: string;
+  updated_at: string;
+  local_path: string | null;
+  user_id: string;
+  short_url: string | null;
+}
+
+export interface ChangeSet {
+  items: SyncItem[];
+  next_cursor: string | null;
+  has_more: boolean;
+}
+
+function parseSyncCursor(
+  cursor: string | null | undefined,
+): { time: string; id: string } | null | undefined {
+  const raw = cursor?.trim();
+  if (!raw) return null;
+
+  const separator = raw.indexOf("|");
+  const timePart = separator === -1 ? raw : raw.slice(0, separator);
+  const id = separator === -1 ? "" : raw.slice(separator + 1);
+
+  // Epoch milliseconds are accepted for older clients
+  if (/^-?\d+$/.test(timePart)) {
+    const date = new Date(Number(timePart));
+    return Number.isNaN(date.getTime())
+      ? undefined
+      : { time: date.toISOString(), id };
+  }
+  // Keep the original string so microsecond precision reaches Postgres intact
+  return Number.isNaN(Date.parse(timePart)) ? undefined : { time: timePart, id };
+}
+
+/**
+ * Items updated after `cursor`, oldest change first.
+ * @returns The change set, or null if the cursor can't be parsed.
+ */
+export async function getChangesSince(
+  userId: string,
+  cursor?: string | null,
+  limit?: number,
+): Promise<ChangeSet | null> {
+  const after = parseSyncCursor(cursor);
+  if (after === undefined) return null;
+
+  const pageSize = Math.min(
+    Math.max(limit ?? SYNC_DEFAULT_PAGE_SIZE, 1),
+    SYNC_MAX_PAGE_SIZE,
+  );
+
+  const rows = await prisma.$queryRaw<(SyncItem & { cursor_ts: string })[]>`
+    SELECT id, url, status, message, title, filemoon_url, encoding_progress,
+           thumbnail_url, local_path, user_id, short_url,
+           to_char(added_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.MS"Z"') AS added_at,
+           to_char(updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.MS"Z"') AS updated_at,
+           to_char(updated_at AT TIME ZONE 'UTC', 'YYYY-MM-DD"T"HH24:MI:SS.US"Z"') AS cursor_ts
+    FROM queue
+    WHERE user_id = ${userId}
+      AND (${after?.time ?? null}::TIMESTAMPTZ IS NULL
+           OR (updated_at, id) > (${after?.time ?? null}::TIMESTAMPTZ, ${after?.id ?? ""}))
+    ORDER BY updated_at ASC, id ASC
+    LIMIT ${pageSize + 1}
+  `;
+
+  const hasMore = rows.length > pageSize;
+  const page = rows.slice(0, pageSize);
+  const last = page[page.length - 1];
+
+  return {
+    items: page.map(({ cursor_ts, ...item }) => item),
+    next_cursor: last ? `${last.cursor_ts}|${last.id}` : (cursor ?? null),
+    has_more: hasMore,
+  };
+}
+
 /**
  * Cancels an item in the queue.
  * @param itemId The ID of the item to cancel.
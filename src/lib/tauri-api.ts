@@ -10,11 +10,546 @@ export interface QueueItem {
   filemoon_url?: string;
   encoding_progress?: number;
   thumbnail_url?: string;
-  added_at?: number;
-  updated_at?: number;
+  added_at?: string; // ISO-8601, UTC
+  updated_at?: string; // ISO-8601, UTC
   local_path?: string;
   user_id?: string;
   is_public?: boolean;
+  short_url?: string;
+  thumbnail_uploaded?: boolean;
+  locked?: boolean;
+  format_rung?: number;
+  format_used?: string;
+  download_sections?: string;
+  mirror_urls?: string[];
+  source_index?: number;
+  priority?: number;
+  failure_count?: number;
+  language?: string;
+  caption_languages?: string[];
+  format_override?: string;
+  tags?: string[];
+  template_id?: string;
+  upload_verified_at?: string; // ISO-8601, UTC
+  message_code?: string; // see tauri/src/messages.rs
+  message_params?: MessageParams;
+  playlist_id?: string; // set on items queued by add_playlist
+  subtitle_paths?: string[]; // subtitle files kept beside the download
+  upload_provider?: string; // declared provider used instead of Filemoon
+  upload_url?: string; // link on that provider
+  collection?: string; // gallery collection, set by bulk edits
+  retry_count?: number; // automatic retries so far (see tauri/src/retry_policy.rs)
+  max_retries?: number | null; // this item's cap; unset follows max_auto_retries
+  next_retry_at?: string | null; // ISO-8601, UTC; when the next automatic retry is due
+  // Size, progress, speed and ETA of the running or last download
+  total_bytes?: number | null;
+  downloaded_bytes?: number | null;
+  speed_bps?: number | null; // bytes per second; cleared when the download ends
+  eta_seconds?: number | null;
+  // "remote": Filemoon fetches the source URL itself, nothing is downloaded here
+  transfer_mode?: "remote" | null;
+  remote_filecode?: string | null; // file code of the running remote upload
+  upload_target?: string | null; // set from the item's queue template; overrides the setting
+}
+
+// Parameters filling the {name} placeholders of a message template
+export type MessageParams = Record<string, string | number | boolean | null>;
+
+// Returned by get_message_catalog: the English template for each message code
+export interface MessageCatalogEntry {
+  code: string;
+  template: string;
+}
+
+export interface NotificationRule {
+  id?: string;
+  user_id?: string;
+  name: string;
+  condition: string; // e.g. "status == failed AND retries >= 3"
+  channel: "telegram" | "webhook";
+  target: string; // Telegram chat id or webhook URL
+  enabled: boolean;
+  created_at?: string;
+}
+
+export interface QueueTemplate {
+  id?: string;
+  user_id?: string;
+  name: string;
+  url_pattern: string; // may contain {date}, {yyyy}, {mm}, {dd}
+  format?: string;
+  upload_target?: string;
+  tags: string[];
+  download_sections?: string;
+  priority: number;
+  schedule?: string; // "daily HH:MM" or "weekly <day> HH:MM", local time
+  enabled: boolean;
+  last_run_at?: string;
+  created_at?: string;
+}
+
+// Returned by find_duplicates
+export interface DuplicateGroup {
+  reasons: ("video_id" | "checksum" | "title")[];
+  items: QueueItem[];
+  suggested_keep?: string; // id of the item to keep
+}
+
+// Returned by get_active_jobs
+export interface ActiveJob {
+  id: string;
+  kind: "download" | "upload" | "status_check";
+  item_id?: string;
+  started_at: string; // ISO-8601, UTC
+  progress?: number; // 0-100
+  detail?: string;
+}
+
+// Returned by get_item_timeline
+export interface TimelineEvent {
+  at: string; // ISO-8601, UTC
+  kind:
+    | "added"
+    | "status"
+    | "retry"
+    | "upload_attempt"
+    | "transfer"
+    | "provider_error"
+    | "captured"
+    | "hook";
+  status?: string;
+  message?: string;
+  message_code?: string;
+  message_params?: MessageParams;
+  detail?: Record<string, unknown>;
+}
+
+export interface ItemTimeline {
+  item: QueueItem;
+  events: TimelineEvent[];
+  retries: number;
+  upload_attempts: number;
+  logs: { id: string; stream: string; line: string; timestamp: string }[];
+}
+
+// Returned by get_hook_runs
+export interface HookRun {
+  id: string;
+  item_id: string;
+  event: "after_download" | "after_upload" | "on_failure";
+  command: string;
+  exit_code?: number;
+  stdout: string;
+  stderr: string;
+  error?: string;
+  started_at: string; // ISO-8601, UTC
+  finished_at: string; // ISO-8601, UTC
+}
+
+// Returned by get_uploads: one row per destination of an item's upload
+export interface UploadRecord {
+  item_id: string;
+  target: string;
+  status: "uploading" | "uploaded" | "failed";
+  url?: string;
+  error?: string;
+  updated_at: string; // ISO-8601, UTC
+}
+
+// Returned by get_disk_space
+export interface DiskSpace {
+  directory: string;
+  free_bytes: number;
+  min_free_bytes?: number; // absent when the check is turned off
+  paused: boolean;
+  paused_since?: string; // ISO-8601, UTC
+}
+
+// Returned by get_disk_usage, for the settings screen
+export interface DiskUsage {
+  directory: string;
+  total_bytes: number;
+  free_bytes: number;
+  download_dir_bytes: number; // everything under the download directory
+  min_free_bytes?: number; // absent when the check is turned off
+  paused: boolean;
+  blocked_items: number; // items waiting for enough space to download
+}
+
+// Returned by create_share_token
+export interface ShareToken {
+  token: string;
+  item_id: string;
+  expires_at: string; // ISO-8601, UTC
+}
+
+// Returned by resolve_share_token
+export interface SharedItem {
+  item_id: string;
+  title?: string;
+  thumbnail_url?: string;
+  embed_url: string;
+  player_url: string;
+  short_url?: string;
+  expires_at: string; // ISO-8601, UTC
+}
+
+// Payload of the "queue://progress" event
+export interface DownloadProgress {
+  item_id: string;
+  percent: number;
+  speed?: string; // as printed by yt-dlp, e.g. "1.20MiB/s"
+  eta?: string; // e.g. "00:08"
+  total_bytes?: number | null;
+  downloaded_bytes?: number | null;
+  speed_bps?: number | null;
+  eta_seconds?: number | null;
+}
+
+// Returned by plan_queue
+export interface QueuePlan {
+  items: {
+    item_id: string;
+    url: string;
+    title?: string;
+    duration_secs?: number;
+    estimated_bytes?: number;
+    error?: string;
+  }[];
+  queued_items: number;
+  sized_items: number;
+  estimated_download_bytes: number;
+  uploads: {
+    provider: string;
+    upload_bytes: number;
+    used_bytes: number;
+    quota_bytes?: number;
+    projected_bytes: number;
+    will_exceed_quota?: boolean;
+  }[];
+  download_bytes_per_sec?: number;
+  upload_bytes_per_sec?: number;
+  estimated_download_secs?: number;
+  estimated_upload_secs?: number;
+  estimated_total_secs?: number;
+}
+
+// A playlist or channel whose entries were queued together
+export interface Playlist {
+  id: string;
+  user_id: string;
+  url: string;
+  title?: string;
+  source_id?: string;
+  entry_count: number;
+  added_at: string; // ISO-8601, UTC
+}
+
+// Returned by add_playlist
+export interface PlaylistImport {
+  playlist: Playlist;
+  item_ids: string[];
+  skipped: { url: string; reason: string }[];
+}
+
+// One entry per URL returned by add_queue_items_bulk
+export interface BulkAddResult {
+  url: string;
+  result: "added" | "duplicate" | "unsupported";
+  item_id: string | null;
+  reason: string | null;
+}
+
+// Payload of the clipboard://url-detected event
+export interface ClipboardUrlDetected {
+  user_id: string;
+  mode: "confirm" | "enqueue";
+  urls: string[];
+  results: BulkAddResult[] | null; // with "enqueue", unless queuing failed
+}
+
+// Returned by get_playlist_rollups
+export interface PlaylistRollup {
+  playlist: Playlist;
+  total: number;
+  done: number;
+  failed: number;
+  cancelled: number;
+  pending: number;
+  status: "in_progress" | "complete" | "complete_with_failures";
+  summary: string; // e.g. "complete with 5 failures"
+}
+
+// Returned by capture_page
+export interface PageCapture {
+  warc_path: string;
+  captured: string[];
+  failed: string[];
+}
+
+// Returned by get_available_formats
+export interface FormatList {
+  url: string;
+  title?: string;
+  duration_secs?: number;
+  formats: {
+    format_id: string;
+    ext?: string;
+    resolution?: string;
+    width?: number;
+    height?: number;
+    fps?: number;
+    vcodec?: string;
+    acodec?: string;
+    filesize?: number;
+    tbr?: number;
+    note?: string;
+    has_video: boolean;
+    has_audio: boolean;
+    selector: string; // pass to set_item_format or as format_override
+  }[];
+}
+
+// Returned by get_connection_status
+export interface ConnectionStatus {
+  online: boolean;
+  offline_items: number; // added while offline, not yet synced
+  pending_writes: number;
+}
+
+// Returned by explain_slow_queries
+export interface SlowQueryReport {
+  plans: {
+    name: string;
+    query: string;
+    total_cost?: number;
+    estimated_rows?: number;
+    indexes_used: string[];
+    seq_scans: string[];
+    sorts: boolean;
+    plan: string[]; // EXPLAIN output, one line per node
+    warning?: string;
+  }[];
+  flagged: number;
+}
+
+// Returned by get_api_capabilities
+export interface ApiCapabilities {
+  backend_version: string;
+  response_envelope_version: number;
+  features: {
+    upload_providers: string[];
+    quota_providers: string[];
+    notification_channels: string[];
+    hook_placeholders: string[];
+    http_api: boolean;
+    worker: boolean;
+  };
+  commands: { name: string; version: number }[]; // schema version per command
+}
+
+// Returned by open_forensics: what a failed download left behind
+export interface ForensicBundle {
+  path: string; // the bundle folder, also opened in the file manager
+  manifest: {
+    item_id: string;
+    url: string;
+    status: string;
+    message: string | null;
+    collected_at: string;
+    kept: { name: string; bytes: number }[];
+    dropped: { name: string; bytes: number }[]; // deleted, over the size cap
+  };
+  stderr: string;
+}
+
+// Sent as the startup_report event and returned by get_startup_report
+export interface StartupReport {
+  app_version: string;
+  role: "primary" | "viewer";
+  database_ok: boolean;
+  database_message: string | null;
+  schema_version: string | null; // newest applied migration
+  migrations_applied: string[]; // applied by this launch
+  downloads_requeued: number; // interrupted by the last shutdown
+  uploads_requeued: number;
+  retries_pending: number;
+  offline_writes_pending: number;
+  tools: {
+    name: string;
+    found: boolean;
+    path: string | null;
+    version: string | null;
+    message: string | null;
+  }[];
+  elapsed_ms: number;
+}
+
+// One direction of get_concurrency_status; see tauri/src/concurrency.rs
+export interface ConcurrencyLimit {
+  limit: number; // transfers allowed at once right now
+  max: number;
+  active: number;
+  bytes_per_sec: number | null; // total speed measured at the current limit
+  last_change: {
+    at: string;
+    from: number;
+    to: number;
+    reason: string;
+  } | null;
+}
+
+export interface ConcurrencyStatus {
+  adaptive: boolean;
+  downloads: ConcurrencyLimit;
+  uploads: ConcurrencyLimit;
+}
+
+// Returned by check_ytdlp_version and update_ytdlp
+export interface YtdlpVersion {
+  binary: string; // the executable downloads run
+  source: "env" | "setting" | "managed" | "path";
+  version?: string; // absent when it doesn't run
+  managed_path?: string; // where update_ytdlp installs yt-dlp
+  managed_version?: string;
+  latest_version?: string; // absent when GitHub couldn't be reached
+  update_available: boolean;
+  message?: string;
+}
+
+// Returned by get_effective_config
+export interface EffectiveConfig {
+  last_change: {
+    generation: number; // saves since the app started
+    user_id?: string; // absent for the machine-wide settings
+    changed_at: string;
+  };
+  active_user?: string; // whose settings the HTTP client and yt-dlp follow
+  settings: AppSettings; // with the machine-wide values filled in
+  http: {
+    user_agent: string;
+    filemoon_api_base: string;
+    filemoon_site_base: string;
+    filemoon_upload_server_url: string;
+  };
+  ytdlp_path: string;
+  ytdlp_source: "env" | "setting" | "managed" | "path";
+  concurrency: ConcurrencyStatus;
+}
+
+// Returned by test_webhook
+export interface WebhookTest {
+  status: number; // HTTP status the webhook answered with
+  signed: boolean;
+}
+
+// Returned by list_providers; see tauri/src/providers.rs for the file format
+export interface DeclaredProvider {
+  name: string;
+  api_key_env?: string;
+  server?: { url: string; url_field: string };
+  upload: {
+    url?: string;
+    file_field: string;
+    fields: Record<string, string>;
+    success_field?: string;
+    success_values: string[];
+    file_code_field: string;
+  };
+  status?: {
+    url: string;
+    ready_field: string;
+    ready_values: string[];
+    interval_secs?: number;
+    max_checks?: number;
+  };
+  link?: string;
+}
+
+export interface ProviderListing {
+  file?: string; // providers.json that was read
+  providers: DeclaredProvider[];
+  errors: string[]; // definitions skipped at startup and why
+}
+
+// Which items a bulk edit applies to; unset fields match everything
+export interface BulkFilter {
+  ids?: string[];
+  statuses?: string[];
+  tag?: string;
+  title_contains?: string; // case-insensitive
+  url_contains?: string;
+  playlist_id?: string;
+  collection?: string;
+}
+
+export type BulkEdit =
+  | { op: "add_tag"; tag: string }
+  | { op: "remove_tag"; tag: string }
+  | { op: "replace_in_title"; find: string; replace: string }
+  | { op: "set_collection"; collection?: string | null };
+
+// What a destructive command would do; pass `token` back as confirmToken to go
+// ahead. Expires after five minutes, and is refused if the items changed meanwhile.
+export interface Confirmation {
+  token: string;
+  action: string;
+  summary: string;
+  item_count: number;
+  sample: string[]; // titles of the first 10 items
+  bytes?: number; // size of the files to delete
+  expires_at: string; // ISO-8601, UTC
+}
+
+// Returned by clear_completed_items, delete_local_files, merge_duplicates and
+// apply_bulk_edit: a confirmation to show, or once confirmed, the result
+export interface Confirmed<T> {
+  confirmation?: Confirmation;
+  result?: T;
+}
+
+export interface ClearResult {
+  total_deleted: number;
+  status_types: string[];
+  user_id: string;
+}
+
+export interface LocalDeletion {
+  deleted: number;
+  failed: number;
+  bytes_freed: number;
+}
+
+// Returned by preview_bulk_edit and apply_bulk_edit
+export interface BulkEditResult {
+  dry_run: boolean;
+  matched: number;
+  changed: number;
+  changes: { id: string; title?: string; before: unknown; after: unknown }[]; // first 50
+}
+
+// Returned by get_bandwidth_report
+export interface BandwidthReport {
+  month: string;
+  since: string; // first day covered, YYYY-MM-DD
+  days: { day: string; download_bytes: number; upload_bytes: number }[];
+  items: {
+    item_id: string;
+    title?: string;
+    download_bytes: number;
+    upload_bytes: number;
+  }[];
+  month_download_bytes: number;
+  month_upload_bytes: number;
+  data_cap_bytes?: number;
+  pending: {
+    queued_items: number;
+    estimated_download_bytes: number;
+    awaiting_upload_items: number;
+    awaiting_upload_bytes: number;
+  };
+  projected_month_bytes: number;
+  will_exceed_cap?: boolean;
 }
 
 export interface AppSettings {
@@ -22,7 +557,74 @@ export interface AppSettings {
   download_directory?: string;
   delete_after_upload?: string;
   auto_upload?: string;
-  upload_target?: string;
+  upload_target?: string; // Comma-separated; the first is the main target, the rest are mirrors
+  ffmpeg_path?: string;
+  shortener_url?: string;
+  shortener_api_key?: string;
+  filemoon_monthly_quota_gb?: string;
+  b2_monthly_quota_gb?: string;
+  preferred_format?: string;
+  format_fallback_ladder?: string;
+  telegram_bot_token?: string;
+  review_before_upload?: string;
+  download_stall_minutes?: string;
+  download_timeout_minutes?: string;
+  upload_timeout_minutes?: string;
+  subtitle_languages?: string;
+  monthly_data_cap_gb?: string;
+  delete_grace_days?: string;
+  hook_after_download?: string;
+  hook_after_upload?: string;
+  hook_on_failure?: string;
+  min_free_space_gb?: string;
+  progress_persistence?: string; // "throttled" (default) or "memory"
+  provider_http_headers?: string; // "Name: value" per line, sent to providers
+  filemoon_api_base?: string; // overrides https://filemoonapi.com/api
+  filemoon_site_base?: string; // overrides https://filemoon.sx
+  filemoon_upload_server_url?: string;
+  capture_page?: string; // "true" saves the watch page and thumbnail as a WARC
+  download_window_start?: string; // "HH:MM"; downloads only start inside the window
+  download_window_end?: string; // "HH:MM"; before start for an overnight window
+  download_window_days?: string; // e.g. "mon,tue,fri"; empty for every day
+  subtitle_auto_captions?: string; // "false" skips auto-generated captions
+  cookies_file?: string; // Netscape cookies.txt for private/age-restricted videos
+  cookies_from_browser?: string; // "chrome", "firefox", "edge", ... or "firefox:profile"
+  media_library_dir?: string; // Jellyfin/Plex folder for kept downloads
+  missing_ffmpeg_action?: string; // "premerged" (default) or "block" when a format needs ffmpeg
+  archive_org_access_key?: string; // Internet Archive S3 keys, from archive.org/account/s3.php
+  archive_org_secret_key?: string;
+  archive_org_collection?: string; // defaults to "opensource_movies"
+  s3_endpoint?: string; // e.g. https://s3.us-west-004.backblazeb2.com or a MinIO URL
+  s3_bucket?: string;
+  s3_region?: string; // defaults to "us-east-1"
+  s3_access_key?: string;
+  s3_secret_key?: string;
+  reuse_existing_uploads?: string; // "true" links to a same-named file already on Filemoon
+  auto_upload_max_mb?: string; // Larger files are left for a manual upload
+  auto_upload_tags?: string; // Comma-separated; only items with one of these tags auto-upload
+  auto_upload_sites?: string; // Comma-separated, e.g. "youtube, vimeo.com"
+  upload_window_start?: string; // "HH:MM"; auto-uploads only start inside the window
+  upload_window_end?: string; // "HH:MM"; before start for an overnight window
+  upload_window_days?: string; // e.g. "mon,tue,fri"; empty for every day
+  performance_mode?: string; // "performance", "balanced" (default) or "responsive"
+  process_priority?: string; // "normal", "below_normal" or "idle"; overrides the mode
+  transcode_target?: string; // e.g. "h264/aac mp4"; empty to upload downloads as they are
+  transcode_crf?: string; // 0-51, lower is better quality; default 23
+  transcode_max_height?: string; // e.g. "1080"; taller videos are scaled down
+  adaptive_concurrency?: string; // "true" to tune how many transfers run at once
+  max_concurrent_downloads?: string; // 1-16, upper bound when adaptive; default 4
+  max_concurrent_uploads?: string; // 1-16, upper bound when adaptive; default 3
+  ytdlp_path?: string; // yt-dlp to run instead of the managed copy or the one on PATH
+  desktop_notifications?: string; // "true" for OS notifications on finished and failed items
+  clipboard_watch?: string; // "off" (default), "confirm" or "enqueue" copied video links
+  clipboard_sites?: string; // e.g. "youtube, vimeo.com"; empty for every supported site
+  webhook_url?: string; // receives a JSON POST for every status an item enters
+  webhook_secret?: string; // signs the POSTs: X-PermaVid-Signature: sha256=<hmac>
+  local_api_enabled?: string; // "true" to serve the REST API on 127.0.0.1
+  local_api_port?: string; // default 47615
+  local_api_token?: string; // at least 16 characters; sent as "Authorization: Bearer <token>"
+  local_api_lan?: string; // "true" to answer on the LAN too, e.g. /dashboard from a phone
+  max_auto_retries?: string; // automatic retries of temporary failures, 0-20 (default 3, "0" = off)
 }
 
 // Define the expected structure of the response from the trigger_upload command
@@ -130,6 +732,26 @@ export async function addQueueItem(item: QueueItem) {
   }
 }
 
+// Queue a pasted list of links; every URL gets its own result
+export async function addQueueItemsBulk(
+  urls: string[]
+): Promise<BulkAddResult[]> {
+  const userId = getCurrentUserIdClient();
+  const response: any = await invoke("add_queue_items_bulk", { urls, userId });
+  return response?.data ?? [];
+}
+
+export async function getDiskUsage(): Promise<DiskUsage | null> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("get_disk_usage", { userId });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting disk usage:", error);
+    return null;
+  }
+}
+
 export async function updateQueueItem(item: QueueItem) {
   try {
     await invoke("update_queue_item", { item });
@@ -152,7 +774,12 @@ export async function updateItemStatus(
   }
 }
 
-export async function clearCompletedItems(statusTypes: string[]) {
+// Without confirmToken nothing is cleared: the response's data.confirmation says
+// what would be, with the token to clear it
+export async function clearCompletedItems(
+  statusTypes: string[],
+  confirmToken?: string,
+) {
   try {
     console.log(
       "[Tauri API] Calling clear_completed_items with direct array:",
@@ -165,19 +792,18 @@ export async function clearCompletedItems(statusTypes: string[]) {
     const response: any = await invoke("clear_completed_items", {
       statusTypes,
       userId,
+      confirmToken,
     });
     console.log("[Tauri API] clear_completed_items response:", response);
 
     // Log detailed information from the response
-    if (response && response.success && response.data) {
+    if (response && response.success && response.data?.result) {
+      const result: ClearResult = response.data.result;
       console.log(
-        `[Tauri API] Successfully deleted ${response.data.total_deleted} items`,
+        `[Tauri API] Successfully deleted ${result.total_deleted} items`,
       );
-      console.log(
-        `[Tauri API] Status types cleared:`,
-        response.data.status_types,
-      );
-      console.log(`[Tauri API] User ID:`, response.data.user_id);
+      console.log(`[Tauri API] Status types cleared:`, result.status_types);
+      console.log(`[Tauri API] User ID:`, result.user_id);
     } else if (response && !response.success) {
       console.warn(
         "[Tauri API] Clear operation was not successful:",
@@ -316,6 +942,81 @@ export async function retryItem(id: string): Promise<string> {
 }
 // --- END ADDED ---
 
+// Upload the already-downloaded file again, without re-downloading
+export async function retryUpload(id: string): Promise<string> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("retry_upload", { id, userId });
+    return response?.message || "Upload queued";
+  } catch (error) {
+    console.error("Error retrying upload via Tauri:", error);
+    return error instanceof Error ? error.message : String(error);
+  }
+}
+
+// Queue every video of a playlist or channel URL
+export async function addPlaylist(
+  url: string,
+  limit?: number
+): Promise<PlaylistImport | null> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("add_playlist", { url, userId, limit });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("Error adding playlist via Tauri:", error);
+    return null;
+  }
+}
+
+export async function getPlaylistRollups(): Promise<PlaylistRollup[]> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("get_playlist_rollups", { userId });
+    return response?.data ?? [];
+  } catch (error) {
+    console.error("Error getting playlist rollups via Tauri:", error);
+    return [];
+  }
+}
+
+// Items of a playlist; pass status "failed" to list only its failures
+export async function getChildren(
+  parentId: string,
+  status?: string
+): Promise<QueueItem[]> {
+  try {
+    const response: any = await invoke("get_children", { parentId, status });
+    return response?.data ?? [];
+  } catch (error) {
+    console.error("Error getting playlist items via Tauri:", error);
+    return [];
+  }
+}
+
+// Move queued items to the front in the given order; returns the new processing order
+export async function reorderQueue(ids: string[]): Promise<string[]> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("reorder_queue", { userId, ids });
+    return response?.data ?? [];
+  } catch (error) {
+    console.error("Error reordering queue via Tauri:", error);
+    return [];
+  }
+}
+
+// Start a queued item next, even outside the download window
+export async function forceStartItem(id: string): Promise<boolean> {
+  try {
+    const response: any = await invoke("force_start_item", { id });
+    return response?.success ?? false;
+  } catch (error) {
+    console.error("Error force-starting item via Tauri:", error);
+    return false;
+  }
+}
+
 // --- ADDED: Function to trigger upload via Tauri ---
 export async function triggerUpload(id: string): Promise<UploadResponse> {
   try {
@@ -429,3 +1130,213 @@ export async function debugCheckStatus(
   }
 }
 // --- END ADDED ---
+
+export async function getMessageCatalog(): Promise<MessageCatalogEntry[]> {
+  try {
+    const response: any = await invoke("get_message_catalog");
+    return response?.data ?? [];
+  } catch (error) {
+    console.error("[Tauri API] Error getting message catalog:", error);
+    return [];
+  }
+}
+
+export async function listProviders(): Promise<ProviderListing | null> {
+  try {
+    const response: any = await invoke("list_providers");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error listing upload providers:", error);
+    return null;
+  }
+}
+
+export async function previewBulkEdit(
+  filter: BulkFilter,
+  edit: BulkEdit,
+): Promise<BulkEditResult | null> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("preview_bulk_edit", {
+      userId,
+      filter,
+      edit,
+    });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error previewing bulk edit:", error);
+    return null;
+  }
+}
+
+// Without confirmToken nothing changes and the confirmation to show comes back
+// instead; preview with previewBulkEdit first
+export async function applyBulkEdit(
+  filter: BulkFilter,
+  edit: BulkEdit,
+  confirmToken?: string,
+): Promise<Confirmed<BulkEditResult> | null> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("apply_bulk_edit", {
+      userId,
+      filter,
+      edit,
+      confirmToken,
+    });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error applying bulk edit:", error);
+    return null;
+  }
+}
+
+// Delete the local files of the items `filter` matches. Without confirmToken nothing
+// is deleted and the confirmation to show comes back instead.
+export async function deleteLocalFiles(
+  filter: BulkFilter,
+  confirmToken?: string,
+): Promise<Confirmed<LocalDeletion> | null> {
+  try {
+    const userId = getCurrentUserIdClient();
+    const response: any = await invoke("delete_local_files", {
+      userId,
+      filter,
+      confirmToken,
+    });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error deleting local files:", error);
+    throw error;
+  }
+}
+
+// Throws when the item has no bundle
+export async function openForensics(id: string): Promise<ForensicBundle | null> {
+  const response: any = await invoke("open_forensics", { id });
+  return response?.data ?? null;
+}
+
+// Null while the startup checks are still running
+// Returned by get_db_schema_info (see tauri/src/migrations.rs)
+export interface SchemaInfo {
+  current_version: string | null;
+  latest_version: string;
+  applied: {
+    version: string;
+    source: "app" | "prisma";
+    applied_at: string; // ISO-8601, UTC
+    modified: boolean; // the SQL changed after it was applied
+  }[];
+  pending: string[];
+  unmanaged: boolean; // tables without a migration history; nothing is applied
+}
+
+export async function getDbSchemaInfo(): Promise<SchemaInfo | null> {
+  try {
+    const response: any = await invoke("get_db_schema_info");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting schema info:", error);
+    return null;
+  }
+}
+
+export async function getStartupReport(): Promise<StartupReport | null> {
+  try {
+    const response: any = await invoke("get_startup_report");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting startup report:", error);
+    return null;
+  }
+}
+
+export async function getConcurrencyStatus(): Promise<ConcurrencyStatus | null> {
+  try {
+    const response: any = await invoke("get_concurrency_status");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting concurrency status:", error);
+    return null;
+  }
+}
+
+export async function checkYtdlpVersion(): Promise<YtdlpVersion | null> {
+  try {
+    const response: any = await invoke("check_ytdlp_version");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error checking yt-dlp version:", error);
+    return null;
+  }
+}
+
+// Download the latest yt-dlp into the app data directory; throws when the download
+// or its checksum fails
+export async function updateYtdlp(): Promise<YtdlpVersion | null> {
+  try {
+    const response: any = await invoke("update_ytdlp");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error updating yt-dlp:", error);
+    throw error;
+  }
+}
+
+export async function getEffectiveConfig(userId: string): Promise<EffectiveConfig | null> {
+  try {
+    const response: any = await invoke("get_effective_config", { userId });
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting effective config:", error);
+    return null;
+  }
+}
+
+// Post a test event to the saved webhook_url; throws when it can't be reached or
+// answers with an error
+export async function testWebhook(): Promise<WebhookTest | null> {
+  const userId = getCurrentUserIdClient();
+  const response: any = await invoke("test_webhook", { userId });
+  return response?.data ?? null;
+}
+
+// Cap the automatic retries of one item (0-20); null follows max_auto_retries again
+export async function setItemMaxRetries(
+  id: string,
+  maxRetries: number | null,
+): Promise<number | null> {
+  const response: any = await invoke("set_item_max_retries", { id, maxRetries });
+  return response?.data ?? null;
+}
+
+// Have Filemoon fetch the item from its source ("remote") or download it here ("local")
+export async function setItemTransferMode(
+  id: string,
+  mode: "remote" | "local",
+): Promise<"remote" | null> {
+  const response: any = await invoke("set_item_transfer_mode", { id, mode });
+  return response?.data ?? null;
+}
+
+// The dashboard's address with its read-only token; null while the local API is off
+export async function getDashboardUrl(): Promise<string | null> {
+  try {
+    const response: any = await invoke("get_dashboard_url");
+    return response?.data ?? null;
+  } catch (error) {
+    console.error("[Tauri API] Error getting dashboard URL:", error);
+    return null;
+  }
+}
+
+export async function getUploads(itemId: string): Promise<UploadRecord[]> {
+  try {
+    const response: any = await invoke("get_uploads", { itemId });
+    return response?.data ?? [];
+  } catch (error) {
+    console.error("[Tauri API] Error getting uploads:", error);
+    return [];
+  }
+}
@@ -0,0 +1,263 @@
+// The Internet Archive as an upload target (upload_target "archive_org"). Files go
+// through IA-S3, the Archive's S3-like API: a single PUT creates an archive.org item
+// with its metadata and stores the video in it. The keys come from
+// https://archive.org/account/s3.php and are kept in archive_org_access_key and
+// archive_org_secret_key.
+//
+// Every queue item gets its own archive.org item, named after the video where the
+// site is known ("permavid-youtube-dQw4w9WgXcQ-1a2b3c4d"). Its title, original URL
+// and date are taken from the queue item and its provenance record. The item's
+// details page is kept as upload_url, with upload_provider "archive_org". The
+// Archive derives streamable copies in the background, so the upload counts as done
+// as soon as the PUT is accepted.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use tokio_util::io::ReaderStream;
+
+use crate::db::{AppSettings, ProvenanceRecord, QueueItem};
+use crate::providers::{StepError, STEP_STATUS, STEP_UPLOAD};
+use crate::{filemoon, lenient, urls};
+
+pub const PROVIDER: &str = "archive_org";
+
+const S3_ENDPOINT: &str = "https://s3.us.archive.org";
+const DETAILS_BASE: &str = "https://archive.org/details";
+const METADATA_BASE: &str = "https://archive.org/metadata";
+const DEFAULT_COLLECTION: &str = "opensource_movies";
+const IDENTIFIER_PREFIX: &str = "permavid";
+// archive.org rejects longer identifiers
+const MAX_IDENTIFIER_LEN: usize = 100;
+
+pub struct Credentials {
+    access_key: String,
+    secret_key: String,
+}
+
+impl Credentials {
+    pub fn from_settings(settings: &AppSettings) -> Result<Self, String> {
+        let key = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(str::to_string)
+        };
+        match (
+            key(&settings.archive_org_access_key),
+            key(&settings.archive_org_secret_key),
+        ) {
+            (Some(access_key), Some(secret_key)) => Ok(Credentials {
+                access_key,
+                secret_key,
+            }),
+            _ => Err(
+                "Internet Archive keys not configured (archive_org_access_key, archive_org_secret_key)"
+                    .to_string(),
+            ),
+        }
+    }
+}
+
+// What the archive.org item says about the video
+pub struct ItemMetadata {
+    pub title: String,
+    pub source_url: String,
+    pub date: DateTime<Utc>,
+    pub subjects: Vec<String>,
+    pub language: Option<String>,
+    pub collection: String,
+}
+
+impl ItemMetadata {
+    // The date is when PermaVid captured the video, or when it was queued if there
+    // is no provenance record
+    pub fn for_item(
+        item: &QueueItem,
+        provenance: Option<&ProvenanceRecord>,
+        settings: &AppSettings,
+    ) -> Self {
+        ItemMetadata {
+            title: item
+                .title
+                .clone()
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| item.url.clone()),
+            source_url: provenance
+                .and_then(|record| record.webpage_url.clone())
+                .unwrap_or_else(|| item.url.clone()),
+            date: provenance
+                .map(|record| record.captured_at)
+                .or(item.added_at)
+                .unwrap_or_else(Utc::now),
+            subjects: item.tags.clone().unwrap_or_default(),
+            language: item.language.clone(),
+            collection: settings
+                .archive_org_collection
+                .as_deref()
+                .map(str::trim)
+                .filter(|collection| !collection.is_empty())
+                .unwrap_or(DEFAULT_COLLECTION)
+                .to_string(),
+        }
+    }
+}
+
+// archive.org identifiers allow letters, digits, '.', '-' and '_'
+pub fn identifier(item: &QueueItem) -> String {
+    let name = urls::video_key(&item.url).unwrap_or_else(|| "video".to_string());
+    let id = item.id.as_deref().unwrap_or_default();
+    let suffix: String = id.chars().filter(|c| *c != '-').take(8).collect();
+    let mut identifier: String = format!("{}-{}-{}", IDENTIFIER_PREFIX, name, suffix)
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    if identifier.len() > MAX_IDENTIFIER_LEN {
+        let keep = MAX_IDENTIFIER_LEN - suffix.len() - 1;
+        identifier = format!("{}-{}", &identifier[..keep], suffix);
+    }
+    identifier
+}
+
+// Sizes of the files uploaded to an archive.org item, leaving out the copies the
+// Archive derives from them. Empty until the item's files are listed, which can
+// take a while after the upload.
+pub async fn stored_sizes(
+    client: &reqwest::Client,
+    identifier: &str,
+) -> Result<Vec<u64>, StepError> {
+    let response = client
+        .get(format!("{}/{}/files", METADATA_BASE, identifier))
+        .send()
+        .await
+        .map_err(|e| StepError {
+            transient: filemoon::is_transient_error(&e),
+            ..StepError::new(STEP_STATUS, format!("Request failed: {}", e))
+        })?;
+    let status = response.status().as_u16();
+    if !(200..300).contains(&status) {
+        return Err(StepError {
+            http_status: Some(status),
+            transient: status == 503 || filemoon::is_transient_status(status),
+            ..StepError::new(STEP_STATUS, format!("HTTP {}", status))
+        });
+    }
+    let body: JsonValue = response
+        .json()
+        .await
+        .map_err(|e| StepError::new(STEP_STATUS, format!("Failed to parse metadata: {}", e)))?;
+    let files = body
+        .get("result")
+        .and_then(|files| files.as_array())
+        .cloned()
+        .unwrap_or_default();
+    Ok(files
+        .iter()
+        .filter(|file| file.get("source").and_then(lenient::text).as_deref() == Some("original"))
+        .filter_map(|file| file.get("size").and_then(lenient::text)?.parse().ok())
+        .collect())
+}
+
+pub fn details_url(identifier: &str) -> String {
+    format!("{}/{}", DETAILS_BASE, identifier)
+}
+
+// Header values must be plain ASCII; IA-S3 decodes values wrapped in uri(...)
+fn meta_value(text: &str) -> String {
+    format!("uri({})", urls::percent_encode(text, false))
+}
+
+// The message from an IA-S3 XML error (<Error><Message>...</Message></Error>)
+fn error_message(body: &str) -> Option<&str> {
+    let start = body.find("<Message>")? + "<Message>".len();
+    let end = body[start..].find("</Message>")? + start;
+    Some(body[start..end].trim()).filter(|message| !message.is_empty())
+}
+
+// Create the archive.org item and store the file in it
+pub async fn upload(
+    client: &reqwest::Client,
+    credentials: &Credentials,
+    identifier: &str,
+    metadata: &ItemMetadata,
+    path: &Path,
+    file_name: &str,
+    bytes: u64,
+) -> Result<(), StepError> {
+    let url = format!(
+        "{}/{}/{}",
+        S3_ENDPOINT,
+        identifier,
+        urls::percent_encode(file_name, false)
+    );
+
+    // Streamed like Filemoon uploads, so memory use stays flat
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| StepError::new(STEP_UPLOAD, format!("Failed to open file: {}", e)))?;
+    let file_stream = ReaderStream::with_capacity(file, filemoon::UPLOAD_CHUNK_BYTES);
+
+    let mut request = client
+        .put(url)
+        .header(
+            "authorization",
+            format!("LOW {}:{}", credentials.access_key, credentials.secret_key),
+        )
+        .header("x-amz-auto-make-bucket", "1")
+        .header("x-archive-size-hint", bytes.to_string())
+        .header("x-archive-meta-mediatype", "movies")
+        .header("x-archive-meta-collection", metadata.collection.as_str())
+        .header("x-archive-meta-title", meta_value(&metadata.title))
+        .header("x-archive-meta-source", meta_value(&metadata.source_url))
+        .header(
+            "x-archive-meta-originalurl",
+            meta_value(&metadata.source_url),
+        )
+        .header(
+            "x-archive-meta-date",
+            metadata.date.format("%Y-%m-%d").to_string(),
+        )
+        .header("content-length", bytes.to_string());
+    if let Some(language) = &metadata.language {
+        request = request.header("x-archive-meta-language", meta_value(language));
+    }
+    // Repeated fields are numbered: x-archive-meta01-subject, x-archive-meta02-subject
+    for (i, subject) in metadata.subjects.iter().enumerate() {
+        request = request.header(
+            format!("x-archive-meta{:02}-subject", i + 1),
+            meta_value(subject),
+        );
+    }
+
+    let response = request
+        .body(reqwest::Body::wrap_stream(file_stream))
+        .send()
+        .await
+        .map_err(|e| StepError {
+            transient: filemoon::is_transient_error(&e),
+            ..StepError::new(STEP_UPLOAD, format!("Request failed: {}", e))
+        })?;
+    let status = response.status().as_u16();
+    if (200..300).contains(&status) {
+        return Ok(());
+    }
+    let text = response.text().await.unwrap_or_default();
+    let message = match error_message(&text) {
+        Some(message) => format!("HTTP {}: {}", status, message),
+        None => format!("HTTP {}", status),
+    };
+    Err(StepError {
+        http_status: Some(status),
+        body: Some(text),
+        // 503 is IA-S3 asking clients to slow down, not maintenance as on Filemoon
+        transient: status == 503 || filemoon::is_transient_status(status),
+        ..StepError::new(STEP_UPLOAD, message)
+    })
+}
@@ -0,0 +1,244 @@
+// Rules for uploading finished downloads without anyone asking. auto_upload switches
+// it on; these settings narrow it down, and every one that is set must hold:
+//
+//   auto_upload_max_mb    only files up to this size
+//   auto_upload_tags      only items carrying one of these tags ("clips, shorts")
+//   auto_upload_sites     only items from these sites ("youtube, vimeo.com")
+//   upload_window_start/_end/_days
+//                         only inside this window, read like the download window
+//
+// The rules are checked when a download completes and when a reviewed item is
+// approved. An item that fails one stays "downloaded" for a manual upload, with the
+// reason as its message, so big raw masters wait while small clips go straight up.
+// An item that only misses the upload window waits for it instead; run() queues it
+// once the window opens.
+
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::db::{AppSettings, QueueItem};
+use crate::download_window::DownloadWindow;
+use crate::messages::{self, ItemMessage};
+use crate::{long_paths, settings_watch, urls, AppState};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, PartialEq)]
+pub enum Decision {
+    // auto_upload is off
+    Off,
+    Upload,
+    // A rule keeps the item for a manual upload; carries the reason
+    Manual(String),
+    // Everything matches but the upload window is closed; carries the window
+    AwaitWindow(String),
+}
+
+struct Rules {
+    max_bytes: Option<u64>,
+    tags: Vec<String>,
+    sites: Vec<String>,
+    window: Option<DownloadWindow>,
+}
+
+fn list(value: &Option<String>) -> Vec<String> {
+    value
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+impl Rules {
+    fn from_settings(settings: &AppSettings) -> Result<Self, String> {
+        let max_bytes = match settings
+            .auto_upload_max_mb
+            .as_deref()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+        {
+            Some(value) => match value.parse::<f64>() {
+                Ok(mb) if mb > 0.0 => Some((mb * 1024.0 * 1024.0) as u64),
+                _ => {
+                    return Err(format!(
+                        "Invalid auto_upload_max_mb '{}'; use a positive number",
+                        value
+                    ))
+                }
+            },
+            None => None,
+        };
+        Ok(Rules {
+            max_bytes,
+            tags: list(&settings.auto_upload_tags),
+            sites: list(&settings.auto_upload_sites),
+            window: DownloadWindow::parse(
+                "upload_window",
+                &settings.upload_window_start,
+                &settings.upload_window_end,
+                &settings.upload_window_days,
+            )?,
+        })
+    }
+}
+
+fn file_size(item: &QueueItem) -> Option<u64> {
+    let path = item.local_path.as_deref()?;
+    fs::metadata(long_paths::extended(Path::new(path)))
+        .ok()
+        .map(|m| m.len())
+}
+
+pub fn decide(settings: &AppSettings, item: &QueueItem, now: DateTime<Local>) -> Decision {
+    if settings.auto_upload.as_deref() != Some("true") {
+        return Decision::Off;
+    }
+    let rules = match Rules::from_settings(settings) {
+        Ok(rules) => rules,
+        Err(e) => return Decision::Manual(e),
+    };
+
+    if let Some(max_bytes) = rules.max_bytes {
+        match file_size(item) {
+            Some(size) if size > max_bytes => {
+                return Decision::Manual(format!(
+                    "{} MB is over the {} MB auto-upload limit",
+                    size / (1024 * 1024),
+                    max_bytes / (1024 * 1024)
+                ))
+            }
+            Some(_) => {}
+            None => return Decision::Manual("file size unknown".to_string()),
+        }
+    }
+    if !rules.tags.is_empty() {
+        let tagged = item.tags.as_deref().unwrap_or_default().iter().any(|tag| {
+            rules
+                .tags
+                .iter()
+                .any(|wanted| wanted.eq_ignore_ascii_case(tag.trim()))
+        });
+        if !tagged {
+            return Decision::Manual(format!("no auto-upload tag ({})", rules.tags.join(", ")));
+        }
+    }
+    if !rules.sites.is_empty() && !urls::site_matches(&item.url, &rules.sites) {
+        return Decision::Manual(format!(
+            "site is not set to auto-upload ({})",
+            rules.sites.join(", ")
+        ));
+    }
+    match rules.window {
+        Some(window) if !window.is_open(now) => Decision::AwaitWindow(window.describe()),
+        _ => Decision::Upload,
+    }
+}
+
+// Check the rule settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    Rules::from_settings(settings).map(|_| ())
+}
+
+// Decide for a finished (or approved) download and act on it: queue the upload, or
+// leave the item "downloaded" with the reason. Returns whether an upload was queued.
+pub async fn apply(app_state: &AppState, item_id: &str, user_id: &str) -> bool {
+    let settings = match app_state.db.get_settings(user_id).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Auto-upload skipped for {}: {}", item_id, e);
+            return false;
+        }
+    };
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return false,
+        Err(e) => {
+            eprintln!("Auto-upload skipped for {}: {}", item_id, e);
+            return false;
+        }
+    };
+
+    let message = match decide(&settings, &item, Local::now()) {
+        Decision::Off => return false,
+        Decision::Upload => {
+            println!("Auto-upload enabled, queueing upload for {}", item_id);
+            return match app_state
+                .uploads
+                .enqueue(item_id.to_string(), user_id.to_string())
+            {
+                Ok(()) => true,
+                Err(e) => {
+                    eprintln!("Auto-upload failed for {}: {}", item_id, e);
+                    false
+                }
+            };
+        }
+        Decision::Manual(reason) => {
+            ItemMessage::new(messages::AUTO_UPLOAD_HELD).with("reason", reason)
+        }
+        Decision::AwaitWindow(window) => {
+            ItemMessage::new(messages::AUTO_UPLOAD_WAITING).with("window", window)
+        }
+    };
+    println!("Item {}: {}", item_id, message.text());
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "downloaded", Some(message))
+        .await
+    {
+        eprintln!("Error updating status after auto-upload check: {}", e);
+    }
+    false
+}
+
+// Queue the items waiting for their owner's upload window once it opens
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting upload window watcher...");
+
+    // A settings change starts the next sweep at once (see settings_watch.rs)
+    let mut changes = app_handle.state::<AppState>().settings_watch.subscribe();
+    loop {
+        settings_watch::wait(&mut changes, CHECK_INTERVAL).await;
+        let app_state = app_handle.state::<AppState>();
+        let waiting = match app_state.db.get_items_in_statuses(&["downloaded"]).await {
+            Ok(items) => items,
+            Err(e) => {
+                eprintln!("Upload window check failed: {}", e);
+                continue;
+            }
+        };
+
+        // One settings read per user per sweep
+        let mut settings_by_user: HashMap<String, Option<AppSettings>> = HashMap::new();
+        for item in waiting
+            .iter()
+            .filter(|item| item.message_code.as_deref() == Some(messages::AUTO_UPLOAD_WAITING))
+        {
+            let (Some(item_id), Some(user_id)) = (item.id.as_deref(), item.user_id.as_deref())
+            else {
+                continue;
+            };
+            if !settings_by_user.contains_key(user_id) {
+                let settings = app_state.db.get_settings(user_id).await.ok();
+                settings_by_user.insert(user_id.to_string(), settings);
+            }
+            let opened = match settings_by_user.get(user_id) {
+                // With auto-upload switched off since, the item just stays downloaded
+                Some(Some(settings)) => !matches!(
+                    decide(settings, item, Local::now()),
+                    Decision::AwaitWindow(_) | Decision::Off
+                ),
+                _ => false,
+            };
+            if opened {
+                apply(&app_state, item_id, user_id).await;
+            }
+        }
+    }
+}
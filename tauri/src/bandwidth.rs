@@ -0,0 +1,243 @@
+// Bandwidth accounting for downloads and uploads, per item and per day, with a
+// projection of what the rest of the queue will cost against the monthly data cap
+// set by the ISP (`monthly_data_cap_gb`).
+
+use chrono::{Datelike, Duration as ChronoDuration, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::db::QueueItem;
+use crate::{long_paths, timestamps, AppState};
+
+pub const DOWNLOAD: &str = "download";
+pub const UPLOAD: &str = "upload";
+
+pub const DEFAULT_REPORT_DAYS: i64 = 31;
+pub const MAX_REPORT_DAYS: i64 = 366;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+// Items not downloaded yet
+const PENDING_DOWNLOAD_STATUSES: &[&str] = &["queued", "downloading", "blocked_disk_space"];
+// Items downloaded and still to be uploaded
+const PENDING_UPLOAD_STATUSES: &[&str] = &[
+    "downloaded",
+    "pending_review",
+    "on_hold",
+    "provider_unavailable",
+];
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct DayUsage {
+    pub day: String,
+    pub download_bytes: i64,
+    pub upload_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItemUsage {
+    pub item_id: String,
+    pub title: Option<String>,
+    pub download_bytes: i64,
+    pub upload_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PendingEstimate {
+    pub queued_items: usize,
+    // Queued items times the average download size seen so far
+    pub estimated_download_bytes: i64,
+    pub awaiting_upload_items: usize,
+    // Size of the downloaded files still to be uploaded
+    pub awaiting_upload_bytes: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BandwidthReport {
+    pub month: String,
+    pub since: String,
+    pub days: Vec<DayUsage>,
+    pub items: Vec<ItemUsage>,
+    pub month_download_bytes: i64,
+    pub month_upload_bytes: i64,
+    pub data_cap_bytes: Option<i64>,
+    pub pending: PendingEstimate,
+    // This month's traffic plus everything still pending
+    pub projected_month_bytes: i64,
+    // None when no cap is configured
+    pub will_exceed_cap: Option<bool>,
+}
+
+// Configured cap in bytes; unset, unparsable or non-positive means no cap
+fn data_cap_bytes(value: Option<&str>) -> Option<i64> {
+    value
+        .and_then(|gb| gb.trim().parse::<f64>().ok())
+        .filter(|gb| *gb > 0.0)
+        .map(|gb| (gb * BYTES_PER_GB) as i64)
+}
+
+pub async fn record(
+    app_state: &AppState,
+    user_id: &str,
+    item_id: &str,
+    direction: &str,
+    bytes: i64,
+) {
+    if bytes <= 0 {
+        return;
+    }
+    if let Err(e) = app_state
+        .db
+        .record_bandwidth(user_id, item_id, direction, bytes)
+        .await
+    {
+        eprintln!(
+            "Failed to record {} bandwidth for item {}: {}",
+            direction, item_id, e
+        );
+    }
+}
+
+pub async fn report(
+    app_state: &AppState,
+    user_id: &str,
+    days: Option<i64>,
+) -> Result<BandwidthReport, String> {
+    let days = days.unwrap_or(DEFAULT_REPORT_DAYS).clamp(1, MAX_REPORT_DAYS);
+    let now = timestamps::now();
+    let month_start = Utc
+        .with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now);
+    let since = (now - ChronoDuration::days(days - 1))
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .map(|start| Utc.from_utc_datetime(&start))
+        .unwrap_or(now);
+    // Query far enough back to cover both the requested window and this month
+    let query_since = since.min(month_start);
+
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve settings: {}", e))?;
+    let by_day = app_state
+        .db
+        .get_bandwidth_by_day(user_id, query_since)
+        .await
+        .map_err(|e| format!("Failed to retrieve bandwidth usage: {}", e))?;
+    let items = app_state
+        .db
+        .get_bandwidth_by_item(user_id, since)
+        .await
+        .map_err(|e| format!("Failed to retrieve bandwidth usage: {}", e))?;
+
+    let since_day = since.format("%Y-%m-%d").to_string();
+    let month_day = month_start.format("%Y-%m-%d").to_string();
+    let mut days_map: BTreeMap<String, DayUsage> = BTreeMap::new();
+    let (mut month_download_bytes, mut month_upload_bytes) = (0, 0);
+    for (day, direction, bytes) in by_day {
+        // Days are YYYY-MM-DD, so string order is date order
+        if day >= month_day {
+            match direction.as_str() {
+                DOWNLOAD => month_download_bytes += bytes,
+                UPLOAD => month_upload_bytes += bytes,
+                _ => {}
+            }
+        }
+        if day < since_day {
+            continue;
+        }
+        let entry = days_map.entry(day.clone()).or_insert_with(|| DayUsage {
+            day,
+            ..DayUsage::default()
+        });
+        match direction.as_str() {
+            DOWNLOAD => entry.download_bytes += bytes,
+            UPLOAD => entry.upload_bytes += bytes,
+            _ => {}
+        }
+    }
+
+    let items: Vec<ItemUsage> = items
+        .into_iter()
+        .map(|(item_id, title, download_bytes, upload_bytes)| ItemUsage {
+            item_id,
+            title,
+            download_bytes,
+            upload_bytes,
+        })
+        .collect();
+
+    let pending = estimate_pending(app_state, user_id, &items).await?;
+    // Everything still to be downloaded is uploaded afterwards too, so it counts twice
+    let pending_bytes = pending.estimated_download_bytes * 2 + pending.awaiting_upload_bytes;
+    let projected_month_bytes = month_download_bytes + month_upload_bytes + pending_bytes;
+    let data_cap_bytes = data_cap_bytes(settings.monthly_data_cap_gb.as_deref());
+
+    Ok(BandwidthReport {
+        month: now.format("%Y-%m").to_string(),
+        since: since_day,
+        days: days_map.into_values().collect(),
+        items,
+        month_download_bytes,
+        month_upload_bytes,
+        will_exceed_cap: data_cap_bytes.map(|cap| projected_month_bytes > cap),
+        data_cap_bytes,
+        pending,
+        projected_month_bytes,
+    })
+}
+
+async fn estimate_pending(
+    app_state: &AppState,
+    user_id: &str,
+    history: &[ItemUsage],
+) -> Result<PendingEstimate, String> {
+    let load = |statuses: &'static [&'static str]| async move {
+        app_state
+            .db
+            .get_items_in_statuses(statuses)
+            .await
+            .map_err(|e| format!("Failed to retrieve queue items: {}", e))
+    };
+    let mine = |item: &QueueItem| item.user_id.as_deref() == Some(user_id);
+
+    let queued_items = load(PENDING_DOWNLOAD_STATUSES)
+        .await?
+        .iter()
+        .filter(|item| mine(item))
+        .count();
+    let awaiting: Vec<_> = load(PENDING_UPLOAD_STATUSES)
+        .await?
+        .into_iter()
+        .filter(|item| mine(item))
+        .collect();
+    let awaiting_upload_bytes = awaiting
+        .iter()
+        .filter_map(|item| item.local_path.as_deref())
+        .filter_map(|path| fs::metadata(long_paths::extended(Path::new(path))).ok())
+        .map(|meta| meta.len() as i64)
+        .sum();
+
+    let downloaded: Vec<i64> = history
+        .iter()
+        .map(|item| item.download_bytes)
+        .filter(|bytes| *bytes > 0)
+        .collect();
+    let average_download = if downloaded.is_empty() {
+        0
+    } else {
+        downloaded.iter().sum::<i64>() / downloaded.len() as i64
+    };
+
+    Ok(PendingEstimate {
+        queued_items,
+        estimated_download_bytes: average_download * queued_items as i64,
+        awaiting_upload_items: awaiting.len(),
+        awaiting_upload_bytes,
+    })
+}
@@ -0,0 +1,117 @@
+// Queueing a pasted list of links at once. Each URL is checked on its own: it has to
+// be an http(s) link to a supported site, one whose videos urls::video_key recognises
+// (YouTube, Vimeo, TikTok, ...), and must not be queued already. The valid ones are
+// inserted in a single transaction and every URL gets its own result back, so the UI
+// can show which links went in and why the others didn't. Links to other sites can
+// still be added one at a time with add_queue_item.
+
+use serde::{Deserialize, Serialize};
+
+use crate::db::QueueItem;
+use crate::{urls, AppState};
+
+// URLs accepted in one call
+pub const MAX_URLS: usize = 1000;
+
+pub const RESULT_ADDED: &str = "added";
+pub const RESULT_DUPLICATE: &str = "duplicate";
+pub const RESULT_UNSUPPORTED: &str = "unsupported";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkAddResult {
+    // The URL as given
+    pub url: String,
+    // One of the RESULT_* values
+    pub result: String,
+    // The new item's id, when added
+    pub item_id: Option<String>,
+    // Why the URL was not added
+    pub reason: Option<String>,
+}
+
+// Why `url` can't be queued in bulk, if it can't
+fn unsupported_reason(url: &str) -> Option<String> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return Some("not an http(s) URL".to_string());
+    }
+    if urls::video_key(url).is_none() {
+        return Some("not a video link of a supported site".to_string());
+    }
+    None
+}
+
+pub async fn add(
+    app_state: &AppState,
+    raw_urls: &[String],
+    user_id: &str,
+) -> Result<Vec<BulkAddResult>, String> {
+    let raw_urls: Vec<&str> = raw_urls
+        .iter()
+        .map(|url| url.trim())
+        .filter(|url| !url.is_empty())
+        .collect();
+    if raw_urls.is_empty() {
+        return Err("No URLs given".to_string());
+    }
+    if raw_urls.len() > MAX_URLS {
+        return Err(format!(
+            "{} URLs given; add at most {} at once",
+            raw_urls.len(),
+            MAX_URLS
+        ));
+    }
+
+    // Results in input order; the supported URLs' are completed once inserted
+    let mut results = Vec::with_capacity(raw_urls.len());
+    let mut positions = Vec::new();
+    let mut items = Vec::new();
+    for raw in &raw_urls {
+        let url = urls::normalize_url(raw);
+        match unsupported_reason(&url) {
+            Some(reason) => results.push(BulkAddResult {
+                url: raw.to_string(),
+                result: RESULT_UNSUPPORTED.to_string(),
+                item_id: None,
+                reason: Some(reason),
+            }),
+            None => {
+                positions.push(results.len());
+                results.push(BulkAddResult {
+                    url: raw.to_string(),
+                    result: RESULT_ADDED.to_string(),
+                    item_id: None,
+                    reason: None,
+                });
+                items.push(QueueItem::queued(&url, user_id));
+            }
+        }
+    }
+    if items.is_empty() {
+        return Ok(results);
+    }
+
+    let inserted = app_state
+        .db
+        .add_queue_items(&items)
+        .await
+        .map_err(|e| format!("Database error adding items: {}", e))?;
+    let mut added = false;
+    for (position, outcome) in positions.into_iter().zip(inserted) {
+        let result = &mut results[position];
+        match outcome {
+            Ok(id) => {
+                result.item_id = Some(id);
+                added = true;
+            }
+            Err(status) => {
+                result.result = RESULT_DUPLICATE.to_string();
+                result.reason = Some(format!("already queued or archived (status: {})", status));
+            }
+        }
+    }
+
+    if added {
+        app_state.queue_wakeup.wake();
+    }
+    Ok(results)
+}
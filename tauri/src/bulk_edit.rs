@@ -0,0 +1,160 @@
+// Bulk metadata edits across the gallery, for cleaning up hundreds of legacy items
+// at once: tag everything a filter matches, find-and-replace in titles, or file items
+// under a collection. An edit runs as a single UPDATE over the filter's result; a
+// preview runs the same expressions as a SELECT and reports what would change,
+// without writing anything.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+// Items shown in a preview (or in the report of an applied edit)
+pub const PREVIEW_LIMIT: i64 = 50;
+
+// Which items an edit applies to. Every criterion that is set must match; an empty
+// filter matches the user's whole gallery.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BulkFilter {
+    pub ids: Option<Vec<String>>,
+    pub statuses: Option<Vec<String>>,
+    pub tag: Option<String>,
+    // Case-insensitive substring matches
+    pub title_contains: Option<String>,
+    pub url_contains: Option<String>,
+    pub playlist_id: Option<String>,
+    pub collection: Option<String>,
+}
+
+// WHERE clause for a BulkFilter, with the user id as $1 and the filter's fields as
+// $2..$8 in declaration order
+pub const FILTER_SQL: &str = "user_id = $1
+    AND ($2::TEXT[] IS NULL OR id = ANY($2))
+    AND ($3::TEXT[] IS NULL OR status = ANY($3))
+    AND ($4::TEXT IS NULL OR $4 = ANY(tags))
+    AND ($5::TEXT IS NULL OR strpos(lower(COALESCE(title, '')), lower($5)) > 0)
+    AND ($6::TEXT IS NULL OR strpos(lower(url), lower($6)) > 0)
+    AND ($7::TEXT IS NULL OR playlist_id = $7)
+    AND ($8::TEXT IS NULL OR collection = $8)";
+
+fn blank_to_none(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+impl BulkFilter {
+    // Blank strings and empty lists mean "any"
+    pub fn normalized(&self) -> Self {
+        let list = |values: &Option<Vec<String>>| {
+            values.as_ref().filter(|values| !values.is_empty()).cloned()
+        };
+        BulkFilter {
+            ids: list(&self.ids),
+            statuses: list(&self.statuses),
+            tag: blank_to_none(&self.tag),
+            title_contains: blank_to_none(&self.title_contains),
+            url_contains: blank_to_none(&self.url_contains),
+            playlist_id: blank_to_none(&self.playlist_id),
+            collection: blank_to_none(&self.collection),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkEdit {
+    AddTag { tag: String },
+    RemoveTag { tag: String },
+    // Literal, case-sensitive replacement of every occurrence of `find`
+    ReplaceInTitle { find: String, replace: String },
+    // None (or blank) takes the items out of their collection
+    SetCollection { collection: Option<String> },
+}
+
+impl BulkEdit {
+    pub fn normalized(&self) -> Result<Self, String> {
+        match self {
+            BulkEdit::AddTag { tag } | BulkEdit::RemoveTag { tag } => {
+                let tag = tag.trim().to_string();
+                if tag.is_empty() {
+                    return Err("Tag must not be empty".to_string());
+                }
+                Ok(match self {
+                    BulkEdit::AddTag { .. } => BulkEdit::AddTag { tag },
+                    _ => BulkEdit::RemoveTag { tag },
+                })
+            }
+            BulkEdit::ReplaceInTitle { find, replace } => {
+                if find.is_empty() {
+                    return Err("Text to find must not be empty".to_string());
+                }
+                Ok(BulkEdit::ReplaceInTitle {
+                    find: find.clone(),
+                    replace: replace.clone(),
+                })
+            }
+            BulkEdit::SetCollection { collection } => Ok(BulkEdit::SetCollection {
+                collection: blank_to_none(collection),
+            }),
+        }
+    }
+
+    // The column the edit writes and the SQL expression for its new value, reading
+    // params() from $9 on
+    pub fn assignment(&self) -> (&'static str, &'static str) {
+        match self {
+            BulkEdit::AddTag { .. } => (
+                "tags",
+                "CASE WHEN $9::TEXT = ANY(tags) THEN tags ELSE array_append(tags, $9::TEXT) END",
+            ),
+            BulkEdit::RemoveTag { .. } => ("tags", "array_remove(tags, $9::TEXT)"),
+            BulkEdit::ReplaceInTitle { .. } => ("title", "replace(title, $9::TEXT, $10::TEXT)"),
+            BulkEdit::SetCollection { .. } => ("collection", "$9::TEXT"),
+        }
+    }
+
+    pub fn params(&self) -> Vec<Option<String>> {
+        match self {
+            BulkEdit::AddTag { tag } | BulkEdit::RemoveTag { tag } => vec![Some(tag.clone())],
+            BulkEdit::ReplaceInTitle { find, replace } => {
+                vec![Some(find.clone()), Some(replace.clone())]
+            }
+            BulkEdit::SetCollection { collection } => vec![collection.clone()],
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            BulkEdit::AddTag { tag } => format!("add tag '{}'", tag),
+            BulkEdit::RemoveTag { tag } => format!("remove tag '{}'", tag),
+            BulkEdit::ReplaceInTitle { find, replace } => {
+                format!("replace '{}' with '{}' in titles", find, replace)
+            }
+            BulkEdit::SetCollection {
+                collection: Some(collection),
+            } => format!("set collection to '{}'", collection),
+            BulkEdit::SetCollection { collection: None } => "clear collection".to_string(),
+        }
+    }
+}
+
+// One item an edit changes, with the edited column's value before and after
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkChange {
+    pub id: String,
+    pub title: Option<String>,
+    pub before: JsonValue,
+    pub after: JsonValue,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkEditResult {
+    pub dry_run: bool,
+    // Items the filter matched
+    pub matched: i64,
+    // Items whose value actually changes (or changed)
+    pub changed: i64,
+    // The first PREVIEW_LIMIT of those, newest first
+    pub changes: Vec<BulkChange>,
+}
@@ -0,0 +1,244 @@
+// Per-item cancellation tokens for in-flight downloads and uploads.
+// cancel_item trips the token and whichever stage is running for the item
+// (yt-dlp and its ffmpeg post-processing, or the upload request) aborts.
+// The PID of each item's yt-dlp process is tracked too, so cancelling one item
+// kills exactly that process tree and never the downloads of other items.
+//
+// On Unix every child that may be killed is started in a process group of its own
+// (see in_own_group), so killing the group also stops whatever it spawned, however
+// deep: yt-dlp's ffmpeg, or the shell commands a hook runs. Windows kills the tree
+// with taskkill /T.
+//
+// Each registration gets a generation number, so a guard that outlives a newer
+// registration for the same item (a retry that started before the old stage
+// finished unwinding) leaves the newer token and PID in place when it is dropped.
+
+use std::collections::HashMap;
+use std::io;
+use std::process::{Output, Stdio};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use tokio::time::error::Elapsed;
+use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
+
+#[derive(Default)]
+pub struct CancelRegistry {
+    tokens: Mutex<HashMap<String, (u64, CancellationToken)>>,
+    processes: Mutex<HashMap<String, (u64, u32)>>,
+    next_generation: AtomicU64,
+}
+
+// Keeps an item's token registered while a stage runs and removes it when dropped
+pub struct CancelGuard<'a> {
+    registry: &'a CancelRegistry,
+    id: String,
+    generation: u64,
+    token: CancellationToken,
+}
+
+impl CancelRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Register a fresh token for an item that is entering an active stage
+    pub fn register(&self, id: &str) -> CancelGuard<'_> {
+        let token = CancellationToken::new();
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        self.tokens
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), (generation, token.clone()));
+        CancelGuard {
+            registry: self,
+            id: id.to_string(),
+            generation,
+            token,
+        }
+    }
+
+    // Cancel the active stage for an item. Returns false if nothing was running.
+    pub fn cancel(&self, id: &str) -> bool {
+        match self.tokens.lock().unwrap().get(id) {
+            Some((_, token)) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    // PID of the child process running for an item, if any
+    pub fn process_id(&self, id: &str) -> Option<u32> {
+        self.processes.lock().unwrap().get(id).map(|&(_, pid)| pid)
+    }
+}
+
+impl CancelGuard<'_> {
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    // Record the child process of this stage; forgotten together with the token
+    pub fn track_process(&self, pid: u32) {
+        self.registry
+            .processes
+            .lock()
+            .unwrap()
+            .insert(self.id.clone(), (self.generation, pid));
+    }
+}
+
+impl Drop for CancelGuard<'_> {
+    // Only this guard's own entries go; a newer registration for the item stays
+    fn drop(&mut self) {
+        let mut tokens = self.registry.tokens.lock().unwrap();
+        if tokens.get(&self.id).map(|&(generation, _)| generation) == Some(self.generation) {
+            tokens.remove(&self.id);
+        }
+        drop(tokens);
+        let mut processes = self.registry.processes.lock().unwrap();
+        if processes.get(&self.id).map(|&(generation, _)| generation) == Some(self.generation) {
+            processes.remove(&self.id);
+        }
+    }
+}
+
+// Start the command as the leader of a new process group, so kill_pid_tree can stop
+// it together with everything it spawns
+pub fn in_own_group(cmd: &mut Command) -> &mut Command {
+    #[cfg(unix)]
+    cmd.process_group(0);
+    cmd
+}
+
+// Kill a child process together with anything it spawned (yt-dlp runs ffmpeg as a child)
+pub async fn kill_process_tree(child: &mut tokio::process::Child) {
+    // Already gone, e.g. killed directly by cancel_item
+    if let Ok(Some(_)) = child.try_wait() {
+        return;
+    }
+    if let Some(pid) = child.id() {
+        kill_pid_tree(pid).await;
+    }
+}
+
+async fn read_all(pipe: Option<impl AsyncRead + Unpin>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    if let Some(mut pipe) = pipe {
+        let _ = pipe.read_to_end(&mut bytes).await;
+    }
+    bytes
+}
+
+// Command::output with a time limit. A command that runs over is killed together
+// with everything it started.
+pub async fn output_with_timeout(
+    cmd: &mut Command,
+    limit: Duration,
+) -> Result<io::Result<Output>, Elapsed> {
+    in_own_group(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(e) => return Ok(Err(e)),
+    };
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+
+    let result = timeout(limit, async {
+        let (status, stdout, stderr) =
+            tokio::join!(child.wait(), read_all(stdout), read_all(stderr));
+        status.map(|status| Output {
+            status,
+            stdout,
+            stderr,
+        })
+    })
+    .await;
+    if result.is_err() {
+        kill_process_tree(&mut child).await;
+    }
+    result
+}
+
+// Kill a process by PID along with its children
+pub async fn kill_pid_tree(pid: u32) {
+    let pid_arg = pid.to_string();
+    if cfg!(target_os = "windows") {
+        let result = tokio::process::Command::new("taskkill")
+            .args(["/PID", pid_arg.as_str(), "/T", "/F"])
+            .output()
+            .await;
+        match result {
+            Ok(output) if output.status.success() => {}
+            Ok(output) => eprintln!(
+                "taskkill failed for PID {}: {}",
+                pid,
+                String::from_utf8_lossy(&output.stderr)
+            ),
+            Err(e) => eprintln!("Error executing taskkill for PID {}: {}", pid, e),
+        }
+        return;
+    }
+
+    #[cfg(unix)]
+    {
+        // The whole group the process leads (see in_own_group)
+        if unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGKILL) } == 0 {
+            return;
+        }
+    }
+
+    // Not a group leader: children first, so ffmpeg is not left running once its
+    // parent is gone. pkill exits with 1 when there are no children, which is fine.
+    if let Err(e) = tokio::process::Command::new("pkill")
+        .args(["-KILL", "-P", pid_arg.as_str()])
+        .output()
+        .await
+    {
+        eprintln!("Error executing pkill for children of PID {}: {}", pid, e);
+    }
+    match tokio::process::Command::new("kill")
+        .args(["-KILL", pid_arg.as_str()])
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => eprintln!(
+            "kill failed for PID {}: {}",
+            pid,
+            String::from_utf8_lossy(&output.stderr)
+        ),
+        Err(e) => eprintln!("Error executing kill for PID {}: {}", pid, e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_stale_guard_leaves_the_newer_registration_alone() {
+        let registry = CancelRegistry::new();
+        let old = registry.register("item");
+        old.track_process(1);
+        let new = registry.register("item");
+        new.track_process(2);
+
+        drop(old);
+        assert_eq!(registry.process_id("item"), Some(2));
+        assert!(registry.cancel("item"));
+        assert!(new.token().is_cancelled());
+
+        drop(new);
+        assert_eq!(registry.process_id("item"), None);
+        assert!(!registry.cancel("item"));
+    }
+}
@@ -0,0 +1,184 @@
+// Capability discovery for the frontend and third-party integrators: what this
+// backend build is, which optional features it has, and which schema version each
+// command speaks, so callers can adapt instead of probing and guessing.
+
+use serde::{Deserialize, Serialize};
+
+use crate::hooks;
+use crate::notifier;
+use crate::quota;
+
+// Version of the { success, message, data } envelope every command returns
+pub const RESPONSE_ENVELOPE_VERSION: u32 = 1;
+
+pub const UPLOAD_PROVIDERS: &[&str] = &["filemoon", "archive_org", "s3"];
+
+// Commands and the version of their argument/response schema. Bump a command's
+// version whenever its arguments or `data` change incompatibly; new commands
+// start at 1. Keep in step with generate_handler! in main.rs.
+const COMMAND_VERSIONS: &[(&str, u32)] = &[
+    ("open_external_link", 1),
+    // 2: added_at/updated_at are ISO-8601 strings instead of epoch milliseconds
+    ("get_queue_items", 2),
+    ("add_queue_item", 1),
+    ("update_queue_item", 1),
+    ("update_item_status", 1),
+    // 2: two-step; returns a confirmation until called with its confirm_token
+    ("clear_completed_items", 2),
+    ("get_settings", 1),
+    ("save_settings", 1),
+    ("get_global_settings", 1),
+    // 2: takes the user_id of an admin
+    ("save_global_settings", 2),
+    ("get_download_directory", 1),
+    ("create_directory", 1),
+    ("import_from_file", 1),
+    ("retry_item", 1),
+    ("trigger_upload", 1),
+    ("cancel_item", 1),
+    ("debug_check_status", 1),
+    ("run_diagnostics", 1),
+    ("create_snapshot", 1),
+    ("list_snapshots", 1),
+    ("restore_from_snapshot", 1),
+    ("tail_item_output", 1),
+    ("add_by_id", 1),
+    ("get_embed_info", 1),
+    ("refresh_all_statuses", 1),
+    ("get_provider_errors", 1),
+    ("lock_item", 1),
+    ("unlock_item", 1),
+    ("get_quota_report", 1),
+    ("get_bandwidth_report", 1),
+    ("set_download_sections", 1),
+    ("set_mirror_urls", 1),
+    ("set_item_priority", 1),
+    ("resume_cancelled", 1),
+    ("get_instance_role", 1),
+    ("set_worker_enabled", 1),
+    ("get_worker_enabled", 1),
+    ("get_provenance", 1),
+    ("export_provenance", 1),
+    ("get_changes_since", 1),
+    ("get_notification_rules", 1),
+    ("save_notification_rule", 1),
+    ("delete_notification_rule", 1),
+    ("retry_all_failed", 1),
+    ("approve_upload", 1),
+    ("approve_uploads", 1),
+    ("set_item_thumbnail", 1),
+    ("get_api_capabilities", 1),
+    ("get_queue_templates", 1),
+    ("save_queue_template", 1),
+    ("delete_queue_template", 1),
+    ("instantiate_template", 1),
+    ("find_duplicates", 1),
+    // 2: two-step; returns a confirmation until called with its confirm_token
+    ("merge_duplicates", 2),
+    ("get_active_jobs", 1),
+    ("cancel_job", 1),
+    ("get_item_timeline", 1),
+    ("get_message_catalog", 1),
+    ("get_hook_runs", 1),
+    ("get_disk_space", 1),
+    ("retry_upload", 1),
+    ("create_share_token", 1),
+    ("resolve_share_token", 1),
+    ("export_share_link", 1),
+    ("plan_queue", 1),
+    ("get_live_progress", 1),
+    ("flush_progress", 1),
+    ("add_playlist", 1),
+    ("get_playlists", 1),
+    ("explain_slow_queries", 1),
+    ("get_connection_status", 1),
+    ("get_available_formats", 1),
+    ("set_item_format", 1),
+    ("capture_page", 1),
+    ("get_playlist_rollups", 1),
+    ("get_children", 1),
+    ("reorder_queue", 1),
+    ("force_start_item", 1),
+    ("list_providers", 1),
+    ("preview_bulk_edit", 1),
+    // 2: two-step; returns a confirmation until called with its confirm_token
+    ("apply_bulk_edit", 2),
+    ("get_uploads", 1),
+    ("add_queue_items_bulk", 1),
+    ("get_startup_report", 1),
+    ("open_forensics", 1),
+    ("get_concurrency_status", 1),
+    ("get_disk_usage", 1),
+    ("check_ytdlp_version", 1),
+    ("update_ytdlp", 1),
+    ("delete_local_files", 1),
+    ("get_effective_config", 1),
+    ("test_webhook", 1),
+    ("set_item_max_retries", 1),
+    ("get_db_schema_info", 1),
+    ("set_item_transfer_mode", 1),
+    ("reset_settings", 1),
+    ("set_user_admin", 1),
+    ("get_dashboard_url", 1),
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub backend_version: String,
+    pub response_envelope_version: u32,
+    pub features: Features,
+    pub commands: Vec<CommandVersion>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Features {
+    pub upload_providers: Vec<String>,
+    // Providers with monthly quota tracking
+    pub quota_providers: Vec<String>,
+    pub notification_channels: Vec<String>,
+    // Placeholders accepted in hook script command lines
+    pub hook_placeholders: Vec<String>,
+    // Whether this backend serves the local HTTP API
+    pub http_api: bool,
+    // Whether this instance runs the download/upload worker
+    pub worker: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommandVersion {
+    pub name: String,
+    pub version: u32,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+// `declared_providers` are the upload providers loaded from providers.json
+pub fn describe(worker: bool, http_api: bool, declared_providers: Vec<String>) -> Capabilities {
+    Capabilities {
+        backend_version: env!("CARGO_PKG_VERSION").to_string(),
+        response_envelope_version: RESPONSE_ENVELOPE_VERSION,
+        features: Features {
+            upload_providers: strings(UPLOAD_PROVIDERS)
+                .into_iter()
+                .chain(declared_providers)
+                .collect(),
+            quota_providers: strings(quota::PROVIDERS),
+            notification_channels: strings(&[
+                notifier::CHANNEL_TELEGRAM,
+                notifier::CHANNEL_WEBHOOK,
+            ]),
+            hook_placeholders: strings(hooks::PLACEHOLDERS),
+            http_api,
+            worker,
+        },
+        commands: COMMAND_VERSIONS
+            .iter()
+            .map(|(name, version)| CommandVersion {
+                name: name.to_string(),
+                version: *version,
+            })
+            .collect(),
+    }
+}
@@ -0,0 +1,225 @@
+// Opt-in clipboard monitoring. With `clipboard_watch` set to "confirm" or "enqueue",
+// the primary instance reads the clipboard text every POLL_INTERVAL and looks for
+// video links of supported sites, the ones urls::video_key recognises. Every new copy
+// holding such links sends one `clipboard://url-detected` event: with "confirm" the
+// UI asks before queuing them, with "enqueue" they are queued first (the same way as
+// add_queue_items_bulk) and the event carries the results. `clipboard_sites` narrows
+// the sites down ("youtube, vimeo.com"), matched like auto_upload_sites.
+//
+// The watcher follows the active user's settings (see settings_watch.rs) and only
+// reacts to text copied while it is on, never to what was on the clipboard already.
+// Clipboard contents are neither stored nor logged; only a hash of the last text is
+// kept, to tell a new copy from the same one.
+
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+use tauri::{AppHandle, ClipboardManager, Manager};
+use tokio::time::sleep;
+
+use crate::bulk_add::{self, BulkAddResult};
+use crate::db::AppSettings;
+use crate::{urls, AppState};
+
+pub const MODE_OFF: &str = "off";
+pub const MODE_CONFIRM: &str = "confirm";
+pub const MODE_ENQUEUE: &str = "enqueue";
+
+pub const EVENT_URL_DETECTED: &str = "clipboard://url-detected";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(1500);
+// Links taken from one copy
+const MAX_URLS: usize = 50;
+
+// Payload of EVENT_URL_DETECTED
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlDetected {
+    pub user_id: String,
+    // MODE_CONFIRM or MODE_ENQUEUE
+    pub mode: String,
+    // Normalized, in the order they were copied
+    pub urls: Vec<String>,
+    // One per URL with MODE_ENQUEUE; None when confirming, or when queuing failed
+    pub results: Option<Vec<BulkAddResult>>,
+}
+
+fn mode_of(settings: &AppSettings) -> &str {
+    settings
+        .clipboard_watch
+        .as_deref()
+        .map(str::trim)
+        .filter(|mode| !mode.is_empty())
+        .unwrap_or(MODE_OFF)
+}
+
+fn sites_of(settings: &AppSettings) -> Vec<String> {
+    settings
+        .clipboard_sites
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(|site| site.trim().to_lowercase())
+        .filter(|site| !site.is_empty())
+        .collect()
+}
+
+// Check the clipboard settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    match mode_of(settings) {
+        MODE_OFF | MODE_CONFIRM | MODE_ENQUEUE => Ok(()),
+        other => Err(format!(
+            "Invalid clipboard_watch '{}'; use {}, {} or {}",
+            other, MODE_OFF, MODE_CONFIRM, MODE_ENQUEUE
+        )),
+    }
+}
+
+// The supported video links in `text` from one of `sites` (any site when empty),
+// normalized and without repeats
+fn detect(text: &str, sites: &[String]) -> Vec<String> {
+    let mut found = Vec::new();
+    for word in text.split_whitespace() {
+        let word =
+            word.trim_matches(|c: char| matches!(c, '<' | '>' | '(' | ')' | '"' | '\'' | ','));
+        if !(word.starts_with("http://") || word.starts_with("https://")) {
+            continue;
+        }
+        let url = urls::normalize_url(word);
+        if urls::video_key(&url).is_none() {
+            continue;
+        }
+        if !sites.is_empty() && !urls::site_matches(&url, sites) {
+            continue;
+        }
+        if !found.contains(&url) {
+            found.push(url);
+        }
+        if found.len() == MAX_URLS {
+            break;
+        }
+    }
+    found
+}
+
+#[derive(Default)]
+struct Watch {
+    user_id: String,
+    mode: String,
+    sites: Vec<String>,
+}
+
+impl Watch {
+    async fn load(app_state: &AppState, user_id: String) -> Self {
+        match app_state.db.get_settings(&user_id).await {
+            Ok(settings) => Self {
+                mode: mode_of(&settings).to_string(),
+                sites: sites_of(&settings),
+                user_id,
+            },
+            Err(e) => {
+                eprintln!("Clipboard watcher could not load settings: {}", e);
+                Self {
+                    user_id,
+                    ..Self::default()
+                }
+            }
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.mode == MODE_CONFIRM || self.mode == MODE_ENQUEUE
+    }
+}
+
+// The clipboard text; empty when it holds something else or can't be read
+async fn read_text(app_handle: &AppHandle) -> String {
+    let handle = app_handle.clone();
+    // Reading goes through the main thread's event loop, so wait for it off the runtime
+    match tokio::task::spawn_blocking(move || handle.clipboard_manager().read_text()).await {
+        Ok(Ok(text)) => text.unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+fn fingerprint(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+// Watch the clipboard while the active user has clipboard_watch on, until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting clipboard watcher...");
+    let app_state = app_handle.state::<AppState>();
+    let mut changes = app_state.settings_watch.subscribe();
+    let mut watch = Watch::default();
+    let mut reload = true;
+    // Fingerprint of the text last looked at; while None, the next read only sets it
+    let mut last_seen: Option<u64> = None;
+
+    loop {
+        let user_id = app_state
+            .settings_watch
+            .active_user()
+            .unwrap_or_else(|| "local-user".to_string());
+        if reload || watch.user_id != user_id {
+            let was_on = watch.is_on();
+            watch = Watch::load(&app_state, user_id).await;
+            if !was_on {
+                last_seen = None;
+            }
+            reload = false;
+        }
+
+        if watch.is_on() {
+            let text = read_text(&app_handle).await;
+            let seen = fingerprint(&text);
+            if last_seen.is_some() && last_seen != Some(seen) {
+                let urls = detect(&text, &watch.sites);
+                if !urls.is_empty() {
+                    report(&app_handle, &watch, urls).await;
+                }
+            }
+            last_seen = Some(seen);
+        }
+
+        tokio::select! {
+            changed = changes.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                reload = true;
+            }
+            _ = sleep(POLL_INTERVAL) => {}
+        }
+    }
+
+    println!("Clipboard watcher stopped");
+}
+
+async fn report(app_handle: &AppHandle, watch: &Watch, urls: Vec<String>) {
+    println!("Clipboard: {} video link(s) copied", urls.len());
+    let results = if watch.mode == MODE_ENQUEUE {
+        let app_state = app_handle.state::<AppState>();
+        match bulk_add::add(&app_state, &urls, &watch.user_id).await {
+            Ok(results) => Some(results),
+            Err(e) => {
+                eprintln!("Failed to queue copied links: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let payload = UrlDetected {
+        user_id: watch.user_id.clone(),
+        mode: watch.mode.clone(),
+        urls,
+        results,
+    };
+    if let Err(e) = app_handle.emit_all(EVENT_URL_DETECTED, payload) {
+        eprintln!("Failed to emit {} event: {}", EVENT_URL_DETECTED, e);
+    }
+}
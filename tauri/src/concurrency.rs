@@ -0,0 +1,377 @@
+// How many downloads and uploads run at once. By default one of each, and downloads
+// wait while an upload runs. With adaptive_concurrency set to "true" each direction
+// is tuned from what it measures, between one transfer and max_concurrent_downloads
+// (default 4) or max_concurrent_uploads (default 3), and downloads no longer wait
+// for uploads.
+//
+// The limit is reconsidered after every EPOCH_TRANSFERS finished transfers:
+//
+//   - at least half of them failed (errors, stalls): halve the limit
+//   - speed per transfer collapsed, below COLLAPSE_RATIO of an even share of the
+//     best speed seen: one fewer
+//   - the last step up raised the total speed by less than MIN_GAIN: one fewer, and
+//     stay there for HOLD
+//   - otherwise one more, up to the maximum
+//
+// Failures that say nothing about the connection, such as a removed video, are not
+// counted.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::bandwidth;
+use crate::db::AppSettings;
+use crate::timestamps;
+
+pub const DEFAULT_MAX_DOWNLOADS: usize = 4;
+pub const DEFAULT_MAX_UPLOADS: usize = 3;
+pub const MAX_LIMIT: usize = 16;
+
+const EPOCH_TRANSFERS: usize = 3;
+const FAILURE_BACKOFF_RATE: f64 = 0.5;
+const COLLAPSE_RATIO: f64 = 0.5;
+const MIN_GAIN: f64 = 0.1;
+const HOLD: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LimitChange {
+    pub at: DateTime<Utc>,
+    pub from: usize,
+    pub to: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectionStatus {
+    pub limit: usize,
+    pub max: usize,
+    pub active: usize,
+    // Total speed over the last finished epoch
+    pub bytes_per_sec: Option<f64>,
+    pub last_change: Option<LimitChange>,
+}
+
+// Returned by get_concurrency_status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcurrencyStatus {
+    pub adaptive: bool,
+    pub downloads: DirectionStatus,
+    pub uploads: DirectionStatus,
+}
+
+struct Transfer {
+    bytes: i64,
+    secs: f64,
+    finished: Instant,
+    failed: bool,
+}
+
+struct Direction {
+    limit: usize,
+    max: usize,
+    active: HashSet<String>,
+    // Transfers finished since the limit last changed
+    epoch: Vec<Transfer>,
+    // Total speed last measured at each limit
+    speed_at: HashMap<usize, f64>,
+    best_stream_speed: f64,
+    // No stepping above this limit until the instant passes
+    held: Option<(usize, Instant)>,
+    last_change: Option<LimitChange>,
+}
+
+impl Direction {
+    fn new(max: usize) -> Self {
+        Self {
+            limit: 1,
+            max,
+            active: HashSet::new(),
+            epoch: Vec::new(),
+            speed_at: HashMap::new(),
+            best_stream_speed: 0.0,
+            held: None,
+            last_change: None,
+        }
+    }
+
+    // Set the range; the limit stays where it is when it still fits
+    fn configure(&mut self, adaptive: bool, max: usize) {
+        self.max = if adaptive { max } else { 1 };
+        self.limit = self.limit.clamp(1, self.max);
+    }
+
+    fn finish(&mut self, transfer: Transfer, direction: &str) {
+        self.epoch.push(transfer);
+        if self.epoch.len() < EPOCH_TRANSFERS {
+            return;
+        }
+        let epoch = std::mem::take(&mut self.epoch);
+        if let Some((to, reason)) = self.decide(&epoch) {
+            println!(
+                "Concurrent {}s: {} -> {} ({})",
+                direction, self.limit, to, reason
+            );
+            self.last_change = Some(LimitChange {
+                at: timestamps::now(),
+                from: self.limit,
+                to,
+                reason,
+            });
+            self.limit = to;
+        }
+    }
+
+    fn decide(&mut self, epoch: &[Transfer]) -> Option<(usize, String)> {
+        let failures = epoch.iter().filter(|t| t.failed).count();
+        if failures as f64 / epoch.len() as f64 >= FAILURE_BACKOFF_RATE {
+            let to = (self.limit / 2).max(1);
+            return (to < self.limit).then(|| {
+                (
+                    to,
+                    format!("{} of {} transfers failed", failures, epoch.len()),
+                )
+            });
+        }
+
+        let finished: Vec<&Transfer> = epoch.iter().filter(|t| !t.failed).collect();
+        let bytes: i64 = finished.iter().map(|t| t.bytes).sum();
+        let busy_secs: f64 = finished.iter().map(|t| t.secs).sum();
+        // Wall time from the first start to the last finish, over which they overlapped
+        let started = finished
+            .iter()
+            .map(|t| {
+                t.finished
+                    .checked_sub(Duration::from_secs_f64(t.secs))
+                    .unwrap_or(t.finished)
+            })
+            .min();
+        let ended = finished.iter().map(|t| t.finished).max();
+        let span_secs = match (started, ended) {
+            (Some(started), Some(ended)) => (ended - started).as_secs_f64(),
+            _ => 0.0,
+        };
+        if bytes <= 0 || busy_secs <= 0.0 || span_secs <= 0.0 {
+            return None;
+        }
+        let speed = bytes as f64 / span_secs;
+        let stream_speed = bytes as f64 / busy_secs;
+        self.speed_at.insert(self.limit, speed);
+        let best_stream_speed = self.best_stream_speed;
+        self.best_stream_speed = best_stream_speed.max(stream_speed);
+
+        // Sharing the line slows each transfer down, but not below an even share
+        let fair_share = best_stream_speed / self.limit as f64;
+        if self.limit > 1 && stream_speed < fair_share * COLLAPSE_RATIO {
+            return Some((
+                self.limit - 1,
+                format!(
+                    "speed per transfer collapsed to {:.0}% of an even share",
+                    stream_speed / fair_share * 100.0
+                ),
+            ));
+        }
+        if let Some(below) = self.speed_at.get(&(self.limit - 1)) {
+            if speed < below * (1.0 + MIN_GAIN) {
+                self.held = Some((self.limit - 1, Instant::now() + HOLD));
+                return Some((
+                    self.limit - 1,
+                    format!(
+                        "{} at once were no faster than {}",
+                        self.limit,
+                        self.limit - 1
+                    ),
+                ));
+            }
+        }
+        let ceiling = match self.held {
+            Some((ceiling, until)) if until > Instant::now() => ceiling,
+            _ => self.max,
+        };
+        (self.limit < ceiling.min(self.max))
+            .then(|| (self.limit + 1, "throughput held up".to_string()))
+    }
+
+    fn status(&self) -> DirectionStatus {
+        DirectionStatus {
+            limit: self.limit,
+            max: self.max,
+            active: self.active.len(),
+            bytes_per_sec: self.speed_at.get(&self.limit).copied(),
+            last_change: self.last_change.clone(),
+        }
+    }
+}
+
+struct Inner {
+    adaptive: bool,
+    downloads: Direction,
+    uploads: Direction,
+}
+
+impl Inner {
+    fn direction(&mut self, direction: &str) -> &mut Direction {
+        if direction == bandwidth::UPLOAD {
+            &mut self.uploads
+        } else {
+            &mut self.downloads
+        }
+    }
+}
+
+// A running transfer; its place is given back when dropped
+pub struct Slot {
+    inner: Arc<Mutex<Inner>>,
+    released: Arc<Notify>,
+    direction: &'static str,
+    item_id: String,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.inner
+            .lock()
+            .unwrap()
+            .direction(self.direction)
+            .active
+            .remove(&self.item_id);
+        self.released.notify_one();
+    }
+}
+
+pub struct Concurrency {
+    inner: Arc<Mutex<Inner>>,
+    // Wakes the upload worker when an upload slot frees up
+    released: Arc<Notify>,
+}
+
+fn max_setting(value: &Option<String>, default: usize) -> Result<usize, String> {
+    match value.as_deref().map(str::trim).filter(|v| !v.is_empty()) {
+        Some(value) => match value.parse::<usize>() {
+            Ok(max) if (1..=MAX_LIMIT).contains(&max) => Ok(max),
+            _ => Err(format!(
+                "Invalid concurrency limit '{}'; use a number from 1 to {}",
+                value, MAX_LIMIT
+            )),
+        },
+        None => Ok(default),
+    }
+}
+
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    max_setting(&settings.max_concurrent_downloads, DEFAULT_MAX_DOWNLOADS)?;
+    max_setting(&settings.max_concurrent_uploads, DEFAULT_MAX_UPLOADS)?;
+    Ok(())
+}
+
+impl Default for Concurrency {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                adaptive: false,
+                downloads: Direction::new(1),
+                uploads: Direction::new(1),
+            })),
+            released: Arc::new(Notify::new()),
+        }
+    }
+}
+
+impl Concurrency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Apply the global settings; called by the queue processor on every pass
+    pub fn configure(&self, settings: &AppSettings) {
+        let adaptive = settings.adaptive_concurrency.as_deref() == Some("true");
+        let max_downloads = max_setting(&settings.max_concurrent_downloads, DEFAULT_MAX_DOWNLOADS)
+            .unwrap_or(DEFAULT_MAX_DOWNLOADS);
+        let max_uploads = max_setting(&settings.max_concurrent_uploads, DEFAULT_MAX_UPLOADS)
+            .unwrap_or(DEFAULT_MAX_UPLOADS);
+        let mut inner = self.inner.lock().unwrap();
+        if inner.adaptive != adaptive {
+            println!(
+                "Adaptive concurrency {}",
+                if adaptive { "on" } else { "off" }
+            );
+        }
+        inner.adaptive = adaptive;
+        inner.downloads.configure(adaptive, max_downloads);
+        inner.uploads.configure(adaptive, max_uploads);
+        drop(inner);
+        // A raised limit may let a waiting upload start
+        self.released.notify_one();
+    }
+
+    pub fn is_adaptive(&self) -> bool {
+        self.inner.lock().unwrap().adaptive
+    }
+
+    pub fn has_free_slot(&self, direction: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let direction = inner.direction(direction);
+        direction.active.len() < direction.limit
+    }
+
+    // Take a place for `item_id` if one is free
+    pub fn try_start(&self, direction: &'static str, item_id: &str) -> Option<Slot> {
+        let mut inner = self.inner.lock().unwrap();
+        let state = inner.direction(direction);
+        if state.active.len() >= state.limit || !state.active.insert(item_id.to_string()) {
+            return None;
+        }
+        Some(Slot {
+            inner: self.inner.clone(),
+            released: self.released.clone(),
+            direction,
+            item_id: item_id.to_string(),
+        })
+    }
+
+    // Wait until an upload place frees up or the limit is raised
+    pub async fn upload_released(&self) {
+        self.released.notified().await;
+    }
+
+    pub fn active_ids(&self, direction: &str) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.direction(direction).active.iter().cloned().collect()
+    }
+
+    pub fn record(&self, direction: &str, bytes: i64, elapsed: Duration) {
+        self.finish(direction, bytes, elapsed, false);
+    }
+
+    pub fn record_failure(&self, direction: &str) {
+        self.finish(direction, 0, Duration::ZERO, true);
+    }
+
+    fn finish(&self, direction: &str, bytes: i64, elapsed: Duration, failed: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if !inner.adaptive {
+            return;
+        }
+        let transfer = Transfer {
+            bytes,
+            secs: elapsed.as_secs_f64(),
+            finished: Instant::now(),
+            failed,
+        };
+        inner.direction(direction).finish(transfer, direction);
+        drop(inner);
+        self.released.notify_one();
+    }
+
+    pub fn status(&self) -> ConcurrencyStatus {
+        let inner = self.inner.lock().unwrap();
+        ConcurrencyStatus {
+            adaptive: inner.adaptive,
+            downloads: inner.downloads.status(),
+            uploads: inner.uploads.status(),
+        }
+    }
+}
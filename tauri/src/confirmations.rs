@@ -0,0 +1,205 @@
+// Two-step confirmation for destructive commands (clear_completed_items,
+// delete_local_files, merge_duplicates, apply_bulk_edit). Called without a token, such a command changes nothing: it
+// works out which items it would affect and returns a summary of them with a
+// confirmation token. Called again with that token, it works the set out afresh and
+// only goes ahead if it is still exactly the set the summary described, so a frontend
+// showing stale state can't remove items the user never saw. A token is single-use,
+// bound to the command, the user and the arguments, and expires after TOKEN_TTL.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+use crate::db::QueueItem;
+use crate::timestamps;
+
+pub const TOKEN_TTL: Duration = Duration::from_secs(5 * 60);
+// Titles listed in a summary
+const SAMPLE_LIMIT: usize = 10;
+
+pub const ACTION_CLEAR: &str = "clear_completed_items";
+pub const ACTION_DELETE_LOCAL: &str = "delete_local_files";
+pub const ACTION_MERGE_DUPLICATES: &str = "merge_duplicates";
+pub const ACTION_BULK_EDIT: &str = "apply_bulk_edit";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Confirmation {
+    pub token: String,
+    pub action: String,
+    pub summary: String,
+    pub item_count: usize,
+    // Titles (or URLs) of the first SAMPLE_LIMIT items
+    pub sample: Vec<String>,
+    // Size of the files involved, when files are deleted
+    pub bytes: Option<u64>,
+    #[serde(with = "timestamps::iso8601")]
+    pub expires_at: DateTime<Utc>,
+}
+
+// `data` of a destructive command: what it would do and the token to go ahead, or,
+// once confirmed, what it did
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Confirmed<T> {
+    pub confirmation: Option<Confirmation>,
+    pub result: Option<T>,
+}
+
+impl<T> Confirmed<T> {
+    pub fn pending(confirmation: Confirmation) -> Self {
+        Self {
+            confirmation: Some(confirmation),
+            result: None,
+        }
+    }
+
+    pub fn done(result: T) -> Self {
+        Self {
+            confirmation: None,
+            result: Some(result),
+        }
+    }
+}
+
+// What a destructive command would do, worked out on each call
+pub struct Plan {
+    pub summary: String,
+    pub items: Vec<QueueItem>,
+    pub bytes: Option<u64>,
+    fingerprint: String,
+}
+
+impl Plan {
+    // `args` are the command's arguments, `items` the items it would affect
+    pub fn new(args: &impl Serialize, items: Vec<QueueItem>, summary: String) -> Self {
+        let mut keys: Vec<String> = items
+            .iter()
+            .map(|item| {
+                format!(
+                    "{}\t{}\t{}",
+                    item.id.as_deref().unwrap_or_default(),
+                    item.status,
+                    item.local_path.as_deref().unwrap_or_default()
+                )
+            })
+            .collect();
+        keys.sort();
+        let mut hasher = Sha256::new();
+        hasher.update(serde_json::to_string(args).unwrap_or_default());
+        for key in &keys {
+            hasher.update(b"\n");
+            hasher.update(key);
+        }
+        let fingerprint = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Self {
+            summary,
+            items,
+            bytes: None,
+            fingerprint,
+        }
+    }
+
+    pub fn with_bytes(mut self, bytes: u64) -> Self {
+        self.bytes = Some(bytes);
+        self
+    }
+
+    pub fn item_ids(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .filter_map(|item| item.id.clone())
+            .collect()
+    }
+}
+
+struct Pending {
+    action: &'static str,
+    user_id: String,
+    fingerprint: String,
+    expires: Instant,
+}
+
+#[derive(Default)]
+pub struct Confirmations {
+    pending: Mutex<HashMap<String, Pending>>,
+}
+
+impl Confirmations {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // First step: remember the plan and hand out a token for it
+    pub fn issue(&self, action: &'static str, user_id: &str, plan: &Plan) -> Confirmation {
+        let token = Uuid::new_v4().to_string();
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|_, p| p.expires > Instant::now());
+        pending.insert(
+            token.clone(),
+            Pending {
+                action,
+                user_id: user_id.to_string(),
+                fingerprint: plan.fingerprint.clone(),
+                expires: Instant::now() + TOKEN_TTL,
+            },
+        );
+
+        Confirmation {
+            token,
+            action: action.to_string(),
+            summary: plan.summary.clone(),
+            item_count: plan.items.len(),
+            sample: plan
+                .items
+                .iter()
+                .take(SAMPLE_LIMIT)
+                .map(|item| item.title.clone().unwrap_or_else(|| item.url.clone()))
+                .collect(),
+            bytes: plan.bytes,
+            expires_at: timestamps::now() + chrono::Duration::seconds(TOKEN_TTL.as_secs() as i64),
+        }
+    }
+
+    // Second step: use up `token`, which must have been issued for this action and
+    // user over the same plan
+    pub fn redeem(
+        &self,
+        token: &str,
+        action: &str,
+        user_id: &str,
+        plan: &Plan,
+    ) -> Result<(), String> {
+        let pending = self
+            .pending
+            .lock()
+            .unwrap()
+            .remove(token)
+            .ok_or_else(|| "Unknown or already used confirmation token".to_string())?;
+        if pending.expires <= Instant::now() {
+            return Err("The confirmation expired; review and confirm again".to_string());
+        }
+        if pending.action != action {
+            return Err(format!(
+                "The confirmation token was issued for {}, not {}",
+                pending.action, action
+            ));
+        }
+        if pending.user_id != user_id {
+            return Err("The confirmation token was issued to another user".to_string());
+        }
+        if pending.fingerprint != plan.fingerprint {
+            return Err(format!(
+                "The items changed since this was confirmed ({}); review and confirm again",
+                plan.summary
+            ));
+        }
+        Ok(())
+    }
+}
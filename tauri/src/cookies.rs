@@ -0,0 +1,82 @@
+// Login cookies for private, members-only and age-restricted videos. yt-dlp reads
+// them either from a Netscape cookies.txt file (`cookies_file`) or straight from a
+// browser's profile (`cookies_from_browser`: "chrome", "firefox", "edge", ...,
+// optionally with a profile, "firefox:work"). Only one of the two may be set.
+//
+// A download that fails because the site wants a login ends as "auth_required"
+// rather than "failed", so it is clear that retrying alone won't help.
+
+use std::path::Path;
+
+use crate::db::AppSettings;
+
+// Browsers yt-dlp can read cookies from
+const BROWSERS: &[&str] = &[
+    "brave", "chrome", "chromium", "edge", "firefox", "opera", "safari", "vivaldi", "whale",
+];
+
+pub enum CookieSource {
+    File(String),
+    Browser(String),
+}
+
+fn setting(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+impl CookieSource {
+    pub fn from_settings(settings: &AppSettings) -> Result<Option<Self>, String> {
+        match (
+            setting(&settings.cookies_file),
+            setting(&settings.cookies_from_browser),
+        ) {
+            (None, None) => Ok(None),
+            (Some(_), Some(_)) => {
+                Err("Set either cookies_file or cookies_from_browser, not both".to_string())
+            }
+            (Some(path), None) => Ok(Some(CookieSource::File(path.to_string()))),
+            (None, Some(spec)) => {
+                // "firefox:profile" or "chrome+gnomekeyring:Profile 1"
+                let browser = spec
+                    .split(|c| c == ':' || c == '+')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                if !BROWSERS.contains(&browser.as_str()) {
+                    return Err(format!(
+                        "Unsupported browser '{}' in cookies_from_browser; use one of {}",
+                        browser,
+                        BROWSERS.join(", ")
+                    ));
+                }
+                Ok(Some(CookieSource::Browser(spec.to_string())))
+            }
+        }
+    }
+
+    pub fn ytdlp_args(&self) -> [&str; 2] {
+        match self {
+            CookieSource::File(path) => ["--cookies", path],
+            CookieSource::Browser(spec) => ["--cookies-from-browser", spec],
+        }
+    }
+
+    // For messages: "cookies file /home/me/cookies.txt" or "firefox browser cookies"
+    pub fn describe(&self) -> String {
+        match self {
+            CookieSource::File(path) => format!("cookies file {}", path),
+            CookieSource::Browser(spec) => format!("{} browser cookies", spec),
+        }
+    }
+}
+
+// Check the cookie settings before they are saved. The file has to exist already;
+// a browser profile is only read when a download starts.
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    if let Some(CookieSource::File(path)) = CookieSource::from_settings(settings)? {
+        if !Path::new(&path).is_file() {
+            return Err(format!("Cookies file not found: {}", path));
+        }
+    }
+    Ok(())
+}
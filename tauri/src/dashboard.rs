@@ -0,0 +1,196 @@
+// Read-only status of the queue and gallery, for checking on overnight progress from
+// another device. The local API (see local_api.rs) serves it as a server-rendered
+// page at PAGE_PATH, which works in any phone browser without loading the app, and
+// as JSON at JSON_PATH. Both take local_api_token, or the read-only dashboard token
+// derived from it, which opens nothing else and can go in a bookmark as ?token=
+// (see local_api::dashboard_url).
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::db::QueueItem;
+use crate::shares::escape_html;
+use crate::timestamps;
+
+pub const PAGE_PATH: &str = "/dashboard";
+pub const JSON_PATH: &str = "/api/dashboard";
+
+// Items listed under recent failures and recent uploads
+const LIST_SIZE: usize = 10;
+const REFRESH_SECONDS: u32 = 60;
+// Statuses of items being worked on right now
+const ACTIVE_STATUSES: [&str; 5] = [
+    "downloading",
+    "transcoding",
+    "encoding",
+    "transferring",
+    "uploading",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DashboardItem {
+    pub id: String,
+    pub title: Option<String>,
+    pub url: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub encoding_progress: Option<i32>,
+    pub filemoon_url: Option<String>,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DashboardStatus {
+    #[serde(with = "timestamps::iso8601")]
+    pub generated_at: DateTime<Utc>,
+    pub total: usize,
+    // Item count per status
+    pub by_status: BTreeMap<String, usize>,
+    pub active: Vec<DashboardItem>,
+    pub recent_failures: Vec<DashboardItem>,
+    pub recently_uploaded: Vec<DashboardItem>,
+}
+
+fn dashboard_item(item: &QueueItem) -> DashboardItem {
+    DashboardItem {
+        id: item.id.clone().unwrap_or_default(),
+        title: item.title.clone(),
+        url: item.url.clone(),
+        status: item.status.clone(),
+        message: item.message.clone(),
+        encoding_progress: item.encoding_progress,
+        filemoon_url: item.filemoon_url.clone(),
+        updated_at: item.updated_at,
+    }
+}
+
+// The counts and latest items of one user's queue
+pub fn status(mut items: Vec<QueueItem>) -> DashboardStatus {
+    items.sort_by(|a, b| b.updated_at.cmp(&a.updated_at));
+    let mut by_status = BTreeMap::new();
+    for item in &items {
+        *by_status.entry(item.status.clone()).or_insert(0) += 1;
+    }
+    let latest = |status: &str, limit: usize| {
+        items
+            .iter()
+            .filter(|item| item.status == status)
+            .take(limit)
+            .map(dashboard_item)
+            .collect::<Vec<_>>()
+    };
+
+    DashboardStatus {
+        generated_at: timestamps::now(),
+        total: items.len(),
+        by_status,
+        active: items
+            .iter()
+            .filter(|item| ACTIVE_STATUSES.contains(&item.status.as_str()))
+            .map(dashboard_item)
+            .collect(),
+        recent_failures: latest("failed", LIST_SIZE),
+        recently_uploaded: latest("uploaded", LIST_SIZE),
+    }
+}
+
+fn item_rows(items: &[DashboardItem], empty: &str) -> String {
+    if items.is_empty() {
+        return format!(r#"<p class="muted">{}</p>"#, empty);
+    }
+    let rows: String = items
+        .iter()
+        .map(|item| {
+            let progress = item
+                .encoding_progress
+                .map(|percent| format!(" ({}%)", percent))
+                .unwrap_or_default();
+            let message = item
+                .message
+                .as_deref()
+                .map(|message| format!(r#"<div class="muted">{}</div>"#, escape_html(message)))
+                .unwrap_or_default();
+            format!(
+                r#"<li><strong>{}</strong> <span class="status">{}{}</span><div class="muted">{}</div>{}</li>"#,
+                escape_html(item.title.as_deref().unwrap_or(&item.url)),
+                escape_html(&item.status),
+                progress,
+                item.updated_at
+                    .map(|time| timestamps::to_iso(&time))
+                    .unwrap_or_default(),
+                message
+            )
+        })
+        .collect();
+    format!("<ul>{}</ul>", rows)
+}
+
+fn html(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta http-equiv="refresh" content="{refresh}">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+<style>
+  body {{ font-family: system-ui, sans-serif; margin: 1rem; background: #111; color: #eee; }}
+  h1 {{ font-size: 1.3rem; }} h2 {{ font-size: 1.1rem; margin-top: 1.5rem; }}
+  table {{ border-collapse: collapse; }} td {{ padding: 0.2rem 1rem 0.2rem 0; }}
+  ul {{ list-style: none; padding: 0; }} li {{ margin-bottom: 0.7rem; word-break: break-word; }}
+  .status {{ margin-left: 0.5rem; color: #8cf; }} .muted {{ color: #999; font-size: 0.85rem; }}
+</style>
+</head>
+<body>
+{body}
+</body>
+</html>
+"#,
+        refresh = REFRESH_SECONDS,
+        title = escape_html(title),
+        body = body
+    )
+}
+
+// A page with just a heading and a line of text, for errors
+pub fn message_page(heading: &str, text: &str) -> String {
+    html(
+        "PermaVid",
+        &format!(
+            "<h1>{}</h1><p>{}</p>",
+            escape_html(heading),
+            escape_html(text)
+        ),
+    )
+}
+
+pub fn page(status: &DashboardStatus) -> String {
+    let counts: String = status
+        .by_status
+        .iter()
+        .map(|(name, count)| format!("<tr><td>{}</td><td>{}</td></tr>", escape_html(name), count))
+        .collect();
+    let body = format!(
+        r#"<h1>PermaVid</h1>
+<p class="muted">{total} item(s), updated {generated}</p>
+<h2>By status</h2>
+<table>{counts}</table>
+<h2>In progress</h2>
+{active}
+<h2>Recent failures</h2>
+{failures}
+<h2>Recently uploaded</h2>
+{uploaded}"#,
+        total = status.total,
+        generated = timestamps::to_iso(&status.generated_at),
+        counts = counts,
+        active = item_rows(&status.active, "Nothing is being processed."),
+        failures = item_rows(&status.recent_failures, "No failures."),
+        uploaded = item_rows(&status.recently_uploaded, "Nothing uploaded yet."),
+    );
+    html("PermaVid dashboard", &body)
+}
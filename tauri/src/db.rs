@@ -2,19 +2,35 @@
 // The backend should connect to the same Neon database defined in NEON_DATABASE_URL
 // The functionality should be updated to use a PostgreSQL client instead of SQLite
 
-use chrono::Utc;
-use deadpool_postgres::{Client as PoolClient, Config, Pool, PoolError, Runtime};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Client as PoolClient, Config, GenericClient, Pool, PoolError, Runtime};
 use dotenv::dotenv;
 use native_tls::TlsConnector as NativeTlsConnector;
 use postgres_native_tls::MakeTlsConnector;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
+use serde_json::{json, Value as JsonValue};
 use std::env;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tauri::AppHandle;
+use tokio::sync::broadcast;
+use tokio_postgres::Row;
 use uuid::Uuid;
 
+use crate::bulk_edit::{self, BulkChange, BulkEdit, BulkEditResult, BulkFilter};
+use crate::filemoon;
+use crate::journal::{PendingWrite, WriteJournal, WriteOutcome};
+use crate::messages::ItemMessage;
+use crate::migrations::{self, SchemaInfo};
+use crate::offline::OfflineStore;
+use crate::progress::Progress;
+use crate::scheduler;
+use crate::secrets;
+use crate::timestamps;
+use crate::urls;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ClearResult {
     pub total_deleted: u64,
@@ -24,9 +40,43 @@ pub struct ClearResult {
 
 // Shared database connection pool
 pub struct Database {
-    pool: Arc<Pool>,
+    // None when NEON_DATABASE_URL isn't set; the app then runs offline only
+    pool: Option<Arc<Pool>>,
+    // Local journal of writes made while the database was unreachable
+    journal: WriteJournal,
+    // Cached queue and items added while the database was unreachable
+    offline: OfflineStore,
+    // Whether the last database call failed for lack of a connection
+    unreachable: AtomicBool,
+    // Fired after every item status write that reached the database, and for every
+    // item added
+    status_events: broadcast::Sender<StatusEvent>,
+}
+
+// Buffered status events per subscriber before the slowest one starts missing events;
+// enough for a full add_queue_items_bulk
+const STATUS_EVENT_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct StatusEvent {
+    pub item_id: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub message_code: Option<String>,
 }
 
+// Retry policy for read-only queries in the hot path
+const DB_RETRY_MAX_ATTEMPTS: u32 = 4;
+const DB_RETRY_BASE_DELAY_MS: u64 = 200;
+const DB_RETRY_MAX_DELAY_MS: u64 = 3_000;
+
+// Provider responses are truncated to this many characters before being stored
+const MAX_PROVIDER_ERROR_BODY: usize = 4_000;
+// Oldest provider errors beyond this count are pruned
+const MAX_PROVIDER_ERRORS: i64 = 1_000;
+// Hook runs kept per item
+const MAX_HOOK_RUNS_PER_ITEM: i64 = 50;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Video {
     pub id: Option<i64>,
@@ -38,7 +88,7 @@ pub struct Video {
     pub created_at: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QueueItem {
     pub id: Option<String>,
     pub url: String,
@@ -48,610 +98,3881 @@ pub struct QueueItem {
     pub filemoon_url: Option<String>,
     pub encoding_progress: Option<i32>,
     pub thumbnail_url: Option<String>,
-    pub added_at: Option<i64>,
-    pub updated_at: Option<i64>,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub added_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub updated_at: Option<DateTime<Utc>>,
     pub local_path: Option<String>,
     pub user_id: Option<String>,
+    pub short_url: Option<String>,
+    pub thumbnail_uploaded: Option<bool>,
+    pub locked: Option<bool>,
+    pub format_rung: Option<i32>,
+    pub format_used: Option<String>,
+    pub download_sections: Option<String>,
+    // Alternate source URLs tried in order when the primary URL is gone
+    pub mirror_urls: Option<Vec<String>>,
+    // 0 = primary URL, n = mirror_urls[n - 1]
+    pub source_index: Option<i32>,
+    // Manual priority; the scheduler adds aging and subtracts failures (see scheduler.rs)
+    pub priority: Option<i32>,
+    // Failed download attempts so far
+    pub failure_count: Option<i32>,
+    // Spoken language reported by the extractor (e.g. "en")
+    pub language: Option<String>,
+    // Languages automatic captions are available in
+    pub caption_languages: Option<Vec<String>>,
+    // yt-dlp format tried before the configured preferred format and fallback ladder
+    pub format_override: Option<String>,
+    pub tags: Option<Vec<String>>,
+    // Queue template this item was created from
+    pub template_id: Option<String>,
+    // When file/info last confirmed the uploaded copy; the local file may go after this
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub upload_verified_at: Option<DateTime<Utc>>,
+    // Code and parameters `message` was rendered from (see messages.rs), for localization
+    pub message_code: Option<String>,
+    pub message_params: Option<JsonValue>,
+    // Playlist or channel this item was expanded from (see playlists.rs)
+    pub playlist_id: Option<String>,
+    // Subtitle files yt-dlp wrote next to the video (see subtitles.rs)
+    pub subtitle_paths: Option<Vec<String>>,
+    // Declared provider the file went to instead of Filemoon, and its link there
+    // (see providers.rs)
+    pub upload_provider: Option<String>,
+    pub upload_url: Option<String>,
+    // Gallery collection the item was filed under (see bulk_edit.rs)
+    pub collection: Option<String>,
+    // Automatic retries so far, the item's own cap (None = the max_auto_retries
+    // setting) and when the next one is due (see retry_policy.rs)
+    pub retry_count: Option<i32>,
+    pub max_retries: Option<i32>,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub next_retry_at: Option<DateTime<Utc>>,
+    // Size, progress, speed and ETA of the running or last download (see progress.rs)
+    pub total_bytes: Option<i64>,
+    pub downloaded_bytes: Option<i64>,
+    pub speed_bps: Option<i64>,
+    pub eta_seconds: Option<i32>,
+    // "remote" for a Filemoon remote upload (see remote_transfer.rs)
+    pub transfer_mode: Option<String>,
+    pub remote_filecode: Option<String>,
+    // Overrides the upload_target setting for this item (see mirrors::item_settings)
+    pub upload_target: Option<String>,
+}
+
+impl QueueItem {
+    // A new queued item for `url` with nothing else set
+    pub fn queued(url: &str, user_id: &str) -> Self {
+        QueueItem {
+            id: None,
+            url: url.to_string(),
+            status: "queued".to_string(),
+            message: None,
+            title: None,
+            filemoon_url: None,
+            encoding_progress: None,
+            thumbnail_url: None,
+            added_at: None,
+            updated_at: None,
+            local_path: None,
+            user_id: Some(user_id.to_string()),
+            short_url: None,
+            thumbnail_uploaded: None,
+            locked: None,
+            format_rung: None,
+            format_used: None,
+            download_sections: None,
+            mirror_urls: None,
+            source_index: None,
+            priority: None,
+            failure_count: None,
+            language: None,
+            caption_languages: None,
+            format_override: None,
+            tags: None,
+            template_id: None,
+            upload_verified_at: None,
+            message_code: None,
+            message_params: None,
+            playlist_id: None,
+            subtitle_paths: None,
+            upload_provider: None,
+            upload_url: None,
+            collection: None,
+            retry_count: None,
+            max_retries: None,
+            next_retry_at: None,
+            total_bytes: None,
+            downloaded_bytes: None,
+            speed_bps: None,
+            eta_seconds: None,
+            transfer_mode: None,
+            remote_filecode: None,
+            upload_target: None,
+        }
+    }
+}
+
+// One status change from the item_events table
+#[derive(Debug)]
+pub struct ItemEvent {
+    pub created_at: DateTime<Utc>,
+    pub status: String,
+    pub message: Option<String>,
+    pub message_code: Option<String>,
+    pub message_params: Option<JsonValue>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AppSettings {
     pub filemoon_api_key: Option<String>,
     pub download_directory: Option<String>,
     pub delete_after_upload: Option<String>,
     pub auto_upload: Option<String>,
     pub upload_target: Option<String>,
+    pub ffmpeg_path: Option<String>,
+    pub shortener_url: Option<String>,
+    pub shortener_api_key: Option<String>,
+    pub filemoon_monthly_quota_gb: Option<String>,
+    pub b2_monthly_quota_gb: Option<String>,
+    pub preferred_format: Option<String>,
+    pub format_fallback_ladder: Option<String>,
+    pub telegram_bot_token: Option<String>,
+    pub review_before_upload: Option<String>,
+    pub download_stall_minutes: Option<String>,
+    pub download_timeout_minutes: Option<String>,
+    pub upload_timeout_minutes: Option<String>,
+    pub subtitle_languages: Option<String>,
+    pub monthly_data_cap_gb: Option<String>,
+    pub delete_grace_days: Option<String>,
+    pub hook_after_download: Option<String>,
+    pub hook_after_upload: Option<String>,
+    pub hook_on_failure: Option<String>,
+    pub min_free_space_gb: Option<String>,
+    pub progress_persistence: Option<String>,
+    pub provider_http_headers: Option<String>,
+    pub filemoon_api_base: Option<String>,
+    pub filemoon_site_base: Option<String>,
+    pub filemoon_upload_server_url: Option<String>,
+    pub capture_page: Option<String>,
+    pub download_window_start: Option<String>,
+    pub download_window_end: Option<String>,
+    pub download_window_days: Option<String>,
+    pub subtitle_auto_captions: Option<String>,
+    pub cookies_file: Option<String>,
+    pub cookies_from_browser: Option<String>,
+    pub media_library_dir: Option<String>,
+    pub missing_ffmpeg_action: Option<String>,
+    pub archive_org_access_key: Option<String>,
+    pub archive_org_secret_key: Option<String>,
+    pub archive_org_collection: Option<String>,
+    pub s3_endpoint: Option<String>,
+    pub s3_bucket: Option<String>,
+    pub s3_region: Option<String>,
+    pub s3_access_key: Option<String>,
+    pub s3_secret_key: Option<String>,
+    pub reuse_existing_uploads: Option<String>,
+    pub auto_upload_max_mb: Option<String>,
+    pub auto_upload_tags: Option<String>,
+    pub auto_upload_sites: Option<String>,
+    pub upload_window_start: Option<String>,
+    pub upload_window_end: Option<String>,
+    pub upload_window_days: Option<String>,
+    pub performance_mode: Option<String>,
+    pub process_priority: Option<String>,
+    pub transcode_target: Option<String>,
+    pub transcode_crf: Option<String>,
+    pub transcode_max_height: Option<String>,
+    pub adaptive_concurrency: Option<String>,
+    pub max_concurrent_downloads: Option<String>,
+    pub max_concurrent_uploads: Option<String>,
+    pub ytdlp_path: Option<String>,
+    pub desktop_notifications: Option<String>,
+    pub clipboard_watch: Option<String>,
+    pub clipboard_sites: Option<String>,
+    pub webhook_url: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub local_api_enabled: Option<String>,
+    pub local_api_port: Option<String>,
+    pub local_api_token: Option<String>,
+    // "true" to serve the local API to the LAN too, e.g. the dashboard for a phone
+    pub local_api_lan: Option<String>,
+    pub max_auto_retries: Option<String>,
+}
+
+impl AppSettings {
+    // Fill every unset field from `defaults`
+    pub fn with_defaults(self, defaults: &AppSettings) -> AppSettings {
+        AppSettings {
+            filemoon_api_key: self
+                .filemoon_api_key
+                .or_else(|| defaults.filemoon_api_key.clone()),
+            download_directory: self
+                .download_directory
+                .or_else(|| defaults.download_directory.clone()),
+            delete_after_upload: self
+                .delete_after_upload
+                .or_else(|| defaults.delete_after_upload.clone()),
+            auto_upload: self.auto_upload.or_else(|| defaults.auto_upload.clone()),
+            upload_target: self
+                .upload_target
+                .or_else(|| defaults.upload_target.clone()),
+            ffmpeg_path: self.ffmpeg_path.or_else(|| defaults.ffmpeg_path.clone()),
+            shortener_url: self
+                .shortener_url
+                .or_else(|| defaults.shortener_url.clone()),
+            shortener_api_key: self
+                .shortener_api_key
+                .or_else(|| defaults.shortener_api_key.clone()),
+            filemoon_monthly_quota_gb: self
+                .filemoon_monthly_quota_gb
+                .or_else(|| defaults.filemoon_monthly_quota_gb.clone()),
+            b2_monthly_quota_gb: self
+                .b2_monthly_quota_gb
+                .or_else(|| defaults.b2_monthly_quota_gb.clone()),
+            preferred_format: self
+                .preferred_format
+                .or_else(|| defaults.preferred_format.clone()),
+            format_fallback_ladder: self
+                .format_fallback_ladder
+                .or_else(|| defaults.format_fallback_ladder.clone()),
+            telegram_bot_token: self
+                .telegram_bot_token
+                .or_else(|| defaults.telegram_bot_token.clone()),
+            review_before_upload: self
+                .review_before_upload
+                .or_else(|| defaults.review_before_upload.clone()),
+            download_stall_minutes: self
+                .download_stall_minutes
+                .or_else(|| defaults.download_stall_minutes.clone()),
+            download_timeout_minutes: self
+                .download_timeout_minutes
+                .or_else(|| defaults.download_timeout_minutes.clone()),
+            upload_timeout_minutes: self
+                .upload_timeout_minutes
+                .or_else(|| defaults.upload_timeout_minutes.clone()),
+            subtitle_languages: self
+                .subtitle_languages
+                .or_else(|| defaults.subtitle_languages.clone()),
+            monthly_data_cap_gb: self
+                .monthly_data_cap_gb
+                .or_else(|| defaults.monthly_data_cap_gb.clone()),
+            delete_grace_days: self
+                .delete_grace_days
+                .or_else(|| defaults.delete_grace_days.clone()),
+            hook_after_download: self
+                .hook_after_download
+                .or_else(|| defaults.hook_after_download.clone()),
+            hook_after_upload: self
+                .hook_after_upload
+                .or_else(|| defaults.hook_after_upload.clone()),
+            hook_on_failure: self
+                .hook_on_failure
+                .or_else(|| defaults.hook_on_failure.clone()),
+            min_free_space_gb: self
+                .min_free_space_gb
+                .or_else(|| defaults.min_free_space_gb.clone()),
+            progress_persistence: self
+                .progress_persistence
+                .or_else(|| defaults.progress_persistence.clone()),
+            provider_http_headers: self
+                .provider_http_headers
+                .or_else(|| defaults.provider_http_headers.clone()),
+            filemoon_api_base: self
+                .filemoon_api_base
+                .or_else(|| defaults.filemoon_api_base.clone()),
+            filemoon_site_base: self
+                .filemoon_site_base
+                .or_else(|| defaults.filemoon_site_base.clone()),
+            filemoon_upload_server_url: self
+                .filemoon_upload_server_url
+                .or_else(|| defaults.filemoon_upload_server_url.clone()),
+            capture_page: self
+                .capture_page
+                .or_else(|| defaults.capture_page.clone()),
+            download_window_start: self
+                .download_window_start
+                .or_else(|| defaults.download_window_start.clone()),
+            download_window_end: self
+                .download_window_end
+                .or_else(|| defaults.download_window_end.clone()),
+            download_window_days: self
+                .download_window_days
+                .or_else(|| defaults.download_window_days.clone()),
+            subtitle_auto_captions: self
+                .subtitle_auto_captions
+                .or_else(|| defaults.subtitle_auto_captions.clone()),
+            cookies_file: self
+                .cookies_file
+                .or_else(|| defaults.cookies_file.clone()),
+            cookies_from_browser: self
+                .cookies_from_browser
+                .or_else(|| defaults.cookies_from_browser.clone()),
+            media_library_dir: self
+                .media_library_dir
+                .or_else(|| defaults.media_library_dir.clone()),
+            missing_ffmpeg_action: self
+                .missing_ffmpeg_action
+                .or_else(|| defaults.missing_ffmpeg_action.clone()),
+            archive_org_access_key: self
+                .archive_org_access_key
+                .or_else(|| defaults.archive_org_access_key.clone()),
+            archive_org_secret_key: self
+                .archive_org_secret_key
+                .or_else(|| defaults.archive_org_secret_key.clone()),
+            archive_org_collection: self
+                .archive_org_collection
+                .or_else(|| defaults.archive_org_collection.clone()),
+            s3_endpoint: self.s3_endpoint.or_else(|| defaults.s3_endpoint.clone()),
+            s3_bucket: self.s3_bucket.or_else(|| defaults.s3_bucket.clone()),
+            s3_region: self.s3_region.or_else(|| defaults.s3_region.clone()),
+            s3_access_key: self
+                .s3_access_key
+                .or_else(|| defaults.s3_access_key.clone()),
+            s3_secret_key: self
+                .s3_secret_key
+                .or_else(|| defaults.s3_secret_key.clone()),
+            reuse_existing_uploads: self
+                .reuse_existing_uploads
+                .or_else(|| defaults.reuse_existing_uploads.clone()),
+            auto_upload_max_mb: self
+                .auto_upload_max_mb
+                .or_else(|| defaults.auto_upload_max_mb.clone()),
+            auto_upload_tags: self
+                .auto_upload_tags
+                .or_else(|| defaults.auto_upload_tags.clone()),
+            auto_upload_sites: self
+                .auto_upload_sites
+                .or_else(|| defaults.auto_upload_sites.clone()),
+            upload_window_start: self
+                .upload_window_start
+                .or_else(|| defaults.upload_window_start.clone()),
+            upload_window_end: self
+                .upload_window_end
+                .or_else(|| defaults.upload_window_end.clone()),
+            upload_window_days: self
+                .upload_window_days
+                .or_else(|| defaults.upload_window_days.clone()),
+            performance_mode: self
+                .performance_mode
+                .or_else(|| defaults.performance_mode.clone()),
+            process_priority: self
+                .process_priority
+                .or_else(|| defaults.process_priority.clone()),
+            transcode_target: self
+                .transcode_target
+                .or_else(|| defaults.transcode_target.clone()),
+            transcode_crf: self
+                .transcode_crf
+                .or_else(|| defaults.transcode_crf.clone()),
+            transcode_max_height: self
+                .transcode_max_height
+                .or_else(|| defaults.transcode_max_height.clone()),
+            adaptive_concurrency: self
+                .adaptive_concurrency
+                .or_else(|| defaults.adaptive_concurrency.clone()),
+            max_concurrent_downloads: self
+                .max_concurrent_downloads
+                .or_else(|| defaults.max_concurrent_downloads.clone()),
+            max_concurrent_uploads: self
+                .max_concurrent_uploads
+                .or_else(|| defaults.max_concurrent_uploads.clone()),
+            ytdlp_path: self.ytdlp_path.or_else(|| defaults.ytdlp_path.clone()),
+            desktop_notifications: self
+                .desktop_notifications
+                .or_else(|| defaults.desktop_notifications.clone()),
+            clipboard_watch: self
+                .clipboard_watch
+                .or_else(|| defaults.clipboard_watch.clone()),
+            clipboard_sites: self
+                .clipboard_sites
+                .or_else(|| defaults.clipboard_sites.clone()),
+            webhook_url: self.webhook_url.or_else(|| defaults.webhook_url.clone()),
+            webhook_secret: self
+                .webhook_secret
+                .or_else(|| defaults.webhook_secret.clone()),
+            local_api_enabled: self
+                .local_api_enabled
+                .or_else(|| defaults.local_api_enabled.clone()),
+            local_api_port: self
+                .local_api_port
+                .or_else(|| defaults.local_api_port.clone()),
+            local_api_token: self
+                .local_api_token
+                .or_else(|| defaults.local_api_token.clone()),
+            local_api_lan: self
+                .local_api_lan
+                .or_else(|| defaults.local_api_lan.clone()),
+            max_auto_retries: self
+                .max_auto_retries
+                .or_else(|| defaults.max_auto_retries.clone()),
+        }
+    }
+
+    // Keep only the fields that differ from `defaults`. An empty field is kept where
+    // the default has a value, so a user can clear a setting the defaults fill in;
+    // reset_user_settings makes a field inherit again.
+    pub fn overrides_of(&self, defaults: &AppSettings) -> AppSettings {
+        fn diff(value: &Option<String>, default: &Option<String>) -> Option<String> {
+            let default_unset = default.as_deref().map_or(true, str::is_empty);
+            match value {
+                Some(v) if Some(v) == default.as_ref() => None,
+                Some(v) if v.is_empty() && default_unset => None,
+                other => other.clone(),
+            }
+        }
+
+        AppSettings {
+            filemoon_api_key: diff(&self.filemoon_api_key, &defaults.filemoon_api_key),
+            download_directory: diff(&self.download_directory, &defaults.download_directory),
+            delete_after_upload: diff(&self.delete_after_upload, &defaults.delete_after_upload),
+            auto_upload: diff(&self.auto_upload, &defaults.auto_upload),
+            upload_target: diff(&self.upload_target, &defaults.upload_target),
+            ffmpeg_path: diff(&self.ffmpeg_path, &defaults.ffmpeg_path),
+            shortener_url: diff(&self.shortener_url, &defaults.shortener_url),
+            shortener_api_key: diff(&self.shortener_api_key, &defaults.shortener_api_key),
+            filemoon_monthly_quota_gb: diff(
+                &self.filemoon_monthly_quota_gb,
+                &defaults.filemoon_monthly_quota_gb,
+            ),
+            b2_monthly_quota_gb: diff(&self.b2_monthly_quota_gb, &defaults.b2_monthly_quota_gb),
+            preferred_format: diff(&self.preferred_format, &defaults.preferred_format),
+            format_fallback_ladder: diff(
+                &self.format_fallback_ladder,
+                &defaults.format_fallback_ladder,
+            ),
+            telegram_bot_token: diff(&self.telegram_bot_token, &defaults.telegram_bot_token),
+            review_before_upload: diff(
+                &self.review_before_upload,
+                &defaults.review_before_upload,
+            ),
+            download_stall_minutes: diff(
+                &self.download_stall_minutes,
+                &defaults.download_stall_minutes,
+            ),
+            download_timeout_minutes: diff(
+                &self.download_timeout_minutes,
+                &defaults.download_timeout_minutes,
+            ),
+            upload_timeout_minutes: diff(
+                &self.upload_timeout_minutes,
+                &defaults.upload_timeout_minutes,
+            ),
+            subtitle_languages: diff(&self.subtitle_languages, &defaults.subtitle_languages),
+            monthly_data_cap_gb: diff(&self.monthly_data_cap_gb, &defaults.monthly_data_cap_gb),
+            delete_grace_days: diff(&self.delete_grace_days, &defaults.delete_grace_days),
+            hook_after_download: diff(&self.hook_after_download, &defaults.hook_after_download),
+            hook_after_upload: diff(&self.hook_after_upload, &defaults.hook_after_upload),
+            hook_on_failure: diff(&self.hook_on_failure, &defaults.hook_on_failure),
+            min_free_space_gb: diff(&self.min_free_space_gb, &defaults.min_free_space_gb),
+            progress_persistence: diff(&self.progress_persistence, &defaults.progress_persistence),
+            provider_http_headers: diff(
+                &self.provider_http_headers,
+                &defaults.provider_http_headers,
+            ),
+            filemoon_api_base: diff(&self.filemoon_api_base, &defaults.filemoon_api_base),
+            filemoon_site_base: diff(&self.filemoon_site_base, &defaults.filemoon_site_base),
+            filemoon_upload_server_url: diff(
+                &self.filemoon_upload_server_url,
+                &defaults.filemoon_upload_server_url,
+            ),
+            capture_page: diff(&self.capture_page, &defaults.capture_page),
+            download_window_start: diff(
+                &self.download_window_start,
+                &defaults.download_window_start,
+            ),
+            download_window_end: diff(&self.download_window_end, &defaults.download_window_end),
+            download_window_days: diff(&self.download_window_days, &defaults.download_window_days),
+            subtitle_auto_captions: diff(
+                &self.subtitle_auto_captions,
+                &defaults.subtitle_auto_captions,
+            ),
+            cookies_file: diff(&self.cookies_file, &defaults.cookies_file),
+            cookies_from_browser: diff(&self.cookies_from_browser, &defaults.cookies_from_browser),
+            media_library_dir: diff(&self.media_library_dir, &defaults.media_library_dir),
+            missing_ffmpeg_action: diff(
+                &self.missing_ffmpeg_action,
+                &defaults.missing_ffmpeg_action,
+            ),
+            archive_org_access_key: diff(
+                &self.archive_org_access_key,
+                &defaults.archive_org_access_key,
+            ),
+            archive_org_secret_key: diff(
+                &self.archive_org_secret_key,
+                &defaults.archive_org_secret_key,
+            ),
+            archive_org_collection: diff(
+                &self.archive_org_collection,
+                &defaults.archive_org_collection,
+            ),
+            s3_endpoint: diff(&self.s3_endpoint, &defaults.s3_endpoint),
+            s3_bucket: diff(&self.s3_bucket, &defaults.s3_bucket),
+            s3_region: diff(&self.s3_region, &defaults.s3_region),
+            s3_access_key: diff(&self.s3_access_key, &defaults.s3_access_key),
+            s3_secret_key: diff(&self.s3_secret_key, &defaults.s3_secret_key),
+            reuse_existing_uploads: diff(
+                &self.reuse_existing_uploads,
+                &defaults.reuse_existing_uploads,
+            ),
+            auto_upload_max_mb: diff(&self.auto_upload_max_mb, &defaults.auto_upload_max_mb),
+            auto_upload_tags: diff(&self.auto_upload_tags, &defaults.auto_upload_tags),
+            auto_upload_sites: diff(&self.auto_upload_sites, &defaults.auto_upload_sites),
+            upload_window_start: diff(&self.upload_window_start, &defaults.upload_window_start),
+            upload_window_end: diff(&self.upload_window_end, &defaults.upload_window_end),
+            upload_window_days: diff(&self.upload_window_days, &defaults.upload_window_days),
+            performance_mode: diff(&self.performance_mode, &defaults.performance_mode),
+            process_priority: diff(&self.process_priority, &defaults.process_priority),
+            transcode_target: diff(&self.transcode_target, &defaults.transcode_target),
+            transcode_crf: diff(&self.transcode_crf, &defaults.transcode_crf),
+            transcode_max_height: diff(&self.transcode_max_height, &defaults.transcode_max_height),
+            adaptive_concurrency: diff(&self.adaptive_concurrency, &defaults.adaptive_concurrency),
+            max_concurrent_downloads: diff(
+                &self.max_concurrent_downloads,
+                &defaults.max_concurrent_downloads,
+            ),
+            max_concurrent_uploads: diff(
+                &self.max_concurrent_uploads,
+                &defaults.max_concurrent_uploads,
+            ),
+            ytdlp_path: diff(&self.ytdlp_path, &defaults.ytdlp_path),
+            desktop_notifications: diff(
+                &self.desktop_notifications,
+                &defaults.desktop_notifications,
+            ),
+            clipboard_watch: diff(&self.clipboard_watch, &defaults.clipboard_watch),
+            clipboard_sites: diff(&self.clipboard_sites, &defaults.clipboard_sites),
+            webhook_url: diff(&self.webhook_url, &defaults.webhook_url),
+            webhook_secret: diff(&self.webhook_secret, &defaults.webhook_secret),
+            local_api_enabled: diff(&self.local_api_enabled, &defaults.local_api_enabled),
+            local_api_port: diff(&self.local_api_port, &defaults.local_api_port),
+            local_api_token: diff(&self.local_api_token, &defaults.local_api_token),
+            local_api_lan: diff(&self.local_api_lan, &defaults.local_api_lan),
+            max_auto_retries: diff(&self.max_auto_retries, &defaults.max_auto_retries),
+        }
+    }
+}
+
+// Whether `key` names a setting
+pub fn is_setting_key(key: &str) -> bool {
+    settings_to_json(&AppSettings::default())
+        .as_object()
+        .map_or(false, |fields| fields.contains_key(key))
+}
+
+fn settings_to_json(settings: &AppSettings) -> serde_json::Value {
+    json!({
+        "filemoon_api_key": settings.filemoon_api_key,
+        "download_directory": settings.download_directory,
+        "delete_after_upload": settings.delete_after_upload,
+        "auto_upload": settings.auto_upload,
+        "upload_target": settings.upload_target,
+        "ffmpeg_path": settings.ffmpeg_path,
+        "shortener_url": settings.shortener_url,
+        "shortener_api_key": settings.shortener_api_key,
+        "filemoon_monthly_quota_gb": settings.filemoon_monthly_quota_gb,
+        "b2_monthly_quota_gb": settings.b2_monthly_quota_gb,
+        "preferred_format": settings.preferred_format,
+        "format_fallback_ladder": settings.format_fallback_ladder,
+        "telegram_bot_token": settings.telegram_bot_token,
+        "review_before_upload": settings.review_before_upload,
+        "download_stall_minutes": settings.download_stall_minutes,
+        "download_timeout_minutes": settings.download_timeout_minutes,
+        "upload_timeout_minutes": settings.upload_timeout_minutes,
+        "subtitle_languages": settings.subtitle_languages,
+        "monthly_data_cap_gb": settings.monthly_data_cap_gb,
+        "delete_grace_days": settings.delete_grace_days,
+        "hook_after_download": settings.hook_after_download,
+        "hook_after_upload": settings.hook_after_upload,
+        "hook_on_failure": settings.hook_on_failure,
+        "min_free_space_gb": settings.min_free_space_gb,
+        "progress_persistence": settings.progress_persistence,
+        "provider_http_headers": settings.provider_http_headers,
+        "filemoon_api_base": settings.filemoon_api_base,
+        "filemoon_site_base": settings.filemoon_site_base,
+        "filemoon_upload_server_url": settings.filemoon_upload_server_url,
+        "capture_page": settings.capture_page,
+        "download_window_start": settings.download_window_start,
+        "download_window_end": settings.download_window_end,
+        "download_window_days": settings.download_window_days,
+        "subtitle_auto_captions": settings.subtitle_auto_captions,
+        "cookies_file": settings.cookies_file,
+        "cookies_from_browser": settings.cookies_from_browser,
+        "media_library_dir": settings.media_library_dir,
+        "missing_ffmpeg_action": settings.missing_ffmpeg_action,
+        "archive_org_access_key": settings.archive_org_access_key,
+        "archive_org_secret_key": settings.archive_org_secret_key,
+        "archive_org_collection": settings.archive_org_collection,
+        "s3_endpoint": settings.s3_endpoint,
+        "s3_bucket": settings.s3_bucket,
+        "s3_region": settings.s3_region,
+        "s3_access_key": settings.s3_access_key,
+        "s3_secret_key": settings.s3_secret_key,
+        "reuse_existing_uploads": settings.reuse_existing_uploads,
+        "auto_upload_max_mb": settings.auto_upload_max_mb,
+        "auto_upload_tags": settings.auto_upload_tags,
+        "auto_upload_sites": settings.auto_upload_sites,
+        "upload_window_start": settings.upload_window_start,
+        "upload_window_end": settings.upload_window_end,
+        "upload_window_days": settings.upload_window_days,
+        "performance_mode": settings.performance_mode,
+        "process_priority": settings.process_priority,
+        "transcode_target": settings.transcode_target,
+        "transcode_crf": settings.transcode_crf,
+        "transcode_max_height": settings.transcode_max_height,
+        "adaptive_concurrency": settings.adaptive_concurrency,
+        "max_concurrent_downloads": settings.max_concurrent_downloads,
+        "max_concurrent_uploads": settings.max_concurrent_uploads,
+        "ytdlp_path": settings.ytdlp_path,
+        "desktop_notifications": settings.desktop_notifications,
+        "clipboard_watch": settings.clipboard_watch,
+        "clipboard_sites": settings.clipboard_sites,
+        "webhook_url": settings.webhook_url,
+        "webhook_secret": settings.webhook_secret,
+        "local_api_enabled": settings.local_api_enabled,
+        "local_api_port": settings.local_api_port,
+        "local_api_token": settings.local_api_token,
+        "local_api_lan": settings.local_api_lan,
+        "max_auto_retries": settings.max_auto_retries
+    })
+}
+
+// Copy the string fields of a settings JSON blob onto `settings`
+fn apply_settings_json(settings: &mut AppSettings, json_value: &serde_json::Value) {
+    let obj = match json_value.as_object() {
+        Some(obj) => obj,
+        None => return,
+    };
+    let get = |key: &str| obj.get(key).and_then(|v| v.as_str()).map(String::from);
+
+    if let Some(val) = get("filemoon_api_key") {
+        settings.filemoon_api_key = Some(val);
+    }
+    if let Some(val) = get("download_directory") {
+        settings.download_directory = Some(val);
+    }
+    if let Some(val) = get("delete_after_upload") {
+        settings.delete_after_upload = Some(val);
+    }
+    if let Some(val) = get("auto_upload") {
+        settings.auto_upload = Some(val);
+    }
+    if let Some(val) = get("upload_target") {
+        settings.upload_target = Some(val);
+    }
+    if let Some(val) = get("ffmpeg_path") {
+        settings.ffmpeg_path = Some(val);
+    }
+    if let Some(val) = get("shortener_url") {
+        settings.shortener_url = Some(val);
+    }
+    if let Some(val) = get("shortener_api_key") {
+        settings.shortener_api_key = Some(val);
+    }
+    if let Some(val) = get("filemoon_monthly_quota_gb") {
+        settings.filemoon_monthly_quota_gb = Some(val);
+    }
+    if let Some(val) = get("b2_monthly_quota_gb") {
+        settings.b2_monthly_quota_gb = Some(val);
+    }
+    if let Some(val) = get("preferred_format") {
+        settings.preferred_format = Some(val);
+    }
+    if let Some(val) = get("format_fallback_ladder") {
+        settings.format_fallback_ladder = Some(val);
+    }
+    if let Some(val) = get("telegram_bot_token") {
+        settings.telegram_bot_token = Some(val);
+    }
+    if let Some(val) = get("review_before_upload") {
+        settings.review_before_upload = Some(val);
+    }
+    if let Some(val) = get("download_stall_minutes") {
+        settings.download_stall_minutes = Some(val);
+    }
+    if let Some(val) = get("download_timeout_minutes") {
+        settings.download_timeout_minutes = Some(val);
+    }
+    if let Some(val) = get("upload_timeout_minutes") {
+        settings.upload_timeout_minutes = Some(val);
+    }
+    if let Some(val) = get("subtitle_languages") {
+        settings.subtitle_languages = Some(val);
+    }
+    if let Some(val) = get("monthly_data_cap_gb") {
+        settings.monthly_data_cap_gb = Some(val);
+    }
+    if let Some(val) = get("delete_grace_days") {
+        settings.delete_grace_days = Some(val);
+    }
+    if let Some(val) = get("hook_after_download") {
+        settings.hook_after_download = Some(val);
+    }
+    if let Some(val) = get("hook_after_upload") {
+        settings.hook_after_upload = Some(val);
+    }
+    if let Some(val) = get("hook_on_failure") {
+        settings.hook_on_failure = Some(val);
+    }
+    if let Some(val) = get("min_free_space_gb") {
+        settings.min_free_space_gb = Some(val);
+    }
+    if let Some(val) = get("progress_persistence") {
+        settings.progress_persistence = Some(val);
+    }
+    if let Some(val) = get("provider_http_headers") {
+        settings.provider_http_headers = Some(val);
+    }
+    if let Some(val) = get("filemoon_api_base") {
+        settings.filemoon_api_base = Some(val);
+    }
+    if let Some(val) = get("filemoon_site_base") {
+        settings.filemoon_site_base = Some(val);
+    }
+    if let Some(val) = get("filemoon_upload_server_url") {
+        settings.filemoon_upload_server_url = Some(val);
+    }
+    if let Some(val) = get("capture_page") {
+        settings.capture_page = Some(val);
+    }
+    if let Some(val) = get("download_window_start") {
+        settings.download_window_start = Some(val);
+    }
+    if let Some(val) = get("download_window_end") {
+        settings.download_window_end = Some(val);
+    }
+    if let Some(val) = get("download_window_days") {
+        settings.download_window_days = Some(val);
+    }
+    if let Some(val) = get("subtitle_auto_captions") {
+        settings.subtitle_auto_captions = Some(val);
+    }
+    if let Some(val) = get("cookies_file") {
+        settings.cookies_file = Some(val);
+    }
+    if let Some(val) = get("cookies_from_browser") {
+        settings.cookies_from_browser = Some(val);
+    }
+    if let Some(val) = get("media_library_dir") {
+        settings.media_library_dir = Some(val);
+    }
+    if let Some(val) = get("missing_ffmpeg_action") {
+        settings.missing_ffmpeg_action = Some(val);
+    }
+    if let Some(val) = get("archive_org_access_key") {
+        settings.archive_org_access_key = Some(val);
+    }
+    if let Some(val) = get("archive_org_secret_key") {
+        settings.archive_org_secret_key = Some(val);
+    }
+    if let Some(val) = get("archive_org_collection") {
+        settings.archive_org_collection = Some(val);
+    }
+    if let Some(val) = get("s3_endpoint") {
+        settings.s3_endpoint = Some(val);
+    }
+    if let Some(val) = get("s3_bucket") {
+        settings.s3_bucket = Some(val);
+    }
+    if let Some(val) = get("s3_region") {
+        settings.s3_region = Some(val);
+    }
+    if let Some(val) = get("s3_access_key") {
+        settings.s3_access_key = Some(val);
+    }
+    if let Some(val) = get("s3_secret_key") {
+        settings.s3_secret_key = Some(val);
+    }
+    if let Some(val) = get("reuse_existing_uploads") {
+        settings.reuse_existing_uploads = Some(val);
+    }
+    if let Some(val) = get("auto_upload_max_mb") {
+        settings.auto_upload_max_mb = Some(val);
+    }
+    if let Some(val) = get("auto_upload_tags") {
+        settings.auto_upload_tags = Some(val);
+    }
+    if let Some(val) = get("auto_upload_sites") {
+        settings.auto_upload_sites = Some(val);
+    }
+    if let Some(val) = get("upload_window_start") {
+        settings.upload_window_start = Some(val);
+    }
+    if let Some(val) = get("upload_window_end") {
+        settings.upload_window_end = Some(val);
+    }
+    if let Some(val) = get("upload_window_days") {
+        settings.upload_window_days = Some(val);
+    }
+    if let Some(val) = get("performance_mode") {
+        settings.performance_mode = Some(val);
+    }
+    if let Some(val) = get("process_priority") {
+        settings.process_priority = Some(val);
+    }
+    if let Some(val) = get("transcode_target") {
+        settings.transcode_target = Some(val);
+    }
+    if let Some(val) = get("transcode_crf") {
+        settings.transcode_crf = Some(val);
+    }
+    if let Some(val) = get("transcode_max_height") {
+        settings.transcode_max_height = Some(val);
+    }
+    if let Some(val) = get("adaptive_concurrency") {
+        settings.adaptive_concurrency = Some(val);
+    }
+    if let Some(val) = get("max_concurrent_downloads") {
+        settings.max_concurrent_downloads = Some(val);
+    }
+    if let Some(val) = get("max_concurrent_uploads") {
+        settings.max_concurrent_uploads = Some(val);
+    }
+    if let Some(val) = get("ytdlp_path") {
+        settings.ytdlp_path = Some(val);
+    }
+    if let Some(val) = get("desktop_notifications") {
+        settings.desktop_notifications = Some(val);
+    }
+    if let Some(val) = get("clipboard_watch") {
+        settings.clipboard_watch = Some(val);
+    }
+    if let Some(val) = get("clipboard_sites") {
+        settings.clipboard_sites = Some(val);
+    }
+    if let Some(val) = get("webhook_url") {
+        settings.webhook_url = Some(val);
+    }
+    if let Some(val) = get("webhook_secret") {
+        settings.webhook_secret = Some(val);
+    }
+    if let Some(val) = get("local_api_enabled") {
+        settings.local_api_enabled = Some(val);
+    }
+    if let Some(val) = get("local_api_port") {
+        settings.local_api_port = Some(val);
+    }
+    if let Some(val) = get("local_api_token") {
+        settings.local_api_token = Some(val);
+    }
+    if let Some(val) = get("local_api_lan") {
+        settings.local_api_lan = Some(val);
+    }
+    if let Some(val) = get("max_auto_retries") {
+        settings.max_auto_retries = Some(val);
+    }
+}
+
+// A failed provider call, kept so raw responses survive after the console closes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderError {
+    pub id: String,
+    pub item_id: Option<String>,
+    pub endpoint: String,
+    pub http_status: Option<i32>,
+    pub message: String,
+    pub response_body: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub created_at: DateTime<Utc>,
+}
+
+// One run of a user hook script for an item (see hooks.rs)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HookRun {
+    pub id: String,
+    pub item_id: String,
+    // "after_download", "after_upload" or "on_failure"
+    pub event: String,
+    pub command: String,
+    // None when the script could not be started, timed out or was killed by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub error: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "timestamps::iso8601")]
+    pub finished_at: DateTime<Utc>,
+}
+
+// Where one destination of an item's upload stands (see mirrors.rs). Each item
+// has at most one row per target.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UploadRecord {
+    pub item_id: String,
+    pub target: String,
+    // "uploading", "uploaded" or "failed"
+    pub status: String,
+    pub url: Option<String>,
+    pub error: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// Capture details recorded once a download finishes, for the provenance manifest
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProvenanceRecord {
+    pub item_id: String,
+    pub webpage_url: Option<String>,
+    pub extractor: Option<String>,
+    pub video_id: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub captured_at: DateTime<Utc>,
+    pub ytdlp_version: Option<String>,
+    pub format: Option<String>,
+    pub file_name: Option<String>,
+    pub file_size: Option<i64>,
+    pub sha256: Option<String>,
+}
+
+// A user-defined notification rule, evaluated by the notifier on every status change
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NotificationRule {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    pub name: String,
+    // e.g. "status == failed AND retries >= 3"; see notifier::parse_condition
+    pub condition: String,
+    // "telegram" or "webhook"
+    pub channel: String,
+    // Telegram chat id or webhook URL
+    pub target: String,
+    #[serde(default = "default_rule_enabled")]
+    pub enabled: bool,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+fn default_rule_enabled() -> bool {
+    true
+}
+
+// A named, reusable archive job; see templates.rs
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QueueTemplate {
+    pub id: Option<String>,
+    pub user_id: Option<String>,
+    pub name: String,
+    // Source URL, or a channel's live/latest URL; may contain {date}, {yyyy}, {mm}, {dd}
+    pub url_pattern: String,
+    pub format: Option<String>,
+    pub upload_target: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub download_sections: Option<String>,
+    #[serde(default)]
+    pub priority: i32,
+    // "daily HH:MM" or "weekly <day> HH:MM" in local time; None means manual only
+    pub schedule: Option<String>,
+    #[serde(default = "default_rule_enabled")]
+    pub enabled: bool,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub last_run_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "timestamps::iso8601_opt")]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+// A playlist or channel whose entries were queued together; see playlists.rs
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Playlist {
+    pub id: String,
+    pub user_id: String,
+    pub url: String,
+    pub title: Option<String>,
+    // The site's own playlist or channel id
+    pub source_id: Option<String>,
+    pub entry_count: i32,
+    #[serde(with = "timestamps::iso8601")]
+    pub added_at: DateTime<Utc>,
+}
+
+// A raw row of the settings table, used for snapshots
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SettingRow {
+    pub key: String,
+    pub value: Option<String>,
+    pub user_id: String,
+}
+
+// A raw row of the global_settings table, used for snapshots
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GlobalSettingRow {
+    pub key: String,
+    pub value: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// A raw row of the uploads table, used for snapshots
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadRow {
+    pub id: String,
+    pub item_id: String,
+    pub target: String,
+    pub status: String,
+    pub url: Option<String>,
+    pub error: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "timestamps::iso8601")]
+    pub updated_at: DateTime<Utc>,
+}
+
+// How many rows of each table a snapshot restore wrote
+#[derive(Debug, Default)]
+pub struct RestoredRows {
+    pub items: u64,
+    pub settings: u64,
+    pub global_settings: u64,
+    pub uploads: u64,
 }
 
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
 
+
+// Columns selected for every QueueTemplate query, in the order queue_template_from_row expects
+const TEMPLATE_COLUMNS: &str = "id, user_id, name, url_pattern, format, upload_target, tags,
+                        download_sections, priority, schedule, enabled, last_run_at, created_at";
+
+fn queue_template_from_row(row: &Row) -> QueueTemplate {
+    QueueTemplate {
+        id: Some(row.get(0)),
+        user_id: Some(row.get(1)),
+        name: row.get(2),
+        url_pattern: row.get(3),
+        format: row.get(4),
+        upload_target: row.get(5),
+        tags: row.get(6),
+        download_sections: row.get(7),
+        priority: row.get(8),
+        schedule: row.get(9),
+        enabled: row.get(10),
+        last_run_at: row.get::<_, Option<DateTime<Utc>>>(11),
+        created_at: Some(row.get::<_, DateTime<Utc>>(12)),
+    }
+}
+
+const PLAYLIST_COLUMNS: &str = "id, user_id, url, title, source_id, entry_count, added_at";
+
+fn playlist_from_row(row: &Row) -> Playlist {
+    Playlist {
+        id: row.get(0),
+        user_id: row.get(1),
+        url: row.get(2),
+        title: row.get(3),
+        source_id: row.get(4),
+        entry_count: row.get(5),
+        added_at: row.get(6),
+    }
+}
+
+// Columns selected for every QueueItem query, in the order queue_item_from_row expects
+const QUEUE_COLUMNS: &str = "id, url, status, message, title, filemoon_url, encoding_progress,
+                        thumbnail_url, added_at, updated_at, local_path, user_id, short_url,
+                        thumbnail_uploaded, locked, format_rung, format_used, download_sections,
+                        mirror_urls, source_index, priority, failure_count, language,
+                        caption_languages, format_override, tags, template_id,
+                        upload_verified_at, message_code, message_params, playlist_id,
+                        subtitle_paths, upload_provider, upload_url, collection,
+                        retry_count, max_retries, next_retry_at, total_bytes,
+                        downloaded_bytes, speed_bps, eta_seconds, transfer_mode,
+                        remote_filecode, upload_target";
+
+// Insert an item row; the duplicate check is up to the caller
+async fn insert_queue_row<C: GenericClient>(
+    client: &C,
+    id: &str,
+    item: &QueueItem,
+    video_key: &Option<String>,
+) -> Result<()> {
+    let added_at_timestamp = item.added_at.unwrap_or_else(timestamps::now);
+    client
+        .execute(
+            "INSERT INTO queue (id, url, status, message, title, filemoon_url,
+                            encoding_progress, thumbnail_url, added_at, updated_at, user_id,
+                            download_sections, mirror_urls, priority, format_override, tags,
+                            template_id, message_code, message_params, playlist_id,
+                            video_key, upload_target)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16,
+                 $17, $18, $19, $20, $21, $22)",
+            &[
+                &id,
+                &item.url,
+                &item.status,
+                &item.message,
+                &item.title,
+                &item.filemoon_url,
+                &item.encoding_progress,
+                &item.thumbnail_url,
+                &added_at_timestamp,
+                &timestamps::now(),
+                &item.user_id.as_ref().unwrap(),
+                &item.download_sections,
+                &item.mirror_urls.clone().unwrap_or_default(),
+                &item.priority.unwrap_or(0),
+                &item.format_override,
+                &item.tags.clone().unwrap_or_default(),
+                &item.template_id,
+                &item.message_code,
+                &item.message_params.as_ref().map(JsonValue::to_string),
+                &item.playlist_id,
+                video_key,
+                &item.upload_target,
+            ],
+        )
+        .await?;
+    Ok(())
+}
+
+fn queue_item_from_row(row: &Row) -> QueueItem {
+    QueueItem {
+        id: Some(row.get::<_, String>(0)),
+        url: row.get::<_, String>(1),
+        status: row.get::<_, String>(2),
+        message: row.get::<_, Option<String>>(3),
+        title: row.get::<_, Option<String>>(4),
+        filemoon_url: row.get::<_, Option<String>>(5),
+        encoding_progress: row.get::<_, Option<i32>>(6),
+        thumbnail_url: row.get::<_, Option<String>>(7),
+        added_at: Some(row.get::<_, DateTime<Utc>>(8)),
+        updated_at: Some(row.get::<_, DateTime<Utc>>(9)),
+        local_path: row.get::<_, Option<String>>(10),
+        user_id: Some(row.get::<_, String>(11)),
+        short_url: row.get::<_, Option<String>>(12),
+        thumbnail_uploaded: row.get::<_, Option<bool>>(13),
+        locked: Some(row.get::<_, bool>(14)),
+        format_rung: row.get::<_, Option<i32>>(15),
+        format_used: row.get::<_, Option<String>>(16),
+        download_sections: row.get::<_, Option<String>>(17),
+        mirror_urls: Some(row.get::<_, Vec<String>>(18)),
+        source_index: row.get::<_, Option<i32>>(19),
+        priority: Some(row.get::<_, i32>(20)),
+        failure_count: Some(row.get::<_, i32>(21)),
+        language: row.get::<_, Option<String>>(22),
+        caption_languages: Some(row.get::<_, Vec<String>>(23)),
+        format_override: row.get::<_, Option<String>>(24),
+        tags: Some(row.get::<_, Vec<String>>(25)),
+        template_id: row.get::<_, Option<String>>(26),
+        upload_verified_at: row.get::<_, Option<DateTime<Utc>>>(27),
+        message_code: row.get::<_, Option<String>>(28),
+        message_params: parse_message_params(row.get::<_, Option<String>>(29)),
+        playlist_id: row.get::<_, Option<String>>(30),
+        subtitle_paths: Some(row.get::<_, Vec<String>>(31)),
+        upload_provider: row.get::<_, Option<String>>(32),
+        upload_url: row.get::<_, Option<String>>(33),
+        collection: row.get::<_, Option<String>>(34),
+        retry_count: Some(row.get::<_, i32>(35)),
+        max_retries: row.get::<_, Option<i32>>(36),
+        next_retry_at: row.get::<_, Option<DateTime<Utc>>>(37),
+        total_bytes: row.get::<_, Option<i64>>(38),
+        downloaded_bytes: row.get::<_, Option<i64>>(39),
+        speed_bps: row.get::<_, Option<i64>>(40),
+        eta_seconds: row.get::<_, Option<i32>>(41),
+        transfer_mode: row.get::<_, Option<String>>(42),
+        remote_filecode: row.get::<_, Option<String>>(43),
+        upload_target: row.get::<_, Option<String>>(44),
+    }
+}
+
+// message_params is stored as JSON text
+fn parse_message_params(raw: Option<String>) -> Option<JsonValue> {
+    raw.and_then(|raw| serde_json::from_str(&raw).ok())
+}
+
+fn provenance_from_row(row: &Row) -> ProvenanceRecord {
+    ProvenanceRecord {
+        item_id: row.get(0),
+        webpage_url: row.get(1),
+        extractor: row.get(2),
+        video_id: row.get(3),
+        captured_at: row.get::<_, DateTime<Utc>>(4),
+        ytdlp_version: row.get(5),
+        format: row.get(6),
+        file_name: row.get(7),
+        file_size: row.get(8),
+        sha256: row.get(9),
+    }
+}
+
+// Runs a read-only database operation, retrying with exponential backoff and jitter
+// while the database can't be reached. Errors the server returns are not retried.
+async fn with_retry<T, F, Fut>(op_name: &str, mut op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < DB_RETRY_MAX_ATTEMPTS && is_connectivity_error(e.as_ref()) => {
+                let delay = backoff_delay(attempt);
+                eprintln!(
+                    "[DB] {} failed (attempt {}/{}): {}. Retrying in {:?}",
+                    op_name, attempt, DB_RETRY_MAX_ATTEMPTS, e, delay
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// True when the error means the database could not be reached at all, as opposed
+// to the server rejecting the statement
+fn is_connectivity_error(e: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    if e.downcast_ref::<PoolError>().is_some() {
+        return true;
+    }
+    match e.downcast_ref::<tokio_postgres::Error>() {
+        Some(pg_error) => pg_error.as_db_error().is_none(),
+        None => false,
+    }
+}
+
+// Exponential backoff capped at DB_RETRY_MAX_DELAY_MS, with up to 50% random jitter
+fn backoff_delay(attempt: u32) -> Duration {
+    let base = DB_RETRY_BASE_DELAY_MS
+        .saturating_mul(1u64 << (attempt - 1).min(16))
+        .min(DB_RETRY_MAX_DELAY_MS);
+    // The clock's sub-second part is random enough to spread out the retries
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos() as u64)
+        .unwrap_or(0);
+    let jitter = nanos % (base / 2 + 1);
+    Duration::from_millis(base + jitter)
+}
+
 impl Database {
-    pub fn new(_app_handle: &AppHandle) -> Result<Self> {
+    pub fn new(app_handle: &AppHandle) -> Result<Self> {
         // Load environment variables from .env file
         dotenv().ok();
 
-        // Get database URL from environment
-        let db_url =
-            env::var("NEON_DATABASE_URL").expect("NEON_DATABASE_URL must be set in .env file");
+        // Get database URL from environment; without one the app works from the
+        // offline store only
+        let pool = match env::var("NEON_DATABASE_URL") {
+            Ok(db_url) => {
+                // Parse the connection string and create a pool config
+                let mut config = Config::new();
+                config.url = Some(db_url);
+                config.connect_timeout = Some(std::time::Duration::from_secs(5));
+
+                // Create TLS connector
+                let tls_connector = NativeTlsConnector::builder()
+                    .danger_accept_invalid_certs(true) // For testing only - remove in production
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let tls = MakeTlsConnector::new(tls_connector);
 
-        // Parse the connection string and create a pool config
-        let mut config = Config::new();
-        config.url = Some(db_url);
-        config.connect_timeout = Some(std::time::Duration::from_secs(5));
+                // Create the connection pool with TLS support
+                let pool = config.create_pool(Some(Runtime::Tokio1), tls)?;
 
-        // Create TLS connector
-        let tls_connector = NativeTlsConnector::builder()
-            .danger_accept_invalid_certs(true) // For testing only - remove in production
-            .build()
-            .map_err(|e| e.to_string())?;
-        let tls = MakeTlsConnector::new(tls_connector);
+                println!("Created Neon PostgreSQL connection pool");
+                Some(Arc::new(pool))
+            }
+            Err(_) => {
+                eprintln!("NEON_DATABASE_URL is not set; running offline with the local store");
+                None
+            }
+        };
 
-        // Create the connection pool with TLS support
-        let pool = config.create_pool(Some(Runtime::Tokio1), tls)?;
+        // Open the local write journal and offline store in the app data directory
+        let journal = WriteJournal::open(app_handle.path_resolver().app_data_dir());
+        let offline = OfflineStore::open(app_handle.path_resolver().app_data_dir());
 
-        println!("Created Neon PostgreSQL connection pool");
+        let (status_events, _) = broadcast::channel(STATUS_EVENT_CAPACITY);
 
         // Return the database instance
         Ok(Database {
-            pool: Arc::new(pool),
+            unreachable: AtomicBool::new(pool.is_none()),
+            pool,
+            journal,
+            offline,
+            status_events,
         })
     }
 
-    // Helper function to get a client from the pool
-    async fn get_client(&self) -> std::result::Result<PoolClient, PoolError> {
-        self.pool.get().await
+    pub fn subscribe_status_events(&self) -> broadcast::Receiver<StatusEvent> {
+        self.status_events.subscribe()
+    }
+
+    fn emit_status(&self, id: &str, status: &str, message: &Option<ItemMessage>) {
+        // No subscribers is fine; nobody is interested yet
+        let _ = self.status_events.send(StatusEvent {
+            item_id: id.to_string(),
+            status: status.to_string(),
+            message: message.as_ref().map(ItemMessage::text),
+            message_code: message.as_ref().map(|m| m.code.clone()),
+        });
+    }
+
+    // Append to the item's timeline when it enters a new status. Repeated updates in
+    // the same status (download progress) are not recorded. A failure here is only
+    // logged, since the status itself has already been written.
+    async fn record_status_event(
+        &self,
+        client: &PoolClient,
+        id: &str,
+        status: &str,
+        message: Option<&ItemMessage>,
+    ) {
+        let result = client
+            .execute(
+                "INSERT INTO item_events (id, item_id, status, message, message_code,
+                                          message_params, created_at)
+                 SELECT $1, $2, $3, $4, $5, $6, $7
+                 WHERE $3 IS DISTINCT FROM (
+                     SELECT status FROM item_events
+                     WHERE item_id = $2
+                     ORDER BY created_at DESC
+                     LIMIT 1
+                 )",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &id,
+                    &status,
+                    &message.map(ItemMessage::text),
+                    &message.map(|m| m.code.as_str()),
+                    &message.and_then(ItemMessage::params_json),
+                    &timestamps::now(),
+                ],
+            )
+            .await;
+        if let Err(e) = result {
+            eprintln!("[DB] Failed to record status event for item {}: {}", id, e);
+        }
+    }
+
+    // Helper function to get a client from the pool
+    async fn get_client(&self) -> std::result::Result<PoolClient, PoolError> {
+        let result = match &self.pool {
+            Some(pool) => pool.get().await,
+            None => Err(PoolError::Closed),
+        };
+        self.unreachable.store(result.is_err(), Ordering::Relaxed);
+        result
+    }
+
+    // True while the database can't be reached and the offline store is in use
+    pub fn is_offline(&self) -> bool {
+        self.unreachable.load(Ordering::Relaxed)
+    }
+
+    pub fn offline_item_count(&self) -> usize {
+        self.offline.pending_count()
+    }
+
+    // Make a hot-path write, journaling it if the database was unreachable, so callers
+    // such as the download loop keep going; the write is replayed once connectivity
+    // returns.
+    async fn journal_write(&self, write: PendingWrite) -> Result<WriteOutcome> {
+        let id = write.item_id().to_string();
+        let _lock = self.journal.lock_item(&id).await;
+        // Earlier writes for the item are still waiting: this one goes behind them,
+        // so they can't overwrite it when they are replayed
+        if self.journal.has_item(&id) {
+            self.offline.apply(&write);
+            let seq = self.journal.record(write);
+            return match self.replay_item(&id).await {
+                Some(applied) if applied.contains(&seq) => Ok(WriteOutcome::Written),
+                Some(_) => Err(format!("The database rejected the write for item {}", id).into()),
+                None => Ok(WriteOutcome::Deferred),
+            };
+        }
+
+        match self.apply_write(&write).await {
+            Ok(()) => Ok(WriteOutcome::Written),
+            Err(e) if is_connectivity_error(e.as_ref()) => {
+                eprintln!(
+                    "[DB] Database unreachable, journaling write for item {}: {}",
+                    id, e
+                );
+                self.offline.apply(&write);
+                self.journal.record(write);
+                Ok(WriteOutcome::Deferred)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn apply_write(&self, write: &PendingWrite) -> Result<()> {
+        match write {
+            PendingWrite::ItemStatus {
+                id,
+                status,
+                message,
+            } => self.write_item_status(id, status, message).await,
+            PendingWrite::AfterDownload {
+                id,
+                status,
+                title,
+                local_path,
+                thumbnail_url,
+                message,
+            } => {
+                self.write_after_download(id, status, title, local_path, thumbnail_url, message)
+                    .await
+            }
+            PendingWrite::EncodingDetails {
+                id,
+                status,
+                encoding_progress,
+                message,
+            } => {
+                self.write_encoding_details(id, status, *encoding_progress, message)
+                    .await
+            }
+            PendingWrite::DownloadStats(progress) => self.write_download_stats(progress).await,
+        }
+    }
+
+    // Replay the item's journaled writes in order; the caller holds the item's
+    // journal lock. Writes the database rejects are set aside. Returns the sequence
+    // numbers of the writes applied, or None while the database is unreachable.
+    async fn replay_item(&self, id: &str) -> Option<Vec<u64>> {
+        let mut applied = Vec::new();
+        for entry in self.journal.item_writes(id) {
+            match self.apply_write(&entry.write).await {
+                Ok(()) => {
+                    self.journal.complete(entry.seq);
+                    applied.push(entry.seq);
+                }
+                Err(e) if is_connectivity_error(e.as_ref()) => {
+                    eprintln!(
+                        "[DB] Writes for item {} still failing, will try again later: {}",
+                        id, e
+                    );
+                    return None;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[DB] Database rejected a journaled write for item {}, setting it aside: {}",
+                        id, e
+                    );
+                    self.journal.reject(entry.seq, &e.to_string());
+                }
+            }
+        }
+        Some(applied)
+    }
+
+    // Create the tables, or bring them up to date, from the embedded migrations (see
+    // migrations.rs). Returns the migrations applied now.
+    pub async fn migrate(&self) -> Result<Vec<String>> {
+        let mut client = self.get_client().await?;
+        migrations::apply(&mut client).await
+    }
+
+    pub async fn schema_info(&self) -> Result<SchemaInfo> {
+        let client = self.get_client().await?;
+        migrations::info(&client).await
+    }
+
+    // Name of the newest applied migration, from schema_version or else Prisma's
+    // migrations table; None for a database set up without either
+    pub async fn schema_version(&self) -> Result<Option<String>> {
+        if let Some(version) = self.schema_info().await?.current_version {
+            return Ok(Some(version));
+        }
+        let client = self.get_client().await?;
+        let has_migrations: bool = client
+            .query_one("SELECT to_regclass('_prisma_migrations') IS NOT NULL", &[])
+            .await?
+            .get(0);
+        if !has_migrations {
+            return Ok(None);
+        }
+        let row = client
+            .query_opt(
+                "SELECT migration_name FROM _prisma_migrations
+                 WHERE finished_at IS NOT NULL AND rolled_back_at IS NULL
+                 ORDER BY migration_name DESC LIMIT 1",
+                &[],
+            )
+            .await?;
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    // Queued items that failed before and wait for another attempt
+    pub async fn count_queued_retries(&self) -> Result<i64> {
+        let client = self.get_client().await?;
+        let row = client
+            .query_one(
+                "SELECT COUNT(*) FROM queue WHERE status = 'queued' AND failure_count > 0",
+                &[],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Journaled writes plus items added offline
+    pub fn pending_write_count(&self) -> usize {
+        self.journal.len() + self.offline.pending_count()
+    }
+
+    // Push items added offline, oldest first. One the database rejects (a URL that
+    // was queued elsewhere meanwhile) is dropped. Returns the number pushed, or None
+    // while the database is still unreachable.
+    async fn sync_offline_items(&self) -> Option<usize> {
+        let mut pushed = 0;
+        for item in self.offline.pending() {
+            let id = item.id.clone().unwrap_or_default();
+            match self.insert_queue_item(&item).await {
+                Ok(_) => {
+                    self.offline.remove_pending(&id);
+                    pushed += 1;
+                }
+                Err(e) if is_connectivity_error(e.as_ref()) => {
+                    eprintln!("[DB] Offline sync still failing, will try again later: {}", e);
+                    return None;
+                }
+                Err(e) => {
+                    eprintln!("[DB] Dropping item {} added offline: {}", id, e);
+                    self.offline.remove_pending(&id);
+                }
+            }
+        }
+        Some(pushed)
+    }
+
+    // Push items added offline, then replay journaled writes in order.
+    // Returns the number synced successfully.
+    pub async fn replay_pending_writes(&self) -> usize {
+        let mut replayed = match self.sync_offline_items().await {
+            Some(pushed) => pushed,
+            None => return 0,
+        };
+
+        let ids = self.journal.item_ids();
+        if ids.is_empty() {
+            return replayed;
+        }
+
+        println!("[DB] Replaying {} pending write(s)", self.journal.len());
+        for id in ids {
+            let _lock = self.journal.lock_item(&id).await;
+            match self.replay_item(&id).await {
+                Some(applied) => replayed += applied.len(),
+                // Unreachable again; the rest waits for the next round
+                None => break,
+            }
+        }
+
+        replayed
+    }
+
+    pub async fn add_video(&self, video: &Video) -> Result<i64> {
+        let client = self.get_client().await?;
+
+        let row = client
+            .query_one(
+                "INSERT INTO videos (title, url, local_path, thumbnail, status)
+             VALUES ($1, $2, $3, $4, $5) RETURNING id",
+                &[
+                    &video.title,
+                    &video.url,
+                    &video.local_path,
+                    &video.thumbnail,
+                    &video.status,
+                ],
+            )
+            .await?;
+
+        Ok(row.get::<_, i64>(0))
+    }
+
+    pub async fn get_videos(&self) -> Result<Vec<Video>> {
+        let client = self.get_client().await?;
+
+        let rows = client
+            .query(
+                "SELECT id, title, url, local_path, thumbnail, status, created_at
+             FROM videos ORDER BY created_at DESC",
+                &[],
+            )
+            .await?;
+
+        let mut videos = Vec::with_capacity(rows.len());
+        for row in rows {
+            videos.push(Video {
+                id: Some(row.get::<_, i64>(0)),
+                title: row.get::<_, String>(1),
+                url: row.get::<_, String>(2),
+                local_path: row.get::<_, Option<String>>(3),
+                thumbnail: row.get::<_, Option<String>>(4),
+                status: row.get::<_, String>(5),
+                created_at: row.get::<_, String>(6),
+            });
+        }
+
+        Ok(videos)
+    }
+
+    pub async fn update_video_status(&self, id: i64, status: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE videos SET status = $1 WHERE id = $2",
+                &[&status, &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_video_path(&self, id: i64, local_path: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE videos SET local_path = $1, status = 'downloaded' WHERE id = $2",
+                &[&local_path, &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Add an item, or keep it in the offline store while the database is unreachable.
+    // The duplicate check then runs when the item is synced.
+    pub async fn add_queue_item(&self, item: &QueueItem) -> Result<String> {
+        match self.insert_queue_item(item).await {
+            Err(e) if is_connectivity_error(e.as_ref()) => {
+                eprintln!("[DB] Database unreachable, keeping new item offline: {}", e);
+                let id = item
+                    .id
+                    .clone()
+                    .unwrap_or_else(|| Uuid::new_v4().to_string());
+                let mut offline_item = item.clone();
+                offline_item.id = Some(id.clone());
+                offline_item.added_at = Some(item.added_at.unwrap_or_else(timestamps::now));
+                self.offline.add_pending(offline_item);
+                Ok(id)
+            }
+            result => result,
+        }
+    }
+
+    async fn insert_queue_item(&self, item: &QueueItem) -> Result<String> {
+        let client = self.get_client().await?;
+        let id = item
+            .id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let video_key = urls::video_key(&item.url);
+
+        // Check if URL (or another URL of the same video) already exists
+        let rows = client
+            .query(
+                "SELECT status, user_id, filemoon_url, title FROM queue
+                 WHERE url = $1 OR video_key = $2",
+                &[&item.url, &video_key],
+            )
+            .await?;
+
+        if !rows.is_empty() {
+            let status: String = rows[0].get(0);
+            let user_id: String = rows[0].get(1);
+            let filemoon_url: Option<String> = rows[0].get(2);
+            let title: Option<String> = rows[0].get(3);
+            
+            // Check if it's from another user and already archived
+            let default_user = String::new();
+            let current_user_id = item.user_id.as_ref().unwrap_or(&default_user);
+            if &user_id != current_user_id && status == "uploaded" {
+                let mut error_msg = format!(
+                    "URL \'{}\' has already been archived by another user.",
+                    item.url
+                );
+                if let Some(url) = filemoon_url {
+                    error_msg.push_str(&format!(" Filemoon URL: {}", filemoon::embed_url(&url)));
+                }
+                if let Some(t) = title {
+                    error_msg.push_str(&format!(" Title: {}", t));
+                }
+                return Err(error_msg.into());
+            }
+            
+            // If it's the current user or in active queue
+            let error_message = if status == "uploaded" {
+                let mut msg = format!("URL \'{}\' has already been archived.", item.url);
+                if let Some(url) = filemoon_url {
+                    msg.push_str(&format!(" Filemoon URL: {}", filemoon::embed_url(&url)));
+                }
+                msg
+            } else {
+                format!(
+                    "URL \'{}\' already exists in the active queue (status: {}).",
+                    item.url, status
+                )
+            };
+            return Err(error_message.into());
+        }
+
+        insert_queue_row(&client, &id, item, &video_key).await?;
+        self.emit_status(&id, &item.status, &None);
+
+        Ok(id)
+    }
+
+    // Insert several items in one transaction. Per item, in order: Ok(id) when it was
+    // added, Err(status) of the item already queued for its URL or video otherwise.
+    // Items earlier in the list count too, so a URL pasted twice is added once.
+    pub async fn add_queue_items(
+        &self,
+        items: &[QueueItem],
+    ) -> Result<Vec<std::result::Result<String, String>>> {
+        let mut client = self.get_client().await?;
+        let tx = client.transaction().await?;
+
+        let mut results = Vec::with_capacity(items.len());
+        for item in items {
+            let video_key = urls::video_key(&item.url);
+            let existing = tx
+                .query_opt(
+                    "SELECT status FROM queue WHERE url = $1 OR video_key = $2 LIMIT 1",
+                    &[&item.url, &video_key],
+                )
+                .await?;
+            if let Some(row) = existing {
+                results.push(Err(row.get(0)));
+                continue;
+            }
+            let id = item
+                .id
+                .clone()
+                .unwrap_or_else(|| Uuid::new_v4().to_string());
+            insert_queue_row(&tx, &id, item, &video_key).await?;
+            results.push(Ok(id));
+        }
+        tx.commit().await?;
+        for (item, result) in items.iter().zip(&results) {
+            if let Ok(id) = result {
+                self.emit_status(id, &item.status, &None);
+            }
+        }
+
+        Ok(results)
+    }
+
+    pub async fn update_queue_item(&self, item: &QueueItem) -> Result<()> {
+        let client = self.get_client().await?;
+
+        if let Some(id) = &item.id {
+            client
+                .execute(
+                    "UPDATE queue SET
+                 video_key = CASE WHEN url = $1 THEN video_key END,
+                 url = $1,
+                 status = $2,
+                 message = $3,
+                 title = $4,
+                 filemoon_url = $5,
+                 encoding_progress = $6,
+                 thumbnail_url = $7,
+                 updated_at = $8,
+                 local_path = $9,
+                 user_id = $10,
+                 message_code = $11,
+                 message_params = $12
+                 WHERE id = $13",
+                    &[
+                        &item.url,
+                        &item.status,
+                        &item.message,
+                        &item.title,
+                        &item.filemoon_url,
+                        &item.encoding_progress,
+                        &item.thumbnail_url,
+                        &timestamps::now(),
+                        &item.local_path,
+                        &item.user_id.as_ref().unwrap_or(&String::new()),
+                        &item.message_code,
+                        &item.message_params.as_ref().map(JsonValue::to_string),
+                        &id,
+                    ],
+                )
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn update_item_status(
+        &self,
+        id: &str,
+        status: &str,
+        message: Option<ItemMessage>,
+    ) -> Result<WriteOutcome> {
+        self.journal_write(PendingWrite::ItemStatus {
+            id: id.to_string(),
+            status: status.to_string(),
+            message,
+        })
+        .await
+    }
+
+    async fn write_item_status(
+        &self,
+        id: &str,
+        status: &str,
+        message: &Option<ItemMessage>,
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+        let text = message.as_ref().map(ItemMessage::text);
+
+        client
+            .execute(
+                "UPDATE queue SET status = $1, message = $2, message_code = $3,
+                                  message_params = $4, updated_at = $5,
+                                  queue_position = CASE WHEN $1 = 'queued'
+                                                        THEN queue_position END
+                 WHERE id = $6",
+                &[
+                    &status,
+                    &text,
+                    &message.as_ref().map(|m| m.code.as_str()),
+                    &message.as_ref().and_then(ItemMessage::params_json),
+                    &timestamps::now(),
+                    &id,
+                ],
+            )
+            .await?;
+
+        self.record_status_event(&client, id, status, message.as_ref()).await;
+        self.emit_status(id, status, message);
+        Ok(())
+    }
+
+    pub async fn get_queue_items(&self, user_id: &str) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue WHERE user_id = $1 ORDER BY added_at DESC",
+            QUEUE_COLUMNS
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_queue_items", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&user_id]).await?)
+        })
+        .await;
+
+        match rows {
+            Ok(rows) => {
+                let items: Vec<QueueItem> = rows.iter().map(queue_item_from_row).collect();
+                self.offline.cache_items(user_id, &items);
+                Ok(items)
+            }
+            // Show the queue as last seen, plus anything added since
+            Err(e) if is_connectivity_error(e.as_ref()) => {
+                eprintln!("[DB] Database unreachable, showing the offline queue: {}", e);
+                Ok(self.offline.cached_items(user_id))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    // Items updated after `after`, oldest change first. Ties on updated_at are broken by id
+    // so paging never skips rows that share a timestamp.
+    pub async fn get_changes_since(
+        &self,
+        user_id: &str,
+        after_time: Option<DateTime<Utc>>,
+        after_id: &str,
+        limit: i64,
+    ) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue
+             WHERE user_id = $1
+               AND ($2::TIMESTAMPTZ IS NULL OR (updated_at, id) > ($2, $3))
+             ORDER BY updated_at ASC, id ASC
+             LIMIT $4",
+            QUEUE_COLUMNS
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_changes_since", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(query, &[&user_id, &after_time, &after_id, &limit])
+                .await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
+
+    // Effective settings for a user: their own overrides on top of the global defaults
+    pub async fn get_settings(&self, user_id: &str) -> Result<AppSettings> {
+        let user_settings = self.get_user_settings(user_id).await?;
+        let defaults = match self.get_global_settings().await {
+            Ok(defaults) => defaults,
+            Err(e) => {
+                eprintln!("Failed to load global default settings: {}", e);
+                AppSettings::default()
+            }
+        };
+        Ok(user_settings.with_defaults(&defaults))
+    }
+
+    // Only the values this user has set themselves
+    pub async fn get_user_settings(&self, user_id: &str) -> Result<AppSettings> {
+        let mut app_settings = AppSettings::default();
+
+        // Query for settings via Prisma's table
+        let rows = with_retry("get_settings", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT key, value FROM settings WHERE user_id = $1",
+                    &[&user_id],
+                )
+                .await?)
+        })
+        .await;
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => return self.offline_settings(Some(user_id), e),
+        };
+
+        for row in rows {
+            let key: String = row.get(0);
+            let value: Option<String> = row.get(1);
+
+            if let Some(value_str) = value {
+                match key.as_str() {
+                    "filemoon_api_key" => app_settings.filemoon_api_key = Some(value_str),
+                    "download_directory" => app_settings.download_directory = Some(value_str),
+                    "delete_after_upload" => app_settings.delete_after_upload = Some(value_str),
+                    "auto_upload" => app_settings.auto_upload = Some(value_str),
+                    "upload_target" => app_settings.upload_target = Some(value_str),
+                    "ffmpeg_path" => app_settings.ffmpeg_path = Some(value_str),
+                    "shortener_url" => app_settings.shortener_url = Some(value_str),
+                    "shortener_api_key" => app_settings.shortener_api_key = Some(value_str),
+                    "filemoon_monthly_quota_gb" => {
+                        app_settings.filemoon_monthly_quota_gb = Some(value_str)
+                    }
+                    "b2_monthly_quota_gb" => app_settings.b2_monthly_quota_gb = Some(value_str),
+                    "preferred_format" => app_settings.preferred_format = Some(value_str),
+                    "format_fallback_ladder" => {
+                        app_settings.format_fallback_ladder = Some(value_str)
+                    }
+                    "telegram_bot_token" => app_settings.telegram_bot_token = Some(value_str),
+                    "review_before_upload" => app_settings.review_before_upload = Some(value_str),
+                    "download_stall_minutes" => {
+                        app_settings.download_stall_minutes = Some(value_str)
+                    }
+                    "download_timeout_minutes" => {
+                        app_settings.download_timeout_minutes = Some(value_str)
+                    }
+                    "upload_timeout_minutes" => {
+                        app_settings.upload_timeout_minutes = Some(value_str)
+                    }
+                    "subtitle_languages" => app_settings.subtitle_languages = Some(value_str),
+                    "monthly_data_cap_gb" => app_settings.monthly_data_cap_gb = Some(value_str),
+                    "delete_grace_days" => app_settings.delete_grace_days = Some(value_str),
+                    "hook_after_download" => app_settings.hook_after_download = Some(value_str),
+                    "hook_after_upload" => app_settings.hook_after_upload = Some(value_str),
+                    "hook_on_failure" => app_settings.hook_on_failure = Some(value_str),
+                    "min_free_space_gb" => app_settings.min_free_space_gb = Some(value_str),
+                    "progress_persistence" => app_settings.progress_persistence = Some(value_str),
+                    "provider_http_headers" => app_settings.provider_http_headers = Some(value_str),
+                    "filemoon_api_base" => app_settings.filemoon_api_base = Some(value_str),
+                    "filemoon_site_base" => app_settings.filemoon_site_base = Some(value_str),
+                    "filemoon_upload_server_url" => {
+                        app_settings.filemoon_upload_server_url = Some(value_str)
+                    }
+                    "capture_page" => app_settings.capture_page = Some(value_str),
+                    "download_window_start" => app_settings.download_window_start = Some(value_str),
+                    "download_window_end" => app_settings.download_window_end = Some(value_str),
+                    "download_window_days" => app_settings.download_window_days = Some(value_str),
+                    "subtitle_auto_captions" => {
+                        app_settings.subtitle_auto_captions = Some(value_str)
+                    }
+                    "cookies_file" => app_settings.cookies_file = Some(value_str),
+                    "cookies_from_browser" => app_settings.cookies_from_browser = Some(value_str),
+                    "media_library_dir" => app_settings.media_library_dir = Some(value_str),
+                    "missing_ffmpeg_action" => app_settings.missing_ffmpeg_action = Some(value_str),
+                    "archive_org_access_key" => {
+                        app_settings.archive_org_access_key = Some(value_str)
+                    }
+                    "archive_org_secret_key" => {
+                        app_settings.archive_org_secret_key = Some(value_str)
+                    }
+                    "archive_org_collection" => {
+                        app_settings.archive_org_collection = Some(value_str)
+                    }
+                    "s3_endpoint" => app_settings.s3_endpoint = Some(value_str),
+                    "s3_bucket" => app_settings.s3_bucket = Some(value_str),
+                    "s3_region" => app_settings.s3_region = Some(value_str),
+                    "s3_access_key" => app_settings.s3_access_key = Some(value_str),
+                    "s3_secret_key" => app_settings.s3_secret_key = Some(value_str),
+                    "reuse_existing_uploads" => {
+                        app_settings.reuse_existing_uploads = Some(value_str)
+                    }
+                    "auto_upload_max_mb" => app_settings.auto_upload_max_mb = Some(value_str),
+                    "auto_upload_tags" => app_settings.auto_upload_tags = Some(value_str),
+                    "auto_upload_sites" => app_settings.auto_upload_sites = Some(value_str),
+                    "upload_window_start" => app_settings.upload_window_start = Some(value_str),
+                    "upload_window_end" => app_settings.upload_window_end = Some(value_str),
+                    "upload_window_days" => app_settings.upload_window_days = Some(value_str),
+                    "performance_mode" => app_settings.performance_mode = Some(value_str),
+                    "process_priority" => app_settings.process_priority = Some(value_str),
+                    "transcode_target" => app_settings.transcode_target = Some(value_str),
+                    "transcode_crf" => app_settings.transcode_crf = Some(value_str),
+                    "transcode_max_height" => app_settings.transcode_max_height = Some(value_str),
+                    "adaptive_concurrency" => app_settings.adaptive_concurrency = Some(value_str),
+                    "max_concurrent_downloads" => {
+                        app_settings.max_concurrent_downloads = Some(value_str)
+                    }
+                    "max_concurrent_uploads" => {
+                        app_settings.max_concurrent_uploads = Some(value_str)
+                    }
+                    "ytdlp_path" => app_settings.ytdlp_path = Some(value_str),
+                    "desktop_notifications" => app_settings.desktop_notifications = Some(value_str),
+                    "clipboard_watch" => app_settings.clipboard_watch = Some(value_str),
+                    "clipboard_sites" => app_settings.clipboard_sites = Some(value_str),
+                    "webhook_url" => app_settings.webhook_url = Some(value_str),
+                    "webhook_secret" => app_settings.webhook_secret = Some(value_str),
+                    "local_api_enabled" => app_settings.local_api_enabled = Some(value_str),
+                    "local_api_port" => app_settings.local_api_port = Some(value_str),
+                    "local_api_token" => app_settings.local_api_token = Some(value_str),
+                    "local_api_lan" => app_settings.local_api_lan = Some(value_str),
+                    "max_auto_retries" => app_settings.max_auto_retries = Some(value_str),
+                    "user_settings" => {
+                        // Parse JSON settings
+                        if let Ok(json_value) =
+                            serde_json::from_str::<serde_json::Value>(&value_str)
+                        {
+                            apply_settings_json(&mut app_settings, &json_value);
+                        }
+                    }
+                    _ => {} // Ignore other keys
+                }
+            }
+        }
+
+        self.offline.cache_settings(Some(user_id), &app_settings);
+        secrets::resolve(&mut app_settings);
+        Ok(app_settings)
+    }
+
+    // Settings as last read, when reading them failed because the database is
+    // unreachable; `error` otherwise
+    fn offline_settings(
+        &self,
+        user_id: Option<&str>,
+        error: Box<dyn std::error::Error + Send + Sync>,
+    ) -> Result<AppSettings> {
+        if !is_connectivity_error(error.as_ref()) {
+            return Err(error);
+        }
+        match self.offline.cached_settings(user_id) {
+            Some(mut settings) => {
+                eprintln!(
+                    "[DB] Database unreachable, using the settings last read: {}",
+                    error
+                );
+                secrets::resolve(&mut settings);
+                Ok(settings)
+            }
+            None => Err(error),
+        }
+    }
+
+    pub async fn get_global_settings(&self) -> Result<AppSettings> {
+        let rows = with_retry("get_global_settings", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT value FROM global_settings WHERE key = 'defaults'",
+                    &[],
+                )
+                .await?)
+        })
+        .await;
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => return self.offline_settings(None, e),
+        };
+
+        let mut defaults = AppSettings::default();
+        if let Some(value) = rows.first().and_then(|row| row.get::<_, Option<String>>(0)) {
+            if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(&value) {
+                apply_settings_json(&mut defaults, &json_value);
+            }
+        }
+        self.offline.cache_settings(None, &defaults);
+        secrets::resolve(&mut defaults);
+        Ok(defaults)
+    }
+
+    pub async fn save_global_settings(&self, settings: &AppSettings) -> Result<()> {
+        let settings = &secrets::store(secrets::GLOBAL_SCOPE, settings);
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "INSERT INTO global_settings (key, value, updated_at)
+                 VALUES ('defaults', $1, $2)
+                 ON CONFLICT (key)
+                 DO UPDATE SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at",
+                &[&settings_to_json(settings).to_string(), &timestamps::now()],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn save_settings(&self, settings: &AppSettings, user_id: &str) -> Result<()> {
+        // The frontend sends back the resolved settings, so values equal to the
+        // global default are dropped to keep them inherited
+        let defaults = self.get_global_settings().await.unwrap_or_default();
+        let settings = &settings.overrides_of(&defaults);
+        // API keys go to the OS keychain; only references are stored (see secrets.rs)
+        let settings = &secrets::store(user_id, settings);
+        self.write_user_settings(user_id, settings).await
+    }
+
+    // Drop the user's own values of `keys`, so they inherit the global defaults again
+    pub async fn reset_user_settings(&self, user_id: &str, keys: &[String]) -> Result<()> {
+        let mut json_value = settings_to_json(&self.get_user_settings(user_id).await?);
+        if let Some(fields) = json_value.as_object_mut() {
+            for key in keys {
+                fields.insert(key.clone(), JsonValue::Null);
+            }
+        }
+        let mut settings = AppSettings::default();
+        apply_settings_json(&mut settings, &json_value);
+        // Also forgets the keychain entries of secrets that were reset
+        let settings = &secrets::store(user_id, &settings);
+        self.write_user_settings(user_id, settings).await
+    }
+
+    // Whether the user may change the global settings
+    pub async fn is_admin(&self, user_id: &str) -> Result<bool> {
+        let client = self.get_client().await?;
+        let row = client
+            .query_one(
+                "SELECT EXISTS (SELECT 1 FROM users WHERE id = $1 AND is_admin)",
+                &[&user_id],
+            )
+            .await?;
+        Ok(row.get(0))
+    }
+
+    // Returns false when there is no such user
+    pub async fn set_admin(&self, user_id: &str, admin: bool) -> Result<bool> {
+        let client = self.get_client().await?;
+        let updated = client
+            .execute(
+                "UPDATE users SET is_admin = $2 WHERE id = $1",
+                &[&user_id, &admin],
+            )
+            .await?;
+        Ok(updated > 0)
+    }
+
+    // Replace the user's stored settings with `settings`, as they are to be stored
+    async fn write_user_settings(&self, user_id: &str, settings: &AppSettings) -> Result<()> {
+        let mut client = self.get_client().await?;
+
+        // Create JSON representation for all settings
+        let settings_json = settings_to_json(settings);
+
+        // Use a transaction to ensure atomic operations
+        let tx = client.transaction().await?;
+
+        // Clean up any old individual setting rows for this user
+        tx.execute(
+            "DELETE FROM settings WHERE user_id = $1 AND key IN ('filemoon_api_key', 'download_directory', 'delete_after_upload', 'auto_upload', 'upload_target', 'ffmpeg_path', 'shortener_url', 'shortener_api_key', 'filemoon_monthly_quota_gb', 'b2_monthly_quota_gb', 'preferred_format', 'format_fallback_ladder', 'telegram_bot_token', 'review_before_upload', 'download_stall_minutes', 'download_timeout_minutes', 'upload_timeout_minutes', 'subtitle_languages', 'monthly_data_cap_gb', 'delete_grace_days', 'hook_after_download', 'hook_after_upload', 'hook_on_failure', 'min_free_space_gb', 'progress_persistence', 'provider_http_headers', 'filemoon_api_base', 'filemoon_site_base', 'filemoon_upload_server_url', 'capture_page', 'download_window_start', 'download_window_end', 'download_window_days', 'subtitle_auto_captions', 'cookies_file', 'cookies_from_browser', 'media_library_dir', 'missing_ffmpeg_action', 'archive_org_access_key', 'archive_org_secret_key', 'archive_org_collection', 's3_endpoint', 's3_bucket', 's3_region', 's3_access_key', 's3_secret_key', 'reuse_existing_uploads', 'auto_upload_max_mb', 'auto_upload_tags', 'auto_upload_sites', 'upload_window_start', 'upload_window_end', 'upload_window_days', 'performance_mode', 'process_priority', 'transcode_target', 'transcode_crf', 'transcode_max_height', 'adaptive_concurrency', 'max_concurrent_downloads', 'max_concurrent_uploads', 'ytdlp_path', 'desktop_notifications', 'clipboard_watch', 'clipboard_sites', 'webhook_url', 'webhook_secret', 'local_api_enabled', 'local_api_port', 'local_api_token', 'local_api_lan', 'max_auto_retries')",
+            &[&user_id],
+        ).await?;
+
+        // Save only the JSON blob - no need for individual settings
+        tx.execute(
+            "INSERT INTO settings (key, value, user_id)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (key, user_id)
+             DO UPDATE SET value = EXCLUDED.value",
+            &[&"user_settings", &settings_json.to_string(), &user_id],
+        )
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    // Move secrets still stored in plaintext, from before they were kept in the OS
+    // keychain, out of the settings tables (see secrets.rs). Returns the number of
+    // rows rewritten.
+    pub async fn secure_stored_secrets(&self) -> Result<usize> {
+        let client = self.get_client().await?;
+        let mut secured = 0;
+
+        let rows = client
+            .query(
+                "SELECT user_id, value FROM settings WHERE key = 'user_settings'",
+                &[],
+            )
+            .await?;
+        for row in rows {
+            let user_id: String = row.get(0);
+            let value: Option<String> = row.get(1);
+            let json_value = match value.and_then(|v| serde_json::from_str(&v).ok()) {
+                Some(json_value) => json_value,
+                None => continue,
+            };
+            let mut plain = AppSettings::default();
+            apply_settings_json(&mut plain, &json_value);
+            if !secrets::has_plaintext(&plain) {
+                continue;
+            }
+            let stored = settings_to_json(&secrets::store(&user_id, &plain));
+            if stored == settings_to_json(&plain) {
+                continue;
+            }
+            client
+                .execute(
+                    "UPDATE settings SET value = $1 WHERE key = 'user_settings' AND user_id = $2",
+                    &[&stored.to_string(), &user_id],
+                )
+                .await?;
+            secured += 1;
+        }
+
+        // Rows of the older one-row-per-setting layout
+        let rows = client
+            .query(
+                "SELECT user_id, key, value FROM settings WHERE key = ANY($1)",
+                &[&secrets::SECRET_SETTINGS],
+            )
+            .await?;
+        for row in rows {
+            let user_id: String = row.get(0);
+            let key: String = row.get(1);
+            let value: Option<String> = row.get(2);
+            let reference = match value.and_then(|v| secrets::store_value(&user_id, &key, &v)) {
+                Some(reference) => reference,
+                None => continue,
+            };
+            client
+                .execute(
+                    "UPDATE settings SET value = $1 WHERE key = $2 AND user_id = $3",
+                    &[&reference, &key, &user_id],
+                )
+                .await?;
+            secured += 1;
+        }
+
+        let row = client
+            .query_opt(
+                "SELECT value FROM global_settings WHERE key = 'defaults'",
+                &[],
+            )
+            .await?;
+        let json_value = row
+            .and_then(|row| row.get::<_, Option<String>>(0))
+            .and_then(|v| serde_json::from_str::<serde_json::Value>(&v).ok());
+        if let Some(json_value) = json_value {
+            let mut plain = AppSettings::default();
+            apply_settings_json(&mut plain, &json_value);
+            if secrets::has_plaintext(&plain) {
+                let stored = settings_to_json(&secrets::store(secrets::GLOBAL_SCOPE, &plain));
+                if stored != settings_to_json(&plain) {
+                    client
+                        .execute(
+                            "UPDATE global_settings SET value = $1, updated_at = $2
+                             WHERE key = 'defaults'",
+                            &[&stored.to_string(), &timestamps::now()],
+                        )
+                        .await?;
+                    secured += 1;
+                }
+            }
+        }
+
+        Ok(secured)
+    }
+
+    // Manually ordered items first, then highest scheduling priority, oldest first among
+    // equals (see scheduler.rs). Skips `deferred_ids` (items backing off after a stall)
+    // and the items of `excluded_users` (whose download window is closed).
+    pub async fn get_next_queued_item(
+        &self,
+        deferred_ids: &[String],
+        excluded_users: &[String],
+    ) -> Result<Option<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue WHERE status = 'queued' AND NOT locked
+               AND NOT (id = ANY($1)) AND NOT (user_id = ANY($2))
+             ORDER BY {} LIMIT 1",
+            QUEUE_COLUMNS,
+            scheduler::QUEUE_ORDER_SQL.as_str()
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_next_queued_item", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&deferred_ids, &excluded_users]).await?)
+        })
+        .await?;
+
+        Ok(rows.first().map(queue_item_from_row))
+    }
+
+    pub async fn is_item_in_status(&self, statuses: &[&str]) -> Result<bool> {
+        if statuses.is_empty() {
+            return Ok(false);
+        }
+
+        with_retry("is_item_in_status", || async move {
+            let client = self.get_client().await?;
+
+            // Check each status type individually
+            for &status in statuses {
+                let rows = client
+                    .query("SELECT 1 FROM queue WHERE status = $1 LIMIT 1", &[&status])
+                    .await?;
+
+                if !rows.is_empty() {
+                    return Ok(true);
+                }
+            }
+
+            Ok(false)
+        })
+        .await
+    }
+
+    pub async fn update_item_after_download(
+        &self,
+        id: &str,
+        status: &str,
+        title: Option<String>,
+        local_path: Option<String>,
+        thumbnail_url: Option<String>,
+        message: Option<ItemMessage>,
+    ) -> Result<WriteOutcome> {
+        self.journal_write(PendingWrite::AfterDownload {
+            id: id.to_string(),
+            status: status.to_string(),
+            title,
+            local_path,
+            thumbnail_url,
+            message,
+        })
+        .await
+    }
+
+    async fn write_after_download(
+        &self,
+        id: &str,
+        status: &str,
+        title: &Option<String>,
+        local_path: &Option<String>,
+        thumbnail_url: &Option<String>,
+        message: &Option<ItemMessage>,
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+        let text = message.as_ref().map(ItemMessage::text);
+
+        client
+            .execute(
+                "UPDATE queue SET
+                status = $1,
+                title = $2,
+                local_path = $3,
+                thumbnail_url = $4,
+                message = $5,
+                message_code = $6,
+                message_params = $7,
+                updated_at = $8
+            WHERE id = $9",
+                &[
+                    &status,
+                    title,
+                    local_path,
+                    thumbnail_url,
+                    &text,
+                    &message.as_ref().map(|m| m.code.as_str()),
+                    &message.as_ref().and_then(ItemMessage::params_json),
+                    &timestamps::now(),
+                    &id,
+                ],
+            )
+            .await?;
+
+        self.record_status_event(&client, id, status, message.as_ref()).await;
+        self.emit_status(id, status, message);
+        Ok(())
+    }
+
+    pub async fn get_item_by_id(&self, id: &str) -> Result<Option<QueueItem>> {
+        let query = format!("SELECT {} FROM queue WHERE id = $1 LIMIT 1", QUEUE_COLUMNS);
+        let query = query.as_str();
+        let rows = with_retry("get_item_by_id", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&id]).await?)
+        })
+        .await?;
+
+        Ok(rows.first().map(queue_item_from_row))
+    }
+
+    pub async fn get_items_for_status_check(&self) -> Result<Vec<(String, String, String)>> {
+        let client = self.get_client().await?;
+
+        let rows = client
+            .query(
+                "SELECT q.id, q.filemoon_url, s.value, q.user_id
+             FROM queue q
+             JOIN settings s ON s.key = 'user_settings' AND s.user_id = q.user_id
+             WHERE 1=0",
+                &[],
+            )
+            .await?;
+
+        let mut items = Vec::with_capacity(rows.len());
+        for row in rows {
+            let id: String = row.get(0);
+            let filemoon_url: String = row.get(1);
+            let settings_json: String = row.get(2);
+            let user_id: Option<String> = row.get(3);
+
+            // Parse the JSON settings to extract the API key
+            match serde_json::from_str::<serde_json::Value>(&settings_json) {
+                Ok(settings_obj) => {
+                    if let Some(api_key) = settings_obj
+                        .get("filemoon_api_key")
+                        .and_then(|v| v.as_str())
+                    {
+                        if !api_key.is_empty() {
+                            items.push((id, filemoon_url, api_key.to_string()));
+                        }
+                    }
+                }
+                Err(_) => {
+                    // Failed to parse settings JSON, skip this item
+                }
+            }
+        }
+
+        Ok(items)
+    }
+
+    pub async fn get_items_in_statuses(&self, statuses: &[&str]) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue WHERE status = ANY($1) AND NOT locked ORDER BY updated_at ASC",
+            QUEUE_COLUMNS
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_items_in_statuses", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&statuses]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
+
+    pub async fn record_upload_usage(
+        &self,
+        user_id: &str,
+        item_id: &str,
+        provider: &str,
+        bytes: i64,
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "INSERT INTO upload_usage (id, user_id, item_id, provider, bytes, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &user_id,
+                    &item_id,
+                    &provider,
+                    &bytes,
+                    &timestamps::now(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Bytes moved for an item in one direction ("download" or "upload")
+    pub async fn record_bandwidth(
+        &self,
+        user_id: &str,
+        item_id: &str,
+        direction: &str,
+        bytes: i64,
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "INSERT INTO bandwidth_usage (id, user_id, item_id, direction, bytes, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &user_id,
+                    &item_id,
+                    &direction,
+                    &bytes,
+                    &timestamps::now(),
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // (UTC day as YYYY-MM-DD, direction, bytes) since `since`
+    pub async fn get_bandwidth_by_day(
+        &self,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(String, String, i64)>> {
+        let rows = with_retry("get_bandwidth_by_day", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT to_char(created_at AT TIME ZONE 'UTC', 'YYYY-MM-DD') AS day,
+                            direction, COALESCE(SUM(bytes), 0)::BIGINT
+                     FROM bandwidth_usage
+                     WHERE user_id = $1 AND created_at >= $2
+                     GROUP BY day, direction
+                     ORDER BY day",
+                    &[&user_id, &since],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect())
+    }
+
+    // (item id, title, download bytes, upload bytes) since `since`, biggest first
+    pub async fn get_bandwidth_by_item(
+        &self,
+        user_id: &str,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<(String, Option<String>, i64, i64)>> {
+        let rows = with_retry("get_bandwidth_by_item", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT b.item_id, q.title,
+                            COALESCE(SUM(b.bytes) FILTER (WHERE b.direction = 'download'), 0)::BIGINT,
+                            COALESCE(SUM(b.bytes) FILTER (WHERE b.direction = 'upload'), 0)::BIGINT
+                     FROM bandwidth_usage b
+                     LEFT JOIN queue q ON q.id = b.item_id
+                     WHERE b.user_id = $1 AND b.created_at >= $2
+                     GROUP BY b.item_id, q.title
+                     ORDER BY SUM(b.bytes) DESC",
+                    &[&user_id, &since],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3)))
+            .collect())
+    }
+
+    // Status changes recorded for an item, oldest first
+    pub async fn get_item_events(&self, item_id: &str) -> Result<Vec<ItemEvent>> {
+        let rows = with_retry("get_item_events", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT created_at, status, message, message_code, message_params
+                     FROM item_events
+                     WHERE item_id = $1
+                     ORDER BY created_at ASC",
+                    &[&item_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ItemEvent {
+                created_at: row.get(0),
+                status: row.get(1),
+                message: row.get(2),
+                message_code: row.get(3),
+                message_params: parse_message_params(row.get(4)),
+            })
+            .collect())
+    }
+
+    // Finished transfers for an item, oldest first: (time, direction, bytes)
+    pub async fn get_item_bandwidth(
+        &self,
+        item_id: &str,
+    ) -> Result<Vec<(DateTime<Utc>, String, i64)>> {
+        let rows = with_retry("get_item_bandwidth", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT created_at, direction, bytes FROM bandwidth_usage
+                     WHERE item_id = $1
+                     ORDER BY created_at ASC",
+                    &[&item_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect())
+    }
+
+    // Bytes and upload count per provider for the current calendar month (UTC)
+    pub async fn get_monthly_usage(&self, user_id: &str) -> Result<Vec<(String, i64, i64)>> {
+        let rows = with_retry("get_monthly_usage", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT provider, COALESCE(SUM(bytes), 0)::BIGINT, COUNT(*)
+                     FROM upload_usage
+                     WHERE user_id = $1
+                       AND created_at >= date_trunc('month', now() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+                     GROUP BY provider",
+                    &[&user_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect())
+    }
+
+    pub async fn set_download_sections(&self, id: &str, sections: Option<&str>) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET download_sections = $1, updated_at = $2 WHERE id = $3",
+                &[&sections, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // A new format starts the item's fallback ladder from the top again
+    pub async fn set_format_override(&self, id: &str, format: Option<&str>) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET format_override = $1, format_rung = NULL, updated_at = $2
+                 WHERE id = $3",
+                &[&format, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    pub async fn set_mirror_urls(&self, id: &str, mirror_urls: &[String]) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET mirror_urls = $1, updated_at = $2 WHERE id = $3",
+                &[&mirror_urls, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // Language metadata captured from the download's info.json
+    pub async fn set_language_metadata(
+        &self,
+        id: &str,
+        language: Option<&str>,
+        caption_languages: &[String],
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET language = $1, caption_languages = $2, updated_at = $3
+                 WHERE id = $4",
+                &[&language, &caption_languages, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Subtitle files found next to the download
+    pub async fn set_subtitle_paths(&self, id: &str, paths: &[String]) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET subtitle_paths = $1, updated_at = $2 WHERE id = $3",
+                &[&paths, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Record an upload to a declared provider (see providers.rs)
+    pub async fn set_provider_upload(&self, id: &str, provider: &str, url: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET upload_provider = $1, upload_url = $2, updated_at = $3
+                 WHERE id = $4",
+                &[&provider, &url, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Also drops the item's manual queue position, so the new priority takes effect
+    pub async fn set_priority(&self, id: &str, priority: i32) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET priority = $1, queue_position = NULL, updated_at = $2
+                 WHERE id = $3",
+                &[&priority, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // Put the user's queued items `ids` at the front of the queue, in that order,
+    // replacing any earlier manual order. Returns how many items were placed.
+    pub async fn reorder_queue(&self, user_id: &str, ids: &[String]) -> Result<u64> {
+        let mut client = self.get_client().await?;
+        let tx = client.transaction().await?;
+
+        tx.execute(
+            "UPDATE queue SET queue_position = NULL
+             WHERE user_id = $1 AND queue_position IS NOT NULL",
+            &[&user_id],
+        )
+        .await?;
+        let placed = tx
+            .execute(
+                "UPDATE queue SET queue_position = ordered.position::INTEGER, updated_at = $3
+                 FROM unnest($2::TEXT[]) WITH ORDINALITY AS ordered(id, position)
+                 WHERE queue.id = ordered.id AND queue.user_id = $1 AND queue.status = 'queued'",
+                &[&user_id, &ids, &timestamps::now()],
+            )
+            .await?;
+
+        tx.commit().await?;
+        Ok(placed)
+    }
+
+    // Ids of the user's queued items in the order the processor will pick them
+    pub async fn get_queue_order(&self, user_id: &str) -> Result<Vec<String>> {
+        let query = format!(
+            "SELECT id FROM queue WHERE user_id = $1 AND status = 'queued' ORDER BY {}",
+            scheduler::QUEUE_ORDER_SQL.as_str()
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_queue_order", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&user_id]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    // Count a failed download attempt; the scheduler demotes items that keep failing
+    pub async fn record_download_failure(&self, id: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET failure_count = failure_count + 1, updated_at = $1 WHERE id = $2",
+                &[&timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Which source the next download attempt should use (0 = primary URL)
+    pub async fn set_source_index(&self, id: &str, index: i32) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET source_index = $1, updated_at = $2 WHERE id = $3",
+                &[&index, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Rung of the format fallback ladder the next download attempt should use
+    pub async fn set_format_rung(&self, id: &str, rung: i32) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET format_rung = $1, updated_at = $2 WHERE id = $3",
+                &[&rung, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Format that produced the successful download
+    pub async fn set_format_used(&self, id: &str, format: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET format_used = $1, updated_at = $2 WHERE id = $3",
+                &[&format, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Size, progress, speed and ETA of the item's download, as last reported
+    pub async fn set_download_stats(&self, progress: &Progress) -> Result<WriteOutcome> {
+        self.journal_write(PendingWrite::DownloadStats(progress.clone()))
+            .await
+    }
+
+    async fn write_download_stats(&self, progress: &Progress) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET total_bytes = $1, downloaded_bytes = $2, speed_bps = $3,
+                                  eta_seconds = $4
+                 WHERE id = $5",
+                &[
+                    &progress.total_bytes,
+                    &progress.downloaded_bytes,
+                    &progress.speed_bps,
+                    &progress.eta_seconds,
+                    &progress.item_id,
+                ],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // When the failed item should be re-queued automatically; None cancels that
+    pub async fn set_next_retry_at(&self, id: &str, at: Option<DateTime<Utc>>) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET next_retry_at = $1 WHERE id = $2",
+                &[&at, &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Failed items whose automatic retry is due at `now`, oldest first
+    pub async fn get_due_retries(&self, now: DateTime<Utc>) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue
+             WHERE status = 'failed' AND next_retry_at <= $1 AND NOT locked
+             ORDER BY next_retry_at ASC",
+            QUEUE_COLUMNS
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_due_retries", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[&now]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
+
+    // Claim a due automatic retry: counts it and clears next_retry_at. Returns the new
+    // retry count, or None when the item was retried or changed in the meantime.
+    pub async fn claim_retry(&self, id: &str) -> Result<Option<i32>> {
+        let client = self.get_client().await?;
+
+        let row = client
+            .query_opt(
+                "UPDATE queue SET retry_count = retry_count + 1, next_retry_at = NULL
+                 WHERE id = $1 AND status = 'failed' AND next_retry_at IS NOT NULL
+                 RETURNING retry_count",
+                &[&id],
+            )
+            .await?;
+
+        Ok(row.map(|row| row.get(0)))
+    }
+
+    // Start counting automatic retries from zero again, after a manual retry
+    pub async fn reset_retries(&self, id: &str) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET retry_count = 0, next_retry_at = NULL WHERE id = $1",
+                &[&id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // The item's own automatic retry cap; None falls back to max_auto_retries.
+    // Returns false if the item doesn't exist.
+    pub async fn set_max_retries(&self, id: &str, max_retries: Option<i32>) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET max_retries = $1, updated_at = $2 WHERE id = $3",
+                &[&max_retries, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // Transfer the item with a Filemoon remote upload ("remote") or download it as
+    // usual (None). Returns false if the item doesn't exist.
+    pub async fn set_transfer_mode(&self, id: &str, mode: Option<&str>) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET transfer_mode = $1, updated_at = $2 WHERE id = $3",
+                &[&mode, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // File code of the item's running remote upload; None once it is over
+    pub async fn set_remote_filecode(&self, id: &str, filecode: Option<&str>) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET remote_filecode = $1 WHERE id = $2",
+                &[&filecode, &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    // Items Filemoon is fetching with a remote upload, oldest first
+    pub async fn get_remote_transfers(&self) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue
+             WHERE status = 'transferring' AND remote_filecode IS NOT NULL
+             ORDER BY updated_at ASC",
+            QUEUE_COLUMNS
+        );
+        let query = query.as_str();
+        let rows = with_retry("get_remote_transfers", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
+
+    // Lock or unlock an item. Returns false if the item doesn't exist.
+    pub async fn set_item_locked(&self, id: &str, locked: bool) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET locked = $1, updated_at = $2 WHERE id = $3",
+                &[&locked, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // Record whether the custom thumbnail upload succeeded
+    // Point the item at a new thumbnail; it has not been pushed to the provider yet
+    pub async fn set_custom_thumbnail(&self, id: &str, thumbnail: &str) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET thumbnail_url = $1, thumbnail_uploaded = NULL, updated_at = $2
+                 WHERE id = $3",
+                &[&thumbnail, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    pub async fn set_thumbnail_uploaded(&self, id: &str, uploaded: bool) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "UPDATE queue SET thumbnail_uploaded = $1, updated_at = $2 WHERE id = $3",
+                &[&uploaded, &timestamps::now(), &id],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn record_provider_error(
+        &self,
+        item_id: Option<&str>,
+        endpoint: &str,
+        http_status: Option<i32>,
+        message: &str,
+        response_body: Option<&str>,
+    ) -> Result<()> {
+        let client = self.get_client().await?;
+
+        let response_body = response_body.map(|body| {
+            body.chars()
+                .take(MAX_PROVIDER_ERROR_BODY)
+                .collect::<String>()
+        });
+        client
+            .execute(
+                "INSERT INTO provider_errors (id, item_id, endpoint, http_status, message,
+                                              response_body, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &item_id,
+                    &endpoint,
+                    &http_status,
+                    &message,
+                    &response_body,
+                    &timestamps::now(),
+                ],
+            )
+            .await?;
+
+        // Keep the table bounded
+        client
+            .execute(
+                "DELETE FROM provider_errors WHERE id IN (
+                     SELECT id FROM provider_errors ORDER BY created_at DESC OFFSET $1
+                 )",
+                &[&MAX_PROVIDER_ERRORS],
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_provider_errors(
+        &self,
+        item_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<ProviderError>> {
+        let rows = with_retry("get_provider_errors", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT id, item_id, endpoint, http_status, message, response_body, created_at
+                     FROM provider_errors
+                     WHERE $1::TEXT IS NULL OR item_id = $1
+                     ORDER BY created_at DESC
+                     LIMIT $2",
+                    &[&item_id, &limit],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| ProviderError {
+                id: row.get(0),
+                item_id: row.get(1),
+                endpoint: row.get(2),
+                http_status: row.get(3),
+                message: row.get(4),
+                response_body: row.get(5),
+                created_at: row.get::<_, DateTime<Utc>>(6),
+            })
+            .collect())
     }
 
-    pub async fn add_video(&self, video: &Video) -> Result<i64> {
+    pub async fn record_hook_run(&self, run: &HookRun) -> Result<()> {
         let client = self.get_client().await?;
 
-        let row = client
-            .query_one(
-                "INSERT INTO videos (title, url, local_path, thumbnail, status)
-             VALUES ($1, $2, $3, $4, $5) RETURNING id",
+        client
+            .execute(
+                "INSERT INTO hook_runs (id, item_id, event, command, exit_code, stdout, stderr,
+                                        error, started_at, finished_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
                 &[
-                    &video.title,
-                    &video.url,
-                    &video.local_path,
-                    &video.thumbnail,
-                    &video.status,
+                    &run.id,
+                    &run.item_id,
+                    &run.event,
+                    &run.command,
+                    &run.exit_code,
+                    &run.stdout,
+                    &run.stderr,
+                    &run.error,
+                    &run.started_at,
+                    &run.finished_at,
                 ],
             )
             .await?;
 
-        Ok(row.get::<_, i64>(0))
-    }
-
-    pub async fn get_videos(&self) -> Result<Vec<Video>> {
-        let client = self.get_client().await?;
-
-        let rows = client
-            .query(
-                "SELECT id, title, url, local_path, thumbnail, status, created_at
-             FROM videos ORDER BY created_at DESC",
-                &[],
+        // Keep the most recent runs of each item
+        client
+            .execute(
+                "DELETE FROM hook_runs WHERE id IN (
+                     SELECT id FROM hook_runs
+                     WHERE item_id = $1
+                     ORDER BY started_at DESC
+                     OFFSET $2
+                 )",
+                &[&run.item_id, &MAX_HOOK_RUNS_PER_ITEM],
             )
             .await?;
 
-        let mut videos = Vec::with_capacity(rows.len());
-        for row in rows {
-            videos.push(Video {
-                id: Some(row.get::<_, i64>(0)),
-                title: row.get::<_, String>(1),
-                url: row.get::<_, String>(2),
-                local_path: row.get::<_, Option<String>>(3),
-                thumbnail: row.get::<_, Option<String>>(4),
-                status: row.get::<_, String>(5),
-                created_at: row.get::<_, String>(6),
-            });
-        }
+        Ok(())
+    }
 
-        Ok(videos)
+    // Hook runs for an item, newest first
+    pub async fn get_hook_runs(&self, item_id: &str) -> Result<Vec<HookRun>> {
+        let rows = with_retry("get_hook_runs", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT id, item_id, event, command, exit_code, stdout, stderr, error,
+                            started_at, finished_at
+                     FROM hook_runs
+                     WHERE item_id = $1
+                     ORDER BY started_at DESC",
+                    &[&item_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| HookRun {
+                id: row.get(0),
+                item_id: row.get(1),
+                event: row.get(2),
+                command: row.get(3),
+                exit_code: row.get(4),
+                stdout: row.get(5),
+                stderr: row.get(6),
+                error: row.get(7),
+                started_at: row.get::<_, DateTime<Utc>>(8),
+                finished_at: row.get::<_, DateTime<Utc>>(9),
+            })
+            .collect())
     }
 
-    pub async fn update_video_status(&self, id: i64, status: &str) -> Result<()> {
+    // Record where an upload to `target` stands, replacing the item's earlier row for it
+    pub async fn record_upload(
+        &self,
+        item_id: &str,
+        target: &str,
+        status: &str,
+        url: Option<&str>,
+        error: Option<&str>,
+    ) -> Result<()> {
         let client = self.get_client().await?;
 
         client
             .execute(
-                "UPDATE videos SET status = $1 WHERE id = $2",
-                &[&status, &id],
+                "INSERT INTO uploads (id, item_id, target, status, url, error, updated_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (item_id, target) DO UPDATE SET
+                    status = EXCLUDED.status,
+                    url = COALESCE(EXCLUDED.url, uploads.url),
+                    error = EXCLUDED.error,
+                    updated_at = EXCLUDED.updated_at",
+                &[
+                    &Uuid::new_v4().to_string(),
+                    &item_id,
+                    &target,
+                    &status,
+                    &url,
+                    &error,
+                    &timestamps::now(),
+                ],
             )
             .await?;
 
         Ok(())
     }
 
-    pub async fn update_video_path(&self, id: i64, local_path: &str) -> Result<()> {
+    // Every destination of an item's upload, in the order they were first tried
+    pub async fn get_uploads(&self, item_id: &str) -> Result<Vec<UploadRecord>> {
+        let rows = with_retry("get_uploads", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT item_id, target, status, url, error, updated_at
+                     FROM uploads
+                     WHERE item_id = $1
+                     ORDER BY created_at",
+                    &[&item_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| UploadRecord {
+                item_id: row.get(0),
+                target: row.get(1),
+                status: row.get(2),
+                url: row.get(3),
+                error: row.get(4),
+                updated_at: row.get::<_, DateTime<Utc>>(5),
+            })
+            .collect())
+    }
+
+    // Store (or replace, after a re-download) the capture details for an item
+    pub async fn record_provenance(&self, record: &ProvenanceRecord) -> Result<()> {
         let client = self.get_client().await?;
 
         client
             .execute(
-                "UPDATE videos SET local_path = $1, status = 'downloaded' WHERE id = $2",
-                &[&local_path, &id],
+                "INSERT INTO provenance (item_id, webpage_url, extractor, video_id, captured_at,
+                                         ytdlp_version, format, file_name, file_size, sha256)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                 ON CONFLICT (item_id) DO UPDATE SET
+                    webpage_url = EXCLUDED.webpage_url,
+                    extractor = EXCLUDED.extractor,
+                    video_id = EXCLUDED.video_id,
+                    captured_at = EXCLUDED.captured_at,
+                    ytdlp_version = EXCLUDED.ytdlp_version,
+                    format = EXCLUDED.format,
+                    file_name = EXCLUDED.file_name,
+                    file_size = EXCLUDED.file_size,
+                    sha256 = EXCLUDED.sha256",
+                &[
+                    &record.item_id,
+                    &record.webpage_url,
+                    &record.extractor,
+                    &record.video_id,
+                    &record.captured_at,
+                    &record.ytdlp_version,
+                    &record.format,
+                    &record.file_name,
+                    &record.file_size,
+                    &record.sha256,
+                ],
             )
             .await?;
 
         Ok(())
     }
 
-    pub async fn add_queue_item(&self, item: &QueueItem) -> Result<String> {
-        let client = self.get_client().await?;
-        let id = item
-            .id
-            .clone()
-            .unwrap_or_else(|| Uuid::new_v4().to_string());
+    pub async fn get_provenance(&self, item_id: &str) -> Result<Option<ProvenanceRecord>> {
+        let row = with_retry("get_provenance", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query_opt(
+                    "SELECT item_id, webpage_url, extractor, video_id, captured_at,
+                            ytdlp_version, format, file_name, file_size, sha256
+                     FROM provenance WHERE item_id = $1",
+                    &[&item_id],
+                )
+                .await?)
+        })
+        .await?;
 
-        // Check if URL already exists for current user
-        let rows = client
-            .query(
-                "SELECT status, user_id, filemoon_url, title FROM queue WHERE url = $1",
-                &[&item.url],
-            )
-            .await?;
+        Ok(row.as_ref().map(provenance_from_row))
+    }
 
-        if !rows.is_empty() {
-            let status: String = rows[0].get(0);
-            let user_id: String = rows[0].get(1);
-            let filemoon_url: Option<String> = rows[0].get(2);
-            let title: Option<String> = rows[0].get(3);
-            
-            // Check if it's from another user and already archived
-            let default_user = String::new();
-            let current_user_id = item.user_id.as_ref().unwrap_or(&default_user);
-            if &user_id != current_user_id && status == "uploaded" {
-                let mut error_msg = format!(
-                    "URL \'{}\' has already been archived by another user.",
-                    item.url
-                );
-                if let Some(url) = filemoon_url {
-                    error_msg.push_str(&format!(" Filemoon URL: https://filemoon.sx/e/{}", url));
-                }
-                if let Some(t) = title {
-                    error_msg.push_str(&format!(" Title: {}", t));
-                }
-                return Err(error_msg.into());
-            }
-            
-            // If it's the current user or in active queue
-            let error_message = if status == "uploaded" {
-                let mut msg = format!("URL \'{}\' has already been archived.", item.url);
-                if let Some(url) = filemoon_url {
-                    msg.push_str(&format!(" Filemoon URL: https://filemoon.sx/e/{}", url));
-                }
-                msg
-            } else {
-                format!(
-                    "URL \'{}\' already exists in the active queue (status: {}).",
-                    item.url, status
+    // Provenance of every item the user owns, for duplicate detection
+    pub async fn get_user_provenance(&self, user_id: &str) -> Result<Vec<ProvenanceRecord>> {
+        let rows = with_retry("get_user_provenance", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT p.item_id, p.webpage_url, p.extractor, p.video_id, p.captured_at,
+                            p.ytdlp_version, p.format, p.file_name, p.file_size, p.sha256
+                     FROM provenance p
+                     JOIN queue q ON q.id = p.item_id
+                     WHERE q.user_id = $1",
+                    &[&user_id],
                 )
-            };
-            return Err(error_message.into());
-        }
+                .await?)
+        })
+        .await?;
 
-        // Convert timestamps to SystemTime for PostgreSQL compatibility
-        let added_at_timestamp = if let Some(added_at) = item.added_at {
-            SystemTime::UNIX_EPOCH + std::time::Duration::from_millis(added_at as u64)
-        } else {
-            SystemTime::now()
-        };
+        Ok(rows.iter().map(provenance_from_row).collect())
+    }
 
-        // Insert new queue item
-        client
+    pub async fn get_notification_rules(&self, user_id: &str) -> Result<Vec<NotificationRule>> {
+        let rows = with_retry("get_notification_rules", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT id, user_id, name, condition, channel, target, enabled, created_at
+                     FROM notification_rules
+                     WHERE user_id = $1
+                     ORDER BY created_at ASC",
+                    &[&user_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| NotificationRule {
+                id: Some(row.get(0)),
+                user_id: Some(row.get(1)),
+                name: row.get(2),
+                condition: row.get(3),
+                channel: row.get(4),
+                target: row.get(5),
+                enabled: row.get(6),
+                created_at: Some(row.get::<_, DateTime<Utc>>(7)),
+            })
+            .collect())
+    }
+
+    // Insert a new rule, or update the user's existing rule with the same id
+    pub async fn save_notification_rule(
+        &self,
+        rule: &NotificationRule,
+        user_id: &str,
+    ) -> Result<String> {
+        let client = self.get_client().await?;
+        let id = rule
+            .id
+            .clone()
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let updated = client
             .execute(
-                "INSERT INTO queue (id, url, status, message, title, filemoon_url,
-                                encoding_progress, thumbnail_url, added_at, updated_at, user_id)
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                "INSERT INTO notification_rules (id, user_id, name, condition, channel, target,
+                                                 enabled, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                 ON CONFLICT (id) DO UPDATE SET
+                    name = EXCLUDED.name,
+                    condition = EXCLUDED.condition,
+                    channel = EXCLUDED.channel,
+                    target = EXCLUDED.target,
+                    enabled = EXCLUDED.enabled
+                 WHERE notification_rules.user_id = EXCLUDED.user_id",
                 &[
                     &id,
-                    &item.url,
-                    &item.status,
-                    &item.message,
-                    &item.title,
-                    &item.filemoon_url,
-                    &item.encoding_progress,
-                    &item.thumbnail_url,
-                    &added_at_timestamp,
-                    &SystemTime::now(),
-                    &item.user_id.as_ref().unwrap(),
+                    &user_id,
+                    &rule.name,
+                    &rule.condition,
+                    &rule.channel,
+                    &rule.target,
+                    &rule.enabled,
+                    &timestamps::now(),
                 ],
             )
             .await?;
 
+        if updated == 0 {
+            return Err(format!("Notification rule {} belongs to another user", id).into());
+        }
         Ok(id)
     }
 
-    pub async fn update_queue_item(&self, item: &QueueItem) -> Result<()> {
+    // Returns false if the user has no rule with this id
+    pub async fn delete_notification_rule(&self, id: &str, user_id: &str) -> Result<bool> {
         let client = self.get_client().await?;
 
-        if let Some(id) = &item.id {
-            client
-                .execute(
-                    "UPDATE queue SET
-                 url = $1,
-                 status = $2,
-                 message = $3,
-                 title = $4,
-                 filemoon_url = $5,
-                 encoding_progress = $6,
-                 thumbnail_url = $7,
-                 updated_at = $8,
-                 local_path = $9,
-                 user_id = $10
-                 WHERE id = $11",
-                    &[
-                        &item.url,
-                        &item.status,
-                        &item.message,
-                        &item.title,
-                        &item.filemoon_url,
-                        &item.encoding_progress,
-                        &item.thumbnail_url,
-                        &SystemTime::now(),
-                        &item.local_path,
-                        &item.user_id.as_ref().unwrap_or(&String::new()),
-                        &id,
-                    ],
-                )
-                .await?;
-        }
+        let deleted = client
+            .execute(
+                "DELETE FROM notification_rules WHERE id = $1 AND user_id = $2",
+                &[&id, &user_id],
+            )
+            .await?;
 
-        Ok(())
+        Ok(deleted > 0)
     }
 
-    pub async fn update_item_status(
+    pub async fn get_queue_templates(&self, user_id: &str) -> Result<Vec<QueueTemplate>> {
+        let rows = with_retry("get_queue_templates", || async move {
+            let client = self.get_client().await?;
+            let query = format!(
+                "SELECT {} FROM queue_templates WHERE user_id = $1 ORDER BY name ASC",
+                TEMPLATE_COLUMNS
+            );
+            Ok(client.query(&query, &[&user_id]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_template_from_row).collect())
+    }
+
+    pub async fn get_queue_template(
         &self,
         id: &str,
-        status: &str,
-        message: Option<String>,
-    ) -> Result<()> {
+        user_id: &str,
+    ) -> Result<Option<QueueTemplate>> {
+        let row = with_retry("get_queue_template", || async move {
+            let client = self.get_client().await?;
+            let query = format!(
+                "SELECT {} FROM queue_templates WHERE id = $1 AND user_id = $2",
+                TEMPLATE_COLUMNS
+            );
+            Ok(client.query_opt(&query, &[&id, &user_id]).await?)
+        })
+        .await?;
+
+        Ok(row.as_ref().map(queue_template_from_row))
+    }
+
+    // Enabled templates with a schedule, for every user
+    pub async fn get_scheduled_templates(&self) -> Result<Vec<QueueTemplate>> {
+        let rows = with_retry("get_scheduled_templates", || async move {
+            let client = self.get_client().await?;
+            let query = format!(
+                "SELECT {} FROM queue_templates WHERE enabled AND schedule IS NOT NULL",
+                TEMPLATE_COLUMNS
+            );
+            Ok(client.query(&query, &[]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_template_from_row).collect())
+    }
+
+    // Insert a new template, or update the user's existing template with the same id
+    pub async fn save_queue_template(
+        &self,
+        template: &QueueTemplate,
+        user_id: &str,
+    ) -> Result<String> {
         let client = self.get_client().await?;
+        let id = template
+            .id
+            .clone()
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
 
-        client
+        let updated = client
             .execute(
-                "UPDATE queue SET status = $1, message = $2, updated_at = $3 WHERE id = $4",
-                &[&status, &message, &SystemTime::now(), &id],
+                "INSERT INTO queue_templates (id, user_id, name, url_pattern, format,
+                                              upload_target, tags, download_sections, priority,
+                                              schedule, enabled, created_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+                 ON CONFLICT (id) DO UPDATE SET
+                    name = EXCLUDED.name,
+                    url_pattern = EXCLUDED.url_pattern,
+                    format = EXCLUDED.format,
+                    upload_target = EXCLUDED.upload_target,
+                    tags = EXCLUDED.tags,
+                    download_sections = EXCLUDED.download_sections,
+                    priority = EXCLUDED.priority,
+                    schedule = EXCLUDED.schedule,
+                    enabled = EXCLUDED.enabled
+                 WHERE queue_templates.user_id = EXCLUDED.user_id",
+                &[
+                    &id,
+                    &user_id,
+                    &template.name,
+                    &template.url_pattern,
+                    &template.format,
+                    &template.upload_target,
+                    &template.tags,
+                    &template.download_sections,
+                    &template.priority,
+                    &template.schedule,
+                    &template.enabled,
+                    &timestamps::now(),
+                ],
             )
             .await?;
 
-        Ok(())
+        if updated == 0 {
+            return Err(format!("Queue template {} belongs to another user", id).into());
+        }
+        Ok(id)
     }
 
-    pub async fn get_queue_items(&self, user_id: &str) -> Result<Vec<QueueItem>> {
+    // Returns false if the user has no template with this id
+    pub async fn delete_queue_template(&self, id: &str, user_id: &str) -> Result<bool> {
         let client = self.get_client().await?;
 
-        let rows = client
-            .query(
-                "SELECT id, url, status, message, title, filemoon_url, encoding_progress,
-                        thumbnail_url, added_at, updated_at, local_path, user_id
-                 FROM queue
-                 WHERE user_id = $1
-                 ORDER BY added_at DESC",
-                &[&user_id],
+        let deleted = client
+            .execute(
+                "DELETE FROM queue_templates WHERE id = $1 AND user_id = $2",
+                &[&id, &user_id],
             )
             .await?;
 
-        let mut items = Vec::with_capacity(rows.len());
-        for row in rows {
-            items.push(QueueItem {
-                id: Some(row.get::<_, String>(0)),
-                url: row.get::<_, String>(1),
-                status: row.get::<_, String>(2),
-                message: row.get::<_, Option<String>>(3),
-                title: row.get::<_, Option<String>>(4),
-                filemoon_url: row.get::<_, Option<String>>(5),
-                encoding_progress: row.get::<_, Option<i32>>(6),
-                thumbnail_url: row.get::<_, Option<String>>(7),
-                added_at: Some(
-                    row.get::<_, SystemTime>(8)
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as i64,
-                ),
-                updated_at: Some(
-                    row.get::<_, SystemTime>(9)
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis() as i64,
-                ),
-                local_path: row.get::<_, Option<String>>(10),
-                user_id: Some(row.get::<_, String>(11)),
-            });
-        }
-
-        Ok(items)
+        Ok(deleted > 0)
     }
 
-    pub async fn get_settings(&self, user_id: &str) -> Result<AppSettings> {
+    pub async fn mark_template_run(&self, id: &str, at: DateTime<Utc>) -> Result<()> {
         let client = self.get_client().await?;
 
-        let mut app_settings = AppSettings::default();
-
-        // Query for settings via Prisma's table
-        let rows = client
-            .query(
-                "SELECT key, value FROM settings WHERE user_id = $1",
-                &[&user_id],
+        client
+            .execute(
+                "UPDATE queue_templates SET last_run_at = $1 WHERE id = $2",
+                &[&at, &id],
             )
             .await?;
 
-        for row in rows {
-            let key: String = row.get(0);
-            let value: Option<String> = row.get(1);
+        Ok(())
+    }
 
-            if let Some(value_str) = value {
-                match key.as_str() {
-                    "filemoon_api_key" => app_settings.filemoon_api_key = Some(value_str),
-                    "download_directory" => app_settings.download_directory = Some(value_str),
-                    "delete_after_upload" => app_settings.delete_after_upload = Some(value_str),
-                    "auto_upload" => app_settings.auto_upload = Some(value_str),
-                    "upload_target" => app_settings.upload_target = Some(value_str),
-                    "user_settings" => {
-                        // Parse JSON settings
-                        if let Ok(json_value) =
-                            serde_json::from_str::<serde_json::Value>(&value_str)
-                        {
-                            if let Some(obj) = json_value.as_object() {
-                                if let Some(val) =
-                                    obj.get("filemoon_api_key").and_then(|v| v.as_str())
-                                {
-                                    app_settings.filemoon_api_key = Some(val.to_string());
-                                }
-                                if let Some(val) =
-                                    obj.get("download_directory").and_then(|v| v.as_str())
-                                {
-                                    app_settings.download_directory = Some(val.to_string());
-                                }
-                                if let Some(val) =
-                                    obj.get("delete_after_upload").and_then(|v| v.as_str())
-                                {
-                                    app_settings.delete_after_upload = Some(val.to_string());
-                                }
-                                if let Some(val) = obj.get("auto_upload").and_then(|v| v.as_str()) {
-                                    app_settings.auto_upload = Some(val.to_string());
-                                }
-                                if let Some(val) = obj.get("upload_target").and_then(|v| v.as_str())
-                                {
-                                    app_settings.upload_target = Some(val.to_string());
-                                }
-                            }
-                        }
-                    }
-                    _ => {} // Ignore other keys
-                }
-            }
-        }
+    pub async fn add_playlist(&self, playlist: &Playlist) -> Result<()> {
+        let client = self.get_client().await?;
+
+        client
+            .execute(
+                "INSERT INTO playlists (id, user_id, url, title, source_id, entry_count, added_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)",
+                &[
+                    &playlist.id,
+                    &playlist.user_id,
+                    &playlist.url,
+                    &playlist.title,
+                    &playlist.source_id,
+                    &playlist.entry_count,
+                    &playlist.added_at,
+                ],
+            )
+            .await?;
 
-        Ok(app_settings)
+        Ok(())
     }
 
-    pub async fn save_settings(&self, settings: &AppSettings, user_id: &str) -> Result<()> {
-        let mut client = self.get_client().await?;
+    // Newest first
+    pub async fn get_playlists(&self, user_id: &str) -> Result<Vec<Playlist>> {
+        let rows = with_retry("get_playlists", || async move {
+            let client = self.get_client().await?;
+            let query = format!(
+                "SELECT {} FROM playlists WHERE user_id = $1 ORDER BY added_at DESC",
+                PLAYLIST_COLUMNS
+            );
+            Ok(client.query(&query, &[&user_id]).await?)
+        })
+        .await?;
 
-        // Create JSON representation for all settings
-        let settings_json = json!({
-            "filemoon_api_key": settings.filemoon_api_key,
-            "download_directory": settings.download_directory,
-            "delete_after_upload": settings.delete_after_upload,
-            "auto_upload": settings.auto_upload,
-            "upload_target": settings.upload_target
-        });
+        Ok(rows.iter().map(playlist_from_row).collect())
+    }
 
-        // Use a transaction to ensure atomic operations
+    // Items queued from one playlist, in playlist order, optionally of one status only
+    pub async fn get_playlist_children(
+        &self,
+        playlist_id: &str,
+        status: Option<&str>,
+    ) -> Result<Vec<QueueItem>> {
+        let rows = with_retry("get_playlist_children", || async move {
+            let client = self.get_client().await?;
+            let query = format!(
+                "SELECT {} FROM queue
+                 WHERE playlist_id = $1 AND ($2::TEXT IS NULL OR status = $2)
+                 ORDER BY added_at ASC, id ASC",
+                QUEUE_COLUMNS
+            );
+            Ok(client.query(&query, &[&playlist_id, &status]).await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
+
+    // (playlist id, status, item count) for every playlist of the user
+    pub async fn get_playlist_status_counts(
+        &self,
+        user_id: &str,
+    ) -> Result<Vec<(String, String, i64)>> {
+        let rows = with_retry("get_playlist_status_counts", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT playlist_id, status, COUNT(*) FROM queue
+                     WHERE user_id = $1 AND playlist_id IS NOT NULL
+                     GROUP BY playlist_id, status",
+                    &[&user_id],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2)))
+            .collect())
+    }
+
+    // Fold duplicates into the surviving item: it takes the merged mirror URLs and
+    // the duplicates' rows (and their provenance) are deleted, all in one transaction.
+    // Returns the number of duplicates deleted.
+    pub async fn merge_duplicate_items(
+        &self,
+        keep_id: &str,
+        duplicate_ids: &[String],
+        mirror_urls: &[String],
+        user_id: &str,
+    ) -> Result<u64> {
+        let mut client = self.get_client().await?;
         let tx = client.transaction().await?;
 
-        // Clean up any old individual setting rows for this user
-        tx.execute(
-            "DELETE FROM settings WHERE user_id = $1 AND key IN ('filemoon_api_key', 'download_directory', 'delete_after_upload', 'auto_upload', 'upload_target')",
-            &[&user_id],
-        ).await?;
+        let updated = tx
+            .execute(
+                "UPDATE queue SET mirror_urls = $1, updated_at = $2 WHERE id = $3 AND user_id = $4",
+                &[&mirror_urls, &timestamps::now(), &keep_id, &user_id],
+            )
+            .await?;
+        if updated == 0 {
+            return Err(format!("Item {} not found", keep_id).into());
+        }
 
-        // Save only the JSON blob - no need for individual settings
         tx.execute(
-            "INSERT INTO settings (key, value, user_id)
-             VALUES ($1, $2, $3)
-             ON CONFLICT (key, user_id)
-             DO UPDATE SET value = EXCLUDED.value",
-            &[&"user_settings", &settings_json.to_string(), &user_id],
+            "DELETE FROM provenance WHERE item_id = ANY($1)
+               AND item_id IN (SELECT id FROM queue WHERE user_id = $2)",
+            &[&duplicate_ids, &user_id],
         )
         .await?;
+        let deleted = tx
+            .execute(
+                "DELETE FROM queue WHERE id = ANY($1) AND user_id = $2 AND id <> $3",
+                &[&duplicate_ids, &user_id, &keep_id],
+            )
+            .await?;
 
         tx.commit().await?;
+        Ok(deleted)
+    }
 
-        Ok(())
+    // Every item of the user's that `filter` matches, newest first
+    pub async fn find_items(&self, user_id: &str, filter: &BulkFilter) -> Result<Vec<QueueItem>> {
+        let query = format!(
+            "SELECT {} FROM queue WHERE {} ORDER BY added_at DESC",
+            QUEUE_COLUMNS,
+            bulk_edit::FILTER_SQL
+        );
+        let query = query.as_str();
+        let rows = with_retry("find_items", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    query,
+                    &[
+                        &user_id,
+                        &filter.ids,
+                        &filter.statuses,
+                        &filter.tag,
+                        &filter.title_contains,
+                        &filter.url_contains,
+                        &filter.playlist_id,
+                        &filter.collection,
+                    ],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows.iter().map(queue_item_from_row).collect())
     }
 
-    pub async fn get_next_queued_item(&self) -> Result<Option<QueueItem>> {
+    // Remove the given items of the user's from the queue
+    pub async fn delete_items(&self, user_id: &str, ids: &[String]) -> Result<u64> {
         let client = self.get_client().await?;
-
-        let rows = client
-            .query(
-                "SELECT id, url, status, message, title, filemoon_url, encoding_progress,
-                        thumbnail_url, added_at, updated_at, local_path, user_id
-                 FROM queue
-                 WHERE status = 'queued'
-                 ORDER BY added_at ASC
-                 LIMIT 1",
-                &[],
+        Ok(client
+            .execute(
+                "DELETE FROM queue WHERE user_id = $1 AND id = ANY($2)",
+                &[&user_id, &ids],
             )
-            .await?;
-
-        if rows.is_empty() {
-            return Ok(None);
-        }
-
-        let row = &rows[0];
-        let item = QueueItem {
-            id: Some(row.get::<_, String>(0)),
-            url: row.get::<_, String>(1),
-            status: row.get::<_, String>(2),
-            message: row.get::<_, Option<String>>(3),
-            title: row.get::<_, Option<String>>(4),
-            filemoon_url: row.get::<_, Option<String>>(5),
-            encoding_progress: row.get::<_, Option<i32>>(6),
-            thumbnail_url: row.get::<_, Option<String>>(7),
-            added_at: Some(
-                row.get::<_, SystemTime>(8)
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
-            ),
-            updated_at: Some(
-                row.get::<_, SystemTime>(9)
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
-            ),
-            local_path: row.get::<_, Option<String>>(10),
-            user_id: Some(row.get::<_, String>(11)),
-        };
-
-        Ok(Some(item))
+            .await?)
     }
 
-    pub async fn is_item_in_status(&self, statuses: &[&str]) -> Result<bool> {
-        if statuses.is_empty() {
-            return Ok(false);
+    // Apply `edit` to every item of the user's that `filter` matches, or with
+    // `dry_run` only report what it would change. Counts and the sample of changes
+    // are read in the same transaction as the UPDATE, before it runs.
+    pub async fn bulk_edit(
+        &self,
+        user_id: &str,
+        filter: &BulkFilter,
+        edit: &BulkEdit,
+        dry_run: bool,
+    ) -> Result<BulkEditResult> {
+        let (column, expression) = edit.assignment();
+        let edit_params = edit.params();
+        let now = timestamps::now();
+        let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = vec![
+            &user_id,
+            &filter.ids,
+            &filter.statuses,
+            &filter.tag,
+            &filter.title_contains,
+            &filter.url_contains,
+            &filter.playlist_id,
+            &filter.collection,
+        ];
+        for param in &edit_params {
+            params.push(param);
         }
+        let changes_sql = format!("{} IS DISTINCT FROM {}", column, expression);
 
-        let client = self.get_client().await?;
+        let mut client = self.get_client().await?;
+        let tx = client.transaction().await?;
 
-        // Check each status type individually
-        for &status in statuses {
-            let rows = client
-                .query("SELECT 1 FROM queue WHERE status = $1 LIMIT 1", &[&status])
-                .await?;
+        let counts = tx
+            .query_one(
+                format!(
+                    "SELECT COUNT(*), COUNT(*) FILTER (WHERE {}) FROM queue WHERE {}",
+                    changes_sql,
+                    bulk_edit::FILTER_SQL
+                )
+                .as_str(),
+                &params,
+            )
+            .await?;
+        let rows = tx
+            .query(
+                format!(
+                    "SELECT id, title, to_jsonb({})::TEXT, to_jsonb({})::TEXT FROM queue
+                     WHERE {} AND {}
+                     ORDER BY added_at DESC
+                     LIMIT {}",
+                    column,
+                    expression,
+                    bulk_edit::FILTER_SQL,
+                    changes_sql,
+                    bulk_edit::PREVIEW_LIMIT
+                )
+                .as_str(),
+                &params,
+            )
+            .await?;
+        let json = |raw: Option<String>| {
+            raw.and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or(JsonValue::Null)
+        };
+        let changes = rows
+            .iter()
+            .map(|row| BulkChange {
+                id: row.get(0),
+                title: row.get(1),
+                before: json(row.get(2)),
+                after: json(row.get(3)),
+            })
+            .collect();
 
-            if !rows.is_empty() {
-                return Ok(true);
-            }
+        let mut changed: i64 = counts.get(1);
+        if !dry_run {
+            params.push(&now);
+            let updated = tx
+                .execute(
+                    format!(
+                        "UPDATE queue SET {} = {}, updated_at = ${} WHERE {} AND {}",
+                        column,
+                        expression,
+                        params.len(),
+                        bulk_edit::FILTER_SQL,
+                        changes_sql
+                    )
+                    .as_str(),
+                    &params,
+                )
+                .await?;
+            changed = updated as i64;
         }
+        tx.commit().await?;
 
-        Ok(false)
+        Ok(BulkEditResult {
+            dry_run,
+            matched: counts.get(0),
+            changed,
+            changes,
+        })
     }
 
-    pub async fn update_item_after_download(
-        &self,
-        id: &str,
-        status: &str,
-        title: Option<String>,
-        local_path: Option<String>,
-        thumbnail_url: Option<String>,
-        message: Option<String>,
-    ) -> Result<()> {
+    pub async fn set_upload_verified(&self, id: &str, at: DateTime<Utc>) -> Result<()> {
         let client = self.get_client().await?;
 
         client
             .execute(
-                "UPDATE queue SET
-                status = $1,
-                title = $2,
-                local_path = $3,
-                thumbnail_url = $4,
-                message = $5,
-                updated_at = $6
-            WHERE id = $7",
-                &[
-                    &status,
-                    &title,
-                    &local_path,
-                    &thumbnail_url,
-                    &message,
-                    &SystemTime::now(),
-                    &id,
-                ],
+                "UPDATE queue SET upload_verified_at = $1, updated_at = $2 WHERE id = $3",
+                &[&at, &timestamps::now(), &id],
             )
             .await?;
 
         Ok(())
     }
 
-    pub async fn get_item_by_id(&self, id: &str) -> Result<Option<QueueItem>> {
+    // Forget the local copy once it has been deleted
+    pub async fn clear_local_path(&self, id: &str) -> Result<()> {
         let client = self.get_client().await?;
 
-        let rows = client
-            .query(
-                "SELECT id, url, status, message, title, filemoon_url, encoding_progress,
-                        thumbnail_url, added_at, updated_at, local_path, user_id
-                 FROM queue
-                 WHERE id = $1
-                 LIMIT 1",
-                &[&id],
+        client
+            .execute(
+                "UPDATE queue SET local_path = NULL, updated_at = $1 WHERE id = $2",
+                &[&timestamps::now(), &id],
             )
             .await?;
 
-        if rows.is_empty() {
-            return Ok(None);
-        }
-
-        let row = &rows[0];
-        let item = QueueItem {
-            id: Some(row.get::<_, String>(0)),
-            url: row.get::<_, String>(1),
-            status: row.get::<_, String>(2),
-            message: row.get::<_, Option<String>>(3),
-            title: row.get::<_, Option<String>>(4),
-            filemoon_url: row.get::<_, Option<String>>(5),
-            encoding_progress: row.get::<_, Option<i32>>(6),
-            thumbnail_url: row.get::<_, Option<String>>(7),
-            added_at: Some(
-                row.get::<_, SystemTime>(8)
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
-            ),
-            updated_at: Some(
-                row.get::<_, SystemTime>(9)
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap()
-                    .as_millis() as i64,
-            ),
-            local_path: row.get::<_, Option<String>>(10),
-            user_id: Some(row.get::<_, String>(11)),
-        };
-
-        Ok(Some(item))
+        Ok(())
     }
 
-    pub async fn get_items_for_status_check(&self) -> Result<Vec<(String, String, String)>> {
+    pub async fn set_short_url(&self, id: &str, short_url: &str) -> Result<()> {
         let client = self.get_client().await?;
 
-        let rows = client
-            .query(
-                "SELECT q.id, q.filemoon_url, s.value, q.user_id
-             FROM queue q
-             JOIN settings s ON s.key = 'user_settings' AND s.user_id = q.user_id
-             WHERE 1=0",
-                &[],
+        client
+            .execute(
+                "UPDATE queue SET short_url = $1, updated_at = $2 WHERE id = $3",
+                &[&short_url, &timestamps::now(), &id],
             )
             .await?;
 
-        let mut items = Vec::with_capacity(rows.len());
-        for row in rows {
-            let id: String = row.get(0);
-            let filemoon_url: String = row.get(1);
-            let settings_json: String = row.get(2);
-            let user_id: Option<String> = row.get(3);
-
-            // Parse the JSON settings to extract the API key
-            match serde_json::from_str::<serde_json::Value>(&settings_json) {
-                Ok(settings_obj) => {
-                    if let Some(api_key) = settings_obj
-                        .get("filemoon_api_key")
-                        .and_then(|v| v.as_str())
-                    {
-                        if !api_key.is_empty() {
-                            items.push((id, filemoon_url, api_key.to_string()));
-                        }
-                    }
-                }
-                Err(_) => {
-                    // Failed to parse settings JSON, skip this item
-                }
-            }
-        }
-
-        Ok(items)
+        Ok(())
     }
 
     pub async fn update_item_encoding_details(
@@ -659,9 +3980,26 @@ impl Database {
         id: &str,
         status: &str,
         encoding_progress: Option<i32>,
-        message: Option<String>,
+        message: Option<ItemMessage>,
+    ) -> Result<WriteOutcome> {
+        self.journal_write(PendingWrite::EncodingDetails {
+            id: id.to_string(),
+            status: status.to_string(),
+            encoding_progress,
+            message,
+        })
+        .await
+    }
+
+    async fn write_encoding_details(
+        &self,
+        id: &str,
+        status: &str,
+        encoding_progress: Option<i32>,
+        message: &Option<ItemMessage>,
     ) -> Result<()> {
         let client = self.get_client().await?;
+        let text = message.as_ref().map(ItemMessage::text);
 
         client
             .execute(
@@ -669,84 +4007,343 @@ impl Database {
                 status = $1,
                 encoding_progress = $2,
                 message = $3,
-                updated_at = $4
-            WHERE id = $5",
+                message_code = $4,
+                message_params = $5,
+                updated_at = $6
+            WHERE id = $7",
                 &[
                     &status,
                     &encoding_progress,
-                    &message,
-                    &SystemTime::now(),
+                    &text,
+                    &message.as_ref().map(|m| m.code.as_str()),
+                    &message.as_ref().and_then(ItemMessage::params_json),
+                    &timestamps::now(),
                     &id,
                 ],
             )
             .await?;
 
+        self.record_status_event(&client, id, status, message.as_ref()).await;
+        self.emit_status(id, status, message);
         Ok(())
     }
 
-    pub async fn clear_items_by_status(
-        &self,
-        status_types: &[String],
-        user_id: &str,
-    ) -> Result<ClearResult> {
-        if status_types.is_empty() {
-            println!("[DEBUG] clear_items_by_status: No status types provided");
-            return Ok(ClearResult {
-                total_deleted: 0,
-                status_types: vec![],
-                user_id: user_id.to_string(),
-            });
-        }
+    // Every queue row for every user, used for local snapshots
+    pub async fn get_all_queue_items(&self) -> Result<Vec<QueueItem>> {
+        let query = format!("SELECT {} FROM queue ORDER BY added_at ASC", QUEUE_COLUMNS);
+        let query = query.as_str();
+        let rows = with_retry("get_all_queue_items", || async move {
+            let client = self.get_client().await?;
+            Ok(client.query(query, &[]).await?)
+        })
+        .await?;
 
-        println!(
-            "[DEBUG] clear_items_by_status called with status_types: {:?}, user_id: {}",
-            status_types, user_id
-        );
+        Ok(rows.iter().map(queue_item_from_row).collect())
+    }
 
-        let client = self.get_client().await?;
+    pub async fn get_all_settings_rows(&self) -> Result<Vec<SettingRow>> {
+        let rows = with_retry("get_all_settings_rows", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query("SELECT key, value, user_id FROM settings", &[])
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| SettingRow {
+                key: row.get(0),
+                value: row.get(1),
+                user_id: row.get(2),
+            })
+            .collect())
+    }
 
-        // First, count items before deletion for debugging
-        for status in status_types {
-            let count_result = client
+    pub async fn get_all_global_settings_rows(&self) -> Result<Vec<GlobalSettingRow>> {
+        let rows = with_retry("get_all_global_settings_rows", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query("SELECT key, value, updated_at FROM global_settings", &[])
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| GlobalSettingRow {
+                key: row.get(0),
+                value: row.get(1),
+                updated_at: row.get::<_, DateTime<Utc>>(2),
+            })
+            .collect())
+    }
+
+    pub async fn get_all_upload_rows(&self) -> Result<Vec<UploadRow>> {
+        let rows = with_retry("get_all_upload_rows", || async move {
+            let client = self.get_client().await?;
+            Ok(client
                 .query(
-                    "SELECT COUNT(*) FROM queue WHERE status = $1 AND user_id = $2",
-                    &[&status.as_str(), &user_id],
+                    "SELECT id, item_id, target, status, url, error, created_at, updated_at
+                     FROM uploads
+                     ORDER BY created_at",
+                    &[],
+                )
+                .await?)
+        })
+        .await?;
+
+        Ok(rows
+            .iter()
+            .map(|row| UploadRow {
+                id: row.get(0),
+                item_id: row.get(1),
+                target: row.get(2),
+                status: row.get(3),
+                url: row.get(4),
+                error: row.get(5),
+                created_at: row.get::<_, DateTime<Utc>>(6),
+                updated_at: row.get::<_, DateTime<Utc>>(7),
+            })
+            .collect())
+    }
+
+    // Upsert queue, settings, global settings and uploads rows from a snapshot in a
+    // single transaction. Rows that exist in the database but not in the snapshot
+    // are left alone.
+    pub async fn restore_snapshot_rows(
+        &self,
+        queue: &[QueueItem],
+        settings: &[SettingRow],
+        global_settings: &[GlobalSettingRow],
+        uploads: &[UploadRow],
+    ) -> Result<RestoredRows> {
+        let mut client = self.get_client().await?;
+        let tx = client.transaction().await?;
+        let mut restored = RestoredRows::default();
+
+        for item in queue {
+            let id = match &item.id {
+                Some(id) => id,
+                None => continue,
+            };
+            let added_at = item.added_at.unwrap_or_default();
+            let updated_at = item.updated_at.unwrap_or_else(timestamps::now);
+            let user_id = item.user_id.clone().unwrap_or_default();
+
+            restored.items += tx
+                .execute(
+                    "INSERT INTO queue (id, url, status, message, title, filemoon_url,
+                                encoding_progress, thumbnail_url, added_at, updated_at,
+                                local_path, user_id, short_url, thumbnail_uploaded, locked,
+                                format_rung, format_used, download_sections, mirror_urls,
+                                source_index, priority, failure_count, language,
+                                caption_languages, format_override, tags, template_id,
+                                upload_verified_at, message_code, message_params, playlist_id,
+                                subtitle_paths, upload_provider, upload_url, collection,
+                                retry_count, max_retries, next_retry_at, total_bytes,
+                                downloaded_bytes, speed_bps, eta_seconds, transfer_mode,
+                                remote_filecode, upload_target)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15,
+                             $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28,
+                             $29, $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41,
+                             $42, $43, $44, $45)
+                     ON CONFLICT (id) DO UPDATE SET
+                        url = EXCLUDED.url,
+                        status = EXCLUDED.status,
+                        message = EXCLUDED.message,
+                        title = EXCLUDED.title,
+                        filemoon_url = EXCLUDED.filemoon_url,
+                        encoding_progress = EXCLUDED.encoding_progress,
+                        thumbnail_url = EXCLUDED.thumbnail_url,
+                        added_at = EXCLUDED.added_at,
+                        updated_at = EXCLUDED.updated_at,
+                        local_path = EXCLUDED.local_path,
+                        user_id = EXCLUDED.user_id,
+                        short_url = EXCLUDED.short_url,
+                        thumbnail_uploaded = EXCLUDED.thumbnail_uploaded,
+                        locked = EXCLUDED.locked,
+                        format_rung = EXCLUDED.format_rung,
+                        format_used = EXCLUDED.format_used,
+                        download_sections = EXCLUDED.download_sections,
+                        mirror_urls = EXCLUDED.mirror_urls,
+                        source_index = EXCLUDED.source_index,
+                        priority = EXCLUDED.priority,
+                        failure_count = EXCLUDED.failure_count,
+                        language = EXCLUDED.language,
+                        caption_languages = EXCLUDED.caption_languages,
+                        format_override = EXCLUDED.format_override,
+                        tags = EXCLUDED.tags,
+                        template_id = EXCLUDED.template_id,
+                        upload_verified_at = EXCLUDED.upload_verified_at,
+                        message_code = EXCLUDED.message_code,
+                        message_params = EXCLUDED.message_params,
+                        playlist_id = EXCLUDED.playlist_id,
+                        subtitle_paths = EXCLUDED.subtitle_paths,
+                        upload_provider = EXCLUDED.upload_provider,
+                        upload_url = EXCLUDED.upload_url,
+                        collection = EXCLUDED.collection,
+                        retry_count = EXCLUDED.retry_count,
+                        max_retries = EXCLUDED.max_retries,
+                        next_retry_at = EXCLUDED.next_retry_at,
+                        total_bytes = EXCLUDED.total_bytes,
+                        downloaded_bytes = EXCLUDED.downloaded_bytes,
+                        speed_bps = EXCLUDED.speed_bps,
+                        eta_seconds = EXCLUDED.eta_seconds,
+                        transfer_mode = EXCLUDED.transfer_mode,
+                        remote_filecode = EXCLUDED.remote_filecode,
+                        upload_target = EXCLUDED.upload_target,
+                        video_key = NULL",
+                    &[
+                        id,
+                        &item.url,
+                        &item.status,
+                        &item.message,
+                        &item.title,
+                        &item.filemoon_url,
+                        &item.encoding_progress,
+                        &item.thumbnail_url,
+                        &added_at,
+                        &updated_at,
+                        &item.local_path,
+                        &user_id,
+                        &item.short_url,
+                        &item.thumbnail_uploaded,
+                        &item.locked.unwrap_or(false),
+                        &item.format_rung,
+                        &item.format_used,
+                        &item.download_sections,
+                        &item.mirror_urls.clone().unwrap_or_default(),
+                        &item.source_index,
+                        &item.priority.unwrap_or(0),
+                        &item.failure_count.unwrap_or(0),
+                        &item.language,
+                        &item.caption_languages.clone().unwrap_or_default(),
+                        &item.format_override,
+                        &item.tags.clone().unwrap_or_default(),
+                        &item.template_id,
+                        &item.upload_verified_at,
+                        &item.message_code,
+                        &item.message_params.as_ref().map(JsonValue::to_string),
+                        &item.playlist_id,
+                        &item.subtitle_paths.clone().unwrap_or_default(),
+                        &item.upload_provider,
+                        &item.upload_url,
+                        &item.collection,
+                        &item.retry_count.unwrap_or(0),
+                        &item.max_retries,
+                        &item.next_retry_at,
+                        &item.total_bytes,
+                        &item.downloaded_bytes,
+                        &item.speed_bps,
+                        &item.eta_seconds,
+                        &item.transfer_mode,
+                        &item.remote_filecode,
+                        &item.upload_target,
+                    ],
                 )
                 .await?;
+        }
 
-            let count: i64 = count_result[0].get(0);
-            println!(
-                "[DEBUG] Found {} items with status '{}' for user '{}'",
-                count, status, user_id
-            );
+        for setting in settings {
+            restored.settings += tx
+                .execute(
+                    "INSERT INTO settings (key, value, user_id)
+                     VALUES ($1, $2, $3)
+                     ON CONFLICT (key, user_id)
+                     DO UPDATE SET value = EXCLUDED.value",
+                    &[&setting.key, &setting.value, &setting.user_id],
+                )
+                .await?;
         }
 
-        // Delete items for each status type individually
-        let mut total_deleted = 0;
-        for status in status_types {
-            let result = client
+        for setting in global_settings {
+            restored.global_settings += tx
                 .execute(
-                    "DELETE FROM queue WHERE status = $1 AND user_id = $2",
-                    &[&status.as_str(), &user_id],
+                    "INSERT INTO global_settings (key, value, updated_at)
+                     VALUES ($1, $2, $3)
+                     ON CONFLICT (key)
+                     DO UPDATE SET value = EXCLUDED.value, updated_at = EXCLUDED.updated_at",
+                    &[&setting.key, &setting.value, &setting.updated_at],
                 )
                 .await?;
+        }
 
-            println!(
-                "[DEBUG] Deleted {} items with status '{}' for user '{}'",
-                result, status, user_id
-            );
-            total_deleted += result;
+        for upload in uploads {
+            restored.uploads += tx
+                .execute(
+                    "INSERT INTO uploads (id, item_id, target, status, url, error, created_at,
+                                          updated_at)
+                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+                     ON CONFLICT (item_id, target) DO UPDATE SET
+                        status = EXCLUDED.status,
+                        url = EXCLUDED.url,
+                        error = EXCLUDED.error,
+                        created_at = EXCLUDED.created_at,
+                        updated_at = EXCLUDED.updated_at",
+                    &[
+                        &upload.id,
+                        &upload.item_id,
+                        &upload.target,
+                        &upload.status,
+                        &upload.url,
+                        &upload.error,
+                        &upload.created_at,
+                        &upload.updated_at,
+                    ],
+                )
+                .await?;
         }
 
-        println!(
-            "[DEBUG] clear_items_by_status completed. Total deleted: {}",
-            total_deleted
-        );
-        Ok(ClearResult {
-            total_deleted,
-            status_types: status_types.to_vec(),
-            user_id: user_id.to_string(),
+        tx.commit().await?;
+
+        Ok(restored)
+    }
+
+    // Items whose video key hasn't been worked out yet (see indexes.rs)
+    pub async fn get_items_without_video_key(&self) -> Result<Vec<(String, String)>> {
+        let rows = with_retry("get_items_without_video_key", || async move {
+            let client = self.get_client().await?;
+            Ok(client
+                .query(
+                    "SELECT id, url FROM queue WHERE video_key IS NULL ORDER BY added_at ASC",
+                    &[],
+                )
+                .await?)
         })
+        .await?;
+
+        Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+    }
+
+    // Returns false if another item already holds the key
+    pub async fn set_video_key(&self, id: &str, video_key: &str) -> Result<bool> {
+        let client = self.get_client().await?;
+
+        let updated = client
+            .execute(
+                "UPDATE queue SET video_key = $1
+                 WHERE id = $2
+                   AND NOT EXISTS (SELECT 1 FROM queue WHERE video_key = $1)",
+                &[&video_key, &id],
+            )
+            .await?;
+
+        Ok(updated > 0)
+    }
+
+    // The planner's estimate for a query, one line per plan node. Nothing is executed.
+    pub async fn explain(
+        &self,
+        query: &str,
+        params: &[&(dyn tokio_postgres::types::ToSql + Sync)],
+    ) -> Result<Vec<String>> {
+        let client = self.get_client().await?;
+        let rows = client
+            .query(format!("EXPLAIN {}", query).as_str(), params)
+            .await?;
+
+        Ok(rows.iter().map(|row| row.get(0)).collect())
     }
 
     // Method for manual import from a specific path - called via Tauri command
@@ -0,0 +1,109 @@
+// Native desktop notifications for items that finished downloading, finished
+// uploading (with the link to the upload) or failed, for users with the
+// `desktop_notifications` setting on. Like the hook runner, this follows the
+// database's status events and fires on the same writes (see hooks::hook_event), so
+// each download, upload or failure is announced once, whichever path finished it.
+
+use tauri::api::notification::Notification;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::db::{QueueItem, StatusEvent};
+use crate::{filemoon, hooks, AppState};
+
+// Listen for status changes and show the matching notifications until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting desktop notifications...");
+    let mut events = app_handle.state::<AppState>().db.subscribe_status_events();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Some(kind) = hooks::hook_event(&event) {
+                    notify(&app_handle, kind, &event).await;
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!(
+                    "Desktop notifications fell behind and skipped {} status event(s)",
+                    missed
+                );
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    println!("Desktop notifications stopped");
+}
+
+async fn notify(app_handle: &AppHandle, kind: &str, event: &StatusEvent) {
+    let app_state = app_handle.state::<AppState>();
+
+    let item = match app_state.db.get_item_by_id(&event.item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!(
+                "Desktop notifications could not load item {}: {}",
+                event.item_id, e
+            );
+            return;
+        }
+    };
+    let user_id = item
+        .user_id
+        .clone()
+        .unwrap_or_else(|| "local-user".to_string());
+    let settings = app_state
+        .db
+        .get_settings(&user_id)
+        .await
+        .unwrap_or_default();
+    if settings.desktop_notifications.as_deref() != Some("true") {
+        return;
+    }
+
+    let (title, body) = content(kind, &item, event.message.as_deref());
+    let result = Notification::new(&app_handle.config().tauri.bundle.identifier)
+        .title(title)
+        .body(body)
+        .show();
+    if let Err(e) = result {
+        eprintln!(
+            "Failed to show desktop notification for item {}: {}",
+            event.item_id, e
+        );
+    }
+}
+
+// Where the uploaded copy can be watched
+fn upload_link(item: &QueueItem) -> Option<String> {
+    item.filemoon_url
+        .as_deref()
+        .filter(|code| !code.is_empty())
+        .map(filemoon::player_url)
+        .or_else(|| item.upload_url.clone())
+        .or_else(|| item.short_url.clone())
+}
+
+// Title and body of the notification for a hook event
+fn content(kind: &str, item: &QueueItem, message: Option<&str>) -> (String, String) {
+    let name = item.title.clone().unwrap_or_else(|| item.url.clone());
+    match kind {
+        hooks::EVENT_AFTER_DOWNLOAD => ("Download finished".to_string(), name),
+        hooks::EVENT_AFTER_UPLOAD => (
+            "Upload finished".to_string(),
+            match upload_link(item) {
+                Some(link) => format!("{}\n{}", name, link),
+                None => name,
+            },
+        ),
+        _ => (
+            "Item failed".to_string(),
+            match message.filter(|m| !m.is_empty()) {
+                Some(message) => format!("{}\n{}", name, message),
+                None => name,
+            },
+        ),
+    }
+}
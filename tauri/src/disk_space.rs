@@ -0,0 +1,430 @@
+// Pausing downloads while the download volume is nearly full. Before each download
+// the processor checks the free space on the volume of the download directory; below
+// `min_free_space_gb` the item stays queued and nothing new starts. The UI gets one
+// "disk_space_low" event when the pause begins and one "disk_space_ok" event once
+// enough space has been freed (e.g. by delete-after-upload) and downloads go on.
+//
+// Above that floor, an item whose estimated size (from yt-dlp's metadata, see
+// planner::probe) would not fit is marked "blocked_disk_space" instead of letting
+// yt-dlp run out of space half-way. It needs twice the estimate, since yt-dlp keeps
+// the separate video and audio streams until the merged file is written. Blocked
+// items are queued again once the space they need is free. Items are only probed
+// when less than PROBE_BELOW spare space is left.
+//
+// Free space is read with statvfs on Unix and GetDiskFreeSpaceExW on Windows.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use crate::db::{AppSettings, QueueItem};
+use crate::messages::{self, ItemMessage};
+use crate::{long_paths, planner, timestamps, AppState};
+
+pub const DEFAULT_MIN_FREE_GB: f64 = 5.0;
+// How often a paused processor looks at the free space again
+pub const PAUSE_RECHECK: Duration = Duration::from_secs(60);
+
+pub const BLOCKED_STATUS: &str = "blocked_disk_space";
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+// Room needed per byte of the estimated size
+const ESTIMATE_FACTOR: u64 = 2;
+// With more spare space than this nothing is probed
+const PROBE_BELOW: u64 = 100 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiskSpace {
+    pub directory: String,
+    pub free_bytes: u64,
+    // None when the check is turned off
+    pub min_free_bytes: Option<u64>,
+    pub paused: bool,
+    pub paused_since: Option<String>,
+}
+
+// Returned by get_disk_usage, for the settings screen
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiskUsage {
+    pub directory: String,
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    // Size of everything in the download directory
+    pub download_dir_bytes: u64,
+    // None when the check is turned off
+    pub min_free_bytes: Option<u64>,
+    pub paused: bool,
+    pub blocked_items: usize,
+}
+
+pub struct VolumeSpace {
+    pub total_bytes: u64,
+    // Available to this user, which may be less than what is free on the volume
+    pub free_bytes: u64,
+}
+
+#[derive(Default)]
+pub struct SpaceWatch {
+    // When downloads were paused; None while there is enough space
+    low_since: Mutex<Option<DateTime<Utc>>>,
+    // Bytes each blocked item needs; unknown after a restart
+    blocked: Mutex<HashMap<String, u64>>,
+    last_release_check: Mutex<Option<Instant>>,
+}
+
+impl SpaceWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn paused_since(&self) -> Option<DateTime<Utc>> {
+        *self.low_since.lock().unwrap()
+    }
+
+    // Returns true when this starts a new pause
+    fn mark_low(&self) -> bool {
+        let mut low_since = self.low_since.lock().unwrap();
+        if low_since.is_some() {
+            return false;
+        }
+        *low_since = Some(timestamps::now());
+        true
+    }
+
+    // Returns when the pause started, if downloads were paused
+    fn mark_ok(&self) -> Option<DateTime<Utc>> {
+        self.low_since.lock().unwrap().take()
+    }
+
+    fn set_needed(&self, item_id: &str, needed: Option<u64>) {
+        let mut blocked = self.blocked.lock().unwrap();
+        match needed {
+            Some(needed) => blocked.insert(item_id.to_string(), needed),
+            None => blocked.remove(item_id),
+        };
+    }
+
+    fn needed(&self, item_id: &str) -> Option<u64> {
+        self.blocked.lock().unwrap().get(item_id).copied()
+    }
+
+    // Whether PAUSE_RECHECK has passed since blocked items were last looked at
+    fn release_check_due(&self) -> bool {
+        let mut last = self.last_release_check.lock().unwrap();
+        if last.map_or(false, |at| at.elapsed() < PAUSE_RECHECK) {
+            return false;
+        }
+        *last = Some(Instant::now());
+        true
+    }
+}
+
+// Free space to keep on the download volume; "0" turns the check off
+pub fn min_free_bytes(settings: &AppSettings) -> Option<u64> {
+    let gb = settings
+        .min_free_space_gb
+        .as_deref()
+        .and_then(|v| v.trim().parse::<f64>().ok())
+        .filter(|gb| *gb >= 0.0)
+        .unwrap_or(DEFAULT_MIN_FREE_GB);
+    if gb == 0.0 {
+        None
+    } else {
+        Some((gb * BYTES_PER_GB) as u64)
+    }
+}
+
+// The directory itself, or its closest ancestor that exists yet
+fn existing_dir(dir: &Path) -> PathBuf {
+    dir.ancestors()
+        .find(|path| path.exists())
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+#[cfg(unix)]
+fn query_volume(dir: &Path) -> std::io::Result<VolumeSpace> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs only writes to the struct, and `path` is NUL-terminated
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let block = u64::from(stat.f_frsize);
+    Ok(VolumeSpace {
+        total_bytes: u64::from(stat.f_blocks) * block,
+        free_bytes: u64::from(stat.f_bavail) * block,
+    })
+}
+
+#[cfg(windows)]
+fn query_volume(dir: &Path) -> std::io::Result<VolumeSpace> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let path: Vec<u16> = long_paths::extended(dir)
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let (mut free, mut total, mut total_free) = (0u64, 0u64, 0u64);
+    // SAFETY: `path` is NUL-terminated and the out-parameters are valid u64s
+    if unsafe { GetDiskFreeSpaceExW(path.as_ptr(), &mut free, &mut total, &mut total_free) } == 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(VolumeSpace {
+        total_bytes: total,
+        free_bytes: free,
+    })
+}
+
+// Size and free space of the volume holding `dir`, which need not exist yet
+pub fn volume_space(dir: &Path) -> Result<VolumeSpace, String> {
+    query_volume(&existing_dir(dir))
+        .map_err(|e| format!("Failed to check free space of {}: {}", dir.display(), e))
+}
+
+// Bytes available to this user on the volume holding `dir`
+pub fn free_bytes(dir: &Path) -> Result<u64, String> {
+    volume_space(dir).map(|space| space.free_bytes)
+}
+
+// Whether a download into `dir` may start. A failed measurement never blocks
+// downloads; it is only logged.
+pub async fn has_room(app_handle: &AppHandle, settings: &AppSettings, dir: &str) -> bool {
+    let watch = &app_handle.state::<AppState>().disk_space;
+    let min_free = match min_free_bytes(settings) {
+        Some(min_free) => min_free,
+        None => {
+            watch.mark_ok();
+            return true;
+        }
+    };
+    let free = match free_bytes(Path::new(dir)) {
+        Ok(free) => free,
+        Err(e) => {
+            eprintln!("{}", e);
+            return true;
+        }
+    };
+
+    if free < min_free {
+        if watch.mark_low() {
+            let message = format!(
+                "Only {:.1} GB free in the download directory (minimum {:.1} GB). \
+                 New downloads are paused until space is freed.",
+                free as f64 / BYTES_PER_GB,
+                min_free as f64 / BYTES_PER_GB
+            );
+            println!("{}", message);
+            let payload = json!({
+                "directory": dir,
+                "free_bytes": free,
+                "min_free_bytes": min_free,
+                "message": message,
+            });
+            if let Err(e) = app_handle.emit_all("disk_space_low", payload) {
+                eprintln!("Failed to emit disk_space_low event: {}", e);
+            }
+        }
+        return false;
+    }
+
+    if let Some(since) = watch.mark_ok() {
+        println!("Free space recovered; resuming downloads");
+        let payload = json!({
+            "directory": dir,
+            "free_bytes": free,
+            "min_free_bytes": min_free,
+            "message": "Enough free space again. Downloads have resumed.",
+            "paused_since": timestamps::to_iso(&since),
+        });
+        if let Err(e) = app_handle.emit_all("disk_space_ok", payload) {
+            eprintln!("Failed to emit disk_space_ok event: {}", e);
+        }
+    }
+    true
+}
+
+fn gb(bytes: u64) -> f64 {
+    (bytes as f64 / BYTES_PER_GB * 10.0).round() / 10.0
+}
+
+// Whether the estimated size of `item` fits in the download directory `dir` above the
+// min_free_space_gb floor. Returns the message to block the item with when it
+// doesn't; an unknown size or a failed measurement never blocks.
+pub async fn preflight(
+    app_state: &AppState,
+    settings: &AppSettings,
+    item: &QueueItem,
+    dir: &str,
+) -> Option<ItemMessage> {
+    let min_free = min_free_bytes(settings)?;
+    let spare = match free_bytes(Path::new(dir)) {
+        Ok(free) => free.saturating_sub(min_free),
+        Err(e) => {
+            eprintln!("{}", e);
+            return None;
+        }
+    };
+    if spare >= PROBE_BELOW {
+        return None;
+    }
+
+    let estimated = planner::probe(item, settings).await.estimated_bytes?;
+    let needed = estimated.max(0) as u64 * ESTIMATE_FACTOR;
+    let item_id = item.id.clone().unwrap_or_default();
+    if needed <= spare {
+        app_state.disk_space.set_needed(&item_id, None);
+        return None;
+    }
+    app_state.disk_space.set_needed(&item_id, Some(needed));
+    Some(
+        ItemMessage::new(messages::DOWNLOAD_DISK_SPACE_BLOCKED)
+            .with("needed_gb", gb(needed))
+            .with("free_gb", gb(spare))
+            .with("min_free_gb", gb(min_free)),
+    )
+}
+
+// Queue blocked items again once the space they need is free. Called by the queue
+// processor on every pass; looks at most every PAUSE_RECHECK.
+pub async fn release_blocked(app_state: &AppState) {
+    if !app_state.disk_space.release_check_due() {
+        return;
+    }
+    let items = match app_state.db.get_items_in_statuses(&[BLOCKED_STATUS]).await {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("DB Error fetching items blocked on disk space: {}", e);
+            return;
+        }
+    };
+
+    for item in items {
+        let Some(item_id) = item.id.as_deref() else {
+            continue;
+        };
+        let user_id = item.user_id.as_deref().unwrap_or("local-user");
+        let Ok(settings) = app_state.db.get_settings(user_id).await else {
+            continue;
+        };
+        // Unknown after a restart; the item is probed again when it starts
+        let needed = app_state.disk_space.needed(item_id);
+        if let (Some(needed), Some(dir)) = (needed, download_root(&settings)) {
+            let min_free = min_free_bytes(&settings).unwrap_or(0);
+            let fits = free_bytes(Path::new(&dir))
+                .map_or(true, |free| free.saturating_sub(min_free) >= needed);
+            if !fits {
+                continue;
+            }
+        }
+
+        match app_state
+            .db
+            .update_item_status(
+                item_id,
+                "queued",
+                Some(ItemMessage::new(messages::DOWNLOAD_DISK_SPACE_FREED)),
+            )
+            .await
+        {
+            Ok(_) => {
+                println!("Item {}: enough disk space now, queued again", item_id);
+                app_state.disk_space.set_needed(item_id, None);
+            }
+            Err(e) => eprintln!("Failed to requeue item {}: {}", item_id, e),
+        }
+    }
+}
+
+// Where a user's downloads go
+fn download_root(settings: &AppSettings) -> Option<String> {
+    match settings.download_directory.clone() {
+        Some(dir) if !dir.is_empty() => Some(dir),
+        _ => dirs::download_dir().map(|dir| dir.to_string_lossy().to_string()),
+    }
+}
+
+// Total size of the files under `dir`; symlinks are not followed
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.path().symlink_metadata().ok()?;
+            if metadata.is_dir() {
+                Some(dir_size(&entry.path()))
+            } else {
+                metadata.is_file().then(|| metadata.len())
+            }
+        })
+        .sum()
+}
+
+// Free space on the download volume of a user, and whether downloads are paused
+pub async fn report(app_state: &AppState, user_id: &str) -> Result<DiskSpace, String> {
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let directory = download_root(&settings)
+        .ok_or_else(|| "Could not determine download directory".to_string())?;
+    let free_bytes = free_bytes(Path::new(&directory))?;
+    let paused_since = app_state.disk_space.paused_since();
+
+    Ok(DiskSpace {
+        directory,
+        free_bytes,
+        min_free_bytes: min_free_bytes(&settings),
+        paused: paused_since.is_some(),
+        paused_since: paused_since.as_ref().map(timestamps::to_iso),
+    })
+}
+
+// What the settings screen shows about the download volume of a user
+pub async fn usage(app_state: &AppState, user_id: &str) -> Result<DiskUsage, String> {
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let directory = download_root(&settings)
+        .ok_or_else(|| "Could not determine download directory".to_string())?;
+    let space = volume_space(Path::new(&directory))?;
+    let root = long_paths::extended(Path::new(&directory));
+    let download_dir_bytes = tokio::task::spawn_blocking(move || dir_size(&root))
+        .await
+        .unwrap_or(0);
+    let blocked_items = app_state
+        .db
+        .get_items_in_statuses(&[BLOCKED_STATUS])
+        .await
+        .map_err(|e| format!("Failed to count blocked items: {}", e))?
+        .iter()
+        .filter(|item| item.user_id.as_deref() == Some(user_id))
+        .count();
+
+    Ok(DiskUsage {
+        directory,
+        total_bytes: space.total_bytes,
+        free_bytes: space.free_bytes,
+        download_dir_bytes,
+        min_free_bytes: min_free_bytes(&settings),
+        paused: app_state.disk_space.paused_since().is_some(),
+        blocked_items,
+    })
+}
@@ -0,0 +1,153 @@
+// Time window downloads may start in, from the download_window_* settings, in local
+// time. "22:00" to "06:00" is an overnight window; download_window_days ("mon,tue",
+// empty for every day) names the days a window opens on, so with "fri" the window
+// above runs from Friday 22:00 to Saturday 06:00. Outside the window queued items
+// stay queued; a download already running is not interrupted. force_start_item
+// lets a single item start regardless.
+
+use chrono::{DateTime, Datelike, Duration as ChronoDuration, Local, NaiveTime, Weekday};
+use std::sync::Mutex;
+
+use crate::db::AppSettings;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // Days the window opens on; empty for every day
+    pub days: Vec<Weekday>,
+}
+
+// Items force_start_item asked to run now, oldest request first
+#[derive(Default)]
+pub struct ForcedStarts {
+    ids: Mutex<Vec<String>>,
+}
+
+impl ForcedStarts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&self, id: &str) {
+        let mut ids = self.ids.lock().unwrap();
+        if !ids.iter().any(|existing| existing == id) {
+            ids.push(id.to_string());
+        }
+    }
+
+    pub fn remove(&self, id: &str) {
+        self.ids.lock().unwrap().retain(|existing| existing != id);
+    }
+
+    pub fn ids(&self) -> Vec<String> {
+        self.ids.lock().unwrap().clone()
+    }
+}
+
+fn setting(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+fn parse_time(name: &str, value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| format!("Invalid {} '{}'; use HH:MM", name, value))
+}
+
+fn parse_days(name: &str, value: &str) -> Result<Vec<Weekday>, String> {
+    let mut days = Vec::new();
+    for day in value.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let day = day
+            .parse::<Weekday>()
+            .map_err(|_| format!("Invalid day '{}' in {}", day, name))?;
+        if !days.contains(&day) {
+            days.push(day);
+        }
+    }
+    Ok(days)
+}
+
+impl DownloadWindow {
+    // None when no window is set (start and end both empty): downloads run any time
+    pub fn from_settings(settings: &AppSettings) -> Result<Option<Self>, String> {
+        Self::parse(
+            "download_window",
+            &settings.download_window_start,
+            &settings.download_window_end,
+            &settings.download_window_days,
+        )
+    }
+
+    // A window from the <prefix>_start, <prefix>_end and <prefix>_days settings; the
+    // upload window (see auto_upload.rs) is read the same way
+    pub fn parse(
+        prefix: &str,
+        start: &Option<String>,
+        end: &Option<String>,
+        days: &Option<String>,
+    ) -> Result<Option<Self>, String> {
+        let (start_name, end_name) = (format!("{}_start", prefix), format!("{}_end", prefix));
+        let (start, end) = match (setting(start), setting(end)) {
+            (None, None) => return Ok(None),
+            (Some(start), Some(end)) => {
+                (parse_time(&start_name, start)?, parse_time(&end_name, end)?)
+            }
+            _ => {
+                return Err(format!(
+                    "Set both {} and {}, or neither",
+                    start_name, end_name
+                ))
+            }
+        };
+        if start == end {
+            return Err(format!(
+                "The {} must not start and end at the same time",
+                prefix.replace('_', " ")
+            ));
+        }
+        let days = match setting(days) {
+            Some(days) => parse_days(&format!("{}_days", prefix), days)?,
+            None => Vec::new(),
+        };
+        Ok(Some(DownloadWindow { start, end, days }))
+    }
+
+    fn opens_on(&self, day: Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    pub fn is_open(&self, now: DateTime<Local>) -> bool {
+        let time = now.time();
+        if self.start < self.end {
+            return self.start <= time && time < self.end && self.opens_on(now.weekday());
+        }
+        // Overnight: the evening part belongs to today's window, the early hours to
+        // the window that opened yesterday
+        if time >= self.start {
+            self.opens_on(now.weekday())
+        } else if time < self.end {
+            self.opens_on((now - ChronoDuration::days(1)).weekday())
+        } else {
+            false
+        }
+    }
+
+    // "22:00-06:00" or "22:00-06:00 on Fri, Sat"
+    pub fn describe(&self) -> String {
+        let hours = format!(
+            "{}-{}",
+            self.start.format("%H:%M"),
+            self.end.format("%H:%M")
+        );
+        if self.days.is_empty() {
+            return hours;
+        }
+        let days: Vec<String> = self.days.iter().map(|d| d.to_string()).collect();
+        format!("{} on {}", hours, days.join(", "))
+    }
+}
+
+// Check the window settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    DownloadWindow::from_settings(settings).map(|_| ())
+}
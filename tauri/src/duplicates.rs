@@ -0,0 +1,255 @@
+// Duplicate finder for the archive. Items are grouped when they share a normalized
+// video ID (from provenance, or parsed from the URL), the SHA-256 of the downloaded
+// file, or a near-identical title. A group can then be merged into one surviving
+// item, which keeps every source URL of the group as a mirror.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+
+use crate::db::{ProvenanceRecord, QueueItem};
+use crate::urls;
+
+pub const BY_VIDEO_ID: &str = "video_id";
+pub const BY_CHECKSUM: &str = "checksum";
+pub const BY_TITLE: &str = "title";
+pub const CRITERIA: &[&str] = &[BY_VIDEO_ID, BY_CHECKSUM, BY_TITLE];
+
+// Share of title words two titles must have in common to count as the same video
+const TITLE_SIMILARITY: f64 = 0.85;
+// Shorter titles ("Live", "Intro") match far too much to be compared fuzzily
+const MIN_TITLE_WORDS: usize = 3;
+
+// Items that are being worked on can't be merged away
+const ACTIVE_STATUSES: &[&str] = &["downloading", "transcoding", "uploading", "encoding"];
+
+lazy_static! {
+    static ref WORD_REGEX: Regex = Regex::new(r"[\p{L}\p{N}]+").unwrap();
+    // Quality tags that differ between copies of the same video
+    static ref NOISE_WORD_REGEX: Regex =
+        Regex::new(r"^(?:\d{3,4}p|4k|8k|hd|uhd|fhd|hq|60fps|hdr)$").unwrap();
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    // Which criteria linked the items: "video_id", "checksum" and/or "title"
+    pub reasons: Vec<String>,
+    pub items: Vec<QueueItem>,
+    // Item we'd keep: uploaded before anything else, then the oldest
+    pub suggested_keep: Option<String>,
+}
+
+// Which criteria to group by; None or an empty list means all of them
+pub fn parse_criteria(by: Option<Vec<String>>) -> Result<Vec<&'static str>, String> {
+    let requested = by.unwrap_or_default();
+    if requested.is_empty() {
+        return Ok(CRITERIA.to_vec());
+    }
+    requested
+        .iter()
+        .map(|name| {
+            let name = name.trim().to_ascii_lowercase();
+            CRITERIA
+                .iter()
+                .copied()
+                .find(|c| *c == name)
+                .ok_or_else(|| {
+                    format!(
+                        "Unknown duplicate criterion '{}'. Use one of: {}",
+                        name,
+                        CRITERIA.join(", ")
+                    )
+                })
+        })
+        .collect()
+}
+
+fn video_key(item: &QueueItem, provenance: Option<&ProvenanceRecord>) -> Option<String> {
+    let from_provenance = provenance.and_then(|p| match (&p.extractor, &p.video_id) {
+        (Some(extractor), Some(video_id)) => {
+            Some(format!("{}:{}", extractor.to_ascii_lowercase(), video_id))
+        }
+        _ => None,
+    });
+    from_provenance.or_else(|| urls::video_key(&item.url))
+}
+
+fn title_words(title: &str) -> BTreeSet<String> {
+    WORD_REGEX
+        .find_iter(&title.to_lowercase())
+        .map(|m| m.as_str().to_string())
+        .filter(|word| !NOISE_WORD_REGEX.is_match(word))
+        .collect()
+}
+
+fn titles_match(a: &BTreeSet<String>, b: &BTreeSet<String>) -> bool {
+    if a.len().min(b.len()) < MIN_TITLE_WORDS {
+        return false;
+    }
+    let shared = a.intersection(b).count();
+    let total = a.union(b).count();
+    shared as f64 / total as f64 >= TITLE_SIMILARITY
+}
+
+// Union-find over item indices, remembering which criteria joined each set
+struct Groups {
+    parent: Vec<usize>,
+    reasons: HashMap<usize, BTreeSet<&'static str>>,
+}
+
+impl Groups {
+    fn new(len: usize) -> Self {
+        Groups {
+            parent: (0..len).collect(),
+            reasons: HashMap::new(),
+        }
+    }
+
+    fn root(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn join(&mut self, a: usize, b: usize, reason: &'static str) {
+        let (a, b) = (self.root(a), self.root(b));
+        if a != b {
+            self.parent[b] = a;
+            let moved = self.reasons.remove(&b).unwrap_or_default();
+            self.reasons.entry(a).or_default().extend(moved);
+        }
+        self.reasons.entry(a).or_default().insert(reason);
+    }
+}
+
+// Join every run of items that share a key
+fn join_by_key(groups: &mut Groups, keys: &[Option<String>], reason: &'static str) {
+    let mut first_with: HashMap<&str, usize> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(key) = key {
+            match first_with.get(key.as_str()) {
+                Some(&first) => groups.join(first, i, reason),
+                None => {
+                    first_with.insert(key.as_str(), i);
+                }
+            }
+        }
+    }
+}
+
+fn suggested_keep(items: &[QueueItem]) -> Option<String> {
+    items
+        .iter()
+        .min_by_key(|item| (item.filemoon_url.is_none(), item.added_at))
+        .and_then(|item| item.id.clone())
+}
+
+pub fn find(
+    items: Vec<QueueItem>,
+    provenance: &[ProvenanceRecord],
+    criteria: &[&str],
+) -> Vec<DuplicateGroup> {
+    let provenance: HashMap<&str, &ProvenanceRecord> = provenance
+        .iter()
+        .map(|record| (record.item_id.as_str(), record))
+        .collect();
+    let record_of =
+        |item: &QueueItem| item.id.as_deref().and_then(|id| provenance.get(id).copied());
+    let mut groups = Groups::new(items.len());
+
+    if criteria.contains(&BY_VIDEO_ID) {
+        let keys: Vec<Option<String>> = items
+            .iter()
+            .map(|item| video_key(item, record_of(item)))
+            .collect();
+        join_by_key(&mut groups, &keys, BY_VIDEO_ID);
+    }
+    if criteria.contains(&BY_CHECKSUM) {
+        let keys: Vec<Option<String>> = items
+            .iter()
+            .map(|item| record_of(item).and_then(|record| record.sha256.clone()))
+            .collect();
+        join_by_key(&mut groups, &keys, BY_CHECKSUM);
+    }
+    if criteria.contains(&BY_TITLE) {
+        let words: Vec<BTreeSet<String>> = items
+            .iter()
+            .map(|item| item.title.as_deref().map(title_words).unwrap_or_default())
+            .collect();
+        for i in 0..items.len() {
+            for j in (i + 1)..items.len() {
+                if titles_match(&words[i], &words[j]) {
+                    groups.join(i, j, BY_TITLE);
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<QueueItem>> = HashMap::new();
+    for (i, item) in items.into_iter().enumerate() {
+        let root = groups.root(i);
+        members.entry(root).or_default().push(item);
+    }
+
+    let mut found: Vec<DuplicateGroup> = members
+        .into_iter()
+        .filter(|(_, items)| items.len() > 1)
+        .map(|(root, items)| DuplicateGroup {
+            reasons: groups
+                .reasons
+                .get(&root)
+                .map(|reasons| reasons.iter().map(|r| r.to_string()).collect())
+                .unwrap_or_default(),
+            suggested_keep: suggested_keep(&items),
+            items,
+        })
+        .collect();
+    // Biggest groups first, then by the oldest item so the order is stable
+    found.sort_by_key(|group| {
+        (
+            std::cmp::Reverse(group.items.len()),
+            group.items.iter().filter_map(|item| item.added_at).min(),
+        )
+    });
+    found
+}
+
+// Check a merge before anything is deleted
+pub fn check_mergeable(duplicates: &[QueueItem]) -> Result<(), String> {
+    for item in duplicates {
+        let id = item.id.as_deref().unwrap_or("?");
+        if item.locked == Some(true) {
+            return Err(format!("Item {} is locked. Unlock it before merging.", id));
+        }
+        if ACTIVE_STATUSES.contains(&item.status.as_str()) {
+            return Err(format!(
+                "Item {} is {}. Wait for it to finish before merging.",
+                id, item.status
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The survivor's mirrors followed by every duplicate's URL and mirrors, without
+// repeats and without the survivor's own URL
+pub fn merged_mirror_urls(keep: &QueueItem, duplicates: &[QueueItem]) -> Vec<String> {
+    let mut merged: Vec<String> = Vec::new();
+    let candidates = keep
+        .mirror_urls
+        .iter()
+        .flatten()
+        .chain(duplicates.iter().flat_map(|item| {
+            std::iter::once(&item.url).chain(item.mirror_urls.iter().flatten())
+        }));
+    for url in candidates {
+        let url = urls::normalize_url(url);
+        if url != keep.url && !merged.contains(&url) {
+            merged.push(url);
+        }
+    }
+    merged
+}
@@ -0,0 +1,541 @@
+// End-to-end harness, built with `--features e2e`. With PERMAVID_E2E=1 the app runs
+// the whole queue -> download -> upload -> status pipeline against stand-ins:
+// Filemoon is a mock HTTP server on 127.0.0.1 and yt-dlp is a shell script that
+// writes a small file plus the info.json the downloader reads. Everything else (the
+// queue processor, the upload queue, the database) is the real thing, so point
+// NEON_DATABASE_URL at a scratch database first:
+//
+//     PERMAVID_E2E=1 cargo tauri dev --features e2e
+//
+// Items are queued for E2E_USER and removed again afterwards. Each scenario prints
+// PASS or FAIL and the app exits with status 1 if any failed. Unix only: the fake
+// yt-dlp needs /bin/sh.
+
+use serde_json::{json, Value as JsonValue};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use crate::bulk_edit::BulkFilter;
+use crate::db::{AppSettings, QueueItem};
+use crate::{http, status_refresh, tools, AppState};
+
+pub const E2E_ENV: &str = "PERMAVID_E2E";
+pub const E2E_USER: &str = "e2e-harness";
+
+const MOCK_API_KEY: &str = "e2e-key";
+// The processor idles up to 15s between polls, so leave plenty of room
+const SCENARIO_TIMEOUT: Duration = Duration::from_secs(180);
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Statuses an item stays in until someone acts on it
+const SETTLED_STATUSES: &[&str] = &[
+    "uploaded",
+    "failed",
+    "unsupported",
+    "cancelled",
+    "pending_review",
+    "on_hold",
+];
+
+// Every status an item of the harness user can be left in, for cleanup
+const ALL_STATUSES: &[&str] = &[
+    "queued",
+    "downloading",
+    "downloaded",
+    "uploading",
+    "uploaded",
+    "transferring",
+    "encoding",
+    "encoded",
+    "failed",
+    "unsupported",
+    "cancelled",
+    "pending_review",
+    "on_hold",
+];
+
+struct Scenario {
+    name: &'static str,
+    // Path on example.com; the fake yt-dlp fails URLs containing "unsupported"
+    path: &'static str,
+    expect_status: &'static str,
+    expect_upload: bool,
+}
+
+const SCENARIOS: &[Scenario] = &[
+    Scenario {
+        name: "download, upload and status",
+        path: "videos/e2e-happy-path",
+        expect_status: "uploaded",
+        expect_upload: true,
+    },
+    Scenario {
+        name: "unsupported URL",
+        path: "videos/e2e-unsupported",
+        expect_status: "unsupported",
+        expect_upload: false,
+    },
+];
+
+// Understands the command lines the app builds well enough: --dump-single-json and
+// -J print metadata, a download writes a 64 KiB file and its info.json next to
+// --output, printing progress lines on the way
+const FAKE_YTDLP: &str = r#"#!/bin/sh
+# Fake yt-dlp for the PermaVid end-to-end harness (see e2e.rs)
+case "$1" in
+  --version) echo "2099.01.01-e2e"; exit 0 ;;
+  -U) echo "yt-dlp is up to date (2099.01.01-e2e)"; exit 0 ;;
+esac
+url="$1"
+output=""
+dump=""
+while [ $# -gt 0 ]; do
+  case "$1" in
+    --output) output="$2" ;;
+    --dump-single-json|-J) dump="1" ;;
+  esac
+  shift
+done
+case "$url" in
+  *unsupported*) echo "ERROR: Unsupported URL: $url" >&2; exit 1 ;;
+esac
+id="e2e$(printf '%s' "$url" | cksum | cut -d ' ' -f 1)"
+if [ -n "$dump" ]; then
+  echo "{\"id\": \"$id\", \"title\": \"E2E video $id\", \"webpage_url\": \"$url\", \"ext\": \"mp4\", \"filesize_approx\": 65536, \"entries\": []}"
+  exit 0
+fi
+dir=$(dirname "$output")
+mkdir -p "$dir"
+dd if=/dev/zero of="$dir/$id.mp4" bs=1024 count=64 2>/dev/null
+for pct in 25.0 50.0 75.0 100.0; do
+  echo "[download]  $pct% of 64.00KiB at 1.00MiB/s ETA 00:00"
+done
+cat > "$dir/$id.info.json" <<EOF
+{"id": "$id", "title": "E2E video $id", "webpage_url": "$url", "ext": "mp4", "_filename": "$id.mp4", "extractor_key": "Generic"}
+EOF
+"#;
+
+pub fn enabled() -> bool {
+    std::env::var(E2E_ENV).map_or(false, |v| v == "1")
+}
+
+fn write_fake_ytdlp(dir: &Path) -> Result<PathBuf, String> {
+    let path = dir.join("yt-dlp");
+    std::fs::write(&path, FAKE_YTDLP).map_err(|e| format!("Failed to write fake yt-dlp: {}", e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| format!("Failed to make fake yt-dlp executable: {}", e))?;
+    }
+    Ok(path)
+}
+
+// What the mock has been sent, for the scenarios to check
+#[derive(Default)]
+struct MockLog {
+    // Uploaded file name by the file code handed out for it
+    uploads: HashMap<String, String>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct MockFilemoon {
+    base: String,
+    log: Arc<Mutex<MockLog>>,
+}
+
+impl MockFilemoon {
+    async fn start() -> Result<Self, String> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| format!("Mock Filemoon failed to bind: {}", e))?;
+        let base = format!(
+            "http://{}",
+            listener.local_addr().map_err(|e| e.to_string())?
+        );
+        let log = Arc::new(Mutex::new(MockLog::default()));
+
+        let server_base = base.clone();
+        let server_log = log.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let base = server_base.clone();
+                let log = server_log.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = serve(stream, &base, &log).await {
+                        eprintln!("[e2e] Mock Filemoon connection error: {}", e);
+                    }
+                });
+            }
+        });
+
+        println!("[e2e] Mock Filemoon listening on {}", base);
+        Ok(MockFilemoon { base, log })
+    }
+
+    // Harness user settings: provider endpoints on the mock, auto-upload on
+    fn settings(&self, download_directory: &Path) -> AppSettings {
+        AppSettings {
+            filemoon_api_key: Some(MOCK_API_KEY.to_string()),
+            filemoon_api_base: Some(format!("{}/api", self.base)),
+            filemoon_site_base: Some(self.base.clone()),
+            filemoon_upload_server_url: Some(format!("{}/api/upload/server", self.base)),
+            download_directory: Some(download_directory.to_string_lossy().to_string()),
+            auto_upload: Some("true".to_string()),
+            review_before_upload: Some("false".to_string()),
+            ..AppSettings::default()
+        }
+    }
+
+    fn uploaded(&self, filecode: &str) -> bool {
+        self.log.lock().unwrap().uploads.contains_key(filecode)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// One HTTP/1.1 request. A chunked body keeps its framing; the mock only searches it.
+async fn read_request(stream: &mut TcpStream) -> std::io::Result<Option<Request>> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 16 * 1024];
+    let header_end = loop {
+        if let Some(pos) = find(&buf, b"\r\n\r\n") {
+            break pos + 4;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let target = request_line.next().unwrap_or("/").to_string();
+    let headers: HashMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let mut body = buf[header_end..].to_vec();
+    let content_length = headers
+        .get("content-length")
+        .and_then(|v| v.parse::<usize>().ok());
+    let chunked = headers
+        .get("transfer-encoding")
+        .map_or(false, |v| v.contains("chunked"));
+    loop {
+        let complete = match content_length {
+            Some(length) => body.len() >= length,
+            None => !chunked || body.ends_with(b"0\r\n\r\n"),
+        };
+        if complete {
+            break;
+        }
+        let n = stream.read(&mut chunk).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..n]);
+    }
+
+    let url = reqwest::Url::parse(&format!("http://mock{}", target))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    Ok(Some(Request {
+        method,
+        path: url.path().to_string(),
+        query: url.query_pairs().into_owned().collect(),
+        body,
+    }))
+}
+
+async fn serve(mut stream: TcpStream, base: &str, log: &Mutex<MockLog>) -> std::io::Result<()> {
+    let request = match read_request(&mut stream).await? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+    let (status, body) = respond(&request, base, log);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+// Filemoon's answers. file/info comes back in the loose shape Filemoon sometimes
+// uses (numbers as strings, a single entry as a bare object), so the lenient
+// parsing is exercised too.
+fn respond(request: &Request, base: &str, log: &Mutex<MockLog>) -> (&'static str, String) {
+    const OK: &str = "200 OK";
+    // The upload form carries the key in its body rather than the query
+    if request.path != "/upload"
+        && request.query.get("key").map(String::as_str) != Some(MOCK_API_KEY)
+    {
+        return (OK, json!({"status": 403, "msg": "Invalid key"}).to_string());
+    }
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/upload/server") => (
+            OK,
+            json!({"status": 200, "msg": "OK", "result": format!("{}/upload", base)}).to_string(),
+        ),
+        ("POST", "/upload") => {
+            let body = String::from_utf8_lossy(&request.body);
+            let filename = body
+                .split("filename=\"")
+                .nth(1)
+                .and_then(|rest| rest.split('"').next())
+                .unwrap_or("upload.bin")
+                .to_string();
+            let mut log = log.lock().unwrap();
+            let filecode = format!("e2e{:04}", log.uploads.len() + 1);
+            println!(
+                "[e2e] Mock Filemoon received '{}' as {}",
+                filename, filecode
+            );
+            log.uploads.insert(filecode.clone(), filename.clone());
+            (
+                OK,
+                json!({
+                    "status": 200,
+                    "msg": "OK",
+                    "files": [{"filecode": filecode, "filename": filename, "status": "OK"}]
+                })
+                .to_string(),
+            )
+        }
+        ("GET", "/api/file/info") => {
+            let log = log.lock().unwrap();
+            let mut entries: Vec<JsonValue> = request
+                .query
+                .get("file_code")
+                .map(String::as_str)
+                .unwrap_or_default()
+                .split(',')
+                .filter_map(|code| {
+                    log.uploads.get(code).map(|name| {
+                        json!({
+                            "status": "200",
+                            "file_code": code,
+                            "name": name,
+                            "canplay": "1",
+                            "length": "12"
+                        })
+                    })
+                })
+                .collect();
+            let result = if entries.len() == 1 {
+                entries.remove(0)
+            } else {
+                JsonValue::Array(entries)
+            };
+            (
+                OK,
+                json!({"status": "200", "msg": "OK", "result": result}).to_string(),
+            )
+        }
+        ("GET", "/api/encoding/status") => {
+            let code = request.query.get("file_code").cloned().unwrap_or_default();
+            (
+                OK,
+                json!({
+                    "status": 200,
+                    "msg": "OK",
+                    "result": {"file_code": code, "status": "FINISHED", "progress": 100}
+                })
+                .to_string(),
+            )
+        }
+        _ => (
+            "404 Not Found",
+            json!({"status": 404, "msg": "Not found"}).to_string(),
+        ),
+    }
+}
+
+async fn wait_until_settled(app_state: &AppState, id: &str) -> Result<QueueItem, String> {
+    let started = Instant::now();
+    loop {
+        let item = app_state
+            .db
+            .get_item_by_id(id)
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("item {} disappeared", id))?;
+        if SETTLED_STATUSES.contains(&item.status.as_str()) {
+            return Ok(item);
+        }
+        if started.elapsed() > SCENARIO_TIMEOUT {
+            return Err(format!(
+                "still '{}' after {:?}",
+                item.status, SCENARIO_TIMEOUT
+            ));
+        }
+        sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn run_scenario(
+    app_state: &AppState,
+    mock: &MockFilemoon,
+    scenario: &Scenario,
+    run_id: &str,
+) -> Result<(), String> {
+    let url = format!("https://example.com/{}?run={}", scenario.path, run_id);
+    let id = app_state
+        .db
+        .add_queue_item(&QueueItem::queued(&url, E2E_USER))
+        .await
+        .map_err(|e| format!("failed to queue {}: {}", url, e))?;
+
+    let item = wait_until_settled(app_state, &id).await?;
+    if item.status != scenario.expect_status {
+        return Err(format!(
+            "ended as '{}' ({}), expected '{}'",
+            item.status,
+            item.message.unwrap_or_default(),
+            scenario.expect_status
+        ));
+    }
+    if !scenario.expect_upload {
+        return Ok(());
+    }
+
+    let filecode = item
+        .filemoon_url
+        .ok_or_else(|| "no file code recorded".to_string())?;
+    if !mock.uploaded(&filecode) {
+        return Err(format!("file code {} was never handed out", filecode));
+    }
+
+    // Status: file/info has to report the upload as playable
+    let info = status_refresh::fetch_file_info(
+        app_state,
+        &http::client(),
+        MOCK_API_KEY,
+        &[filecode.clone()],
+    )
+    .await?;
+    match info
+        .result
+        .unwrap_or_default()
+        .iter()
+        .find(|entry| entry.file_code == filecode)
+    {
+        Some(entry) if entry.status == Some(200) && entry.canplay == Some(1) => Ok(()),
+        Some(entry) => Err(format!(
+            "file/info reported status {:?}, canplay {:?}",
+            entry.status, entry.canplay
+        )),
+        None => Err(format!("file/info has no entry for {}", filecode)),
+    }
+}
+
+async fn remove_items(app_state: &AppState) {
+    let filter = BulkFilter {
+        statuses: Some(ALL_STATUSES.iter().map(|s| s.to_string()).collect()),
+        ..BulkFilter::default()
+    };
+    let removed = match app_state.db.find_items(E2E_USER, &filter).await {
+        Ok(items) => {
+            let ids: Vec<String> = items.into_iter().filter_map(|item| item.id).collect();
+            app_state.db.delete_items(E2E_USER, &ids).await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = removed {
+        eprintln!("[e2e] Failed to remove harness items: {}", e);
+    }
+}
+
+// Returns the number of failed scenarios
+async fn run_scenarios(app_handle: &AppHandle) -> Result<usize, String> {
+    if !cfg!(unix) {
+        return Err("the fake yt-dlp needs a Unix shell".to_string());
+    }
+    let app_state = app_handle.state::<AppState>();
+
+    let root = std::env::temp_dir().join(format!("permavid-e2e-{}", Uuid::new_v4()));
+    let downloads = root.join("downloads");
+    std::fs::create_dir_all(&downloads)
+        .map_err(|e| format!("Failed to create {}: {}", downloads.display(), e))?;
+    let ytdlp = write_fake_ytdlp(&root)?;
+    std::env::set_var(tools::YTDLP_ENV, &ytdlp);
+
+    let mock = MockFilemoon::start().await?;
+    app_state
+        .db
+        .save_settings(&mock.settings(&downloads), E2E_USER)
+        .await
+        .map_err(|e| format!("Failed to save harness settings: {}", e))?;
+    let settings = app_state
+        .db
+        .get_settings(E2E_USER)
+        .await
+        .map_err(|e| format!("Failed to load harness settings: {}", e))?;
+    http::configure(&settings)?;
+    // Leftovers of an earlier run that didn't finish
+    remove_items(&app_state).await;
+
+    let run_id = Uuid::new_v4().to_string();
+    let mut failures = 0;
+    for scenario in SCENARIOS {
+        println!("[e2e] Running '{}'", scenario.name);
+        match run_scenario(&app_state, &mock, scenario, &run_id).await {
+            Ok(()) => println!("[e2e] PASS {}", scenario.name),
+            Err(e) => {
+                failures += 1;
+                println!("[e2e] FAIL {}: {}", scenario.name, e);
+            }
+        }
+    }
+
+    remove_items(&app_state).await;
+    if let Err(e) = std::fs::remove_dir_all(&root) {
+        eprintln!("[e2e] Failed to remove {}: {}", root.display(), e);
+    }
+    Ok(failures)
+}
+
+pub async fn run(app_handle: AppHandle) {
+    let failures = match run_scenarios(&app_handle).await {
+        Ok(failures) => failures,
+        Err(e) => {
+            eprintln!("[e2e] Harness setup failed: {}", e);
+            app_handle.exit(1);
+            return;
+        }
+    };
+    if failures == 0 {
+        println!("[e2e] All {} scenario(s) passed", SCENARIOS.len());
+    } else {
+        println!(
+            "[e2e] {} of {} scenario(s) failed",
+            failures,
+            SCENARIOS.len()
+        );
+    }
+    app_handle.exit(if failures == 0 { 0 } else { 1 });
+}
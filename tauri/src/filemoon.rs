@@ -0,0 +1,743 @@
+// Filemoon URL formats and link helpers, kept in one place so the frontend and
+// exports never need to build provider URLs themselves.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::time::Duration;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::providers::{StepError, STEP_SERVER, STEP_STATUS, STEP_UPLOAD};
+use crate::{http, lenient};
+
+// Defaults; each can be overridden in settings (see http.rs)
+pub const FILEMOON_SITE_BASE: &str = "https://filemoon.sx";
+pub const FILEMOON_API_BASE: &str = "https://filemoonapi.com/api";
+
+pub const UPLOAD_SERVER_ENDPOINT: &str = "https://api.filemoon.sx/api/upload/server";
+
+// Files asked for when searching the account for an earlier upload
+const FILE_SEARCH_PER_PAGE: &str = "50";
+// Largest image accepted as a custom thumbnail
+const MAX_THUMBNAIL_BYTES: usize = 5 * 1024 * 1024;
+
+// Uploads at least this large probe their upload server first
+pub const PROBE_MIN_BYTES: u64 = 100 * 1024 * 1024;
+// Upload servers tried (the first plus replacements) before giving up
+pub const MAX_SERVER_PROBES: u32 = 3;
+// Size of the chunks an upload is read from disk in
+pub const UPLOAD_CHUNK_BYTES: usize = 256 * 1024;
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+// Tries of each upload step (GetServer, the upload itself) before a transient
+// failure fails the item
+pub const UPLOAD_MAX_ATTEMPTS: u32 = 4;
+const RETRY_BASE_DELAY_SECS: u64 = 5;
+const RETRY_MAX_DELAY_SECS: u64 = 120;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmbedInfo {
+    pub item_id: String,
+    pub provider: String,
+    pub filecode: String,
+    pub embed_url: String,
+    pub embed_html: String,
+    pub player_url: String,
+    pub download_url: Option<String>,
+    pub short_url: Option<String>,
+}
+
+pub fn site_base() -> String {
+    http::endpoints()
+        .filemoon_site_base
+        .unwrap_or_else(|| FILEMOON_SITE_BASE.to_string())
+}
+
+pub fn api_base() -> String {
+    http::endpoints()
+        .filemoon_api_base
+        .unwrap_or_else(|| FILEMOON_API_BASE.to_string())
+}
+
+pub fn upload_server_endpoint() -> String {
+    http::endpoints()
+        .filemoon_upload_server_url
+        .unwrap_or_else(|| UPLOAD_SERVER_ENDPOINT.to_string())
+}
+
+pub fn embed_url(filecode: &str) -> String {
+    format!("{}/e/{}", site_base(), filecode)
+}
+
+pub fn player_url(filecode: &str) -> String {
+    format!("{}/d/{}", site_base(), filecode)
+}
+
+pub fn embed_html(filecode: &str) -> String {
+    format!(
+        r#"<iframe src="{}" width="640" height="360" frameborder="0" marginwidth="0" marginheight="0" scrolling="no" allowfullscreen></iframe>"#,
+        embed_url(filecode)
+    )
+}
+
+// Ask the API for a direct download link. Only some accounts have access to this,
+// so any failure just means no direct link is available.
+pub async fn fetch_direct_link(filecode: &str, api_key: &str) -> Option<String> {
+    let response = http::client()
+        .get(format!("{}/file/direct_link", api_base()))
+        .query(&[("key", api_key), ("file_code", filecode)])
+        .send()
+        .await
+        .ok()?;
+    let body: JsonValue = response.json().await.ok()?;
+
+    if body.get("status").and_then(|s| s.as_u64()) != Some(200) {
+        return None;
+    }
+
+    let result = body.get("result")?;
+    result
+        .get("url")
+        .and_then(|u| u.as_str())
+        .or_else(|| {
+            result
+                .get("versions")
+                .and_then(|v| v.as_array())
+                .and_then(|versions| versions.first())
+                .and_then(|v| v.get("url"))
+                .and_then(|u| u.as_str())
+        })
+        .map(String::from)
+}
+
+pub async fn embed_info(item_id: &str, filecode: &str, api_key: Option<&str>) -> EmbedInfo {
+    let download_url = match api_key.filter(|k| !k.is_empty()) {
+        Some(key) => fetch_direct_link(filecode, key).await,
+        None => None,
+    };
+
+    EmbedInfo {
+        item_id: item_id.to_string(),
+        provider: "filemoon".to_string(),
+        filecode: filecode.to_string(),
+        embed_url: embed_url(filecode),
+        embed_html: embed_html(filecode),
+        player_url: player_url(filecode),
+        download_url,
+        short_url: None,
+    }
+}
+
+// Load thumbnail image bytes from a remote URL (as stored from info.json) or a local file
+pub async fn load_thumbnail(source: &str) -> Result<Vec<u8>, String> {
+    let bytes = if source.starts_with("http://") || source.starts_with("https://") {
+        let response = reqwest::get(source)
+            .await
+            .map_err(|e| format!("Failed to download thumbnail: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Failed to download thumbnail (HTTP {})",
+                response.status()
+            ));
+        }
+        response
+            .bytes()
+            .await
+            .map_err(|e| format!("Failed to read thumbnail: {}", e))?
+            .to_vec()
+    } else {
+        tokio::fs::read(source)
+            .await
+            .map_err(|e| format!("Failed to read thumbnail file {}: {}", source, e))?
+    };
+
+    if bytes.is_empty() || bytes.len() > MAX_THUMBNAIL_BYTES {
+        return Err(format!(
+            "Thumbnail size {} bytes is outside the accepted range",
+            bytes.len()
+        ));
+    }
+    Ok(bytes)
+}
+
+// Set a custom player thumbnail for an uploaded file
+pub async fn upload_thumbnail(filecode: &str, api_key: &str, image: Vec<u8>) -> Result<(), String> {
+    let form = reqwest::multipart::Form::new()
+        .text("key", api_key.to_string())
+        .text("file_code", filecode.to_string())
+        .part(
+            "thumbnail",
+            reqwest::multipart::Part::bytes(image).file_name(format!("{}.jpg", filecode)),
+        );
+
+    let response = http::client()
+        .post(format!("{}/file/thumbnail", api_base()))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| format!("Thumbnail upload request failed: {}", e))?;
+
+    let status = response.status();
+    let raw_text = response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read thumbnail upload response: {}", e))?;
+    let body: JsonValue = serde_json::from_str(&raw_text).map_err(|e| {
+        format!(
+            "Failed to parse thumbnail upload response: {}. Raw Body: {}",
+            e, raw_text
+        )
+    })?;
+
+    if !status.is_success() || body.get("status").and_then(|s| s.as_u64()) != Some(200) {
+        return Err(format!(
+            "Thumbnail upload API Error (HTTP {}): {}",
+            status,
+            body.get("msg")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error")
+        ));
+    }
+    Ok(())
+}
+
+// Whether a JSON response reports success. Filemoon sometimes leaves its own status
+// out of an otherwise fine answer, so a 2xx with no readable API status counts too.
+pub fn is_ok_response(http_status: u16, api_status: Option<u16>) -> bool {
+    (200..300).contains(&http_status) && api_status.map_or(true, |status| status == 200)
+}
+
+// Filemoon answers 422 or 503 (in the HTTP status or the API's own status field,
+// sometimes with an HTML "maintenance" page) while it is down for maintenance
+pub fn is_maintenance_response(http_status: u16, api_status: Option<u16>, body: &str) -> bool {
+    const MAINTENANCE_STATUSES: &[u16] = &[422, 503];
+    MAINTENANCE_STATUSES.contains(&http_status)
+        || api_status.map_or(false, |status| MAINTENANCE_STATUSES.contains(&status))
+        || body.to_ascii_lowercase().contains("maintenance")
+}
+
+// Worth retrying: timeouts, rate limiting and server errors. 503 is left out because
+// it means maintenance, which holds the item instead (see is_maintenance_response).
+pub fn is_transient_status(http_status: u16) -> bool {
+    matches!(http_status, 408 | 429 | 500 | 502 | 504)
+}
+
+// Connection failures and requests cut off mid-transfer, as opposed to a request
+// that could never be built
+pub fn is_transient_error(e: &reqwest::Error) -> bool {
+    e.is_timeout() || e.is_connect() || e.is_request() || e.is_body()
+}
+
+// Exponential backoff from RETRY_BASE_DELAY_SECS, capped at RETRY_MAX_DELAY_SECS,
+// with up to 50% random jitter
+pub fn retry_delay(attempt: u32) -> Duration {
+    let base = RETRY_BASE_DELAY_SECS
+        .saturating_mul(1u64 << (attempt.max(1) - 1).min(16))
+        .min(RETRY_MAX_DELAY_SECS)
+        * 1000;
+    let jitter = (Uuid::new_v4().as_u128() % (base as u128 / 2 + 1)) as u64;
+    Duration::from_millis(base + jitter)
+}
+
+fn name_stem(name: &str) -> &str {
+    name.rsplit_once('.').map_or(name, |(stem, _)| stem).trim()
+}
+
+// How far apart Filemoon's length and the local file's duration may be, in seconds;
+// Filemoon rounds the length it reports
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+
+fn number(file: &JsonValue, keys: &[&str]) -> Option<f64> {
+    keys.iter()
+        .find_map(|key| file.get(*key).and_then(lenient::text))
+        .and_then(|value| value.trim().parse::<f64>().ok())
+}
+
+// File code of a file/list entry if it is the same video: the same title stem, and
+// the same size or, where Filemoon reports no size, the same length. An entry that
+// can't be compared on either is never taken for a match.
+fn matching_file_code(
+    file: &JsonValue,
+    stem: &str,
+    bytes: u64,
+    duration_secs: Option<f64>,
+) -> Option<String> {
+    let title = file
+        .get("title")
+        .or_else(|| file.get("name"))
+        .and_then(lenient::text)?;
+    if !name_stem(&title).eq_ignore_ascii_case(stem) {
+        return None;
+    }
+    let same = match (number(file, &["size", "file_size"]), duration_secs) {
+        (Some(size), _) => size as u64 == bytes,
+        (None, Some(duration)) => number(file, &["length", "duration"]).map_or(false, |length| {
+            (length - duration).abs() <= DURATION_TOLERANCE_SECS
+        }),
+        (None, None) => false,
+    };
+    if !same {
+        return None;
+    }
+    file.get("file_code")
+        .or_else(|| file.get("filecode"))
+        .and_then(lenient::text)
+        .filter(|code| !code.is_empty())
+}
+
+// File code of a file already on the account under the same name as `file_name`
+// (ignoring case and extension) and of `bytes` bytes, or of the same duration where
+// Filemoon reports no size. Lets a shared account link to an earlier upload of the
+// same video instead of storing a second copy.
+pub async fn find_existing_file(
+    client: &reqwest::Client,
+    api_key: &str,
+    file_name: &str,
+    bytes: u64,
+    duration_secs: Option<f64>,
+) -> Result<Option<String>, String> {
+    let stem = name_stem(file_name);
+    let response = client
+        .get(format!("{}/file/list", api_base()))
+        .query(&[
+            ("key", api_key),
+            ("title", stem),
+            ("per_page", FILE_SEARCH_PER_PAGE),
+        ])
+        .send()
+        .await
+        .map_err(|e| format!("Filemoon file search failed: {}", e))?;
+    let status = response.status().as_u16();
+    let body: JsonValue = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse Filemoon file search response: {}", e))?;
+    let api_status = body
+        .get("status")
+        .and_then(lenient::text)
+        .and_then(|s| s.parse().ok());
+    if !is_ok_response(status, api_status) {
+        return Err(format!(
+            "Filemoon file search failed (HTTP {}): {}",
+            status,
+            body.get("msg")
+                .and_then(lenient::text)
+                .unwrap_or_else(|| "unknown error".to_string())
+        ));
+    }
+
+    let files = body
+        .pointer("/result/files")
+        .and_then(|files| files.as_array())
+        .cloned()
+        .unwrap_or_default();
+    Ok(files
+        .iter()
+        .find_map(|file| matching_file_code(file, stem, bytes, duration_secs)))
+}
+
+// Ask the API for an upload server
+pub async fn request_upload_server(
+    client: &reqwest::Client,
+    api_key: &str,
+) -> Result<String, String> {
+    let response = client
+        .get(upload_server_endpoint())
+        .query(&[("key", api_key)])
+        .send()
+        .await
+        .map_err(|e| format!("Filemoon GetServer request failed: {}", e))?;
+
+    let status = response.status();
+    let body: JsonValue = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse Filemoon GetServer response: {}", e))?;
+    match body.get("result").and_then(|r| r.as_str()) {
+        Some(url) if status.is_success() && !url.is_empty() => Ok(url.to_string()),
+        _ => Err(format!(
+            "Filemoon GetServer API Error (HTTP {}): {}",
+            status,
+            body.get("msg")
+                .and_then(|m| m.as_str())
+                .unwrap_or("unknown error")
+        )),
+    }
+}
+
+// Cheap liveness check of an upload server before committing a large file to it.
+// Any HTTP answer below 500 counts as alive (servers may reject HEAD itself);
+// connection errors, timeouts and 5xx mean the node is unhealthy.
+pub async fn probe_upload_server(client: &reqwest::Client, url: &str) -> Result<(), String> {
+    let response = client
+        .head(url)
+        .timeout(PROBE_TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| {
+            if e.is_timeout() {
+                format!("no answer within {}s", PROBE_TIMEOUT.as_secs())
+            } else {
+                format!("unreachable ({})", e)
+            }
+        })?;
+
+    if response.status().is_server_error() {
+        return Err(format!("answered HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+// Upload a file in one go: ask for a server, then stream the file to it. Used for
+// mirror copies (see mirrors.rs); the main upload in perform_upload adds server
+// probing, maintenance holds and per-step status messages on top of the same calls.
+// Returns the new file code.
+pub async fn upload_file(
+    client: &reqwest::Client,
+    api_key: &str,
+    path: &Path,
+    file_name: &str,
+    bytes: u64,
+) -> Result<String, StepError> {
+    let server = request_upload_server(client, api_key)
+        .await
+        .map_err(|e| StepError {
+            transient: true,
+            ..StepError::new(STEP_SERVER, e)
+        })?;
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| StepError::new(STEP_UPLOAD, format!("Failed to open file: {}", e)))?;
+    let file_stream = ReaderStream::with_capacity(file, UPLOAD_CHUNK_BYTES);
+    let form = reqwest::multipart::Form::new()
+        .text("key", api_key.to_string())
+        .part(
+            "file",
+            reqwest::multipart::Part::stream_with_length(
+                reqwest::Body::wrap_stream(file_stream),
+                bytes,
+            )
+            .file_name(file_name.to_string()),
+        );
+
+    let response = client
+        .post(&server)
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| StepError {
+            transient: is_transient_error(&e),
+            ..StepError::new(STEP_UPLOAD, format!("Request failed: {}", e))
+        })?;
+    let status = response.status().as_u16();
+    let text = response.text().await.unwrap_or_default();
+    let body: JsonValue = serde_json::from_str(&text).unwrap_or(JsonValue::Null);
+    let api_status = body
+        .get("status")
+        .and_then(lenient::text)
+        .and_then(|s| s.parse().ok());
+    let file_code = body
+        .pointer("/files/0/filecode")
+        .and_then(lenient::text)
+        .filter(|code| !code.is_empty());
+    match file_code {
+        Some(file_code) if is_ok_response(status, api_status) => Ok(file_code),
+        _ => Err(StepError {
+            http_status: Some(status),
+            message: format!(
+                "HTTP {}: {}",
+                status,
+                body.get("msg")
+                    .and_then(lenient::text)
+                    .unwrap_or_else(|| "no file code in the response".to_string())
+            ),
+            body: Some(text),
+            transient: is_transient_status(status),
+            ..StepError::new(STEP_UPLOAD, String::new())
+        }),
+    }
+}
+
+// Where a Filemoon remote upload stands
+#[derive(Debug, Clone, PartialEq)]
+pub enum RemoteState {
+    // Waiting in Filemoon's queue
+    Pending,
+    // Being fetched from the source
+    Working,
+    Completed,
+    // Filemoon gave up on the URL; carries its reason
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteStatus {
+    pub state: RemoteState,
+    pub bytes_total: Option<i64>,
+    pub bytes_downloaded: Option<i64>,
+}
+
+// GET one of the remote upload endpoints and return the body of a successful answer
+async fn remote_request(
+    client: &reqwest::Client,
+    step: &'static str,
+    endpoint: &str,
+    query: &[(&str, &str)],
+) -> Result<JsonValue, StepError> {
+    let response = client
+        .get(format!("{}/{}", api_base(), endpoint))
+        .query(query)
+        .send()
+        .await
+        .map_err(|e| StepError {
+            transient: is_transient_error(&e),
+            ..StepError::new(step, format!("Request failed: {}", e))
+        })?;
+    let status = response.status().as_u16();
+    let text = response.text().await.unwrap_or_default();
+    let body: JsonValue = serde_json::from_str(&text).unwrap_or(JsonValue::Null);
+    let api_status = body
+        .get("status")
+        .and_then(lenient::text)
+        .and_then(|s| s.parse().ok());
+    if is_ok_response(status, api_status) && !body.is_null() {
+        return Ok(body);
+    }
+    Err(StepError {
+        http_status: Some(api_status.filter(|s| *s >= 400).unwrap_or(status)),
+        message: format!(
+            "HTTP {}: {}",
+            status,
+            body.get("msg")
+                .and_then(lenient::text)
+                .unwrap_or_else(|| "unreadable response".to_string())
+        ),
+        body: Some(text),
+        transient: is_transient_status(status) || api_status.map_or(false, is_transient_status),
+        ..StepError::new(step, String::new())
+    })
+}
+
+// Have Filemoon fetch `url` itself instead of uploading a local file. Returns the
+// file code the video will have once the transfer is done.
+pub async fn add_remote_upload(
+    client: &reqwest::Client,
+    api_key: &str,
+    url: &str,
+) -> Result<String, StepError> {
+    let body = remote_request(
+        client,
+        STEP_UPLOAD,
+        "remote/add",
+        &[("key", api_key), ("url", url)],
+    )
+    .await?;
+    ["/result/filecode", "/result/file_code"]
+        .iter()
+        .find_map(|pointer| body.pointer(pointer).and_then(lenient::text))
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| StepError {
+            body: Some(body.to_string()),
+            ..StepError::new(
+                STEP_UPLOAD,
+                "No file code in the remote upload response".to_string(),
+            )
+        })
+}
+
+// Progress of a remote upload started with add_remote_upload
+pub async fn remote_upload_status(
+    client: &reqwest::Client,
+    api_key: &str,
+    filecode: &str,
+) -> Result<RemoteStatus, StepError> {
+    let body = remote_request(
+        client,
+        STEP_STATUS,
+        "remote/status",
+        &[("key", api_key), ("file_code", filecode)],
+    )
+    .await?;
+    // A list with an entry per file code, or sometimes the entry on its own
+    let entry = match body.get("result") {
+        Some(JsonValue::Array(entries)) => entries.first(),
+        other => other,
+    }
+    .filter(|entry| entry.is_object())
+    .ok_or_else(|| StepError {
+        body: Some(body.to_string()),
+        ..StepError::new(
+            STEP_STATUS,
+            format!("No remote upload {} on the account", filecode),
+        )
+    })?;
+
+    let field = |key: &str| entry.get(key).and_then(lenient::text);
+    let bytes = |key: &str| field(key).and_then(|value| value.parse::<i64>().ok());
+    let state = match field("status").unwrap_or_default().to_uppercase().as_str() {
+        "COMPLETED" | "DONE" | "OK" => RemoteState::Completed,
+        "ERROR" | "FAILED" => RemoteState::Failed(
+            field("error")
+                .or_else(|| field("msg"))
+                .filter(|reason| !reason.is_empty())
+                .unwrap_or_else(|| "Filemoon could not fetch the URL".to_string()),
+        ),
+        "WORKING" | "DOWNLOADING" => RemoteState::Working,
+        _ => RemoteState::Pending,
+    };
+    Ok(RemoteStatus {
+        state,
+        bytes_total: bytes("bytes_total"),
+        bytes_downloaded: bytes("bytes_downloaded"),
+    })
+}
+
+// Filemoon response fields are read through the lenient deserializers: a field of
+// an unexpected type or shape becomes None instead of failing the whole response.
+// A status of None means Filemoon left it out or sent something unreadable.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilemoonUploadResult {
+    #[serde(default, deserialize_with = "lenient::opt_list")]
+    pub files: Option<Vec<FilemoonFile>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilemoonGetUploadServerResponse {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub msg: String,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub result: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilemoonUploadResponse {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub msg: String,
+    #[serde(default, deserialize_with = "lenient::opt_list")]
+    pub files: Option<Vec<FilemoonFile>>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct FilemoonFile {
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub filecode: String,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub filename: Option<String>,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilemoonRestartResponse {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub msg: String,
+    // Add other fields if the API returns more data
+}
+
+fn deserialize_result<'de, D>(
+    deserializer: D,
+) -> Result<Option<FilemoonEncodingStatusResult>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: serde_json::Value = serde_json::Value::deserialize(deserializer)?;
+    match value {
+        serde_json::Value::Object(_) => {
+            // Try to deserialize as the result object; one we can't read counts as
+            // no result yet
+            Ok(serde_json::from_value(value).ok())
+        }
+        serde_json::Value::Array(mut arr) if arr.len() == 1 => {
+            // A one-entry list standing in for the object
+            Ok(serde_json::from_value(arr.remove(0)).ok())
+        }
+        serde_json::Value::Array(ref arr) if arr.is_empty() => {
+            // Empty array means no result yet
+            Ok(None)
+        }
+        serde_json::Value::Null => {
+            // Null means no result
+            Ok(None)
+        }
+        _ => {
+            // Any other type, treat as no result
+            Ok(None)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilemoonEncodingStatusResult {
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub file_code: String,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub quality: Option<String>,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub progress: Option<String>, // Can be numeric or string like "91"
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub status: String, // e.g., "ENCODING", "FINISHED", "ERROR"
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilemoonEncodingStatusResponse {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub msg: String,
+    #[serde(default, deserialize_with = "deserialize_result")]
+    pub result: Option<FilemoonEncodingStatusResult>,
+}
+
+// --- ADDED: Structs for Filemoon File Info API ---
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilemoonFileInfoResponse {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>,
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub msg: String,
+    #[serde(default, deserialize_with = "lenient::opt_list")]
+    pub result: Option<Vec<FilemoonFileInfoResult>>, // API returns an array
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilemoonFileInfoResult {
+    #[serde(default, deserialize_with = "lenient::opt_u16")]
+    pub status: Option<u16>, // Status per file in the result array
+    #[serde(default, deserialize_with = "lenient::string")]
+    pub file_code: String,
+    #[serde(default, deserialize_with = "lenient::opt_string")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "lenient::opt_i32")]
+    pub canplay: Option<i32>, // 0 or 1
+    // Duration in seconds; Filemoon sends it as a string or a number
+    pub length: Option<JsonValue>,
+    // Size of the stored file in bytes, where Filemoon reports it
+    #[serde(alias = "file_size")]
+    pub size: Option<JsonValue>,
+    // Add other fields if needed (views, uploaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ok_responses_need_a_2xx_and_no_other_api_status() {
+        assert!(is_ok_response(200, Some(200)));
+        assert!(is_ok_response(200, None));
+        assert!(!is_ok_response(200, Some(400)));
+        assert!(!is_ok_response(500, Some(200)));
+        assert!(!is_ok_response(302, None));
+    }
+}
@@ -0,0 +1,276 @@
+// Forensic bundles for failed downloads. Once a download gives up for good (failed,
+// unsupported, auth_required), whatever the attempt left in the item's download
+// directory (partial media, the .info.json, subtitles) is moved to
+// forensics/<item id>/ in the app data directory, beside yt-dlp's stderr in
+// stderr.log and a manifest.json describing the failure. The download directory is
+// left without stray partial files, and open_forensics shows what went wrong.
+//
+// A bundle keeps at most MAX_BUNDLE_BYTES of files, smallest first, so the info.json
+// and logs survive even when a partial video doesn't; larger leftovers are deleted
+// and listed as dropped. Bundles older than MAX_AGE go, then the oldest ones until
+// all of them fit in MAX_TOTAL_BYTES. A new failure of the same item replaces its
+// bundle.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+
+use crate::db::QueueItem;
+use crate::{long_paths, sanitize_filename, timestamps, AppState};
+
+const FORENSICS_DIR_NAME: &str = "forensics";
+const MANIFEST_FILE: &str = "manifest.json";
+const STDERR_FILE: &str = "stderr.log";
+
+pub const MAX_BUNDLE_BYTES: u64 = 512 * 1024 * 1024;
+pub const MAX_TOTAL_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+// The end of stderr is what explains a failure
+const MAX_STDERR_BYTES: usize = 1024 * 1024;
+
+// Statuses after which an item's leftovers are bundled
+const GAVE_UP_STATUSES: &[&str] = &["failed", "unsupported", "auth_required"];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForensicFile {
+    pub name: String,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub item_id: String,
+    pub url: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub collected_at: DateTime<Utc>,
+    // Files moved into the bundle
+    pub kept: Vec<ForensicFile>,
+    // Leftovers deleted because they didn't fit in MAX_BUNDLE_BYTES
+    pub dropped: Vec<ForensicFile>,
+}
+
+// Returned by open_forensics
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ForensicBundle {
+    pub path: String,
+    pub manifest: Manifest,
+    pub stderr: String,
+}
+
+pub fn forensics_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(FORENSICS_DIR_NAME)
+}
+
+fn bundle_dir(root: &Path, item_id: &str) -> PathBuf {
+    root.join(sanitize_filename(item_id))
+}
+
+fn root_for(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    app_handle
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| forensics_dir(&dir))
+        .ok_or_else(|| "Could not determine app data directory".to_string())
+}
+
+// Move a file, copying when the bundle is on another volume than the downloads
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| entry.metadata().ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+fn tail(text: &str, max_bytes: usize) -> &str {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let mut start = text.len() - max_bytes;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+// Bundle what a failed attempt left in `download_dir`. The item's directory is
+// removed once empty.
+fn collect(
+    root: &Path,
+    item: &QueueItem,
+    download_dir: &Path,
+    stderr: &str,
+) -> std::io::Result<Manifest> {
+    let item_id = item.id.clone().unwrap_or_default();
+    let bundle = long_paths::extended(&bundle_dir(root, &item_id));
+    if bundle.exists() {
+        fs::remove_dir_all(&bundle)?;
+    }
+    fs::create_dir_all(&bundle)?;
+
+    let stderr = tail(stderr, MAX_STDERR_BYTES);
+    fs::write(bundle.join(STDERR_FILE), stderr)?;
+
+    let download_dir = long_paths::extended(download_dir);
+    let mut leftovers: Vec<(PathBuf, ForensicFile)> = fs::read_dir(&download_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| {
+                    let metadata = entry.metadata().ok()?;
+                    metadata.is_file().then(|| {
+                        let file = ForensicFile {
+                            name: entry.file_name().to_string_lossy().to_string(),
+                            bytes: metadata.len(),
+                        };
+                        (entry.path(), file)
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    leftovers.sort_by_key(|(_, file)| file.bytes);
+
+    let mut budget = MAX_BUNDLE_BYTES.saturating_sub(stderr.len() as u64);
+    let (mut kept, mut dropped) = (Vec::new(), Vec::new());
+    for (path, file) in leftovers {
+        if file.bytes <= budget && move_file(&path, &bundle.join(&file.name)).is_ok() {
+            budget -= file.bytes;
+            kept.push(file);
+        } else {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("Failed to delete leftover {}: {}", path.display(), e);
+            }
+            dropped.push(file);
+        }
+    }
+    // remove_dir refuses non-empty directories, which is what we want
+    let _ = fs::remove_dir(&download_dir);
+
+    let manifest = Manifest {
+        item_id,
+        url: item.url.clone(),
+        status: item.status.clone(),
+        message: item.message.clone(),
+        collected_at: timestamps::now(),
+        kept,
+        dropped,
+    };
+    let json = serde_json::to_string_pretty(&manifest)?;
+    fs::write(bundle.join(MANIFEST_FILE), json)?;
+    Ok(manifest)
+}
+
+// Remove bundles past MAX_AGE, then the oldest until the rest fit in MAX_TOTAL_BYTES
+fn prune(root: &Path) {
+    let mut bundles: Vec<(PathBuf, SystemTime, u64)> = fs::read_dir(root)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .map(|entry| {
+                    let modified = entry
+                        .metadata()
+                        .and_then(|m| m.modified())
+                        .unwrap_or(SystemTime::UNIX_EPOCH);
+                    (entry.path(), modified, dir_size(&entry.path()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    // Newest first
+    bundles.sort_by(|a, b| b.1.cmp(&a.1));
+
+    let mut total = 0;
+    for (path, modified, size) in bundles {
+        let expired = modified.elapsed().map_or(false, |age| age > MAX_AGE);
+        if !expired && total + size <= MAX_TOTAL_BYTES {
+            total += size;
+            continue;
+        }
+        match fs::remove_dir_all(&path) {
+            Ok(()) => println!("Pruned forensic bundle {}", path.display()),
+            Err(e) => eprintln!("Failed to prune forensic bundle {}: {}", path.display(), e),
+        }
+    }
+}
+
+// Bundle the leftovers of a download attempt if the item has given up; an item that
+// was queued again (another format, a mirror) keeps its files to resume from.
+pub async fn collect_if_gave_up(
+    app_handle: &AppHandle,
+    item_id: &str,
+    download_dir: &str,
+    stderr: &str,
+) {
+    let app_state = app_handle.state::<AppState>();
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) if GAVE_UP_STATUSES.contains(&item.status.as_str()) => item,
+        _ => return,
+    };
+    let root = match root_for(app_handle) {
+        Ok(root) => root,
+        Err(e) => {
+            eprintln!("Forensic bundle for item {} skipped: {}", item_id, e);
+            return;
+        }
+    };
+
+    match collect(&root, &item, Path::new(download_dir), stderr) {
+        Ok(manifest) => println!(
+            "Kept {} file(s) of failed item {} in {} ({} dropped)",
+            manifest.kept.len(),
+            item_id,
+            bundle_dir(&root, item_id).display(),
+            manifest.dropped.len()
+        ),
+        Err(e) => eprintln!("Failed to bundle leftovers of item {}: {}", item_id, e),
+    }
+    prune(&root);
+}
+
+// Read an item's bundle and show its folder in the system file manager
+pub fn open(app_handle: &AppHandle, item_id: &str) -> Result<ForensicBundle, String> {
+    let bundle = bundle_dir(&root_for(app_handle)?, item_id);
+    let manifest_json = fs::read_to_string(long_paths::extended(&bundle.join(MANIFEST_FILE)))
+        .map_err(|_| format!("No forensic bundle for item {}", item_id))?;
+    let manifest: Manifest = serde_json::from_str(&manifest_json)
+        .map_err(|e| format!("Unreadable forensic manifest for item {}: {}", item_id, e))?;
+    let stderr =
+        fs::read_to_string(long_paths::extended(&bundle.join(STDERR_FILE))).unwrap_or_default();
+
+    let path = long_paths::plain(&bundle);
+    let program = if cfg!(target_os = "windows") {
+        "explorer"
+    } else if cfg!(target_os = "macos") {
+        "open"
+    } else {
+        "xdg-open"
+    };
+    if let Err(e) = tokio::process::Command::new(program).arg(&path).spawn() {
+        eprintln!("Could not open {} with {}: {}", path, program, e);
+    }
+
+    Ok(ForensicBundle {
+        path,
+        manifest,
+        stderr,
+    })
+}
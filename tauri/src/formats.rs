@@ -0,0 +1,281 @@
+// Format fallback ladder for yt-dlp downloads. A failed download is re-queued on the
+// next rung (lower quality) instead of being marked failed straight away.
+// Also lists the formats a video offers, so one can be picked per item.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::db::AppSettings;
+use crate::{cancellation, tools};
+
+// Used when no fallback ladder is configured
+pub const DEFAULT_FALLBACK_LADDER: &[&str] =
+    &["bestvideo[height<=720]+bestaudio/best[height<=720]", "best"];
+
+// Rungs in a configured ladder are separated by semicolons, since `/` and `+`
+// are already part of yt-dlp's format syntax
+const LADDER_SEPARATOR: char = ';';
+
+// Rungs in order. `None` means no `--format` argument (yt-dlp's own default).
+// `site_defaults` replaces the default ladder for sites with their own (see social.rs).
+pub fn ladder(
+    preferred: Option<&str>,
+    fallbacks: Option<&str>,
+    site_defaults: Option<&[&str]>,
+) -> Vec<Option<String>> {
+    let mut rungs: Vec<Option<String>> = vec![preferred
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .map(String::from)];
+
+    let configured: Vec<String> = fallbacks
+        .map(|f| {
+            f.split(LADDER_SEPARATOR)
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    let fallbacks = if configured.is_empty() {
+        site_defaults
+            .unwrap_or(DEFAULT_FALLBACK_LADDER)
+            .iter()
+            .map(|f| f.to_string())
+            .collect()
+    } else {
+        configured
+    };
+
+    for format in fallbacks {
+        let format = Some(format);
+        if !rungs.contains(&format) {
+            rungs.push(format);
+        }
+    }
+    rungs
+}
+
+// What to do when a format needs ffmpeg to merge streams and ffmpeg is missing
+// (`missing_ffmpeg_action`): download a pre-merged format instead, or hold the item
+pub const MISSING_FFMPEG_PREMERGED: &str = "premerged";
+pub const MISSING_FFMPEG_BLOCK: &str = "block";
+
+pub fn block_on_missing_ffmpeg(setting: Option<&str>) -> bool {
+    setting.map(str::trim) == Some(MISSING_FFMPEG_BLOCK)
+}
+
+// Check missing_ffmpeg_action before it is saved; unset means "premerged"
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    match settings.missing_ffmpeg_action.as_deref().map(str::trim) {
+        None | Some("") | Some(MISSING_FFMPEG_PREMERGED) | Some(MISSING_FFMPEG_BLOCK) => Ok(()),
+        Some(other) => Err(format!(
+            "Invalid missing_ffmpeg_action '{}'; use '{}' or '{}'",
+            other, MISSING_FFMPEG_PREMERGED, MISSING_FFMPEG_BLOCK
+        )),
+    }
+}
+
+// Split a selector at `separator`, leaving filters such as [height<=720] alone
+fn split_top_level(format: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut start) = (0, 0);
+    for (i, c) in format.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            c if c == separator && depth == 0 => {
+                parts.push(&format[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&format[start..]);
+    parts
+}
+
+// Whether yt-dlp may merge separate video and audio streams for this rung, which
+// needs ffmpeg. Without ffmpeg it keeps the streams unmerged, so the archived file is
+// video-only. yt-dlp's own default (no --format) is "bv*+ba/b", which merges.
+pub fn needs_merge(format: Option<&str>) -> bool {
+    match format {
+        Some(format) => split_top_level(format, '/')
+            .iter()
+            .any(|alternative| split_top_level(alternative, '+').len() > 1),
+        None => true,
+    }
+}
+
+// The rung rewritten to formats that already hold both video and audio: merging
+// alternatives are dropped, the rest must have both streams, and yt-dlp's "best"
+// (best single file with video and audio) closes the list
+pub fn premerged(format: Option<&str>) -> String {
+    let mut alternatives: Vec<String> = format
+        .map(|format| split_top_level(format, '/'))
+        .unwrap_or_default()
+        .into_iter()
+        .map(str::trim)
+        .filter(|alt| !alt.is_empty() && split_top_level(alt, '+').len() == 1)
+        .filter(|alt| *alt != "best" && *alt != "b")
+        .map(|alt| format!("{}[vcodec!=none][acodec!=none]", alt))
+        .collect();
+    alternatives.push("best".to_string());
+    alternatives.join("/")
+}
+
+// Human readable name of a rung, as recorded on the item
+pub fn label(format: Option<&str>) -> String {
+    format.unwrap_or("default").to_string()
+}
+
+// One format yt-dlp offers for a video, for the format picker
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AvailableFormat {
+    pub format_id: String,
+    pub ext: Option<String>,
+    pub resolution: Option<String>,
+    pub width: Option<i64>,
+    pub height: Option<i64>,
+    pub fps: Option<f64>,
+    pub vcodec: Option<String>,
+    pub acodec: Option<String>,
+    pub filesize: Option<i64>,
+    pub tbr: Option<f64>,
+    pub note: Option<String>,
+    pub has_video: bool,
+    pub has_audio: bool,
+    // Value to store as the item's format; video-only formats get the best audio added
+    pub selector: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FormatList {
+    pub url: String,
+    pub title: Option<String>,
+    pub duration_secs: Option<f64>,
+    pub formats: Vec<AvailableFormat>,
+}
+
+const LIST_TIMEOUT: Duration = Duration::from_secs(90);
+
+fn text(info: &JsonValue, key: &str) -> Option<String> {
+    info.get(key)
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+}
+
+// yt-dlp reports a missing stream as codec "none"
+fn codec(info: &JsonValue, key: &str) -> Option<String> {
+    text(info, key).filter(|c| c != "none")
+}
+
+fn available_format(info: &JsonValue) -> Option<AvailableFormat> {
+    let format_id = text(info, "format_id")?;
+    let vcodec = codec(info, "vcodec");
+    let acodec = codec(info, "acodec");
+    let has_video = vcodec.is_some() || info.get("height").and_then(|v| v.as_i64()).is_some();
+    let has_audio = acodec.is_some();
+    let selector = if has_video && !has_audio {
+        format!("{}+bestaudio/{}", format_id, format_id)
+    } else {
+        format_id.clone()
+    };
+    Some(AvailableFormat {
+        ext: text(info, "ext"),
+        resolution: text(info, "resolution"),
+        width: info.get("width").and_then(|v| v.as_i64()),
+        height: info.get("height").and_then(|v| v.as_i64()),
+        fps: info.get("fps").and_then(|v| v.as_f64()),
+        filesize: info
+            .get("filesize")
+            .and_then(|v| v.as_i64())
+            .or_else(|| info.get("filesize_approx").and_then(|v| v.as_i64())),
+        tbr: info.get("tbr").and_then(|v| v.as_f64()),
+        note: text(info, "format_note"),
+        vcodec,
+        acodec,
+        has_video,
+        has_audio,
+        selector,
+        format_id,
+    })
+}
+
+// The formats yt-dlp offers for `url`, worst to best as it lists them
+pub async fn available(url: &str) -> Result<FormatList, String> {
+    let mut cmd = Command::new(tools::ytdlp_binary());
+    cmd.arg("--dump-single-json")
+        .arg("--skip-download")
+        .arg("--no-playlist")
+        .arg("--no-warnings")
+        .arg(url);
+
+    let output = match cancellation::output_with_timeout(&mut cmd, LIST_TIMEOUT).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("Failed to run yt-dlp: {}", e)),
+        Err(_) => {
+            return Err(format!(
+                "Listing formats timed out after {} s",
+                LIST_TIMEOUT.as_secs()
+            ))
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("yt-dlp failed")
+            .trim()
+            .to_string());
+    }
+
+    let info: JsonValue = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Unreadable yt-dlp output: {}", e))?;
+    let formats = info
+        .get("formats")
+        .and_then(|v| v.as_array())
+        .map(|formats| formats.iter().filter_map(available_format).collect())
+        .unwrap_or_default();
+    Ok(FormatList {
+        url: url.to_string(),
+        title: text(&info, "title"),
+        duration_secs: info.get("duration").and_then(|v| v.as_f64()),
+        formats,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merging_rungs_need_ffmpeg() {
+        assert!(needs_merge(None));
+        assert!(needs_merge(Some("bv*+ba/b")));
+        assert!(needs_merge(Some("best/bestvideo[height<=720]+bestaudio")));
+    }
+
+    #[test]
+    fn single_file_rungs_do_not() {
+        assert!(!needs_merge(Some("best")));
+        assert!(!needs_merge(Some("22/18")));
+        assert!(!needs_merge(Some("b[format_note*=a+b]")));
+    }
+
+    #[test]
+    fn missing_ffmpeg_action_takes_the_known_values() {
+        let mut settings = AppSettings::default();
+        for value in ["", MISSING_FFMPEG_PREMERGED, MISSING_FFMPEG_BLOCK] {
+            settings.missing_ffmpeg_action = Some(value.to_string());
+            assert!(validate(&settings).is_ok());
+        }
+        settings.missing_ffmpeg_action = Some("skip".to_string());
+        assert!(validate(&settings).is_err());
+    }
+}
@@ -0,0 +1,290 @@
+// User-defined hook scripts. Each hook setting holds a command line such as
+//
+//     /usr/local/bin/index-video --id {id} --file "{local_path}"
+//
+// run after a download finishes, after an upload, or when an item fails. The line is
+// split into arguments before placeholders are filled in, so a title with spaces or
+// quotes reaches the script as one argument and nothing goes through a shell. Every
+// run is stored with the item, along with its exit code and output.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::process::Command;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::db::{AppSettings, HookRun, QueueItem, StatusEvent};
+use crate::{cancellation, filemoon, messages, timestamps, AppState};
+
+pub const EVENT_AFTER_DOWNLOAD: &str = "after_download";
+pub const EVENT_AFTER_UPLOAD: &str = "after_upload";
+pub const EVENT_ON_FAILURE: &str = "on_failure";
+
+pub const PLACEHOLDERS: &[&str] = &[
+    "id",
+    "url",
+    "title",
+    "status",
+    "message",
+    "local_path",
+    "filecode",
+    "embed_url",
+    "short_url",
+    "event",
+];
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(600);
+// Output kept per stream and run
+const MAX_OUTPUT_CHARS: usize = 8_000;
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{([a-z_]+)\}").unwrap();
+}
+
+fn hook_command<'a>(settings: &'a AppSettings, event: &str) -> Option<&'a str> {
+    let command = match event {
+        EVENT_AFTER_DOWNLOAD => settings.hook_after_download.as_deref(),
+        EVENT_AFTER_UPLOAD => settings.hook_after_upload.as_deref(),
+        EVENT_ON_FAILURE => settings.hook_on_failure.as_deref(),
+        _ => None,
+    };
+    command.map(str::trim).filter(|command| !command.is_empty())
+}
+
+// Split a command line into arguments. Whitespace separates arguments and single or
+// double quotes group them; backslashes are kept as they are so Windows paths work.
+pub fn split_command(line: &str) -> Result<Vec<String>, String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            None if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            None => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if let Some(q) = quote {
+        return Err(format!("Unclosed {} quote in hook command", q));
+    }
+    if in_arg {
+        args.push(current);
+    }
+    if args.is_empty() {
+        return Err("Hook command is empty".to_string());
+    }
+    Ok(args)
+}
+
+// Check the hook settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    for event in [EVENT_AFTER_DOWNLOAD, EVENT_AFTER_UPLOAD, EVENT_ON_FAILURE] {
+        let line = match hook_command(settings, event) {
+            Some(line) => line,
+            None => continue,
+        };
+        split_command(line).map_err(|e| format!("Invalid {} hook: {}", event, e))?;
+        if let Some(unknown) = PLACEHOLDER_REGEX
+            .captures_iter(line)
+            .map(|caps| caps[1].to_string())
+            .find(|name| !PLACEHOLDERS.contains(&name.as_str()))
+        {
+            return Err(format!(
+                "Invalid {} hook: unknown placeholder {{{}}}. Use one of: {}",
+                event,
+                unknown,
+                PLACEHOLDERS.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn fill_placeholders(arg: &str, item: &QueueItem, event: &str) -> String {
+    PLACEHOLDER_REGEX
+        .replace_all(arg, |caps: &Captures| {
+            let value = match &caps[1] {
+                "id" => item.id.clone(),
+                "url" => Some(item.url.clone()),
+                "title" => item.title.clone(),
+                "status" => Some(item.status.clone()),
+                "message" => item.message.clone(),
+                "local_path" => item.local_path.clone(),
+                "filecode" => item.filemoon_url.clone(),
+                "embed_url" => item.filemoon_url.as_deref().map(filemoon::embed_url),
+                "short_url" => item.short_url.clone(),
+                "event" => Some(event.to_string()),
+                _ => return caps[0].to_string(),
+            };
+            value.unwrap_or_default()
+        })
+        .into_owned()
+}
+
+// Which hook a status change triggers. Only the writes that finish a download or an
+// upload count, not every later write of the same status (approval, retries).
+pub fn hook_event(event: &StatusEvent) -> Option<&'static str> {
+    let code = event.message_code.as_deref();
+    match event.status.as_str() {
+        "downloaded" | "pending_review"
+            if matches!(
+                code,
+                Some(messages::DOWNLOAD_COMPLETE) | Some(messages::DOWNLOAD_AWAITING_REVIEW)
+            ) =>
+        {
+            Some(EVENT_AFTER_DOWNLOAD)
+        }
+        "uploaded"
+            if matches!(
+                code,
+                Some(messages::UPLOAD_DONE)
+                    | Some(messages::UPLOAD_DECLARED_DONE)
+                    | Some(messages::UPLOAD_LINKED_EXISTING)
+            ) =>
+        {
+            Some(EVENT_AFTER_UPLOAD)
+        }
+        "failed" | "unsupported" | "auth_required" | "blocked_missing_ffmpeg" => {
+            Some(EVENT_ON_FAILURE)
+        }
+        _ => None,
+    }
+}
+
+// Listen for status changes and run the matching hooks until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting hook runner...");
+    let mut events = app_handle.state::<AppState>().db.subscribe_status_events();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                if let Some(hook) = hook_event(&event) {
+                    // A slow script must not hold up the hooks of other items
+                    let app_handle = app_handle.clone();
+                    tokio::spawn(async move {
+                        run_hook(&app_handle, hook, &event.item_id).await;
+                    });
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!("Hook runner fell behind and skipped {} status event(s)", missed);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    println!("Hook runner stopped");
+}
+
+async fn run_hook(app_handle: &AppHandle, event: &str, item_id: &str) {
+    let app_state = app_handle.state::<AppState>();
+
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Hook runner could not load item {}: {}", item_id, e);
+            return;
+        }
+    };
+    let user_id = item.user_id.clone().unwrap_or_else(|| "local-user".to_string());
+    let settings = match app_state.db.get_settings(&user_id).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Hook runner could not load settings for {}: {}", user_id, e);
+            return;
+        }
+    };
+    let line = match hook_command(&settings, event) {
+        Some(line) => line.to_string(),
+        None => return,
+    };
+
+    let started_at = timestamps::now();
+    let mut hook_run = HookRun {
+        id: Uuid::new_v4().to_string(),
+        item_id: item_id.to_string(),
+        event: event.to_string(),
+        command: line.clone(),
+        exit_code: None,
+        stdout: String::new(),
+        stderr: String::new(),
+        error: None,
+        started_at,
+        finished_at: started_at,
+    };
+
+    match split_command(&line) {
+        Ok(args) => {
+            let args: Vec<String> = args
+                .iter()
+                .map(|arg| fill_placeholders(arg, &item, event))
+                .collect();
+            match execute(&args).await {
+                Ok(output) => {
+                    hook_run.exit_code = output.status.code();
+                    hook_run.stdout = truncate_output(&output.stdout);
+                    hook_run.stderr = truncate_output(&output.stderr);
+                    if !output.status.success() {
+                        hook_run.error = Some(format!("Hook exited with {}", output.status));
+                    }
+                }
+                Err(e) => hook_run.error = Some(e),
+            }
+        }
+        Err(e) => hook_run.error = Some(e),
+    }
+    hook_run.finished_at = timestamps::now();
+
+    match &hook_run.error {
+        Some(e) => eprintln!("{} hook for item {} failed: {}", event, item_id, e),
+        None => println!("{} hook for item {} finished", event, item_id),
+    }
+    if let Err(e) = app_state.db.record_hook_run(&hook_run).await {
+        eprintln!("Error saving {} hook run for item {}: {}", event, item_id, e);
+    }
+}
+
+async fn execute(args: &[String]) -> Result<std::process::Output, String> {
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| "Hook command is empty".to_string())?;
+    let mut command = Command::new(program);
+    command.args(rest);
+
+    match cancellation::output_with_timeout(&mut command, HOOK_TIMEOUT).await {
+        Ok(result) => result.map_err(|e| format!("Failed to start '{}': {}", program, e)),
+        Err(_) => Err(format!(
+            "Timed out after {} min and was stopped",
+            HOOK_TIMEOUT.as_secs() / 60
+        )),
+    }
+}
+
+// The end of a stream is usually what explains a failure, so that is what is kept
+fn truncate_output(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let total = text.chars().count();
+    if total <= MAX_OUTPUT_CHARS {
+        return text.into_owned();
+    }
+    text.chars().skip(total - MAX_OUTPUT_CHARS).collect()
+}
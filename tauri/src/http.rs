@@ -0,0 +1,125 @@
+// The HTTP client shared by provider calls (Filemoon, the link shortener). Every
+// request identifies the app with a versioned User-Agent and carries the extra headers
+// from the `provider_http_headers` setting ("Name: value", one per line). Filemoon's
+// endpoints can be pointed at alternative domains or a proxy with the filemoon_*_base
+// and filemoon_upload_server_url settings; see filemoon::api_base and friends.
+//
+// The configuration is process-wide: it is applied at startup from the global
+// settings and again whenever settings are loaded or saved.
+
+use lazy_static::lazy_static;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::sync::{Arc, RwLock};
+
+use crate::db::AppSettings;
+
+pub const USER_AGENT: &str = concat!(
+    "PermaVid/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/FahadBinHussain/PermaVid)"
+);
+
+#[derive(Debug, Clone, Default)]
+pub struct Endpoints {
+    pub filemoon_api_base: Option<String>,
+    pub filemoon_site_base: Option<String>,
+    pub filemoon_upload_server_url: Option<String>,
+}
+
+struct ProviderConfig {
+    client: reqwest::Client,
+    endpoints: Endpoints,
+}
+
+lazy_static! {
+    static ref CONFIG: RwLock<Arc<ProviderConfig>> = RwLock::new(Arc::new(ProviderConfig {
+        client: build_client(HeaderMap::new()).unwrap_or_default(),
+        endpoints: Endpoints::default(),
+    }));
+}
+
+// "Name: value" lines; blank lines and lines starting with '#' are skipped
+pub fn parse_headers(raw: Option<&str>) -> Result<HeaderMap, String> {
+    let mut headers = HeaderMap::new();
+    for line in raw.unwrap_or_default().lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid header line '{}', expected \"Name: value\"", line))?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| format!("Invalid header name '{}'", name.trim()))?;
+        let value = HeaderValue::from_str(value.trim())
+            .map_err(|_| format!("Invalid value for header '{}'", name))?;
+        headers.insert(name, value);
+    }
+    Ok(headers)
+}
+
+// A base URL override, without its trailing slash; None when the setting is empty
+fn parse_base_url(setting: &str, raw: Option<&str>) -> Result<Option<String>, String> {
+    let url = match raw.map(str::trim).filter(|u| !u.is_empty()) {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    if !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err(format!(
+            "{} must start with http:// or https://: {}",
+            setting, url
+        ));
+    }
+    Ok(Some(url.trim_end_matches('/').to_string()))
+}
+
+fn endpoints_of(settings: &AppSettings) -> Result<Endpoints, String> {
+    Ok(Endpoints {
+        filemoon_api_base: parse_base_url(
+            "filemoon_api_base",
+            settings.filemoon_api_base.as_deref(),
+        )?,
+        filemoon_site_base: parse_base_url(
+            "filemoon_site_base",
+            settings.filemoon_site_base.as_deref(),
+        )?,
+        filemoon_upload_server_url: parse_base_url(
+            "filemoon_upload_server_url",
+            settings.filemoon_upload_server_url.as_deref(),
+        )?,
+    })
+}
+
+// Custom headers are applied after the User-Agent, so they may replace it
+fn build_client(headers: HeaderMap) -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .default_headers(headers)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+// Check the provider settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    parse_headers(settings.provider_http_headers.as_deref())?;
+    endpoints_of(settings)?;
+    Ok(())
+}
+
+pub fn configure(settings: &AppSettings) -> Result<(), String> {
+    let headers = parse_headers(settings.provider_http_headers.as_deref())?;
+    let config = ProviderConfig {
+        client: build_client(headers)?,
+        endpoints: endpoints_of(settings)?,
+    };
+    *CONFIG.write().unwrap() = Arc::new(config);
+    Ok(())
+}
+
+// A cheap handle to the shared client; clones share one connection pool
+pub fn client() -> reqwest::Client {
+    CONFIG.read().unwrap().client.clone()
+}
+
+pub fn endpoints() -> Endpoints {
+    CONFIG.read().unwrap().endpoints.clone()
+}
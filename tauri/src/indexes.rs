@@ -0,0 +1,215 @@
+// Index upkeep and a query-plan diagnostic. The queue's video_key column (the
+// "<site>:<id>" of urls::video_key) is unique, so two URLs of one video can't both be
+// queued. New items get their key on insert; older rows, restored rows and rows whose
+// URL changed are filled in by the backfill, which leaves a row without a key when
+// another item already holds it (duplicates.rs can merge those).
+// explain_slow_queries runs EXPLAIN (never the query itself) on the queries the
+// gallery and the queue processor rely on, and flags plans that scan the whole table.
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Manager};
+use tokio_postgres::types::ToSql;
+
+use crate::{urls, AppState};
+
+// Estimated plan cost above which a query is flagged
+const SLOW_COST: f64 = 10_000.0;
+
+lazy_static! {
+    // "Limit  (cost=0.15..8.17 rows=1 width=520)"
+    static ref COST_REGEX: Regex = Regex::new(r"cost=[\d.]+\.\.([\d.]+) rows=(\d+)").unwrap();
+    static ref INDEX_REGEX: Regex = Regex::new(
+        r"(?:Index(?: Only)? Scan(?: Backward)? using|Bitmap Index Scan on) (\S+)"
+    )
+    .unwrap();
+    static ref SEQ_SCAN_REGEX: Regex = Regex::new(r"Seq Scan on (\S+)").unwrap();
+    static ref SORT_REGEX: Regex = Regex::new(r"\bSort\s+\(cost").unwrap();
+}
+
+#[derive(Debug, Default)]
+pub struct BackfillSummary {
+    pub keyed: usize,
+    // Another item already holds the same video
+    pub duplicates: usize,
+    // URLs of sites urls::video_key doesn't know
+    pub unrecognized: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueryPlan {
+    pub name: String,
+    pub query: String,
+    pub total_cost: Option<f64>,
+    pub estimated_rows: Option<i64>,
+    pub indexes_used: Vec<String>,
+    pub seq_scans: Vec<String>,
+    pub sorts: bool,
+    // EXPLAIN output, one line per plan node
+    pub plan: Vec<String>,
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SlowQueryReport {
+    pub plans: Vec<QueryPlan>,
+    pub flagged: usize,
+}
+
+// Key every row that has none yet
+pub async fn backfill_video_keys(app_state: &AppState) -> Result<BackfillSummary, String> {
+    let items = app_state
+        .db
+        .get_items_without_video_key()
+        .await
+        .map_err(|e| format!("Failed to load items without a video key: {}", e))?;
+
+    let mut summary = BackfillSummary::default();
+    for (id, url) in items {
+        let key = match urls::video_key(&url) {
+            Some(key) => key,
+            None => {
+                summary.unrecognized += 1;
+                continue;
+            }
+        };
+        match app_state.db.set_video_key(&id, &key).await {
+            Ok(true) => summary.keyed += 1,
+            Ok(false) => summary.duplicates += 1,
+            Err(e) => eprintln!("Error setting video key of item {}: {}", id, e),
+        }
+    }
+    Ok(summary)
+}
+
+pub async fn run_backfill(app_handle: AppHandle) {
+    let app_state = app_handle.state::<AppState>();
+    match backfill_video_keys(&app_state).await {
+        Ok(summary) if summary.keyed + summary.duplicates > 0 => println!(
+            "Backfilled video keys: {} keyed, {} duplicate(s) left unkeyed",
+            summary.keyed, summary.duplicates
+        ),
+        Ok(_) => {}
+        Err(e) => eprintln!("Video key backfill failed: {}", e),
+    }
+}
+
+fn analyze(name: &str, query: &str, plan: Vec<String>) -> QueryPlan {
+    let costs = plan.first().and_then(|line| COST_REGEX.captures(line));
+    let total_cost = costs.as_ref().and_then(|caps| caps[1].parse::<f64>().ok());
+    let estimated_rows = costs.as_ref().and_then(|caps| caps[2].parse::<i64>().ok());
+
+    let mut indexes_used = Vec::new();
+    let mut seq_scans = Vec::new();
+    for line in &plan {
+        for caps in INDEX_REGEX.captures_iter(line) {
+            if !indexes_used.contains(&caps[1].to_string()) {
+                indexes_used.push(caps[1].to_string());
+            }
+        }
+        for caps in SEQ_SCAN_REGEX.captures_iter(line) {
+            if !seq_scans.contains(&caps[1].to_string()) {
+                seq_scans.push(caps[1].to_string());
+            }
+        }
+    }
+    let sorts = plan.iter().any(|line| SORT_REGEX.is_match(line));
+
+    let warning = if total_cost.map_or(false, |cost| cost > SLOW_COST) {
+        Some(format!(
+            "Estimated cost {:.0} is above {:.0}",
+            total_cost.unwrap_or_default(),
+            SLOW_COST
+        ))
+    } else if !seq_scans.is_empty() {
+        // Postgres prefers a full scan of a small table, so this only matters once it grows
+        Some(format!(
+            "Sequential scan on {}; run ANALYZE if the table is large",
+            seq_scans.join(", ")
+        ))
+    } else {
+        None
+    };
+
+    QueryPlan {
+        name: name.to_string(),
+        query: query.to_string(),
+        total_cost,
+        estimated_rows,
+        indexes_used,
+        seq_scans,
+        sorts,
+        plan,
+        warning,
+    }
+}
+
+async fn explain(
+    app_state: &AppState,
+    name: &str,
+    query: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<QueryPlan, String> {
+    let plan = app_state
+        .db
+        .explain(query, params)
+        .await
+        .map_err(|e| format!("Failed to explain {}: {}", name, e))?;
+    Ok(analyze(name, query, plan))
+}
+
+// The same shapes as the db.rs queries they stand for
+pub async fn explain_slow_queries(
+    app_state: &AppState,
+    user_id: &str,
+) -> Result<SlowQueryReport, String> {
+    let processing = vec!["downloaded", "uploading"];
+    let no_time: Option<DateTime<Utc>> = None;
+
+    let plans = vec![
+        explain(
+            app_state,
+            "queue_items",
+            "SELECT * FROM queue WHERE user_id = $1 ORDER BY added_at DESC",
+            &[&user_id],
+        )
+        .await?,
+        explain(
+            app_state,
+            "gallery_by_status",
+            "SELECT * FROM queue WHERE user_id = $1 AND status = $2 ORDER BY added_at DESC",
+            &[&user_id, &"uploaded"],
+        )
+        .await?,
+        explain(
+            app_state,
+            "items_in_statuses",
+            "SELECT * FROM queue WHERE status = ANY($1) AND NOT locked ORDER BY updated_at ASC",
+            &[&processing],
+        )
+        .await?,
+        explain(
+            app_state,
+            "changes_since",
+            "SELECT * FROM queue WHERE user_id = $1
+               AND ($2::TIMESTAMPTZ IS NULL OR (updated_at, id) > ($2, $3))
+             ORDER BY updated_at ASC, id ASC LIMIT 100",
+            &[&user_id, &no_time, &""],
+        )
+        .await?,
+        explain(
+            app_state,
+            "duplicate_check",
+            "SELECT status FROM queue WHERE url = $1 OR video_key = $2",
+            &[&"", &""],
+        )
+        .await?,
+    ];
+
+    Ok(SlowQueryReport {
+        flagged: plans.iter().filter(|plan| plan.warning.is_some()).count(),
+        plans,
+    })
+}
@@ -0,0 +1,310 @@
+// Single-instance guard. The first instance takes a lock file in the app data dir and
+// runs the background workers; a second instance either hands over to it or, when
+// started with --viewer (or PERMAVID_VIEWER=1), runs viewer-only with the workers
+// disabled. Handing over means asking the first instance to bring its window to the
+// front: it listens on a loopback port noted in the lock file, and a second instance
+// that connects there and sends FOCUS_REQUEST exits quietly afterwards.
+// A primary instance can also switch its workers off at runtime; that choice is
+// stored in the app data dir so it belongs to this install, not to a user.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+
+const LOCK_FILE_NAME: &str = "instance.lock";
+const SETTINGS_FILE_NAME: &str = "instance_settings.json";
+// The holder rewrites the lock file this often
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+// A lock file not refreshed for this long belongs to a crashed instance
+const STALE_AFTER: Duration = Duration::from_secs(60);
+// What a second instance sends to the first one's port
+const FOCUS_REQUEST: &str = "focus";
+// How long a second instance waits for the first one to answer
+const FOCUS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum InstanceRole {
+    Primary,
+    Viewer,
+}
+
+pub struct InstanceLock {
+    path: Mutex<Option<PathBuf>>,
+    // Where a second instance asks this one to show its window
+    focus_listener: Mutex<Option<TcpListener>>,
+    focus_port: Option<u16>,
+}
+
+// True when this launch asked to be a viewer-only secondary instance
+pub fn viewer_requested() -> bool {
+    std::env::args().any(|arg| arg == "--viewer")
+        || std::env::var("PERMAVID_VIEWER").map_or(false, |v| v == "1" || v == "true")
+}
+
+fn write_lock_contents(file: &mut fs::File, focus_port: Option<u16>) -> std::io::Result<()> {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    file.set_len(0)?;
+    write!(file, "pid={}\nheartbeat={}\n", std::process::id(), now)?;
+    if let Some(port) = focus_port {
+        writeln!(file, "port={}", port)?;
+    }
+    file.sync_all()
+}
+
+// The port the lock holder listens on, if it noted one
+fn focus_port(lock_contents: &str) -> Option<u16> {
+    lock_contents
+        .lines()
+        .find_map(|line| line.strip_prefix("port="))
+        .and_then(|port| port.trim().parse().ok())
+}
+
+// Ask the instance holding the lock in `dir` to bring its window to the front.
+// Returns false when it could not be reached.
+pub fn focus_primary(dir: Option<PathBuf>) -> bool {
+    let port = match dir
+        .and_then(|dir| fs::read_to_string(dir.join(LOCK_FILE_NAME)).ok())
+        .and_then(|contents| focus_port(&contents))
+    {
+        Some(port) => port,
+        None => return false,
+    };
+    let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+    let result = TcpStream::connect_timeout(&addr, FOCUS_TIMEOUT).and_then(|mut stream| {
+        stream.set_read_timeout(Some(FOCUS_TIMEOUT))?;
+        writeln!(stream, "{}", FOCUS_REQUEST)?;
+        // Answered once the window was asked to show
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+        Ok(reply.trim() == "ok")
+    });
+    match result {
+        Ok(answered) => answered,
+        Err(e) => {
+            eprintln!("Could not reach the running instance: {}", e);
+            false
+        }
+    }
+}
+
+fn show_main_window(app_handle: &AppHandle) {
+    if let Some(window) = app_handle.get_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
+
+// Answer one connection to the focus port
+async fn answer_focus_request(stream: tokio::net::TcpStream, app_handle: &AppHandle) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+
+    let mut stream = tokio::io::BufReader::new(stream);
+    let mut request = String::new();
+    let read = tokio::time::timeout(FOCUS_TIMEOUT, stream.read_line(&mut request)).await;
+    if !matches!(read, Ok(Ok(_))) || request.trim() != FOCUS_REQUEST {
+        return;
+    }
+    println!("Another PermaVid launch asked for this window");
+    show_main_window(app_handle);
+    let _ = stream.get_mut().write_all(b"ok\n").await;
+}
+
+fn is_stale(path: &PathBuf) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map_or(true, |age| age > STALE_AFTER)
+}
+
+impl InstanceLock {
+    // Take the lock, replacing it if the previous holder stopped refreshing it.
+    // Returns Err if another live instance holds it.
+    pub fn acquire(dir: Option<PathBuf>) -> Result<InstanceLock, String> {
+        let dir = dir.ok_or_else(|| "Could not resolve app data directory".to_string())?;
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+        let path = dir.join(LOCK_FILE_NAME);
+
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    // Without the port a second launch can't hand over, but this one runs fine
+                    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                        .map_err(|e| eprintln!("Failed to open the instance focus port: {}", e))
+                        .ok();
+                    let focus_port = listener
+                        .as_ref()
+                        .and_then(|listener| listener.local_addr().ok())
+                        .map(|addr| addr.port());
+                    write_lock_contents(&mut file, focus_port)
+                        .map_err(|e| format!("Failed to write instance lock: {}", e))?;
+                    return Ok(InstanceLock {
+                        path: Mutex::new(Some(path)),
+                        focus_listener: Mutex::new(listener),
+                        focus_port,
+                    });
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if !is_stale(&path) {
+                        let holder = fs::read_to_string(&path).unwrap_or_default();
+                        return Err(format!(
+                            "Another PermaVid instance is already running ({})",
+                            holder.lines().next().unwrap_or("unknown pid")
+                        ));
+                    }
+                    println!("Removing stale instance lock at {}", path.display());
+                    let _ = fs::remove_file(&path);
+                }
+                Err(e) => return Err(format!("Failed to create instance lock: {}", e)),
+            }
+        }
+
+        Err("Could not acquire instance lock".to_string())
+    }
+
+    // A viewer instance holds no lock
+    pub fn none() -> InstanceLock {
+        InstanceLock {
+            path: Mutex::new(None),
+            focus_listener: Mutex::new(None),
+            focus_port: None,
+        }
+    }
+
+    pub fn role(&self) -> InstanceRole {
+        if self.path.lock().unwrap().is_some() {
+            InstanceRole::Primary
+        } else {
+            InstanceRole::Viewer
+        }
+    }
+
+    // Keep the lock file fresh so other instances don't treat it as stale
+    pub async fn heartbeat(&self) {
+        loop {
+            tokio::time::sleep(HEARTBEAT_INTERVAL).await;
+            let path = match self.path.lock().unwrap().clone() {
+                Some(path) => path,
+                None => return,
+            };
+            let result = OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .and_then(|mut file| write_lock_contents(&mut file, self.focus_port));
+            if let Err(e) = result {
+                eprintln!("Failed to refresh instance lock: {}", e);
+            }
+        }
+    }
+
+    // Bring the window to the front whenever a second launch asks for it
+    pub async fn serve_focus_requests(&self, app_handle: AppHandle) {
+        let listener = match self.focus_listener.lock().unwrap().take() {
+            Some(listener) => listener,
+            None => return,
+        };
+        let listener = match listener
+            .set_nonblocking(true)
+            .and_then(|_| tokio::net::TcpListener::from_std(listener))
+        {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Failed to listen on the instance focus port: {}", e);
+                return;
+            }
+        };
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => answer_focus_request(stream, &app_handle).await,
+                Err(e) => eprintln!("Error accepting on the instance focus port: {}", e),
+            }
+        }
+    }
+
+    pub fn release(&self) {
+        if let Some(path) = self.path.lock().unwrap().take() {
+            if let Err(e) = fs::remove_file(&path) {
+                eprintln!("Failed to remove instance lock: {}", e);
+            }
+        }
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+// Settings that apply to this install only and are never synced to the database
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct InstanceSettings {
+    #[serde(default = "default_worker_enabled")]
+    worker_enabled: bool,
+}
+
+fn default_worker_enabled() -> bool {
+    true
+}
+
+// Runtime switch for the background processor
+pub struct WorkerToggle {
+    path: Option<PathBuf>,
+    enabled: AtomicBool,
+}
+
+impl WorkerToggle {
+    // Load the saved state from `dir`; workers are enabled when nothing was saved
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|d| d.join(SETTINGS_FILE_NAME));
+
+        let enabled = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str::<InstanceSettings>(&content).ok())
+            .map_or(true, |settings| settings.worker_enabled);
+
+        if !enabled {
+            println!("Background worker is disabled for this instance");
+        }
+
+        WorkerToggle {
+            path,
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> Result<(), String> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+            }
+            let content = serde_json::to_string_pretty(&InstanceSettings {
+                worker_enabled: enabled,
+            })
+            .map_err(|e| format!("Failed to serialize instance settings: {}", e))?;
+            fs::write(path, content)
+                .map_err(|e| format!("Failed to save instance settings: {}", e))?;
+        }
+
+        self.enabled.store(enabled, Ordering::SeqCst);
+        Ok(())
+    }
+}
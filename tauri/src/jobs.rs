@@ -0,0 +1,133 @@
+// Registry of running background jobs (downloads, transcodes, uploads, provider
+// status checks) for the activity panel. Each job is registered for as long as its
+// guard lives, carries its live progress, and can be cancelled by job ID. Item jobs
+// share the item's cancellation token, so cancel_job behaves like cancel_item.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::timestamps;
+
+pub const KIND_DOWNLOAD: &str = "download";
+pub const KIND_UPLOAD: &str = "upload";
+pub const KIND_TRANSCODE: &str = "transcode";
+pub const KIND_STATUS_CHECK: &str = "status_check";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActiveJob {
+    pub id: String,
+    pub kind: String,
+    // None for jobs that span many items, such as a status check
+    pub item_id: Option<String>,
+    pub started_at: String,
+    // 0-100, when the job reports it
+    pub progress: Option<f32>,
+    pub detail: Option<String>,
+}
+
+struct Entry {
+    job: ActiveJob,
+    token: CancellationToken,
+}
+
+#[derive(Default)]
+pub struct JobRegistry {
+    jobs: Mutex<HashMap<String, Entry>>,
+}
+
+// Keeps a job listed while it runs and removes it when dropped
+pub struct JobGuard<'a> {
+    registry: &'a JobRegistry,
+    id: String,
+    token: CancellationToken,
+}
+
+impl JobRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start(
+        &self,
+        kind: &str,
+        item_id: Option<&str>,
+        token: CancellationToken,
+    ) -> JobGuard<'_> {
+        let id = Uuid::new_v4().to_string();
+        let job = ActiveJob {
+            id: id.clone(),
+            kind: kind.to_string(),
+            item_id: item_id.map(String::from),
+            started_at: timestamps::to_iso(&timestamps::now()),
+            progress: None,
+            detail: None,
+        };
+        self.jobs.lock().unwrap().insert(
+            id.clone(),
+            Entry {
+                job,
+                token: token.clone(),
+            },
+        );
+        JobGuard {
+            registry: self,
+            id,
+            token,
+        }
+    }
+
+    // Record progress for a running job; ignored once the job has finished
+    pub fn update(&self, job_id: &str, progress: Option<f32>, detail: Option<String>) {
+        if let Some(entry) = self.jobs.lock().unwrap().get_mut(job_id) {
+            entry.job.progress = progress.map(|p| p.clamp(0.0, 100.0));
+            entry.job.detail = detail;
+        }
+    }
+
+    // Running jobs, oldest first
+    pub fn list(&self) -> Vec<ActiveJob> {
+        let mut jobs: Vec<ActiveJob> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .values()
+            .map(|entry| entry.job.clone())
+            .collect();
+        jobs.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+        jobs
+    }
+
+    // Returns false if no job with this ID is running
+    pub fn cancel(&self, job_id: &str) -> bool {
+        match self.jobs.lock().unwrap().get(job_id) {
+            Some(entry) => {
+                entry.token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl JobGuard<'_> {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn update(&self, progress: Option<f32>, detail: Option<String>) {
+        self.registry.update(&self.id, progress, detail);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+}
+
+impl Drop for JobGuard<'_> {
+    fn drop(&mut self) {
+        self.registry.jobs.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,287 @@
+// Local write-ahead journal for status writes that could not reach Neon.
+// Entries are kept on disk in the app data directory so they survive a
+// restart and are replayed once the database is reachable again.
+//
+// Each item's writes are kept in order. A write only replaces the earlier ones it
+// overwrites completely (a status change replaces an earlier status change, but not
+// the local path recorded after a download), and once an item has writes waiting,
+// new writes for it queue behind them, so replaying leaves the item as the newest
+// write left it.
+//
+// Every entry has a sequence number and is completed by it, so a replay never drops a
+// write it didn't apply. Replays of one item hold its lock from lock_item, so the
+// background replay and a new write for the item can't replay it at the same time.
+// A write the database rejects (rather than one it couldn't be reached for) is moved
+// to rejected_writes.json next to the journal, so it doesn't hold up the others.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tokio::sync::{Mutex as AsyncMutex, MutexGuard};
+
+use crate::messages::ItemMessage;
+use crate::progress::Progress;
+use crate::timestamps;
+
+const JOURNAL_FILE_NAME: &str = "pending_writes.json";
+const REJECTED_FILE_NAME: &str = "rejected_writes.json";
+// Replay locks; items share them by hash, which at worst serialises two replays
+const ITEM_LOCKS: usize = 32;
+
+// A hot-path write that could not reach the database
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PendingWrite {
+    ItemStatus {
+        id: String,
+        status: String,
+        message: Option<ItemMessage>,
+    },
+    AfterDownload {
+        id: String,
+        status: String,
+        title: Option<String>,
+        local_path: Option<String>,
+        thumbnail_url: Option<String>,
+        message: Option<ItemMessage>,
+    },
+    EncodingDetails {
+        id: String,
+        status: String,
+        encoding_progress: Option<i32>,
+        message: Option<ItemMessage>,
+    },
+    DownloadStats(Progress),
+}
+
+impl PendingWrite {
+    pub fn item_id(&self) -> &str {
+        match self {
+            PendingWrite::ItemStatus { id, .. } => id,
+            PendingWrite::AfterDownload { id, .. } => id,
+            PendingWrite::EncodingDetails { id, .. } => id,
+            PendingWrite::DownloadStats(progress) => &progress.item_id,
+        }
+    }
+
+    // Whether this write sets everything `older` set, so `older` can be dropped
+    fn supersedes(&self, older: &PendingWrite) -> bool {
+        if self.item_id() != older.item_id() {
+            return false;
+        }
+        match self {
+            PendingWrite::ItemStatus { .. } => matches!(older, PendingWrite::ItemStatus { .. }),
+            PendingWrite::AfterDownload { .. } => matches!(
+                older,
+                PendingWrite::ItemStatus { .. } | PendingWrite::AfterDownload { .. }
+            ),
+            PendingWrite::EncodingDetails { .. } => matches!(
+                older,
+                PendingWrite::ItemStatus { .. } | PendingWrite::EncodingDetails { .. }
+            ),
+            PendingWrite::DownloadStats(_) => matches!(older, PendingWrite::DownloadStats(_)),
+        }
+    }
+}
+
+// A write waiting in the journal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub seq: u64,
+    pub write: PendingWrite,
+}
+
+// A journaled write the database refused, kept for inspection
+#[derive(Debug, Serialize, Deserialize)]
+struct RejectedWrite {
+    write: PendingWrite,
+    error: String,
+    #[serde(with = "timestamps::iso8601")]
+    rejected_at: DateTime<Utc>,
+}
+
+// Where a journaled write went
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteOutcome {
+    // Stored in the database
+    Written,
+    // Kept in the journal until the database can be reached
+    Deferred,
+}
+
+pub struct WriteJournal {
+    path: Option<PathBuf>,
+    entries: Mutex<Vec<JournalEntry>>,
+    item_locks: Vec<AsyncMutex<()>>,
+}
+
+impl WriteJournal {
+    // Open the journal in `dir`, loading any entries left over from a previous run.
+    // Without a directory the journal is kept in memory only.
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|d| d.join(JOURNAL_FILE_NAME));
+
+        let content = path.as_ref().and_then(|p| fs::read_to_string(p).ok());
+        let entries = content
+            .as_deref()
+            .and_then(|content| {
+                serde_json::from_str::<Vec<JournalEntry>>(content)
+                    .ok()
+                    // Journals written before entries were numbered
+                    .or_else(|| {
+                        serde_json::from_str::<Vec<PendingWrite>>(content)
+                            .ok()
+                            .map(|writes| {
+                                writes
+                                    .into_iter()
+                                    .zip(1..)
+                                    .map(|(write, seq)| JournalEntry { seq, write })
+                                    .collect()
+                            })
+                    })
+            })
+            .unwrap_or_default();
+
+        if !entries.is_empty() {
+            println!(
+                "[Journal] Loaded {} pending write(s) from previous session",
+                entries.len()
+            );
+        }
+
+        WriteJournal {
+            path,
+            entries: Mutex::new(entries),
+            item_locks: (0..ITEM_LOCKS).map(|_| AsyncMutex::new(())).collect(),
+        }
+    }
+
+    // Held while the item's writes are replayed or a new one is made
+    pub async fn lock_item(&self, id: &str) -> MutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        self.item_locks[hasher.finish() as usize % ITEM_LOCKS]
+            .lock()
+            .await
+    }
+
+    // Record a write behind the item's earlier ones, dropping those it overwrites.
+    // Returns the write's sequence number.
+    pub fn record(&self, write: PendingWrite) -> u64 {
+        let mut entries = self.entries.lock().unwrap();
+        let seq = entries.iter().map(|entry| entry.seq).max().unwrap_or(0) + 1;
+        entries.retain(|older| !write.supersedes(&older.write));
+        entries.push(JournalEntry { seq, write });
+        self.persist(&entries);
+        seq
+    }
+
+    pub fn has_item(&self, id: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|entry| entry.write.item_id() == id)
+    }
+
+    // The item's waiting writes, oldest first
+    pub fn item_writes(&self, id: &str) -> Vec<JournalEntry> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| entry.write.item_id() == id)
+            .cloned()
+            .collect()
+    }
+
+    // Items with waiting writes, in the order of their oldest one
+    pub fn item_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = Vec::new();
+        for entry in self.entries.lock().unwrap().iter() {
+            let id = entry.write.item_id();
+            if !ids.iter().any(|known| known == id) {
+                ids.push(id.to_string());
+            }
+        }
+        ids
+    }
+
+    // Drop a write once it has reached the database
+    pub fn complete(&self, seq: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if let Some(index) = entries.iter().position(|entry| entry.seq == seq) {
+            entries.remove(index);
+            self.persist(&entries);
+        }
+    }
+
+    // Move a write the database refused out of the way, into rejected_writes.json
+    pub fn reject(&self, seq: u64, error: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let index = match entries.iter().position(|entry| entry.seq == seq) {
+            Some(index) => index,
+            None => return,
+        };
+        let entry = entries.remove(index);
+        self.persist(&entries);
+
+        let path = match &self.path {
+            Some(path) => path.with_file_name(REJECTED_FILE_NAME),
+            None => return,
+        };
+        let mut rejected: Vec<RejectedWrite> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        rejected.push(RejectedWrite {
+            write: entry.write,
+            error: error.to_string(),
+            rejected_at: timestamps::now(),
+        });
+        let result = serde_json::to_string_pretty(&rejected)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("[Journal] Failed to save rejected write: {}", e);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    fn persist(&self, entries: &[JournalEntry]) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        if entries.is_empty() {
+            if path.exists() {
+                if let Err(e) = fs::remove_file(path) {
+                    eprintln!("[Journal] Failed to remove empty journal: {}", e);
+                }
+            }
+            return;
+        }
+
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        // Write to a temp file first so a crash never leaves a half-written journal
+        let tmp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_string(entries)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&tmp_path, json).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_path, path).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            eprintln!("[Journal] Failed to persist pending writes: {}", e);
+        }
+    }
+}
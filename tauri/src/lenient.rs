@@ -0,0 +1,83 @@
+// Lenient deserializers for provider responses. Filemoon is not consistent about
+// types: numbers arrive as strings and strings as numbers, fields go missing, and a
+// list sometimes comes back as a single object. The Filemoon response structs read
+// their fields through these, with #[serde(default, deserialize_with = "...")], so
+// one odd field can't fail the whole response. Values that still make no sense
+// become None (or an empty string) and the caller treats them as unknown.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value as JsonValue;
+
+pub fn text(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(s) => Some(s.trim().to_string()),
+        JsonValue::Number(n) => Some(n.to_string()),
+        JsonValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+fn integer(value: &JsonValue) -> Option<i64> {
+    match value {
+        JsonValue::Number(n) => n.as_i64().or_else(|| n.as_f64().map(|f| f.round() as i64)),
+        JsonValue::String(s) => {
+            let s = s.trim().trim_end_matches('%');
+            s.parse::<i64>()
+                .ok()
+                .or_else(|| s.parse::<f64>().ok().map(|f| f.round() as i64))
+        }
+        JsonValue::Bool(b) => Some(*b as i64),
+        _ => None,
+    }
+}
+
+// Any scalar as text; missing or null is ""
+pub fn string<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(text(&JsonValue::deserialize(deserializer)?).unwrap_or_default())
+}
+
+// Any scalar as text; missing, null or blank is None
+pub fn opt_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(text(&JsonValue::deserialize(deserializer)?).filter(|s| !s.is_empty()))
+}
+
+// A number, numeric string ("200", "91%") or boolean
+pub fn opt_i32<'de, D>(deserializer: D) -> Result<Option<i32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(integer(&JsonValue::deserialize(deserializer)?).and_then(|n| i32::try_from(n).ok()))
+}
+
+pub fn opt_u16<'de, D>(deserializer: D) -> Result<Option<u16>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(integer(&JsonValue::deserialize(deserializer)?).and_then(|n| u16::try_from(n).ok()))
+}
+
+// A list, or a single object standing in for a one-entry list. Entries that can't
+// be read are dropped; anything else is None.
+pub fn opt_list<'de, D, T>(deserializer: D) -> Result<Option<Vec<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    Ok(match JsonValue::deserialize(deserializer)? {
+        JsonValue::Array(entries) => Some(
+            entries
+                .into_iter()
+                .filter_map(|entry| serde_json::from_value(entry).ok())
+                .collect(),
+        ),
+        entry @ JsonValue::Object(_) => serde_json::from_value(entry).ok().map(|e| vec![e]),
+        _ => None,
+    })
+}
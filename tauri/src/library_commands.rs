@@ -0,0 +1,565 @@
+// Commands over the archived library: duplicates, bulk edits, provenance, timelines,
+// share links and page captures.
+
+use std::fs;
+use std::path::Path;
+use tauri::State;
+
+use crate::bulk_edit::{BulkEdit, BulkEditResult, BulkFilter};
+use crate::confirmations::{self, Confirmed, Plan};
+use crate::db::{HookRun, ProvenanceRecord, QueueItem, UploadRecord};
+use crate::duplicates::{self, DuplicateGroup};
+use crate::processor::active_source_url;
+use crate::provenance::{self, ProvenanceManifest};
+use crate::shares::{self, ShareToken, SharedItem};
+use crate::timeline::{self, ItemTimeline};
+use crate::warc::{self, PageCapture};
+use crate::{filemoon, http, local_api, long_paths, timestamps, tools, AppState, Response};
+
+// Group the user's items that look like the same video
+#[tauri::command]
+pub async fn find_duplicates(
+    user_id: String,
+    by: Option<Vec<String>>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<DuplicateGroup>>, String> {
+    let criteria = duplicates::parse_criteria(by)?;
+    let items = app_state
+        .db
+        .get_queue_items(&user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve queue items: {}", e))?;
+    let provenance = app_state
+        .db
+        .get_user_provenance(&user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve provenance: {}", e))?;
+
+    let groups = duplicates::find(items, &provenance, &criteria);
+    Ok(Response {
+        success: true,
+        message: format!("Found {} group(s) of duplicates", groups.len()),
+        data: Some(groups),
+    })
+}
+
+// Keep one item and delete its duplicates; their source URLs become its mirrors.
+// Two-step like clear_completed_items.
+#[tauri::command]
+pub async fn merge_duplicates(
+    keep_id: String,
+    duplicate_ids: Vec<String>,
+    user_id: String,
+    confirm_token: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Confirmed<u64>>, String> {
+    let duplicate_ids: Vec<String> = duplicate_ids
+        .into_iter()
+        .filter(|id| *id != keep_id)
+        .collect();
+    if duplicate_ids.is_empty() {
+        return Err("No duplicates to merge".to_string());
+    }
+
+    let owned = |item: &QueueItem| item.user_id.as_deref() == Some(user_id.as_str());
+    let keep = match app_state.db.get_item_by_id(&keep_id).await {
+        Ok(Some(item)) if owned(&item) => item,
+        Ok(_) => return Err(format!("Item {} not found.", keep_id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let mut duplicate_items = Vec::new();
+    for id in &duplicate_ids {
+        match app_state.db.get_item_by_id(id).await {
+            Ok(Some(item)) if owned(&item) => duplicate_items.push(item),
+            Ok(_) => return Err(format!("Item {} not found.", id)),
+            Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+        }
+    }
+    duplicates::check_mergeable(&duplicate_items)?;
+
+    let mirror_urls = duplicates::merged_mirror_urls(&keep, &duplicate_items);
+    let summary = format!(
+        "Delete {} duplicate(s) of '{}' and keep their URLs as its mirrors",
+        duplicate_items.len(),
+        keep.title.as_deref().unwrap_or(&keep.url)
+    );
+    let mut items = duplicate_items;
+    items.push(keep);
+    let plan = Plan::new(&(&keep_id, &duplicate_ids), items, summary);
+
+    let Some(token) = confirm_token else {
+        let confirmation =
+            app_state
+                .confirmations
+                .issue(confirmations::ACTION_MERGE_DUPLICATES, &user_id, &plan);
+        return Ok(Response {
+            success: true,
+            message: confirmation.summary.clone(),
+            data: Some(Confirmed::pending(confirmation)),
+        });
+    };
+    app_state.confirmations.redeem(
+        &token,
+        confirmations::ACTION_MERGE_DUPLICATES,
+        &user_id,
+        &plan,
+    )?;
+
+    match app_state
+        .db
+        .merge_duplicate_items(&keep_id, &duplicate_ids, &mirror_urls, &user_id)
+        .await
+    {
+        Ok(deleted) => Ok(Response {
+            success: true,
+            message: format!(
+                "Merged {} duplicate(s) into item {} ({} mirror URL(s))",
+                deleted,
+                keep_id,
+                mirror_urls.len()
+            ),
+            data: Some(Confirmed::done(deleted)),
+        }),
+        Err(e) => Err(format!("Failed to merge duplicates: {}", e)),
+    }
+}
+
+// What a bulk edit would change, without changing anything (see bulk_edit.rs)
+#[tauri::command]
+pub async fn preview_bulk_edit(
+    user_id: String,
+    filter: BulkFilter,
+    edit: BulkEdit,
+    app_state: State<'_, AppState>,
+) -> Result<Response<BulkEditResult>, String> {
+    run_bulk_edit(&app_state, &user_id, &filter, &edit, true).await
+}
+
+// Apply a bulk edit to every matching item in one statement. Two-step like
+// clear_completed_items; the confirmation is for the items the filter matches now.
+#[tauri::command]
+pub async fn apply_bulk_edit(
+    user_id: String,
+    filter: BulkFilter,
+    edit: BulkEdit,
+    confirm_token: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Confirmed<BulkEditResult>>, String> {
+    let filter = filter.normalized();
+    let edit = edit.normalized()?;
+    let items = app_state
+        .db
+        .find_items(&user_id, &filter)
+        .await
+        .map_err(|e| e.to_string())?;
+    let summary = format!("{} on {} item(s)", edit.describe(), items.len());
+    let plan = Plan::new(&(&filter, &edit), items, summary);
+
+    let Some(token) = confirm_token else {
+        let confirmation =
+            app_state
+                .confirmations
+                .issue(confirmations::ACTION_BULK_EDIT, &user_id, &plan);
+        return Ok(Response {
+            success: true,
+            message: confirmation.summary.clone(),
+            data: Some(Confirmed::pending(confirmation)),
+        });
+    };
+    app_state
+        .confirmations
+        .redeem(&token, confirmations::ACTION_BULK_EDIT, &user_id, &plan)?;
+
+    let response = run_bulk_edit(&app_state, &user_id, &filter, &edit, false).await?;
+    Ok(Response {
+        success: response.success,
+        message: response.message,
+        data: response.data.map(Confirmed::done),
+    })
+}
+
+async fn run_bulk_edit(
+    app_state: &AppState,
+    user_id: &str,
+    filter: &BulkFilter,
+    edit: &BulkEdit,
+    dry_run: bool,
+) -> Result<Response<BulkEditResult>, String> {
+    let edit = edit.normalized()?;
+    let result = app_state
+        .db
+        .bulk_edit(user_id, &filter.normalized(), &edit, dry_run)
+        .await
+        .map_err(|e| format!("Database error running bulk edit: {}", e))?;
+
+    Ok(Response {
+        success: true,
+        message: format!(
+            "{} ({}): {} of {} matching item(s)",
+            if dry_run { "Would change" } else { "Changed" },
+            edit.describe(),
+            result.changed,
+            result.matched
+        ),
+        data: Some(result),
+    })
+}
+
+// Embed/player/download links for an uploaded item
+#[tauri::command]
+pub async fn get_embed_info(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<filemoon::EmbedInfo>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+
+    let filecode = match item.filemoon_url.as_deref() {
+        Some(code) if !code.is_empty() => code.to_string(),
+        _ => return Err(format!("Item {} has not been uploaded yet.", id)),
+    };
+
+    let api_key = match item.user_id.as_deref() {
+        Some(user_id) => app_state
+            .db
+            .get_settings(user_id)
+            .await
+            .ok()
+            .and_then(|s| s.filemoon_api_key),
+        None => None,
+    };
+
+    let mut info = filemoon::embed_info(&id, &filecode, api_key.as_deref()).await;
+    info.short_url = item.short_url;
+    Ok(Response {
+        success: true,
+        message: "Embed info retrieved successfully".to_string(),
+        data: Some(info),
+    })
+}
+
+// Chain-of-custody manifest for an item: source, capture details, checksum and destinations
+#[tauri::command]
+pub async fn get_provenance(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<ProvenanceManifest>, String> {
+    let manifest = load_provenance_manifest(&id, &app_state).await?;
+    Ok(Response {
+        success: true,
+        message: "Provenance retrieved successfully".to_string(),
+        data: Some(manifest),
+    })
+}
+
+// Everything that happened to an item, in order, for the item detail view
+#[tauri::command]
+pub async fn get_item_timeline(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<ItemTimeline>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+
+    let timeline = timeline::build(&app_state, item).await?;
+    Ok(Response {
+        success: true,
+        message: format!("Retrieved {} timeline event(s)", timeline.events.len()),
+        data: Some(timeline),
+    })
+}
+
+// Hook script runs for an item, newest first, with their exit codes and output
+#[tauri::command]
+pub async fn get_hook_runs(
+    item_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<HookRun>>, String> {
+    match app_state.db.get_hook_runs(&item_id).await {
+        Ok(runs) => Ok(Response {
+            success: true,
+            message: format!("Retrieved {} hook run(s)", runs.len()),
+            data: Some(runs),
+        }),
+        Err(e) => Err(format!("Database error retrieving hook runs: {}", e)),
+    }
+}
+
+// Where each of an item's upload destinations stands: the main upload target and
+// its mirrors (see mirrors.rs)
+#[tauri::command]
+pub async fn get_uploads(
+    item_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<UploadRecord>>, String> {
+    match app_state.db.get_uploads(&item_id).await {
+        Ok(uploads) => Ok(Response {
+            success: true,
+            message: format!("Retrieved {} upload(s)", uploads.len()),
+            data: Some(uploads),
+        }),
+        Err(e) => Err(format!("Database error retrieving uploads: {}", e)),
+    }
+}
+
+fn share_secret_for(app_handle: &tauri::AppHandle) -> Result<Vec<u8>, String> {
+    let dir = app_handle
+        .path_resolver()
+        .app_data_dir()
+        .ok_or_else(|| "Could not determine app data directory".to_string())?;
+    shares::load_secret(&dir)
+}
+
+pub async fn resolve_share(
+    token: &str,
+    app_handle: &tauri::AppHandle,
+    app_state: &State<'_, AppState>,
+) -> Result<SharedItem, String> {
+    let (item_id, expires_at) = shares::verify(&share_secret_for(app_handle)?, token)?;
+    match app_state.db.get_item_by_id(&item_id).await {
+        Ok(Some(item)) => shares::shared_item(&item, expires_at),
+        Ok(None) => Err("The shared item no longer exists".to_string()),
+        Err(e) => Err(format!("Database error retrieving item: {}", e)),
+    }
+}
+
+// Signed token for sharing one uploaded item, valid for `expires_in_hours` (default 72)
+#[tauri::command]
+pub async fn create_share_token(
+    id: String,
+    expires_in_hours: Option<i64>,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<ShareToken>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    // Refuse up front rather than hand out a token that resolves to nothing
+    let token = shares::create(&share_secret_for(&app_handle)?, &id, expires_in_hours)?;
+    shares::shared_item(&item, token.expires_at)?;
+
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Share link valid until {}",
+            timestamps::to_iso(&token.expires_at)
+        ),
+        data: Some(token),
+    })
+}
+
+// The player links and metadata a share token grants access to
+#[tauri::command]
+pub async fn resolve_share_token(
+    token: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<SharedItem>, String> {
+    let shared = resolve_share(&token, &app_handle, &app_state).await?;
+    Ok(Response {
+        success: true,
+        message: "Share token is valid".to_string(),
+        data: Some(shared),
+    })
+}
+
+// Write a standalone HTML page for a share token that can be sent to someone. It
+// leads to the local API's share page, so others can only open it with local_api_lan.
+#[tauri::command]
+pub async fn export_share_link(
+    token: String,
+    output_path: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let shared = resolve_share(&token, &app_handle, &app_state).await?;
+    let share_url = local_api::share_url(&app_state, &token)
+        .await
+        .ok_or_else(|| {
+            "Share links open through the local API; turn on local_api_enabled first".to_string()
+        })?;
+    fs::write(&output_path, shares::link_file_html(&shared, &share_url))
+        .map_err(|e| format!("Failed to write share link file: {}", e))?;
+
+    Ok(Response {
+        success: true,
+        message: format!("Share link to {} exported to {}", share_url, output_path),
+        data: Some(output_path),
+    })
+}
+
+// Capture (or recapture) an item's watch page and thumbnail into a WARC next to its
+// downloaded file, for items downloaded before capture_page was switched on
+#[tauri::command]
+pub async fn capture_page(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<PageCapture>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let local_path = item
+        .local_path
+        .clone()
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| "Item has no downloaded file to store the capture next to".to_string())?;
+    let page_url = app_state
+        .db
+        .get_provenance(&id)
+        .await
+        .map_err(|e| format!("Database error retrieving provenance: {}", e))?
+        .and_then(|record| record.webpage_url)
+        .unwrap_or_else(|| active_source_url(&item));
+
+    let capture = warc::capture(
+        &page_url,
+        item.thumbnail_url.as_deref(),
+        Path::new(&local_path),
+    )
+    .await?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Captured {} resource(s) to {}",
+            capture.captured.len(),
+            capture.warc_path
+        ),
+        data: Some(capture),
+    })
+}
+
+// Write the provenance manifest to a JSON file so it can travel with the archived copy
+#[tauri::command]
+pub async fn export_provenance(
+    id: String,
+    output_path: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let manifest = load_provenance_manifest(&id, &app_state).await?;
+    let content = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize provenance manifest: {}", e))?;
+    fs::write(&output_path, content)
+        .map_err(|e| format!("Failed to write provenance manifest: {}", e))?;
+
+    Ok(Response {
+        success: true,
+        message: format!("Provenance manifest exported to {}", output_path),
+        data: Some(output_path),
+    })
+}
+
+async fn load_provenance_manifest(
+    id: &str,
+    app_state: &State<'_, AppState>,
+) -> Result<ProvenanceManifest, String> {
+    let item = match app_state.db.get_item_by_id(id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let record = app_state
+        .db
+        .get_provenance(id)
+        .await
+        .map_err(|e| format!("Database error retrieving provenance: {}", e))?;
+
+    Ok(provenance::build_manifest(&item, record.as_ref()))
+}
+
+// Gather capture details for a freshly downloaded item. Hashing runs off the async
+// runtime since files can be several GB.
+pub async fn build_provenance_record(
+    item_id: &str,
+    local_path: Option<&str>,
+    webpage_url: Option<String>,
+    extractor: Option<String>,
+    video_id: Option<String>,
+    format: String,
+) -> ProvenanceRecord {
+    let (sha256, file_size) = match local_path {
+        Some(path) => {
+            let path = long_paths::extended(Path::new(path));
+            match tokio::task::spawn_blocking(move || provenance::sha256_file(&path)).await {
+                Ok(Ok((digest, size))) => (Some(digest), Some(size as i64)),
+                Ok(Err(e)) => {
+                    eprintln!("Failed to hash downloaded file for {}: {}", item_id, e);
+                    (None, None)
+                }
+                Err(e) => {
+                    eprintln!("Hashing task failed for {}: {}", item_id, e);
+                    (None, None)
+                }
+            }
+        }
+        None => (None, None),
+    };
+
+    ProvenanceRecord {
+        item_id: item_id.to_string(),
+        webpage_url,
+        extractor,
+        video_id,
+        captured_at: timestamps::now(),
+        ytdlp_version: tools::detect_ytdlp().await.version,
+        format: Some(format),
+        file_name: local_path.and_then(|p| {
+            Path::new(p)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        }),
+        file_size,
+        sha256,
+    }
+}
+
+#[tauri::command]
+pub async fn debug_check_status(filecode: String, api_key: String) -> Result<Response<String>, String> {
+    println!("=== MANUAL FILEMOON STATUS CHECK ===");
+    println!("Checking filecode: {}", filecode);
+
+    let client = http::client();
+    let url = format!("{}/encoding/status", filemoon::api_base());
+
+    match client
+        .get(&url)
+        .query(&[("key", &api_key), ("file_code", &filecode)])
+        .send()
+        .await
+    {
+        Ok(response) => {
+            let status = response.status();
+            match response.text().await {
+                Ok(raw_text) => {
+                    println!("Raw Filemoon API Response: {}", raw_text);
+                    println!("HTTP Status: {}", status);
+
+                    return Ok(Response {
+                        success: true,
+                        message: format!("API Response (HTTP {})", status),
+                        data: Some(raw_text),
+                    });
+                }
+                Err(e) => {
+                    let error_msg = format!("Failed to read response: {}", e);
+                    println!("{}", error_msg);
+                    return Err(error_msg);
+                }
+            }
+        }
+        Err(e) => {
+            let error_msg = format!("Request failed: {}", e);
+            println!("{}", error_msg);
+            return Err(error_msg);
+        }
+    }
+}
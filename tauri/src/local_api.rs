@@ -0,0 +1,602 @@
+// A small REST server on 127.0.0.1 for browser extensions and bookmarklets, so the
+// page being watched can be queued while the app runs in the background. It runs
+// while `local_api_enabled` is "true", on `local_api_port` (DEFAULT_PORT when unset),
+// and every endpoint but /api/ping needs `local_api_token`, sent as
+// "Authorization: Bearer <token>" or X-PermaVid-Token. Items are added for, and
+// listed from, the active user (see settings_watch.rs), whose settings the server
+// follows: a save restarts it on the new port or with the new token at once. With
+// `local_api_lan` "true" it listens on every interface, so a phone on the LAN can
+// open the dashboard.
+//
+// The dashboard also opens with its own token, derived from local_api_token, which
+// grants nothing but the dashboard. That one goes in a bookmark as ?token=, so a URL
+// seen on the LAN or left in a phone's history can't be used to queue items.
+//
+//   GET  /api/ping              the app and its version; no token needed
+//   GET  /api/queue             the user's items; ?status=queued,failed&limit=50
+//   POST /api/queue             {"url": "...", "title": "..."} or {"urls": [...]}
+//   GET  /api/queue/<id>        one item and its status
+//   GET  /api/dashboard         queue and gallery status (see dashboard.rs)
+//   GET  /dashboard             the same as a page; both take the dashboard token,
+//                               the page also as ?token=
+//   GET  /share/<token>         a shared item's player (see shares.rs); the share
+//                               token stands in for the API token
+//   GET  /api/share/<token>     the same as JSON
+//
+// Answers use the same {success, message, data} shape as the commands. Requests
+// whose Host isn't the loopback address (or, on the LAN, an IP address) are refused,
+// so a web page can't reach the server through DNS rebinding. Responses allow any origin, since the token, not
+// cookies, is what grants access. HTTP itself is handled by hyper's HTTP/1 server,
+// the one reqwest is built on, so no web framework is pulled in for a few routes.
+
+use hyper::body::HttpBody;
+use hyper::server::conn::Http;
+use hyper::service::service_fn;
+use hyper::{Body, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::{IpAddr, UdpSocket};
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio::time::timeout;
+
+use crate::bulk_add;
+use crate::dashboard;
+use crate::db::{AppSettings, QueueItem};
+use crate::library_commands::resolve_share;
+use crate::{settings_watch, shares, urls, AppState, Response};
+
+pub const DEFAULT_PORT: u16 = 47615;
+// Shortest local_api_token accepted
+pub const MIN_TOKEN_LEN: usize = 16;
+// Hex digits of the dashboard token
+const DASHBOARD_TOKEN_LEN: usize = 32;
+
+// How often the server checks whether the active user changed, between saves
+const RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Config {
+    port: u16,
+    token: String,
+    user_id: String,
+    // Listening on every interface, not only loopback
+    lan: bool,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+#[derive(Deserialize)]
+struct AddRequest {
+    url: Option<String>,
+    title: Option<String>,
+    urls: Option<Vec<String>>,
+}
+
+fn enabled(settings: &AppSettings) -> bool {
+    settings.local_api_enabled.as_deref() == Some("true")
+}
+
+fn token_of(settings: &AppSettings) -> String {
+    settings
+        .local_api_token
+        .as_deref()
+        .unwrap_or_default()
+        .trim()
+        .to_string()
+}
+
+fn port_of(settings: &AppSettings) -> Result<u16, String> {
+    match settings
+        .local_api_port
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    {
+        Some(value) => match value.parse::<u16>() {
+            Ok(port) if port >= 1024 => Ok(port),
+            _ => Err(format!(
+                "Invalid local_api_port '{}'; use a port from 1024 to 65535",
+                value
+            )),
+        },
+        None => Ok(DEFAULT_PORT),
+    }
+}
+
+// Check the local API settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    port_of(settings)?;
+    if enabled(settings) && token_of(settings).len() < MIN_TOKEN_LEN {
+        return Err(format!(
+            "The local API needs a local_api_token of at least {} characters",
+            MIN_TOKEN_LEN
+        ));
+    }
+    Ok(())
+}
+
+// Whether the active user's settings have the server on
+pub async fn is_enabled(app_state: &AppState) -> bool {
+    wanted(app_state).await.is_some()
+}
+
+// This machine's address on the LAN: the source of a route to the outside. Connecting
+// a UDP socket sends nothing.
+fn lan_address() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+// The read-only token that opens the dashboard and nothing else
+fn dashboard_token(token: &str) -> String {
+    let mut derived = shares::signature(token.as_bytes(), "permavid-dashboard");
+    derived.truncate(DASHBOARD_TOKEN_LEN);
+    derived
+}
+
+// Where the server answers, on the LAN address when it listens there
+fn base_url(config: &Config) -> String {
+    let host = match lan_address() {
+        Some(address) if config.lan => address.to_string(),
+        _ => "127.0.0.1".to_string(),
+    };
+    format!("http://{}:{}", host, config.port)
+}
+
+// Where a share token opens; None while the server is off
+pub async fn share_url(app_state: &AppState, token: &str) -> Option<String> {
+    let config = wanted(app_state).await?;
+    Some(format!(
+        "{}{}{}",
+        base_url(&config),
+        shares::PAGE_PREFIX,
+        token
+    ))
+}
+
+// The dashboard's bookmarkable address, with its read-only token; None while the
+// server is off
+pub async fn dashboard_url(app_state: &AppState) -> Option<String> {
+    let config = wanted(app_state).await?;
+    Some(format!(
+        "{}{}?token={}",
+        base_url(&config),
+        dashboard::PAGE_PATH,
+        dashboard_token(&config.token)
+    ))
+}
+
+// The server the active user's settings ask for; None while it should be off
+async fn wanted(app_state: &AppState) -> Option<Config> {
+    let user_id = app_state
+        .settings_watch
+        .active_user()
+        .unwrap_or_else(|| "local-user".to_string());
+    let settings = app_state.db.get_settings(&user_id).await.ok()?;
+    if !enabled(&settings) || validate(&settings).is_err() {
+        return None;
+    }
+    Some(Config {
+        port: port_of(&settings).ok()?,
+        token: token_of(&settings),
+        user_id,
+        lan: settings.local_api_lan.as_deref() == Some("true"),
+    })
+}
+
+// Start, restart or stop the server as the settings change, until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting local API supervisor...");
+    let mut changes = app_handle.state::<AppState>().settings_watch.subscribe();
+    let mut running: Option<(Config, JoinHandle<()>)> = None;
+
+    loop {
+        let config = wanted(&app_handle.state::<AppState>()).await;
+        if running.as_ref().map(|(current, _)| current) != config.as_ref() {
+            if let Some((current, server)) = running.take() {
+                server.abort();
+                println!("Local API on port {} stopped", current.port);
+            }
+            if let Some(config) = config {
+                let address = if config.lan { "0.0.0.0" } else { "127.0.0.1" };
+                match TcpListener::bind((address, config.port)).await {
+                    Ok(listener) => {
+                        println!("Local API listening on {}:{}", address, config.port);
+                        let server = tokio::spawn(serve(
+                            app_handle.clone(),
+                            listener,
+                            Arc::new(config.clone()),
+                        ));
+                        running = Some((config, server));
+                    }
+                    // Tried again on the next round
+                    Err(e) => {
+                        eprintln!("Local API could not listen on port {}: {}", config.port, e)
+                    }
+                }
+            }
+        }
+        settings_watch::wait(&mut changes, RECHECK_INTERVAL).await;
+    }
+}
+
+async fn serve(app_handle: AppHandle, listener: TcpListener, config: Arc<Config>) {
+    let mut http = Http::new();
+    http.http1_only(true)
+        .http1_header_read_timeout(READ_TIMEOUT);
+    while let Ok((stream, _)) = listener.accept().await {
+        let app_handle = app_handle.clone();
+        let config = config.clone();
+        let service = service_fn(move |request| {
+            let app_handle = app_handle.clone();
+            let config = config.clone();
+            async move { Ok::<_, Infallible>(handle(&app_handle, &config, request).await) }
+        });
+        let connection = http.serve_connection(stream, service);
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!("Local API connection error: {}", e);
+            }
+        });
+    }
+}
+
+// The body, refusing one larger than MAX_BODY_BYTES
+async fn read_body(mut body: Body) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk.map_err(|e| format!("Failed to read request: {}", e))?;
+        if bytes.len() + chunk.len() > MAX_BODY_BYTES {
+            return Err("Request body too large".to_string());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+async fn read_request(request: hyper::Request<Body>) -> Result<Request, String> {
+    let (parts, body) = request.into_parts();
+    let headers = parts
+        .headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+    let body = match timeout(READ_TIMEOUT, read_body(body)).await {
+        Ok(body) => body?,
+        Err(_) => return Err("Timed out reading the request".to_string()),
+    };
+    Ok(Request {
+        method: parts.method.to_string(),
+        path: parts.uri.path().trim_end_matches('/').to_string(),
+        query: reqwest::Url::parse(&format!("http://localhost{}", parts.uri))
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default(),
+        headers,
+        body,
+    })
+}
+
+async fn handle(
+    app_handle: &AppHandle,
+    config: &Config,
+    request: hyper::Request<Body>,
+) -> hyper::Response<Body> {
+    let (status, body, content_type) = match read_request(request).await {
+        // A phone browser gets a page for errors too
+        Ok(request) if is_page(&request.path) => {
+            let (status, body) = respond(app_handle, config, &request).await;
+            let body = if status == StatusCode::OK {
+                body
+            } else {
+                let message = serde_json::from_str::<Value>(&body)
+                    .ok()
+                    .and_then(|answer| answer["message"].as_str().map(str::to_string))
+                    .unwrap_or_default();
+                dashboard::message_page(status.canonical_reason().unwrap_or("Error"), &message)
+            };
+            (status, body, "text/html; charset=utf-8")
+        }
+        Ok(request) => {
+            let (status, body) = respond(app_handle, config, &request).await;
+            (status, body, "application/json")
+        }
+        Err(e) => (StatusCode::BAD_REQUEST, failure(&e), "application/json"),
+    };
+    let mut response = hyper::Response::builder()
+        .status(status)
+        .header("Access-Control-Allow-Origin", "*")
+        .header("Access-Control-Allow-Methods", "GET, POST, OPTIONS")
+        .header(
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type, X-PermaVid-Token",
+        )
+        .header("Access-Control-Allow-Private-Network", "true")
+        .header("Cache-Control", "no-store");
+    if !body.is_empty() {
+        response = response.header("Content-Type", content_type);
+    }
+    response.body(Body::from(body)).unwrap_or_default()
+}
+
+fn success<T: serde::Serialize>(message: String, data: T) -> String {
+    serde_json::to_string(&Response {
+        success: true,
+        message,
+        data: Some(data),
+    })
+    .unwrap_or_default()
+}
+
+fn failure(message: &str) -> String {
+    serde_json::to_string(&Response::<()> {
+        success: false,
+        message: message.to_string(),
+        data: None,
+    })
+    .unwrap_or_default()
+}
+
+// Paths answered with HTML rather than JSON
+fn is_page(path: &str) -> bool {
+    path == dashboard::PAGE_PATH || path.starts_with(shares::PAGE_PREFIX)
+}
+
+// Only the loopback names on our own port, or any IP address when serving the LAN:
+// DNS rebinding needs a host name
+fn host_allowed(request: &Request, config: &Config) -> bool {
+    let host = request
+        .headers
+        .get("host")
+        .map(String::as_str)
+        .unwrap_or_default();
+    let name = match host.rsplit_once(':') {
+        Some((name, port)) if port == config.port.to_string() => name,
+        _ => return false,
+    };
+    if name == "127.0.0.1" || name == "localhost" {
+        return true;
+    }
+    let address = name.trim_start_matches('[').trim_end_matches(']');
+    config.lan && address.parse::<IpAddr>().is_ok()
+}
+
+fn authorized(request: &Request, token: &str) -> bool {
+    let given = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or_else(|| request.headers.get("x-permavid-token").map(String::as_str))
+        .unwrap_or_default()
+        .trim();
+    if shares::same_signature(given, token) {
+        return true;
+    }
+    // The dashboard also opens with its read-only token, which may be in the query.
+    // The API token never is, so it stays out of URLs.
+    let dashboard = request.method == "GET"
+        && [dashboard::PAGE_PATH, dashboard::JSON_PATH].contains(&request.path.as_str());
+    let in_query = request
+        .query
+        .get("token")
+        .map(|value| value.trim())
+        .unwrap_or_default();
+    let dashboard_token = dashboard_token(token);
+    dashboard
+        && (shares::same_signature(given, &dashboard_token)
+            || shares::same_signature(in_query, &dashboard_token))
+}
+
+async fn respond(
+    app_handle: &AppHandle,
+    config: &Config,
+    request: &Request,
+) -> (StatusCode, String) {
+    if !host_allowed(request, config) {
+        return (
+            StatusCode::FORBIDDEN,
+            failure("Requests must be addressed to 127.0.0.1 or, on the LAN, an IP address"),
+        );
+    }
+    if request.method == "OPTIONS" {
+        return (StatusCode::NO_CONTENT, String::new());
+    }
+    if request.method == "GET" && request.path == "/api/ping" {
+        let data = json!({"app": "PermaVid", "version": env!("CARGO_PKG_VERSION")});
+        return (
+            StatusCode::OK,
+            success("PermaVid is running".to_string(), data),
+        );
+    }
+    if request.method == "GET" {
+        if let Some(token) = request.path.strip_prefix(shares::PAGE_PREFIX) {
+            return match resolve_share(token, app_handle, &app_handle.state()).await {
+                Ok(shared) => (StatusCode::OK, shares::share_page(&shared)),
+                Err(e) => (StatusCode::NOT_FOUND, failure(&e)),
+            };
+        }
+        if let Some(token) = request.path.strip_prefix(shares::JSON_PREFIX) {
+            return match resolve_share(token, app_handle, &app_handle.state()).await {
+                Ok(shared) => (
+                    StatusCode::OK,
+                    success("Share token is valid".to_string(), shared),
+                ),
+                Err(e) => (StatusCode::NOT_FOUND, failure(&e)),
+            };
+        }
+    }
+    if !authorized(request, &config.token) {
+        return (
+            StatusCode::UNAUTHORIZED,
+            failure("Missing or wrong API token"),
+        );
+    }
+
+    let app_state = app_handle.state::<AppState>();
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/api/queue") => list_items(&app_state, config, request).await,
+        ("POST", "/api/queue") => add_items(&app_state, config, request).await,
+        ("GET", path) if path.starts_with("/api/queue/") => {
+            get_item(&app_state, config, &path["/api/queue/".len()..]).await
+        }
+        ("GET", dashboard::JSON_PATH) | ("GET", dashboard::PAGE_PATH) => {
+            dashboard_status(&app_state, config, &request.path).await
+        }
+        _ => (StatusCode::NOT_FOUND, failure("No such endpoint")),
+    }
+}
+
+async fn list_items(
+    app_state: &AppState,
+    config: &Config,
+    request: &Request,
+) -> (StatusCode, String) {
+    let statuses: Vec<String> = request
+        .query
+        .get("status")
+        .map(|value| {
+            value
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let limit = request
+        .query
+        .get("limit")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .min(MAX_LIST_LIMIT);
+
+    let mut items = match app_state.db.get_queue_items(&config.user_id).await {
+        Ok(items) => items,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, failure(&e.to_string())),
+    };
+    items.retain(|item| statuses.is_empty() || statuses.contains(&item.status));
+    items.truncate(limit);
+    app_state.live_progress.overlay(&mut items);
+    (
+        StatusCode::OK,
+        success(format!("{} item(s)", items.len()), items),
+    )
+}
+
+async fn dashboard_status(
+    app_state: &AppState,
+    config: &Config,
+    path: &str,
+) -> (StatusCode, String) {
+    let mut items = match app_state.db.get_queue_items(&config.user_id).await {
+        Ok(items) => items,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, failure(&e.to_string())),
+    };
+    app_state.live_progress.overlay(&mut items);
+    let status = dashboard::status(items);
+    if path == dashboard::PAGE_PATH {
+        return (StatusCode::OK, dashboard::page(&status));
+    }
+    (
+        StatusCode::OK,
+        success(format!("{} item(s)", status.total), status),
+    )
+}
+
+async fn add_items(
+    app_state: &AppState,
+    config: &Config,
+    request: &Request,
+) -> (StatusCode, String) {
+    let add: AddRequest = match serde_json::from_slice(&request.body) {
+        Ok(add) => add,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                failure(&format!("Invalid JSON: {}", e)),
+            )
+        }
+    };
+
+    if let Some(list) = add.urls {
+        return match bulk_add::add(app_state, &list, &config.user_id).await {
+            Ok(results) => {
+                let added = results
+                    .iter()
+                    .filter(|r| r.result == bulk_add::RESULT_ADDED)
+                    .count();
+                (
+                    StatusCode::OK,
+                    success(
+                        format!("{} of {} URL(s) added to queue", added, results.len()),
+                        results,
+                    ),
+                )
+            }
+            Err(e) => (StatusCode::BAD_REQUEST, failure(&e)),
+        };
+    }
+
+    let url = match add.url.as_deref().map(str::trim).filter(|u| !u.is_empty()) {
+        Some(url) => urls::normalize_url(url),
+        None => {
+            return (
+                StatusCode::BAD_REQUEST,
+                failure("Give a url or a list of urls"),
+            )
+        }
+    };
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        return (
+            StatusCode::BAD_REQUEST,
+            failure("Only http(s) URLs can be queued"),
+        );
+    }
+    let mut item = QueueItem::queued(&url, &config.user_id);
+    item.title = add.title.filter(|t| !t.trim().is_empty());
+    match app_state.db.add_queue_item(&item).await {
+        Ok(id) => {
+            app_state.queue_wakeup.wake();
+            (
+                StatusCode::CREATED,
+                success(
+                    "Item added to queue successfully".to_string(),
+                    json!({"item_id": id, "url": url}),
+                ),
+            )
+        }
+        // Already queued or archived
+        Err(e) => (StatusCode::CONFLICT, failure(&e.to_string())),
+    }
+}
+
+async fn get_item(app_state: &AppState, config: &Config, item_id: &str) -> (StatusCode, String) {
+    match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) if item.user_id.as_deref() == Some(config.user_id.as_str()) => {
+            let mut items = vec![item];
+            app_state.live_progress.overlay(&mut items);
+            let item = items.remove(0);
+            (
+                StatusCode::OK,
+                success(format!("Item is {}", item.status), item),
+            )
+        }
+        Ok(_) => (StatusCode::NOT_FOUND, failure("No such item")),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, failure(&e.to_string())),
+    }
+}
@@ -0,0 +1,75 @@
+// Windows path length handling. Win32 file APIs reject paths of MAX_PATH (260)
+// characters or more unless they carry the \\?\ prefix, so a deep download directory
+// can break the move/upload stage even though yt-dlp wrote the file. File operations
+// on downloaded media go through `extended`, and yt-dlp is told to trim output names
+// so the files it writes stay under the limit in the first place.
+
+use std::path::{Component, Path, PathBuf};
+
+pub const WINDOWS_MAX_PATH: usize = 260;
+// CreateDirectory leaves room for an 8.3 file name inside the directory
+const WINDOWS_MAX_DIR_PATH: usize = WINDOWS_MAX_PATH - 12;
+
+const VERBATIM_PREFIX: &str = r"\\?\";
+const VERBATIM_UNC_PREFIX: &str = r"\\?\UNC\";
+
+// Room for what yt-dlp appends to an output name: ".f137", ".en-orig.vtt", ".info.json",
+// ".part", plus the terminating NUL
+const SUFFIX_RESERVE: usize = 32;
+// Names are never trimmed below this; a directory that deep relies on the prefix instead
+const MIN_NAME_LEN: usize = 16;
+
+// The path as it should be handed to std::fs. On Windows, long absolute paths get the
+// \\?\ prefix; everything else (and every path on other platforms) is returned as is.
+pub fn extended(path: &Path) -> PathBuf {
+    if cfg!(windows) {
+        to_verbatim(path)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+fn to_verbatim(path: &Path) -> PathBuf {
+    let raw = path.to_string_lossy();
+    // Verbatim paths are not normalised, so "." and ".." would be taken literally
+    let has_dots = path
+        .components()
+        .any(|c| matches!(c, Component::CurDir | Component::ParentDir));
+    if raw.starts_with(VERBATIM_PREFIX)
+        || raw.chars().count() < WINDOWS_MAX_DIR_PATH
+        || !path.is_absolute()
+        || has_dots
+    {
+        return path.to_path_buf();
+    }
+
+    // Separators must already be backslashes for the same reason
+    let raw = raw.replace('/', "\\");
+    match raw.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!("{}{}", VERBATIM_UNC_PREFIX, unc)),
+        None => PathBuf::from(format!("{}{}", VERBATIM_PREFIX, raw)),
+    }
+}
+
+// The path without any \\?\ prefix, for storing in the database and showing to users
+pub fn plain(path: &Path) -> String {
+    let raw = path.to_string_lossy();
+    if let Some(unc) = raw.strip_prefix(VERBATIM_UNC_PREFIX) {
+        format!(r"\\{}", unc)
+    } else if let Some(local) = raw.strip_prefix(VERBATIM_PREFIX) {
+        local.to_string()
+    } else {
+        raw.to_string()
+    }
+}
+
+// Longest output name (without extension) that keeps files in `dir` under MAX_PATH,
+// for yt-dlp's --trim-filenames. None where there is no such limit.
+pub fn name_budget(dir: &Path) -> Option<usize> {
+    if !cfg!(windows) {
+        return None;
+    }
+    let used = plain(dir).chars().count() + 1; // directory plus separator
+    let budget = WINDOWS_MAX_PATH.saturating_sub(used + SUFFIX_RESERVE);
+    Some(budget.max(MIN_NAME_LEN))
+}
@@ -0,0 +1,279 @@
+// Media-server layout for kept downloads. With `media_library_dir` set, every finished
+// download that stays on disk (delete_after_upload off) also appears in that folder
+// the way Jellyfin, Plex and Kodi expect a movie:
+//
+//   <media_library_dir>/<Title> (<Year>)/<Title> (<Year>).mp4
+//                                        <Title> (<Year>).en.vtt
+//                                        <Title> (<Year>).nfo
+//                                        poster.jpg
+//
+// The video and subtitles are hard links to the files in the download directory, so
+// they take no extra space; where a hard link isn't possible (another drive) they are
+// copied. The .nfo carries the title, plot, premiered date, channel, tags and runtime
+// from yt-dlp's info.json. Deleting a download in PermaVid leaves its library folder
+// alone, and hard-linked data stays on disk until that folder is removed as well.
+
+use chrono::{Datelike, NaiveDate};
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::db::AppSettings;
+use crate::{http, long_paths, sanitize_filename, subtitles};
+
+const POSTER_STEM: &str = "poster";
+const POSTER_TIMEOUT: Duration = Duration::from_secs(30);
+
+// What the .nfo says about a video, read from info.json while it is still on disk
+#[derive(Debug, Clone, Default)]
+pub struct LibraryMetadata {
+    pub title: String,
+    pub plot: Option<String>,
+    pub premiered: Option<NaiveDate>,
+    pub studio: Option<String>,
+    pub tags: Vec<String>,
+    pub runtime_minutes: Option<i64>,
+    pub extractor: Option<String>,
+    pub video_id: Option<String>,
+}
+
+fn info_str(info: &JsonValue, key: &str) -> Option<String> {
+    info.get(key)
+        .and_then(|v| v.as_str())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+}
+
+impl LibraryMetadata {
+    // `title` is the item's title after the fallback chain (see titles.rs)
+    pub fn from_info(title: Option<&str>, info: &JsonValue) -> Self {
+        let premiered = ["release_date", "upload_date"]
+            .iter()
+            .filter_map(|key| info_str(info, key))
+            .find_map(|date| NaiveDate::parse_from_str(&date, "%Y%m%d").ok());
+        let tags = info
+            .get("tags")
+            .and_then(|v| v.as_array())
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        LibraryMetadata {
+            title: title
+                .map(str::to_string)
+                .or_else(|| info_str(info, "title"))
+                .unwrap_or_else(|| "Untitled".to_string()),
+            plot: info_str(info, "description"),
+            premiered,
+            studio: info_str(info, "channel").or_else(|| info_str(info, "uploader")),
+            tags,
+            runtime_minutes: info
+                .get("duration")
+                .and_then(|v| v.as_f64())
+                .map(|secs| (secs / 60.0).round() as i64)
+                .filter(|minutes| *minutes > 0),
+            extractor: info_str(info, "extractor_key").or_else(|| info_str(info, "extractor")),
+            video_id: info_str(info, "id"),
+        }
+    }
+
+    // "<Title> (<Year>)", the name media servers match on
+    fn folder_name(&self) -> String {
+        let title = sanitize_filename(&self.title);
+        match self.premiered {
+            Some(date) => format!("{} ({})", title, date.year()),
+            None => title,
+        }
+    }
+}
+
+pub fn library_dir(settings: &AppSettings) -> Option<PathBuf> {
+    settings
+        .media_library_dir
+        .as_deref()
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+}
+
+// Only downloads that are kept go into the library
+pub fn enabled(settings: &AppSettings) -> bool {
+    library_dir(settings).is_some() && settings.delete_after_upload.as_deref() != Some("true")
+}
+
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    match library_dir(settings) {
+        Some(dir) if !dir.is_dir() => {
+            Err(format!("Media library folder not found: {}", dir.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// Kodi-style movie .nfo, which Jellyfin and Plex (with the XBMCnfo agent) read as well
+pub fn nfo(metadata: &LibraryMetadata, poster: Option<&str>) -> String {
+    let mut lines = vec![
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>"#.to_string(),
+        "<movie>".to_string(),
+    ];
+    let mut element = |name: &str, value: &str| {
+        lines.push(format!("  <{0}>{1}</{0}>", name, escape_xml(value)));
+    };
+
+    element("title", &metadata.title);
+    if let Some(plot) = &metadata.plot {
+        element("plot", plot);
+    }
+    if let Some(date) = metadata.premiered {
+        element("premiered", &date.format("%Y-%m-%d").to_string());
+        element("year", &date.year().to_string());
+    }
+    if let Some(studio) = &metadata.studio {
+        element("studio", studio);
+    }
+    if let Some(minutes) = metadata.runtime_minutes {
+        element("runtime", &minutes.to_string());
+    }
+    for tag in &metadata.tags {
+        element("tag", tag);
+    }
+    if let Some(poster) = poster {
+        lines.push(format!(
+            "  <thumb aspect=\"poster\">{}</thumb>",
+            escape_xml(poster)
+        ));
+    }
+    if let Some(id) = &metadata.video_id {
+        let source = metadata
+            .extractor
+            .as_deref()
+            .unwrap_or("source")
+            .to_ascii_lowercase();
+        lines.push(format!(
+            "  <uniqueid type=\"{}\" default=\"true\">{}</uniqueid>",
+            escape_xml(&source),
+            escape_xml(id)
+        ));
+    }
+    lines.push("</movie>".to_string());
+    lines.join("\n") + "\n"
+}
+
+// Hard link `from` to `to`, or copy it where a link isn't possible
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), String> {
+    let (from, to) = (long_paths::extended(from), long_paths::extended(to));
+    if to.exists() {
+        return Ok(());
+    }
+    fs::hard_link(&from, &to)
+        .or_else(|_| fs::copy(&from, &to).map(|_| ()))
+        .map_err(|e| {
+            format!(
+                "Failed to place {} in the media library: {}",
+                to.display(),
+                e
+            )
+        })
+}
+
+async fn fetch_poster(folder: &Path, thumbnail_url: &str) -> Result<String, String> {
+    let response = http::client()
+        .get(thumbnail_url)
+        .timeout(POSTER_TIMEOUT)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| format!("Failed to fetch thumbnail: {}", e))?;
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read thumbnail: {}", e))?;
+    let extension = infer::get(&bytes)
+        .filter(|kind| kind.mime_type().starts_with("image/"))
+        .map_or("jpg", |kind| kind.extension());
+    let name = format!("{}.{}", POSTER_STEM, extension);
+    fs::write(long_paths::extended(&folder.join(&name)), &bytes)
+        .map_err(|e| format!("Failed to write poster: {}", e))?;
+    Ok(name)
+}
+
+// Put a finished download into the library; returns the item's library folder
+pub async fn add(
+    settings: &AppSettings,
+    video_path: &Path,
+    metadata: &LibraryMetadata,
+    thumbnail_url: Option<&str>,
+    subtitle_paths: &[String],
+) -> Result<PathBuf, String> {
+    let library = library_dir(settings).ok_or("No media library folder is set")?;
+    let extension = video_path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mp4".to_string());
+
+    // Another video with the same title and year gets its id added to the name
+    let mut base = metadata.folder_name();
+    let video_size = fs::metadata(long_paths::extended(video_path))
+        .map(|m| m.len())
+        .map_err(|e| format!("Failed to read {}: {}", video_path.display(), e))?;
+    let taken = |base: &str| {
+        let existing = library.join(base).join(format!("{}.{}", base, extension));
+        fs::metadata(long_paths::extended(&existing)).map_or(false, |m| m.len() != video_size)
+    };
+    if taken(&base) {
+        if let Some(id) = &metadata.video_id {
+            base = format!("{} [{}]", base, sanitize_filename(id));
+        }
+    }
+
+    let folder = library.join(&base);
+    fs::create_dir_all(long_paths::extended(&folder))
+        .map_err(|e| format!("Failed to create {}: {}", folder.display(), e))?;
+
+    link_or_copy(video_path, &folder.join(format!("{}.{}", base, extension)))?;
+    for subtitle in subtitle_paths {
+        let subtitle = Path::new(subtitle);
+        let extension = subtitle
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let name = match subtitles::language_of(&subtitle.to_string_lossy()) {
+            Some(language) => format!("{}.{}.{}", base, language, extension),
+            None => format!("{}.{}", base, extension),
+        };
+        if let Err(e) = link_or_copy(subtitle, &folder.join(name)) {
+            eprintln!("{}", e);
+        }
+    }
+
+    let poster = match thumbnail_url {
+        Some(url) => match fetch_poster(&folder, url).await {
+            Ok(name) => Some(name),
+            Err(e) => {
+                eprintln!("Media library entry {} has no poster: {}", base, e);
+                None
+            }
+        },
+        None => None,
+    };
+    fs::write(
+        long_paths::extended(&folder.join(format!("{}.nfo", base))),
+        nfo(metadata, poster.as_deref()),
+    )
+    .map_err(|e| format!("Failed to write .nfo file: {}", e))?;
+
+    Ok(folder)
+}
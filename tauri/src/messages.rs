@@ -0,0 +1,393 @@
+// Status messages written to queue items, as a code plus parameters. The rendered
+// English text is still stored in `message` for older clients, notifications and
+// logs; `message_code` and `message_params` let the frontend look the code up in its
+// own translations instead. The English templates below are the source catalog.
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value as JsonValue};
+
+// Free-form text without a code, e.g. set by the frontend through update_item_status
+pub const TEXT: &str = "text";
+pub const CANCELLED: &str = "item.cancelled";
+
+pub const DOWNLOAD_STARTING: &str = "download.starting";
+pub const DOWNLOAD_PROGRESS: &str = "download.progress";
+pub const DOWNLOAD_COMPLETE: &str = "download.complete";
+pub const DOWNLOAD_AWAITING_REVIEW: &str = "download.awaiting_review";
+pub const DOWNLOAD_RETRYING: &str = "download.retrying";
+pub const DOWNLOAD_RESUMING: &str = "download.resuming";
+pub const DOWNLOAD_AUTO_RETRY: &str = "download.auto_retry";
+pub const DOWNLOAD_FAILED: &str = "download.failed";
+pub const DOWNLOAD_WAIT_FAILED: &str = "download.wait_failed";
+pub const DOWNLOAD_SPAWN_FAILED: &str = "download.spawn_failed";
+pub const DOWNLOAD_SETTINGS_FAILED: &str = "download.settings_failed";
+pub const DOWNLOAD_DIR_UNKNOWN: &str = "download.dir_unknown";
+pub const DOWNLOAD_DIR_FAILED: &str = "download.dir_failed";
+pub const DOWNLOAD_STALLED_RETRY: &str = "download.stalled_retry";
+pub const DOWNLOAD_STALLED_GAVE_UP: &str = "download.stalled_gave_up";
+pub const DOWNLOAD_FORMAT_FALLBACK: &str = "download.format_fallback";
+pub const DOWNLOAD_MIRROR_FALLBACK: &str = "download.mirror_fallback";
+pub const DOWNLOAD_EXTRACTOR_UPDATED: &str = "download.extractor_updated";
+pub const DOWNLOAD_UNSUPPORTED: &str = "download.unsupported";
+pub const DOWNLOAD_UNSUPPORTED_DETAIL: &str = "download.unsupported_detail";
+pub const DOWNLOAD_AUTH_REQUIRED: &str = "download.auth_required";
+pub const DOWNLOAD_AUTH_REJECTED: &str = "download.auth_rejected";
+pub const DOWNLOAD_STARTING_PREMERGED: &str = "download.starting_premerged";
+pub const DOWNLOAD_FFMPEG_MISSING: &str = "download.ffmpeg_missing";
+pub const DOWNLOAD_INTERRUPTED: &str = "download.interrupted";
+pub const DOWNLOAD_DISK_SPACE_BLOCKED: &str = "download.disk_space_blocked";
+pub const DOWNLOAD_DISK_SPACE_FREED: &str = "download.disk_space_freed";
+
+pub const UPLOAD_APPROVED: &str = "upload.approved";
+pub const UPLOAD_RETRY_PREPARING: &str = "upload.retry_preparing";
+pub const UPLOAD_AUTO_RETRY: &str = "upload.auto_retry";
+pub const UPLOAD_RESUMED: &str = "upload.resumed";
+pub const UPLOAD_STARTING: &str = "upload.starting";
+pub const UPLOAD_DONE: &str = "upload.done";
+pub const UPLOAD_HELD_MAINTENANCE: &str = "upload.held_maintenance";
+pub const UPLOAD_PROVIDER_BACK: &str = "upload.provider_back";
+pub const UPLOAD_QUOTA_EXCEEDED: &str = "upload.quota_exceeded";
+pub const UPLOAD_QUOTA_RESUMED: &str = "upload.quota_resumed";
+pub const UPLOAD_FILE_MISSING: &str = "upload.file_missing";
+pub const UPLOAD_API_KEY_MISSING: &str = "upload.api_key_missing";
+pub const UPLOAD_SERVER_ERROR: &str = "upload.server_error";
+pub const UPLOAD_SERVER_PARSE_FAILED: &str = "upload.server_parse_failed";
+pub const UPLOAD_SERVER_REQUEST_FAILED: &str = "upload.server_request_failed";
+pub const UPLOAD_NO_HEALTHY_SERVER: &str = "upload.no_healthy_server";
+pub const UPLOAD_TIMEOUT: &str = "upload.timeout";
+pub const UPLOAD_API_ERROR: &str = "upload.api_error";
+pub const UPLOAD_PARSE_FAILED: &str = "upload.parse_failed";
+pub const UPLOAD_READ_FAILED: &str = "upload.read_failed";
+pub const UPLOAD_REQUEST_FAILED: &str = "upload.request_failed";
+pub const UPLOAD_RETRYING: &str = "upload.retrying";
+pub const UPLOAD_DECLARED_DONE: &str = "upload.declared_done";
+pub const UPLOAD_DECLARED_FAILED: &str = "upload.declared_failed";
+pub const UPLOAD_DECLARED_READY: &str = "upload.declared_ready";
+pub const UPLOAD_DECLARED_NOT_READY: &str = "upload.declared_not_ready";
+pub const UPLOAD_LINKED_EXISTING: &str = "upload.linked_existing";
+pub const AUTO_UPLOAD_HELD: &str = "auto_upload.held";
+pub const AUTO_UPLOAD_WAITING: &str = "auto_upload.waiting";
+pub const UPLOAD_INTERRUPTED: &str = "upload.interrupted";
+
+pub const ENCODING_READY: &str = "encoding.ready";
+pub const ENCODING_IN_PROGRESS: &str = "encoding.in_progress";
+pub const ENCODING_FILE_GONE: &str = "encoding.file_gone";
+
+pub const TRANSCODE_STARTING: &str = "transcode.starting";
+pub const TRANSCODE_PROGRESS: &str = "transcode.progress";
+pub const TRANSCODE_FAILED: &str = "transcode.failed";
+
+pub const REMOTE_PENDING: &str = "remote.pending";
+pub const REMOTE_PROGRESS: &str = "remote.progress";
+pub const REMOTE_DONE: &str = "remote.done";
+pub const REMOTE_FAILED: &str = "remote.failed";
+pub const REMOTE_API_ERROR: &str = "remote.api_error";
+pub const REMOTE_REQUEST_FAILED: &str = "remote.request_failed";
+pub const REMOTE_API_KEY_MISSING: &str = "remote.api_key_missing";
+
+// English template for every code; {name} is replaced by the parameter of that name
+pub const CATALOG: &[(&str, &str)] = &[
+    (TEXT, "{text}"),
+    (CANCELLED, "Cancelled by user"),
+    (DOWNLOAD_STARTING, "Download starting..."),
+    (DOWNLOAD_PROGRESS, "Downloading: {percent}%"),
+    (DOWNLOAD_COMPLETE, "Download complete"),
+    (
+        DOWNLOAD_AWAITING_REVIEW,
+        "Download complete; waiting for review before upload",
+    ),
+    (DOWNLOAD_RETRYING, "Retrying download..."),
+    (DOWNLOAD_RESUMING, "Resuming cancelled download..."),
+    (
+        DOWNLOAD_AUTO_RETRY,
+        "Failed temporarily; retrying automatically (retry {attempt} of {max_attempts})",
+    ),
+    (
+        DOWNLOAD_FAILED,
+        "yt-dlp exited with code {exit_code}. Stderr: {stderr}",
+    ),
+    (DOWNLOAD_WAIT_FAILED, "Failed to wait for yt-dlp process: {error}"),
+    (
+        DOWNLOAD_SPAWN_FAILED,
+        "Failed to run yt-dlp ({binary}): {error}. Install it from Settings or set ytdlp_path.",
+    ),
+    (DOWNLOAD_SETTINGS_FAILED, "Failed to get settings: {error}"),
+    (
+        DOWNLOAD_DIR_UNKNOWN,
+        "Download directory not set and default couldn't be determined.",
+    ),
+    (
+        DOWNLOAD_DIR_FAILED,
+        "Failed to create download directory '{path}': {error}",
+    ),
+    (
+        DOWNLOAD_STALLED_RETRY,
+        "Download stalled ({reason}); retrying in {minutes} min \
+         (stalled attempt {attempt} of {max_attempts})",
+    ),
+    (
+        DOWNLOAD_STALLED_GAVE_UP,
+        "Download stalled ({reason}); gave up after {max_attempts} stalled attempts",
+    ),
+    (
+        DOWNLOAD_FORMAT_FALLBACK,
+        "Download with format '{failed_format}' failed; retrying with '{next_format}'",
+    ),
+    (
+        DOWNLOAD_MIRROR_FALLBACK,
+        "{reason}; trying mirror {mirror} of {mirror_count}: {url}",
+    ),
+    (
+        DOWNLOAD_EXTRACTOR_UPDATED,
+        "Extractor looked outdated; updated yt-dlp and retrying once",
+    ),
+    (DOWNLOAD_UNSUPPORTED, "{reason}. This item will not be retried."),
+    (
+        DOWNLOAD_UNSUPPORTED_DETAIL,
+        "{reason}. This item will not be retried. ({detail})",
+    ),
+    (
+        DOWNLOAD_AUTH_REQUIRED,
+        "{reason}. Add a cookies file or a browser to read cookies from in settings, then retry. ({detail})",
+    ),
+    (
+        DOWNLOAD_AUTH_REJECTED,
+        "{reason}, and the {cookies} weren't accepted. Refresh them, then retry. ({detail})",
+    ),
+    (
+        DOWNLOAD_STARTING_PREMERGED,
+        "Download starting... ffmpeg was not found, so a pre-merged format is used ({format})",
+    ),
+    (
+        DOWNLOAD_FFMPEG_MISSING,
+        "Format {format} needs ffmpeg to merge video and audio, and ffmpeg was not found. \
+         Install ffmpeg or set its path in settings, then retry.",
+    ),
+    (
+        DOWNLOAD_INTERRUPTED,
+        "Download was interrupted when the app last closed; queued again",
+    ),
+    (
+        DOWNLOAD_DISK_SPACE_BLOCKED,
+        "Blocked: insufficient disk space. Needs about {needed_gb} GB, {free_gb} GB is free \
+         beyond the {min_free_gb} GB kept spare. Starts once space is freed.",
+    ),
+    (DOWNLOAD_DISK_SPACE_FREED, "Enough disk space is free again; queued"),
+    (UPLOAD_APPROVED, "Approved for upload"),
+    (UPLOAD_RETRY_PREPARING, "Preparing to retry upload..."),
+    (
+        UPLOAD_AUTO_RETRY,
+        "Upload failed temporarily; retrying automatically (retry {attempt} of {max_attempts})",
+    ),
+    (UPLOAD_RESUMED, "Resumed after cancel; ready to upload"),
+    (UPLOAD_STARTING, "Starting upload..."),
+    (UPLOAD_DONE, "Uploaded to Filemoon: {filecode}"),
+    (
+        UPLOAD_HELD_MAINTENANCE,
+        "Filemoon is under maintenance; the upload resumes automatically",
+    ),
+    (UPLOAD_PROVIDER_BACK, "Filemoon is back; resuming upload"),
+    (
+        UPLOAD_QUOTA_EXCEEDED,
+        "Upload of {upload_gb} GB would exceed the {provider} monthly quota \
+         ({used_gb} of {quota_gb} GB used)",
+    ),
+    (
+        UPLOAD_QUOTA_RESUMED,
+        "{provider} monthly quota has room again; resuming upload",
+    ),
+    (UPLOAD_FILE_MISSING, "Local file not found at: {path}"),
+    (UPLOAD_API_KEY_MISSING, "Filemoon API key not configured"),
+    (
+        UPLOAD_SERVER_ERROR,
+        "Filemoon GetServer API Error (Status {status}): {error}",
+    ),
+    (
+        UPLOAD_SERVER_PARSE_FAILED,
+        "Failed to parse Filemoon GetServer response: {error}",
+    ),
+    (
+        UPLOAD_SERVER_REQUEST_FAILED,
+        "Filemoon GetServer request failed: {error}",
+    ),
+    (UPLOAD_NO_HEALTHY_SERVER, "{error} ({detail})"),
+    (
+        UPLOAD_TIMEOUT,
+        "Upload exceeded the {minutes} min upload time limit",
+    ),
+    (
+        UPLOAD_API_ERROR,
+        "Filemoon Upload API Error (Status {status}): {error} - Parsed from JSON: {response}",
+    ),
+    (
+        UPLOAD_PARSE_FAILED,
+        "Failed to parse Filemoon Upload JSON response (Status {status}): {error}. \
+         Raw Body: {body}",
+    ),
+    (
+        UPLOAD_READ_FAILED,
+        "Failed to read Filemoon Upload response body (Status {status}): {error}",
+    ),
+    (UPLOAD_REQUEST_FAILED, "Filemoon Upload request failed: {error}"),
+    (
+        UPLOAD_RETRYING,
+        "{step} attempt {attempt} of {max_attempts} failed: {error}. Retrying in {seconds}s",
+    ),
+    (UPLOAD_DECLARED_DONE, "Uploaded to {provider}: {url}"),
+    (UPLOAD_DECLARED_FAILED, "Upload to {provider} failed: {error}"),
+    (UPLOAD_DECLARED_READY, "{provider} status: Ready"),
+    (
+        UPLOAD_DECLARED_NOT_READY,
+        "{provider} did not report the file ready after {checks} checks; last answer: {detail}",
+    ),
+    (UPLOAD_LINKED_EXISTING, "Linked to the copy already on Filemoon: {filecode}"),
+    (AUTO_UPLOAD_HELD, "Left for manual upload: {reason}"),
+    (AUTO_UPLOAD_WAITING, "Upload waits for the upload window ({window})"),
+    (
+        UPLOAD_INTERRUPTED,
+        "Upload was interrupted when the app last closed; uploading again",
+    ),
+    (ENCODING_READY, "Filemoon status: Ready (canplay=1)"),
+    (
+        ENCODING_IN_PROGRESS,
+        "Filemoon status: Exists (canplay={canplay})",
+    ),
+    (
+        ENCODING_FILE_GONE,
+        "Filemoon no longer has this file (status {status})",
+    ),
+    (TRANSCODE_STARTING, "Transcoding to {target}"),
+    (TRANSCODE_PROGRESS, "Transcoding: {percent}%"),
+    (TRANSCODE_FAILED, "Transcoding failed: {error}"),
+    (
+        REMOTE_PENDING,
+        "Waiting for Filemoon to fetch the video (remote upload {filecode})",
+    ),
+    (REMOTE_PROGRESS, "Filemoon is fetching the video: {percent}%"),
+    (REMOTE_DONE, "Fetched by Filemoon from the source: {filecode}"),
+    (REMOTE_FAILED, "Filemoon could not fetch the video: {error}"),
+    (
+        REMOTE_API_ERROR,
+        "Filemoon remote upload API Error (Status {status}): {error}",
+    ),
+    (
+        REMOTE_REQUEST_FAILED,
+        "Filemoon remote upload request failed: {error}",
+    ),
+    (
+        REMOTE_API_KEY_MISSING,
+        "Filemoon API key not configured; remote uploads need one",
+    ),
+];
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{([a-z_]+)\}").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredMessage")]
+pub struct ItemMessage {
+    pub code: String,
+    pub params: Map<String, JsonValue>,
+}
+
+// Journal entries written before messages had codes hold plain text
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredMessage {
+    Text(String),
+    Coded {
+        code: String,
+        #[serde(default)]
+        params: Map<String, JsonValue>,
+    },
+}
+
+impl From<StoredMessage> for ItemMessage {
+    fn from(stored: StoredMessage) -> Self {
+        match stored {
+            StoredMessage::Text(text) => ItemMessage::text_only(text),
+            StoredMessage::Coded { code, params } => ItemMessage { code, params },
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CatalogEntry {
+    pub code: String,
+    pub template: String,
+}
+
+impl ItemMessage {
+    pub fn new(code: &str) -> Self {
+        ItemMessage {
+            code: code.to_string(),
+            params: Map::new(),
+        }
+    }
+
+    pub fn text_only(text: impl Into<String>) -> Self {
+        ItemMessage::new(TEXT).with("text", text.into())
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<JsonValue>) -> Self {
+        self.params.insert(key.to_string(), value.into());
+        self
+    }
+
+    // The message in English, as stored in the `message` column
+    pub fn text(&self) -> String {
+        let template = match template(&self.code) {
+            Some(template) => template,
+            None => {
+                return self
+                    .params
+                    .get("text")
+                    .map(display)
+                    .unwrap_or_else(|| self.code.clone())
+            }
+        };
+        PLACEHOLDER_REGEX
+            .replace_all(template, |caps: &Captures| match self.params.get(&caps[1]) {
+                Some(value) => display(value),
+                None => caps[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    // Parameters as stored in the `message_params` column
+    pub fn params_json(&self) -> Option<String> {
+        if self.params.is_empty() {
+            None
+        } else {
+            Some(JsonValue::Object(self.params.clone()).to_string())
+        }
+    }
+}
+
+pub fn template(code: &str) -> Option<&'static str> {
+    CATALOG
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, template)| *template)
+}
+
+pub fn catalog() -> Vec<CatalogEntry> {
+    CATALOG
+        .iter()
+        .map(|(code, template)| CatalogEntry {
+            code: code.to_string(),
+            template: template.to_string(),
+        })
+        .collect()
+}
+
+fn display(value: &JsonValue) -> String {
+    match value {
+        JsonValue::String(s) => s.clone(),
+        JsonValue::Null => "none".to_string(),
+        other => other.to_string(),
+    }
+}
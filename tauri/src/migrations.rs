@@ -0,0 +1,282 @@
+// Embedded schema migrations. The SQL files under prisma/migrations are compiled in
+// and applied on startup by the primary instance, so a new database only needs
+// NEON_DATABASE_URL: the tables are created on the first run and upgraded on later
+// ones. Applied migrations are recorded in the schema_version table with the SHA-256
+// of their SQL; each one runs in its own transaction, under an advisory lock so two
+// instances starting at once don't both apply it.
+//
+// A database set up with `prisma migrate deploy` has its history adopted from
+// _prisma_migrations on the first run, and migrations the app applies are written
+// there too, so either tool can be used afterwards. A database whose tables exist
+// without either history is left alone and reported as unmanaged by
+// get_db_schema_info. A new migration needs its directory added to MIGRATIONS.
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Client as PoolClient;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::timestamps;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+pub const SOURCE_APP: &str = "app";
+pub const SOURCE_PRISMA: &str = "prisma";
+
+// pg_advisory_lock key held while migrating ("permavid" in ASCII)
+const LOCK_KEY: i64 = 0x7065_726d_6176_6964;
+
+const CREATE_SCHEMA_VERSION: &str = "CREATE TABLE IF NOT EXISTS schema_version (
+    version TEXT NOT NULL PRIMARY KEY,
+    checksum TEXT NOT NULL,
+    source TEXT NOT NULL,
+    applied_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+)";
+
+pub struct Migration {
+    // The directory name, e.g. "20261020090000_add_uploads"; sorts by age
+    pub version: &'static str,
+    pub sql: &'static str,
+}
+
+macro_rules! migration {
+    ($version:literal) => {
+        Migration {
+            version: $version,
+            sql: include_str!(concat!(
+                "../../prisma/migrations/",
+                $version,
+                "/migration.sql"
+            )),
+        }
+    };
+}
+
+// Oldest first
+pub const MIGRATIONS: &[Migration] = &[
+    migration!("20250904144544_new"),
+    migration!("20261016120000_add_short_url"),
+    migration!("20261016130000_add_global_settings"),
+    migration!("20261016140000_add_thumbnail_uploaded"),
+    migration!("20261016150000_add_provider_errors"),
+    migration!("20261016160000_add_queue_locked"),
+    migration!("20261016170000_add_upload_usage"),
+    migration!("20261016180000_add_format_ladder"),
+    migration!("20261016190000_add_download_sections"),
+    migration!("20261016200000_add_provenance"),
+    migration!("20261016210000_add_queue_sync_index"),
+    migration!("20261016220000_add_notification_rules"),
+    migration!("20261016230000_add_mirror_urls"),
+    migration!("20261017000000_add_queue_priority"),
+    migration!("20261017010000_add_language_metadata"),
+    migration!("20261017020000_add_bandwidth_usage"),
+    migration!("20261017030000_add_queue_templates"),
+    migration!("20261017040000_add_item_events"),
+    migration!("20261017050000_add_upload_verified_at"),
+    migration!("20261017060000_add_message_codes"),
+    migration!("20261017070000_add_hook_runs"),
+    migration!("20261017080000_add_playlists"),
+    migration!("20261017090000_add_gallery_indexes"),
+    migration!("20261017100000_add_queue_position"),
+    migration!("20261017110000_add_subtitle_paths"),
+    migration!("20261018090000_add_upload_provider"),
+    migration!("20261019090000_add_queue_collection"),
+    migration!("20261020090000_add_uploads"),
+    migration!("20261021090000_add_queue_retry_policy"),
+    migration!("20261022090000_add_videos"),
+    migration!("20261022100000_add_schema_version"),
+    migration!("20261023090000_add_download_stats"),
+    migration!("20261024090000_add_remote_transfer"),
+    migration!("20261025090000_add_item_upload_target"),
+    migration!("20261026090000_add_user_admin"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppliedMigration {
+    pub version: String,
+    // SOURCE_APP or SOURCE_PRISMA
+    pub source: String,
+    #[serde(with = "timestamps::iso8601")]
+    pub applied_at: DateTime<Utc>,
+    // The embedded SQL differs from what was applied
+    pub modified: bool,
+}
+
+// Returned by get_db_schema_info
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SchemaInfo {
+    // Newest applied migration; None before the first run
+    pub current_version: Option<String>,
+    // Newest migration this build knows
+    pub latest_version: String,
+    pub applied: Vec<AppliedMigration>,
+    // Known migrations not applied yet, oldest first
+    pub pending: Vec<String>,
+    // Tables exist without a migration history, so nothing is applied automatically
+    pub unmanaged: bool,
+}
+
+// SHA-256 of the SQL as lowercase hex, the same checksum Prisma records
+pub fn checksum(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+pub fn latest_version() -> &'static str {
+    MIGRATIONS.last().map_or("", |m| m.version)
+}
+
+async fn table_exists(client: &PoolClient, name: &str) -> Result<bool> {
+    let row = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&name])
+        .await?;
+    Ok(row.get(0))
+}
+
+// Checksums of the recorded migrations by version
+async fn recorded(client: &PoolClient) -> Result<HashMap<String, String>> {
+    let rows = client
+        .query("SELECT version, checksum FROM schema_version", &[])
+        .await?;
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+// Record the migrations Prisma finished as applied. Returns how many were adopted.
+async fn adopt_prisma_history(client: &PoolClient) -> Result<u64> {
+    let known: Vec<&str> = MIGRATIONS.iter().map(|m| m.version).collect();
+    let adopted = client
+        .execute(
+            "INSERT INTO schema_version (version, checksum, source, applied_at)
+             SELECT migration_name, checksum, $1, finished_at FROM _prisma_migrations
+             WHERE finished_at IS NOT NULL AND rolled_back_at IS NULL
+               AND migration_name = ANY($2)
+             ON CONFLICT (version) DO NOTHING",
+            &[&SOURCE_PRISMA, &known],
+        )
+        .await?;
+    Ok(adopted)
+}
+
+// Apply every migration not applied yet. Returns the versions applied now.
+pub async fn apply(client: &mut PoolClient) -> Result<Vec<String>> {
+    client
+        .execute("SELECT pg_advisory_lock($1)", &[&LOCK_KEY])
+        .await?;
+    let result = apply_locked(client).await;
+    if let Err(e) = client
+        .execute("SELECT pg_advisory_unlock($1)", &[&LOCK_KEY])
+        .await
+    {
+        eprintln!("Error releasing the migration lock: {}", e);
+    }
+    result
+}
+
+async fn apply_locked(client: &mut PoolClient) -> Result<Vec<String>> {
+    client.batch_execute(CREATE_SCHEMA_VERSION).await?;
+    let prisma = table_exists(client, "_prisma_migrations").await?;
+
+    let mut applied = recorded(client).await?;
+    if applied.is_empty() {
+        if prisma {
+            let adopted = adopt_prisma_history(client).await?;
+            println!("Adopted {} migration(s) from _prisma_migrations", adopted);
+            applied = recorded(client).await?;
+        } else if table_exists(client, "queue").await? {
+            return Err("The database has tables but no migration history; \
+                        leaving its schema alone"
+                .into());
+        }
+    }
+
+    let mut ran = Vec::new();
+    for migration in MIGRATIONS {
+        let sum = checksum(migration.sql);
+        if let Some(recorded) = applied.get(migration.version) {
+            if *recorded != sum {
+                eprintln!(
+                    "Migration {} changed after it was applied; not running it again",
+                    migration.version
+                );
+            }
+            continue;
+        }
+
+        let tx = client.transaction().await?;
+        tx.batch_execute(migration.sql)
+            .await
+            .map_err(|e| format!("Migration {} failed: {}", migration.version, e))?;
+        tx.execute(
+            "INSERT INTO schema_version (version, checksum, source) VALUES ($1, $2, $3)",
+            &[&migration.version, &sum, &SOURCE_APP],
+        )
+        .await?;
+        if prisma {
+            tx.execute(
+                "INSERT INTO _prisma_migrations (id, checksum, finished_at, migration_name,
+                                                 started_at, applied_steps_count)
+                 VALUES ($1, $2, now(), $3, now(), 1)",
+                &[&Uuid::new_v4().to_string(), &sum, &migration.version],
+            )
+            .await?;
+        }
+        tx.commit().await?;
+
+        println!("Applied migration {}", migration.version);
+        ran.push(migration.version.to_string());
+    }
+    Ok(ran)
+}
+
+// What is applied and what isn't, without changing anything
+pub async fn info(client: &PoolClient) -> Result<SchemaInfo> {
+    let rows = if table_exists(client, "schema_version").await? {
+        client
+            .query(
+                "SELECT version, checksum, source, applied_at FROM schema_version
+                 ORDER BY version ASC",
+                &[],
+            )
+            .await?
+    } else {
+        Vec::new()
+    };
+    let unmanaged = rows.is_empty()
+        && table_exists(client, "queue").await?
+        && !table_exists(client, "_prisma_migrations").await?;
+
+    let applied: Vec<AppliedMigration> = rows
+        .iter()
+        .map(|row| {
+            let version: String = row.get(0);
+            let recorded: String = row.get(1);
+            let modified = MIGRATIONS
+                .iter()
+                .find(|m| m.version == version)
+                .map_or(false, |m| checksum(m.sql) != recorded);
+            AppliedMigration {
+                version,
+                source: row.get(2),
+                applied_at: row.get(3),
+                modified,
+            }
+        })
+        .collect();
+    let pending = MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .map(|m| m.version.to_string())
+        .collect();
+
+    Ok(SchemaInfo {
+        current_version: applied.last().map(|a| a.version.clone()),
+        latest_version: latest_version().to_string(),
+        applied,
+        pending,
+        unmanaged,
+    })
+}
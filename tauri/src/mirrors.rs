@@ -0,0 +1,387 @@
+// Uploading one item to several hosts. upload_target takes a comma-separated list
+// ("filemoon, filesvc, s3"): the first entry is the main target, which the item's
+// status and links (filemoon_url, upload_url) follow as before, and the rest are
+// mirrors. Once the main upload succeeds the file is copied to each mirror in turn,
+// retrying transient failures like any other upload. A mirror that fails does not
+// fail the item; uploading the item again sends only the copies still missing.
+//
+// Every destination's outcome, the main target's included, is kept in the uploads
+// table (see get_uploads).
+
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use crate::db::{AppSettings, QueueItem, UploadRecord};
+use crate::providers::{StepError, STEP_UPLOAD};
+use crate::quota::{self, QuotaCheck};
+use crate::upload::record_provider_error;
+use crate::{
+    archive_org, bandwidth, filemoon, http, jobs, long_paths, s3, sanitize_filename, titles,
+    watchdog, AppState,
+};
+
+pub const STATUS_UPLOADING: &str = "uploading";
+pub const STATUS_UPLOADED: &str = "uploaded";
+pub const STATUS_FAILED: &str = "failed";
+
+const DEFAULT_TARGET: &str = "filemoon";
+// Item statuses after a finished main upload; uploading such an item again only
+// sends the missing mirror copies
+pub const UPLOADED_ITEM_STATUSES: &[&str] = &["uploaded", "encoded"];
+
+// The targets in an upload_target value, in order, without blanks or repeats
+pub fn parse_targets(value: Option<&str>) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for target in value.unwrap_or_default().split(',') {
+        let target = target.trim().to_ascii_lowercase();
+        if !target.is_empty() && !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+    targets
+}
+
+// The settings an item is uploaded with: its own upload target, set from a queue
+// template, takes the place of the upload_target setting
+pub fn item_settings(settings: &AppSettings, item: &QueueItem) -> AppSettings {
+    let mut settings = settings.clone();
+    if item.upload_target.is_some() {
+        settings.upload_target = item.upload_target.clone();
+    }
+    settings
+}
+
+// The main target; Filemoon when none is set
+pub fn main_target(settings: &AppSettings) -> String {
+    parse_targets(settings.upload_target.as_deref())
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| DEFAULT_TARGET.to_string())
+}
+
+pub fn mirror_targets(settings: &AppSettings) -> Vec<String> {
+    parse_targets(settings.upload_target.as_deref())
+        .into_iter()
+        .skip(1)
+        .collect()
+}
+
+// Mirrors the item still has to be copied to
+pub fn pending(settings: &AppSettings, uploads: &[UploadRecord]) -> Vec<String> {
+    mirror_targets(settings)
+        .into_iter()
+        .filter(|target| {
+            !uploads
+                .iter()
+                .any(|upload| &upload.target == target && upload.status == STATUS_UPLOADED)
+        })
+        .collect()
+}
+
+// Record how the main upload ended, read back from the item. Uploads held for a
+// quota or an outage, or cancelled, are left unrecorded until they run again.
+pub async fn record_main(app_state: &AppState, item_id: &str, target: &str) {
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) => item,
+        _ => return,
+    };
+    let (status, url, error) = match item.status.as_str() {
+        "uploaded" => {
+            let url = if item.upload_provider.as_deref() == Some(target) {
+                item.upload_url.clone()
+            } else {
+                item.filemoon_url.as_deref().map(filemoon::player_url)
+            };
+            (STATUS_UPLOADED, url, None)
+        }
+        "failed" => (STATUS_FAILED, None, item.message.clone()),
+        _ => return,
+    };
+    if let Err(e) = app_state
+        .db
+        .record_upload(item_id, target, status, url.as_deref(), error.as_deref())
+        .await
+    {
+        eprintln!(
+            "Failed to record {} upload of item {}: {}",
+            target, item_id, e
+        );
+    }
+}
+
+// Copy the item's file to every mirror it is still missing from. Returns how many
+// copies failed.
+pub async fn upload(
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    targets: &[String],
+) -> usize {
+    let item_id = item.id.as_deref().unwrap_or_default();
+    let user_id = item.user_id.as_deref().unwrap_or_default();
+    let local_path =
+        long_paths::extended(Path::new(item.local_path.as_deref().unwrap_or_default()));
+    let upload_bytes = fs::metadata(&local_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+
+    let cancel_guard = app_state.cancellations.register(item_id);
+    let job = app_state.jobs.start(
+        jobs::KIND_UPLOAD,
+        Some(item_id),
+        cancel_guard.token().clone(),
+    );
+
+    let mut failed = 0;
+    for target in targets {
+        let fail = |error: String| async move {
+            println!(
+                "Mirror upload of item {} to {} failed: {}",
+                item_id, target, error
+            );
+            if let Err(e) = app_state
+                .db
+                .record_upload(item_id, target, STATUS_FAILED, None, Some(&error))
+                .await
+            {
+                eprintln!(
+                    "Failed to record {} upload of item {}: {}",
+                    target, item_id, e
+                );
+            }
+        };
+
+        if upload_bytes <= 0 {
+            fail(format!("Local file not found at: {}", local_path.display())).await;
+            failed += 1;
+            continue;
+        }
+        match quota::check_upload(app_state, settings, user_id, item_id, target, upload_bytes).await
+        {
+            Ok(QuotaCheck::Exceeded(warning)) => {
+                fail(warning.message).await;
+                failed += 1;
+                continue;
+            }
+            Ok(QuotaCheck::Warn(warning)) => println!("{}", warning.message),
+            Ok(QuotaCheck::Ok) => {}
+            Err(e) => eprintln!("Quota check failed, uploading anyway: {}", e),
+        }
+
+        if let Err(e) = app_state
+            .db
+            .record_upload(item_id, target, STATUS_UPLOADING, None, None)
+            .await
+        {
+            eprintln!(
+                "Failed to record {} upload of item {}: {}",
+                target, item_id, e
+            );
+        }
+        job.update(
+            None,
+            Some(format!(
+                "Mirroring {} MB to {}",
+                upload_bytes / (1024 * 1024),
+                target
+            )),
+        );
+        println!("Mirroring item {} to {}...", item_id, target);
+
+        match send_with_retries(
+            app_state,
+            item,
+            settings,
+            target,
+            &local_path,
+            upload_bytes.max(0) as u64,
+            cancel_guard.token(),
+        )
+        .await
+        {
+            Ok((url, started)) => {
+                app_state
+                    .throughput
+                    .record(bandwidth::UPLOAD, upload_bytes, started.elapsed());
+                bandwidth::record(app_state, user_id, item_id, bandwidth::UPLOAD, upload_bytes)
+                    .await;
+                if let Err(e) = app_state
+                    .db
+                    .record_upload_usage(user_id, item_id, target, upload_bytes)
+                    .await
+                {
+                    eprintln!("Failed to record upload usage: {}", e);
+                }
+                println!(
+                    "Mirror upload of item {} to {} successful: {}",
+                    item_id, target, url
+                );
+                if let Err(e) = app_state
+                    .db
+                    .record_upload(item_id, target, STATUS_UPLOADED, Some(&url), None)
+                    .await
+                {
+                    eprintln!(
+                        "Failed to record {} upload of item {}: {}",
+                        target, item_id, e
+                    );
+                }
+            }
+            Err(error) => {
+                record_provider_error(
+                    app_state,
+                    Some(item_id),
+                    &format!("{}/{}", target, error.step),
+                    error.http_status,
+                    &error.message,
+                    error.body.as_deref(),
+                )
+                .await;
+                fail(error.message).await;
+                failed += 1;
+            }
+        }
+        if cancel_guard.token().is_cancelled() {
+            break;
+        }
+    }
+    failed
+}
+
+// Send the file to `target`, retrying transient failures with the usual backoff.
+// The upload time limit covers all attempts together. Returns the copy's URL and
+// when the successful attempt started.
+async fn send_with_retries(
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    target: &str,
+    path: &Path,
+    bytes: u64,
+    cancel: &CancellationToken,
+) -> Result<(String, Instant), StepError> {
+    let upload_limit = watchdog::upload_limit(settings);
+    let upload_deadline = watchdog::deadline(upload_limit);
+    tokio::pin!(upload_deadline);
+    let mut attempt = 1;
+    loop {
+        let started = Instant::now();
+        let result = tokio::select! {
+            result = send(app_state, item, settings, target, path, bytes) => result,
+            _ = cancel.cancelled() => {
+                return Err(StepError::new(STEP_UPLOAD, "Upload cancelled by user".to_string()));
+            }
+            _ = &mut upload_deadline => {
+                return Err(StepError::new(
+                    STEP_UPLOAD,
+                    format!(
+                        "Timed out after {} minutes",
+                        upload_limit.map_or(0, |limit| limit.as_secs() / 60)
+                    ),
+                ));
+            }
+        };
+
+        let error = match result {
+            Ok(url) => return Ok((url, started)),
+            Err(error) => error,
+        };
+        if !error.transient || attempt >= filemoon::UPLOAD_MAX_ATTEMPTS {
+            return Err(error);
+        }
+        let delay = filemoon::retry_delay(attempt);
+        println!(
+            "Mirror upload to {} failed ({}), retrying in {}s (attempt {}/{})",
+            target,
+            error.message,
+            delay.as_secs(),
+            attempt,
+            filemoon::UPLOAD_MAX_ATTEMPTS
+        );
+        tokio::select! {
+            _ = sleep(delay) => {}
+            _ = cancel.cancelled() => {
+                return Err(StepError::new(STEP_UPLOAD, "Upload cancelled by user".to_string()));
+            }
+        }
+        attempt += 1;
+    }
+}
+
+// One attempt at copying the file to `target`; returns the copy's URL
+async fn send(
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    target: &str,
+    path: &Path,
+    bytes: u64,
+) -> Result<String, StepError> {
+    let config_error = |e: String| StepError::new(STEP_UPLOAD, e);
+    let client = http::client();
+    let item_id = item.id.as_deref().unwrap_or_default();
+    let file_name = sanitize_filename(&titles::upload_file_name(
+        item.title.as_deref(),
+        &item.url,
+        path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("unknown_file"),
+    ));
+
+    if let Some(definition) = app_state.providers.get(target) {
+        let api_key = definition.api_key().map_err(config_error)?;
+        let server = definition.request_server(&client, &api_key).await?;
+        let file_code = definition
+            .send_file(
+                &client,
+                server.as_deref(),
+                &api_key,
+                path,
+                &file_name,
+                bytes,
+            )
+            .await?;
+        return Ok(definition.link(&file_code));
+    }
+
+    match target {
+        DEFAULT_TARGET => {
+            let api_key = settings
+                .filemoon_api_key
+                .clone()
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| config_error("Filemoon API key not configured".to_string()))?;
+            let file_code =
+                filemoon::upload_file(&client, &api_key, path, &file_name, bytes).await?;
+            Ok(filemoon::player_url(&file_code))
+        }
+        archive_org::PROVIDER => {
+            let credentials =
+                archive_org::Credentials::from_settings(settings).map_err(config_error)?;
+            let provenance = app_state.db.get_provenance(item_id).await.ok().flatten();
+            let metadata = archive_org::ItemMetadata::for_item(item, provenance.as_ref(), settings);
+            let identifier = archive_org::identifier(item);
+            archive_org::upload(
+                &client,
+                &credentials,
+                &identifier,
+                &metadata,
+                path,
+                &file_name,
+                bytes,
+            )
+            .await?;
+            Ok(archive_org::details_url(&identifier))
+        }
+        s3::PROVIDER => {
+            let config = s3::S3Config::from_settings(settings).map_err(config_error)?;
+            let key = format!("{}/{}", item_id, file_name);
+            s3::upload(&client, &config, &key, path, bytes, &|_| {}).await?;
+            Ok(config.object_url(&key))
+        }
+        _ => Err(config_error(format!("Unknown upload target '{}'", target))),
+    }
+}
@@ -0,0 +1,363 @@
+// Rule-based notifications. Each user keeps a list of rules in the database, such as
+//
+//     status == failed AND retries >= 3          -> telegram <chat id>
+//     provider == filemoon AND status == encoded -> webhook <url>
+//
+// The notifier listens to the database's status events and sends one message per
+// rule that matches, through the rule's channel.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::json;
+use std::collections::HashMap;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::db::{NotificationRule, StatusEvent};
+use crate::{mirrors, timestamps, AppState};
+
+const TELEGRAM_API_BASE: &str = "https://api.telegram.org";
+
+pub const CHANNEL_TELEGRAM: &str = "telegram";
+pub const CHANNEL_WEBHOOK: &str = "webhook";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Status,
+    Provider,
+    Retries,
+    Title,
+    Url,
+    Message,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        match name.to_ascii_lowercase().as_str() {
+            "status" => Some(Field::Status),
+            "provider" => Some(Field::Provider),
+            "retries" => Some(Field::Retries),
+            "title" => Some(Field::Title),
+            "url" => Some(Field::Url),
+            "message" => Some(Field::Message),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+struct Clause {
+    field: Field,
+    op: Op,
+    value: String,
+}
+
+// A parsed rule condition: every clause must hold
+#[derive(Debug, Clone)]
+pub struct Condition {
+    clauses: Vec<Clause>,
+}
+
+// The values a condition is evaluated against
+#[derive(Debug, Clone)]
+pub struct EventContext {
+    pub item_id: String,
+    pub status: String,
+    pub provider: String,
+    // Download retries so far (the item's rung on the format fallback ladder)
+    pub retries: i64,
+    pub title: Option<String>,
+    pub url: String,
+    pub message: Option<String>,
+}
+
+impl EventContext {
+    fn text(&self, field: Field) -> String {
+        match field {
+            Field::Status => self.status.clone(),
+            Field::Provider => self.provider.clone(),
+            Field::Retries => self.retries.to_string(),
+            Field::Title => self.title.clone().unwrap_or_default(),
+            Field::Url => self.url.clone(),
+            Field::Message => self.message.clone().unwrap_or_default(),
+        }
+    }
+}
+
+// Parse "field op value [AND field op value ...]". Operators: == != > >= < <= contains.
+// Values may be quoted when they contain spaces.
+pub fn parse_condition(input: &str) -> Result<Condition, String> {
+    lazy_static! {
+        static ref AND_REGEX: Regex = Regex::new(r"(?i)\s+AND\s+").unwrap();
+        static ref CLAUSE_REGEX: Regex =
+            Regex::new(r"(?i)^\s*([a-z_]+)\s*(==|!=|>=|<=|>|<|\s+contains\s+)\s*(.+?)\s*$")
+                .unwrap();
+    }
+
+    if input.trim().is_empty() {
+        return Err("Condition is empty".to_string());
+    }
+
+    let mut clauses = Vec::new();
+    for part in AND_REGEX.split(input.trim()) {
+        let caps = CLAUSE_REGEX
+            .captures(part)
+            .ok_or_else(|| format!("Can't read condition '{}'", part.trim()))?;
+
+        let field = Field::parse(&caps[1]).ok_or_else(|| {
+            format!(
+                "Unknown field '{}' (use status, provider, retries, title, url or message)",
+                &caps[1]
+            )
+        })?;
+        let op = match caps[2].trim().to_ascii_lowercase().as_str() {
+            "==" => Op::Eq,
+            "!=" => Op::Ne,
+            ">" => Op::Gt,
+            ">=" => Op::Ge,
+            "<" => Op::Lt,
+            "<=" => Op::Le,
+            _ => Op::Contains,
+        };
+        let value = caps[3]
+            .trim_matches(|c| c == '"' || c == '\'')
+            .to_string();
+
+        let ordered = matches!(op, Op::Gt | Op::Ge | Op::Lt | Op::Le);
+        if ordered && value.parse::<i64>().is_err() {
+            return Err(format!("'{}' needs a number to compare against", part.trim()));
+        }
+
+        clauses.push(Clause { field, op, value });
+    }
+
+    Ok(Condition { clauses })
+}
+
+impl Condition {
+    pub fn matches(&self, ctx: &EventContext) -> bool {
+        self.clauses.iter().all(|clause| {
+            let actual = ctx.text(clause.field);
+            match clause.op {
+                Op::Eq => actual.eq_ignore_ascii_case(&clause.value),
+                Op::Ne => !actual.eq_ignore_ascii_case(&clause.value),
+                Op::Contains => actual
+                    .to_lowercase()
+                    .contains(&clause.value.to_lowercase()),
+                Op::Gt | Op::Ge | Op::Lt | Op::Le => {
+                    match (actual.parse::<i64>(), clause.value.parse::<i64>()) {
+                        (Ok(a), Ok(b)) => match clause.op {
+                            Op::Gt => a > b,
+                            Op::Ge => a >= b,
+                            Op::Lt => a < b,
+                            _ => a <= b,
+                        },
+                        _ => false,
+                    }
+                }
+            }
+        })
+    }
+}
+
+// Check a rule before it is saved
+pub fn validate_rule(rule: &NotificationRule) -> Result<(), String> {
+    if rule.name.trim().is_empty() {
+        return Err("Rule name is required".to_string());
+    }
+    parse_condition(&rule.condition)?;
+
+    match rule.channel.as_str() {
+        CHANNEL_TELEGRAM => {
+            if rule.target.trim().is_empty() {
+                return Err("Telegram rules need a chat id".to_string());
+            }
+        }
+        CHANNEL_WEBHOOK => {
+            let target = rule.target.trim();
+            if !(target.starts_with("https://") || target.starts_with("http://")) {
+                return Err("Webhook rules need an http(s) URL".to_string());
+            }
+        }
+        other => {
+            return Err(format!(
+                "Unknown channel '{}' (use {} or {})",
+                other, CHANNEL_TELEGRAM, CHANNEL_WEBHOOK
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+// Listen for status changes and dispatch matching rules until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting notifier...");
+    let mut events = app_handle.state::<AppState>().db.subscribe_status_events();
+    // Last status each (rule, item) pair fired for, so repeated writes of the same
+    // status (e.g. encoding progress) don't send the same notification twice
+    let mut last_fired: HashMap<(String, String), String> = HashMap::new();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => handle_event(&app_handle, event, &mut last_fired).await,
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!("Notifier fell behind and skipped {} status event(s)", missed);
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    println!("Notifier stopped");
+}
+
+async fn handle_event(
+    app_handle: &AppHandle,
+    event: StatusEvent,
+    last_fired: &mut HashMap<(String, String), String>,
+) {
+    let app_state = app_handle.state::<AppState>();
+
+    let item = match app_state.db.get_item_by_id(&event.item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!("Notifier could not load item {}: {}", event.item_id, e);
+            return;
+        }
+    };
+    let user_id = item.user_id.clone().unwrap_or_else(|| "local-user".to_string());
+
+    let rules = match app_state.db.get_notification_rules(&user_id).await {
+        Ok(rules) => rules,
+        Err(e) => {
+            eprintln!("Notifier could not load rules for {}: {}", user_id, e);
+            return;
+        }
+    };
+    if !rules.iter().any(|rule| rule.enabled) {
+        return;
+    }
+
+    let settings = app_state.db.get_settings(&user_id).await.unwrap_or_default();
+    let settings = mirrors::item_settings(&settings, &item);
+    let ctx = EventContext {
+        item_id: event.item_id.clone(),
+        status: event.status.clone(),
+        provider: mirrors::main_target(&settings),
+        retries: item.format_rung.unwrap_or(0) as i64,
+        title: item.title.clone(),
+        url: item.url.clone(),
+        message: event.message.clone(),
+    };
+
+    for rule in rules.iter().filter(|rule| rule.enabled) {
+        let condition = match parse_condition(&rule.condition) {
+            Ok(condition) => condition,
+            Err(e) => {
+                eprintln!("Skipping notification rule '{}': {}", rule.name, e);
+                continue;
+            }
+        };
+        let key = (rule.id.clone().unwrap_or_default(), ctx.item_id.clone());
+        if !condition.matches(&ctx) {
+            last_fired.remove(&key);
+            continue;
+        }
+        if last_fired.get(&key) == Some(&ctx.status) {
+            continue;
+        }
+        last_fired.insert(key, ctx.status.clone());
+
+        let result = match rule.channel.as_str() {
+            CHANNEL_TELEGRAM => match settings.telegram_bot_token.as_deref() {
+                Some(token) if !token.is_empty() => send_telegram(token, rule, &ctx).await,
+                _ => Err("No Telegram bot token configured".to_string()),
+            },
+            CHANNEL_WEBHOOK => send_webhook(rule, &ctx).await,
+            other => Err(format!("Unknown channel '{}'", other)),
+        };
+
+        match result {
+            Ok(()) => println!(
+                "Notification rule '{}' fired for item {} ({})",
+                rule.name, ctx.item_id, ctx.status
+            ),
+            Err(e) => eprintln!(
+                "Notification rule '{}' failed for item {}: {}",
+                rule.name, ctx.item_id, e
+            ),
+        }
+    }
+}
+
+fn summary_line(ctx: &EventContext) -> String {
+    format!(
+        "{} is now {}",
+        ctx.title.as_deref().unwrap_or(&ctx.url),
+        ctx.status
+    )
+}
+
+async fn send_telegram(
+    token: &str,
+    rule: &NotificationRule,
+    ctx: &EventContext,
+) -> Result<(), String> {
+    let mut text = format!("PermaVid: {}\nRule: {}", summary_line(ctx), rule.name);
+    if let Some(message) = ctx.message.as_deref().filter(|m| !m.is_empty()) {
+        text.push_str(&format!("\n{}", message));
+    }
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/bot{}/sendMessage", TELEGRAM_API_BASE, token))
+        .json(&json!({
+            "chat_id": rule.target.trim(),
+            "text": text,
+            "disable_web_page_preview": true
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Telegram request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Telegram returned HTTP {}", response.status()));
+    }
+    Ok(())
+}
+
+async fn send_webhook(rule: &NotificationRule, ctx: &EventContext) -> Result<(), String> {
+    let response = reqwest::Client::new()
+        .post(rule.target.trim())
+        .json(&json!({
+            "rule": rule.name,
+            "item_id": ctx.item_id,
+            "status": ctx.status,
+            "provider": ctx.provider,
+            "retries": ctx.retries,
+            "title": ctx.title,
+            "url": ctx.url,
+            "message": ctx.message,
+            "summary": summary_line(ctx),
+            "timestamp": timestamps::to_iso(&timestamps::now())
+        }))
+        .send()
+        .await
+        .map_err(|e| format!("Webhook request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Webhook returned HTTP {}", response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,220 @@
+// Local fallback for when Neon can't be reached (or NEON_DATABASE_URL isn't set).
+// Every queue listing and settings read that succeeds is cached in the app data
+// directory, so the queue and settings can still be shown offline, and items added
+// offline are kept here until the database is reachable again, when they are pushed
+// to it like journaled writes. Settings are cached as stored, with keychain
+// references in place of secrets (see secrets.rs).
+//
+// This covers adding and listing items and reading settings, not processing: the
+// queue processor only picks items from the database, so an item added offline is
+// downloaded once it has been synced.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use crate::db::{AppSettings, QueueItem};
+use crate::journal::PendingWrite;
+
+const STORE_FILE_NAME: &str = "offline_queue.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConnectionStatus {
+    pub online: bool,
+    // Items added offline and journaled writes, both waiting to be synced
+    pub offline_items: usize,
+    pub pending_writes: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct StoreData {
+    // The last queue listing read from the database, for each user
+    cached: Vec<QueueItem>,
+    // Items added while offline, oldest first, not yet in the database
+    pending: Vec<QueueItem>,
+    // Each user's own settings as last read
+    #[serde(default)]
+    settings: HashMap<String, AppSettings>,
+    // The global defaults as last read
+    #[serde(default)]
+    global_settings: Option<AppSettings>,
+}
+
+pub struct OfflineStore {
+    path: Option<PathBuf>,
+    data: Mutex<StoreData>,
+}
+
+impl OfflineStore {
+    // Open the store in `dir`, loading what a previous run left behind.
+    // Without a directory the store is kept in memory only.
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let path = dir.map(|d| d.join(STORE_FILE_NAME));
+
+        let data = path
+            .as_ref()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str::<StoreData>(&content).ok())
+            .unwrap_or_default();
+
+        if !data.pending.is_empty() {
+            println!(
+                "[Offline] Loaded {} item(s) added while offline",
+                data.pending.len()
+            );
+        }
+
+        OfflineStore {
+            path,
+            data: Mutex::new(data),
+        }
+    }
+
+    // Replace the user's cached listing with a fresh one from the database
+    pub fn cache_items(&self, user_id: &str, items: &[QueueItem]) {
+        let mut data = self.data.lock().unwrap();
+        data.cached
+            .retain(|item| item.user_id.as_deref() != Some(user_id));
+        data.cached.extend(items.iter().cloned());
+        self.persist(&data);
+    }
+
+    // The user's queue as last seen, with items added offline first
+    pub fn cached_items(&self, user_id: &str) -> Vec<QueueItem> {
+        let data = self.data.lock().unwrap();
+        let mine = |item: &&QueueItem| item.user_id.as_deref() == Some(user_id);
+        data.pending
+            .iter()
+            .rev()
+            .filter(mine)
+            .chain(data.cached.iter().filter(mine))
+            .cloned()
+            .collect()
+    }
+
+    // Remember settings read from the database; `user_id` None for the global defaults
+    pub fn cache_settings(&self, user_id: Option<&str>, settings: &AppSettings) {
+        let mut data = self.data.lock().unwrap();
+        match user_id {
+            Some(user_id) => {
+                data.settings.insert(user_id.to_string(), settings.clone());
+            }
+            None => data.global_settings = Some(settings.clone()),
+        }
+        self.persist(&data);
+    }
+
+    pub fn cached_settings(&self, user_id: Option<&str>) -> Option<AppSettings> {
+        let data = self.data.lock().unwrap();
+        match user_id {
+            Some(user_id) => data.settings.get(user_id).cloned(),
+            None => data.global_settings.clone(),
+        }
+    }
+
+    pub fn add_pending(&self, item: QueueItem) {
+        let mut data = self.data.lock().unwrap();
+        data.pending.push(item);
+        self.persist(&data);
+    }
+
+    pub fn pending(&self) -> Vec<QueueItem> {
+        self.data.lock().unwrap().pending.clone()
+    }
+
+    pub fn remove_pending(&self, id: &str) {
+        let mut data = self.data.lock().unwrap();
+        data.pending.retain(|item| item.id.as_deref() != Some(id));
+        self.persist(&data);
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.data.lock().unwrap().pending.len()
+    }
+
+    // Show a journaled write on the cached copy of its item until it is replayed
+    pub fn apply(&self, write: &PendingWrite) {
+        let mut data = self.data.lock().unwrap();
+        let StoreData {
+            cached, pending, ..
+        } = &mut *data;
+        let item = match cached
+            .iter_mut()
+            .chain(pending.iter_mut())
+            .find(|item| item.id.as_deref() == Some(write.item_id()))
+        {
+            Some(item) => item,
+            None => return,
+        };
+
+        let message = match write {
+            PendingWrite::ItemStatus {
+                status, message, ..
+            } => {
+                item.status = status.clone();
+                message
+            }
+            PendingWrite::AfterDownload {
+                status,
+                title,
+                local_path,
+                thumbnail_url,
+                message,
+                ..
+            } => {
+                item.status = status.clone();
+                item.title = title.clone().or(item.title.take());
+                item.local_path = local_path.clone().or(item.local_path.take());
+                item.thumbnail_url = thumbnail_url.clone().or(item.thumbnail_url.take());
+                message
+            }
+            PendingWrite::EncodingDetails {
+                status,
+                encoding_progress,
+                message,
+                ..
+            } => {
+                item.status = status.clone();
+                item.encoding_progress = *encoding_progress;
+                message
+            }
+            PendingWrite::DownloadStats(progress) => {
+                item.total_bytes = progress.total_bytes;
+                item.downloaded_bytes = progress.downloaded_bytes;
+                item.speed_bps = progress.speed_bps;
+                item.eta_seconds = progress.eta_seconds;
+                &None
+            }
+        };
+        if let Some(message) = message {
+            item.message = Some(message.text());
+            item.message_code = Some(message.code.clone());
+            item.message_params = Some(serde_json::Value::Object(message.params.clone()));
+        }
+        self.persist(&data);
+    }
+
+    fn persist(&self, data: &StoreData) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+
+        // Write to a temp file first so a crash never leaves a half-written store
+        let tmp_path = path.with_extension("json.tmp");
+        let result = serde_json::to_string(data)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&tmp_path, json).map_err(|e| e.to_string()))
+            .and_then(|_| fs::rename(&tmp_path, path).map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            eprintln!("[Offline] Failed to persist offline store: {}", e);
+        }
+    }
+}
@@ -0,0 +1,87 @@
+// Ring-buffered live tail of yt-dlp output per item.
+// Each line is kept in a small per-item buffer (so the UI can fetch the recent
+// backlog with tail_item_output) and forwarded as an `item_output` event.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+use crate::timestamps;
+
+// Lines kept per item
+const TAIL_CAPACITY: usize = 500;
+
+// Items whose output is kept around after they finish
+const MAX_TRACKED_ITEMS: usize = 20;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutputLine {
+    pub id: String,
+    pub stream: String, // "stdout" or "stderr"
+    pub line: String,
+    #[serde(with = "timestamps::iso8601")]
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Default)]
+pub struct OutputTail {
+    buffers: Mutex<HashMap<String, VecDeque<OutputLine>>>,
+    // Item ids in the order their output started, used to evict old buffers
+    order: Mutex<VecDeque<String>>,
+}
+
+impl OutputTail {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Start a fresh buffer for an item that is about to run yt-dlp
+    pub fn reset(&self, id: &str) {
+        let mut buffers = self.buffers.lock().unwrap();
+        let mut order = self.order.lock().unwrap();
+
+        buffers.insert(id.to_string(), VecDeque::with_capacity(TAIL_CAPACITY));
+        order.retain(|existing| existing != id);
+        order.push_back(id.to_string());
+
+        while order.len() > MAX_TRACKED_ITEMS {
+            if let Some(evicted) = order.pop_front() {
+                buffers.remove(&evicted);
+            }
+        }
+    }
+
+    pub fn push(&self, app_handle: &AppHandle, id: &str, stream: &str, line: &str) {
+        let entry = OutputLine {
+            id: id.to_string(),
+            stream: stream.to_string(),
+            line: line.to_string(),
+            timestamp: timestamps::now(),
+        };
+
+        {
+            let mut buffers = self.buffers.lock().unwrap();
+            let buffer = buffers.entry(id.to_string()).or_default();
+            if buffer.len() >= TAIL_CAPACITY {
+                buffer.pop_front();
+            }
+            buffer.push_back(entry.clone());
+        }
+
+        if let Err(e) = app_handle.emit_all("item_output", entry) {
+            eprintln!("Error emitting item_output event for {}: {}", id, e);
+        }
+    }
+
+    // Buffered lines for an item, oldest first
+    pub fn lines(&self, id: &str) -> Vec<OutputLine> {
+        self.buffers
+            .lock()
+            .unwrap()
+            .get(id)
+            .map(|buffer| buffer.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,148 @@
+// How hard archiving may push the machine. performance_mode picks a profile that sets
+// the priority of the yt-dlp and ffmpeg processes and how many fragments yt-dlp
+// fetches at once:
+//
+//   performance   normal priority, 6 fragments at once
+//   balanced      normal priority, 3 fragments (the default)
+//   responsive    below-normal priority, 1 fragment
+//
+// process_priority ("normal", "below_normal" or "idle") overrides the profile's
+// priority. Below normal means nice 10 and the lowest best-effort IO class on Linux,
+// idle means nice 19 and the idle IO class; on Windows they are the BelowNormal and
+// Idle priority classes. Processes yt-dlp starts itself, such as ffmpeg merging the
+// streams, inherit the priority.
+
+use crate::db::AppSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Priority {
+    Normal,
+    BelowNormal,
+    Idle,
+}
+
+pub struct Profile {
+    pub priority: Priority,
+    pub concurrent_fragments: u32,
+}
+
+fn setting(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+fn parse_mode(value: &str) -> Result<Profile, String> {
+    match value {
+        "performance" => Ok(Profile {
+            priority: Priority::Normal,
+            concurrent_fragments: 6,
+        }),
+        "balanced" => Ok(Profile {
+            priority: Priority::Normal,
+            concurrent_fragments: 3,
+        }),
+        "responsive" => Ok(Profile {
+            priority: Priority::BelowNormal,
+            concurrent_fragments: 1,
+        }),
+        _ => Err(format!(
+            "Unknown performance_mode '{}'; use performance, balanced or responsive",
+            value
+        )),
+    }
+}
+
+fn parse_priority(value: &str) -> Result<Priority, String> {
+    match value {
+        "normal" => Ok(Priority::Normal),
+        "below_normal" => Ok(Priority::BelowNormal),
+        "idle" => Ok(Priority::Idle),
+        _ => Err(format!(
+            "Unknown process_priority '{}'; use normal, below_normal or idle",
+            value
+        )),
+    }
+}
+
+fn try_profile(settings: &AppSettings) -> Result<Profile, String> {
+    let mut profile = parse_mode(
+        setting(&settings.performance_mode)
+            .as_deref()
+            .unwrap_or("balanced"),
+    )?;
+    if let Some(priority) = setting(&settings.process_priority) {
+        profile.priority = parse_priority(&priority)?;
+    }
+    Ok(profile)
+}
+
+// The effective profile; invalid values (rejected when saving) fall back to balanced
+pub fn profile(settings: &AppSettings) -> Profile {
+    try_profile(settings).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        Profile {
+            priority: Priority::Normal,
+            concurrent_fragments: 3,
+        }
+    })
+}
+
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    try_profile(settings).map(|_| ())
+}
+
+// Lower the priority of a process that has just been started. Failing to do so only
+// costs responsiveness, so errors are logged and otherwise ignored.
+pub async fn apply(pid: u32, priority: Priority) {
+    if priority == Priority::Normal {
+        return;
+    }
+    let pid_arg = pid.to_string();
+
+    if cfg!(target_os = "windows") {
+        let class = match priority {
+            Priority::Idle => "Idle",
+            _ => "BelowNormal",
+        };
+        let script = format!("(Get-Process -Id {}).PriorityClass = '{}'", pid_arg, class);
+        run(
+            "powershell",
+            &["-NoProfile", "-NonInteractive", "-Command", &script],
+        )
+        .await;
+        return;
+    }
+
+    let niceness = match priority {
+        Priority::Idle => "19",
+        _ => "10",
+    };
+    run("renice", &["-n", niceness, "-p", &pid_arg]).await;
+    if cfg!(target_os = "linux") {
+        let io_class: &[&str] = match priority {
+            Priority::Idle => &["-c", "3"],
+            _ => &["-c", "2", "-n", "7"],
+        };
+        let mut args = io_class.to_vec();
+        args.extend(["-p", pid_arg.as_str()]);
+        run("ionice", &args).await;
+    }
+}
+
+async fn run(program: &str, args: &[&str]) {
+    match tokio::process::Command::new(program)
+        .args(args)
+        .output()
+        .await
+    {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => eprintln!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(e) => eprintln!("Error executing {}: {}", program, e),
+    }
+}
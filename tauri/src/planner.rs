@@ -0,0 +1,315 @@
+// Pre-flight report for the queue. Every queued item is probed with
+// `yt-dlp --dump-single-json` (a bounded number at a time) for the size of the format
+// it would download, and the totals are set against this session's observed transfer
+// speeds and the monthly upload quotas, so a large batch can be checked before it runs.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+
+use crate::db::{AppSettings, QueueItem};
+use crate::processor::active_source_url;
+use crate::{bandwidth, cancellation, formats, quota, social, titles, tools, AppState};
+
+pub const DEFAULT_CONCURRENCY: usize = 4;
+pub const MAX_CONCURRENCY: usize = 16;
+
+// Every queued item is uploaded here after it is downloaded
+const UPLOAD_PROVIDER: &str = "filemoon";
+const PROBE_TIMEOUT: Duration = Duration::from_secs(90);
+// Recent transfers the speed estimate is based on
+const MAX_SAMPLES: usize = 20;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlannedItem {
+    pub item_id: String,
+    pub url: String,
+    pub title: Option<String>,
+    pub duration_secs: Option<f64>,
+    // None when the probe failed or the site reports no size
+    pub estimated_bytes: Option<i64>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProviderPlan {
+    pub provider: String,
+    pub upload_bytes: i64,
+    pub used_bytes: i64,
+    pub quota_bytes: Option<i64>,
+    pub projected_bytes: i64,
+    // None when no quota is configured
+    pub will_exceed_quota: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QueuePlan {
+    pub items: Vec<PlannedItem>,
+    pub queued_items: usize,
+    pub sized_items: usize,
+    // Items without a size are counted at the average of the sized ones
+    pub estimated_download_bytes: i64,
+    pub uploads: Vec<ProviderPlan>,
+    // Averages over recent transfers in this session; None before the first one
+    pub download_bytes_per_sec: Option<f64>,
+    pub upload_bytes_per_sec: Option<f64>,
+    pub estimated_download_secs: Option<u64>,
+    pub estimated_upload_secs: Option<u64>,
+    // Downloads and uploads one after the other, so an upper bound
+    pub estimated_total_secs: Option<u64>,
+}
+
+struct Sample {
+    bytes: i64,
+    secs: f64,
+}
+
+// Speeds of recent downloads and uploads, kept in memory for the plan's time estimate
+#[derive(Default)]
+pub struct Throughput {
+    downloads: Mutex<VecDeque<Sample>>,
+    uploads: Mutex<VecDeque<Sample>>,
+}
+
+impl Throughput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn samples(&self, direction: &str) -> Option<&Mutex<VecDeque<Sample>>> {
+        match direction {
+            bandwidth::DOWNLOAD => Some(&self.downloads),
+            bandwidth::UPLOAD => Some(&self.uploads),
+            _ => None,
+        }
+    }
+
+    pub fn record(&self, direction: &str, bytes: i64, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        if bytes <= 0 || secs <= 0.0 {
+            return;
+        }
+        if let Some(samples) = self.samples(direction) {
+            let mut samples = samples.lock().unwrap();
+            samples.push_back(Sample { bytes, secs });
+            while samples.len() > MAX_SAMPLES {
+                samples.pop_front();
+            }
+        }
+    }
+
+    pub fn bytes_per_sec(&self, direction: &str) -> Option<f64> {
+        let samples = self.samples(direction)?.lock().unwrap();
+        let bytes: i64 = samples.iter().map(|s| s.bytes).sum();
+        let secs: f64 = samples.iter().map(|s| s.secs).sum();
+        if secs > 0.0 {
+            Some(bytes as f64 / secs)
+        } else {
+            None
+        }
+    }
+}
+
+fn size_of(format: &JsonValue) -> Option<i64> {
+    format
+        .get("filesize")
+        .and_then(|v| v.as_i64())
+        .or_else(|| format.get("filesize_approx").and_then(|v| v.as_i64()))
+        .filter(|size| *size > 0)
+}
+
+// Size of what yt-dlp picked; merged formats report their parts separately
+fn estimated_size(info: &JsonValue) -> Option<i64> {
+    if let Some(parts) = info.get("requested_formats").and_then(|v| v.as_array()) {
+        let sizes: Vec<i64> = parts.iter().filter_map(size_of).collect();
+        if !sizes.is_empty() && sizes.len() == parts.len() {
+            return Some(sizes.iter().sum());
+        }
+    }
+    size_of(info)
+}
+
+// What yt-dlp reports for the format the processor would download; the disk space
+// preflight uses the size too
+pub async fn probe(item: &QueueItem, settings: &AppSettings) -> PlannedItem {
+    let url = active_source_url(item);
+    let mut planned = PlannedItem {
+        item_id: item.id.clone().unwrap_or_default(),
+        url: url.clone(),
+        title: item.title.clone(),
+        duration_secs: None,
+        estimated_bytes: None,
+        error: None,
+    };
+
+    // The same format the processor would try first for this item
+    let ladder = formats::ladder(
+        item.format_override
+            .as_deref()
+            .or(settings.preferred_format.as_deref()),
+        settings.format_fallback_ladder.as_deref(),
+        social::default_formats(&url),
+    );
+    let rung = (item.format_rung.unwrap_or(0).max(0) as usize).min(ladder.len() - 1);
+
+    let mut cmd = Command::new(tools::ytdlp_binary());
+    cmd.arg("--dump-single-json")
+        .arg("--skip-download")
+        .arg("--no-playlist")
+        .arg("--no-warnings");
+    if let Some(format) = &ladder[rung] {
+        cmd.arg("--format").arg(format);
+    }
+    cmd.arg(&url);
+
+    let output = match cancellation::output_with_timeout(&mut cmd, PROBE_TIMEOUT).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            planned.error = Some(format!("Failed to run yt-dlp: {}", e));
+            return planned;
+        }
+        Err(_) => {
+            planned.error = Some(format!(
+                "Probe timed out after {} s",
+                PROBE_TIMEOUT.as_secs()
+            ));
+            return planned;
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        planned.error = Some(
+            stderr
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("yt-dlp failed")
+                .trim()
+                .to_string(),
+        );
+        return planned;
+    }
+
+    match serde_json::from_slice::<JsonValue>(&output.stdout) {
+        Ok(info) => {
+            if planned.title.is_none() {
+                planned.title = titles::from_info(&url, &info);
+            }
+            planned.duration_secs = info.get("duration").and_then(|v| v.as_f64());
+            planned.estimated_bytes = estimated_size(&info);
+        }
+        Err(e) => planned.error = Some(format!("Unreadable yt-dlp output: {}", e)),
+    }
+    planned
+}
+
+fn secs_for(bytes: i64, bytes_per_sec: Option<f64>) -> Option<u64> {
+    bytes_per_sec
+        .filter(|speed| *speed > 0.0)
+        .map(|speed| (bytes as f64 / speed).ceil() as u64)
+}
+
+pub async fn plan(
+    app_state: &AppState,
+    user_id: &str,
+    concurrency: Option<usize>,
+) -> Result<QueuePlan, String> {
+    let settings = Arc::new(
+        app_state
+            .db
+            .get_settings(user_id)
+            .await
+            .map_err(|e| format!("Failed to retrieve settings: {}", e))?,
+    );
+    let queued: Vec<QueueItem> = app_state
+        .db
+        .get_items_in_statuses(&["queued"])
+        .await
+        .map_err(|e| format!("Failed to retrieve queue items: {}", e))?
+        .into_iter()
+        .filter(|item| item.user_id.as_deref() == Some(user_id))
+        .collect();
+
+    let permits = Arc::new(Semaphore::new(
+        concurrency
+            .unwrap_or(DEFAULT_CONCURRENCY)
+            .clamp(1, MAX_CONCURRENCY),
+    ));
+    let probes: Vec<_> = queued
+        .into_iter()
+        .map(|item| {
+            let permits = permits.clone();
+            let settings = settings.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await;
+                probe(&item, &settings).await
+            })
+        })
+        .collect();
+    let mut items = Vec::with_capacity(probes.len());
+    for handle in probes {
+        items.push(
+            handle
+                .await
+                .map_err(|e| format!("Probe task failed: {}", e))?,
+        );
+    }
+
+    let sizes: Vec<i64> = items
+        .iter()
+        .filter_map(|item| item.estimated_bytes)
+        .collect();
+    let average = if sizes.is_empty() {
+        0
+    } else {
+        sizes.iter().sum::<i64>() / sizes.len() as i64
+    };
+    let unsized_items = (items.len() - sizes.len()) as i64;
+    let estimated_download_bytes = sizes.iter().sum::<i64>() + average * unsized_items;
+
+    let quota_report = quota::report(app_state, user_id).await?;
+    let uploads = quota_report
+        .providers
+        .into_iter()
+        .map(|usage| {
+            let upload_bytes = if usage.provider == UPLOAD_PROVIDER {
+                estimated_download_bytes
+            } else {
+                0
+            };
+            let projected_bytes = usage.bytes_used + upload_bytes;
+            ProviderPlan {
+                will_exceed_quota: usage.quota_bytes.map(|quota| projected_bytes > quota),
+                provider: usage.provider,
+                upload_bytes,
+                used_bytes: usage.bytes_used,
+                quota_bytes: usage.quota_bytes,
+                projected_bytes,
+            }
+        })
+        .collect();
+
+    let download_bytes_per_sec = app_state.throughput.bytes_per_sec(bandwidth::DOWNLOAD);
+    let upload_bytes_per_sec = app_state.throughput.bytes_per_sec(bandwidth::UPLOAD);
+    let estimated_download_secs = secs_for(estimated_download_bytes, download_bytes_per_sec);
+    let estimated_upload_secs = secs_for(estimated_download_bytes, upload_bytes_per_sec);
+
+    Ok(QueuePlan {
+        queued_items: items.len(),
+        sized_items: sizes.len(),
+        items,
+        estimated_download_bytes,
+        uploads,
+        download_bytes_per_sec,
+        upload_bytes_per_sec,
+        estimated_download_secs,
+        estimated_upload_secs,
+        estimated_total_secs: estimated_download_secs
+            .zip(estimated_upload_secs)
+            .map(|(down, up)| down + up),
+    })
+}
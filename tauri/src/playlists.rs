@@ -0,0 +1,370 @@
+// Playlist and channel URLs. `yt-dlp --flat-playlist -J` lists the entries without
+// resolving each video, every entry becomes its own queue item with the title yt-dlp
+// reported, and all of them share the id of one playlists row so the UI can show
+// them together. A channel answers with one nested playlist per tab (Videos, Shorts,
+// Live), whose entries are collected too.
+//
+// A playlist's rollup sums up its items once they have all settled, e.g. "complete
+// with 5 failures", and get_children lists the items behind it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::time::Duration;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::db::{AppSettings, Playlist, QueueItem};
+use crate::{cancellation, timestamps, tools, urls, AppState};
+
+// Entries queued from one URL unless the caller asks for fewer
+pub const MAX_ENTRIES: usize = 1000;
+
+const LIST_TIMEOUT: Duration = Duration::from_secs(180);
+// A channel nests its tabs one level deep; anything deeper is ignored
+const MAX_NESTING: usize = 2;
+
+pub const ROLLUP_IN_PROGRESS: &str = "in_progress";
+pub const ROLLUP_COMPLETE: &str = "complete";
+pub const ROLLUP_COMPLETE_WITH_FAILURES: &str = "complete_with_failures";
+
+// Statuses an item ends in once it has made it to the provider
+const DONE_STATUSES: &[&str] = &["uploaded", "transferring", "encoding", "encoded"];
+const FAILED_STATUSES: &[&str] = &[
+    "failed",
+    "unsupported",
+    "auth_required",
+    "blocked_missing_ffmpeg",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistImport {
+    pub playlist: Playlist,
+    pub item_ids: Vec<String>,
+    // Entries that were already queued or otherwise rejected, with the reason
+    pub skipped: Vec<SkippedEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub url: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PlaylistRollup {
+    pub playlist: Playlist,
+    pub total: i64,
+    pub done: i64,
+    pub failed: i64,
+    pub cancelled: i64,
+    // Queued, downloading, uploading, or waiting for review
+    pub pending: i64,
+    // One of the ROLLUP_* values
+    pub status: String,
+    pub summary: String,
+}
+
+struct Listing {
+    title: Option<String>,
+    source_id: Option<String>,
+    entries: Vec<PlaylistEntry>,
+}
+
+fn text(info: &JsonValue, key: &str) -> Option<String> {
+    info.get(key)
+        .and_then(|v| v.as_str())
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// Flat entries carry a full URL for most sites and only the video id for some
+fn entry_url(entry: &JsonValue) -> Option<String> {
+    text(entry, "url")
+        .filter(|url| url.starts_with("http://") || url.starts_with("https://"))
+        .or_else(|| text(entry, "webpage_url"))
+        .or_else(|| {
+            let id = text(entry, "id")?;
+            match text(entry, "ie_key").as_deref() {
+                Some("Youtube") | None => urls::canonical_url_for_id("youtube", &id).ok(),
+                Some(_) => None,
+            }
+        })
+}
+
+fn is_unopened_list(entry: &JsonValue) -> bool {
+    text(entry, "_type").as_deref() == Some("playlist")
+        || text(entry, "ie_key").map_or(false, |key| key.ends_with("Tab"))
+}
+
+fn collect_entries(info: &JsonValue, depth: usize, limit: usize, out: &mut Vec<PlaylistEntry>) {
+    let entries = match info.get("entries").and_then(|v| v.as_array()) {
+        Some(entries) => entries,
+        None => return,
+    };
+    for entry in entries {
+        if out.len() >= limit {
+            return;
+        }
+        if entry.is_null() {
+            continue;
+        }
+        if entry.get("entries").is_some() {
+            if depth < MAX_NESTING {
+                collect_entries(entry, depth + 1, limit, out);
+            }
+            continue;
+        }
+        // A nested list yt-dlp didn't open (a channel tab, say) is not a video
+        if is_unopened_list(entry) {
+            continue;
+        }
+        if let Some(url) = entry_url(entry) {
+            let url = urls::normalize_url(&url);
+            if !out.iter().any(|e| e.url == url) {
+                out.push(PlaylistEntry {
+                    url,
+                    title: text(entry, "title"),
+                });
+            }
+        }
+    }
+}
+
+async fn list(url: &str, limit: usize) -> Result<Listing, String> {
+    let mut cmd = Command::new(tools::ytdlp_binary());
+    cmd.arg("--flat-playlist")
+        .arg("-J")
+        .arg("--no-warnings")
+        .arg("--playlist-end")
+        .arg(limit.to_string())
+        .arg(url);
+
+    let output = match cancellation::output_with_timeout(&mut cmd, LIST_TIMEOUT).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(format!("Failed to run yt-dlp: {}", e)),
+        Err(_) => {
+            return Err(format!(
+                "Listing the playlist timed out after {} s",
+                LIST_TIMEOUT.as_secs()
+            ))
+        }
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr
+            .lines()
+            .rev()
+            .find(|line| !line.trim().is_empty())
+            .unwrap_or("yt-dlp failed")
+            .trim()
+            .to_string());
+    }
+
+    let info: JsonValue = serde_json::from_slice(&output.stdout)
+        .map_err(|e| format!("Unreadable yt-dlp output: {}", e))?;
+    let mut entries = Vec::new();
+    collect_entries(&info, 0, limit, &mut entries);
+    Ok(Listing {
+        title: text(&info, "title"),
+        source_id: text(&info, "id"),
+        entries,
+    })
+}
+
+fn queue_item(entry: &PlaylistEntry, user_id: &str, playlist: &Playlist) -> QueueItem {
+    QueueItem {
+        id: None,
+        url: entry.url.clone(),
+        status: "queued".to_string(),
+        message: Some(format!(
+            "Queued from playlist '{}'",
+            playlist.title.as_deref().unwrap_or(&playlist.url)
+        )),
+        title: entry.title.clone(),
+        filemoon_url: None,
+        encoding_progress: None,
+        thumbnail_url: None,
+        added_at: None,
+        updated_at: None,
+        local_path: None,
+        user_id: Some(user_id.to_string()),
+        short_url: None,
+        thumbnail_uploaded: None,
+        locked: None,
+        format_rung: None,
+        format_used: None,
+        download_sections: None,
+        mirror_urls: None,
+        source_index: None,
+        priority: None,
+        failure_count: None,
+        language: None,
+        caption_languages: None,
+        format_override: None,
+        tags: None,
+        template_id: None,
+        upload_verified_at: None,
+        message_code: None,
+        message_params: None,
+        playlist_id: Some(playlist.id.clone()),
+        subtitle_paths: None,
+        upload_provider: None,
+        upload_url: None,
+        collection: None,
+        retry_count: None,
+        max_retries: None,
+        next_retry_at: None,
+        total_bytes: None,
+        downloaded_bytes: None,
+        speed_bps: None,
+        eta_seconds: None,
+        transfer_mode: None,
+        remote_filecode: None,
+        upload_target: None,
+    }
+}
+
+// List the playlist or channel at `url` and queue each of its entries
+pub async fn import(
+    app_state: &AppState,
+    url: &str,
+    user_id: &str,
+    limit: Option<usize>,
+) -> Result<PlaylistImport, String> {
+    let url = url.trim();
+    if url.is_empty() {
+        return Err("No playlist URL given".to_string());
+    }
+    let limit = limit.unwrap_or(MAX_ENTRIES).clamp(1, MAX_ENTRIES);
+
+    let listing = list(url, limit).await?;
+    if listing.entries.is_empty() {
+        return Err(format!("No videos found at {}", url));
+    }
+
+    let mut playlist = Playlist {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        url: url.to_string(),
+        title: listing.title,
+        source_id: listing.source_id,
+        entry_count: 0,
+        added_at: timestamps::now(),
+    };
+
+    let mut item_ids = Vec::new();
+    let mut skipped = Vec::new();
+    for entry in &listing.entries {
+        match app_state
+            .db
+            .add_queue_item(&queue_item(entry, user_id, &playlist))
+            .await
+        {
+            Ok(id) => item_ids.push(id),
+            Err(e) => skipped.push(SkippedEntry {
+                url: entry.url.clone(),
+                reason: e.to_string(),
+            }),
+        }
+    }
+
+    if !item_ids.is_empty() {
+        app_state.queue_wakeup.wake();
+    }
+    playlist.entry_count = item_ids.len() as i32;
+    app_state
+        .db
+        .add_playlist(&playlist)
+        .await
+        .map_err(|e| format!("Failed to save playlist: {}", e))?;
+
+    Ok(PlaylistImport {
+        playlist,
+        item_ids,
+        skipped,
+    })
+}
+
+fn plural(count: i64, noun: &str) -> String {
+    format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" })
+}
+
+// Without auto-upload a downloaded item is as far as it goes
+fn rollup(playlist: Playlist, counts: &[(&str, i64)], settings: &AppSettings) -> PlaylistRollup {
+    let downloaded_is_done = settings.auto_upload.as_deref() != Some("true");
+    let (mut done, mut failed, mut cancelled, mut pending) = (0, 0, 0, 0);
+    for (status, count) in counts {
+        if DONE_STATUSES.contains(status) || (downloaded_is_done && *status == "downloaded") {
+            done += count;
+        } else if FAILED_STATUSES.contains(status) {
+            failed += count;
+        } else if *status == "cancelled" {
+            cancelled += count;
+        } else {
+            pending += count;
+        }
+    }
+    let total = done + failed + cancelled + pending;
+
+    let (status, summary) = if pending > 0 {
+        (
+            ROLLUP_IN_PROGRESS,
+            format!("{} of {} done, {}", done, total, plural(failed, "failure")),
+        )
+    } else if failed > 0 {
+        (
+            ROLLUP_COMPLETE_WITH_FAILURES,
+            format!("complete with {}", plural(failed, "failure")),
+        )
+    } else {
+        (ROLLUP_COMPLETE, "complete".to_string())
+    };
+
+    PlaylistRollup {
+        playlist,
+        total,
+        done,
+        failed,
+        cancelled,
+        pending,
+        status: status.to_string(),
+        summary,
+    }
+}
+
+// Rollups of all of the user's playlists, newest first
+pub async fn rollups(app_state: &AppState, user_id: &str) -> Result<Vec<PlaylistRollup>, String> {
+    let playlists = app_state
+        .db
+        .get_playlists(user_id)
+        .await
+        .map_err(|e| format!("Database error retrieving playlists: {}", e))?;
+    let counts = app_state
+        .db
+        .get_playlist_status_counts(user_id)
+        .await
+        .map_err(|e| format!("Database error counting playlist items: {}", e))?;
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve settings: {}", e))?;
+
+    Ok(playlists
+        .into_iter()
+        .map(|playlist| {
+            let mine: Vec<(&str, i64)> = counts
+                .iter()
+                .filter(|(playlist_id, _, _)| *playlist_id == playlist.id)
+                .map(|(_, status, count)| (status.as_str(), *count))
+                .collect();
+            rollup(playlist, &mine, &settings)
+        })
+        .collect())
+}
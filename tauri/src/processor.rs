@@ -0,0 +1,1513 @@
+// The background queue processor. process_queue_background picks the next queued
+// item whose download window is open and runs process_item for it, which downloads
+// it with yt-dlp (falling back through formats and mirrors), records what came down
+// and hands the item to the upload queue.
+
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tauri::{Manager, State};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::time::sleep;
+
+use crate::db::{AppSettings, QueueItem};
+use crate::download_window::DownloadWindow;
+use crate::library_commands::build_provenance_record;
+use crate::media_library::{self, LibraryMetadata};
+use crate::messages::{self, ItemMessage};
+use crate::queue_commands::review_required;
+use crate::watchdog::{self, StallReason};
+use crate::{
+    auto_upload, bandwidth, cancellation, cookies, disk_space, extract_facebook_video_id,
+    forensics, formats, item_download_dir, jobs, long_paths, performance, progress, queue_wakeup,
+    remote_transfer, sanitize_filename, sections, social, subtitles, titles, tools, transcode,
+    urls, warc, AppState,
+};
+
+// The URL a download attempt should use: the primary URL, or the mirror the item
+// has fallen back to
+pub fn active_source_url(item: &QueueItem) -> String {
+    let index = item.source_index.unwrap_or(0).max(0) as usize;
+    if index == 0 {
+        return item.url.clone();
+    }
+    item.mirror_urls
+        .as_ref()
+        .and_then(|mirrors| mirrors.get(index - 1))
+        .cloned()
+        .unwrap_or_else(|| item.url.clone())
+}
+
+// Re-queue a download whose source is gone on the next mirror URL, starting the
+// format ladder over. Returns false when there is no mirror left to try.
+async fn try_mirror_fallback(
+    app_handle: &tauri::AppHandle,
+    item: &QueueItem,
+    reason: &str,
+) -> bool {
+    let item_id = match item.id.as_deref() {
+        Some(id) => id,
+        None => return false,
+    };
+    let mirrors = item.mirror_urls.clone().unwrap_or_default();
+    let next_index = item.source_index.unwrap_or(0).max(0) as usize + 1;
+    let next_url = match mirrors.get(next_index - 1) {
+        Some(url) => url,
+        None => return false,
+    };
+
+    let app_state: State<'_, AppState> = app_handle.state();
+    if let Err(e) = app_state
+        .db
+        .set_source_index(item_id, next_index as i32)
+        .await
+    {
+        eprintln!("Error saving source index for item {}: {}", item_id, e);
+        return false;
+    }
+    if let Err(e) = app_state.db.set_format_rung(item_id, 0).await {
+        eprintln!("Error resetting format rung for item {}: {}", item_id, e);
+    }
+
+    let message = ItemMessage::new(messages::DOWNLOAD_MIRROR_FALLBACK)
+        .with("reason", reason)
+        .with("mirror", next_index)
+        .with("mirror_count", mirrors.len())
+        .with("url", next_url.clone());
+    app_state
+        .db
+        .update_item_status(item_id, "queued", Some(message))
+        .await
+        .is_ok()
+}
+
+// Re-queue a download the watchdog killed, after a backoff that grows with each
+// stalled attempt. Marks the item failed once it has stalled too many times.
+async fn reschedule_stalled(app_handle: &tauri::AppHandle, item_id: &str, reason: StallReason) {
+    let app_state: State<'_, AppState> = app_handle.state();
+    if let Err(e) = app_state.db.record_download_failure(item_id).await {
+        eprintln!("Error recording failure for item {}: {}", item_id, e);
+    }
+    let (status, message) = match app_state.stall_backoff.record_stall(item_id) {
+        Some((attempt, delay)) => (
+            "queued",
+            ItemMessage::new(messages::DOWNLOAD_STALLED_RETRY)
+                .with("reason", reason.describe())
+                .with("minutes", delay.as_secs() / 60)
+                .with("attempt", attempt)
+                .with("max_attempts", watchdog::MAX_STALL_RETRIES),
+        ),
+        None => (
+            "failed",
+            ItemMessage::new(messages::DOWNLOAD_STALLED_GAVE_UP)
+                .with("reason", reason.describe())
+                .with("max_attempts", watchdog::MAX_STALL_RETRIES),
+        ),
+    };
+
+    println!("Item {}: {}", item_id, message.text());
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, status, Some(message))
+        .await
+    {
+        eprintln!("Error rescheduling stalled item {}: {}", item_id, e);
+    }
+}
+
+// Re-queue a failed download on the next rung of the format ladder. Returns false
+// when the last rung has already been tried, or when the failure is one no other
+// format can fix (see tools::is_terminal).
+async fn try_format_fallback(
+    app_handle: &tauri::AppHandle,
+    item_id: &str,
+    format_ladder: &[Option<String>],
+    format_rung: usize,
+    stderr: &str,
+) -> bool {
+    let next_rung = format_rung + 1;
+    if next_rung >= format_ladder.len() || tools::is_terminal(stderr) {
+        return false;
+    }
+
+    let app_state: State<'_, AppState> = app_handle.state();
+    if let Err(e) = app_state
+        .db
+        .set_format_rung(item_id, next_rung as i32)
+        .await
+    {
+        eprintln!("Error saving format rung for item {}: {}", item_id, e);
+        return false;
+    }
+
+    let message = ItemMessage::new(messages::DOWNLOAD_FORMAT_FALLBACK)
+        .with("failed_format", formats::label(format_ladder[format_rung].as_deref()))
+        .with("next_format", formats::label(format_ladder[next_rung].as_deref()));
+    app_state
+        .db
+        .update_item_status(item_id, "queued", Some(message))
+        .await
+        .is_ok()
+}
+
+// Status message for a yt-dlp run that exited unsuccessfully
+fn exit_failure(code: Option<i32>, stderr: &str) -> ItemMessage {
+    ItemMessage::new(messages::DOWNLOAD_FAILED)
+        .with("exit_code", code)
+        .with("stderr", if stderr.is_empty() { "None" } else { stderr })
+}
+
+// Mark an item as unsupported (DRM, unsupported site). This is terminal: retry_item
+// and retry_all_failed leave these items alone.
+async fn mark_unsupported(
+    app_handle: &tauri::AppHandle,
+    item_id: &str,
+    reason: &str,
+    stderr: &str,
+) {
+    println!("Item {} is unsupported, skipping: {}", item_id, reason);
+    let detail = stderr
+        .lines()
+        .rev()
+        .find(|line| line.contains("ERROR"))
+        .unwrap_or("")
+        .trim();
+    let message = if detail.is_empty() {
+        ItemMessage::new(messages::DOWNLOAD_UNSUPPORTED).with("reason", reason)
+    } else {
+        ItemMessage::new(messages::DOWNLOAD_UNSUPPORTED_DETAIL)
+            .with("reason", reason)
+            .with("detail", detail)
+    };
+
+    let app_state: State<'_, AppState> = app_handle.state();
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "unsupported", Some(message))
+        .await
+    {
+        eprintln!("Error marking item {} as unsupported: {}", item_id, e);
+    }
+}
+
+// Mark an item as needing login cookies (private, members-only or age-restricted).
+// Retrying without changing the cookie settings would fail the same way, so the
+// item waits as "auth_required" until the user retries it.
+async fn mark_auth_required(
+    app_handle: &tauri::AppHandle,
+    item_id: &str,
+    reason: &str,
+    stderr: &str,
+    settings: &AppSettings,
+) {
+    println!("Item {} needs login cookies: {}", item_id, reason);
+    let detail = stderr
+        .lines()
+        .rev()
+        .find(|line| line.contains("ERROR"))
+        .unwrap_or(reason)
+        .trim();
+    let message = match cookies::CookieSource::from_settings(settings) {
+        Ok(Some(source)) => ItemMessage::new(messages::DOWNLOAD_AUTH_REJECTED)
+            .with("cookies", source.describe()),
+        _ => ItemMessage::new(messages::DOWNLOAD_AUTH_REQUIRED),
+    }
+    .with("reason", reason)
+    .with("detail", detail);
+
+    let app_state: State<'_, AppState> = app_handle.state();
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "auth_required", Some(message))
+        .await
+    {
+        eprintln!("Error marking item {} as auth_required: {}", item_id, e);
+    }
+}
+
+// Update yt-dlp after an extractor failure and re-queue the item once.
+// Returns true if the item was re-queued.
+async fn try_extractor_recovery(app_handle: &tauri::AppHandle, item_id: &str) -> bool {
+    let app_state: State<'_, AppState> = app_handle.state();
+    if !app_state.extractor_recovery.claim_retry(item_id) {
+        println!(
+            "Item {} already retried after a yt-dlp update, not retrying again",
+            item_id
+        );
+        return false;
+    }
+
+    let update_result = app_state.extractor_recovery.update_ytdlp().await;
+    let (updated, output) = match &update_result {
+        Ok(output) => (true, output.clone()),
+        Err(e) => (false, e.clone()),
+    };
+
+    let requeued = updated
+        && app_state
+            .db
+            .update_item_status(
+                item_id,
+                "queued",
+                Some(ItemMessage::new(messages::DOWNLOAD_EXTRACTOR_UPDATED)),
+            )
+            .await
+            .is_ok();
+
+    let payload = serde_json::json!({
+        "id": item_id,
+        "updated": updated,
+        "requeued": requeued,
+        "output": output,
+    });
+    if let Err(e) = app_handle.emit_all("ytdlp_self_update", payload) {
+        eprintln!(
+            "Error emitting ytdlp_self_update event for {}: {}",
+            item_id, e
+        );
+    }
+
+    requeued
+}
+
+// The queued item to download next: one force_start_item asked for, otherwise the
+// first in queue order whose owner's download window is open. None when there is
+// nothing to do (or the database can't be read; the caller waits and tries again).
+async fn next_item_to_process(app_state: &AppState) -> Option<QueueItem> {
+    for id in app_state.forced_starts.ids() {
+        app_state.forced_starts.remove(&id);
+        match app_state.db.get_item_by_id(&id).await {
+            Ok(Some(item)) if item.status == "queued" && item.locked != Some(true) => {
+                println!("Force-starting item {}", id);
+                return Some(item);
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("DB Error fetching force-started item {}: {}", id, e),
+        }
+    }
+
+    // Items still starting in another task may not be marked "downloading" yet
+    let mut deferred_ids = app_state.stall_backoff.deferred_ids();
+    deferred_ids.extend(app_state.concurrency.active_ids(bandwidth::DOWNLOAD));
+    // Users whose download window is closed right now
+    let mut waiting_users: Vec<String> = Vec::new();
+    loop {
+        let item = match app_state
+            .db
+            .get_next_queued_item(&deferred_ids, &waiting_users)
+            .await
+        {
+            Ok(Some(item)) => item,
+            Ok(None) => return None,
+            Err(e) => {
+                eprintln!("DB Error fetching next queued item: {}", e);
+                return None;
+            }
+        };
+        let user_id = item
+            .user_id
+            .clone()
+            .unwrap_or_else(|| "local-user".to_string());
+        // A settings error is reported when the item is processed
+        let window = match app_state.db.get_settings(&user_id).await {
+            Ok(settings) => DownloadWindow::from_settings(&settings),
+            Err(_) => return Some(item),
+        };
+        match window {
+            Ok(Some(window)) if !window.is_open(chrono::Local::now()) => {
+                waiting_users.push(user_id)
+            }
+            Ok(_) => return Some(item),
+            Err(e) => {
+                eprintln!("Ignoring download window of user {}: {}", user_id, e);
+                return Some(item);
+            }
+        }
+    }
+}
+
+// Download one queued item and take it through to the upload queue. Runs as its
+// own task, so several items can download at once (see concurrency.rs).
+async fn process_item(app_handle: tauri::AppHandle, next_item: QueueItem) {
+    let item_id = next_item.id.clone().unwrap_or_default();
+    let item_url = active_source_url(&next_item);
+    println!("Processing queue item: ID={}, URL={}", item_id, item_url);
+
+    // Filemoon fetches remote-transfer items itself (see remote_transfer.rs)
+    if remote_transfer::is_remote(&next_item) {
+        remote_transfer::start(&app_handle.state::<AppState>(), &next_item).await;
+        return;
+    }
+
+    let download_dir: String;
+    let mut proceed_with_download = true; // Assume true initially
+    let mut paused_for_space = false;
+
+    // Get settings and mark as downloading
+    let app_state: State<'_, AppState> = app_handle.state();
+    let settings = match app_state
+        .db
+        .get_settings(next_item.user_id.as_deref().unwrap_or("local-user"))
+        .await
+    {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error getting settings for item {}: {}", item_id, e);
+            if let Err(update_err) = app_state
+                .db
+                .update_item_status(
+                    &item_id,
+                    "failed",
+                    Some(
+                        ItemMessage::new(messages::DOWNLOAD_SETTINGS_FAILED)
+                            .with("error", e.to_string()),
+                    ),
+                )
+                .await
+            {
+                eprintln!("Error updating status after settings error: {}", update_err);
+            }
+            AppSettings::default() // Return default to avoid breaking flow, but log error
+        }
+    };
+
+    let download_dir_setting = settings.download_directory.clone();
+    download_dir = match download_dir_setting {
+        Some(dir) if !dir.is_empty() => dir,
+        _ => {
+            match dirs::download_dir() {
+                Some(dir) => dir.to_string_lossy().to_string(),
+                None => {
+                    let failure = ItemMessage::new(messages::DOWNLOAD_DIR_UNKNOWN);
+                    eprintln!("Error for item {}: {}", item_id, failure.text());
+                    if let Err(update_err) = app_state
+                        .db
+                        .update_item_status(&item_id, "failed", Some(failure))
+                        .await
+                    {
+                        eprintln!(
+                            "Error updating status after directory error: {}",
+                            update_err
+                        );
+                    }
+                    String::new() // Return empty string, check later
+                }
+            }
+        }
+    };
+
+    let download_dir = if download_dir.is_empty() {
+        download_dir
+    } else {
+        item_download_dir(&download_dir, &item_id)
+            .to_string_lossy()
+            .to_string()
+    };
+
+    // Pick the format for this attempt from the fallback ladder. A per-item
+    // format (e.g. from a queue template) takes the place of the preferred one.
+    let format_ladder = formats::ladder(
+        next_item
+            .format_override
+            .as_deref()
+            .or(settings.preferred_format.as_deref()),
+        settings.format_fallback_ladder.as_deref(),
+        social::default_formats(&item_url),
+    );
+    let format_rung =
+        (next_item.format_rung.unwrap_or(0).max(0) as usize).min(format_ladder.len() - 1);
+    let mut format = format_ladder[format_rung].clone();
+
+    // Merging separate video and audio streams needs ffmpeg; without it yt-dlp
+    // quietly keeps a video-only file. Either switch to a pre-merged format or
+    // hold the item, as missing_ffmpeg_action says.
+    let mut starting_message = ItemMessage::new(messages::DOWNLOAD_STARTING);
+    let mut missing_ffmpeg = None;
+    if formats::needs_merge(format.as_deref())
+        && !tools::detect_ffmpeg(settings.ffmpeg_path.as_deref())
+            .await
+            .found
+    {
+        if formats::block_on_missing_ffmpeg(settings.missing_ffmpeg_action.as_deref()) {
+            missing_ffmpeg = Some(
+                ItemMessage::new(messages::DOWNLOAD_FFMPEG_MISSING)
+                    .with("format", formats::label(format.as_deref())),
+            );
+        } else {
+            let premerged = formats::premerged(format.as_deref());
+            println!(
+                "Item {}: ffmpeg not found; downloading {} instead of {}",
+                item_id,
+                premerged,
+                formats::label(format.as_deref())
+            );
+            starting_message = ItemMessage::new(messages::DOWNLOAD_STARTING_PREMERGED)
+                .with("format", premerged.clone());
+            format = Some(premerged);
+        }
+    }
+
+    if download_dir.is_empty() {
+        proceed_with_download = false;
+    } else if let Err(e) =
+        fs::create_dir_all(long_paths::extended(Path::new(&download_dir)))
+    {
+        let failure = ItemMessage::new(messages::DOWNLOAD_DIR_FAILED)
+            .with("path", download_dir.clone())
+            .with("error", e.to_string());
+        eprintln!("Error for item {}: {}", item_id, failure.text());
+        if let Err(update_err) = app_state
+            .db
+            .update_item_status(&item_id, "failed", Some(failure))
+            .await
+        {
+            eprintln!(
+                "Error updating status after directory creation error: {}",
+                update_err
+            );
+        }
+        proceed_with_download = false;
+    } else if let Some(message) = missing_ffmpeg {
+        println!("Item {}: {}", item_id, message.text());
+        if let Err(e) = app_state
+            .db
+            .update_item_status(&item_id, "blocked_missing_ffmpeg", Some(message))
+            .await
+        {
+            eprintln!(
+                "Error marking item {} as blocked_missing_ffmpeg: {}",
+                item_id, e
+            );
+        }
+        proceed_with_download = false;
+    } else if !disk_space::has_room(&app_handle, &settings, &download_dir).await {
+        // Leave the item queued; it starts once space has been freed
+        paused_for_space = true;
+        proceed_with_download = false;
+    } else if let Some(message) =
+        disk_space::preflight(&app_state, &settings, &next_item, &download_dir).await
+    {
+        // Too big for the space left; queued again by disk_space::release_blocked
+        println!("Item {}: {}", item_id, message.text());
+        if let Err(e) = app_state
+            .db
+            .update_item_status(&item_id, disk_space::BLOCKED_STATUS, Some(message))
+            .await
+        {
+            eprintln!(
+                "Error marking item {} as blocked on disk space: {}",
+                item_id, e
+            );
+        }
+        proceed_with_download = false;
+    } else if let Err(e) = app_state
+        .db
+        .update_item_status(&item_id, "downloading", Some(starting_message))
+        .await
+    {
+        eprintln!("Error marking item {} as downloading: {}", item_id, e);
+        proceed_with_download = false; // Failed to update status, don't proceed
+    }
+
+    // Execute Download (if safe to proceed)
+    if proceed_with_download {
+        // Check the flag
+        println!("Starting yt-dlp download for item: {}...", item_id);
+
+        // yt-dlp Command Construction
+        // Use a simple, safe output template using the video ID. download_dir is
+        // already this item's own subdirectory, so IDs only need to be unique per item.
+        let output_template = format!("%(id)s.%(ext)s");
+        let output_path_base = Path::new(&download_dir); // Just the directory
+                                                         // output_path_str will contain the directory and the template string
+        let output_path_str = output_path_base
+            .join(&output_template)
+            .to_string_lossy()
+            .to_string();
+
+        let ytdlp_path = tools::ytdlp_binary(); // See ytdlp_manager for which one
+
+        // Process priority and fragments fetched at once (see performance.rs)
+        let profile = performance::profile(&settings);
+
+        let mut cmd = Command::new(&ytdlp_path);
+        cancellation::in_own_group(&mut cmd); // So a cancel also stops its ffmpeg
+        cmd.arg(&item_url); // The URL to download
+        cmd.arg("--write-info-json"); // Get metadata (still useful even if not parsed immediately)
+        cmd.arg("--output"); // Specify output template
+        cmd.arg(&output_path_str); // Pass the full path template
+        cmd.arg("--no-simulate"); // Ensure it actually downloads
+        cmd.arg("--progress"); // Request progress updates
+        cmd.arg("--newline"); // Ensure progress updates are on new lines
+        // Keep output names short enough that every file stays under MAX_PATH
+        if let Some(max_len) = long_paths::name_budget(output_path_base) {
+            cmd.arg("--trim-filenames").arg(max_len.to_string());
+        }
+        cmd.arg("--no-warnings"); // Reduce noise in output
+        cmd.arg("-v"); // Add verbose flag for detailed debugging output
+                       // Robust download parameters for large videos
+        cmd.arg("--fragment-retries").arg("10"); // Retry fragments up to 10 times
+        cmd.arg("--retries").arg("5"); // Retry the whole download up to 5 times
+        cmd.arg("--file-access-retries").arg("10"); // Retry file access operations
+        cmd.arg("--continue"); // Continue partial downloads
+        cmd.arg("--no-part"); // Don't use .part files (can cause issues on some systems)
+        cmd.arg("--concurrent-fragments")
+            .arg(profile.concurrent_fragments.to_string());
+        cmd.arg("--throttled-rate").arg("100K"); // Minimum rate before considering throttled
+        cmd.arg("--sleep-requests").arg("1"); // Sleep 1 second between requests
+        cmd.arg("--sleep-interval").arg("5"); // Sleep 5 seconds before each download
+        cmd.arg("--max-sleep-interval").arg("30"); // Maximum sleep interval of 30 seconds
+        cmd.arg("--socket-timeout").arg("30"); // 30 second socket timeout
+        cmd.arg("--extractor-retries").arg("3"); // Retry extractor operations
+        if let Some(format) = &format {
+            cmd.arg("--format").arg(format);
+        }
+        // Only download the requested time ranges, if any
+        if let Some(raw_sections) = next_item.download_sections.as_deref() {
+            for section in sections::parse_sections(raw_sections).unwrap_or_default() {
+                cmd.arg("--download-sections").arg(section);
+            }
+        }
+        // Fetch and embed subtitles in the configured languages, if any. With
+        // --write-subs yt-dlp also keeps the files beside the video.
+        if let Some(sub_langs) = subtitles::sub_langs_arg(
+            settings.subtitle_languages.as_deref(),
+            next_item.language.as_deref(),
+        ) {
+            cmd.arg("--write-subs");
+            if subtitles::auto_captions_enabled(
+                settings.subtitle_auto_captions.as_deref(),
+            ) {
+                cmd.arg("--write-auto-subs");
+            }
+            cmd.arg("--sub-langs").arg(&sub_langs).arg("--embed-subs");
+        }
+
+        // Point yt-dlp at ffmpeg when it is configured or installed outside PATH,
+        // otherwise bestvideo+bestaudio formats silently fail to merge
+        if let Some(location) =
+            tools::ffmpeg_location_arg(settings.ffmpeg_path.as_deref()).await
+        {
+            cmd.arg("--ffmpeg-location").arg(&location);
+        }
+
+        // Login cookies for private and age-restricted videos
+        match cookies::CookieSource::from_settings(&settings) {
+            Ok(Some(source)) => {
+                cmd.args(source.ytdlp_args());
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Ignoring cookie settings for item {}: {}", item_id, e),
+        }
+
+        cmd.stdout(Stdio::piped()); // Capture standard output
+        cmd.stderr(Stdio::piped()); // Capture standard error
+
+        // Run yt-dlp Process
+        let mut download_success = false;
+        let cancel_guard = app_state.cancellations.register(&item_id);
+        let job = app_state.jobs.start(
+            jobs::KIND_DOWNLOAD,
+            Some(&item_id),
+            cancel_guard.token().clone(),
+        );
+
+        let download_started = std::time::Instant::now();
+        match cmd.spawn() {
+            Ok(mut child) => {
+                if let Some(pid) = child.id() {
+                    cancel_guard.track_process(pid);
+                    performance::apply(pid, profile.priority).await;
+                }
+                let stdout = child.stdout.take().expect("Failed to capture stdout");
+                let stderr = child.stderr.take().expect("Failed to capture stderr");
+
+                let mut stdout_reader = BufReader::new(stdout).lines();
+                let mut stderr_reader = BufReader::new(stderr).lines();
+                app_state.output_tail.reset(&item_id);
+
+                // Any output counts as activity for the stall watchdog
+                let activity = watchdog::Activity::new();
+                let activity_stdout = activity.clone();
+                let activity_stderr = activity.clone();
+
+                // Clone necessary data for the async blocks
+                let item_id_clone_stdout = item_id.clone();
+                let job_id_stdout = job.id().to_string();
+                let persist_progress = progress::persisted(&settings);
+                let app_handle_clone_stdout = app_handle.clone();
+
+                // Create a shared flag to stop progress updates when download completes
+                let progress_stop_flag = Arc::new(AtomicBool::new(false));
+                let progress_stop_flag_clone = progress_stop_flag.clone();
+
+                // Spawn task to read stdout and parse progress
+                let progress_task = tokio::spawn(async move {
+                    let mut write_throttle = progress::WriteThrottle::new();
+                    while let Ok(Some(line)) = stdout_reader.next_line().await {
+                        activity_stdout.touch();
+                        // Check if we should stop updating progress
+                        if progress_stop_flag_clone.load(Ordering::Relaxed) {
+                            break;
+                        }
+
+                        app_handle_clone_stdout
+                            .state::<AppState>()
+                            .output_tail
+                            .push(
+                                &app_handle_clone_stdout,
+                                &item_id_clone_stdout,
+                                "stdout",
+                                &line,
+                            );
+
+                        // Check for progress
+                        if let Some(update) =
+                            progress::parse(&item_id_clone_stdout, &line)
+                        {
+                            let progress_message = update.message();
+                            let state: State<'_, AppState> =
+                                app_handle_clone_stdout.state();
+                            state.jobs.update(
+                                &job_id_stdout,
+                                Some(update.percent),
+                                Some(progress_message.text()),
+                            );
+                            progress::emit(&app_handle_clone_stdout, &update);
+                            state.live_progress.set(update.clone());
+
+                            // The event carries live progress; the row only
+                            // needs to be roughly current, if updated at all
+                            if persist_progress
+                                && write_throttle.should_write(update.percent)
+                            {
+                                if let Err(e) = state
+                                    .db
+                                    .update_item_status(
+                                        &item_id_clone_stdout,
+                                        "downloading",
+                                        Some(progress_message),
+                                    )
+                                    .await
+                                {
+                                    eprintln!("Error updating download progress: {}", e);
+                                }
+                                if let Err(e) = state.db.set_download_stats(&update).await {
+                                    eprintln!("Error updating download stats: {}", e);
+                                }
+                            }
+                        }
+                    }
+                });
+
+                // Spawn task to read stderr
+                let stderr_capture = Arc::new(Mutex::new(String::new()));
+                let stderr_capture_clone = stderr_capture.clone();
+                let item_id_clone_stderr = item_id.clone();
+                let app_handle_clone_stderr = app_handle.clone();
+                tokio::spawn(async move {
+                    while let Ok(Some(line)) = stderr_reader.next_line().await {
+                        activity_stderr.touch();
+                        println!("[yt-dlp stderr] {}", line);
+                        app_handle_clone_stderr
+                            .state::<AppState>()
+                            .output_tail
+                            .push(
+                                &app_handle_clone_stderr,
+                                &item_id_clone_stderr,
+                                "stderr",
+                                &line,
+                            );
+                        let mut capture = stderr_capture_clone.lock().unwrap();
+                        capture.push_str(&line);
+                        capture.push('\n');
+                    }
+                });
+
+                // Stop yt-dlp (and any ffmpeg it spawned) if the item is cancelled
+                // or the watchdog decides the attempt has stalled
+                let mut stalled: Option<StallReason> = None;
+                let limits = watchdog::Limits::from_settings(&settings);
+                let wait_result = tokio::select! {
+                    result = child.wait() => result,
+                    _ = cancel_guard.token().cancelled() => {
+                        println!("Cancellation requested for item {}, stopping yt-dlp", item_id);
+                        cancellation::kill_process_tree(&mut child).await;
+                        child.wait().await
+                    }
+                    reason = watchdog::watch(limits, activity) => {
+                        println!(
+                            "Download for item {} stalled ({}), stopping yt-dlp",
+                            item_id,
+                            reason.describe()
+                        );
+                        stalled = Some(reason);
+                        cancellation::kill_process_tree(&mut child).await;
+                        child.wait().await
+                    }
+                };
+
+                match wait_result {
+                    Ok(status) => {
+                        // Stop progress updates immediately when process completes
+                        progress_stop_flag.store(true, Ordering::Relaxed);
+
+                        // Wait a bit for progress task to stop
+                        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+                        if status.success() {
+                            println!(
+                                "yt-dlp process finished successfully for item: {}",
+                                item_id
+                            );
+                            download_success = true;
+                            app_state.stall_backoff.clear(&item_id);
+                        } else if let Some(reason) = stalled {
+                            app_state.concurrency.record_failure(bandwidth::DOWNLOAD);
+                            reschedule_stalled(&app_handle, &item_id, reason).await;
+                        } else {
+                            // Check if the item was cancelled while downloading
+                            let state_check: State<'_, AppState> = app_handle.state();
+                            let current_item = state_check.db.get_item_by_id(&item_id).await;
+                            
+                            if let Ok(Some(item)) = current_item {
+                                if item.status == "cancelled" {
+                                    println!("Item {} was cancelled by user, not marking as failed", item_id);
+                                    // Don't update to failed, keep it as cancelled
+                                } else {
+                                    // Only mark as failed if not already cancelled
+                                    let stderr_output =
+                                        stderr_capture.lock().unwrap().trim().to_string();
+                                    let failure = exit_failure(
+                                        status.code(),
+                                        &stderr_output,
+                                    );
+                                    eprintln!(
+                                        "Error for item {}: {}",
+                                        item_id,
+                                        failure.text()
+                                    );
+                                    if let Err(e) =
+                                        state_check.db.record_download_failure(&item_id).await
+                                    {
+                                        eprintln!("Error recording failure for item {}: {}", item_id, e);
+                                    }
+                                    let source_gone =
+                                        tools::is_source_gone(&stderr_output);
+                                    // A gone or unsupported source may still be archived from a mirror
+                                    let mirror_reason =
+                                        tools::unsupported_content_reason(&stderr_output)
+                                            .or_else(|| source_gone.then_some("Source is unavailable"));
+                                    // A gone or unsupported video says nothing about the connection
+                                    if mirror_reason.is_none() {
+                                        state_check.concurrency.record_failure(bandwidth::DOWNLOAD);
+                                    }
+                                    let mirrored = match mirror_reason {
+                                        Some(reason) => {
+                                            try_mirror_fallback(&app_handle, &item, reason).await
+                                        }
+                                        None => false,
+                                    };
+                                    if mirrored {
+                                        println!("Item {} re-queued on a mirror source", item_id);
+                                    } else if let Some(reason) =
+                                        tools::unsupported_content_reason(&stderr_output)
+                                    {
+                                        // Terminal: skip the update/fallback retries entirely
+                                        mark_unsupported(&app_handle, &item_id, reason, &stderr_output)
+                                            .await;
+                                    } else if let Some(reason) =
+                                        tools::auth_required_reason(&stderr_output)
+                                    {
+                                        // No point retrying until the cookies change
+                                        mark_auth_required(
+                                            &app_handle,
+                                            &item_id,
+                                            reason,
+                                            &stderr_output,
+                                            &settings,
+                                        )
+                                        .await;
+                                    } else if tools::is_extractor_outdated(&stderr_output)
+                                        && try_extractor_recovery(&app_handle, &item_id).await
+                                    {
+                                        println!("Item {} re-queued after yt-dlp update", item_id);
+                                    } else if try_format_fallback(
+                                        &app_handle,
+                                        &item_id,
+                                        &format_ladder,
+                                        format_rung,
+                                        &stderr_output,
+                                    )
+                                    .await
+                                    {
+                                        println!("Item {} re-queued with a fallback format", item_id);
+                                    } else {
+                                        // Update DB status
+                                        let state_err: State<'_, AppState> = app_handle.state();
+                                        if let Err(e) = state_err
+                                            .db
+                                            .update_item_status(&item_id, "failed", Some(failure))
+                                            .await
+                                        {
+                                            eprintln!(
+                                                "Error updating status after download failure: {}",
+                                                e
+                                            );
+                                        }
+                                    }
+                                    // Keep the leftovers for inspection instead of
+                                    // in the download dir, once the item gave up
+                                    forensics::collect_if_gave_up(
+                                        &app_handle,
+                                        &item_id,
+                                        &download_dir,
+                                        &stderr_output,
+                                    )
+                                    .await;
+                                }
+                            } else {
+                                // Couldn't check status, default to failed
+                                let stderr_output =
+                                    stderr_capture.lock().unwrap().trim().to_string();
+                                let failure =
+                                    exit_failure(status.code(), &stderr_output);
+                                eprintln!("Error for item {}: {}", item_id, failure.text());
+                                let state_err: State<'_, AppState> = app_handle.state();
+                                if let Err(e) = state_err
+                                    .db
+                                    .update_item_status(&item_id, "failed", Some(failure))
+                                    .await
+                                {
+                                    eprintln!(
+                                        "Error updating status after download failure: {}",
+                                        e
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        // Stop progress updates immediately when process fails
+                        progress_stop_flag.store(true, Ordering::Relaxed);
+
+                        let failure = ItemMessage::new(messages::DOWNLOAD_WAIT_FAILED)
+                            .with("error", e.to_string());
+                        eprintln!("Error for item {}: {}", item_id, failure.text());
+                        // Update DB status
+                        let state_err: State<'_, AppState> = app_handle.state();
+                        if let Err(update_e) = state_err
+                            .db
+                            .update_item_status(&item_id, "failed", Some(failure))
+                            .await
+                        {
+                            eprintln!(
+                                "Error updating status after process error: {}",
+                                update_e
+                            );
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                let failure = ItemMessage::new(messages::DOWNLOAD_SPAWN_FAILED)
+                    .with("binary", ytdlp_path.clone())
+                    .with("error", e.to_string());
+                eprintln!("Error for item {}: {}", item_id, failure.text());
+                // Update DB status
+                let state_err: State<'_, AppState> = app_handle.state();
+                if let Err(update_e) = state_err
+                    .db
+                    .update_item_status(&item_id, "failed", Some(failure))
+                    .await
+                {
+                    eprintln!("Error updating status after spawn error: {}", update_e);
+                }
+            }
+        }
+        // Keep the size and amount downloaded; speed and ETA only mean something while
+        // the download runs
+        if let Some(mut last) = app_state.live_progress.get(&item_id) {
+            last.speed_bps = None;
+            last.eta_seconds = None;
+            if let Err(e) = app_state.db.set_download_stats(&last).await {
+                eprintln!("Error saving download stats for item {}: {}", item_id, e);
+            }
+        }
+        app_state.live_progress.remove(&item_id);
+        // END yt-dlp Process
+
+        // After download attempt
+        if download_success {
+            let format_label = formats::label(format.as_deref());
+            if let Err(e) = app_state.db.set_format_used(&item_id, &format_label).await {
+                eprintln!("Error recording format for item {}: {}", item_id, e);
+            }
+
+            // Read info.json to get actual file details
+            let mut actual_video_path: Option<String> = None;
+            let mut video_title: Option<String> = None;
+            let mut thumbnail_url: Option<String> = None;
+            let mut webpage_url: Option<String> = None;
+            let mut extractor: Option<String> = None;
+            let mut source_video_id: Option<String> = None;
+            let mut language: Option<String> = None;
+            let mut caption_languages: Vec<String> = Vec::new();
+            let mut library_metadata: Option<LibraryMetadata> = None;
+            let mut processed_json = false; // Flag to indicate if we successfully processed a JSON
+
+            let item_original_url = item_url.clone(); // Clone the URL for comparison
+
+            println!("Download successful for item {}. Searching for matching .info.json in dir: {}", item_id, download_dir);
+
+            // Search for the *correct* .info.json file by matching the URL inside
+            if let Ok(entries) =
+                fs::read_dir(long_paths::extended(Path::new(&download_dir)))
+            {
+                for entry in entries.filter_map(Result::ok) {
+                    let path = entry.path();
+                    // Check if it's a .info.json file
+                    if path.is_file()
+                        && path.extension().map_or(false, |ext| ext == "json")
+                        && path
+                            .file_stem()
+                            .map_or(false, |stem| stem.to_string_lossy().ends_with(".info"))
+                    {
+                        let json_path_str = long_paths::plain(&path);
+                        println!(
+                            "Item {}: Found potential info.json: {}",
+                            item_id, json_path_str
+                        );
+
+                        // Read and parse the JSON
+                        if let Ok(json_content) = fs::read_to_string(&path) {
+                            if let Ok(info) =
+                                serde_json::from_str::<JsonValue>(&json_content)
+                            {
+                                // *** Match URL from JSON with item URL ***
+                                println!(
+                                    "Item {}: Parsing info.json: {}",
+                                    item_id, json_path_str
+                                );
+                                let json_url = info
+                                    .get("webpage_url")
+                                    .or_else(|| info.get("original_url")) // Fallback to original_url
+                                    .and_then(|v| v.as_str());
+
+                                let urls_match = match json_url {
+                                    Some(j_url) => {
+                                        // Try matching by extracted ID first
+                                        let original_id =
+                                            extract_facebook_video_id(&item_original_url);
+                                        let json_id = extract_facebook_video_id(j_url);
+                                        println!("Item {}: Comparing Original URL '{}' (ID: {:?}) with JSON URL '{}' (ID: {:?})",
+                                                 item_id, item_original_url, original_id, j_url, json_id);
+
+                                        // X and TikTok report other URL shapes than the queued one
+                                        let original_key = urls::video_key(&item_original_url);
+                                        if original_id.is_some()
+                                            && json_id.is_some()
+                                            && original_id == json_id
+                                        {
+                                            println!("Item {}: URLs match based on extracted video ID.", item_id);
+                                            true // IDs match
+                                        } else if original_key.is_some()
+                                            && original_key == urls::video_key(j_url)
+                                        {
+                                            println!("Item {}: URLs match based on site video key.", item_id);
+                                            true
+                                        } else {
+                                            // Fallback to direct string comparison if IDs don't match or couldn't be extracted
+                                            println!("Item {}: Video IDs don't match or couldn't be extracted. Comparing full URLs.", item_id);
+                                            j_url == item_original_url
+                                        }
+                                    }
+                                    None => {
+                                        println!("Item {}: No URL found in JSON. Cannot compare.", item_id);
+                                        false // No URL in JSON to compare
+                                    }
+                                };
+
+                                if urls_match {
+                                    println!("Item {}: Successfully parsed MATCHING info.json: {}", item_id, json_path_str);
+                                    processed_json = true; // Mark that we parsed the correct JSON
+
+                                    // Extract common details
+                                    video_title =
+                                        titles::from_info(&item_original_url, &info);
+                                    thumbnail_url = info
+                                        .get("thumbnail")
+                                        .and_then(|v| v.as_str())
+                                        .map(String::from);
+                                    webpage_url = json_url.map(String::from);
+                                    extractor = info
+                                        .get("extractor_key")
+                                        .or_else(|| info.get("extractor"))
+                                        .and_then(|v| v.as_str())
+                                        .map(String::from);
+                                    source_video_id = info
+                                        .get("id")
+                                        .and_then(|v| v.as_str())
+                                        .map(String::from);
+                                    language = subtitles::language_from_info(&info);
+                                    caption_languages =
+                                        subtitles::caption_languages_from_info(&info);
+                                    if media_library::enabled(&settings) {
+                                        library_metadata =
+                                            Some(LibraryMetadata::from_info(
+                                                video_title.as_deref(),
+                                                &info,
+                                            ));
+                                    }
+                                    let ext = info.get("ext").and_then(|v| v.as_str());
+                                    println!("Item {}: Extracted from info.json - title='{:?}', thumb='{:?}', ext='{:?}'", item_id, video_title, thumbnail_url, ext);
+
+                                    // Determine the actual video file path (Priority: _filename)
+                                    if let Some(relative_filename) =
+                                        info.get("_filename").and_then(|v| v.as_str())
+                                    {
+                                        println!("Item {}: Found '_filename' field in info.json: '{}'", item_id, relative_filename);
+                                        let potential_path = Path::new(&download_dir)
+                                            .join(relative_filename);
+                                        if long_paths::extended(&potential_path)
+                                            .exists()
+                                        {
+                                            actual_video_path = Some(
+                                                potential_path
+                                                    .to_string_lossy()
+                                                    .to_string(),
+                                            );
+                                            println!("Item {}: Confirmed video path from '_filename' exists: {:?}", item_id, actual_video_path);
+                                        } else {
+                                            println!("Item {}: WARNING - Path from '_filename' ('{}') does not exist.", item_id, potential_path.display());
+                                        }
+                                    }
+
+                                    // Construct path from template (Fallback)
+                                    if actual_video_path.is_none() {
+                                        println!("Item {}: '_filename' not found/valid in info.json. Attempting path construction...", item_id);
+                                        // yt-dlp's own title, not the fallback chain's
+                                        let raw_title =
+                                            info.get("title").and_then(|v| v.as_str());
+                                        if let (Some(title), Some(extension)) =
+                                            (raw_title, ext)
+                                        {
+                                            let channel = info
+                                                .get("channel")
+                                                .and_then(|v| v.as_str())
+                                                .unwrap_or("UnknownChannel");
+                                            let base_filename_template =
+                                                "%(title)s by %(channel)s.%(ext)s";
+                                            let sanitized_title = sanitize_filename(title);
+                                            let sanitized_channel =
+                                                sanitize_filename(channel);
+                                            println!("Item {}: Constructing filename with title='{}', channel='{}', ext='{}'", item_id, sanitized_title, sanitized_channel, extension);
+                                            let constructed_filename =
+                                                base_filename_template
+                                                    .replace("%(title)s", &sanitized_title)
+                                                    .replace(
+                                                        "%(channel)s",
+                                                        &sanitized_channel,
+                                                    )
+                                                    .replace("%(ext)s", extension);
+                                            let constructed_path = Path::new(&download_dir)
+                                                .join(&constructed_filename);
+                                            println!(
+                                                "Item {}: Attempting constructed path: {}",
+                                                item_id,
+                                                constructed_path.display()
+                                            );
+                                            if long_paths::extended(&constructed_path)
+                                                .exists()
+                                            {
+                                                actual_video_path = Some(
+                                                    constructed_path
+                                                        .to_string_lossy()
+                                                        .to_string(),
+                                                );
+                                                println!("Item {}: Successfully confirmed constructed video path exists: {:?}", item_id, actual_video_path);
+                                            } else {
+                                                println!("Item {}: WARNING - Constructed video path does not exist: {}", item_id, constructed_path.display());
+                                                let video_path_from_json = json_path_str
+                                                    .replace(
+                                                        ".info.json",
+                                                        &format!(".{}", extension),
+                                                    );
+                                                println!("Item {}: Trying path derived from info.json filename: {}", item_id, video_path_from_json);
+                                                if long_paths::extended(Path::new(
+                                                    &video_path_from_json,
+                                                ))
+                                                .exists()
+                                                {
+                                                    actual_video_path =
+                                                        Some(video_path_from_json);
+                                                    println!("Item {}: Successfully used video path derived from info.json path: {:?}", item_id, actual_video_path);
+                                                } else {
+                                                    println!("Item {}: WARNING - Video path derived from info.json path also doesn't exist: {}", item_id, video_path_from_json);
+                                                }
+                                            }
+                                        } else {
+                                            println!("Item {}: WARNING - Could not extract title or extension from info.json to construct path.", item_id);
+                                        }
+                                    }
+
+                                    // Clean up the processed info.json file
+                                    match fs::remove_file(&path) {
+                                        Ok(_) => println!("Item {}: Removed processed info.json: {}", item_id, json_path_str),
+                                        Err(e) => eprintln!("Item {}: Failed to remove processed info.json {}: {}", item_id, json_path_str, e),
+                                    }
+
+                                    break; // Found the matching json, stop searching
+                                } else {
+                                    // URL didn't match, log and continue searching
+                                    println!("Item {}: URLs do not match (checked IDs and direct comparison), skipping info.json.", item_id);
+                                }
+                            } else {
+                                eprintln!("Item {}: Error parsing JSON content from {}. Skipping.", item_id, json_path_str);
+                            }
+                        } else {
+                            eprintln!(
+                                "Item {}: Error reading file content from {}. Skipping.",
+                                item_id, json_path_str
+                            );
+                        }
+                    }
+                } // End of directory iteration
+            }
+
+            if !processed_json {
+                println!("Item {}: WARNING - Could not find a matching .info.json file. Cannot determine exact filename.", item_id);
+            }
+
+            // Update Database with determined info
+            if actual_video_path.is_none() {
+                println!("Item {}: CRITICAL WARNING - Final video path could not be determined. Upload WILL likely fail. Storing template path as fallback.", item_id);
+                // Storing None instead to make the error more obvious later
+                actual_video_path = None;
+            }
+
+            // Re-encode for the upload hosts if a transcode target is set (see
+            // transcode.rs); on failure the download is kept and the item fails
+            let mut transcode_error: Option<String> = None;
+            if let Some(video_path) = actual_video_path.clone() {
+                match transcode::run(&app_handle, &item_id, Path::new(&video_path), &settings).await
+                {
+                    Ok(Some(transcoded_path)) => actual_video_path = Some(transcoded_path),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("Item {}: Transcoding failed: {}", item_id, e);
+                        transcode_error = Some(e);
+                    }
+                }
+            }
+
+            println!("Item {}: Updating DB status='completed', title='{:?}', path='{:?}', thumb='{:?}'",
+                item_id, video_title, actual_video_path, thumbnail_url);
+
+            // In review mode nothing is uploaded until approve_upload is called
+            let needs_review = review_required(&settings);
+            let (downloaded_status, downloaded_message) = if let Some(error) = &transcode_error {
+                (
+                    "failed",
+                    ItemMessage::new(messages::TRANSCODE_FAILED).with("error", error.as_str()),
+                )
+            } else if needs_review {
+                (
+                    "pending_review",
+                    ItemMessage::new(messages::DOWNLOAD_AWAITING_REVIEW),
+                )
+            } else {
+                ("downloaded", ItemMessage::new(messages::DOWNLOAD_COMPLETE))
+            };
+
+            let update_result = app_state
+                .db
+                .update_item_after_download(
+                    &item_id,
+                    downloaded_status,
+                    video_title.clone(), // Clone needed for potential event emission
+                    actual_video_path.clone(), // Clone needed for potential event emission
+                    thumbnail_url.clone(), // Clone needed for potential event emission
+                    Some(downloaded_message),
+                )
+                .await;
+
+            if let Err(e) = update_result {
+                eprintln!(
+                    "Error updating item {} details after download: {}",
+                    item_id, e
+                );
+            } else {
+                println!(
+                    "Item {} details updated after successful download.",
+                    item_id
+                );
+                // Emit event on successful download & DB update
+                let payload = serde_json::json!({
+                    "id": item_id,
+                    "originalUrl": item_original_url, // Send original URL
+                    "title": video_title,
+                    "localPath": actual_video_path,
+                    "thumbnailUrl": thumbnail_url
+                });
+                if let Err(e) = app_handle.emit_all("download_complete", payload) {
+                    eprintln!(
+                        "Error emitting download_complete event for {}: {}",
+                        item_id, e
+                    );
+                }
+            }
+
+            // Keep the watch page and thumbnail alongside the video
+            if warc::enabled(&settings) {
+                if let Some(video_path) = actual_video_path.as_deref() {
+                    let page_url = webpage_url
+                        .clone()
+                        .unwrap_or_else(|| item_original_url.clone());
+                    match warc::capture(
+                        &page_url,
+                        thumbnail_url.as_deref(),
+                        Path::new(video_path),
+                    )
+                    .await
+                    {
+                        Ok(capture) => println!(
+                            "Item {}: Captured watch page to {}",
+                            item_id, capture.warc_path
+                        ),
+                        Err(e) => eprintln!("Item {}: Page capture failed: {}", item_id, e),
+                    }
+                }
+            }
+
+            // Subtitle files stay beside the video as part of the archive
+            let subtitle_paths = actual_video_path
+                .as_deref()
+                .map(|video_path| subtitles::find_files(Path::new(video_path)))
+                .unwrap_or_default();
+            if !subtitle_paths.is_empty() {
+                println!(
+                    "Item {}: Found {} subtitle file(s)",
+                    item_id,
+                    subtitle_paths.len()
+                );
+                if let Err(e) = app_state
+                    .db
+                    .set_subtitle_paths(&item_id, &subtitle_paths)
+                    .await
+                {
+                    eprintln!("Error saving subtitle paths for item {}: {}", item_id, e);
+                }
+            }
+
+            // Kept downloads also show up in the media server library
+            if let (Some(video_path), Some(metadata)) =
+                (actual_video_path.as_deref(), library_metadata.as_ref())
+            {
+                match media_library::add(
+                    &settings,
+                    Path::new(video_path),
+                    metadata,
+                    thumbnail_url.as_deref(),
+                    &subtitle_paths,
+                )
+                .await
+                {
+                    Ok(folder) => println!(
+                        "Item {}: Added to media library at {}",
+                        item_id,
+                        folder.display()
+                    ),
+                    Err(e) => {
+                        eprintln!("Item {}: Media library entry failed: {}", item_id, e)
+                    }
+                }
+            }
+
+            // Record chain-of-custody details while the file is still on disk
+            let record = build_provenance_record(
+                &item_id,
+                actual_video_path.as_deref(),
+                webpage_url,
+                extractor,
+                source_video_id,
+                format_label,
+            )
+            .await;
+            if let Err(e) = app_state.db.record_provenance(&record).await {
+                eprintln!("Error recording provenance for item {}: {}", item_id, e);
+            }
+            if let Some(size) = actual_video_path
+                .as_deref()
+                .and_then(|path| fs::metadata(long_paths::extended(Path::new(path))).ok())
+            {
+                app_state.throughput.record(
+                    bandwidth::DOWNLOAD,
+                    size.len() as i64,
+                    download_started.elapsed(),
+                );
+                app_state.concurrency.record(
+                    bandwidth::DOWNLOAD,
+                    size.len() as i64,
+                    download_started.elapsed(),
+                );
+                bandwidth::record(
+                    &app_state,
+                    next_item.user_id.as_deref().unwrap_or("local-user"),
+                    &item_id,
+                    bandwidth::DOWNLOAD,
+                    size.len() as i64,
+                )
+                .await;
+            }
+            if processed_json {
+                if let Err(e) = app_state
+                    .db
+                    .set_language_metadata(&item_id, language.as_deref(), &caption_languages)
+                    .await
+                {
+                    eprintln!("Error saving language metadata for item {}: {}", item_id, e);
+                }
+            }
+
+            // Queue the upload if the auto-upload rules allow it (see auto_upload.rs)
+            if download_success && !needs_review && transcode_error.is_none() {
+                let user_id = next_item.user_id.as_deref().unwrap_or("local-user");
+                auto_upload::apply(&app_state, &item_id, user_id).await;
+            }
+        }
+    } else if paused_for_space {
+        sleep(disk_space::PAUSE_RECHECK).await;
+    } else {
+        println!(
+            "Skipping download for item {} due to previous error.",
+            item_id
+        );
+        // No need to sleep long here, the outer loop handles it
+    }
+}
+
+pub async fn process_queue_background(app_handle: tauri::AppHandle) {
+    println!("Starting background queue processor...");
+    loop {
+        let mut item_to_process: Option<QueueItem> = None;
+        let mut should_sleep_long = true; // Sleep longer if no item found or error
+
+        // Check if any active processing is happening
+        let app_state: State<'_, AppState> = app_handle.state();
+
+        // Worker switched off for this instance; set_worker_enabled wakes it again
+        if !app_state.worker.is_enabled() {
+            app_state
+                .queue_wakeup
+                .wait(queue_wakeup::FALLBACK_POLL)
+                .await;
+            continue;
+        }
+
+        // Replay status writes that failed while the database was unreachable
+        if app_state.db.pending_write_count() > 0 {
+            let replayed = app_state.db.replay_pending_writes().await;
+            if replayed > 0 {
+                println!("Replayed {} pending database write(s)", replayed);
+            }
+        }
+
+        disk_space::release_blocked(&app_state).await;
+
+        match app_state.db.get_global_settings().await {
+            Ok(settings) => app_state.concurrency.configure(&settings),
+            Err(e) => eprintln!("DB Error loading concurrency settings: {}", e),
+        }
+
+        // Downloads run up to the concurrency limit (see concurrency.rs); unless it is
+        // adaptive, they also wait while an upload runs
+        let is_already_processing = if !app_state.concurrency.has_free_slot(bandwidth::DOWNLOAD) {
+            true
+        } else if app_state.concurrency.is_adaptive() {
+            false
+        } else {
+            match app_state
+                .db
+                .is_item_in_status(&["downloading", "uploading"])
+                .await
+            {
+                Ok(processing) => processing,
+                Err(e) => {
+                    eprintln!("DB Error checking for active processing: {}", e);
+                    false
+                }
+            }
+        };
+
+        if !is_already_processing {
+            if let Some(item) = next_item_to_process(&app_state).await {
+                item_to_process = Some(item);
+                should_sleep_long = false; // Found item, process immediately
+            }
+        }
+
+        // Process Item (if found) outside the main DB lock scope
+        if let Some(next_item) = item_to_process {
+            let item_id = next_item.id.clone().unwrap_or_default();
+            match app_state
+                .concurrency
+                .try_start(bandwidth::DOWNLOAD, &item_id)
+            {
+                Some(slot) => {
+                    let handle = app_handle.clone();
+                    tokio::spawn(async move {
+                        process_item(handle.clone(), next_item).await;
+                        drop(slot);
+                        handle.state::<AppState>().queue_wakeup.wake();
+                    });
+                }
+                None => should_sleep_long = true,
+            }
+        } else {
+            // Check status of transferring/encoding items if no new item to process
+            let app_state: State<'_, AppState> = app_handle.state();
+            // No more status checking needed - files are simply uploaded
+            should_sleep_long = true;
+        }
+
+        // Go on to the next item after one was processed; otherwise wait for something
+        // to be queued, polling only as a fallback (see queue_wakeup.rs)
+        if should_sleep_long {
+            let app_state: State<'_, AppState> = app_handle.state();
+            app_state
+                .queue_wakeup
+                .wait(queue_wakeup::FALLBACK_POLL)
+                .await;
+        } else {
+            sleep(queue_wakeup::BETWEEN_ITEMS).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_source_url_follows_the_mirror_index() {
+        let mut item: QueueItem = serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/primary",
+            "status": "queued",
+        }))
+        .unwrap();
+        item.mirror_urls = Some(vec!["https://mirror.example/one".to_string()]);
+        assert_eq!(active_source_url(&item), "https://example.com/primary");
+
+        item.source_index = Some(1);
+        assert_eq!(active_source_url(&item), "https://mirror.example/one");
+
+        // Past the end of the list (mirrors edited since), back to the primary URL
+        item.source_index = Some(2);
+        assert_eq!(active_source_url(&item), "https://example.com/primary");
+    }
+}
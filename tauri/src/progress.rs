@@ -0,0 +1,243 @@
+// Live download progress. Every yt-dlp progress line reaches the UI right away as a
+// "queue://progress" event with percent, speed and ETA, while the item row is only
+// rewritten every few seconds, so the database isn't hit once per output line.
+// With `progress_persistence` set to "memory" the row isn't rewritten at all: progress
+// lives in AppState until the download ends, and get_queue_items fills it in.
+//
+// Besides the printed speed and ETA, each update carries them as numbers (bytes per
+// second, seconds) with the size of the file and how much of it is done, which are
+// kept in the item's total_bytes, downloaded_bytes, speed_bps and eta_seconds.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+
+use crate::db::{AppSettings, QueueItem};
+use crate::messages::{self, ItemMessage};
+
+pub const EVENT: &str = "queue://progress";
+
+// Values of `progress_persistence`
+pub const PERSIST_THROTTLED: &str = "throttled";
+pub const PERSIST_MEMORY: &str = "memory";
+// Shortest gap between two progress writes for the same download
+const DB_WRITE_INTERVAL: Duration = Duration::from_secs(3);
+
+lazy_static! {
+    // "[download]  42.0% of ~ 12.34MiB at  1.20MiB/s ETA 00:08 (frag 3/20)"
+    static ref PROGRESS_REGEX: Regex = Regex::new(
+        r"\[download\]\s+(\d{1,3}(?:\.\d+)?)%(?:\s+of\s+~?\s*(\S+))?(?:.*?\bat\s+(\S+))?(?:.*?\bETA\s+(\S+))?"
+    )
+    .unwrap();
+    // "12.34MiB", "980.00KiB/s", "1.5GB"
+    static ref SIZE_REGEX: Regex =
+        Regex::new(r"^(\d+(?:\.\d+)?)\s*([KMGT]i?)?B(?:/s)?$").unwrap();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Progress {
+    pub item_id: String,
+    pub percent: f32,
+    // As yt-dlp prints them, e.g. "1.20MiB/s" and "00:08"
+    pub speed: Option<String>,
+    pub eta: Option<String>,
+    // Size of the file being downloaded (an estimate for fragmented downloads), and
+    // the part of it done so far
+    pub total_bytes: Option<i64>,
+    pub downloaded_bytes: Option<i64>,
+    pub speed_bps: Option<i64>,
+    pub eta_seconds: Option<i32>,
+}
+
+// yt-dlp prints "Unknown" (or "Unknown B/s") before it has an estimate
+fn known(value: Option<regex::Match>) -> Option<String> {
+    value
+        .map(|m| m.as_str().to_string())
+        .filter(|v| !v.starts_with("Unknown"))
+}
+
+// Bytes in a size or speed as yt-dlp prints it; KiB and KB are both taken as 1024
+fn bytes(value: &str) -> Option<i64> {
+    let caps = SIZE_REGEX.captures(value)?;
+    let number = caps[1].parse::<f64>().ok()?;
+    let power = match caps.get(2).map(|unit| &unit.as_str()[..1]) {
+        None => 0,
+        Some("K") => 1,
+        Some("M") => 2,
+        Some("G") => 3,
+        Some(_) => 4,
+    };
+    Some((number * 1024f64.powi(power)).round() as i64)
+}
+
+// Seconds in an ETA such as "08", "01:30" or "1:02:03"
+fn seconds(value: &str) -> Option<i32> {
+    value.split(':').try_fold(0i32, |total, part| {
+        Some(total * 60 + part.parse::<i32>().ok()?)
+    })
+}
+
+pub fn parse(item_id: &str, line: &str) -> Option<Progress> {
+    let caps = PROGRESS_REGEX.captures(line)?;
+    let percent = caps[1].parse::<f32>().ok()?;
+    let speed = known(caps.get(3));
+    let eta = known(caps.get(4));
+    let total_bytes = known(caps.get(2)).as_deref().and_then(bytes);
+    Some(Progress {
+        item_id: item_id.to_string(),
+        percent,
+        total_bytes,
+        downloaded_bytes: total_bytes
+            .map(|total| (total as f64 * percent.min(100.0) as f64 / 100.0).round() as i64),
+        speed_bps: speed.as_deref().and_then(bytes),
+        eta_seconds: eta.as_deref().and_then(seconds),
+        speed,
+        eta,
+    })
+}
+
+impl Progress {
+    // The status message a progress update stands for
+    pub fn message(&self) -> ItemMessage {
+        ItemMessage::new(messages::DOWNLOAD_PROGRESS)
+            .with("percent", (self.percent as f64 * 10.0).round() / 10.0)
+    }
+}
+
+// Whether progress is written to the item row at all; anything but "memory" does
+pub fn persisted(settings: &AppSettings) -> bool {
+    settings.progress_persistence.as_deref().map(str::trim) != Some(PERSIST_MEMORY)
+}
+
+// Check progress_persistence before it is saved; unset means "throttled"
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    match settings.progress_persistence.as_deref().map(str::trim) {
+        None | Some("") | Some(PERSIST_THROTTLED) | Some(PERSIST_MEMORY) => Ok(()),
+        Some(other) => Err(format!(
+            "Invalid progress_persistence '{}'; use '{}' or '{}'",
+            other, PERSIST_THROTTLED, PERSIST_MEMORY
+        )),
+    }
+}
+
+pub fn emit(app_handle: &AppHandle, progress: &Progress) {
+    if let Err(e) = app_handle.emit_all(EVENT, progress) {
+        eprintln!("Failed to emit {} event: {}", EVENT, e);
+    }
+}
+
+// Decides which progress updates of one download are written to the database
+#[derive(Default)]
+pub struct WriteThrottle {
+    last_write: Option<Instant>,
+}
+
+impl WriteThrottle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The first update, the final 100% and at most one per interval in between
+    pub fn should_write(&mut self, percent: f32) -> bool {
+        let due = match self.last_write {
+            None => true,
+            Some(at) => percent >= 100.0 || at.elapsed() >= DB_WRITE_INTERVAL,
+        };
+        if due {
+            self.last_write = Some(Instant::now());
+        }
+        due
+    }
+}
+
+// Latest progress of each running download
+#[derive(Default)]
+pub struct LiveProgress {
+    items: Mutex<HashMap<String, Progress>>,
+}
+
+impl LiveProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&self, progress: Progress) {
+        self.items
+            .lock()
+            .unwrap()
+            .insert(progress.item_id.clone(), progress);
+    }
+
+    pub fn remove(&self, item_id: &str) {
+        self.items.lock().unwrap().remove(item_id);
+    }
+
+    pub fn get(&self, item_id: &str) -> Option<Progress> {
+        self.items.lock().unwrap().get(item_id).cloned()
+    }
+
+    pub fn all(&self) -> Vec<Progress> {
+        self.items.lock().unwrap().values().cloned().collect()
+    }
+
+    // Show in-memory progress on items read from the database
+    pub fn overlay(&self, items: &mut [QueueItem]) {
+        let live = self.items.lock().unwrap();
+        for item in items.iter_mut().filter(|item| item.status == "downloading") {
+            if let Some(progress) = item.id.as_deref().and_then(|id| live.get(id)) {
+                item.total_bytes = progress.total_bytes;
+                item.downloaded_bytes = progress.downloaded_bytes;
+                item.speed_bps = progress.speed_bps;
+                item.eta_seconds = progress.eta_seconds;
+                let message = progress.message();
+                item.message = Some(message.text());
+                item.message_params = Some(serde_json::Value::Object(message.params.clone()));
+                item.message_code = Some(message.code);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_full_progress_line() {
+        let progress = parse(
+            "item",
+            "[download]  50.0% of ~ 10.00MiB at  2.00MiB/s ETA 1:02:03 (frag 3/20)",
+        )
+        .unwrap();
+        assert_eq!(progress.percent, 50.0);
+        assert_eq!(progress.total_bytes, Some(10 * 1024 * 1024));
+        assert_eq!(progress.downloaded_bytes, Some(5 * 1024 * 1024));
+        assert_eq!(progress.speed.as_deref(), Some("2.00MiB/s"));
+        assert_eq!(progress.speed_bps, Some(2 * 1024 * 1024));
+        assert_eq!(progress.eta_seconds, Some(3723));
+    }
+
+    #[test]
+    fn leaves_unknown_values_out() {
+        let progress = parse(
+            "item",
+            "[download]   5.0% of Unknown B at Unknown B/s ETA Unknown",
+        )
+        .unwrap();
+        assert_eq!(progress.percent, 5.0);
+        assert_eq!(progress.total_bytes, None);
+        assert_eq!(progress.downloaded_bytes, None);
+        assert_eq!(progress.speed, None);
+        assert_eq!(progress.eta_seconds, None);
+    }
+
+    #[test]
+    fn ignores_other_lines() {
+        assert!(parse("item", "[info] Downloading 1 format(s): 22").is_none());
+        assert!(parse("item", "[download] Destination: video.mp4").is_none());
+    }
+}
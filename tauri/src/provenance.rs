@@ -0,0 +1,143 @@
+// Per-item provenance manifest: where a copy came from, when and how it was captured,
+// what exactly was stored and where it was uploaded. This is the chain-of-custody
+// record someone citing an archived copy needs, exported as a standalone JSON file.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::db::{ProvenanceRecord, QueueItem};
+use crate::filemoon;
+use crate::subtitles;
+use crate::timestamps;
+
+// Bumped whenever fields are removed or change meaning
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProvenanceManifest {
+    pub manifest_version: u32,
+    pub generated_at: String,
+    pub item_id: String,
+    pub title: Option<String>,
+    pub source: SourceInfo,
+    pub capture: Option<CaptureInfo>,
+    pub destinations: Vec<Destination>,
+    // Subtitle files archived beside the video
+    #[serde(default)]
+    pub subtitles: Vec<SubtitleFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceInfo {
+    // URL exactly as it was queued
+    pub original_url: String,
+    // Canonical page URL reported by the extractor
+    pub webpage_url: Option<String>,
+    pub extractor: Option<String>,
+    pub video_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaptureInfo {
+    pub captured_at: String,
+    pub ytdlp_version: Option<String>,
+    pub format: Option<String>,
+    pub download_sections: Option<String>,
+    pub file_name: Option<String>,
+    pub file_size: Option<i64>,
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubtitleFile {
+    pub language: Option<String>,
+    pub file_name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Destination {
+    pub provider: String,
+    pub url: String,
+}
+
+// SHA-256 of a file as lowercase hex, read in chunks so large videos aren't loaded whole
+pub fn sha256_file(path: &Path) -> std::io::Result<(String, u64)> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+    let mut total: u64 = 0;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        total += read as u64;
+    }
+
+    let digest = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    Ok((digest, total))
+}
+
+pub fn build_manifest(item: &QueueItem, record: Option<&ProvenanceRecord>) -> ProvenanceManifest {
+    let mut destinations = Vec::new();
+    if let Some(code) = item.filemoon_url.as_deref().filter(|c| !c.is_empty()) {
+        destinations.push(Destination {
+            provider: "filemoon".to_string(),
+            url: filemoon::player_url(code),
+        });
+    }
+    if let Some(short) = item.short_url.as_deref().filter(|s| !s.is_empty()) {
+        destinations.push(Destination {
+            provider: "shortlink".to_string(),
+            url: short.to_string(),
+        });
+    }
+
+    let capture = record.map(|record| CaptureInfo {
+        captured_at: timestamps::to_iso(&record.captured_at),
+        ytdlp_version: record.ytdlp_version.clone(),
+        format: record.format.clone().or_else(|| item.format_used.clone()),
+        download_sections: item.download_sections.clone(),
+        file_name: record.file_name.clone(),
+        file_size: record.file_size,
+        sha256: record.sha256.clone(),
+    });
+
+    let subtitles = item
+        .subtitle_paths
+        .iter()
+        .flatten()
+        .map(|path| SubtitleFile {
+            language: subtitles::language_of(path),
+            file_name: Path::new(path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| path.clone()),
+        })
+        .collect();
+
+    ProvenanceManifest {
+        manifest_version: MANIFEST_VERSION,
+        generated_at: timestamps::to_iso(&timestamps::now()),
+        item_id: item.id.clone().unwrap_or_default(),
+        title: item.title.clone(),
+        source: SourceInfo {
+            original_url: item.url.clone(),
+            webpage_url: record.and_then(|r| r.webpage_url.clone()),
+            extractor: record.and_then(|r| r.extractor.clone()),
+            video_id: record.and_then(|r| r.video_id.clone()),
+        },
+        capture,
+        destinations,
+        subtitles,
+    }
+}
@@ -0,0 +1,147 @@
+// Filemoon maintenance handling. Uploads that hit a maintenance response are held
+// as "provider_unavailable" rather than failed; a background probe checks the API
+// every few minutes and re-queues every held upload once it answers again. The UI
+// gets one event when the outage starts and one when it ends, not one per item.
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::messages::{self, ItemMessage};
+use crate::{filemoon, http, settings_watch, timestamps, AppState};
+
+pub const HELD_STATUS: &str = "provider_unavailable";
+const PROBE_INTERVAL: Duration = Duration::from_secs(180);
+
+#[derive(Default)]
+pub struct ProviderWatch {
+    // When the current outage was first seen; None while Filemoon is up
+    down_since: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl ProviderWatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns true when this starts a new outage
+    fn mark_down(&self) -> bool {
+        let mut down_since = self.down_since.lock().unwrap();
+        if down_since.is_some() {
+            return false;
+        }
+        *down_since = Some(timestamps::now());
+        true
+    }
+
+    // Returns when the outage started, if one was in progress
+    fn mark_up(&self) -> Option<DateTime<Utc>> {
+        self.down_since.lock().unwrap().take()
+    }
+}
+
+// Called for every held upload; only the first one of an outage notifies the UI
+pub fn report_outage(app_handle: &AppHandle, watch: &ProviderWatch, detail: &str) {
+    if !watch.mark_down() {
+        return;
+    }
+    println!("Filemoon appears to be under maintenance: {}", detail);
+    let payload = json!({
+        "provider": "filemoon",
+        "message": "Filemoon is under maintenance. Uploads are on hold and resume automatically.",
+        "detail": detail,
+    });
+    if let Err(e) = app_handle.emit_all("provider_unavailable", payload) {
+        eprintln!("Failed to emit provider_unavailable event: {}", e);
+    }
+}
+
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting provider maintenance watch...");
+
+    // A settings change starts the next sweep at once (see settings_watch.rs)
+    let mut changes = app_handle.state::<AppState>().settings_watch.subscribe();
+    loop {
+        settings_watch::wait(&mut changes, PROBE_INTERVAL).await;
+        // Fetched each time, so changed provider settings apply to the next probe
+        let client = http::client();
+        if let Err(e) = probe_and_resume(&app_handle, &client).await {
+            eprintln!("Provider maintenance probe failed: {}", e);
+        }
+    }
+}
+
+async fn probe_and_resume(
+    app_handle: &AppHandle,
+    client: &reqwest::Client,
+) -> Result<(), String> {
+    let app_state = app_handle.state::<AppState>();
+    let held = app_state
+        .db
+        .get_items_in_statuses(&[HELD_STATUS])
+        .await
+        .map_err(|e| format!("Failed to load held items: {}", e))?;
+    if held.is_empty() {
+        app_state.provider_watch.mark_up();
+        return Ok(());
+    }
+
+    // Any held item's API key will do for asking Filemoon whether it is back
+    let user_id = held[0]
+        .user_id
+        .clone()
+        .unwrap_or_else(|| "local-user".to_string());
+    let settings = app_state
+        .db
+        .get_settings(&user_id)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let api_key = match settings.filemoon_api_key.filter(|key| !key.is_empty()) {
+        Some(key) => key,
+        None => return Err("No Filemoon API key configured".to_string()),
+    };
+
+    if let Err(e) = filemoon::request_upload_server(client, &api_key).await {
+        println!("Filemoon still unavailable: {}", e);
+        return Ok(());
+    }
+
+    let mut resumed = 0;
+    for item in held {
+        let (item_id, user_id) = match (item.id, item.user_id) {
+            (Some(id), Some(user_id)) => (id, user_id),
+            _ => continue,
+        };
+        if let Err(e) = app_state
+            .db
+            .update_item_status(
+                &item_id,
+                "downloaded",
+                Some(ItemMessage::new(messages::UPLOAD_PROVIDER_BACK)),
+            )
+            .await
+        {
+            eprintln!("Error releasing held item {}: {}", item_id, e);
+            continue;
+        }
+        match app_state.uploads.enqueue(item_id.clone(), user_id) {
+            Ok(()) => resumed += 1,
+            Err(e) => eprintln!("Error re-queueing upload for item {}: {}", item_id, e),
+        }
+    }
+
+    let since = app_state.provider_watch.mark_up();
+    println!("Filemoon is available again; resumed {} held upload(s)", resumed);
+    let payload = json!({
+        "provider": "filemoon",
+        "message": format!("Filemoon is back. Resumed {} held upload(s).", resumed),
+        "resumed": resumed,
+        "down_since": since.as_ref().map(timestamps::to_iso),
+    });
+    if let Err(e) = app_handle.emit_all("provider_available", payload) {
+        eprintln!("Failed to emit provider_available event: {}", e);
+    }
+    Ok(())
+}
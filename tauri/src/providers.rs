@@ -0,0 +1,669 @@
+// Upload providers declared in JSON rather than in code. Many small hosts work the
+// way Filemoon does: ask one endpoint for an upload server, POST the file there as
+// multipart form data, then poll a status endpoint until the file plays. A host like
+// that can be added by describing it in providers.json in the app data directory (or
+// the file named by PERMAVID_PROVIDERS_FILE). The file is read once at startup, and
+// setting upload_target to a declared name sends uploads there instead of Filemoon.
+//
+// {
+//   "providers": [{
+//     "name": "streamhost",
+//     "api_key_env": "STREAMHOST_KEY",
+//     "server": {
+//       "url": "https://streamhost.example/api/upload/server?key={api_key}",
+//       "url_field": "result"
+//     },
+//     "upload": {
+//       "file_field": "file",
+//       "fields": { "key": "{api_key}" },
+//       "success_field": "status",
+//       "success_values": ["200"],
+//       "file_code_field": "files.0.filecode"
+//     },
+//     "status": {
+//       "url": "https://streamhost.example/api/file/info?key={api_key}&file_code={file_code}",
+//       "ready_field": "result.0.canplay",
+//       "ready_values": ["1"]
+//     },
+//     "link": "https://streamhost.example/d/{file_code}"
+//   }]
+// }
+//
+// Fields are dotted paths into the JSON answer, with numbers indexing lists. Values
+// are compared as text, so 200 and "200" both match. Without "server" the file goes
+// straight to upload.url; without "status" the item stays "uploaded" once the host
+// accepts the file. The API key comes from "api_key" or the environment variable
+// named by "api_key_env".
+//
+// Uploads to a declared provider record the provider and link on the item
+// (upload_provider, upload_url) and leave filemoon_url empty, so the Filemoon status
+// checks skip them. safe_delete counts such an upload as verified once the status
+// step has reported it ready.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::time::sleep;
+use tokio_util::io::ReaderStream;
+
+use crate::messages::{self, ItemMessage};
+use crate::upload::record_provider_error;
+use crate::{capabilities, filemoon, http, lenient, mirrors, quota, AppState};
+
+const FILE_NAME: &str = "providers.json";
+pub const FILE_ENV: &str = "PERMAVID_PROVIDERS_FILE";
+
+const DEFAULT_FILE_FIELD: &str = "file";
+const DEFAULT_STATUS_INTERVAL_SECS: u64 = 30;
+const DEFAULT_STATUS_MAX_CHECKS: u32 = 60;
+
+pub const STEP_SERVER: &str = "server";
+pub const STEP_UPLOAD: &str = "upload";
+pub const STEP_STATUS: &str = "status";
+
+lazy_static! {
+    static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{([a-z_]+)\}").unwrap();
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderDefinition {
+    pub name: String,
+    // Never sent to the frontend
+    #[serde(default, skip_serializing)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    #[serde(default)]
+    pub server: Option<ServerStep>,
+    pub upload: UploadStep,
+    #[serde(default)]
+    pub status: Option<StatusStep>,
+    // Where the file can be watched, e.g. "https://host/d/{file_code}"
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ServerStep {
+    pub url: String,
+    // Field holding the upload URL
+    pub url_field: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UploadStep {
+    // Defaults to "{server}", the URL from the server step
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "default_file_field")]
+    pub file_field: String,
+    // Extra form fields sent with the file
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    #[serde(default)]
+    pub success_field: Option<String>,
+    #[serde(default)]
+    pub success_values: Vec<String>,
+    pub file_code_field: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatusStep {
+    pub url: String,
+    pub ready_field: String,
+    pub ready_values: Vec<String>,
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    #[serde(default)]
+    pub max_checks: Option<u32>,
+}
+
+fn default_file_field() -> String {
+    DEFAULT_FILE_FIELD.to_string()
+}
+
+#[derive(Deserialize)]
+struct DefinitionFile {
+    providers: Vec<JsonValue>,
+}
+
+// What list_providers returns: the definitions in use and why any were skipped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderListing {
+    pub file: Option<String>,
+    pub providers: Vec<ProviderDefinition>,
+    pub errors: Vec<String>,
+}
+
+// A failed step of an upload or status check
+#[derive(Debug)]
+pub struct StepError {
+    pub step: &'static str,
+    pub http_status: Option<u16>,
+    pub message: String,
+    pub body: Option<String>,
+    // Worth retrying: a timeout, dropped connection or 5xx answer
+    pub transient: bool,
+}
+
+impl StepError {
+    pub fn new(step: &'static str, message: String) -> Self {
+        StepError {
+            step,
+            http_status: None,
+            message,
+            body: None,
+            transient: false,
+        }
+    }
+}
+
+pub enum Readiness {
+    Ready,
+    // Not ready yet; carries the value the host answered with
+    Pending(String),
+}
+
+// Placeholders each template may use
+const SERVER_PLACEHOLDERS: &[&str] = &["api_key"];
+const UPLOAD_PLACEHOLDERS: &[&str] = &["api_key", "server", "file_name"];
+const STATUS_PLACEHOLDERS: &[&str] = &["api_key", "file_code"];
+const LINK_PLACEHOLDERS: &[&str] = &["file_code"];
+
+fn check_placeholders(
+    what: &str,
+    template: &str,
+    allowed: &[&str],
+    used: &mut bool,
+) -> Result<(), String> {
+    for captures in PLACEHOLDER_REGEX.captures_iter(template) {
+        let name = &captures[1];
+        if !allowed.contains(&name) {
+            return Err(format!(
+                "{} uses unknown placeholder {{{}}}; allowed here: {}",
+                what,
+                name,
+                allowed.join(", ")
+            ));
+        }
+        *used |= name == "api_key";
+    }
+    Ok(())
+}
+
+fn check_url(what: &str, url: &str) -> Result<(), String> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} must start with http:// or https://: {}",
+            what, url
+        ))
+    }
+}
+
+impl ProviderDefinition {
+    // Check and tidy a definition read from the file
+    fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_ascii_lowercase();
+        if self.name.is_empty() {
+            return Err("Provider name must not be empty".to_string());
+        }
+        if capabilities::UPLOAD_PROVIDERS.contains(&self.name.as_str())
+            || quota::PROVIDERS.contains(&self.name.as_str())
+        {
+            return Err(format!("'{}' is a built-in provider name", self.name));
+        }
+
+        let mut uses_key = false;
+        if let Some(server) = &self.server {
+            check_url("server.url", &server.url)?;
+            check_placeholders(
+                "server.url",
+                &server.url,
+                SERVER_PLACEHOLDERS,
+                &mut uses_key,
+            )?;
+        }
+        let upload_url = self.upload.url.as_deref().unwrap_or("{server}");
+        if upload_url.starts_with("{server}") {
+            if self.server.is_none() {
+                return Err("upload.url is required when there is no server step".to_string());
+            }
+        } else {
+            check_url("upload.url", upload_url)?;
+        }
+        check_placeholders("upload.url", upload_url, UPLOAD_PLACEHOLDERS, &mut uses_key)?;
+        for (field, value) in &self.upload.fields {
+            check_placeholders(
+                &format!("upload.fields.{}", field),
+                value,
+                UPLOAD_PLACEHOLDERS,
+                &mut uses_key,
+            )?;
+        }
+        if self.upload.success_field.is_some() && self.upload.success_values.is_empty() {
+            return Err("upload.success_values must not be empty".to_string());
+        }
+        if let Some(status) = &self.status {
+            check_url("status.url", &status.url)?;
+            check_placeholders(
+                "status.url",
+                &status.url,
+                STATUS_PLACEHOLDERS,
+                &mut uses_key,
+            )?;
+            if status.ready_values.is_empty() {
+                return Err("status.ready_values must not be empty".to_string());
+            }
+        }
+        if let Some(link) = &self.link {
+            check_url("link", link)?;
+            check_placeholders("link", link, LINK_PLACEHOLDERS, &mut uses_key)?;
+        }
+
+        if uses_key && self.api_key.is_none() && self.api_key_env.is_none() {
+            return Err("{api_key} is used but neither api_key nor api_key_env is set".to_string());
+        }
+        Ok(())
+    }
+
+    // The key from the definition, or from the environment variable it names
+    pub fn api_key(&self) -> Result<String, String> {
+        if let Some(var) = &self.api_key_env {
+            return std::env::var(var)
+                .map(|key| key.trim().to_string())
+                .ok()
+                .filter(|key| !key.is_empty())
+                .ok_or_else(|| {
+                    format!(
+                        "{} API key: environment variable {} is not set",
+                        self.name, var
+                    )
+                });
+        }
+        Ok(self.api_key.clone().unwrap_or_default())
+    }
+
+    pub fn link(&self, file_code: &str) -> String {
+        match &self.link {
+            Some(link) => render(link, &[("file_code", file_code)]),
+            None => file_code.to_string(),
+        }
+    }
+
+    // Step 1: ask for the URL to upload to
+    pub async fn request_server(
+        &self,
+        client: &reqwest::Client,
+        api_key: &str,
+    ) -> Result<Option<String>, StepError> {
+        let server = match &self.server {
+            Some(server) => server,
+            None => return Ok(None),
+        };
+        let url = render(&server.url, &[("api_key", api_key)]);
+        let body = send_json(STEP_SERVER, client.get(url)).await?;
+        match field_text(&body, &server.url_field) {
+            Some(upload_url) => Ok(Some(upload_url)),
+            None => Err(StepError {
+                body: Some(body.to_string()),
+                ..StepError::new(
+                    STEP_SERVER,
+                    format!("No upload server in field '{}'", server.url_field),
+                )
+            }),
+        }
+    }
+
+    // Step 2: send the file; returns the file code the host gave it
+    pub async fn send_file(
+        &self,
+        client: &reqwest::Client,
+        server: Option<&str>,
+        api_key: &str,
+        path: &Path,
+        file_name: &str,
+        bytes: u64,
+    ) -> Result<String, StepError> {
+        let vars = [
+            ("api_key", api_key),
+            ("server", server.unwrap_or_default()),
+            ("file_name", file_name),
+        ];
+        let url = render(self.upload.url.as_deref().unwrap_or("{server}"), &vars);
+
+        // Streamed like Filemoon uploads, so memory use stays flat
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| StepError::new(STEP_UPLOAD, format!("Failed to open file: {}", e)))?;
+        let file_stream = ReaderStream::with_capacity(file, filemoon::UPLOAD_CHUNK_BYTES);
+        let mut form = reqwest::multipart::Form::new();
+        for (field, value) in &self.upload.fields {
+            form = form.text(field.clone(), render(value, &vars));
+        }
+        let form = form.part(
+            self.upload.file_field.clone(),
+            reqwest::multipart::Part::stream_with_length(
+                reqwest::Body::wrap_stream(file_stream),
+                bytes,
+            )
+            .file_name(file_name.to_string()),
+        );
+
+        let body = send_json(STEP_UPLOAD, client.post(url).multipart(form)).await?;
+        let rejected = |message: String| StepError {
+            body: Some(body.to_string()),
+            ..StepError::new(STEP_UPLOAD, message)
+        };
+        if let Some(field) = &self.upload.success_field {
+            let value = field_text(&body, field).unwrap_or_default();
+            if !self.upload.success_values.contains(&value) {
+                return Err(rejected(format!(
+                    "Upload rejected ({} = '{}')",
+                    field, value
+                )));
+            }
+        }
+        field_text(&body, &self.upload.file_code_field).ok_or_else(|| {
+            rejected(format!(
+                "No file code in field '{}'",
+                self.upload.file_code_field
+            ))
+        })
+    }
+
+    // Step 3: whether the host reports the file ready
+    pub async fn check_ready(
+        &self,
+        client: &reqwest::Client,
+        api_key: &str,
+        file_code: &str,
+    ) -> Result<Readiness, StepError> {
+        let status = match &self.status {
+            Some(status) => status,
+            None => return Ok(Readiness::Ready),
+        };
+        let url = render(
+            &status.url,
+            &[("api_key", api_key), ("file_code", file_code)],
+        );
+        let body = send_json(STEP_STATUS, client.get(url)).await?;
+        match field_text(&body, &status.ready_field) {
+            Some(value) if status.ready_values.contains(&value) => Ok(Readiness::Ready),
+            Some(value) => Ok(Readiness::Pending(format!(
+                "{} = '{}'",
+                status.ready_field, value
+            ))),
+            None => Ok(Readiness::Pending(format!("no {}", status.ready_field))),
+        }
+    }
+}
+
+fn render(template: &str, vars: &[(&str, &str)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+// Follow a dotted path ("files.0.filecode"). A single object stands in for a
+// one-entry list, as Filemoon sometimes answers that way.
+fn lookup<'a>(value: &'a JsonValue, path: &str) -> Option<&'a JsonValue> {
+    path.split('.')
+        .filter(|key| !key.is_empty())
+        .try_fold(value, |value, key| match value {
+            JsonValue::Array(entries) => key.parse::<usize>().ok().and_then(|i| entries.get(i)),
+            JsonValue::Object(map) => map.get(key).or_else(|| (key == "0").then_some(value)),
+            _ => None,
+        })
+}
+
+fn field_text(body: &JsonValue, path: &str) -> Option<String> {
+    lookup(body, path)
+        .and_then(lenient::text)
+        .filter(|text| !text.is_empty())
+}
+
+async fn send_json(
+    step: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<JsonValue, StepError> {
+    let response = request.send().await.map_err(|e| StepError {
+        transient: filemoon::is_transient_error(&e),
+        ..StepError::new(step, format!("Request failed: {}", e))
+    })?;
+    let status = response.status().as_u16();
+    let text = response.text().await.map_err(|e| StepError {
+        http_status: Some(status),
+        ..StepError::new(step, format!("Failed to read response: {}", e))
+    })?;
+    if !(200..300).contains(&status) {
+        return Err(StepError {
+            http_status: Some(status),
+            body: Some(text),
+            transient: filemoon::is_transient_status(status),
+            ..StepError::new(step, format!("HTTP {}", status))
+        });
+    }
+    serde_json::from_str(&text).map_err(|e| StepError {
+        http_status: Some(status),
+        body: Some(text.clone()),
+        ..StepError::new(step, format!("Response is not JSON: {}", e))
+    })
+}
+
+// Definitions loaded at startup
+pub struct ProviderRegistry {
+    file: Option<PathBuf>,
+    definitions: Vec<ProviderDefinition>,
+    errors: Vec<String>,
+}
+
+impl ProviderRegistry {
+    // Read the definitions file named by PERMAVID_PROVIDERS_FILE, or providers.json in
+    // `dir`. A missing file means no declared providers; a definition that fails to
+    // load is skipped and reported by list_providers.
+    pub fn open(dir: Option<PathBuf>) -> Self {
+        let file = std::env::var(FILE_ENV)
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .map(PathBuf::from)
+            .or_else(|| dir.map(|d| d.join(FILE_NAME)));
+        let mut registry = ProviderRegistry {
+            file: file.clone(),
+            definitions: Vec::new(),
+            errors: Vec::new(),
+        };
+        let path = match file.filter(|path| path.exists()) {
+            Some(path) => path,
+            None => return registry,
+        };
+
+        let entries = match fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                serde_json::from_str::<DefinitionFile>(&content).map_err(|e| e.to_string())
+            }) {
+            Ok(file) => file.providers,
+            Err(e) => {
+                registry
+                    .errors
+                    .push(format!("Failed to read {}: {}", path.display(), e));
+                eprintln!("{}", registry.errors[0]);
+                return registry;
+            }
+        };
+
+        for (index, entry) in entries.into_iter().enumerate() {
+            let loaded = serde_json::from_value::<ProviderDefinition>(entry)
+                .map_err(|e| e.to_string())
+                .and_then(|mut definition| {
+                    definition.validate()?;
+                    if registry.get(&definition.name).is_some() {
+                        return Err(format!("'{}' is declared twice", definition.name));
+                    }
+                    Ok(definition)
+                });
+            match loaded {
+                Ok(definition) => {
+                    println!("Loaded upload provider '{}'", definition.name);
+                    registry.definitions.push(definition);
+                }
+                Err(e) => {
+                    let error = format!("Provider #{} in {}: {}", index + 1, path.display(), e);
+                    eprintln!("{}", error);
+                    registry.errors.push(error);
+                }
+            }
+        }
+        registry
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProviderDefinition> {
+        let name = name.trim();
+        self.definitions
+            .iter()
+            .find(|definition| definition.name.eq_ignore_ascii_case(name))
+    }
+
+    // Check an upload_target: blank (Filemoon), or a comma-separated list of built-in
+    // and declared providers (see mirrors.rs)
+    pub fn check_target(&self, target: Option<&str>) -> Result<(), String> {
+        for target in mirrors::parse_targets(target) {
+            self.check_one_target(&target)?;
+        }
+        Ok(())
+    }
+
+    fn check_one_target(&self, target: &str) -> Result<(), String> {
+        let built_in = capabilities::UPLOAD_PROVIDERS
+            .iter()
+            .any(|provider| provider.eq_ignore_ascii_case(target));
+        if built_in || self.get(target).is_some() {
+            return Ok(());
+        }
+        let mut supported: Vec<String> = capabilities::UPLOAD_PROVIDERS
+            .iter()
+            .map(|provider| provider.to_string())
+            .collect();
+        supported.extend(self.names());
+        Err(format!(
+            "Unknown upload target '{}'. Supported: {}",
+            target,
+            supported.join(", ")
+        ))
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.definitions.iter().map(|d| d.name.clone()).collect()
+    }
+
+    pub fn listing(&self) -> ProviderListing {
+        ProviderListing {
+            file: self.file.as_ref().map(|path| path.display().to_string()),
+            providers: self.definitions.clone(),
+            errors: self.errors.clone(),
+        }
+    }
+}
+
+// Poll the status endpoint after an upload until the host reports the file ready,
+// then mark the item "encoded". Stops early if the item moves on in the meantime.
+pub async fn watch(
+    app_handle: AppHandle,
+    item_id: String,
+    definition: ProviderDefinition,
+    file_code: String,
+) {
+    let status = match &definition.status {
+        Some(status) => status,
+        None => return,
+    };
+    let app_state = app_handle.state::<AppState>();
+    let api_key = match definition.api_key() {
+        Ok(key) => key,
+        Err(e) => {
+            eprintln!("Status checks for item {} skipped: {}", item_id, e);
+            return;
+        }
+    };
+    let client = http::client();
+    let interval = Duration::from_secs(
+        status
+            .interval_secs
+            .unwrap_or(DEFAULT_STATUS_INTERVAL_SECS)
+            .max(1),
+    );
+    let max_checks = status.max_checks.unwrap_or(DEFAULT_STATUS_MAX_CHECKS);
+    let mut detail = "no answer".to_string();
+
+    for _ in 0..max_checks {
+        sleep(interval).await;
+        match app_state.db.get_item_by_id(&item_id).await {
+            Ok(Some(item)) if item.status == "uploaded" => {}
+            Ok(_) => return,
+            Err(e) => {
+                eprintln!("Error loading item {} for a status check: {}", item_id, e);
+                continue;
+            }
+        }
+
+        match definition.check_ready(&client, &api_key, &file_code).await {
+            Ok(Readiness::Ready) => {
+                let message = ItemMessage::new(messages::UPLOAD_DECLARED_READY)
+                    .with("provider", definition.name.clone());
+                if let Err(e) = app_state
+                    .db
+                    .update_item_status(&item_id, "encoded", Some(message))
+                    .await
+                {
+                    eprintln!(
+                        "Error updating status after {} check: {}",
+                        definition.name, e
+                    );
+                }
+                return;
+            }
+            Ok(Readiness::Pending(answer)) => detail = answer,
+            Err(e) => {
+                record_provider_error(
+                    &app_state,
+                    Some(&item_id),
+                    &format!("{}/{}", definition.name, e.step),
+                    e.http_status,
+                    &e.message,
+                    e.body.as_deref(),
+                )
+                .await;
+                detail = e.message;
+            }
+        }
+    }
+
+    let message = ItemMessage::new(messages::UPLOAD_DECLARED_NOT_READY)
+        .with("provider", definition.name.clone())
+        .with("checks", max_checks)
+        .with("detail", detail);
+    println!("Item {}: {}", item_id, message.text());
+    if let Err(e) = app_state
+        .db
+        .update_item_status(&item_id, "uploaded", Some(message))
+        .await
+    {
+        eprintln!(
+            "Error updating status after {} checks: {}",
+            definition.name, e
+        );
+    }
+}
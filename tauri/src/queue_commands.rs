@@ -0,0 +1,1422 @@
+// Commands for the queue itself: adding, listing and editing items, retrying,
+// cancelling and reordering them, and queue templates.
+
+use std::fs;
+use std::path::Path;
+use tauri::{Manager, State};
+
+use crate::bulk_add::{self, BulkAddResult};
+use crate::bulk_edit::BulkFilter;
+use crate::confirmations::{self, Confirmed, Plan};
+use crate::db::{AppSettings, ClearResult, Playlist, QueueItem, QueueTemplate};
+use crate::download_window::DownloadWindow;
+use crate::formats::{self, FormatList};
+use crate::instance::InstanceRole;
+use crate::messages::{self, ItemMessage};
+use crate::output_tail::OutputLine;
+use crate::planner::{self, QueuePlan};
+use crate::playlists::{self, PlaylistImport, PlaylistRollup};
+use crate::progress::Progress;
+use crate::safe_delete::{self, LocalDeletion};
+use crate::status_refresh::{self, RefreshSummary};
+use crate::sync::{self, ChangeSet, SyncCursor};
+use crate::{
+    auto_upload, cancellation, filemoon, long_paths, performance, remote_transfer, retry_policy,
+    scheduler, sections, templates, thumbnails, timestamps, tools, urls, AppState, Response,
+};
+
+#[tauri::command]
+pub fn open_external_link(window: tauri::Window, url: String) -> Result<(), String> {
+    match window.shell_scope().open(&url, None) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn get_queue_items(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<QueueItem>>, String> {
+    match app_state.db.get_queue_items(&user_id).await {
+        Ok(mut items) => {
+            app_state.live_progress.overlay(&mut items);
+            Ok(Response {
+                success: true,
+                message: "Queue items retrieved successfully".to_string(),
+                data: Some(items),
+            })
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Progress of the running downloads as held in memory, including what hasn't been
+// written to the database (all of it with progress_persistence set to "memory")
+#[tauri::command]
+pub fn get_live_progress(app_state: State<'_, AppState>) -> Result<Response<Vec<Progress>>, String> {
+    let live = app_state.live_progress.all();
+    Ok(Response {
+        success: true,
+        message: format!("{} download(s) in progress", live.len()),
+        data: Some(live),
+    })
+}
+
+// Write the in-memory progress of one running download, or of all of them, to the
+// database, e.g. before handing the queue over to another instance
+#[tauri::command]
+pub async fn flush_progress(
+    item_id: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<usize>, String> {
+    let live = match item_id {
+        Some(id) => app_state.live_progress.get(&id).into_iter().collect(),
+        None => app_state.live_progress.all(),
+    };
+    let mut flushed = 0;
+    for progress in live {
+        app_state
+            .db
+            .update_item_status(&progress.item_id, "downloading", Some(progress.message()))
+            .await
+            .map_err(|e| format!("Database error saving progress: {}", e))?;
+        flushed += 1;
+    }
+
+    Ok(Response {
+        success: true,
+        message: format!("Saved progress of {} download(s)", flushed),
+        data: Some(flushed),
+    })
+}
+
+// Items changed since `cursor`, for companion apps that sync the archive index incrementally
+#[tauri::command]
+pub async fn get_changes_since(
+    user_id: String,
+    cursor: Option<String>,
+    limit: Option<i64>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<ChangeSet>, String> {
+    let previous = SyncCursor::parse(cursor.as_deref())?;
+    let limit = sync::page_size(limit);
+
+    let after_time = previous.as_ref().map(|cursor| cursor.updated_at);
+    let after_id = previous.as_ref().map_or("", |cursor| cursor.id.as_str());
+    match app_state
+        .db
+        .get_changes_since(&user_id, after_time, after_id, limit + 1)
+        .await
+    {
+        Ok(items) => {
+            let changes = sync::change_set(items, limit, previous.as_ref());
+            Ok(Response {
+                success: true,
+                message: format!("{} changed item(s) retrieved", changes.items.len()),
+                data: Some(changes),
+            })
+        }
+        Err(e) => Err(format!("Database error retrieving changes: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn add_queue_item(
+    item: QueueItem,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let mut item_with_user = item;
+    item_with_user.user_id = Some(user_id);
+    item_with_user.url = urls::normalize_url(&item_with_user.url);
+    if let Some(raw_sections) = item_with_user.download_sections.take() {
+        item_with_user.download_sections = sections::normalize(&raw_sections)?;
+    }
+    match app_state.db.add_queue_item(&item_with_user).await {
+        Ok(id) => {
+            app_state.queue_wakeup.wake();
+            Ok(Response {
+                success: true,
+                message: "Item added to queue successfully".to_string(),
+                data: Some(id),
+            })
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Queue a pasted list of URLs at once, with a result per URL (see bulk_add.rs)
+#[tauri::command]
+pub async fn add_queue_items_bulk(
+    urls: Vec<String>,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<BulkAddResult>>, String> {
+    let results = bulk_add::add(&app_state, &urls, &user_id).await?;
+    let added = results
+        .iter()
+        .filter(|r| r.result == bulk_add::RESULT_ADDED)
+        .count();
+    Ok(Response {
+        success: true,
+        message: format!("{} of {} URL(s) added to queue", added, results.len()),
+        data: Some(results),
+    })
+}
+
+// Queue every video of a playlist or channel, grouped under one playlist id
+#[tauri::command]
+pub async fn add_playlist(
+    url: String,
+    user_id: String,
+    limit: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<PlaylistImport>, String> {
+    let import = playlists::import(&app_state, &url, &user_id, limit).await?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Queued {} video(s) from playlist, {} skipped",
+            import.item_ids.len(),
+            import.skipped.len()
+        ),
+        data: Some(import),
+    })
+}
+
+#[tauri::command]
+pub async fn get_playlists(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<Playlist>>, String> {
+    match app_state.db.get_playlists(&user_id).await {
+        Ok(playlists) => Ok(Response {
+            success: true,
+            message: format!("{} playlist(s) retrieved", playlists.len()),
+            data: Some(playlists),
+        }),
+        Err(e) => Err(format!("Database error retrieving playlists: {}", e)),
+    }
+}
+
+// Each playlist with its items summed up, e.g. "complete with 5 failures"
+#[tauri::command]
+pub async fn get_playlist_rollups(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<PlaylistRollup>>, String> {
+    let rollups = playlists::rollups(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!("{} playlist rollup(s) retrieved", rollups.len()),
+        data: Some(rollups),
+    })
+}
+
+// The items queued from a playlist; pass status "failed" to list only its failures
+#[tauri::command]
+pub async fn get_children(
+    parent_id: String,
+    status: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<QueueItem>>, String> {
+    match app_state
+        .db
+        .get_playlist_children(&parent_id, status.as_deref())
+        .await
+    {
+        Ok(items) => Ok(Response {
+            success: true,
+            message: format!("{} item(s) in playlist {}", items.len(), parent_id),
+            data: Some(items),
+        }),
+        Err(e) => Err(format!("Database error retrieving playlist items: {}", e)),
+    }
+}
+
+// Queue a video by site name and ID, for integrations that don't have full URLs
+#[tauri::command]
+pub async fn add_by_id(
+    site: String,
+    video_id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let url = urls::canonical_url_for_id(&site, &video_id)?;
+    let item = QueueItem::queued(&url, &user_id);
+
+    match app_state.db.add_queue_item(&item).await {
+        Ok(id) => {
+            app_state.queue_wakeup.wake();
+            Ok(Response {
+                success: true,
+                message: format!("Queued {}", url),
+                data: Some(id),
+            })
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn update_queue_item(
+    item: QueueItem,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    match app_state.db.update_queue_item(&item).await {
+        Ok(_) => Ok(Response {
+            success: true,
+            message: "Queue item updated successfully".to_string(),
+            data: None,
+        }),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn update_item_status(
+    id: String,
+    status: String,
+    message: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    match app_state
+        .db
+        .update_item_status(&id, &status, message.map(ItemMessage::text_only))
+        .await
+    {
+        Ok(_) => Ok(Response {
+            success: true,
+            message: "Status updated successfully".to_string(),
+            data: None,
+        }),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Remove the user's items in the given statuses from the queue. The first call only
+// returns what would go and a token; the second, with the token, removes them (see
+// confirmations.rs).
+#[tauri::command]
+pub async fn clear_completed_items(
+    status_types: Vec<String>,
+    user_id: String,
+    confirm_token: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Confirmed<ClearResult>>, String> {
+    // An empty filter would match everything
+    let items = if status_types.is_empty() {
+        Vec::new()
+    } else {
+        let filter = BulkFilter {
+            statuses: Some(status_types.clone()),
+            ..BulkFilter::default()
+        };
+        app_state
+            .db
+            .find_items(&user_id, &filter)
+            .await
+            .map_err(|e| e.to_string())?
+    };
+    let summary = format!(
+        "Remove {} item(s) with status {} from the queue",
+        items.len(),
+        status_types.join(", ")
+    );
+    let plan = Plan::new(&status_types, items, summary);
+
+    let Some(token) = confirm_token else {
+        let confirmation =
+            app_state
+                .confirmations
+                .issue(confirmations::ACTION_CLEAR, &user_id, &plan);
+        return Ok(Response {
+            success: true,
+            message: confirmation.summary.clone(),
+            data: Some(Confirmed::pending(confirmation)),
+        });
+    };
+    app_state
+        .confirmations
+        .redeem(&token, confirmations::ACTION_CLEAR, &user_id, &plan)?;
+
+    let total_deleted = app_state
+        .db
+        .delete_items(&user_id, &plan.item_ids())
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Cleared {} items with status types: {:?}",
+            total_deleted, status_types
+        ),
+        data: Some(Confirmed::done(ClearResult {
+            total_deleted,
+            status_types,
+            user_id,
+        })),
+    })
+}
+
+// Delete the local files of the user's items that `filter` matches, keeping the items.
+// Two-step like clear_completed_items.
+#[tauri::command]
+pub async fn delete_local_files(
+    user_id: String,
+    filter: BulkFilter,
+    confirm_token: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Confirmed<LocalDeletion>>, String> {
+    let filter = filter.normalized();
+    let items: Vec<QueueItem> = app_state
+        .db
+        .find_items(&user_id, &filter)
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        // Leave files a download, transcode or upload is working on
+        .filter(|item| !["downloading", "transcoding", "uploading"].contains(&item.status.as_str()))
+        .filter(|item| safe_delete::local_size(item).is_some())
+        .collect();
+    let bytes: u64 = items.iter().filter_map(safe_delete::local_size).sum();
+    let summary = format!(
+        "Delete the local files of {} item(s), {:.1} GB",
+        items.len(),
+        bytes as f64 / 1_073_741_824.0
+    );
+    let plan = Plan::new(&filter, items, summary).with_bytes(bytes);
+
+    let Some(token) = confirm_token else {
+        let confirmation =
+            app_state
+                .confirmations
+                .issue(confirmations::ACTION_DELETE_LOCAL, &user_id, &plan);
+        return Ok(Response {
+            success: true,
+            message: confirmation.summary.clone(),
+            data: Some(Confirmed::pending(confirmation)),
+        });
+    };
+    app_state
+        .confirmations
+        .redeem(&token, confirmations::ACTION_DELETE_LOCAL, &user_id, &plan)?;
+
+    let deletion = safe_delete::delete_local_copies(&app_state, &plan.items).await;
+    Ok(Response {
+        success: deletion.failed == 0,
+        message: format!(
+            "Deleted the local files of {} item(s); {} could not be deleted",
+            deletion.deleted, deletion.failed
+        ),
+        data: Some(Confirmed::done(deletion)),
+    })
+}
+
+#[tauri::command]
+pub async fn import_from_file(
+    path: String,
+    _app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    if !Path::new(&path).exists() {
+        return Err(format!("File does not exist: {}", path));
+    }
+
+    // With the move to PostgreSQL, file import is now a stub that returns a message
+    // Inform the user about the database migration
+    Ok(Response {
+        success: false,
+        message: "Database import from SQLite files is no longer supported as the application now uses Neon PostgreSQL. Use the db:init:neon script to set up your database.".to_string(),
+        data: None,
+    })
+}
+
+#[tauri::command]
+pub async fn retry_item(id: String, app_state: State<'_, AppState>) -> Result<Response<()>, String> {
+    let item_result = app_state.db.get_item_by_id(&id).await;
+    match item_result {
+        Ok(Some(item)) => {
+            // auth_required and blocked_* items failed to download, so they go back in
+            // the queue below
+            if matches!(
+                item.status.as_str(),
+                "failed" | "auth_required" | "blocked_missing_ffmpeg" | "blocked_disk_space"
+            ) {
+                // Check if this was an upload failure or a download failure. Items that
+                // failed before messages had codes only have the text to go by.
+                let is_upload_failure = match (&item.message_code, &item.message) {
+                    (Some(code), _) if code != messages::TEXT => code.starts_with("upload."),
+                    (_, Some(msg)) => {
+                        msg.contains("API key")
+                            || msg.contains("Upload")
+                            || msg.contains("upload")
+                            || msg.contains("Filemoon")
+                            || msg.contains("Files.vc")
+                    }
+                    _ => false,
+                };
+
+                // Check if the item has a local path (indicating it was downloaded successfully)
+                let has_local_path =
+                    item.local_path.is_some() && !item.local_path.as_ref().unwrap().is_empty();
+
+                // A manual retry gets the full number of automatic retries again
+                if let Err(e) = app_state.db.reset_retries(&id).await {
+                    eprintln!("Error resetting retries for item {}: {}", id, e);
+                }
+
+                if is_upload_failure || has_local_path {
+                    println!("Retrying upload for item {}", id);
+                    // This was an upload failure, so trigger upload directly
+                    if let Err(e) = app_state
+                        .db
+                        .update_item_status(
+                            &id,
+                            "downloaded",
+                            Some(ItemMessage::new(messages::UPLOAD_RETRY_PREPARING)),
+                        )
+                        .await
+                    {
+                        return Err(format!("Database error updating status: {}", e));
+                    }
+
+                    // Return success and let the trigger_upload command be called separately
+                    return Ok(Response {
+                        success: true,
+                        message: "Item prepared for upload retry.".to_string(),
+                        data: None,
+                    });
+                } else if item.filemoon_url.is_none() {
+                    // This was a download failure, so requeue for download
+                    if let Err(e) = app_state.db.set_format_rung(&id, 0).await {
+                        eprintln!("Error resetting format rung for item {}: {}", id, e);
+                    }
+                    if let Err(e) = app_state.db.set_source_index(&id, 0).await {
+                        eprintln!("Error resetting source for item {}: {}", id, e);
+                    }
+                    match app_state
+                        .db
+                        .update_item_status(
+                            &id,
+                            "queued",
+                            Some(ItemMessage::new(messages::DOWNLOAD_RETRYING)),
+                        )
+                        .await
+                    {
+                        Ok(_) => {
+                            app_state.queue_wakeup.wake();
+                            Ok(Response {
+                                success: true,
+                                message: "Item re-queued for download.".to_string(),
+                                data: None,
+                            })
+                        }
+                        Err(e) => Err(format!("Database error updating status: {}", e)),
+                    }
+                } else {
+                    Err(format!(
+                        "Item is not in a retryable failed state (status: {}, has_upload_url: {}).",
+                        item.status,
+                        item.filemoon_url.is_some()
+                    ))
+                }
+            } else if item.status == "unsupported" {
+                Err(format!(
+                    "Item {} can't be downloaded and won't be retried: {}",
+                    id,
+                    item.message.unwrap_or_default()
+                ))
+            } else {
+                Err(format!(
+                    "Item is not in a failed state (status: {}).",
+                    item.status
+                ))
+            }
+        }
+        Ok(None) => Err(format!("Retry failed: Item {} not found.", id)),
+        Err(e) => Err(format!("Database error retrieving item: {}", e)),
+    }
+}
+
+// Statuses an upload can be retried from with the local copy that is already on disk.
+// "completed" is what older frontends showed for a finished download.
+const RETRY_UPLOAD_STATUSES: &[&str] = &["failed", "completed", "downloaded"];
+
+// Upload the already-downloaded file again without going back through yt-dlp. Unlike
+// retry_item this does not guess from the failure message whether the download or the
+// upload failed; it only checks that the local file is still there.
+#[tauri::command]
+pub async fn retry_upload(
+    id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    if app_state.instance.role() == InstanceRole::Viewer {
+        return Err(
+            "This is a viewer-only instance; uploads run in the main instance.".to_string(),
+        );
+    }
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Retry failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    if !RETRY_UPLOAD_STATUSES.contains(&item.status.as_str()) {
+        return Err(format!(
+            "Item {} can't be re-uploaded from status '{}'.",
+            id, item.status
+        ));
+    }
+
+    let local_path = match item.local_path.as_deref().filter(|path| !path.is_empty()) {
+        Some(path) => path,
+        None => {
+            return Err(format!(
+                "Item {} has no downloaded file; use retry to download it again.",
+                id
+            ))
+        }
+    };
+    match fs::metadata(long_paths::extended(Path::new(local_path))) {
+        Ok(meta) if meta.is_file() && meta.len() > 0 => {}
+        Ok(_) => return Err(format!("Local file is empty or not a file: {}", local_path)),
+        Err(e) => return Err(format!("Local file not usable at {}: {}", local_path, e)),
+    }
+
+    if let Err(e) = app_state.db.reset_retries(&id).await {
+        eprintln!("Error resetting retries for item {}: {}", id, e);
+    }
+    app_state
+        .db
+        .update_item_status(
+            &id,
+            "downloaded",
+            Some(ItemMessage::new(messages::UPLOAD_RETRY_PREPARING)),
+        )
+        .await
+        .map_err(|e| format!("Database error updating status: {}", e))?;
+    app_state.uploads.enqueue(id.clone(), user_id)?;
+    println!("Re-uploading item {} from {}", id, local_path);
+
+    Ok(Response {
+        success: true,
+        message: "Upload queued from the downloaded file".to_string(),
+        data: None,
+    })
+}
+
+pub fn review_required(settings: &AppSettings) -> bool {
+    settings.review_before_upload.as_deref() == Some("true")
+}
+
+// Release a reviewed download for upload. Queues the upload straight away when the
+// auto-upload rules allow it; otherwise the item waits as "downloaded" like any other.
+#[tauri::command]
+pub async fn approve_upload(
+    id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    if item.status != "pending_review" {
+        return Err(format!(
+            "Item {} is not waiting for review (status: {}).",
+            id, item.status
+        ));
+    }
+
+    app_state
+        .db
+        .update_item_status(
+            &id,
+            "downloaded",
+            Some(ItemMessage::new(messages::UPLOAD_APPROVED)),
+        )
+        .await
+        .map_err(|e| format!("Database error updating status: {}", e))?;
+
+    let message = if auto_upload::apply(&app_state, &id, &user_id).await {
+        "Approved; upload queued".to_string()
+    } else {
+        "Approved; ready to upload".to_string()
+    };
+
+    Ok(Response {
+        success: true,
+        message,
+        data: None,
+    })
+}
+
+// Approve several reviewed downloads at once. Returns the ids that were approved;
+// the rest are logged and skipped.
+#[tauri::command]
+pub async fn approve_uploads(
+    ids: Vec<String>,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<String>>, String> {
+    let mut approved = Vec::new();
+    for id in ids {
+        match approve_upload(id.clone(), user_id.clone(), app_state.clone()).await {
+            Ok(_) => approved.push(id),
+            Err(e) => eprintln!("Bulk approve skipped item {}: {}", id, e),
+        }
+    }
+
+    Ok(Response {
+        success: true,
+        message: format!("Approved {} item(s) for upload", approved.len()),
+        data: Some(approved),
+    })
+}
+
+// Retry every failed item for a user. Unsupported items are a separate, terminal
+// status and are never picked up here.
+#[tauri::command]
+pub async fn retry_all_failed(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<usize>, String> {
+    let failed = app_state
+        .db
+        .get_items_in_statuses(&["failed"])
+        .await
+        .map_err(|e| format!("Database error retrieving failed items: {}", e))?;
+
+    let mut retried = 0;
+    for item in failed
+        .into_iter()
+        .filter(|item| item.user_id.as_deref() == Some(user_id.as_str()))
+    {
+        let id = item.id.unwrap_or_default();
+        match retry_item(id.clone(), app_state.clone()).await {
+            Ok(_) => retried += 1,
+            Err(e) => eprintln!("Retry-all skipped item {}: {}", id, e),
+        }
+    }
+
+    Ok(Response {
+        success: true,
+        message: format!("Retried {} failed item(s)", retried),
+        data: Some(retried),
+    })
+}
+
+#[tauri::command]
+pub async fn cancel_item(id: String, app_state: State<'_, AppState>) -> Result<Response<()>, String> {
+    // First, get the item to check its status
+    let current_status = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item.status.clone(),
+        Ok(None) => return Err(format!("Cancel failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error checking item existence: {}", e)),
+    };
+
+    // Kill this item's own yt-dlp process tree right away, even if its download task is
+    // busy; the token then lets that task wind down. Other items' downloads are untouched.
+    if let Some(pid) = app_state.cancellations.process_id(&id) {
+        println!("Killing process {} of item {}", pid, id);
+        cancellation::kill_pid_tree(pid).await;
+    }
+
+    // Abort the in-flight stage for this item through its cancellation token
+    if app_state.cancellations.cancel(&id) {
+        println!("Cancellation token triggered for item {}", id);
+    } else if current_status == "downloading" {
+        // Nothing runs for it in this session (e.g. left over from an older one)
+        println!("No running download found for item {}", id);
+    }
+
+    // Now update the database status
+    match app_state
+        .db
+        .update_item_status(&id, "cancelled", Some(ItemMessage::new(messages::CANCELLED)))
+        .await
+    {
+        Ok(_) => {
+            println!("Item {} marked as cancelled in database", id);
+            Ok(Response {
+                success: true,
+                message: "Item cancelled successfully".to_string(),
+                data: None,
+            })
+        }
+        Err(e) => Err(format!(
+            "Database error updating status to cancelled: {}",
+            e
+        )),
+    }
+}
+
+// Pre-flight check for the queued items: their probed sizes, the upload volume and
+// quota impact per provider and the time it would take at this session's speeds
+#[tauri::command]
+pub async fn plan_queue(
+    user_id: String,
+    concurrency: Option<usize>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<QueuePlan>, String> {
+    let plan = planner::plan(&app_state, &user_id, concurrency).await?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Planned {} queued item(s), {} with a known size",
+            plan.queued_items, plan.sized_items
+        ),
+        data: Some(plan),
+    })
+}
+
+// Limit an item's download to time ranges like "10:00-15:30, 1:02:00-inf".
+// An empty string clears the ranges so the whole video is downloaded.
+#[tauri::command]
+pub async fn set_download_sections(
+    id: String,
+    sections: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let stored = sections::normalize(&sections)?;
+
+    match app_state
+        .db
+        .set_download_sections(&id, stored.as_deref())
+        .await
+    {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: match stored {
+                Some(s) => format!("Download limited to sections {}", s),
+                None => "Download sections cleared".to_string(),
+            },
+            data: None,
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving download sections: {}", e)),
+    }
+}
+
+// The formats a video offers, for picking one before it is queued or downloaded
+#[tauri::command]
+pub async fn get_available_formats(url: String) -> Result<Response<FormatList>, String> {
+    let list = formats::available(&urls::normalize_url(&url)).await?;
+    Ok(Response {
+        success: true,
+        message: format!("{} format(s) available", list.formats.len()),
+        data: Some(list),
+    })
+}
+
+// Download an item in a specific yt-dlp format (e.g. "bestvideo[height<=1080]+bestaudio/best"
+// or a selector from get_available_formats). An empty string goes back to the
+// preferred_format setting.
+#[tauri::command]
+pub async fn set_item_format(
+    id: String,
+    format: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let format = Some(format.trim()).filter(|f| !f.is_empty());
+
+    match app_state.db.set_format_override(&id, format).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: match format {
+                Some(f) => format!("Item will be downloaded as {}", f),
+                None => "Item format reset to the default".to_string(),
+            },
+            data: None,
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving item format: {}", e)),
+    }
+}
+
+// Cancel a running job. Item jobs go through cancel_item so the item is marked
+// cancelled too; jobs without an item are just signalled to stop.
+#[tauri::command]
+pub async fn cancel_job(
+    job_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let job = match app_state.jobs.list().into_iter().find(|job| job.id == job_id) {
+        Some(job) => job,
+        None => return Err(format!("Job {} is not running.", job_id)),
+    };
+
+    match job.item_id {
+        Some(item_id) => cancel_item(item_id, app_state.clone()).await,
+        None => {
+            app_state.jobs.cancel(&job_id);
+            Ok(Response {
+                success: true,
+                message: format!("Cancellation requested for {} job", job.kind),
+                data: None,
+            })
+        }
+    }
+}
+
+// Freeze an item so the background worker leaves it alone during manual edits
+#[tauri::command]
+pub async fn lock_item(id: String, app_state: State<'_, AppState>) -> Result<Response<()>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Lock failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error checking item existence: {}", e)),
+    };
+
+    // The worker already owns these; locking now would leave the status half-updated
+    if ["downloading", "transcoding", "uploading"].contains(&item.status.as_str()) {
+        return Err(format!(
+            "Item {} is currently {}. Cancel it before locking.",
+            id, item.status
+        ));
+    }
+
+    match app_state.db.set_item_locked(&id, true).await {
+        Ok(_) => Ok(Response {
+            success: true,
+            message: "Item locked".to_string(),
+            data: None,
+        }),
+        Err(e) => Err(format!("Database error locking item: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn unlock_item(id: String, app_state: State<'_, AppState>) -> Result<Response<()>, String> {
+    match app_state.db.set_item_locked(&id, false).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: "Item unlocked".to_string(),
+            data: None,
+        }),
+        Ok(false) => Err(format!("Unlock failed: Item {} not found.", id)),
+        Err(e) => Err(format!("Database error unlocking item: {}", e)),
+    }
+}
+
+// Put a cancelled item back to work. Partially downloaded files are left in place so
+// yt-dlp's --continue picks up where it stopped; items cancelled mid-upload go
+// back to downloaded when their file is still there.
+#[tauri::command]
+pub async fn resume_cancelled(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Resume failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error checking item existence: {}", e)),
+    };
+
+    if item.status != "cancelled" {
+        return Err(format!(
+            "Item {} is not cancelled (status: {}). Use retry_item for failed items.",
+            id, item.status
+        ));
+    }
+
+    let file_downloaded = item
+        .local_path
+        .as_deref()
+        .map_or(false, |p| !p.is_empty() && Path::new(p).exists());
+    let (status, message) = if file_downloaded {
+        ("downloaded", messages::UPLOAD_RESUMED)
+    } else {
+        ("queued", messages::DOWNLOAD_RESUMING)
+    };
+
+    match app_state
+        .db
+        .update_item_status(&id, status, Some(ItemMessage::new(message)))
+        .await
+    {
+        Ok(_) => {
+            app_state.queue_wakeup.wake();
+            Ok(Response {
+                success: true,
+                message: format!("Item resumed as {}", status),
+                data: None,
+            })
+        }
+        Err(e) => Err(format!("Database error updating status: {}", e)),
+    }
+}
+
+// Push the item's thumbnail to Filemoon once encoding has finished so the remote
+// player shows the right preview. The outcome is stored on the item.
+pub(crate) async fn sync_thumbnail(app_state: &AppState, item_id: &str, api_key: &str) {
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return,
+        Err(e) => {
+            eprintln!(
+                "Failed to load item {} for thumbnail upload: {}",
+                item_id, e
+            );
+            return;
+        }
+    };
+    if item.thumbnail_uploaded == Some(true) {
+        return;
+    }
+    let (filecode, source) = match (item.filemoon_url, item.thumbnail_url) {
+        (Some(filecode), Some(source)) if !filecode.is_empty() && !source.is_empty() => {
+            (filecode, source)
+        }
+        _ => return,
+    };
+
+    let result = match filemoon::load_thumbnail(&source).await {
+        Ok(image) => filemoon::upload_thumbnail(&filecode, api_key, image).await,
+        Err(e) => Err(e),
+    };
+    let uploaded = match result {
+        Ok(()) => {
+            println!("Uploaded custom thumbnail for item {}", item_id);
+            true
+        }
+        Err(e) => {
+            eprintln!("Thumbnail upload failed for item {}: {}", item_id, e);
+            false
+        }
+    };
+
+    if let Err(e) = app_state.db.set_thumbnail_uploaded(item_id, uploaded).await {
+        eprintln!(
+            "Failed to record thumbnail upload for item {}: {}",
+            item_id, e
+        );
+    }
+}
+
+// Give an item a custom thumbnail: `source` is either an image file to use as-is or a
+// position in the downloaded video ("95", "1:35") to grab a frame from with ffmpeg.
+// The image goes into the thumbnail cache and, for items already on Filemoon, is
+// pushed to Filemoon's custom thumbnail API.
+#[tauri::command]
+pub async fn set_item_thumbnail(
+    id: String,
+    source: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let user_id = item.user_id.clone().unwrap_or_else(|| "local-user".to_string());
+    let settings = app_state.db.get_settings(&user_id).await.unwrap_or_default();
+
+    let cache = thumbnails::cache_dir(
+        &app_handle
+            .path_resolver()
+            .app_data_dir()
+            .ok_or_else(|| "Could not resolve app data directory".to_string())?,
+    );
+
+    let source_path = Path::new(source.trim());
+    let stored = if source_path.is_file() {
+        thumbnails::store_image(source_path, &cache, &id).await?
+    } else if let Some(position) = thumbnails::parse_position(&source) {
+        let video = item
+            .local_path
+            .as_deref()
+            .filter(|p| !p.is_empty() && Path::new(p).exists())
+            .ok_or_else(|| {
+                format!(
+                    "Item {} has no downloaded file to take a frame from.",
+                    id
+                )
+            })?;
+        let ffmpeg = tools::detect_ffmpeg(settings.ffmpeg_path.as_deref()).await;
+        let ffmpeg_path = match (ffmpeg.found, ffmpeg.path) {
+            (true, Some(path)) => path,
+            _ => {
+                return Err(ffmpeg
+                    .message
+                    .unwrap_or_else(|| "ffmpeg not found".to_string()))
+            }
+        };
+        thumbnails::extract_frame(
+            &ffmpeg_path,
+            Path::new(video),
+            position,
+            &cache,
+            &id,
+            performance::profile(&settings).priority,
+        )
+        .await?
+    } else {
+        return Err(format!(
+            "'{}' is neither an image file nor a position in the video.",
+            source
+        ));
+    };
+
+    let stored = stored.to_string_lossy().to_string();
+    match app_state.db.set_custom_thumbnail(&id, &stored).await {
+        Ok(true) => {}
+        Ok(false) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error saving thumbnail: {}", e)),
+    }
+
+    // Only Filemoon has a custom thumbnail API
+    let on_filemoon = item.filemoon_url.as_deref().map_or(false, |f| !f.is_empty());
+    let message = match settings.filemoon_api_key.as_deref() {
+        Some(api_key) if on_filemoon && !api_key.is_empty() => {
+            sync_thumbnail(&app_state, &id, api_key).await;
+            let pushed = matches!(
+                app_state.db.get_item_by_id(&id).await,
+                Ok(Some(updated)) if updated.thumbnail_uploaded == Some(true)
+            );
+            if pushed {
+                "Thumbnail updated and sent to Filemoon".to_string()
+            } else {
+                "Thumbnail updated locally; sending it to Filemoon failed (see logs)".to_string()
+            }
+        }
+        _ => "Thumbnail updated".to_string(),
+    };
+
+    Ok(Response {
+        success: true,
+        message,
+        data: Some(stored),
+    })
+}
+
+#[tauri::command]
+pub async fn get_queue_templates(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<QueueTemplate>>, String> {
+    match app_state.db.get_queue_templates(&user_id).await {
+        Ok(templates) => Ok(Response {
+            success: true,
+            message: format!("Retrieved {} queue template(s)", templates.len()),
+            data: Some(templates),
+        }),
+        Err(e) => Err(format!("Failed to retrieve queue templates: {}", e)),
+    }
+}
+
+// Create or update a reusable job such as "weekly council meeting stream"
+#[tauri::command]
+pub async fn save_queue_template(
+    template: QueueTemplate,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let mut template = template;
+    templates::validate(&mut template, &app_state.providers)?;
+
+    match app_state.db.save_queue_template(&template, &user_id).await {
+        Ok(id) => Ok(Response {
+            success: true,
+            message: format!("Queue template '{}' saved", template.name),
+            data: Some(id),
+        }),
+        Err(e) => Err(format!("Failed to save queue template: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_queue_template(
+    id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    match app_state.db.delete_queue_template(&id, &user_id).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: "Queue template deleted".to_string(),
+            data: None,
+        }),
+        Ok(false) => Err(format!("Queue template {} not found.", id)),
+        Err(e) => Err(format!("Failed to delete queue template: {}", e)),
+    }
+}
+
+// Queue one item from a template; `url` replaces the template's URL pattern if given
+#[tauri::command]
+pub async fn instantiate_template(
+    id: String,
+    user_id: String,
+    url: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    let template = match app_state.db.get_queue_template(&id, &user_id).await {
+        Ok(Some(template)) => template,
+        Ok(None) => return Err(format!("Queue template {} not found.", id)),
+        Err(e) => return Err(format!("Failed to retrieve queue template: {}", e)),
+    };
+
+    let item_id = templates::instantiate(&app_state, &template, url).await?;
+    if let Err(e) = app_state.db.mark_template_run(&id, timestamps::now()).await {
+        eprintln!("Error marking run of template {}: {}", id, e);
+    }
+    Ok(Response {
+        success: true,
+        message: format!("Queued item from template '{}'", template.name),
+        data: Some(item_id),
+    })
+}
+
+// Re-poll Filemoon for every transferring/encoding/encoded item and report what changed
+#[tauri::command]
+pub async fn refresh_all_statuses(
+    app_state: State<'_, AppState>,
+) -> Result<Response<RefreshSummary>, String> {
+    let summary = status_refresh::refresh_all(&app_state).await?;
+    Ok(Response {
+        success: summary.errors.is_empty(),
+        message: format!(
+            "Checked {} items: {} changed, {} unchanged, {} skipped, {} errors",
+            summary.checked,
+            summary.changed,
+            summary.unchanged,
+            summary.skipped,
+            summary.errors.len()
+        ),
+        data: Some(summary),
+    })
+}
+
+// Recent yt-dlp output for an item; live lines follow as `item_output` events
+#[tauri::command]
+pub async fn tail_item_output(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<OutputLine>>, String> {
+    let lines = app_state.output_tail.lines(&id);
+    Ok(Response {
+        success: true,
+        message: format!("{} buffered output line(s)", lines.len()),
+        data: Some(lines),
+    })
+}
+
+#[tauri::command]
+pub async fn trigger_upload(
+    id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    if app_state.instance.role() == InstanceRole::Viewer {
+        return Err(
+            "This is a viewer-only instance; uploads run in the main instance.".to_string(),
+        );
+    }
+    if !app_state.worker.is_enabled() {
+        return Err("The background worker is disabled for this instance.".to_string());
+    }
+
+    // Manual uploads share the worker queue with auto-uploads
+    app_state.uploads.submit(id, user_id).await
+}
+
+// Manual scheduling priority for an item (higher runs sooner, -100..=100). Waiting
+// time raises and failed attempts lower the priority the scheduler actually uses.
+#[tauri::command]
+pub async fn set_item_priority(
+    id: String,
+    priority: i32,
+    app_state: State<'_, AppState>,
+) -> Result<Response<i32>, String> {
+    let priority = scheduler::clamp_priority(priority);
+
+    match app_state.db.set_priority(&id, priority).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: format!("Priority set to {}", priority),
+            data: Some(priority),
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving priority: {}", e)),
+    }
+}
+
+// Automatic retries allowed for one item (0..=20, 0 = none); None goes back to the
+// max_auto_retries setting. See retry_policy.rs.
+#[tauri::command]
+pub async fn set_item_max_retries(
+    id: String,
+    max_retries: Option<i32>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Option<i32>>, String> {
+    let max_retries = max_retries.map(retry_policy::validate_max).transpose()?;
+
+    match app_state.db.set_max_retries(&id, max_retries).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: match max_retries {
+                Some(max) => format!("Automatic retries set to {}", max),
+                None => "Automatic retries follow the settings again".to_string(),
+            },
+            data: Some(max_retries),
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving retry limit: {}", e)),
+    }
+}
+
+// Have Filemoon fetch the item from its source URL ("remote") instead of downloading
+// and uploading it here ("local" or None). See remote_transfer.rs.
+#[tauri::command]
+pub async fn set_item_transfer_mode(
+    id: String,
+    mode: Option<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Option<String>>, String> {
+    let mode = remote_transfer::validate_mode(mode.as_deref())?;
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    if matches!(
+        item.status.as_str(),
+        "downloading" | "uploading" | "transferring"
+    ) {
+        return Err(format!(
+            "Item {} is {}; change its transfer mode once that is over.",
+            id, item.status
+        ));
+    }
+
+    match app_state.db.set_transfer_mode(&id, mode).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: match mode {
+                Some(_) => "Filemoon will fetch this item from its source".to_string(),
+                None => "This item will be downloaded and uploaded from here".to_string(),
+            },
+            data: Some(mode.map(str::to_string)),
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving transfer mode: {}", e)),
+    }
+}
+
+// Start a queued item next, even outside its owner's download window. It waits
+// for a download that is already running to finish.
+#[tauri::command]
+pub async fn force_start_item(
+    id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error fetching item: {}", e)),
+    };
+    if item.status != "queued" {
+        return Err(format!(
+            "Item {} is not queued (status: {}).",
+            id, item.status
+        ));
+    }
+    if item.locked == Some(true) {
+        return Err(format!("Item {} is locked. Unlock it first.", id));
+    }
+
+    app_state.forced_starts.add(&id);
+    app_state.queue_wakeup.wake();
+    let window = match app_state
+        .db
+        .get_settings(item.user_id.as_deref().unwrap_or("local-user"))
+        .await
+    {
+        Ok(settings) => DownloadWindow::from_settings(&settings).ok().flatten(),
+        Err(_) => None,
+    };
+    Ok(Response {
+        success: true,
+        message: match window {
+            Some(window) if !window.is_open(chrono::Local::now()) => format!(
+                "Item starts next, outside the {} download window",
+                window.describe()
+            ),
+            _ => "Item starts next".to_string(),
+        },
+        data: None,
+    })
+}
+
+// Put queued items at the front of the queue in the given order (an empty list goes
+// back to the priority order). Returns the ids of all queued items in the order
+// they will be processed.
+#[tauri::command]
+pub async fn reorder_queue(
+    user_id: String,
+    ids: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<String>>, String> {
+    let placed = app_state
+        .db
+        .reorder_queue(&user_id, &ids)
+        .await
+        .map_err(|e| format!("Database error reordering queue: {}", e))?;
+    let order = app_state
+        .db
+        .get_queue_order(&user_id)
+        .await
+        .map_err(|e| format!("Database error reading queue order: {}", e))?;
+
+    Ok(Response {
+        success: true,
+        message: match ids.len() {
+            0 => "Manual queue order cleared".to_string(),
+            n => format!("{} of {} item(s) moved to the front", placed, n),
+        },
+        data: Some(order),
+    })
+}
+
+// Alternate source URLs are tried in order when the primary URL is deleted or
+// blocked (e.g. geo-restricted). Duplicates and the primary URL itself are dropped.
+// An empty list clears the mirrors.
+#[tauri::command]
+pub async fn set_mirror_urls(
+    id: String,
+    urls: Vec<String>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<String>>, String> {
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error fetching item: {}", e)),
+    };
+
+    let mut mirrors: Vec<String> = Vec::new();
+    for raw in urls.iter().map(|url| url.trim()).filter(|url| !url.is_empty()) {
+        if !(raw.starts_with("http://") || raw.starts_with("https://")) {
+            return Err(format!("'{}' is not an http(s) URL", raw));
+        }
+        let url = urls::normalize_url(raw);
+        if url != item.url && !mirrors.contains(&url) {
+            mirrors.push(url);
+        }
+    }
+
+    match app_state.db.set_mirror_urls(&id, &mirrors).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: match mirrors.len() {
+                0 => "Mirror sources cleared".to_string(),
+                n => format!("{} mirror source(s) saved", n),
+            },
+            data: Some(mirrors),
+        }),
+        Ok(false) => Err(format!("Item {} not found.", id)),
+        Err(e) => Err(format!("Database error saving mirror sources: {}", e)),
+    }
+}
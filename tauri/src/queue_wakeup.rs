@@ -0,0 +1,39 @@
+// Wakes the queue processor as soon as there may be work for it: an item queued or
+// retried, a forced start, changed settings, an upload finishing (downloads wait
+// while one runs) or the worker switched back on. Between wakeups the processor
+// polls only every FALLBACK_POLL, which still catches changes made elsewhere
+// (another instance, the download window opening, a stall backoff running out).
+
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+pub const FALLBACK_POLL: Duration = Duration::from_secs(60);
+// Pause between two items, so an item that keeps failing to start can't spin the
+// loop against the database
+pub const BETWEEN_ITEMS: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct QueueWakeup {
+    notify: Notify,
+}
+
+impl QueueWakeup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A wakeup sent while the processor is busy is kept, so it checks the queue
+    // again straight after the current item
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    // Wait for a wakeup, or at most `timeout`
+    pub async fn wait(&self, timeout: Duration) {
+        tokio::select! {
+            _ = self.notify.notified() => {}
+            _ = sleep(timeout) => {}
+        }
+    }
+}
@@ -0,0 +1,259 @@
+// Monthly upload accounting per provider, checked against the quota settings
+// before each upload so a budget is not exceeded silently. An upload that would go
+// over is held as "on_hold"; a background sweep re-queues held uploads once they
+// fit again, which is after the month turns over (usage counts per UTC calendar
+// month) or once the quota is raised or removed.
+
+use crate::db::{AppSettings, QueueItem};
+use crate::messages::{self, ItemMessage};
+use crate::{mirrors, safe_delete, settings_watch, AppState};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+pub const PROVIDERS: &[&str] = &["filemoon", "b2"];
+
+pub const HELD_STATUS: &str = "on_hold";
+// How often held uploads are checked; a settings change checks them at once
+const RESUME_INTERVAL: Duration = Duration::from_secs(3600);
+
+// Share of a quota at which a warning is emitted
+const QUOTA_WARNING_RATIO: f64 = 0.9;
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProviderUsage {
+    pub provider: String,
+    pub bytes_used: i64,
+    pub uploads: i64,
+    pub quota_bytes: Option<i64>,
+    pub percent_used: Option<f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaReport {
+    pub month: String,
+    pub providers: Vec<ProviderUsage>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct QuotaWarning {
+    pub item_id: String,
+    pub provider: String,
+    pub bytes_used: i64,
+    pub upload_bytes: i64,
+    pub quota_bytes: i64,
+    pub message: String,
+}
+
+pub enum QuotaCheck {
+    Ok,
+    // Upload fits but pushes usage past the warning threshold
+    Warn(QuotaWarning),
+    // Upload would exceed the quota; the item is held
+    Exceeded(QuotaWarning),
+}
+
+fn quota_setting<'a>(settings: &'a AppSettings, provider: &str) -> Option<&'a str> {
+    match provider {
+        "filemoon" => settings.filemoon_monthly_quota_gb.as_deref(),
+        "b2" => settings.b2_monthly_quota_gb.as_deref(),
+        _ => None,
+    }
+}
+
+// Configured monthly quota in bytes; unset, unparsable or non-positive means unlimited
+pub fn quota_bytes(settings: &AppSettings, provider: &str) -> Option<i64> {
+    quota_setting(settings, provider)
+        .and_then(|gb| gb.trim().parse::<f64>().ok())
+        .filter(|gb| *gb > 0.0)
+        .map(|gb| (gb * BYTES_PER_GB) as i64)
+}
+
+pub async fn report(app_state: &AppState, user_id: &str) -> Result<QuotaReport, String> {
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve settings: {}", e))?;
+    let usage = app_state
+        .db
+        .get_monthly_usage(user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve upload usage: {}", e))?;
+
+    let mut providers: Vec<String> = PROVIDERS.iter().map(|p| p.to_string()).collect();
+    for (provider, _, _) in &usage {
+        if !providers.contains(provider) {
+            providers.push(provider.clone());
+        }
+    }
+
+    let providers = providers
+        .into_iter()
+        .map(|provider| {
+            let (bytes_used, uploads) = usage
+                .iter()
+                .find(|(p, _, _)| *p == provider)
+                .map(|(_, bytes, count)| (*bytes, *count))
+                .unwrap_or((0, 0));
+            let quota_bytes = quota_bytes(&settings, &provider);
+            ProviderUsage {
+                percent_used: quota_bytes.map(|q| bytes_used as f64 * 100.0 / q as f64),
+                provider,
+                bytes_used,
+                uploads,
+                quota_bytes,
+            }
+        })
+        .collect();
+
+    Ok(QuotaReport {
+        month: Utc::now().format("%Y-%m").to_string(),
+        providers,
+    })
+}
+
+// Check whether uploading `upload_bytes` more to `provider` stays within this month's quota
+pub async fn check_upload(
+    app_state: &AppState,
+    settings: &AppSettings,
+    user_id: &str,
+    item_id: &str,
+    provider: &str,
+    upload_bytes: i64,
+) -> Result<QuotaCheck, String> {
+    let quota_bytes = match quota_bytes(settings, provider) {
+        Some(quota) => quota,
+        None => return Ok(QuotaCheck::Ok),
+    };
+
+    let bytes_used = app_state
+        .db
+        .get_monthly_usage(user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve upload usage: {}", e))?
+        .into_iter()
+        .find(|(p, _, _)| p == provider)
+        .map(|(_, bytes, _)| bytes)
+        .unwrap_or(0);
+
+    let after = bytes_used + upload_bytes;
+    let warning = |message: String| QuotaWarning {
+        item_id: item_id.to_string(),
+        provider: provider.to_string(),
+        bytes_used,
+        upload_bytes,
+        quota_bytes,
+        message,
+    };
+
+    if after > quota_bytes {
+        let mut exceeded = warning(String::new());
+        exceeded.message = exceeded_message(&exceeded).text();
+        Ok(QuotaCheck::Exceeded(exceeded))
+    } else if after as f64 >= quota_bytes as f64 * QUOTA_WARNING_RATIO {
+        Ok(QuotaCheck::Warn(warning(format!(
+            "{} monthly quota is {:.0}% used after this upload",
+            provider,
+            after as f64 * 100.0 / quota_bytes as f64
+        ))))
+    } else {
+        Ok(QuotaCheck::Ok)
+    }
+}
+
+// Status message for an item held because its upload would exceed the quota
+pub fn exceeded_message(warning: &QuotaWarning) -> ItemMessage {
+    let gb = |bytes: i64| (bytes as f64 / BYTES_PER_GB * 100.0).round() / 100.0;
+    ItemMessage::new(messages::UPLOAD_QUOTA_EXCEEDED)
+        .with("upload_gb", gb(warning.upload_bytes))
+        .with("provider", warning.provider.clone())
+        .with("used_gb", gb(warning.bytes_used))
+        .with("quota_gb", gb(warning.quota_bytes))
+}
+
+// The provider whose quota held the item, as recorded in its status message, or
+// its main upload target
+fn held_provider(item: &QueueItem, settings: &AppSettings) -> String {
+    item.message_params
+        .as_ref()
+        .and_then(|params| params.get("provider"))
+        .and_then(|provider| provider.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| mirrors::main_target(settings))
+}
+
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting quota hold sweep...");
+
+    let mut changes = app_handle.state::<AppState>().settings_watch.subscribe();
+    loop {
+        settings_watch::wait(&mut changes, RESUME_INTERVAL).await;
+        if let Err(e) = resume_held(&app_handle).await {
+            eprintln!("Quota hold sweep failed: {}", e);
+        }
+    }
+}
+
+async fn resume_held(app_handle: &AppHandle) -> Result<(), String> {
+    let app_state = app_handle.state::<AppState>();
+    let held = app_state
+        .db
+        .get_items_in_statuses(&[HELD_STATUS])
+        .await
+        .map_err(|e| format!("Failed to load held items: {}", e))?;
+
+    for item in held {
+        let (item_id, user_id) = match (&item.id, &item.user_id) {
+            (Some(id), Some(user_id)) => (id.clone(), user_id.clone()),
+            _ => continue,
+        };
+        let settings = match app_state.db.get_settings(&user_id).await {
+            Ok(settings) => mirrors::item_settings(&settings, &item),
+            Err(e) => {
+                eprintln!("Failed to load settings for held item {}: {}", item_id, e);
+                continue;
+            }
+        };
+        let provider = held_provider(&item, &settings);
+        let upload_bytes = safe_delete::local_size(&item).unwrap_or(0) as i64;
+
+        // The upload checks the quota again, so items resumed together that don't
+        // all fit are simply held again
+        match check_upload(
+            &app_state,
+            &settings,
+            &user_id,
+            &item_id,
+            &provider,
+            upload_bytes,
+        )
+        .await
+        {
+            Ok(QuotaCheck::Exceeded(_)) => continue,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("Quota check for held item {} failed: {}", item_id, e);
+                continue;
+            }
+        }
+
+        let message = ItemMessage::new(messages::UPLOAD_QUOTA_RESUMED).with("provider", provider);
+        if let Err(e) = app_state
+            .db
+            .update_item_status(&item_id, "downloaded", Some(message))
+            .await
+        {
+            eprintln!("Error releasing held item {}: {}", item_id, e);
+            continue;
+        }
+        match app_state.uploads.enqueue(item_id.clone(), user_id) {
+            Ok(()) => println!("Quota has room again; resumed upload of item {}", item_id),
+            Err(e) => eprintln!("Error re-queueing upload for item {}: {}", item_id, e),
+        }
+    }
+    Ok(())
+}
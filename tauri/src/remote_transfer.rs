@@ -0,0 +1,307 @@
+// Filemoon remote uploads. An item whose transfer_mode is REMOTE is never downloaded
+// here: when it comes up in the queue, Filemoon is handed its source URL
+// (remote/add) and fetches the video itself. The item then waits in "transferring"
+// while a sweep polls remote/status every POLL_INTERVAL, and ends up "uploaded" with
+// the file code, as after a normal upload. The file code is kept in remote_filecode,
+// so polling picks up again after a restart.
+//
+// No local copy is made, so transcoding, the media library, mirror uploads and hooks
+// that need the file don't apply to these items. Cancelling stops the polling but
+// can't stop Filemoon from finishing a transfer it has started.
+
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::db::{AppSettings, QueueItem};
+use crate::filemoon::{self, RemoteState, RemoteStatus};
+use crate::messages::{self, ItemMessage};
+use crate::progress::Progress;
+use crate::providers::StepError;
+use crate::processor::active_source_url;
+use crate::upload::{create_short_url, record_provider_error};
+use crate::{http, mirrors, settings_watch, AppState};
+
+pub const REMOTE: &str = "remote";
+// The default; "local" is accepted for it too
+pub const LOCAL: &str = "local";
+
+// How often running remote uploads are checked
+const POLL_INTERVAL: Duration = Duration::from_secs(20);
+
+pub fn is_remote(item: &QueueItem) -> bool {
+    item.transfer_mode.as_deref() == Some(REMOTE)
+}
+
+// Check a transfer mode before it is saved; None stands for a local download
+pub fn validate_mode(mode: Option<&str>) -> Result<Option<&'static str>, String> {
+    match mode.map(str::trim).filter(|mode| !mode.is_empty()) {
+        None => Ok(None),
+        Some(mode) if mode.eq_ignore_ascii_case(LOCAL) => Ok(None),
+        Some(mode) if mode.eq_ignore_ascii_case(REMOTE) => Ok(Some(REMOTE)),
+        Some(mode) => Err(format!(
+            "Invalid transfer mode '{}'; use '{}' or '{}'",
+            mode, LOCAL, REMOTE
+        )),
+    }
+}
+
+async fn settings_for(app_state: &AppState, item: &QueueItem) -> AppSettings {
+    let user_id = item.user_id.as_deref().unwrap_or("local-user");
+    app_state.db.get_settings(user_id).await.unwrap_or_default()
+}
+
+fn api_key(settings: &AppSettings) -> Option<String> {
+    settings
+        .filemoon_api_key
+        .clone()
+        .filter(|key| !key.trim().is_empty())
+}
+
+// The item's failure for a remote/add or remote/status call that went wrong
+fn request_failure(e: &StepError) -> ItemMessage {
+    match e.http_status {
+        Some(status) => ItemMessage::new(messages::REMOTE_API_ERROR)
+            .with("status", status)
+            .with("error", e.message.clone()),
+        None => ItemMessage::new(messages::REMOTE_REQUEST_FAILED).with("error", e.message.clone()),
+    }
+}
+
+async fn fail(app_state: &AppState, item_id: &str, failure: ItemMessage) {
+    eprintln!(
+        "Remote upload of item {} failed: {}",
+        item_id,
+        failure.text()
+    );
+    if let Err(e) = app_state.db.set_remote_filecode(item_id, None).await {
+        eprintln!("Error clearing remote upload of item {}: {}", item_id, e);
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "failed", Some(failure))
+        .await
+    {
+        eprintln!("Error updating status after failed remote upload: {}", e);
+    }
+}
+
+// Hand a queued item's source URL to Filemoon. Called by the queue processor in
+// place of the download; the sweep in `run` takes it from there.
+pub async fn start(app_state: &AppState, item: &QueueItem) {
+    let id = item.id.clone().unwrap_or_default();
+    let url = active_source_url(item);
+    let settings = settings_for(app_state, item).await;
+    let api_key = match api_key(&settings) {
+        Some(key) => key,
+        None => {
+            fail(
+                app_state,
+                &id,
+                ItemMessage::new(messages::REMOTE_API_KEY_MISSING),
+            )
+            .await;
+            return;
+        }
+    };
+
+    println!("Item {}: asking Filemoon to fetch {}", id, url);
+    let filecode = match filemoon::add_remote_upload(&http::client(), &api_key, &url).await {
+        Ok(filecode) => filecode,
+        Err(e) => {
+            record_provider_error(
+                app_state,
+                Some(&id),
+                "remote/add",
+                e.http_status,
+                &e.message,
+                e.body.as_deref(),
+            )
+            .await;
+            fail(app_state, &id, request_failure(&e)).await;
+            return;
+        }
+    };
+
+    // Stored before the status changes, so the sweep never sees the item without it
+    if let Err(e) = app_state.db.set_remote_filecode(&id, Some(&filecode)).await {
+        eprintln!("Error saving remote upload of item {}: {}", id, e);
+        return;
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(
+            &id,
+            "transferring",
+            Some(ItemMessage::new(messages::REMOTE_PENDING).with("filecode", filecode)),
+        )
+        .await
+    {
+        eprintln!("Error updating status after starting remote upload: {}", e);
+    }
+}
+
+// Record Filemoon's progress on the item; the status is only written when it moved
+async fn report_progress(app_state: &AppState, item: &QueueItem, status: &RemoteStatus) {
+    let id = item.id.clone().unwrap_or_default();
+    let percent = match (status.bytes_downloaded, status.bytes_total) {
+        (Some(done), Some(total)) if total > 0 => Some(done as f32 * 100.0 / total as f32),
+        _ => None,
+    };
+    let progress = Progress {
+        item_id: id.clone(),
+        percent: percent.unwrap_or(0.0),
+        speed: None,
+        eta: None,
+        total_bytes: status.bytes_total,
+        downloaded_bytes: status.bytes_downloaded,
+        speed_bps: None,
+        eta_seconds: None,
+    };
+    if let Err(e) = app_state.db.set_download_stats(&progress).await {
+        eprintln!("Error saving remote upload progress of item {}: {}", id, e);
+    }
+
+    let percent = match percent {
+        Some(percent) if status.state == RemoteState::Working => percent,
+        _ => return,
+    };
+    let message =
+        ItemMessage::new(messages::REMOTE_PROGRESS).with("percent", format!("{:.1}", percent));
+    if item.message.as_deref() == Some(message.text().as_str()) {
+        return;
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(&id, "transferring", Some(message))
+        .await
+    {
+        eprintln!("Error updating remote upload progress: {}", e);
+    }
+}
+
+// Filemoon has the file: link the item to it, as a finished upload does
+async fn finish(
+    app_state: &AppState,
+    item_id: &str,
+    filecode: &str,
+    status: &RemoteStatus,
+    settings: &AppSettings,
+) {
+    // Cancelled or retried while Filemoon was still busy
+    let mut item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) if item.status == "transferring" => item,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Error loading item {} after remote upload: {}", item_id, e);
+            return;
+        }
+    };
+
+    println!("Remote upload of item {} done: {}", item_id, filecode);
+    item.filemoon_url = Some(filecode.to_string());
+    if let Err(e) = app_state.db.update_queue_item(&item).await {
+        eprintln!("Failed to update Filemoon URL in DB: {}", e);
+    }
+    if let Err(e) = app_state.db.set_remote_filecode(item_id, None).await {
+        eprintln!("Error clearing remote upload of item {}: {}", item_id, e);
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(
+            item_id,
+            "uploaded",
+            Some(ItemMessage::new(messages::REMOTE_DONE).with("filecode", filecode)),
+        )
+        .await
+    {
+        eprintln!("Error updating status after remote upload: {}", e);
+    }
+    mirrors::record_main(app_state, item_id, "filemoon").await;
+
+    // Nothing went over this machine's connection, but it counts against the quota
+    if let Some(bytes) = status.bytes_total.filter(|bytes| *bytes > 0) {
+        let user_id = item.user_id.as_deref().unwrap_or("local-user");
+        if let Err(e) = app_state
+            .db
+            .record_upload_usage(user_id, item_id, "filemoon", bytes)
+            .await
+        {
+            eprintln!("Failed to record upload usage: {}", e);
+        }
+    }
+    create_short_url(app_state, item_id, filecode, settings).await;
+}
+
+// Check one running remote upload
+async fn poll(app_state: &AppState, item: &QueueItem) {
+    let id = item.id.clone().unwrap_or_default();
+    let filecode = item.remote_filecode.clone().unwrap_or_default();
+    let settings = settings_for(app_state, item).await;
+    let api_key = match api_key(&settings) {
+        Some(key) => key,
+        None => {
+            fail(
+                app_state,
+                &id,
+                ItemMessage::new(messages::REMOTE_API_KEY_MISSING),
+            )
+            .await;
+            return;
+        }
+    };
+
+    match filemoon::remote_upload_status(&http::client(), &api_key, &filecode).await {
+        Ok(status) => match &status.state {
+            RemoteState::Completed => finish(app_state, &id, &filecode, &status, &settings).await,
+            RemoteState::Failed(reason) => {
+                let failure =
+                    ItemMessage::new(messages::REMOTE_FAILED).with("error", reason.clone());
+                fail(app_state, &id, failure).await;
+            }
+            RemoteState::Pending | RemoteState::Working => {
+                report_progress(app_state, item, &status).await;
+            }
+        },
+        // Asked again on the next round
+        Err(e) if e.transient => {
+            eprintln!(
+                "Checking remote upload of item {} failed, will try again: {}",
+                id, e.message
+            );
+        }
+        Err(e) => {
+            record_provider_error(
+                app_state,
+                Some(&id),
+                "remote/status",
+                e.http_status,
+                &e.message,
+                e.body.as_deref(),
+            )
+            .await;
+            fail(app_state, &id, request_failure(&e)).await;
+        }
+    }
+}
+
+async fn poll_all(app_state: &AppState) {
+    match app_state.db.get_remote_transfers().await {
+        Ok(items) => {
+            for item in items {
+                poll(app_state, &item).await;
+            }
+        }
+        Err(e) => eprintln!("Error loading remote uploads: {}", e),
+    }
+}
+
+// Poll Filemoon for every running remote upload until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting remote upload checks...");
+    let app_state = app_handle.state::<AppState>();
+    let mut changes = app_state.settings_watch.subscribe();
+    loop {
+        poll_all(&app_state).await;
+        settings_watch::wait(&mut changes, POLL_INTERVAL).await;
+    }
+}
@@ -0,0 +1,356 @@
+// Automatic retries. When an item fails for a reason that may go away by itself (a
+// dropped connection, a timeout, a 5xx or 429 from Filemoon), it is scheduled to be
+// retried after a backoff that doubles with every automatic retry, from BACKOFF_BASE
+// up to BACKOFF_CAP. Downloads go back in the queue; uploads are re-sent from the
+// downloaded file. Permanent failures (a 404, a removed, private or geo-blocked
+// video, a bad API key) stay failed for the user to look at.
+//
+// Each item gets `max_auto_retries` automatic retries (DEFAULT_MAX_RETRIES when unset,
+// "0" turns them off), or its own `max_retries` when set with set_item_max_retries.
+// The schedule lives in the queue table (retry_count, next_retry_at), so retries
+// that were due while the app was closed run on the next start. A manual retry
+// starts the count from zero again.
+
+use chrono::Duration as ChronoDuration;
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::db::{AppSettings, QueueItem};
+use crate::messages::{self, ItemMessage};
+use crate::{filemoon, long_paths, settings_watch, timestamps, tools, AppState};
+
+pub const DEFAULT_MAX_RETRIES: i32 = 3;
+// Highest max_auto_retries or max_retries accepted
+pub const MAX_RETRIES_LIMIT: i32 = 20;
+
+const BACKOFF_BASE: Duration = Duration::from_secs(60);
+const BACKOFF_CAP: Duration = Duration::from_secs(60 * 60);
+// How often due retries are looked for
+const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+// yt-dlp errors from the connection or the site's servers rather than the video
+const NETWORK_MARKERS: &[&str] = &[
+    "timed out",
+    "connection reset",
+    "connection refused",
+    "connection aborted",
+    "remote end closed connection",
+    "network is unreachable",
+    "temporary failure in name resolution",
+    "name or service not known",
+    "getaddrinfo failed",
+    "incompleteread",
+    "unable to download webpage",
+    "http error 429",
+    "http error 500",
+    "http error 502",
+    "http error 503",
+    "http error 504",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stage {
+    Download,
+    Upload,
+}
+
+fn param<'a>(item: &'a QueueItem, name: &str) -> Option<&'a JsonValue> {
+    item.message_params.as_ref()?.get(name)
+}
+
+fn transient_http_status(item: &QueueItem) -> bool {
+    let status = match param(item, "status") {
+        Some(JsonValue::Number(n)) => n.as_u64(),
+        Some(JsonValue::String(s)) => s.trim().parse().ok(),
+        _ => None,
+    };
+    status.map_or(false, |status| filemoon::is_transient_status(status as u16))
+}
+
+fn network_error(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    NETWORK_MARKERS.iter().any(|marker| stderr.contains(marker))
+}
+
+// Which step to retry when the item's failure is transient; None when it is
+// permanent or can't be told from the failure message
+pub fn transient_stage(item: &QueueItem) -> Option<Stage> {
+    match item.message_code.as_deref()? {
+        messages::DOWNLOAD_FAILED => {
+            let stderr = param(item, "stderr").and_then(JsonValue::as_str)?;
+            (!tools::is_source_gone(stderr) && network_error(stderr)).then_some(Stage::Download)
+        }
+        messages::DOWNLOAD_WAIT_FAILED => Some(Stage::Download),
+        // A remote upload starts over from the queue, like a download
+        messages::REMOTE_REQUEST_FAILED => Some(Stage::Download),
+        messages::REMOTE_API_ERROR => transient_http_status(item).then_some(Stage::Download),
+        messages::UPLOAD_SERVER_ERROR | messages::UPLOAD_API_ERROR => {
+            transient_http_status(item).then_some(Stage::Upload)
+        }
+        messages::UPLOAD_SERVER_REQUEST_FAILED
+        | messages::UPLOAD_NO_HEALTHY_SERVER
+        | messages::UPLOAD_TIMEOUT
+        | messages::UPLOAD_READ_FAILED
+        | messages::UPLOAD_REQUEST_FAILED => Some(Stage::Upload),
+        _ => None,
+    }
+}
+
+fn parse_max(value: &str, name: &str) -> Result<i32, String> {
+    match value.trim().parse::<i32>() {
+        Ok(max) if (0..=MAX_RETRIES_LIMIT).contains(&max) => Ok(max),
+        _ => Err(format!(
+            "Invalid {} '{}'; use a number from 0 to {}",
+            name,
+            value.trim(),
+            MAX_RETRIES_LIMIT
+        )),
+    }
+}
+
+// Check the automatic retry settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    match settings
+        .max_auto_retries
+        .as_deref()
+        .filter(|v| !v.trim().is_empty())
+    {
+        Some(value) => parse_max(value, "max_auto_retries").map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+// Check a per-item cap before it is saved
+pub fn validate_max(max_retries: i32) -> Result<i32, String> {
+    parse_max(&max_retries.to_string(), "max_retries")
+}
+
+// Automatic retries the item gets in total
+fn max_retries(item: &QueueItem, settings: &AppSettings) -> i32 {
+    item.max_retries.unwrap_or_else(|| {
+        settings
+            .max_auto_retries
+            .as_deref()
+            .and_then(|value| parse_max(value, "max_auto_retries").ok())
+            .unwrap_or(DEFAULT_MAX_RETRIES)
+    })
+}
+
+// Wait before automatic retry number `retries_done + 1`
+pub fn backoff(retries_done: i32) -> Duration {
+    let factor = 2u32.saturating_pow(retries_done.clamp(0, 16) as u32);
+    BACKOFF_BASE.saturating_mul(factor).min(BACKOFF_CAP)
+}
+
+async fn settings_for(app_state: &AppState, item: &QueueItem) -> AppSettings {
+    let user_id = item.user_id.as_deref().unwrap_or("local-user");
+    app_state.db.get_settings(user_id).await.unwrap_or_default()
+}
+
+// Schedule the next automatic retry of an item that just failed, if it gets one
+async fn schedule(app_state: &AppState, item_id: &str) {
+    let item = match app_state.db.get_item_by_id(item_id).await {
+        Ok(Some(item)) if item.status == "failed" => item,
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("Automatic retries could not load item {}: {}", item_id, e);
+            return;
+        }
+    };
+    let settings = settings_for(app_state, &item).await;
+    let max = max_retries(&item, &settings);
+    let done = item.retry_count.unwrap_or(0);
+
+    let next = match transient_stage(&item) {
+        Some(_) if done < max => {
+            let delay = backoff(done);
+            println!(
+                "Item {} failed temporarily; automatic retry {} of {} in {}s",
+                item_id,
+                done + 1,
+                max,
+                delay.as_secs()
+            );
+            ChronoDuration::from_std(delay)
+                .ok()
+                .map(|delay| timestamps::now() + delay)
+        }
+        _ => None,
+    };
+    if let Err(e) = app_state.db.set_next_retry_at(item_id, next).await {
+        eprintln!("Error scheduling retry for item {}: {}", item_id, e);
+    }
+}
+
+fn has_local_file(item: &QueueItem) -> bool {
+    item.local_path
+        .as_deref()
+        .filter(|path| !path.is_empty())
+        .and_then(|path| fs::metadata(long_paths::extended(Path::new(path))).ok())
+        .map_or(false, |meta| meta.is_file() && meta.len() > 0)
+}
+
+// Put a failed item whose retry is due back to work
+async fn requeue(app_state: &AppState, item: &QueueItem, attempt: i32, max: i32) {
+    let id = item.id.clone().unwrap_or_default();
+    let user_id = item
+        .user_id
+        .clone()
+        .unwrap_or_else(|| "local-user".to_string());
+
+    if transient_stage(item) == Some(Stage::Upload) && has_local_file(item) {
+        let message = ItemMessage::new(messages::UPLOAD_AUTO_RETRY)
+            .with("attempt", attempt)
+            .with("max_attempts", max);
+        println!("Item {}: {}", id, message.text());
+        if let Err(e) = app_state
+            .db
+            .update_item_status(&id, "downloaded", Some(message))
+            .await
+        {
+            eprintln!("Error re-queuing upload of item {}: {}", id, e);
+            return;
+        }
+        if let Err(e) = app_state.uploads.enqueue(id.clone(), user_id) {
+            eprintln!("Error re-queuing upload of item {}: {}", id, e);
+        }
+        return;
+    }
+
+    // Start over from the preferred format and the primary source, like retry_item
+    if let Err(e) = app_state.db.set_format_rung(&id, 0).await {
+        eprintln!("Error resetting format rung for item {}: {}", id, e);
+    }
+    if let Err(e) = app_state.db.set_source_index(&id, 0).await {
+        eprintln!("Error resetting source for item {}: {}", id, e);
+    }
+    let message = ItemMessage::new(messages::DOWNLOAD_AUTO_RETRY)
+        .with("attempt", attempt)
+        .with("max_attempts", max);
+    println!("Item {}: {}", id, message.text());
+    match app_state
+        .db
+        .update_item_status(&id, "queued", Some(message))
+        .await
+    {
+        Ok(_) => app_state.queue_wakeup.wake(),
+        Err(e) => eprintln!("Error re-queuing item {}: {}", id, e),
+    }
+}
+
+// Re-queue every failed item whose retry is due
+async fn retry_due(app_state: &AppState) {
+    let due = match app_state.db.get_due_retries(timestamps::now()).await {
+        Ok(due) => due,
+        Err(e) => {
+            eprintln!("Error loading due retries: {}", e);
+            return;
+        }
+    };
+
+    for item in due {
+        let id = item.id.clone().unwrap_or_default();
+        // The cap may have been lowered since the retry was scheduled
+        let max = max_retries(&item, &settings_for(app_state, &item).await);
+        if item.retry_count.unwrap_or(0) >= max {
+            if let Err(e) = app_state.db.set_next_retry_at(&id, None).await {
+                eprintln!("Error cancelling retry of item {}: {}", id, e);
+            }
+            continue;
+        }
+        match app_state.db.claim_retry(&id).await {
+            Ok(Some(attempt)) => requeue(app_state, &item, attempt, max).await,
+            // Retried by hand or changed in the meantime
+            Ok(None) => {}
+            Err(e) => eprintln!("Error claiming retry of item {}: {}", id, e),
+        }
+    }
+}
+
+async fn sweep(app_handle: AppHandle) {
+    let app_state = app_handle.state::<AppState>();
+    let mut changes = app_state.settings_watch.subscribe();
+    loop {
+        retry_due(&app_state).await;
+        settings_watch::wait(&mut changes, SWEEP_INTERVAL).await;
+    }
+}
+
+// Schedule retries for failing items and run them when due, until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting automatic retries...");
+    let mut events = app_handle.state::<AppState>().db.subscribe_status_events();
+    tokio::spawn(sweep(app_handle.clone()));
+
+    loop {
+        match events.recv().await {
+            Ok(event) if event.status == "failed" => {
+                schedule(&app_handle.state::<AppState>(), &event.item_id).await;
+            }
+            Ok(_) => {}
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!(
+                    "Automatic retries fell behind and skipped {} status event(s)",
+                    missed
+                );
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    println!("Automatic retries stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn failed(code: &str, params: JsonValue) -> QueueItem {
+        let mut item = QueueItem::queued("https://example.com/video", "user");
+        item.status = "failed".to_string();
+        item.message_code = Some(code.to_string());
+        item.message_params = Some(params);
+        item
+    }
+
+    #[test]
+    fn retries_downloads_that_hit_network_errors() {
+        let item = failed(
+            messages::DOWNLOAD_FAILED,
+            json!({"stderr": "ERROR: [youtube] abc: Connection reset by peer"}),
+        );
+        assert_eq!(transient_stage(&item), Some(Stage::Download));
+    }
+
+    #[test]
+    fn does_not_retry_missing_sources() {
+        let item = failed(
+            messages::DOWNLOAD_FAILED,
+            json!({"stderr": "ERROR: [youtube] abc: Video unavailable"}),
+        );
+        assert_eq!(transient_stage(&item), None);
+    }
+
+    #[test]
+    fn retries_uploads_only_on_transient_statuses() {
+        let item = failed(messages::UPLOAD_API_ERROR, json!({"status": 502}));
+        assert_eq!(transient_stage(&item), Some(Stage::Upload));
+        let item = failed(messages::UPLOAD_API_ERROR, json!({"status": "504"}));
+        assert_eq!(transient_stage(&item), Some(Stage::Upload));
+        let item = failed(messages::UPLOAD_API_ERROR, json!({"status": 400}));
+        assert_eq!(transient_stage(&item), None);
+        let item = failed(messages::UPLOAD_TIMEOUT, json!({}));
+        assert_eq!(transient_stage(&item), Some(Stage::Upload));
+    }
+
+    #[test]
+    fn needs_a_message_code() {
+        let item = QueueItem::queued("https://example.com/video", "user");
+        assert_eq!(transient_stage(&item), None);
+    }
+}
@@ -0,0 +1,467 @@
+// S3-compatible object storage as an upload target (upload_target "s3"): AWS S3,
+// Backblaze B2, Wasabi, MinIO and anything else speaking the S3 API. Requests are
+// signed with AWS Signature Version 4 and use path-style addressing
+// (<endpoint>/<bucket>/<key>), which all of those accept.
+//
+// Files up to MULTIPART_THRESHOLD go up in a single streamed PUT. Larger ones use a
+// multipart upload in parts of at least PART_SIZE, each part retried on its own, so
+// a dropped connection late in a large file doesn't start it over. An upload that
+// fails for good is aborted; a cancelled one may leave parts behind, which a bucket
+// lifecycle rule for incomplete multipart uploads cleans up.
+//
+// Objects are stored as <item id>/<file name>, and the object's URL is kept on the
+// queue item as upload_url, with upload_provider "s3". Whether that URL can be
+// opened without credentials depends on the bucket's access policy.
+
+use chrono::{DateTime, Utc};
+use reqwest::{Method, Url};
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::Path;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::db::AppSettings;
+use crate::providers::{StepError, STEP_STATUS, STEP_UPLOAD};
+use crate::{filemoon, shares, urls};
+
+pub const PROVIDER: &str = "s3";
+
+const DEFAULT_REGION: &str = "us-east-1";
+const SERVICE: &str = "s3";
+// Payload hash for streamed bodies, which aren't hashed before sending
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const MULTIPART_THRESHOLD: u64 = 100 * 1024 * 1024;
+const PART_SIZE: u64 = 64 * 1024 * 1024;
+// S3 allows at most 10,000 parts, so very large files get larger parts
+const MAX_PARTS: u64 = 10_000;
+
+pub struct S3Config {
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+fn setting(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+fn parse_endpoint(endpoint: &str) -> Result<Url, String> {
+    let url = Url::parse(endpoint.trim_end_matches('/'))
+        .map_err(|e| format!("Invalid S3 endpoint '{}': {}", endpoint, e))?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return Err(format!(
+            "Invalid S3 endpoint '{}': expected an http(s) URL",
+            endpoint
+        ));
+    }
+    Ok(url)
+}
+
+// Bucket names are 3-63 lowercase letters, digits, dots and hyphens
+fn check_bucket(bucket: &str) -> Result<(), String> {
+    let valid = (3..=63).contains(&bucket.len())
+        && bucket
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid S3 bucket name '{}'", bucket))
+    }
+}
+
+// Checked when settings are saved; missing keys are only reported on upload
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    if let Some(endpoint) = setting(&settings.s3_endpoint) {
+        parse_endpoint(&endpoint)?;
+    }
+    if let Some(bucket) = setting(&settings.s3_bucket) {
+        check_bucket(&bucket)?;
+    }
+    Ok(())
+}
+
+impl S3Config {
+    pub fn from_settings(settings: &AppSettings) -> Result<Self, String> {
+        let required = |value: &Option<String>, name: &str| {
+            setting(value).ok_or_else(|| format!("S3 upload target is missing {}", name))
+        };
+        let endpoint = parse_endpoint(&required(&settings.s3_endpoint, "s3_endpoint")?)?;
+        let bucket = required(&settings.s3_bucket, "s3_bucket")?;
+        check_bucket(&bucket)?;
+        Ok(S3Config {
+            endpoint,
+            bucket,
+            region: setting(&settings.s3_region).unwrap_or_else(|| DEFAULT_REGION.to_string()),
+            access_key: required(&settings.s3_access_key, "s3_access_key")?,
+            secret_key: required(&settings.s3_secret_key, "s3_secret_key")?,
+        })
+    }
+
+    // Path of an object, percent-encoded the way it is signed
+    fn object_path(&self, key: &str) -> String {
+        format!(
+            "{}/{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            self.bucket,
+            urls::percent_encode(key, true)
+        )
+    }
+
+    pub fn object_url(&self, key: &str) -> String {
+        let mut url = self.endpoint.clone();
+        url.set_path(&self.object_path(key));
+        url.to_string()
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    // A request to `key` signed with SigV4. `query` must already be percent-encoded.
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        method: Method,
+        key: &str,
+        query: &[(&str, String)],
+        payload_hash: &str,
+    ) -> reqwest::RequestBuilder {
+        self.request_path(client, method, self.object_path(key), query, payload_hash)
+    }
+
+    // The same for an object path that is already encoded
+    fn request_path(
+        &self,
+        client: &reqwest::Client,
+        method: Method,
+        path: String,
+        query: &[(&str, String)],
+        payload_hash: &str,
+    ) -> reqwest::RequestBuilder {
+        let now = Utc::now();
+        let mut query: Vec<(&str, String)> = query.to_vec();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let authorization = self.authorization(&method, &path, &query, payload_hash, now);
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url.set_query(Some(&query).filter(|q| !q.is_empty()).map(|q| q.as_str()));
+
+        client
+            .request(method, url)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+    }
+
+    fn authorization(
+        &self,
+        method: &Method,
+        path: &str,
+        query: &str,
+        payload_hash: &str,
+        now: DateTime<Utc>,
+    ) -> String {
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SERVICE);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method.as_str(),
+            path,
+            query,
+            self.host(),
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let mut key = format!("AWS4{}", self.secret_key).into_bytes();
+        for part in [date.as_str(), self.region.as_str(), SERVICE, "aws4_request"] {
+            key = shares::hmac_sha256(&key, part.as_bytes());
+        }
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key,
+            scope,
+            signed_headers,
+            hex(&shares::hmac_sha256(&key, string_to_sign.as_bytes()))
+        )
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn sha256_hex(body: &[u8]) -> String {
+    hex(&Sha256::digest(body))
+}
+
+// The text of the first <tag> in an S3 XML answer
+fn xml_value<'a>(body: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = body.find(&open)? + open.len();
+    let end = body[start..].find(&format!("</{}>", tag))? + start;
+    Some(body[start..end].trim()).filter(|value| !value.is_empty())
+}
+
+// Send a request, failing on anything but 2xx. Returns the response headers' ETag
+// and the body.
+async fn send(request: reqwest::RequestBuilder) -> Result<(Option<String>, String), StepError> {
+    let response = request.send().await.map_err(|e| StepError {
+        transient: filemoon::is_transient_error(&e),
+        ..StepError::new(STEP_UPLOAD, format!("Request failed: {}", e))
+    })?;
+    let status = response.status().as_u16();
+    let etag = response
+        .headers()
+        .get("etag")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let text = response.text().await.unwrap_or_default();
+    if (200..300).contains(&status) {
+        return Ok((etag, text));
+    }
+    let message = match (xml_value(&text, "Code"), xml_value(&text, "Message")) {
+        (Some(code), Some(message)) => format!("HTTP {}: {} ({})", status, message, code),
+        (None, Some(message)) => format!("HTTP {}: {}", status, message),
+        _ => format!("HTTP {}", status),
+    };
+    Err(StepError {
+        http_status: Some(status),
+        body: Some(text),
+        // 503 is S3's SlowDown, not maintenance as on Filemoon
+        transient: status == 503 || filemoon::is_transient_status(status),
+        ..StepError::new(STEP_UPLOAD, message)
+    })
+}
+
+// `len` bytes of the file from `offset`, streamed
+async fn file_body(path: &Path, offset: u64, len: u64) -> Result<reqwest::Body, StepError> {
+    let open_error =
+        |e: std::io::Error| StepError::new(STEP_UPLOAD, format!("Failed to read file: {}", e));
+    let mut file = tokio::fs::File::open(path).await.map_err(open_error)?;
+    file.seek(SeekFrom::Start(offset))
+        .await
+        .map_err(open_error)?;
+    let stream = ReaderStream::with_capacity(file.take(len), filemoon::UPLOAD_CHUNK_BYTES);
+    Ok(reqwest::Body::wrap_stream(stream))
+}
+
+fn part_size(bytes: u64) -> u64 {
+    PART_SIZE.max((bytes + MAX_PARTS - 1) / MAX_PARTS)
+}
+
+// Size of the object at `object_url` (an upload_url), or None if the bucket has no
+// such object
+pub async fn stored_size(
+    client: &reqwest::Client,
+    config: &S3Config,
+    object_url: &str,
+) -> Result<Option<u64>, StepError> {
+    let url = Url::parse(object_url)
+        .map_err(|e| StepError::new(STEP_STATUS, format!("Invalid object URL: {}", e)))?;
+    let bucket_path = config.object_path("");
+    if url.host_str() != config.endpoint.host_str() || !url.path().starts_with(&bucket_path) {
+        return Err(StepError::new(
+            STEP_STATUS,
+            format!("{} is not in the configured bucket", object_url),
+        ));
+    }
+    let request = config.request_path(
+        client,
+        Method::HEAD,
+        url.path().to_string(),
+        &[],
+        &sha256_hex(b""),
+    );
+    let response = request.send().await.map_err(|e| StepError {
+        transient: filemoon::is_transient_error(&e),
+        ..StepError::new(STEP_STATUS, format!("Request failed: {}", e))
+    })?;
+    let status = response.status().as_u16();
+    if status == 404 {
+        return Ok(None);
+    }
+    if !(200..300).contains(&status) {
+        return Err(StepError {
+            http_status: Some(status),
+            transient: status == 503 || filemoon::is_transient_status(status),
+            ..StepError::new(STEP_STATUS, format!("HTTP {}", status))
+        });
+    }
+    Ok(response
+        .headers()
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok()))
+}
+
+// Store the file at `key`. `progress` is called with the bytes sent so far after
+// each multipart part.
+pub async fn upload(
+    client: &reqwest::Client,
+    config: &S3Config,
+    key: &str,
+    path: &Path,
+    bytes: u64,
+    progress: &(dyn Fn(u64) + Send + Sync),
+) -> Result<(), StepError> {
+    if bytes <= MULTIPART_THRESHOLD {
+        let request = config
+            .request(client, Method::PUT, key, &[], UNSIGNED_PAYLOAD)
+            .header("content-length", bytes)
+            .body(file_body(path, 0, bytes).await?);
+        return send(request).await.map(|_| ());
+    }
+
+    let (_, answer) = send(config.request(
+        client,
+        Method::POST,
+        key,
+        &[("uploads", String::new())],
+        &sha256_hex(b""),
+    ))
+    .await?;
+    let upload_id = xml_value(&answer, "UploadId")
+        .map(str::to_string)
+        .ok_or_else(|| StepError {
+            body: Some(answer.clone()),
+            ..StepError::new(STEP_UPLOAD, "No UploadId in answer".to_string())
+        })?;
+
+    let result = upload_parts(client, config, key, path, bytes, &upload_id, progress).await;
+    if result.is_err() {
+        let abort = config.request(
+            client,
+            Method::DELETE,
+            key,
+            &[("uploadId", urls::percent_encode(&upload_id, false))],
+            &sha256_hex(b""),
+        );
+        if let Err(e) = send(abort).await {
+            eprintln!(
+                "Failed to abort S3 multipart upload {}: {}",
+                upload_id, e.message
+            );
+        }
+    }
+    result
+}
+
+async fn upload_parts(
+    client: &reqwest::Client,
+    config: &S3Config,
+    key: &str,
+    path: &Path,
+    bytes: u64,
+    upload_id: &str,
+    progress: &(dyn Fn(u64) + Send + Sync),
+) -> Result<(), StepError> {
+    let upload_id_param = urls::percent_encode(upload_id, false);
+    let part_size = part_size(bytes);
+    let mut etags = Vec::new();
+    let mut offset = 0;
+    while offset < bytes {
+        let number = etags.len() + 1;
+        let len = part_size.min(bytes - offset);
+        let mut attempt = 1;
+        let etag = loop {
+            let request = config
+                .request(
+                    client,
+                    Method::PUT,
+                    key,
+                    &[
+                        ("partNumber", number.to_string()),
+                        ("uploadId", upload_id_param.clone()),
+                    ],
+                    UNSIGNED_PAYLOAD,
+                )
+                .header("content-length", len)
+                .body(file_body(path, offset, len).await?);
+            match send(request).await {
+                Ok((Some(etag), _)) => break etag,
+                Ok((None, _)) => {
+                    return Err(StepError::new(
+                        STEP_UPLOAD,
+                        format!("No ETag for part {}", number),
+                    ))
+                }
+                Err(e) if e.transient && attempt < filemoon::UPLOAD_MAX_ATTEMPTS => {
+                    eprintln!(
+                        "S3 part {} failed (attempt {}): {}; retrying",
+                        number, attempt, e.message
+                    );
+                    tokio::time::sleep(filemoon::retry_delay(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        etags.push(etag);
+        offset += len;
+        progress(offset);
+    }
+
+    let parts: String = etags
+        .iter()
+        .enumerate()
+        .map(|(i, etag)| {
+            format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+                i + 1,
+                etag
+            )
+        })
+        .collect();
+    let body = format!(
+        "<CompleteMultipartUpload>{}</CompleteMultipartUpload>",
+        parts
+    );
+    let request = config
+        .request(
+            client,
+            Method::POST,
+            key,
+            &[("uploadId", upload_id_param)],
+            &sha256_hex(body.as_bytes()),
+        )
+        .body(body);
+    // CompleteMultipartUpload can answer 200 with an <Error> in the body
+    let (_, answer) = send(request).await?;
+    if let Some(message) = xml_value(&answer, "Message").filter(|_| answer.contains("<Error>")) {
+        return Err(StepError {
+            body: Some(answer.clone()),
+            transient: true,
+            ..StepError::new(STEP_UPLOAD, message.to_string())
+        });
+    }
+    Ok(())
+}
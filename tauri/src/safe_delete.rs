@@ -0,0 +1,409 @@
+// Verified deletion of local copies. With delete_after_upload on, the local file is
+// the only copy until the main upload target has really got the upload, so a 200
+// from the upload endpoint is not enough. The file is removed only once the upload
+// checks out, and only `delete_grace_days` after that:
+// - Filemoon: file/info reports it as existing, playable, with a duration and the
+//   same size as the local file (or, where Filemoon reports no size, the same
+//   duration)
+// - S3: the object is in the bucket with the local file's size
+// - archive.org: the item lists an uploaded file of the local file's size
+// - declared providers: the definition's status step reported the file ready. A
+//   provider without a status step can't be checked, so its uploads are kept.
+// Nothing is deleted either while a mirror upload (see mirrors.rs) is still missing
+// or failed. delete_local_files removes local copies on request instead, once
+// confirmed (see confirmations.rs).
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+use crate::db::{AppSettings, QueueItem};
+use crate::filemoon::FilemoonFileInfoResult;
+use crate::{
+    archive_org, http, long_paths, mirrors, remove_empty_item_dir, s3, settings_watch,
+    status_refresh, timestamps, transcode, AppState,
+};
+
+pub const DEFAULT_GRACE_DAYS: i64 = 0;
+const CHECK_INTERVAL: Duration = Duration::from_secs(600);
+// How far the uploaded file may be from the local one: a fraction of the size, and
+// seconds of duration (Filemoon rounds the length it reports)
+const SIZE_TOLERANCE: f64 = 0.01;
+const DURATION_TOLERANCE_SECS: f64 = 2.0;
+
+const FILEMOON: &str = "filemoon";
+
+// Statuses of items that have been handed to Filemoon
+const UPLOADED_STATUSES: &[&str] = &["uploaded", "transferring", "encoding", "encoded"];
+
+// Returned by delete_local_files
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalDeletion {
+    pub deleted: usize,
+    pub failed: usize,
+    pub bytes_freed: u64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Verified,
+    // Not ready yet (still encoding); checked again on the next sweep
+    Pending(String),
+    // Filemoon reports something wrong; the local copy is kept
+    Suspicious(String),
+}
+
+// Days to keep the local copy after verification; unset or invalid uses the default
+fn grace_period(settings: &AppSettings) -> ChronoDuration {
+    let days = settings
+        .delete_grace_days
+        .as_deref()
+        .and_then(|v| v.trim().parse::<i64>().ok())
+        .filter(|days| *days >= 0)
+        .unwrap_or(DEFAULT_GRACE_DAYS);
+    ChronoDuration::days(days)
+}
+
+fn reported_number(value: Option<&JsonValue>) -> Option<f64> {
+    match value? {
+        JsonValue::Number(n) => n.as_f64(),
+        JsonValue::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+// What is known about the local copy, to hold the upload against
+struct LocalFile {
+    size: Option<u64>,
+    duration: Option<f64>,
+}
+
+fn compare_size(remote: u64, local: Option<u64>) -> Verdict {
+    match local {
+        Some(size) if (remote as f64 - size as f64).abs() <= size as f64 * SIZE_TOLERANCE => {
+            Verdict::Verified
+        }
+        Some(size) => {
+            Verdict::Suspicious(format!("{} bytes uploaded, {} bytes locally", remote, size))
+        }
+        None => Verdict::Suspicious("local file missing".to_string()),
+    }
+}
+
+fn judge(info: Option<&FilemoonFileInfoResult>, local: &LocalFile) -> Verdict {
+    let info = match info {
+        Some(info) => info,
+        None => return Verdict::Suspicious("missing from file/info".to_string()),
+    };
+    match info.status {
+        Some(200) => {}
+        Some(status) => return Verdict::Suspicious(format!("file/info status {}", status)),
+        None => return Verdict::Pending("no file/info status reported".to_string()),
+    }
+    if info.canplay != Some(1) {
+        return Verdict::Pending("not playable yet".to_string());
+    }
+    let length = match reported_number(info.length.as_ref()) {
+        Some(length) if length > 0.0 => length,
+        Some(_) => return Verdict::Suspicious("zero duration reported".to_string()),
+        None => return Verdict::Pending("no duration reported yet".to_string()),
+    };
+
+    match reported_number(info.size.as_ref()) {
+        Some(remote) => compare_size(remote as u64, local.size),
+        None => match local.duration {
+            Some(duration) if (length - duration).abs() <= DURATION_TOLERANCE_SECS => {
+                Verdict::Verified
+            }
+            Some(duration) => Verdict::Suspicious(format!(
+                "{:.0}s on Filemoon, {:.0}s locally",
+                length, duration
+            )),
+            None => Verdict::Pending(
+                "no size reported and the local duration could not be read".to_string(),
+            ),
+        },
+    }
+}
+
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting verified local file cleanup...");
+
+    // A settings change starts the next sweep at once (see settings_watch.rs)
+    let mut changes = app_handle.state::<AppState>().settings_watch.subscribe();
+    loop {
+        settings_watch::wait(&mut changes, CHECK_INTERVAL).await;
+        if let Err(e) = sweep(&app_handle).await {
+            eprintln!("Local file cleanup failed: {}", e);
+        }
+    }
+}
+
+async fn sweep(app_handle: &AppHandle) -> Result<(), String> {
+    let app_state = app_handle.state::<AppState>();
+    let items = app_state
+        .db
+        .get_items_in_statuses(UPLOADED_STATUSES)
+        .await
+        .map_err(|e| format!("Failed to load uploaded items: {}", e))?;
+
+    let client = http::client();
+    let mut settings_by_user: HashMap<String, AppSettings> = HashMap::new();
+    let now = timestamps::now();
+
+    for item in items {
+        let (item_id, user_id) = match (&item.id, &item.user_id) {
+            (Some(id), Some(user_id)) => (id.clone(), user_id.clone()),
+            _ => continue,
+        };
+        if item.local_path.as_deref().map_or(true, str::is_empty) {
+            continue;
+        }
+        let target = match uploaded_to(&item) {
+            Some(target) => target.to_string(),
+            None => continue,
+        };
+
+        if !settings_by_user.contains_key(&user_id) {
+            let settings = app_state
+                .db
+                .get_settings(&user_id)
+                .await
+                .map_err(|e| format!("Failed to load settings: {}", e))?;
+            settings_by_user.insert(user_id.clone(), settings);
+        }
+        let settings = mirrors::item_settings(&settings_by_user[&user_id], &item);
+        if settings.delete_after_upload.as_deref() != Some("true") {
+            continue;
+        }
+
+        // The local file is the source for copies still to be made
+        let uploads = match app_state.db.get_uploads(&item_id).await {
+            Ok(uploads) => uploads,
+            Err(e) => {
+                eprintln!("Error loading uploads of item {}: {}", item_id, e);
+                continue;
+            }
+        };
+        let pending = mirrors::pending(&settings, &uploads);
+        if !pending.is_empty() {
+            println!(
+                "Keeping local copy of item {}: not mirrored to {} yet",
+                item_id,
+                pending.join(", ")
+            );
+            continue;
+        }
+
+        let verified_at = match item.upload_verified_at {
+            Some(at) => at,
+            None => match verify(&app_state, &client, &settings, &item, &target).await {
+                Some(at) => at,
+                None => continue,
+            },
+        };
+        if now >= verified_at + grace_period(&settings) {
+            delete_local_copy(&app_state, &item).await;
+        }
+    }
+    Ok(())
+}
+
+// Where the item's main upload went: the provider it records, or Filemoon when it
+// has a file code
+fn uploaded_to(item: &QueueItem) -> Option<&str> {
+    match item.upload_provider.as_deref() {
+        Some(provider) if !provider.is_empty() => Some(provider),
+        _ if item
+            .filemoon_url
+            .as_deref()
+            .map_or(false, |code| !code.is_empty()) =>
+        {
+            Some(FILEMOON)
+        }
+        _ => None,
+    }
+}
+
+async fn check_filemoon(
+    app_state: &AppState,
+    client: &reqwest::Client,
+    settings: &AppSettings,
+    item: &QueueItem,
+) -> Verdict {
+    let filecode = item.filemoon_url.clone().unwrap_or_default();
+    let api_key = match settings
+        .filemoon_api_key
+        .as_deref()
+        .filter(|k| !k.is_empty())
+    {
+        Some(key) => key,
+        None => return Verdict::Pending("no Filemoon API key".to_string()),
+    };
+    let response = match status_refresh::fetch_file_info(
+        app_state,
+        client,
+        api_key,
+        &[filecode.clone()],
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err(e) => return Verdict::Pending(e),
+    };
+    let results = response.result.unwrap_or_default();
+    let info = results.iter().find(|r| r.file_code == filecode);
+
+    // ffprobe is only asked when Filemoon gives no size to compare
+    let duration = match (info.and_then(|info| info.size.as_ref()), &item.local_path) {
+        (None, Some(path)) => transcode::duration(settings, Path::new(path)).await,
+        _ => None,
+    };
+    let local = LocalFile {
+        size: local_size(item),
+        duration,
+    };
+    judge(info, &local)
+}
+
+async fn check_s3(client: &reqwest::Client, settings: &AppSettings, item: &QueueItem) -> Verdict {
+    let config = match s3::S3Config::from_settings(settings) {
+        Ok(config) => config,
+        Err(e) => return Verdict::Pending(e),
+    };
+    let url = match item.upload_url.as_deref() {
+        Some(url) => url,
+        None => return Verdict::Suspicious("no object URL recorded".to_string()),
+    };
+    match s3::stored_size(client, &config, url).await {
+        Ok(Some(size)) => compare_size(size, local_size(item)),
+        Ok(None) => Verdict::Suspicious("object not in the bucket".to_string()),
+        Err(e) => Verdict::Pending(e.message),
+    }
+}
+
+async fn check_archive_org(client: &reqwest::Client, item: &QueueItem) -> Verdict {
+    let identifier = archive_org::identifier(item);
+    let sizes = match archive_org::stored_sizes(client, &identifier).await {
+        Ok(sizes) => sizes,
+        Err(e) => return Verdict::Pending(e.message),
+    };
+    let local = local_size(item);
+    match sizes.iter().find(|size| Some(**size) == local) {
+        Some(size) => compare_size(*size, local),
+        None if sizes.is_empty() => Verdict::Pending("no files listed yet".to_string()),
+        None => compare_size(sizes[0], local),
+    }
+}
+
+// Declared providers are checked by providers::watch, which marks the item
+// "encoded" once the host reports the file ready
+fn check_declared(app_state: &AppState, item: &QueueItem, provider: &str) -> Verdict {
+    match app_state.providers.get(provider) {
+        Some(definition) if definition.status.is_none() => {
+            Verdict::Pending(format!("{} has no status step to check against", provider))
+        }
+        Some(_) if item.status == "encoded" => Verdict::Verified,
+        Some(_) => Verdict::Pending("not reported ready yet".to_string()),
+        None => Verdict::Pending(format!("provider {} is no longer declared", provider)),
+    }
+}
+
+// Check the upload on its target; returns the verification time once it checks out
+async fn verify(
+    app_state: &AppState,
+    client: &reqwest::Client,
+    settings: &AppSettings,
+    item: &QueueItem,
+    target: &str,
+) -> Option<DateTime<Utc>> {
+    let item_id = item.id.as_deref()?;
+    let verdict = match target {
+        FILEMOON => check_filemoon(app_state, client, settings, item).await,
+        s3::PROVIDER => check_s3(client, settings, item).await,
+        archive_org::PROVIDER => check_archive_org(client, item).await,
+        provider => check_declared(app_state, item, provider),
+    };
+
+    match verdict {
+        Verdict::Verified => {
+            let now = timestamps::now();
+            if let Err(e) = app_state.db.set_upload_verified(item_id, now).await {
+                eprintln!(
+                    "Error saving upload verification for item {}: {}",
+                    item_id, e
+                );
+                return None;
+            }
+            println!("Upload of item {} verified on {}", item_id, target);
+            Some(now)
+        }
+        Verdict::Pending(reason) => {
+            println!("Upload of item {} not verified yet: {}", item_id, reason);
+            None
+        }
+        Verdict::Suspicious(reason) => {
+            eprintln!(
+                "Keeping local copy of item {}: upload to {} looks wrong ({})",
+                item_id, target, reason
+            );
+            None
+        }
+    }
+}
+
+// Size of an item's local file, if it is still on disk
+pub fn local_size(item: &QueueItem) -> Option<u64> {
+    let local_path = item.local_path.as_deref().filter(|path| !path.is_empty())?;
+    std::fs::metadata(long_paths::extended(Path::new(local_path)))
+        .ok()
+        .filter(|metadata| metadata.is_file())
+        .map(|metadata| metadata.len())
+}
+
+// Delete the item's local file and forget its path. Returns false if the file could
+// not be deleted.
+async fn delete_local_copy(app_state: &AppState, item: &QueueItem) -> bool {
+    let (item_id, local_path) = match (&item.id, &item.local_path) {
+        (Some(id), Some(path)) => (id, path),
+        _ => return false,
+    };
+    let path = long_paths::extended(Path::new(local_path));
+
+    if path.exists() {
+        if let Err(e) = std::fs::remove_file(&path) {
+            eprintln!("Failed to delete local file {}: {}", local_path, e);
+            return false;
+        }
+        println!("Deleted local copy: {}", local_path);
+        remove_empty_item_dir(&path, item_id);
+    }
+    if let Err(e) = app_state.db.clear_local_path(item_id).await {
+        eprintln!("Error clearing local path for item {}: {}", item_id, e);
+    }
+    true
+}
+
+// Delete the local copies of `items` on the user's request (delete_local_files),
+// without waiting for an upload to be verified
+pub async fn delete_local_copies(app_state: &AppState, items: &[QueueItem]) -> LocalDeletion {
+    let mut deletion = LocalDeletion {
+        deleted: 0,
+        failed: 0,
+        bytes_freed: 0,
+    };
+    for item in items {
+        let bytes = local_size(item).unwrap_or(0);
+        if delete_local_copy(app_state, item).await {
+            deletion.deleted += 1;
+            deletion.bytes_freed += bytes;
+        } else {
+            deletion.failed += 1;
+        }
+    }
+    deletion
+}
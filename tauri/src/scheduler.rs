@@ -0,0 +1,45 @@
+// Queue scheduling policy. Each queued item gets an effective priority:
+//
+//     priority + aging boost - failure penalty
+//
+// The aging boost grows the longer an item has waited, so old items in a large
+// mixed queue can't be starved by newer high-priority ones. The failure penalty
+// pushes items that keep failing behind everything else, so hopeless cases stop
+// taking the download slot from items that can still succeed.
+//
+// Items the user put in order with reorder_queue (queue_position) go ahead of all
+// of that, in the given order.
+
+use lazy_static::lazy_static;
+
+// One point of boost per this many minutes spent in the queue
+pub const AGING_STEP_MINUTES: i64 = 30;
+// Aging alone never lifts an item more than this far
+pub const MAX_AGING_BOOST: i64 = 10;
+// Points lost per failed download attempt
+pub const FAILURE_PENALTY: i64 = 3;
+
+// Manual priorities are clamped to this range
+pub const MIN_PRIORITY: i32 = -100;
+pub const MAX_PRIORITY: i32 = 100;
+
+lazy_static! {
+    // SQL expression for the effective priority of a queue row
+    pub static ref EFFECTIVE_PRIORITY_SQL: String = format!(
+        "(priority
+          + LEAST(FLOOR(EXTRACT(EPOCH FROM (NOW() - added_at)) / {}), {})
+          - failure_count * {})",
+        AGING_STEP_MINUTES * 60,
+        MAX_AGING_BOOST,
+        FAILURE_PENALTY
+    );
+    // ORDER BY clause the processor picks queued items in
+    pub static ref QUEUE_ORDER_SQL: String = format!(
+        "queue_position ASC NULLS LAST, {} DESC, added_at ASC",
+        EFFECTIVE_PRIORITY_SQL.as_str()
+    );
+}
+
+pub fn clamp_priority(priority: i32) -> i32 {
+    priority.clamp(MIN_PRIORITY, MAX_PRIORITY)
+}
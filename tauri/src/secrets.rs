@@ -0,0 +1,174 @@
+// API keys and other credentials kept in the OS keychain (Windows Credential Manager,
+// the macOS Keychain, or the Secret Service on Linux) instead of the settings table.
+// When settings are saved, each value in SECRET_SETTINGS goes to the keychain under
+// the service SERVICE and the account "<scope>/<setting>", where the scope is the
+// user id or GLOBAL_SCOPE, and the stored JSON only holds "keychain:<account>".
+// Loading settings swaps the references back for the values, so the rest of the app
+// never sees them; values read are cached for the life of the process.
+//
+// Where no keychain can be reached (a Linux box without a Secret Service, say), the
+// value is stored as before and a warning is logged. secure_stored on startup moves
+// values still stored in plaintext, from before this existed, into the keychain.
+//
+// Snapshots and the database hold only references, so restoring one on another
+// machine needs the keys entered again.
+
+use lazy_static::lazy_static;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::db::AppSettings;
+
+pub const SERVICE: &str = "PermaVid";
+pub const GLOBAL_SCOPE: &str = "global";
+pub const REFERENCE_PREFIX: &str = "keychain:";
+
+// Settings whose values are kept in the keychain
+pub const SECRET_SETTINGS: &[&str] = &[
+    "filemoon_api_key",
+    "shortener_api_key",
+    "telegram_bot_token",
+    "archive_org_secret_key",
+    "s3_secret_key",
+    "webhook_secret",
+    "local_api_token",
+];
+
+lazy_static! {
+    // Values by account, as last read from or written to the keychain
+    static ref CACHE: Mutex<HashMap<String, String>> = Mutex::new(HashMap::new());
+}
+
+fn fields(settings: &mut AppSettings) -> [(&'static str, &mut Option<String>); 7] {
+    [
+        ("filemoon_api_key", &mut settings.filemoon_api_key),
+        ("shortener_api_key", &mut settings.shortener_api_key),
+        ("telegram_bot_token", &mut settings.telegram_bot_token),
+        (
+            "archive_org_secret_key",
+            &mut settings.archive_org_secret_key,
+        ),
+        ("s3_secret_key", &mut settings.s3_secret_key),
+        ("webhook_secret", &mut settings.webhook_secret),
+        ("local_api_token", &mut settings.local_api_token),
+    ]
+}
+
+pub fn is_reference(value: &str) -> bool {
+    value.starts_with(REFERENCE_PREFIX)
+}
+
+fn account(scope: &str, setting: &str) -> String {
+    format!("{}/{}", scope, setting)
+}
+
+fn entry(account: &str) -> keyring::Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, account)
+}
+
+// The value behind a reference; None when the keychain has no such entry
+pub fn reveal(reference: &str) -> Option<String> {
+    let account = reference.strip_prefix(REFERENCE_PREFIX)?;
+    if let Some(value) = CACHE.lock().unwrap().get(account) {
+        return Some(value.clone());
+    }
+
+    match entry(account).and_then(|entry| entry.get_password()) {
+        Ok(value) => {
+            CACHE
+                .lock()
+                .unwrap()
+                .insert(account.to_string(), value.clone());
+            Some(value)
+        }
+        Err(keyring::Error::NoEntry) => {
+            eprintln!("The keychain has no entry for {}", account);
+            None
+        }
+        Err(e) => {
+            eprintln!("Could not read {} from the keychain: {}", account, e);
+            None
+        }
+    }
+}
+
+// Keep `value` in the keychain and return the reference to store instead
+fn keep(scope: &str, setting: &str, value: &str) -> keyring::Result<String> {
+    let account = account(scope, setting);
+    let unchanged = CACHE.lock().unwrap().get(&account).map(String::as_str) == Some(value);
+    if !unchanged {
+        entry(&account)?.set_password(value)?;
+        CACHE
+            .lock()
+            .unwrap()
+            .insert(account.clone(), value.to_string());
+    }
+    Ok(format!("{}{}", REFERENCE_PREFIX, account))
+}
+
+// Drop a value that is no longer set
+fn forget(scope: &str, setting: &str) {
+    let account = account(scope, setting);
+    CACHE.lock().unwrap().remove(&account);
+    match entry(&account).and_then(|entry| entry.delete_password()) {
+        Ok(()) | Err(keyring::Error::NoEntry) => {}
+        Err(e) => eprintln!("Could not remove {} from the keychain: {}", account, e),
+    }
+}
+
+// Replace the references in settings just loaded with the values they point to
+pub fn resolve(settings: &mut AppSettings) {
+    for (_, field) in fields(settings) {
+        if let Some(revealed) = field.as_deref().filter(|v| is_reference(v)).map(reveal) {
+            *field = revealed;
+        }
+    }
+}
+
+// The settings to store for `scope`: secrets moved to the keychain and replaced by
+// references. A secret the keychain won't take stays as it is.
+pub fn store(scope: &str, settings: &AppSettings) -> AppSettings {
+    let mut stored = settings.clone();
+    for (setting, field) in fields(&mut stored) {
+        let value = match field.as_deref().filter(|v| !v.is_empty()) {
+            Some(value) if is_reference(value) => continue,
+            Some(value) => value.to_string(),
+            None => {
+                forget(scope, setting);
+                continue;
+            }
+        };
+        match keep(scope, setting, &value) {
+            Ok(reference) => *field = Some(reference),
+            Err(e) => eprintln!(
+                "Keychain unavailable, storing {} in the database: {}",
+                setting, e
+            ),
+        }
+    }
+    stored
+}
+
+// Secrets in `settings` still stored as plaintext
+pub fn has_plaintext(settings: &AppSettings) -> bool {
+    fields(&mut settings.clone()).iter().any(|(_, field)| {
+        field
+            .as_deref()
+            .map_or(false, |v| !v.is_empty() && !is_reference(v))
+    })
+}
+
+// Move one plaintext secret of the old one-row-per-setting layout into the keychain.
+// Returns the reference to store instead.
+pub fn store_value(scope: &str, setting: &str, value: &str) -> Option<String> {
+    if !SECRET_SETTINGS.contains(&setting) || value.is_empty() || is_reference(value) {
+        return None;
+    }
+    match keep(scope, setting, value) {
+        Ok(reference) => Some(reference),
+        Err(e) => {
+            eprintln!("Keychain unavailable, leaving {} as it is: {}", setting, e);
+            None
+        }
+    }
+}
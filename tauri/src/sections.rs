@@ -0,0 +1,40 @@
+// Time ranges passed to yt-dlp's --download-sections so only a slice of a long
+// video is downloaded (and later uploaded).
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    // [[hh:]mm:]ss[.ms]-[[hh:]mm:]ss[.ms] or an open end "inf"
+    static ref TIME_RANGE_REGEX: Regex = Regex::new(
+        r"^(?:\d+:){0,2}\d+(?:\.\d+)?-(?:(?:\d+:){0,2}\d+(?:\.\d+)?|inf)$"
+    )
+    .unwrap();
+}
+
+// Parse a comma separated list of time ranges ("10:00-15:30, 1:02:00-inf") into
+// yt-dlp section arguments ("*10:00-15:30"). An empty input means no sections.
+pub fn parse_sections(input: &str) -> Result<Vec<String>, String> {
+    let mut sections = Vec::new();
+    for range in input.split(',').map(str::trim).filter(|r| !r.is_empty()) {
+        let range = range.trim_start_matches('*').replace(' ', "");
+        if !TIME_RANGE_REGEX.is_match(&range) {
+            return Err(format!(
+                "'{}' is not a valid time range. Use start-end, e.g. 10:00-15:30",
+                range
+            ));
+        }
+        sections.push(format!("*{}", range));
+    }
+    Ok(sections)
+}
+
+// Validated form of a user supplied range list for storage; None when empty
+pub fn normalize(input: &str) -> Result<Option<String>, String> {
+    let sections = parse_sections(input)?;
+    if sections.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(sections.join(",")))
+    }
+}
@@ -0,0 +1,266 @@
+// Commands for reading and saving per-user and global settings.
+
+use tauri::State;
+
+use crate::db::{self, AppSettings};
+use crate::{
+    auto_upload, clipboard, concurrency, cookies, download_window, formats, hooks, http, local_api,
+    media_library, performance, progress, retry_policy, s3, settings_watch, transcode, webhooks,
+    ytdlp_manager, AppState, Response,
+};
+
+#[tauri::command]
+pub async fn get_settings(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<AppSettings>, String> {
+    match app_state.db.get_settings(&user_id).await {
+        Ok(settings) => {
+            // The signed-in user's provider settings apply to the shared HTTP client
+            app_state.settings_watch.set_active_user(&user_id);
+            if let Err(e) = http::configure(&settings) {
+                eprintln!("Ignoring invalid provider HTTP settings: {}", e);
+            }
+            ytdlp_manager::configure(&settings);
+            Ok(Response {
+                success: true,
+                message: "Settings retrieved successfully".to_string(),
+                data: Some(settings),
+            })
+        }
+        Err(e) => {
+            eprintln!("Error retrieving settings: {}", e);
+            Ok(Response {
+                success: true,
+                message: "Settings table empty or not found, using defaults".to_string(),
+                data: Some(AppSettings {
+                    filemoon_api_key: None,
+                    download_directory: None,
+                    delete_after_upload: None,
+                    auto_upload: None,
+                    upload_target: None,
+                    ffmpeg_path: None,
+                    shortener_url: None,
+                    shortener_api_key: None,
+                    filemoon_monthly_quota_gb: None,
+                    b2_monthly_quota_gb: None,
+                    preferred_format: None,
+                    format_fallback_ladder: None,
+                    telegram_bot_token: None,
+                    review_before_upload: None,
+                    download_stall_minutes: None,
+                    download_timeout_minutes: None,
+                    upload_timeout_minutes: None,
+                    subtitle_languages: None,
+                    monthly_data_cap_gb: None,
+                    delete_grace_days: None,
+                    hook_after_download: None,
+                    hook_after_upload: None,
+                    hook_on_failure: None,
+                    min_free_space_gb: None,
+                    progress_persistence: None,
+                    provider_http_headers: None,
+                    filemoon_api_base: None,
+                    filemoon_site_base: None,
+                    filemoon_upload_server_url: None,
+                    capture_page: None,
+                    download_window_start: None,
+                    download_window_end: None,
+                    download_window_days: None,
+                    subtitle_auto_captions: None,
+                    cookies_file: None,
+                    cookies_from_browser: None,
+                    media_library_dir: None,
+                    missing_ffmpeg_action: None,
+                    archive_org_access_key: None,
+                    archive_org_secret_key: None,
+                    archive_org_collection: None,
+                    s3_endpoint: None,
+                    s3_bucket: None,
+                    s3_region: None,
+                    s3_access_key: None,
+                    s3_secret_key: None,
+                    reuse_existing_uploads: None,
+                    auto_upload_max_mb: None,
+                    auto_upload_tags: None,
+                    auto_upload_sites: None,
+                    upload_window_start: None,
+                    upload_window_end: None,
+                    upload_window_days: None,
+                    performance_mode: None,
+                    process_priority: None,
+                    transcode_target: None,
+                    transcode_crf: None,
+                    transcode_max_height: None,
+                    adaptive_concurrency: None,
+                    max_concurrent_downloads: None,
+                    max_concurrent_uploads: None,
+                    ytdlp_path: None,
+                    desktop_notifications: None,
+                    clipboard_watch: None,
+                    clipboard_sites: None,
+                    webhook_url: None,
+                    webhook_secret: None,
+                    local_api_enabled: None,
+                    local_api_port: None,
+                    local_api_token: None,
+                    local_api_lan: None,
+                    max_auto_retries: None,
+                }),
+            })
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn save_settings(
+    settings: AppSettings,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    hooks::validate(&settings)?;
+    http::validate(&settings)?;
+    download_window::validate(&settings)?;
+    cookies::validate(&settings)?;
+    media_library::validate(&settings)?;
+    s3::validate(&settings)?;
+    auto_upload::validate(&settings)?;
+    performance::validate(&settings)?;
+    transcode::validate(&settings)?;
+    formats::validate(&settings)?;
+    progress::validate(&settings)?;
+    concurrency::validate(&settings)?;
+    clipboard::validate(&settings)?;
+    webhooks::validate(&settings)?;
+    local_api::validate(&settings)?;
+    retry_policy::validate(&settings)?;
+    app_state
+        .providers
+        .check_target(settings.upload_target.as_deref())?;
+    match app_state.db.save_settings(&settings, &user_id).await {
+        Ok(_) => {
+            // Apply the effective settings, which may inherit machine-wide values, and
+            // tell the background workers (see settings_watch.rs)
+            settings_watch::apply(&app_state, Some(&user_id)).await?;
+            Ok(Response {
+                success: true,
+                message: "Settings saved successfully".to_string(),
+                data: None,
+            })
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Machine-wide defaults inherited by every user that hasn't overridden a field
+#[tauri::command]
+pub async fn get_global_settings(
+    app_state: State<'_, AppState>,
+) -> Result<Response<AppSettings>, String> {
+    match app_state.db.get_global_settings().await {
+        Ok(settings) => Ok(Response {
+            success: true,
+            message: "Global settings retrieved successfully".to_string(),
+            data: Some(settings),
+        }),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+async fn require_admin(app_state: &AppState, user_id: &str) -> Result<(), String> {
+    match app_state.db.is_admin(user_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err("Only an admin can change the global settings".to_string()),
+        Err(e) => Err(format!("Database error checking admin rights: {}", e)),
+    }
+}
+
+// Only admins may change these, as they apply to every user (hooks run commands)
+#[tauri::command]
+pub async fn save_global_settings(
+    settings: AppSettings,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    require_admin(&app_state, &user_id).await?;
+    hooks::validate(&settings)?;
+    http::validate(&settings)?;
+    download_window::validate(&settings)?;
+    cookies::validate(&settings)?;
+    media_library::validate(&settings)?;
+    s3::validate(&settings)?;
+    auto_upload::validate(&settings)?;
+    performance::validate(&settings)?;
+    transcode::validate(&settings)?;
+    formats::validate(&settings)?;
+    progress::validate(&settings)?;
+    concurrency::validate(&settings)?;
+    clipboard::validate(&settings)?;
+    webhooks::validate(&settings)?;
+    local_api::validate(&settings)?;
+    retry_policy::validate(&settings)?;
+    app_state
+        .providers
+        .check_target(settings.upload_target.as_deref())?;
+    match app_state.db.save_global_settings(&settings).await {
+        Ok(_) => {
+            settings_watch::apply(&app_state, None).await?;
+            Ok(Response {
+                success: true,
+                message: "Global settings saved successfully".to_string(),
+                data: None,
+            })
+        }
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+// Let the user's `keys` inherit the global defaults again
+#[tauri::command]
+pub async fn reset_settings(
+    keys: Vec<String>,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    if let Some(key) = keys.iter().find(|key| !db::is_setting_key(key)) {
+        return Err(format!("Unknown setting '{}'", key));
+    }
+    app_state
+        .db
+        .reset_user_settings(&user_id, &keys)
+        .await
+        .map_err(|e| e.to_string())?;
+    settings_watch::apply(&app_state, Some(&user_id)).await?;
+    Ok(Response {
+        success: true,
+        message: format!("{} setting(s) now follow the global defaults", keys.len()),
+        data: None,
+    })
+}
+
+// Grant or take away another user's right to change the global settings
+#[tauri::command]
+pub async fn set_user_admin(
+    target_user_id: String,
+    admin: bool,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    require_admin(&app_state, &user_id).await?;
+    if !admin && target_user_id == user_id {
+        return Err("Admins can't take away their own admin rights".to_string());
+    }
+    match app_state.db.set_admin(&target_user_id, admin).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: format!(
+                "User {} is {} an admin",
+                target_user_id,
+                if admin { "now" } else { "no longer" }
+            ),
+            data: None,
+        }),
+        Ok(false) => Err(format!("User {} not found.", target_user_id)),
+        Err(e) => Err(format!("Database error updating user: {}", e)),
+    }
+}
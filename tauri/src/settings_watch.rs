@@ -0,0 +1,167 @@
+// Puts saved settings into effect straight away. save_settings and
+// save_global_settings call apply(), which reconfigures the process-wide parts (the
+// provider HTTP client, the yt-dlp to run, the concurrency limits), wakes the queue
+// processor and then publishes a new generation on a watch channel. The background
+// workers that sweep on a timer (the upload window watcher, the provider probe, the
+// verified local file cleanup) wait on that channel alongside their timer, so their
+// next sweep runs with the new settings at once instead of up to an interval later.
+// Workers that read settings per item (downloads, hooks, notifications) pick up a
+// change on the next item anyway.
+//
+// The HTTP client and yt-dlp follow the effective settings of the active user: the
+// one whose settings were last loaded or saved, inheriting any machine-wide values
+// they haven't overridden. The concurrency limits follow the machine-wide settings.
+// get_effective_config reports what is in force.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::sleep;
+
+use crate::concurrency::ConcurrencyStatus;
+use crate::db::AppSettings;
+use crate::{filemoon, http, timestamps, ytdlp_manager, AppState};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsChange {
+    // Counts saves since the app started; 0 until the first one
+    pub generation: u64,
+    // Whose settings were saved; None for the machine-wide ones
+    pub user_id: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub changed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpConfig {
+    pub user_agent: String,
+    pub filemoon_api_base: String,
+    pub filemoon_site_base: String,
+    pub filemoon_upload_server_url: String,
+}
+
+// Returned by get_effective_config
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    pub last_change: SettingsChange,
+    pub active_user: Option<String>,
+    // The requested user's settings with the machine-wide values filled in
+    pub settings: AppSettings,
+    pub http: HttpConfig,
+    pub ytdlp_path: String,
+    pub ytdlp_source: String,
+    pub concurrency: ConcurrencyStatus,
+}
+
+pub struct SettingsWatch {
+    sender: watch::Sender<SettingsChange>,
+    active_user: Mutex<Option<String>>,
+}
+
+impl SettingsWatch {
+    pub fn new() -> Self {
+        let (sender, _) = watch::channel(SettingsChange {
+            generation: 0,
+            user_id: None,
+            changed_at: timestamps::now(),
+        });
+        Self {
+            sender,
+            active_user: Mutex::new(None),
+        }
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<SettingsChange> {
+        self.sender.subscribe()
+    }
+
+    pub fn last_change(&self) -> SettingsChange {
+        self.sender.borrow().clone()
+    }
+
+    pub fn set_active_user(&self, user_id: &str) {
+        *self.active_user.lock().unwrap() = Some(user_id.to_string());
+    }
+
+    pub fn active_user(&self) -> Option<String> {
+        self.active_user.lock().unwrap().clone()
+    }
+
+    fn publish(&self, user_id: Option<&str>) -> SettingsChange {
+        // send_modify also succeeds while no worker is subscribed
+        self.sender.send_modify(|change| {
+            change.generation += 1;
+            change.user_id = user_id.map(str::to_string);
+            change.changed_at = timestamps::now();
+        });
+        self.last_change()
+    }
+}
+
+// Apply the settings just saved for `user_id`, or the machine-wide ones with None,
+// and tell the workers
+pub async fn apply(app_state: &AppState, user_id: Option<&str>) -> Result<SettingsChange, String> {
+    if let Some(user_id) = user_id {
+        app_state.settings_watch.set_active_user(user_id);
+    }
+
+    let global = app_state
+        .db
+        .get_global_settings()
+        .await
+        .map_err(|e| format!("Failed to load global settings: {}", e))?;
+    app_state.concurrency.configure(&global);
+
+    let effective = match app_state.settings_watch.active_user() {
+        Some(user_id) => app_state.db.get_settings(&user_id).await.unwrap_or(global),
+        None => global,
+    };
+    http::configure(&effective)?;
+    ytdlp_manager::configure(&effective);
+
+    // A new download window, directory or limit may let queued items start
+    app_state.queue_wakeup.wake();
+    Ok(app_state.settings_watch.publish(user_id))
+}
+
+// Wait for the next settings change, or at most `timeout`
+pub async fn wait(changes: &mut watch::Receiver<SettingsChange>, timeout: Duration) {
+    tokio::select! {
+        changed = changes.changed() => {
+            // Only when the app is shutting down; keep to the timer
+            if changed.is_err() {
+                sleep(timeout).await;
+            }
+        }
+        _ = sleep(timeout) => {}
+    }
+}
+
+pub async fn effective_config(
+    app_state: &AppState,
+    user_id: &str,
+) -> Result<EffectiveConfig, String> {
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let (ytdlp_path, ytdlp_source) = ytdlp_manager::resolve();
+
+    Ok(EffectiveConfig {
+        last_change: app_state.settings_watch.last_change(),
+        active_user: app_state.settings_watch.active_user(),
+        settings,
+        http: HttpConfig {
+            user_agent: http::USER_AGENT.to_string(),
+            filemoon_api_base: filemoon::api_base(),
+            filemoon_site_base: filemoon::site_base(),
+            filemoon_upload_server_url: filemoon::upload_server_endpoint(),
+        },
+        ytdlp_path,
+        ytdlp_source: ytdlp_source.to_string(),
+        concurrency: app_state.concurrency.status(),
+    })
+}
@@ -0,0 +1,220 @@
+// Expiring share tokens for single items. A token reads "<item id>.<expiry>.<signature>",
+// with the expiry in Unix seconds and the signature an HMAC-SHA256 over the first two
+// parts, keyed with a secret kept in the app data directory. Tokens can't be forged or
+// extended and nothing is stored per token. Resolving one yields only that item's player
+// links and a little metadata, never anything else in the library. Recipients open a
+// token through the local API (see local_api.rs), which checks it on every request.
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+use crate::db::QueueItem;
+use crate::{filemoon, timestamps};
+
+// Where the local API serves a token, as a page and as JSON
+pub const PAGE_PREFIX: &str = "/share/";
+pub const JSON_PREFIX: &str = "/api/share/";
+
+pub const DEFAULT_TTL_HOURS: i64 = 72;
+pub const MAX_TTL_HOURS: i64 = 90 * 24;
+
+const SECRET_FILE_NAME: &str = "share_secret";
+const HMAC_BLOCK_SIZE: usize = 64;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareToken {
+    pub token: String,
+    pub item_id: String,
+    #[serde(with = "timestamps::iso8601")]
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SharedItem {
+    pub item_id: String,
+    pub title: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub embed_url: String,
+    pub player_url: String,
+    pub short_url: Option<String>,
+    #[serde(with = "timestamps::iso8601")]
+    pub expires_at: DateTime<Utc>,
+}
+
+fn secret_path(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(SECRET_FILE_NAME)
+}
+
+// The signing secret, created on first use. Deleting the file invalidates every token.
+pub fn load_secret(app_data_dir: &Path) -> Result<Vec<u8>, String> {
+    let path = secret_path(app_data_dir);
+    if let Ok(secret) = fs::read_to_string(&path) {
+        let secret = secret.trim();
+        if !secret.is_empty() {
+            return Ok(secret.as_bytes().to_vec());
+        }
+    }
+
+    let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    fs::create_dir_all(app_data_dir)
+        .map_err(|e| format!("Failed to create app data directory: {}", e))?;
+    fs::write(&path, &secret).map_err(|e| format!("Failed to save share secret: {}", e))?;
+    Ok(secret.into_bytes())
+}
+
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut block = [0u8; HMAC_BLOCK_SIZE];
+    if key.len() > HMAC_BLOCK_SIZE {
+        let hashed = Sha256::digest(key);
+        block[..hashed.len()].copy_from_slice(&hashed);
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+
+    let mut inner = Sha256::new();
+    inner.update(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    inner.update(message);
+    let mut outer = Sha256::new();
+    outer.update(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(inner.finalize());
+    outer.finalize().to_vec()
+}
+
+pub fn signature(secret: &[u8], payload: &str) -> String {
+    hmac_sha256(secret, payload.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+// Compare without stopping at the first difference, so timing reveals nothing
+pub fn same_signature(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+pub fn create(secret: &[u8], item_id: &str, ttl_hours: Option<i64>) -> Result<ShareToken, String> {
+    let ttl_hours = ttl_hours.unwrap_or(DEFAULT_TTL_HOURS);
+    if ttl_hours <= 0 || ttl_hours > MAX_TTL_HOURS {
+        return Err(format!(
+            "Share links can last between 1 and {} hours",
+            MAX_TTL_HOURS
+        ));
+    }
+    let expires_at = timestamps::now() + ChronoDuration::hours(ttl_hours);
+    let payload = format!("{}.{}", item_id, expires_at.timestamp());
+    Ok(ShareToken {
+        token: format!("{}.{}", payload, signature(secret, &payload)),
+        item_id: item_id.to_string(),
+        expires_at,
+    })
+}
+
+// The item id and expiry of a valid, unexpired token
+pub fn verify(secret: &[u8], token: &str) -> Result<(String, DateTime<Utc>), String> {
+    let invalid = || "Invalid share token".to_string();
+    let (payload, given) = token.trim().rsplit_once('.').ok_or_else(invalid)?;
+    if !same_signature(&signature(secret, payload), given) {
+        return Err(invalid());
+    }
+    let (item_id, expiry) = payload.rsplit_once('.').ok_or_else(invalid)?;
+    let expires_at = expiry
+        .parse::<i64>()
+        .ok()
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+        .ok_or_else(invalid)?;
+    if timestamps::now() >= expires_at {
+        return Err(format!(
+            "Share link expired at {}",
+            timestamps::to_iso(&expires_at)
+        ));
+    }
+    Ok((item_id.to_string(), expires_at))
+}
+
+pub fn shared_item(item: &QueueItem, expires_at: DateTime<Utc>) -> Result<SharedItem, String> {
+    let filecode = item
+        .filemoon_url
+        .as_deref()
+        .filter(|code| !code.is_empty())
+        .ok_or_else(|| {
+            "This item has not been uploaded, so there is nothing to share".to_string()
+        })?;
+    Ok(SharedItem {
+        item_id: item.id.clone().unwrap_or_default(),
+        title: item.title.clone(),
+        thumbnail_url: item.thumbnail_url.clone(),
+        embed_url: filemoon::embed_url(filecode),
+        player_url: filemoon::player_url(filecode),
+        short_url: item.short_url.clone(),
+        expires_at,
+    })
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// The page the local API serves for a valid token, with the player
+pub fn share_page(shared: &SharedItem) -> String {
+    let title = escape_html(shared.title.as_deref().unwrap_or("Shared video"));
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<meta name="robots" content="noindex">
+<title>{title}</title>
+</head>
+<body>
+<h1>{title}</h1>
+<iframe src="{embed}" width="640" height="360" frameborder="0" scrolling="no" allowfullscreen></iframe>
+<p><a href="{player}">Open on Filemoon</a></p>
+<p>This link expires at {expires}.</p>
+</body>
+</html>
+"#,
+        title = title,
+        embed = escape_html(&shared.embed_url),
+        player = escape_html(&shared.player_url),
+        expires = timestamps::to_iso(&shared.expires_at),
+    )
+}
+
+// A standalone file for the recipient. It only points at the local API's share page,
+// which checks the token on every visit, so the player links never leave the app
+// and stop working once the token expires.
+pub fn link_file_html(shared: &SharedItem, share_url: &str) -> String {
+    let title = escape_html(shared.title.as_deref().unwrap_or("Shared video"));
+    let url = escape_html(share_url);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="0; url={url}">
+<title>{title}</title>
+</head>
+<body>
+<h1>{title}</h1>
+<p><a href="{url}">Watch the video</a> (until {expires})</p>
+</body>
+</html>
+"#,
+        title = title,
+        url = url,
+        expires = timestamps::to_iso(&shared.expires_at),
+    )
+}
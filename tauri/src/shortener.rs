@@ -0,0 +1,51 @@
+// Optional short links for archived items, created through a Shlink instance
+// (self-hosted or hosted) configured in settings.
+
+use serde_json::{json, Value as JsonValue};
+
+use crate::http;
+
+// The shortener is only used when both the instance URL and the API key are set
+pub fn is_configured(base_url: Option<&str>, api_key: Option<&str>) -> bool {
+    base_url.map_or(false, |u| !u.trim().is_empty())
+        && api_key.map_or(false, |k| !k.trim().is_empty())
+}
+
+// Create (or reuse) a short URL for `long_url`. `findIfExists` makes Shlink return the
+// existing short URL for the same target, so the link stays stable across retries.
+pub async fn shorten(base_url: &str, api_key: &str, long_url: &str) -> Result<String, String> {
+    let endpoint = format!(
+        "{}/rest/v3/short-urls",
+        base_url.trim().trim_end_matches('/')
+    );
+
+    let response = http::client()
+        .post(&endpoint)
+        .header("X-Api-Key", api_key.trim())
+        .json(&json!({ "longUrl": long_url, "findIfExists": true }))
+        .send()
+        .await
+        .map_err(|e| format!("Short link request failed: {}", e))?;
+
+    let status = response.status();
+    let body: JsonValue = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse short link response: {}", e))?;
+
+    if !status.is_success() {
+        let detail = body
+            .get("detail")
+            .and_then(|d| d.as_str())
+            .unwrap_or("unknown error");
+        return Err(format!(
+            "Short link API error (Status {}): {}",
+            status, detail
+        ));
+    }
+
+    body.get("shortUrl")
+        .and_then(|u| u.as_str())
+        .map(String::from)
+        .ok_or_else(|| "Short link response did not contain a shortUrl".to_string())
+}
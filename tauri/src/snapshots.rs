@@ -0,0 +1,181 @@
+// Time-stamped local snapshots of the queue, settings, global settings and uploads
+// tables.
+// Snapshots are gzip-compressed JSON files in the app data directory and
+// protect against accidental bulk deletes or a lost Neon branch.
+
+use chrono::Utc;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::db::{Database, GlobalSettingRow, QueueItem, SettingRow, UploadRow};
+
+const SNAPSHOT_DIR_NAME: &str = "snapshots";
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+const SNAPSHOT_EXTENSION: &str = ".json.gz";
+// 2 added the global settings and uploads; format 1 snapshots restore without them
+const SNAPSHOT_FORMAT_VERSION: u32 = 2;
+
+// How many snapshots to keep before the oldest are pruned
+pub const SNAPSHOT_KEEP_COUNT: usize = 10;
+
+// How often the background task takes a snapshot
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotData {
+    pub version: u32,
+    pub created_at: String,
+    pub queue: Vec<QueueItem>,
+    pub settings: Vec<SettingRow>,
+    #[serde(default)]
+    pub global_settings: Vec<GlobalSettingRow>,
+    #[serde(default)]
+    pub uploads: Vec<UploadRow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SnapshotInfo {
+    pub file_name: String,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+pub fn snapshot_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(SNAPSHOT_DIR_NAME)
+}
+
+// Export the database into a new snapshot file and prune old ones
+pub async fn create_snapshot(db: &Database, dir: &Path) -> Result<SnapshotInfo, String> {
+    let queue = db
+        .get_all_queue_items()
+        .await
+        .map_err(|e| format!("Failed to read queue for snapshot: {}", e))?;
+    let settings = db
+        .get_all_settings_rows()
+        .await
+        .map_err(|e| format!("Failed to read settings for snapshot: {}", e))?;
+    let global_settings = db
+        .get_all_global_settings_rows()
+        .await
+        .map_err(|e| format!("Failed to read global settings for snapshot: {}", e))?;
+    let uploads = db
+        .get_all_upload_rows()
+        .await
+        .map_err(|e| format!("Failed to read uploads for snapshot: {}", e))?;
+
+    let now = Utc::now();
+    let data = SnapshotData {
+        version: SNAPSHOT_FORMAT_VERSION,
+        created_at: now.to_rfc3339(),
+        queue,
+        settings,
+        global_settings,
+        uploads,
+    };
+
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create snapshot directory: {}", e))?;
+    let file_name = format!(
+        "{}{}{}",
+        SNAPSHOT_PREFIX,
+        now.format("%Y%m%d-%H%M%S"),
+        SNAPSHOT_EXTENSION
+    );
+    let path = dir.join(&file_name);
+
+    let json =
+        serde_json::to_vec(&data).map_err(|e| format!("Failed to encode snapshot: {}", e))?;
+    let file = File::create(&path).map_err(|e| format!("Failed to create snapshot file: {}", e))?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder
+        .write_all(&json)
+        .map_err(|e| format!("Failed to write snapshot file: {}", e))?;
+    encoder
+        .finish()
+        .map_err(|e| format!("Failed to finish snapshot file: {}", e))?;
+
+    println!(
+        "Wrote database snapshot {} ({} queue items, {} settings, {} uploads)",
+        file_name,
+        data.queue.len(),
+        data.settings.len(),
+        data.uploads.len()
+    );
+
+    prune_snapshots(dir, SNAPSHOT_KEEP_COUNT);
+
+    let size_bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    Ok(SnapshotInfo {
+        file_name,
+        path: path.to_string_lossy().to_string(),
+        size_bytes,
+    })
+}
+
+// Snapshots in the directory, newest first. File names sort chronologically.
+pub fn list_snapshots(dir: &Path) -> Vec<SnapshotInfo> {
+    let mut snapshots: Vec<SnapshotInfo> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .filter_map(|entry| {
+                let file_name = entry.file_name().to_string_lossy().to_string();
+                if !file_name.starts_with(SNAPSHOT_PREFIX)
+                    || !file_name.ends_with(SNAPSHOT_EXTENSION)
+                {
+                    return None;
+                }
+                Some(SnapshotInfo {
+                    size_bytes: entry.metadata().map(|m| m.len()).unwrap_or(0),
+                    path: entry.path().to_string_lossy().to_string(),
+                    file_name,
+                })
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    snapshots.sort_by(|a, b| b.file_name.cmp(&a.file_name));
+    snapshots
+}
+
+fn prune_snapshots(dir: &Path, keep: usize) {
+    for old in list_snapshots(dir).into_iter().skip(keep) {
+        match fs::remove_file(&old.path) {
+            Ok(_) => println!("Pruned old snapshot {}", old.file_name),
+            Err(e) => eprintln!("Failed to prune snapshot {}: {}", old.file_name, e),
+        }
+    }
+}
+
+pub fn read_snapshot(dir: &Path, file_name: &str) -> Result<SnapshotData, String> {
+    // Only accept bare snapshot file names so the command can't read arbitrary paths
+    if file_name.contains('/')
+        || file_name.contains('\\')
+        || !file_name.starts_with(SNAPSHOT_PREFIX)
+    {
+        return Err(format!("Invalid snapshot name: {}", file_name));
+    }
+
+    let file = File::open(dir.join(file_name))
+        .map_err(|e| format!("Failed to open snapshot {}: {}", file_name, e))?;
+    let mut json = String::new();
+    GzDecoder::new(file)
+        .read_to_string(&mut json)
+        .map_err(|e| format!("Failed to decompress snapshot {}: {}", file_name, e))?;
+
+    let data: SnapshotData = serde_json::from_str(&json)
+        .map_err(|e| format!("Failed to parse snapshot {}: {}", file_name, e))?;
+    if data.version > SNAPSHOT_FORMAT_VERSION {
+        return Err(format!(
+            "Snapshot {} was written by a newer version (format {})",
+            file_name, data.version
+        ));
+    }
+
+    Ok(data)
+}
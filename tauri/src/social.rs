@@ -0,0 +1,75 @@
+// Quirks of short-video sites (X/Twitter and TikTok). Their videos are mostly vertical
+// and come as single files with audio, so the default ladder's 720p height cap and
+// separate audio stream do not fit them. Their metadata also often lacks a usable
+// title, in which case the post text stands in for it.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value as JsonValue;
+
+use crate::titles::clean_text;
+use crate::urls;
+
+pub const SITE_TWITTER: &str = "twitter";
+pub const SITE_TIKTOK: &str = "tiktok";
+
+// Formats tried when neither a preferred format nor a fallback ladder is configured
+const TWITTER_FORMATS: &[&str] = &["best[ext=mp4]/best", "best"];
+// Watermark-free H.264 plays everywhere; H.265 variants are only a fallback
+const TIKTOK_FORMATS: &[&str] = &["best[vcodec^=h264]/best[ext=mp4]/best", "best"];
+
+lazy_static! {
+    // Placeholders yt-dlp uses when a post has no text, e.g. "TikTok video #7301234567890123456"
+    static ref PLACEHOLDER_TITLE_REGEX: Regex =
+        Regex::new(r"^(?:(?:TikTok|Twitter|X) video #?\d*|.+ -)$").unwrap();
+}
+
+// Which short-video site a URL belongs to, if any
+pub fn site(url: &str) -> Option<&'static str> {
+    let key = urls::video_key(url)?;
+    [SITE_TWITTER, SITE_TIKTOK]
+        .into_iter()
+        .find(|site| key.starts_with(&format!("{}:", site)))
+}
+
+// The default fallback ladder for a URL, replacing formats::DEFAULT_FALLBACK_LADDER
+pub fn default_formats(url: &str) -> Option<&'static [&'static str]> {
+    match site(url)? {
+        SITE_TWITTER => Some(TWITTER_FORMATS),
+        SITE_TIKTOK => Some(TIKTOK_FORMATS),
+        _ => None,
+    }
+}
+
+// The title to record for a download. Short-video posts with an empty or placeholder
+// title are named after their text (tweet text, TikTok caption) instead.
+pub fn title_from_info(url: &str, info: &JsonValue) -> Option<String> {
+    let title = info
+        .get("title")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    if site(url).is_none() {
+        return title.map(String::from);
+    }
+    if let Some(title) = title.filter(|t| !PLACEHOLDER_TITLE_REGEX.is_match(t)) {
+        return Some(clean_text(title));
+    }
+
+    let text = info
+        .get("description")
+        .and_then(|v| v.as_str())
+        .map(clean_text)
+        .filter(|t| !t.is_empty());
+    let uploader = info
+        .get("uploader")
+        .or_else(|| info.get("uploader_id"))
+        .and_then(|v| v.as_str())
+        .filter(|u| !u.is_empty());
+    match (text, uploader) {
+        (Some(text), Some(uploader)) => Some(format!("{} - {}", uploader, text)),
+        (Some(text), None) => Some(text),
+        // Nothing better to go on; keep yt-dlp's placeholder
+        (None, _) => title.map(String::from),
+    }
+}
@@ -0,0 +1,240 @@
+// What the app found when it started, so the UI can show a "ready" state with
+// something behind it instead of a spinner while the background loops come up. The
+// report covers the database (reachable, newest migration), the items put back after
+// the last session ended mid-work, the retries and offline writes still waiting, and
+// the versions of the app, yt-dlp and ffmpeg. It is logged, emitted once as the
+// `startup_report` event and kept for get_startup_report, since the window may start
+// listening only after the event went out.
+//
+// The primary instance first applies any pending schema migrations (see
+// migrations.rs) and moves API keys still stored in plaintext into the OS keychain
+// (see secrets.rs). Recovery runs on it before its queue processor starts: an item
+// still "downloading" or "transcoding" belongs to no running process and is queued
+// again, and one still "uploading" goes back to "downloaded" and into the upload
+// queue. The primary also installs the managed yt-dlp when none is found.
+
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Instant;
+use tauri::{AppHandle, Manager};
+
+use crate::instance::InstanceRole;
+use crate::messages::{self, ItemMessage};
+use crate::tools::{self, ToolStatus};
+use crate::{ytdlp_manager, AppState};
+
+pub const EVENT: &str = "startup_report";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StartupReport {
+    pub app_version: String,
+    pub role: InstanceRole,
+    pub database_ok: bool,
+    pub database_message: Option<String>,
+    // Newest applied migration, e.g. "20261020090000_add_uploads"
+    pub schema_version: Option<String>,
+    // Migrations this launch applied
+    pub migrations_applied: Vec<String>,
+    // Interrupted downloads queued again
+    pub downloads_requeued: usize,
+    // Interrupted uploads queued again
+    pub uploads_requeued: usize,
+    // Queued items that failed before and will be retried
+    pub retries_pending: i64,
+    // Status writes and new items kept while the database was unreachable
+    pub offline_writes_pending: usize,
+    pub tools: Vec<ToolStatus>,
+    pub elapsed_ms: u64,
+}
+
+// The report of this launch, once it is ready
+#[derive(Default)]
+pub struct LastReport(Mutex<Option<StartupReport>>);
+
+impl LastReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self) -> Option<StartupReport> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, report: StartupReport) {
+        *self.0.lock().unwrap() = Some(report);
+    }
+}
+
+// Put back the items the last session left mid-download or mid-upload. Returns how
+// many downloads and uploads were queued again.
+async fn recover_interrupted(app_state: &AppState) -> (usize, usize) {
+    let items = match app_state
+        .db
+        .get_items_in_statuses(&["downloading", "transcoding", "uploading"])
+        .await
+    {
+        Ok(items) => items,
+        Err(e) => {
+            eprintln!("Startup: could not look for interrupted items: {}", e);
+            return (0, 0);
+        }
+    };
+
+    let (mut downloads, mut uploads) = (0, 0);
+    for item in items {
+        let Some(item_id) = item.id.as_deref() else {
+            continue;
+        };
+        if item.status != "uploading" {
+            match app_state
+                .db
+                .update_item_status(
+                    item_id,
+                    "queued",
+                    Some(ItemMessage::new(messages::DOWNLOAD_INTERRUPTED)),
+                )
+                .await
+            {
+                Ok(_) => downloads += 1,
+                Err(e) => eprintln!("Startup: failed to requeue item {}: {}", item_id, e),
+            }
+            continue;
+        }
+
+        if let Err(e) = app_state
+            .db
+            .update_item_status(
+                item_id,
+                "downloaded",
+                Some(ItemMessage::new(messages::UPLOAD_INTERRUPTED)),
+            )
+            .await
+        {
+            eprintln!("Startup: failed to reset upload of item {}: {}", item_id, e);
+            continue;
+        }
+        let user_id = item.user_id.clone().unwrap_or_default();
+        match app_state.uploads.enqueue(item_id.to_string(), user_id) {
+            Ok(()) => uploads += 1,
+            Err(e) => eprintln!("Startup: failed to requeue upload of {}: {}", item_id, e),
+        }
+    }
+    (downloads, uploads)
+}
+
+// Build, log, emit and keep this launch's report. `recover` is set on the primary
+// instance, which is the only one that puts interrupted items back.
+pub async fn run(app_handle: AppHandle, recover: bool) {
+    let started = Instant::now();
+    let app_state = app_handle.state::<AppState>();
+
+    let (migrations_applied, migration_error) = if recover {
+        match app_state.db.migrate().await {
+            Ok(applied) => (applied, None),
+            Err(e) => (
+                Vec::new(),
+                Some(format!("Database migrations failed: {}", e)),
+            ),
+        }
+    } else {
+        (Vec::new(), None)
+    };
+    let (database_ok, database_message, schema_version) = match app_state.db.schema_version().await
+    {
+        Ok(version) => (true, migration_error, version),
+        Err(e) => (false, Some(format!("Database unreachable: {}", e)), None),
+    };
+
+    if recover && database_ok {
+        match app_state.db.secure_stored_secrets().await {
+            Ok(0) => {}
+            Ok(secured) => println!(
+                "Startup: moved the secrets of {} settings row(s) into the keychain",
+                secured
+            ),
+            Err(e) => eprintln!("Startup: could not secure stored secrets: {}", e),
+        }
+    }
+
+    let (downloads_requeued, uploads_requeued) = if recover && database_ok {
+        recover_interrupted(&app_state).await
+    } else {
+        (0, 0)
+    };
+    let retries_pending = if database_ok {
+        app_state
+            .db
+            .count_queued_retries()
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Startup: could not count pending retries: {}", e);
+                0
+            })
+    } else {
+        0
+    };
+
+    let global = app_state.db.get_global_settings().await.ok();
+    if let Some(settings) = &global {
+        ytdlp_manager::configure(settings);
+    }
+    if recover {
+        ytdlp_manager::ensure_installed().await;
+    }
+    let ffmpeg_path = global.and_then(|settings| settings.ffmpeg_path);
+    let tools = vec![
+        tools::detect_ytdlp().await,
+        tools::detect_ffmpeg(ffmpeg_path.as_deref()).await,
+    ];
+
+    let report = StartupReport {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        role: app_state.instance.role(),
+        database_ok,
+        database_message,
+        schema_version,
+        migrations_applied,
+        downloads_requeued,
+        uploads_requeued,
+        retries_pending,
+        offline_writes_pending: app_state.db.pending_write_count(),
+        tools,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    };
+    log(&report);
+
+    app_state.startup.set(report.clone());
+    if let Err(e) = app_handle.emit_all(EVENT, report) {
+        eprintln!("Error emitting {} event: {}", EVENT, e);
+    }
+}
+
+fn log(report: &StartupReport) {
+    println!(
+        "Startup: PermaVid {} ({:?} instance), checks took {} ms",
+        report.app_version, report.role, report.elapsed_ms
+    );
+    match &report.database_message {
+        Some(message) => println!("Startup: {}", message),
+        None => println!(
+            "Startup: database connected, schema {} ({} migration(s) applied now)",
+            report.schema_version.as_deref().unwrap_or("unknown"),
+            report.migrations_applied.len()
+        ),
+    }
+    println!(
+        "Startup: {} download(s) and {} upload(s) requeued after an interruption, \
+         {} item(s) waiting to be retried, {} offline write(s) pending",
+        report.downloads_requeued,
+        report.uploads_requeued,
+        report.retries_pending,
+        report.offline_writes_pending
+    );
+    for tool in &report.tools {
+        println!(
+            "Startup: {} {}",
+            tool.name,
+            tool.version.as_deref().unwrap_or("not found")
+        );
+    }
+}
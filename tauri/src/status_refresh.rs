@@ -0,0 +1,276 @@
+// Bulk re-poll of provider status for items still in flight on Filemoon, used to
+// catch up after the app has been closed for a while.
+
+use crate::db::QueueItem;
+use crate::messages::{self, ItemMessage};
+use crate::filemoon::{self, FilemoonFileInfoResponse};
+use crate::queue_commands::sync_thumbnail;
+use crate::upload::record_provider_error;
+use crate::{http, jobs, AppState};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+pub const REFRESH_STATUSES: &[&str] = &["transferring", "encoding", "encoded"];
+
+// file/info accepts a comma separated list of file codes
+const BATCH_SIZE: usize = 50;
+// Pause between batch requests to stay under the API rate limit
+const BATCH_DELAY: Duration = Duration::from_millis(1000);
+// Retries for a batch answered with HTTP 429, with doubling delay
+const RATE_LIMIT_RETRIES: u32 = 3;
+const RATE_LIMIT_BASE_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct StatusChange {
+    pub item_id: String,
+    pub title: Option<String>,
+    pub old_status: String,
+    pub new_status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct RefreshSummary {
+    pub checked: usize,
+    pub changed: usize,
+    pub unchanged: usize,
+    pub skipped: usize,
+    pub changes: Vec<StatusChange>,
+    pub errors: Vec<String>,
+}
+
+// Fetch file/info for one batch of file codes, backing off when rate limited
+pub async fn fetch_file_info(
+    app_state: &AppState,
+    client: &reqwest::Client,
+    api_key: &str,
+    filecodes: &[String],
+) -> Result<FilemoonFileInfoResponse, String> {
+    let joined = filecodes.join(",");
+    let mut attempt = 0;
+
+    loop {
+        let response = client
+            .get(format!("{}/file/info", filemoon::api_base()))
+            .query(&[("key", api_key), ("file_code", joined.as_str())])
+            .send()
+            .await
+            .map_err(|e| format!("Filemoon file/info request failed: {}", e))?;
+
+        let status = response.status();
+        if status == reqwest::StatusCode::TOO_MANY_REQUESTS && attempt < RATE_LIMIT_RETRIES {
+            let delay = RATE_LIMIT_BASE_DELAY * 2u32.pow(attempt);
+            println!(
+                "Filemoon rate limit hit during status refresh, retrying in {:?}",
+                delay
+            );
+            sleep(delay).await;
+            attempt += 1;
+            continue;
+        }
+
+        let raw_text = response
+            .text()
+            .await
+            .map_err(|e| format!("Failed to read Filemoon file/info response: {}", e))?;
+        let error = match serde_json::from_str::<FilemoonFileInfoResponse>(&raw_text) {
+            Ok(body) if filemoon::is_ok_response(status.as_u16(), body.status) => return Ok(body),
+            Ok(body) => format!(
+                "Filemoon file/info API Error (HTTP {}, API Status {:?}): {}",
+                status, body.status, body.msg
+            ),
+            Err(e) => format!("Failed to parse Filemoon file/info response: {}", e),
+        };
+        record_provider_error(
+            app_state,
+            None,
+            "file/info",
+            Some(status.as_u16()),
+            &error,
+            Some(&raw_text),
+        )
+        .await;
+        // A 2xx we couldn't read isn't an error answer: report no entries, which
+        // leaves every item in the batch as it is until the next refresh
+        if status.is_success() {
+            return Ok(FilemoonFileInfoResponse {
+                status: None,
+                msg: error,
+                result: None,
+            });
+        }
+        return Err(error);
+    }
+}
+
+// Filemoon reports these for a file that was deleted or never existed; any other
+// non-200 status only means the file isn't ready yet
+const FILE_GONE_STATUSES: [u16; 2] = [404, 410];
+
+// Status an item should move to given its file/info entry, with progress and
+// message; None leaves the item as it is
+fn resolve_status(
+    file_status: u16,
+    canplay: Option<i32>,
+) -> Option<(&'static str, Option<i32>, ItemMessage)> {
+    if file_status == 200 && canplay == Some(1) {
+        Some(("encoded", Some(100), ItemMessage::new(messages::ENCODING_READY)))
+    } else if file_status == 200 {
+        Some((
+            "encoding",
+            None,
+            ItemMessage::new(messages::ENCODING_IN_PROGRESS).with("canplay", canplay),
+        ))
+    } else if FILE_GONE_STATUSES.contains(&file_status) {
+        Some((
+            "failed",
+            None,
+            ItemMessage::new(messages::ENCODING_FILE_GONE).with("status", file_status),
+        ))
+    } else {
+        None
+    }
+}
+
+pub async fn refresh_all(app_state: &AppState) -> Result<RefreshSummary, String> {
+    let items = app_state
+        .db
+        .get_items_in_statuses(REFRESH_STATUSES)
+        .await
+        .map_err(|e| format!("Failed to load items for status refresh: {}", e))?;
+
+    let mut summary = RefreshSummary::default();
+    let total = items.len();
+    let job = app_state
+        .jobs
+        .start(jobs::KIND_STATUS_CHECK, None, CancellationToken::new());
+
+    // Group by API key so each batch goes out under the owning user's account
+    let mut api_keys: HashMap<String, Option<String>> = HashMap::new();
+    let mut batches: HashMap<String, Vec<QueueItem>> = HashMap::new();
+    for item in items {
+        let filecode = item.filemoon_url.clone().unwrap_or_default();
+        let user_id = item.user_id.clone().unwrap_or_default();
+        if filecode.is_empty() || user_id.is_empty() {
+            summary.skipped += 1;
+            continue;
+        }
+
+        if !api_keys.contains_key(&user_id) {
+            let key = app_state
+                .db
+                .get_settings(&user_id)
+                .await
+                .ok()
+                .and_then(|s| s.filemoon_api_key)
+                .filter(|k| !k.is_empty());
+            api_keys.insert(user_id.clone(), key);
+        }
+
+        match api_keys.get(&user_id).cloned().flatten() {
+            Some(key) => batches.entry(key).or_default().push(item),
+            None => summary.skipped += 1,
+        }
+    }
+
+    let client = http::client();
+    let mut first_request = true;
+    for (api_key, items) in batches {
+        for chunk in items.chunks(BATCH_SIZE) {
+            if job.is_cancelled() {
+                summary.errors.push("Status refresh cancelled".to_string());
+                return Ok(summary);
+            }
+            if !first_request {
+                sleep(BATCH_DELAY).await;
+            }
+            first_request = false;
+
+            let done = summary.checked + summary.skipped;
+            job.update(
+                Some(done as f32 * 100.0 / total.max(1) as f32),
+                Some(format!("Checked {} of {} items", done, total)),
+            );
+
+            let filecodes: Vec<String> = chunk
+                .iter()
+                .map(|item| item.filemoon_url.clone().unwrap_or_default())
+                .collect();
+            let results = match fetch_file_info(app_state, &client, &api_key, &filecodes).await {
+                Ok(body) => body.result.unwrap_or_default(),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    summary.errors.push(e);
+                    continue;
+                }
+            };
+
+            for item in chunk {
+                let item_id = item.id.clone().unwrap_or_default();
+                let filecode = item.filemoon_url.as_deref().unwrap_or_default();
+                summary.checked += 1;
+
+                // No entry, or one without a status: Filemoon has nothing definite to
+                // say yet, so the item stays where it is
+                let (info, file_status) = match results.iter().find(|r| r.file_code == filecode) {
+                    Some(info) => match info.status {
+                        Some(file_status) => (info, file_status),
+                        None => {
+                            summary.unchanged += 1;
+                            continue;
+                        }
+                    },
+                    None => {
+                        println!(
+                            "Filecode {} for item {} missing from file/info results; left as is",
+                            filecode, item_id
+                        );
+                        summary.unchanged += 1;
+                        continue;
+                    }
+                };
+
+                let (new_status, progress, message) =
+                    match resolve_status(file_status, info.canplay) {
+                        Some(resolved) => resolved,
+                        None => {
+                            summary.unchanged += 1;
+                            continue;
+                        }
+                    };
+                if new_status == item.status {
+                    summary.unchanged += 1;
+                    continue;
+                }
+
+                if let Err(e) = app_state
+                    .db
+                    .update_item_encoding_details(&item_id, new_status, progress, Some(message))
+                    .await
+                {
+                    summary.errors.push(format!(
+                        "Failed to update status for item {}: {}",
+                        item_id, e
+                    ));
+                    continue;
+                }
+
+                if new_status == "encoded" {
+                    sync_thumbnail(app_state, &item_id, &api_key).await;
+                }
+
+                summary.changed += 1;
+                summary.changes.push(StatusChange {
+                    item_id,
+                    title: item.title.clone(),
+                    old_status: item.status.clone(),
+                    new_status: new_status.to_string(),
+                });
+            }
+        }
+    }
+
+    Ok(summary)
+}
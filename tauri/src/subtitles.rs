@@ -0,0 +1,119 @@
+// Subtitle download settings. `subtitle_languages` is a comma-separated list of
+// language codes for yt-dlp to fetch and embed, e.g. "en, original". The special
+// entry "original" means the video's own language: the one captured from an
+// earlier attempt's metadata when known, plus YouTube's "<lang>-orig" auto-caption
+// track, which is always in the spoken language. `subtitle_auto_captions` set to
+// "false" leaves out automatically generated captions.
+//
+// Filemoon's API has no way to attach subtitle files to an upload, so they reach it
+// embedded in the video (--embed-subs). yt-dlp also keeps the files beside the
+// video; their paths are recorded on the item and listed in its provenance
+// manifest, and they stay in the archive when the local video is deleted.
+
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+
+use crate::long_paths;
+
+pub const ORIGINAL_LANGUAGE: &str = "original";
+const ORIGINAL_TRACK_PATTERN: &str = ".*-orig";
+
+// Extensions of the subtitle files yt-dlp writes
+const SUBTITLE_EXTENSIONS: &[&str] = &[
+    "vtt", "srt", "ass", "ssa", "ttml", "srv1", "srv2", "srv3", "json3", "lrc",
+];
+
+// Automatic captions are fetched unless the setting is "false"
+pub fn auto_captions_enabled(setting: Option<&str>) -> bool {
+    setting.map(str::trim) != Some("false")
+}
+
+// Build the --sub-langs value, or None when no subtitles are wanted
+pub fn sub_langs_arg(setting: Option<&str>, item_language: Option<&str>) -> Option<String> {
+    let mut langs: Vec<String> = Vec::new();
+    let mut push = |lang: &str| {
+        if !lang.is_empty() && !langs.iter().any(|l| l == lang) {
+            langs.push(lang.to_string());
+        }
+    };
+
+    for entry in setting.unwrap_or("").split(',').map(str::trim) {
+        if entry.eq_ignore_ascii_case(ORIGINAL_LANGUAGE) {
+            if let Some(lang) = item_language {
+                push(lang);
+            }
+            push(ORIGINAL_TRACK_PATTERN);
+        } else {
+            push(entry);
+        }
+    }
+
+    if langs.is_empty() {
+        None
+    } else {
+        Some(langs.join(","))
+    }
+}
+
+// Video language from a yt-dlp info.json, if the extractor reports one
+pub fn language_from_info(info: &JsonValue) -> Option<String> {
+    info.get("language")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|lang| !lang.is_empty())
+        .map(String::from)
+}
+
+// Languages the site offers automatic captions in, sorted
+pub fn caption_languages_from_info(info: &JsonValue) -> Vec<String> {
+    let mut langs: Vec<String> = info
+        .get("automatic_captions")
+        .and_then(|v| v.as_object())
+        .map(|captions| captions.keys().cloned().collect())
+        .unwrap_or_default();
+    langs.sort();
+    langs
+}
+
+// Subtitle files yt-dlp wrote beside `video_path`: "<video stem>.<lang>.<ext>"
+pub fn find_files(video_path: &Path) -> Vec<String> {
+    let (dir, stem) = match (video_path.parent(), video_path.file_stem()) {
+        (Some(dir), Some(stem)) => (dir, format!("{}.", stem.to_string_lossy())),
+        _ => return Vec::new(),
+    };
+    let entries = match fs::read_dir(long_paths::extended(dir)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut paths: Vec<String> = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string());
+            let ext = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_ascii_lowercase());
+            match (name, ext) {
+                (Some(name), Some(ext)) => {
+                    name.starts_with(&stem) && SUBTITLE_EXTENSIONS.contains(&ext.as_str())
+                }
+                _ => false,
+            }
+        })
+        .map(|path| long_paths::plain(&path))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// "en" for "video.en.vtt"; None when the name carries no language
+pub fn language_of(subtitle_path: &str) -> Option<String> {
+    let name = Path::new(subtitle_path)
+        .file_stem()?
+        .to_string_lossy()
+        .to_string();
+    name.rsplit_once('.')
+        .map(|(_, lang)| lang.to_string())
+        .filter(|lang| !lang.is_empty())
+}
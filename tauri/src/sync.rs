@@ -0,0 +1,93 @@
+// Incremental index sync for companion apps. Items are paged in (updated_at, id)
+// order and the cursor names the last row a client has seen, so a client only
+// ever pulls what changed since its previous sync.
+//
+// Cursor format: "<updated_at as RFC 3339 with microseconds>|<item id>". A bare
+// timestamp is also accepted and means "everything updated after this instant".
+// The web API (/api/archives/changes) uses the same format.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::db::QueueItem;
+use crate::timestamps;
+
+pub const DEFAULT_PAGE_SIZE: i64 = 200;
+pub const MAX_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyncCursor {
+    pub updated_at: DateTime<Utc>,
+    pub id: String,
+}
+
+impl SyncCursor {
+    // None for an empty cursor (first sync), Err for one that can't be read
+    pub fn parse(raw: Option<&str>) -> Result<Option<SyncCursor>, String> {
+        let raw = match raw.map(str::trim) {
+            Some(raw) if !raw.is_empty() => raw,
+            _ => return Ok(None),
+        };
+
+        let (time_part, id) = match raw.split_once('|') {
+            Some((time, id)) => (time, id.to_string()),
+            None => (raw, String::new()),
+        };
+        let updated_at = timestamps::parse(time_part)
+            .ok_or_else(|| format!("Invalid sync cursor '{}'", raw))?;
+
+        Ok(Some(SyncCursor { updated_at, id }))
+    }
+
+    // Microsecond precision matches Postgres, so no row is skipped or repeated
+    pub fn encode(&self) -> String {
+        format!(
+            "{}|{}",
+            self.updated_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.id
+        )
+    }
+
+    fn after_item(item: &QueueItem) -> Option<SyncCursor> {
+        Some(SyncCursor {
+            updated_at: item.updated_at?,
+            id: item.id.clone()?,
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeSet {
+    pub items: Vec<QueueItem>,
+    // Pass back as `cursor` on the next call; unchanged when nothing new was found
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+}
+
+pub fn page_size(requested: Option<i64>) -> i64 {
+    requested
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE)
+}
+
+// Build the response from up to `limit + 1` rows; the extra row only signals more pages
+pub fn change_set(
+    mut items: Vec<QueueItem>,
+    limit: i64,
+    previous: Option<&SyncCursor>,
+) -> ChangeSet {
+    let has_more = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+
+    let next_cursor = items
+        .last()
+        .and_then(SyncCursor::after_item)
+        .or_else(|| previous.cloned())
+        .map(|cursor| cursor.encode());
+
+    ChangeSet {
+        items,
+        next_cursor,
+        has_more,
+    }
+}
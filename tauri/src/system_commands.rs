@@ -0,0 +1,616 @@
+// Commands about the app and this machine rather than single items: diagnostics,
+// yt-dlp, snapshots, the database, instance and worker state, quotas, disk space and
+// notification rules.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::time::Duration;
+use tauri::{Manager, State};
+use tokio::time::sleep;
+
+use crate::bandwidth::{self, BandwidthReport};
+use crate::capabilities::{self, Capabilities};
+use crate::concurrency::ConcurrencyStatus;
+use crate::db::{AppSettings, NotificationRule, ProviderError};
+use crate::disk_space::{self, DiskSpace, DiskUsage};
+use crate::forensics::{self, ForensicBundle};
+use crate::indexes::{self, SlowQueryReport};
+use crate::instance::InstanceRole;
+use crate::jobs::ActiveJob;
+use crate::messages::{self, CatalogEntry};
+use crate::migrations::SchemaInfo;
+use crate::offline::ConnectionStatus;
+use crate::providers::ProviderListing;
+use crate::quota::{self, QuotaReport};
+use crate::settings_watch::{self, EffectiveConfig};
+use crate::startup::StartupReport;
+use crate::tools::{self, ToolStatus};
+use crate::webhooks::{self, WebhookTest};
+use crate::ytdlp_manager::{self, YtdlpVersion};
+use crate::{local_api, notifier, snapshots, AppState, Response};
+
+#[tauri::command]
+pub async fn get_download_directory() -> Result<Response<String>, String> {
+    match dirs::download_dir() {
+        Some(dir) => Ok(Response {
+            success: true,
+            message: "Download directory retrieved successfully".to_string(),
+            data: Some(dir.to_string_lossy().to_string()),
+        }),
+        None => Err("Could not determine download directory".to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn create_directory(path: String) -> Result<Response<()>, String> {
+    match fs::create_dir_all(&path) {
+        Ok(_) => Ok(Response {
+            success: true,
+            message: format!("Directory created at: {}", path),
+            data: None,
+        }),
+        Err(e) => Err(format!("Failed to create directory: {}", e)),
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    database_ok: bool,
+    database_message: Option<String>,
+    tools: Vec<ToolStatus>,
+}
+
+#[tauri::command]
+pub async fn run_diagnostics(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<DiagnosticsReport>, String> {
+    let (database_ok, database_message, settings) = match app_state.db.get_settings(&user_id).await
+    {
+        Ok(settings) => (true, None, settings),
+        Err(e) => (
+            false,
+            Some(format!("Database check failed: {}", e)),
+            AppSettings::default(),
+        ),
+    };
+
+    let ytdlp = tools::detect_ytdlp().await;
+    let ffmpeg = tools::detect_ffmpeg(settings.ffmpeg_path.as_deref()).await;
+
+    let all_ok = database_ok && ytdlp.found && ffmpeg.found;
+    Ok(Response {
+        success: true,
+        message: if all_ok {
+            "All checks passed".to_string()
+        } else {
+            "Some checks failed, see details".to_string()
+        },
+        data: Some(DiagnosticsReport {
+            database_ok,
+            database_message,
+            tools: vec![ytdlp, ffmpeg],
+        }),
+    })
+}
+
+// The yt-dlp in use, the managed copy and the latest release
+#[tauri::command]
+pub async fn check_ytdlp_version() -> Result<Response<YtdlpVersion>, String> {
+    let status = ytdlp_manager::check().await;
+    Ok(Response {
+        success: true,
+        message: status.message.clone().unwrap_or_else(|| {
+            format!(
+                "yt-dlp {} ({})",
+                status.version.as_deref().unwrap_or("unknown"),
+                status.source
+            )
+        }),
+        data: Some(status),
+    })
+}
+
+// Download the latest yt-dlp release into the app data directory and use it
+#[tauri::command]
+pub async fn update_ytdlp() -> Result<Response<YtdlpVersion>, String> {
+    let version = ytdlp_manager::install().await?;
+    let status = ytdlp_manager::check().await;
+    let message = if status.source == ytdlp_manager::SOURCE_MANAGED {
+        format!("yt-dlp {} is installed", version)
+    } else {
+        format!(
+            "yt-dlp {} is installed, but {} takes precedence",
+            version, status.binary
+        )
+    };
+    Ok(Response {
+        success: true,
+        message,
+        data: Some(status),
+    })
+}
+
+fn snapshot_dir_for(app_handle: &tauri::AppHandle) -> Result<std::path::PathBuf, String> {
+    app_handle
+        .path_resolver()
+        .app_data_dir()
+        .map(|dir| snapshots::snapshot_dir(&dir))
+        .ok_or_else(|| "Could not determine app data directory".to_string())
+}
+
+#[tauri::command]
+pub async fn create_snapshot(
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<snapshots::SnapshotInfo>, String> {
+    let dir = snapshot_dir_for(&app_handle)?;
+    let info = snapshots::create_snapshot(&app_state.db, &dir).await?;
+    Ok(Response {
+        success: true,
+        message: format!("Snapshot {} created", info.file_name),
+        data: Some(info),
+    })
+}
+
+#[tauri::command]
+pub async fn list_snapshots(
+    app_handle: tauri::AppHandle,
+) -> Result<Response<Vec<snapshots::SnapshotInfo>>, String> {
+    let dir = snapshot_dir_for(&app_handle)?;
+    let list = snapshots::list_snapshots(&dir);
+    Ok(Response {
+        success: true,
+        message: format!("Found {} snapshot(s)", list.len()),
+        data: Some(list),
+    })
+}
+
+#[tauri::command]
+pub async fn restore_from_snapshot(
+    file_name: String,
+    app_handle: tauri::AppHandle,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    let dir = snapshot_dir_for(&app_handle)?;
+    let data = snapshots::read_snapshot(&dir, &file_name)?;
+
+    // Keep a copy of the current state in case the restore was a mistake
+    if let Err(e) = snapshots::create_snapshot(&app_state.db, &dir).await {
+        return Err(format!(
+            "Refusing to restore, pre-restore snapshot failed: {}",
+            e
+        ));
+    }
+
+    match app_state
+        .db
+        .restore_snapshot_rows(
+            &data.queue,
+            &data.settings,
+            &data.global_settings,
+            &data.uploads,
+        )
+        .await
+    {
+        Ok(restored) => {
+            // Restored rows lose their video keys; work them out again
+            tokio::spawn(indexes::run_backfill(app_handle.clone()));
+            Ok(Response {
+                success: true,
+                message: format!(
+                    "Restored {} queue items, {} settings, {} global settings and {} uploads \
+                     from {} (taken {})",
+                    restored.items,
+                    restored.settings,
+                    restored.global_settings,
+                    restored.uploads,
+                    file_name,
+                    data.created_at
+                ),
+                data: None,
+            })
+        }
+        Err(e) => Err(format!("Failed to restore snapshot: {}", e)),
+    }
+}
+
+// Whether Neon is reachable, and what is waiting to be synced to it
+#[tauri::command]
+pub async fn get_connection_status(
+    app_state: State<'_, AppState>,
+) -> Result<Response<ConnectionStatus>, String> {
+    let offline_items = app_state.db.offline_item_count();
+    let status = ConnectionStatus {
+        online: !app_state.db.is_offline(),
+        offline_items,
+        pending_writes: app_state.db.pending_write_count().saturating_sub(offline_items),
+    };
+    Ok(Response {
+        success: true,
+        message: if status.online {
+            "Database reachable".to_string()
+        } else {
+            "Database unreachable, working offline".to_string()
+        },
+        data: Some(status),
+    })
+}
+
+// EXPLAIN the gallery and queue queries and flag the ones not served by an index
+#[tauri::command]
+pub async fn explain_slow_queries(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<SlowQueryReport>, String> {
+    let report = indexes::explain_slow_queries(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Explained {} queries, {} flagged",
+            report.plans.len(),
+            report.flagged
+        ),
+        data: Some(report),
+    })
+}
+
+// Periodically snapshot the database to the app data directory
+pub async fn snapshot_background(app_handle: tauri::AppHandle) {
+    // Give the app a minute to settle before the first snapshot
+    sleep(Duration::from_secs(60)).await;
+    loop {
+        match snapshot_dir_for(&app_handle) {
+            Ok(dir) => {
+                let app_state: State<'_, AppState> = app_handle.state();
+                if let Err(e) = snapshots::create_snapshot(&app_state.db, &dir).await {
+                    eprintln!("Automatic snapshot failed: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Automatic snapshot skipped: {}", e),
+        }
+        sleep(snapshots::SNAPSHOT_INTERVAL).await;
+    }
+}
+
+// Bytes uploaded this month per provider against the configured quotas
+#[tauri::command]
+pub async fn get_quota_report(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<QuotaReport>, String> {
+    let report = quota::report(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!("Quota report for {}", report.month),
+        data: Some(report),
+    })
+}
+
+// Bytes downloaded and uploaded per day and per item over the last `days` days
+// (default 31), with a projection of this month's total against the data cap
+#[tauri::command]
+pub async fn get_bandwidth_report(
+    user_id: String,
+    days: Option<i64>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<BandwidthReport>, String> {
+    let report = bandwidth::report(&app_state, &user_id, days).await?;
+    Ok(Response {
+        success: true,
+        message: format!("Bandwidth report since {}", report.since),
+        data: Some(report),
+    })
+}
+
+// Whether this instance runs the workers (primary) or only browses (viewer)
+#[tauri::command]
+pub fn get_instance_role(app_state: State<'_, AppState>) -> Result<Response<InstanceRole>, String> {
+    Ok(Response {
+        success: true,
+        message: "Instance role retrieved successfully".to_string(),
+        data: Some(app_state.instance.role()),
+    })
+}
+
+// Turn the background processor on or off for this instance (not per user)
+#[tauri::command]
+pub fn set_worker_enabled(
+    enabled: bool,
+    app_state: State<'_, AppState>,
+) -> Result<Response<bool>, String> {
+    app_state.worker.set_enabled(enabled)?;
+    if enabled {
+        app_state.queue_wakeup.wake();
+    }
+    println!(
+        "Background worker {} for this instance",
+        if enabled { "enabled" } else { "disabled" }
+    );
+
+    Ok(Response {
+        success: true,
+        message: if enabled {
+            "Background worker enabled".to_string()
+        } else {
+            "Background worker disabled".to_string()
+        },
+        data: Some(enabled),
+    })
+}
+
+#[tauri::command]
+pub fn get_worker_enabled(app_state: State<'_, AppState>) -> Result<Response<bool>, String> {
+    Ok(Response {
+        success: true,
+        message: "Worker state retrieved successfully".to_string(),
+        data: Some(app_state.worker.is_enabled()),
+    })
+}
+
+// Backend version, optional features and per-command schema versions
+#[tauri::command]
+pub async fn get_api_capabilities(
+    app_state: State<'_, AppState>,
+) -> Result<Response<Capabilities>, String> {
+    let worker =
+        app_state.instance.role() == InstanceRole::Primary && app_state.worker.is_enabled();
+    let http_api = local_api::is_enabled(&app_state).await;
+    Ok(Response {
+        success: true,
+        message: "Capabilities retrieved successfully".to_string(),
+        data: Some(capabilities::describe(
+            worker,
+            http_api,
+            app_state.providers.names(),
+        )),
+    })
+}
+
+// The dashboard's address with its read-only token, for a bookmark on a phone; None
+// while the local API is off
+#[tauri::command]
+pub async fn get_dashboard_url(
+    app_state: State<'_, AppState>,
+) -> Result<Response<Option<String>>, String> {
+    let url = local_api::dashboard_url(&app_state).await;
+    Ok(Response {
+        success: true,
+        message: match url {
+            Some(_) => "Dashboard URL retrieved successfully".to_string(),
+            None => "The local API is off".to_string(),
+        },
+        data: Some(url),
+    })
+}
+
+// An item's failed-download bundle (see forensics.rs), shown in the file manager too
+#[tauri::command]
+pub fn open_forensics(
+    id: String,
+    app_handle: tauri::AppHandle,
+) -> Result<Response<ForensicBundle>, String> {
+    let bundle = forensics::open(&app_handle, &id)?;
+    Ok(Response {
+        success: true,
+        message: format!("Forensic bundle of item {} opened", id),
+        data: Some(bundle),
+    })
+}
+
+// What this launch found at startup (see startup.rs); no data until the checks finish
+#[tauri::command]
+pub fn get_startup_report(app_state: State<'_, AppState>) -> Result<Response<StartupReport>, String> {
+    let report = app_state.startup.get();
+    Ok(Response {
+        success: true,
+        message: if report.is_some() {
+            "Startup report retrieved successfully".to_string()
+        } else {
+            "Startup checks are still running".to_string()
+        },
+        data: report,
+    })
+}
+
+// Applied and pending schema migrations (see migrations.rs)
+#[tauri::command]
+pub async fn get_db_schema_info(
+    app_state: State<'_, AppState>,
+) -> Result<Response<SchemaInfo>, String> {
+    let info = app_state
+        .db
+        .schema_info()
+        .await
+        .map_err(|e| format!("Database error reading schema info: {}", e))?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "Schema {} of {}, {} migration(s) pending",
+            info.current_version.as_deref().unwrap_or("none"),
+            info.latest_version,
+            info.pending.len()
+        ),
+        data: Some(info),
+    })
+}
+
+// Current download and upload limits and the last change adaptive concurrency made
+#[tauri::command]
+pub fn get_concurrency_status(
+    app_state: State<'_, AppState>,
+) -> Result<Response<ConcurrencyStatus>, String> {
+    Ok(Response {
+        success: true,
+        message: "Concurrency status retrieved successfully".to_string(),
+        data: Some(app_state.concurrency.status()),
+    })
+}
+
+// The settings in force for `user_id` and the process-wide configuration applied
+// from them, with the last save that changed it
+#[tauri::command]
+pub async fn get_effective_config(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<EffectiveConfig>, String> {
+    let config = settings_watch::effective_config(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: "Effective configuration retrieved successfully".to_string(),
+        data: Some(config),
+    })
+}
+
+// Post a test event to the user's webhook_url and report the HTTP status
+#[tauri::command]
+pub async fn test_webhook(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<WebhookTest>, String> {
+    let result = webhooks::test(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!("Webhook answered HTTP {}", result.status),
+        data: Some(result),
+    })
+}
+
+// Upload providers declared in providers.json, and any definitions that failed to load
+#[tauri::command]
+pub fn list_providers(app_state: State<'_, AppState>) -> Result<Response<ProviderListing>, String> {
+    let listing = app_state.providers.listing();
+    Ok(Response {
+        success: true,
+        message: format!("{} declared upload providers", listing.providers.len()),
+        data: Some(listing),
+    })
+}
+
+// Downloads, uploads and status checks running right now, for the activity panel
+#[tauri::command]
+pub fn get_active_jobs(app_state: State<'_, AppState>) -> Result<Response<Vec<ActiveJob>>, String> {
+    let jobs = app_state.jobs.list();
+    Ok(Response {
+        success: true,
+        message: format!("{} active job(s)", jobs.len()),
+        data: Some(jobs),
+    })
+}
+
+// Recent failed provider calls, newest first, optionally for a single item
+#[tauri::command]
+pub async fn get_provider_errors(
+    item_id: Option<String>,
+    limit: Option<i64>,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<ProviderError>>, String> {
+    let limit = limit.unwrap_or(100).clamp(1, 1000);
+    match app_state
+        .db
+        .get_provider_errors(item_id.as_deref(), limit)
+        .await
+    {
+        Ok(errors) => Ok(Response {
+            success: true,
+            message: format!("Retrieved {} provider errors", errors.len()),
+            data: Some(errors),
+        }),
+        Err(e) => Err(format!("Failed to retrieve provider errors: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn get_notification_rules(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<Vec<NotificationRule>>, String> {
+    match app_state.db.get_notification_rules(&user_id).await {
+        Ok(rules) => Ok(Response {
+            success: true,
+            message: format!("Retrieved {} notification rule(s)", rules.len()),
+            data: Some(rules),
+        }),
+        Err(e) => Err(format!("Failed to retrieve notification rules: {}", e)),
+    }
+}
+
+// Create or update a rule such as "status == failed AND retries >= 3" -> telegram
+#[tauri::command]
+pub async fn save_notification_rule(
+    rule: NotificationRule,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<String>, String> {
+    notifier::validate_rule(&rule)?;
+
+    match app_state.db.save_notification_rule(&rule, &user_id).await {
+        Ok(id) => Ok(Response {
+            success: true,
+            message: format!("Notification rule '{}' saved", rule.name),
+            data: Some(id),
+        }),
+        Err(e) => Err(format!("Failed to save notification rule: {}", e)),
+    }
+}
+
+#[tauri::command]
+pub async fn delete_notification_rule(
+    id: String,
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<()>, String> {
+    match app_state.db.delete_notification_rule(&id, &user_id).await {
+        Ok(true) => Ok(Response {
+            success: true,
+            message: "Notification rule deleted".to_string(),
+            data: None,
+        }),
+        Ok(false) => Err(format!("Notification rule {} not found.", id)),
+        Err(e) => Err(format!("Failed to delete notification rule: {}", e)),
+    }
+}
+
+// Size of the download volume, its free space and what the downloads take up, for
+// the settings screen
+#[tauri::command]
+pub async fn get_disk_usage(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<DiskUsage>, String> {
+    let usage = disk_space::usage(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!(
+            "{} of {} bytes free in {}",
+            usage.free_bytes, usage.total_bytes, usage.directory
+        ),
+        data: Some(usage),
+    })
+}
+
+// Free space on the download volume and whether new downloads are paused for it
+#[tauri::command]
+pub async fn get_disk_space(
+    user_id: String,
+    app_state: State<'_, AppState>,
+) -> Result<Response<DiskSpace>, String> {
+    let space = disk_space::report(&app_state, &user_id).await?;
+    Ok(Response {
+        success: true,
+        message: format!("{} bytes free in {}", space.free_bytes, space.directory),
+        data: Some(space),
+    })
+}
+
+// English templates for every status message code, for the frontend's translations
+// to fall back on
+#[tauri::command]
+pub fn get_message_catalog() -> Result<Response<Vec<CatalogEntry>>, String> {
+    let catalog = messages::catalog();
+    Ok(Response {
+        success: true,
+        message: format!("Retrieved {} message template(s)", catalog.len()),
+        data: Some(catalog),
+    })
+}
@@ -0,0 +1,257 @@
+// Queue templates: a named, reusable archive job ("weekly council meeting stream")
+// bundling a source URL pattern with per-item overrides (format, upload target, tags,
+// sections, priority) and an optional schedule. A template can be instantiated by
+// hand with one command, and scheduled templates are queued by a background loop.
+//
+// add_queue_item rejects URLs that are already queued, so a recurring template needs
+// a pattern that renders a new URL per run (see render_url), or an explicit URL when
+// it is instantiated by hand.
+
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, NaiveTime, TimeZone, Utc, Weekday,
+};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::time::sleep;
+
+use crate::db::{QueueItem, QueueTemplate};
+use crate::providers::ProviderRegistry;
+use crate::{scheduler, sections, timestamps, urls, AppState};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    Daily(NaiveTime),
+    Weekly(Weekday, NaiveTime),
+}
+
+// "daily 19:00" or "weekly tue 18:30" (full day names work too), in local time
+pub fn parse_schedule(input: &str) -> Result<Schedule, String> {
+    let invalid = || {
+        format!(
+            "Invalid schedule '{}'. Use \"daily HH:MM\" or \"weekly <day> HH:MM\".",
+            input
+        )
+    };
+    let parts: Vec<&str> = input.split_whitespace().collect();
+    let time = |value: &str| NaiveTime::parse_from_str(value, "%H:%M").map_err(|_| invalid());
+
+    match parts.as_slice() {
+        [kind, at] if kind.eq_ignore_ascii_case("daily") => Ok(Schedule::Daily(time(at)?)),
+        [kind, day, at] if kind.eq_ignore_ascii_case("weekly") => {
+            let day = day.parse::<Weekday>().map_err(|_| invalid())?;
+            Ok(Schedule::Weekly(day, time(at)?))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+// Most recent scheduled time at or before `now`
+fn last_occurrence(schedule: Schedule, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let today = now.weekday().num_days_from_monday() as i64;
+    let (days_back, period, at) = match schedule {
+        Schedule::Daily(at) => (0, 1, at),
+        Schedule::Weekly(day, at) => {
+            let days_back = (today - day.num_days_from_monday() as i64).rem_euclid(7);
+            (days_back, 7, at)
+        }
+    };
+
+    let at_day = |days: i64| {
+        let date = now.date_naive() - ChronoDuration::days(days);
+        // Times skipped by a DST change have no local equivalent; that run is skipped
+        Local.from_local_datetime(&date.and_time(at)).earliest()
+    };
+    match at_day(days_back)? {
+        occurrence if occurrence <= now => Some(occurrence),
+        _ => at_day(days_back + period),
+    }
+}
+
+// Whether a scheduled run has come round since the template last ran (or was created)
+pub fn is_due(template: &QueueTemplate, now: DateTime<Utc>) -> bool {
+    let schedule = match template.schedule.as_deref().map(parse_schedule) {
+        Some(Ok(schedule)) => schedule,
+        _ => return false,
+    };
+    let since = template.last_run_at.or(template.created_at).unwrap_or(now);
+    match last_occurrence(schedule, now.with_timezone(&Local)) {
+        Some(occurrence) => occurrence.with_timezone(&Utc) > since,
+        None => false,
+    }
+}
+
+// Fill in {date} (YYYY-MM-DD), {yyyy}, {mm} and {dd} with the local date
+pub fn render_url(pattern: &str, now: DateTime<Utc>) -> String {
+    let local = now.with_timezone(&Local);
+    pattern
+        .replace("{date}", &local.format("%Y-%m-%d").to_string())
+        .replace("{yyyy}", &local.format("%Y").to_string())
+        .replace("{mm}", &local.format("%m").to_string())
+        .replace("{dd}", &local.format("%d").to_string())
+}
+
+fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        if !normalized.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            normalized.push(tag.to_string());
+        }
+    }
+    normalized
+}
+
+// Check and tidy a template before it is saved
+pub fn validate(template: &mut QueueTemplate, providers: &ProviderRegistry) -> Result<(), String> {
+    template.name = template.name.trim().to_string();
+    if template.name.is_empty() {
+        return Err("Template name must not be empty".to_string());
+    }
+    template.url_pattern = template.url_pattern.trim().to_string();
+    let pattern = template.url_pattern.as_str();
+    if !pattern.starts_with("http://") && !pattern.starts_with("https://") {
+        return Err(format!(
+            "Template URL must start with http:// or https://: {}",
+            template.url_pattern
+        ));
+    }
+
+    template.format = template
+        .format
+        .take()
+        .map(|f| f.trim().to_string())
+        .filter(|f| !f.is_empty());
+    template.upload_target = template
+        .upload_target
+        .take()
+        .map(|t| t.trim().to_ascii_lowercase())
+        .filter(|t| !t.is_empty());
+    providers.check_target(template.upload_target.as_deref())?;
+
+    template.tags = normalize_tags(&template.tags);
+    if let Some(raw_sections) = template.download_sections.take() {
+        template.download_sections = sections::normalize(&raw_sections)?;
+    }
+    template.priority = scheduler::clamp_priority(template.priority);
+    template.schedule = template
+        .schedule
+        .take()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty());
+    if let Some(schedule) = &template.schedule {
+        parse_schedule(schedule)?;
+    }
+    Ok(())
+}
+
+// Queue one item from the template. `url` replaces the rendered pattern when given.
+pub async fn instantiate(
+    app_state: &AppState,
+    template: &QueueTemplate,
+    url: Option<String>,
+) -> Result<String, String> {
+    let user_id = template
+        .user_id
+        .clone()
+        .ok_or_else(|| "Template has no owner".to_string())?;
+    let url = url
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .unwrap_or_else(|| render_url(&template.url_pattern, timestamps::now()));
+
+    let item = QueueItem {
+        id: None,
+        url: urls::normalize_url(&url),
+        status: "queued".to_string(),
+        message: Some(format!("Queued from template '{}'", template.name)),
+        title: None,
+        filemoon_url: None,
+        encoding_progress: None,
+        thumbnail_url: None,
+        added_at: None,
+        updated_at: None,
+        local_path: None,
+        user_id: Some(user_id),
+        short_url: None,
+        thumbnail_uploaded: None,
+        locked: None,
+        format_rung: None,
+        format_used: None,
+        download_sections: template.download_sections.clone(),
+        mirror_urls: None,
+        source_index: None,
+        priority: Some(template.priority),
+        failure_count: None,
+        language: None,
+        caption_languages: None,
+        format_override: template.format.clone(),
+        tags: Some(template.tags.clone()),
+        template_id: template.id.clone(),
+        upload_verified_at: None,
+        message_code: None,
+        message_params: None,
+        playlist_id: None,
+        subtitle_paths: None,
+        upload_provider: None,
+        upload_url: None,
+        collection: None,
+        retry_count: None,
+        max_retries: None,
+        next_retry_at: None,
+        total_bytes: None,
+        downloaded_bytes: None,
+        speed_bps: None,
+        eta_seconds: None,
+        transfer_mode: None,
+        remote_filecode: None,
+        upload_target: template.upload_target.clone(),
+    };
+
+    let id = app_state
+        .db
+        .add_queue_item(&item)
+        .await
+        .map_err(|e| e.to_string())?;
+    app_state.queue_wakeup.wake();
+    Ok(id)
+}
+
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting queue template scheduler...");
+
+    loop {
+        sleep(CHECK_INTERVAL).await;
+        if let Err(e) = run_due_templates(&app_handle).await {
+            eprintln!("Queue template scheduler failed: {}", e);
+        }
+    }
+}
+
+async fn run_due_templates(app_handle: &AppHandle) -> Result<(), String> {
+    let app_state = app_handle.state::<AppState>();
+    let templates = app_state
+        .db
+        .get_scheduled_templates()
+        .await
+        .map_err(|e| format!("Failed to load queue templates: {}", e))?;
+
+    let now = timestamps::now();
+    for template in templates.iter().filter(|t| is_due(t, now)) {
+        let id = match &template.id {
+            Some(id) => id,
+            None => continue,
+        };
+        // Mark the run first, so a failing template is retried at its next slot
+        // rather than every minute
+        if let Err(e) = app_state.db.mark_template_run(id, now).await {
+            eprintln!("Error marking run of template {}: {}", id, e);
+            continue;
+        }
+        match instantiate(&app_state, template, None).await {
+            Ok(item_id) => println!("Template '{}' queued item {}", template.name, item_id),
+            Err(e) => eprintln!("Template '{}' could not queue its item: {}", template.name, e),
+        }
+    }
+    Ok(())
+}
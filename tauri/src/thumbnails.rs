@@ -0,0 +1,105 @@
+// Custom per-item thumbnails. A thumbnail is either an image the user picked or a
+// frame grabbed from the downloaded video with ffmpeg; either way it is copied into
+// the thumbnail cache in the app data dir so it outlives delete-after-upload.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::process::Command;
+
+use crate::performance::{self, Priority};
+
+const THUMBNAIL_DIR_NAME: &str = "thumbnails";
+
+pub fn cache_dir(app_data_dir: &Path) -> PathBuf {
+    app_data_dir.join(THUMBNAIL_DIR_NAME)
+}
+
+// Parse a position in the video: seconds ("95", "95.5") or clock time ("1:35", "01:01:35.5")
+pub fn parse_position(input: &str) -> Option<f64> {
+    let parts: Vec<&str> = input.trim().split(':').collect();
+    if parts.is_empty() || parts.len() > 3 {
+        return None;
+    }
+
+    let last = parts.len() - 1;
+    let mut seconds = 0.0;
+    for (i, part) in parts.iter().enumerate() {
+        let value: f64 = part.parse().ok()?;
+        if !value.is_finite() || value < 0.0 {
+            return None;
+        }
+        // Only the seconds may be fractional, and minutes/seconds stay below 60
+        if (i < last && value.fract() != 0.0) || (i > 0 && value >= 60.0) {
+            return None;
+        }
+        seconds = seconds * 60.0 + value;
+    }
+    Some(seconds)
+}
+
+// Copy a user-supplied image into the cache. The file must really be an image.
+pub async fn store_image(source: &Path, dir: &Path, item_id: &str) -> Result<PathBuf, String> {
+    let bytes = tokio::fs::read(source)
+        .await
+        .map_err(|e| format!("Failed to read image {}: {}", source.display(), e))?;
+    let kind = infer::get(&bytes)
+        .filter(|kind| kind.matcher_type() == infer::MatcherType::Image)
+        .ok_or_else(|| format!("{} is not an image file", source.display()))?;
+
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create thumbnail cache: {}", e))?;
+    let target = dir.join(format!("{}.{}", item_id, kind.extension()));
+    tokio::fs::write(&target, &bytes)
+        .await
+        .map_err(|e| format!("Failed to store thumbnail: {}", e))?;
+    Ok(target)
+}
+
+// Grab a single frame at `position` seconds and store it in the cache as JPEG
+pub async fn extract_frame(
+    ffmpeg: &str,
+    video: &Path,
+    position: f64,
+    dir: &Path,
+    item_id: &str,
+    priority: Priority,
+) -> Result<PathBuf, String> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .map_err(|e| format!("Failed to create thumbnail cache: {}", e))?;
+    let target = dir.join(format!("{}.jpg", item_id));
+
+    let child = Command::new(ffmpeg)
+        .arg("-y")
+        .arg("-ss")
+        .arg(format!("{:.3}", position))
+        .arg("-i")
+        .arg(video)
+        .arg("-frames:v")
+        .arg("1")
+        .arg("-q:v")
+        .arg("2")
+        .arg(&target)
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if let Some(pid) = child.id() {
+        performance::apply(pid, priority).await;
+    }
+    let output = child
+        .wait_with_output()
+        .await
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+
+    if !output.status.success() || !target.exists() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!(
+            "ffmpeg could not extract a frame at {:.3}s: {}",
+            position,
+            stderr.lines().last().unwrap_or("no output")
+        ));
+    }
+    Ok(target)
+}
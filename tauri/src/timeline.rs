@@ -0,0 +1,162 @@
+// Chronological history of one item for the detail view, assembled from the
+// status events, finished transfers, provider errors, hook runs and provenance
+// recorded for it, plus the live yt-dlp output still held in memory.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value as JsonValue};
+
+use crate::db::QueueItem;
+use crate::output_tail::OutputLine;
+use crate::{timestamps, AppState};
+
+// Provider errors included per item
+const MAX_PROVIDER_ERRORS: i64 = 100;
+
+pub const KIND_ADDED: &str = "added";
+pub const KIND_STATUS: &str = "status";
+// Back in the queue after having left it (manual retry, format or mirror fallback, stall)
+pub const KIND_RETRY: &str = "retry";
+pub const KIND_UPLOAD_ATTEMPT: &str = "upload_attempt";
+pub const KIND_TRANSFER: &str = "transfer";
+pub const KIND_PROVIDER_ERROR: &str = "provider_error";
+pub const KIND_CAPTURED: &str = "captured";
+pub const KIND_HOOK: &str = "hook";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TimelineEvent {
+    #[serde(with = "timestamps::iso8601")]
+    pub at: DateTime<Utc>,
+    pub kind: String,
+    pub status: Option<String>,
+    pub message: Option<String>,
+    // Set for status events; see messages.rs
+    pub message_code: Option<String>,
+    pub message_params: Option<JsonValue>,
+    pub detail: Option<JsonValue>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ItemTimeline {
+    pub item: QueueItem,
+    pub events: Vec<TimelineEvent>,
+    pub retries: usize,
+    pub upload_attempts: usize,
+    // Recent yt-dlp output from this session; empty after a restart
+    pub logs: Vec<OutputLine>,
+}
+
+fn event(at: DateTime<Utc>, kind: &str) -> TimelineEvent {
+    TimelineEvent {
+        at,
+        kind: kind.to_string(),
+        status: None,
+        message: None,
+        message_code: None,
+        message_params: None,
+        detail: None,
+    }
+}
+
+pub async fn build(app_state: &AppState, item: QueueItem) -> Result<ItemTimeline, String> {
+    let item_id = item.id.clone().unwrap_or_default();
+    let db_error = |e: Box<dyn std::error::Error + Send + Sync>| {
+        format!("Failed to load timeline for item {}: {}", item_id, e)
+    };
+
+    let status_events = app_state.db.get_item_events(&item_id).await.map_err(db_error)?;
+    let transfers = app_state.db.get_item_bandwidth(&item_id).await.map_err(db_error)?;
+    let provider_errors = app_state
+        .db
+        .get_provider_errors(Some(&item_id), MAX_PROVIDER_ERRORS)
+        .await
+        .map_err(db_error)?;
+    let hook_runs = app_state.db.get_hook_runs(&item_id).await.map_err(db_error)?;
+    let provenance = app_state.db.get_provenance(&item_id).await.map_err(db_error)?;
+
+    let mut events = Vec::new();
+    if let Some(added_at) = item.added_at {
+        let mut added = event(added_at, KIND_ADDED);
+        added.detail = Some(json!({ "url": item.url }));
+        events.push(added);
+    }
+
+    let (mut retries, mut upload_attempts) = (0, 0);
+    let mut left_queue = false;
+    for status_change in status_events {
+        let status = status_change.status;
+        let kind = match status.as_str() {
+            "queued" if left_queue => {
+                retries += 1;
+                KIND_RETRY
+            }
+            "uploading" => {
+                upload_attempts += 1;
+                KIND_UPLOAD_ATTEMPT
+            }
+            _ => KIND_STATUS,
+        };
+        left_queue |= status != "queued";
+        let mut status_event = event(status_change.created_at, kind);
+        status_event.status = Some(status);
+        status_event.message = status_change.message;
+        status_event.message_code = status_change.message_code;
+        status_event.message_params = status_change.message_params;
+        events.push(status_event);
+    }
+
+    for (at, direction, bytes) in transfers {
+        let mut transfer = event(at, KIND_TRANSFER);
+        transfer.message = Some(format!("Finished {} of {} bytes", direction, bytes));
+        transfer.detail = Some(json!({ "direction": direction, "bytes": bytes }));
+        events.push(transfer);
+    }
+
+    for error in provider_errors {
+        let mut provider_error = event(error.created_at, KIND_PROVIDER_ERROR);
+        provider_error.message = Some(error.message);
+        provider_error.detail = Some(json!({
+            "endpoint": error.endpoint,
+            "http_status": error.http_status,
+        }));
+        events.push(provider_error);
+    }
+
+    for run in hook_runs {
+        let mut hook = event(run.finished_at, KIND_HOOK);
+        hook.message = Some(match &run.error {
+            Some(error) => format!("{} hook failed: {}", run.event, error),
+            None => format!("{} hook finished", run.event),
+        });
+        hook.detail = Some(json!({
+            "event": run.event,
+            "command": run.command,
+            "exit_code": run.exit_code,
+        }));
+        events.push(hook);
+    }
+
+    if let Some(record) = provenance {
+        let mut captured = event(record.captured_at, KIND_CAPTURED);
+        captured.message = record.file_name.clone();
+        captured.detail = Some(json!({
+            "extractor": record.extractor,
+            "video_id": record.video_id,
+            "format": record.format,
+            "file_size": record.file_size,
+            "sha256": record.sha256,
+        }));
+        events.push(captured);
+    }
+
+    // Stable sort keeps same-instant events in the order they were gathered
+    events.sort_by_key(|event| event.at);
+
+    Ok(ItemTimeline {
+        logs: app_state.output_tail.lines(&item_id),
+        item,
+        events,
+        retries,
+        upload_attempts,
+    })
+}
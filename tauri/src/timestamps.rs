@@ -0,0 +1,90 @@
+// Timestamp helpers. Everything is stored as timestamptz and handled as
+// DateTime<Utc> in Rust; the API exposes ISO-8601 strings. Older snapshots and
+// imports carry epoch milliseconds, so deserialization accepts both.
+
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serializer};
+
+pub fn now() -> DateTime<Utc> {
+    Utc::now()
+}
+
+// Epoch milliseconds to a UTC timestamp. Values chrono can't represent (a clock
+// far off in either direction) clamp to the epoch instead of panicking.
+pub fn from_millis(millis: i64) -> DateTime<Utc> {
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .unwrap_or_default()
+}
+
+pub fn to_iso(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+// Accepts RFC 3339 / ISO-8601 (any offset, normalized to UTC) or epoch milliseconds
+pub fn parse(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Some(time.with_timezone(&Utc));
+    }
+    value.parse::<i64>().ok().map(from_millis)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawTimestamp {
+    Millis(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl RawTimestamp {
+    fn into_datetime<E: serde::de::Error>(self) -> Result<DateTime<Utc>, E> {
+        match self {
+            RawTimestamp::Millis(millis) => Ok(from_millis(millis)),
+            RawTimestamp::Float(millis) => Ok(from_millis(millis as i64)),
+            RawTimestamp::Text(text) => {
+                parse(&text).ok_or_else(|| E::custom(format!("invalid timestamp '{}'", text)))
+            }
+        }
+    }
+}
+
+// serde `with` module for DateTime<Utc> fields
+pub mod iso8601 {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(time: &DateTime<Utc>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&to_iso(time))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        RawTimestamp::deserialize(deserializer)?.into_datetime()
+    }
+}
+
+// serde `with` module for Option<DateTime<Utc>> fields; pair with #[serde(default)]
+pub mod iso8601_opt {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        time: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match time {
+            Some(time) => serializer.serialize_some(&to_iso(time)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        match Option::<RawTimestamp>::deserialize(deserializer)? {
+            Some(raw) => raw.into_datetime().map(Some),
+            None => Ok(None),
+        }
+    }
+}
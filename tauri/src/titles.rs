@@ -0,0 +1,198 @@
+// Title fallback chain for downloads whose metadata has no usable title (common on
+// Facebook, where yt-dlp reports "Video" or the bare id). The first of these wins:
+//
+//   1. the title yt-dlp reported (for X and TikTok, social::title_from_info)
+//   2. the first line of the description
+//   3. uploader and upload date, "Jane Doe, 2024-05-01"
+//   4. the last readable segment of the URL path, "my-holiday-video" -> "my holiday video"
+//   5. the video id
+//
+// The same title names the queue item and the file uploaded to Filemoon.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::Value as JsonValue;
+
+use crate::social;
+
+// Titles longer than this are cut at a word boundary
+const MAX_TITLE_CHARS: usize = 100;
+
+// URL path segments that never describe the video
+const GENERIC_SEGMENTS: &[&str] = &[
+    "watch", "video", "videos", "v", "reel", "reels", "status", "story", "posts", "share", "embed",
+    "shorts", "clip", "clips", "p", "tv",
+];
+
+lazy_static! {
+    // Titles that say nothing about the video, e.g. "Video", "Facebook video #1234"
+    static ref PLACEHOLDER_TITLE_REGEX: Regex = Regex::new(
+        r"(?i)^(?:untitled|video|watch|reel|(?:facebook|instagram|fb) (?:video|watch|reel)(?: #?\d+)?|\d+)$"
+    )
+    .unwrap();
+    static ref LINK_REGEX: Regex = Regex::new(r"https?://\S+").unwrap();
+    static ref WHITESPACE_REGEX: Regex = Regex::new(r"\s+").unwrap();
+    static ref SLUG_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_-]+$").unwrap();
+    static ref UPLOAD_DATE_REGEX: Regex = Regex::new(r"^(\d{4})(\d{2})(\d{2})$").unwrap();
+}
+
+fn text<'a>(info: &'a JsonValue, key: &str) -> Option<&'a str> {
+    info.get(key)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+}
+
+// Text as a one-line title: links dropped, whitespace collapsed, length capped
+pub fn clean_text(text: &str) -> String {
+    let text = LINK_REGEX.replace_all(text, "");
+    let text = WHITESPACE_REGEX.replace_all(text.trim(), " ");
+    if text.chars().count() <= MAX_TITLE_CHARS {
+        return text.into_owned();
+    }
+    let cut: String = text.chars().take(MAX_TITLE_CHARS).collect();
+    let cut = match cut.rfind(' ') {
+        Some(i) if i > MAX_TITLE_CHARS / 2 => &cut[..i],
+        _ => cut.as_str(),
+    };
+    format!("{}…", cut.trim_end())
+}
+
+fn is_placeholder(title: &str, info: &JsonValue) -> bool {
+    PLACEHOLDER_TITLE_REGEX.is_match(title)
+        || Some(title) == text(info, "id")
+        || Some(title) == text(info, "display_id")
+}
+
+fn description_line(info: &JsonValue) -> Option<String> {
+    text(info, "description")?
+        .lines()
+        .map(clean_text)
+        .find(|line| !line.is_empty())
+}
+
+fn uploader_and_date(info: &JsonValue) -> Option<String> {
+    let uploader = text(info, "uploader")
+        .or_else(|| text(info, "channel"))
+        .or_else(|| text(info, "uploader_id"))?;
+    let date = text(info, "upload_date").and_then(|date| {
+        UPLOAD_DATE_REGEX
+            .captures(date)
+            .map(|caps| format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]))
+    });
+    Some(match date {
+        Some(date) => format!("{}, {}", clean_text(uploader), date),
+        None => clean_text(uploader),
+    })
+}
+
+// The last path segment that reads like words: not an id, not "watch" or "reel"
+pub fn from_url(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    let segments: Vec<&str> = url.path_segments()?.collect();
+    segments.iter().rev().find_map(|segment| {
+        let slug = segment
+            .rsplit_once('.')
+            .map_or(*segment, |(stem, _)| stem)
+            .trim_matches(|c| c == '-' || c == '_');
+        let readable = SLUG_REGEX.is_match(slug)
+            && slug.chars().filter(|c| c.is_ascii_alphabetic()).count() >= 3
+            && !slug.chars().any(|c| c.is_ascii_digit())
+            && !GENERIC_SEGMENTS.contains(&slug.to_ascii_lowercase().as_str());
+        readable.then(|| clean_text(&slug.replace(['-', '_'], " ")))
+    })
+}
+
+// The title to record for a download, following the chain above
+pub fn from_info(url: &str, info: &JsonValue) -> Option<String> {
+    let reported = match social::site(url) {
+        Some(_) => social::title_from_info(url, info),
+        None => text(info, "title").map(String::from),
+    };
+    reported
+        .clone()
+        .filter(|title| !title.is_empty() && !is_placeholder(title, info))
+        .or_else(|| description_line(info))
+        .or_else(|| uploader_and_date(info))
+        .or_else(|| from_url(url))
+        .or(reported)
+        .or_else(|| text(info, "id").map(String::from))
+}
+
+// Name the uploaded file after the item's title, keeping the local file's extension.
+// Without a title the URL slug is used, and failing that the local file name.
+pub fn upload_file_name(title: Option<&str>, url: &str, local_file_name: &str) -> String {
+    let title = title
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(String::from)
+        .or_else(|| from_url(url));
+    let title = match title {
+        Some(title) => title,
+        None => return local_file_name.to_string(),
+    };
+    match local_file_name.rsplit_once('.') {
+        Some((_, ext)) if !ext.is_empty() => format!("{}.{}", title, ext),
+        _ => title,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn clean_text_drops_links_and_extra_whitespace() {
+        assert_eq!(
+            clean_text("  hello   https://example.com/a  world "),
+            "hello world"
+        );
+        let long = "word ".repeat(40);
+        let cleaned = clean_text(&long);
+        assert!(cleaned.ends_with('…'));
+        assert!(cleaned.chars().count() <= MAX_TITLE_CHARS + 1);
+    }
+
+    #[test]
+    fn from_url_takes_the_readable_segment() {
+        assert_eq!(
+            from_url("https://example.com/videos/my-holiday-video").as_deref(),
+            Some("my holiday video")
+        );
+        assert_eq!(from_url("https://example.com/watch/12345"), None);
+    }
+
+    #[test]
+    fn from_info_skips_placeholder_titles() {
+        let url = "https://example.com/v/1";
+        let info = json!({"title": "Video", "id": "1", "description": "\nFirst line\nsecond"});
+        assert_eq!(from_info(url, &info).as_deref(), Some("First line"));
+
+        let info = json!({
+            "title": "1",
+            "id": "1",
+            "uploader": "Jane Doe",
+            "upload_date": "20240501"
+        });
+        assert_eq!(
+            from_info(url, &info).as_deref(),
+            Some("Jane Doe, 2024-05-01")
+        );
+
+        let info = json!({"title": "A real title", "id": "1"});
+        assert_eq!(from_info(url, &info).as_deref(), Some("A real title"));
+    }
+
+    #[test]
+    fn upload_file_name_keeps_the_extension() {
+        assert_eq!(
+            upload_file_name(Some("My clip"), "https://example.com/v/1", "abc.mp4"),
+            "My clip.mp4"
+        );
+        assert_eq!(
+            upload_file_name(None, "https://example.com/v/1", "abc.mp4"),
+            "abc.mp4"
+        );
+    }
+}
@@ -0,0 +1,387 @@
+// Detection of the external tools the downloader depends on (yt-dlp, ffmpeg)
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::process::Command;
+
+use crate::{cancellation, ytdlp_manager};
+
+// Names another yt-dlp executable than the one on PATH, e.g. the scripted fake the
+// end-to-end harness runs against (see e2e.rs)
+pub const YTDLP_ENV: &str = "PERMAVID_YTDLP";
+
+// A binary that hasn't printed its version by then is treated as missing
+const VERSION_PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Don't run `yt-dlp -U` more often than this, however many items fail
+const YTDLP_UPDATE_COOLDOWN: Duration = Duration::from_secs(60 * 60);
+
+// stderr fragments yt-dlp prints when an extractor no longer matches the site. Only
+// unexpected extractor errors carry the "report this issue" hint; network, geo and
+// 4xx failures ("Unable to download JSON metadata: HTTP Error 404") don't, so the
+// bare "Unable to extract"/"Unable to download" prefixes are not enough on their own.
+const EXTRACTOR_OUTDATED_MARKERS: &[&str] = &[
+    "Confirm you are on the latest version",
+    "please report this issue on",
+    "nsig extraction failed",
+    "Signature extraction failed",
+];
+
+// stderr fragments for content yt-dlp will never download, however often it's retried,
+// paired with the explanation stored on the item
+const UNSUPPORTED_CONTENT_MARKERS: &[(&str, &str)] = &[
+    ("DRM protected", "The video is DRM protected and can't be downloaded"),
+    ("DRM-protected", "The video is DRM protected and can't be downloaded"),
+    ("Unsupported URL", "yt-dlp doesn't support this site or URL"),
+    ("is not a valid URL", "The URL isn't valid"),
+    (
+        "This live event will begin",
+        "The live event hasn't started; queue it again once it has aired",
+    ),
+];
+
+// Fragments of yt-dlp ERROR: lines (matched case-insensitively) for videos that need
+// a signed-in session, paired with the explanation stored on the item. Warnings are
+// not looked at: yt-dlp mentions logging in and --cookies in routine warnings about
+// downloads that still work or can be retried.
+const AUTH_REQUIRED_MARKERS: &[(&str, &str)] = &[
+    ("Sign in to confirm your age", "The video is age-restricted"),
+    ("inappropriate for some users", "The video is age-restricted"),
+    ("age-restricted", "The video is age-restricted"),
+    ("Sign in to confirm you", "The site wants a signed-in session"),
+    ("Private video", "The video is private"),
+    ("members-only", "The video is for channel members only"),
+    ("Join this channel to get access", "The video is for channel members only"),
+    ("only available for registered users", "The site requires a login"),
+    ("login required", "The site requires a login"),
+];
+
+// yt-dlp errors meaning this particular URL is gone or blocked for us; a mirror
+// of the same video may still work
+const SOURCE_GONE_MARKERS: &[&str] = &[
+    "Video unavailable",
+    "This video has been removed",
+    "This video is no longer available",
+    "not available in your country",
+    "geo restriction",
+    "geo-restricted",
+    "Private video",
+    "account associated with this video has been terminated",
+    "HTTP Error 404",
+    "HTTP Error 410",
+    "available in your country",
+    "blocked it in your country",
+    "copyright claim",
+    "account has been terminated",
+];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ToolStatus {
+    pub name: String,
+    pub found: bool,
+    pub path: Option<String>,
+    pub version: Option<String>,
+    pub message: Option<String>,
+}
+
+fn ffmpeg_binary_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "ffmpeg.exe"
+    } else {
+        "ffmpeg"
+    }
+}
+
+// Install locations that are commonly not on PATH
+fn common_ffmpeg_locations() -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    if cfg!(target_os = "windows") {
+        candidates.push(PathBuf::from(r"C:\ffmpeg\bin\ffmpeg.exe"));
+        candidates.push(PathBuf::from(r"C:\Program Files\ffmpeg\bin\ffmpeg.exe"));
+        if let Some(home) = dirs::home_dir() {
+            candidates.push(home.join(r"scoop\shims\ffmpeg.exe"));
+        }
+    } else {
+        candidates.push(PathBuf::from("/opt/homebrew/bin/ffmpeg"));
+        candidates.push(PathBuf::from("/usr/local/bin/ffmpeg"));
+        candidates.push(PathBuf::from("/usr/bin/ffmpeg"));
+        candidates.push(PathBuf::from("/snap/bin/ffmpeg"));
+    }
+    candidates
+}
+
+// A configured ffmpeg path may point at the binary itself or at the directory holding it
+fn normalize_ffmpeg_setting(configured: &str) -> PathBuf {
+    let path = Path::new(configured);
+    if path.is_dir() {
+        path.join(ffmpeg_binary_name())
+    } else {
+        path.to_path_buf()
+    }
+}
+
+// Run `<binary> <arg>` and return the first line of its output; None when it fails
+// or hangs past VERSION_PROBE_TIMEOUT
+pub async fn probe_version(binary: &str, arg: &str) -> Option<String> {
+    let mut cmd = Command::new(binary);
+    cmd.arg(arg);
+    let output = cancellation::output_with_timeout(&mut cmd, VERSION_PROBE_TIMEOUT)
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(|line| line.trim().to_string())
+}
+
+// Resolve the ffmpeg binary to use. The configured setting wins; otherwise PATH is
+// tried first and then the common install locations.
+pub async fn detect_ffmpeg(configured: Option<&str>) -> ToolStatus {
+    if let Some(configured) = configured.filter(|c| !c.trim().is_empty()) {
+        let candidate = normalize_ffmpeg_setting(configured.trim());
+        let candidate_str = candidate.to_string_lossy().to_string();
+        let version = probe_version(&candidate_str, "-version").await;
+        return ToolStatus {
+            name: "ffmpeg".to_string(),
+            found: version.is_some(),
+            message: if version.is_some() {
+                Some("Using configured ffmpeg path".to_string())
+            } else {
+                Some(format!(
+                    "Configured ffmpeg path '{}' is not a working ffmpeg binary",
+                    configured
+                ))
+            },
+            path: Some(candidate_str),
+            version,
+        };
+    }
+
+    if let Some(version) = probe_version("ffmpeg", "-version").await {
+        return ToolStatus {
+            name: "ffmpeg".to_string(),
+            found: true,
+            path: Some("ffmpeg".to_string()),
+            version: Some(version),
+            message: Some("Found ffmpeg on PATH".to_string()),
+        };
+    }
+
+    for candidate in common_ffmpeg_locations() {
+        if !candidate.exists() {
+            continue;
+        }
+        let candidate_str = candidate.to_string_lossy().to_string();
+        if let Some(version) = probe_version(&candidate_str, "-version").await {
+            return ToolStatus {
+                name: "ffmpeg".to_string(),
+                found: true,
+                path: Some(candidate_str),
+                version: Some(version),
+                message: Some("Found ffmpeg outside PATH".to_string()),
+            };
+        }
+    }
+
+    ToolStatus {
+        name: "ffmpeg".to_string(),
+        found: false,
+        path: None,
+        version: None,
+        message: Some(
+            "ffmpeg not found. yt-dlp will not be able to merge separate video and audio streams."
+                .to_string(),
+        ),
+    }
+}
+
+// Location to pass to yt-dlp via --ffmpeg-location, if ffmpeg is not simply on PATH
+pub async fn ffmpeg_location_arg(configured: Option<&str>) -> Option<String> {
+    let status = detect_ffmpeg(configured).await;
+    match status.path {
+        Some(path) if status.found && path != "ffmpeg" => Some(path),
+        _ => None,
+    }
+}
+
+// The yt-dlp executable every download, probe and update runs; see ytdlp_manager
+pub fn ytdlp_binary() -> String {
+    ytdlp_manager::resolve().0
+}
+
+pub async fn detect_ytdlp() -> ToolStatus {
+    let (binary, source) = ytdlp_manager::resolve();
+    match probe_version(&binary, "--version").await {
+        Some(version) => ToolStatus {
+            name: "yt-dlp".to_string(),
+            found: true,
+            path: Some(binary),
+            version: Some(version),
+            message: (source == ytdlp_manager::SOURCE_MANAGED)
+                .then(|| "Using the managed yt-dlp".to_string()),
+        },
+        None => ToolStatus {
+            name: "yt-dlp".to_string(),
+            found: false,
+            message: Some(match source {
+                ytdlp_manager::SOURCE_PATH => {
+                    "yt-dlp not found in PATH; install it from Settings".to_string()
+                }
+                _ => format!("yt-dlp at '{}' doesn't run", binary),
+            }),
+            path: None,
+            version: None,
+        },
+    }
+}
+
+// True when a yt-dlp failure looks like an outdated extractor rather than a bad URL
+pub fn is_extractor_outdated(stderr: &str) -> bool {
+    EXTRACTOR_OUTDATED_MARKERS
+        .iter()
+        .any(|marker| stderr.contains(marker))
+        && !is_source_gone(stderr)
+}
+
+// Why a yt-dlp failure can never succeed on retry (DRM, unsupported site), if it can't
+pub fn unsupported_content_reason(stderr: &str) -> Option<&'static str> {
+    UNSUPPORTED_CONTENT_MARKERS
+        .iter()
+        .find(|(marker, _)| stderr.contains(marker))
+        .map(|(_, reason)| *reason)
+}
+
+// Why a yt-dlp failure needs login cookies, if that's what it needs
+pub fn auth_required_reason(stderr: &str) -> Option<&'static str> {
+    stderr
+        .lines()
+        .filter(|line| line.trim_start().starts_with("ERROR:"))
+        .find_map(|line| {
+            let line = line.to_lowercase();
+            AUTH_REQUIRED_MARKERS
+                .iter()
+                .find(|(marker, _)| line.contains(&marker.to_lowercase()))
+                .map(|(_, reason)| *reason)
+        })
+}
+
+// True when a yt-dlp failure means the source itself is deleted, private or geo-blocked
+pub fn is_source_gone(stderr: &str) -> bool {
+    SOURCE_GONE_MARKERS
+        .iter()
+        .any(|marker| stderr.contains(marker))
+}
+
+// True when no format, retry or update will make the download work: the content is
+// unsupported, needs a login, or the source is gone, private or geo-blocked
+pub fn is_terminal(stderr: &str) -> bool {
+    unsupported_content_reason(stderr).is_some()
+        || auth_required_reason(stderr).is_some()
+        || is_source_gone(stderr)
+}
+
+// Tracks the automatic yt-dlp update + single retry done after extractor failures
+#[derive(Default)]
+pub struct ExtractorRecovery {
+    // Items that already got their one retry
+    retried_items: Mutex<HashSet<String>>,
+    // Last update attempt and its result, shared by all items failing around the same time
+    last_update: tokio::sync::Mutex<Option<(Instant, Result<String, String>)>>,
+}
+
+impl ExtractorRecovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Claim the single automatic retry for an item. Returns false if it was already used.
+    pub fn claim_retry(&self, item_id: &str) -> bool {
+        self.retried_items
+            .lock()
+            .unwrap()
+            .insert(item_id.to_string())
+    }
+
+    // Update yt-dlp, reusing the previous result if an update ran recently
+    pub async fn update_ytdlp(&self) -> Result<String, String> {
+        let mut last_update = self.last_update.lock().await;
+        if let Some((ran_at, result)) = last_update.as_ref() {
+            if ran_at.elapsed() < YTDLP_UPDATE_COOLDOWN {
+                return result.clone();
+            }
+        }
+
+        let result = if ytdlp_manager::is_managed() {
+            println!("Updating the managed yt-dlp after extractor failure...");
+            ytdlp_manager::install()
+                .await
+                .map(|version| format!("Managed yt-dlp is at {}", version))
+        } else {
+            self_update().await
+        };
+
+        *last_update = Some((Instant::now(), result.clone()));
+        result
+    }
+}
+
+// `yt-dlp -U`, for a yt-dlp the app doesn't manage
+async fn self_update() -> Result<String, String> {
+    println!("Running yt-dlp -U after extractor failure...");
+    match Command::new(ytdlp_binary()).arg("-U").output().await {
+        Ok(output) => {
+            let stdout = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+            if output.status.success() {
+                Ok(stdout)
+            } else {
+                Err(format!(
+                    "yt-dlp -U exited with code {:?}: {}",
+                    output.status.code(),
+                    if stderr.is_empty() { stdout } else { stderr }
+                ))
+            }
+        }
+        Err(e) => Err(format!("Failed to run yt-dlp -U: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognises_login_walls() {
+        assert_eq!(
+            auth_required_reason(
+                "ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate for some users."
+            ),
+            Some("The video is age-restricted")
+        );
+        assert_eq!(
+            auth_required_reason(
+                "ERROR: [youtube] abc: Private video. Sign in if you've been granted access"
+            ),
+            Some("The video is private")
+        );
+    }
+
+    #[test]
+    fn only_reads_error_lines() {
+        assert_eq!(
+            auth_required_reason("WARNING: [youtube] Private video in playlist skipped"),
+            None
+        );
+        assert_eq!(
+            auth_required_reason("ERROR: Unsupported URL: https://example.com"),
+            None
+        );
+    }
+}
@@ -0,0 +1,450 @@
+// Optional re-encoding between download and upload, for hosts that reject some
+// codecs (AV1, or HEVC from Facebook). transcode_target names the output as
+// "<video codec>/<audio codec> <container>", e.g. "h264/aac mp4"; blank leaves the
+// stage off. transcode_crf sets the quality (lower is better, 23 by default) and
+// transcode_max_height scales taller videos down, e.g. to 1080.
+//
+// The item is "transcoding" while ffmpeg runs, with the percent done parsed from
+// ffmpeg's -progress output against the duration ffprobe reports. A file that
+// already has the target codecs, container and height is left as it is. The new file
+// replaces the download; when ffmpeg fails the download is kept and the item fails
+// with ffmpeg's reason.
+
+use serde_json::Value as JsonValue;
+use std::fs;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Manager};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+use crate::cancellation;
+use crate::db::AppSettings;
+use crate::messages::{self, ItemMessage};
+use crate::performance;
+use crate::progress::WriteThrottle;
+use crate::{disk_space, jobs, long_paths, tools, AppState};
+
+pub const DEFAULT_CRF: u32 = 23;
+const MAX_CRF: u32 = 51;
+
+// Target name, ffmpeg encoder, and the codec name ffprobe reports for it
+const VIDEO_CODECS: &[(&str, &str, &str)] = &[
+    ("h264", "libx264", "h264"),
+    ("h265", "libx265", "hevc"),
+    ("vp9", "libvpx-vp9", "vp9"),
+];
+const AUDIO_CODECS: &[(&str, &str, &str)] = &[
+    ("aac", "aac", "aac"),
+    ("opus", "libopus", "opus"),
+    ("mp3", "libmp3lame", "mp3"),
+];
+const CONTAINERS: &[&str] = &["mp4", "mkv", "webm"];
+
+// Lines of ffmpeg's stderr kept for the failure message
+const STDERR_TAIL_LINES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    video: (&'static str, &'static str, &'static str),
+    audio: (&'static str, &'static str, &'static str),
+    container: &'static str,
+    crf: u32,
+    max_height: Option<u32>,
+}
+
+fn setting(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(|v| v.trim().to_ascii_lowercase())
+        .filter(|v| !v.is_empty())
+}
+
+fn lookup(
+    table: &[(&'static str, &'static str, &'static str)],
+    name: &str,
+) -> Option<(&'static str, &'static str, &'static str)> {
+    table.iter().copied().find(|(target, _, _)| *target == name)
+}
+
+impl Target {
+    // None when transcode_target is blank
+    pub fn from_settings(settings: &AppSettings) -> Result<Option<Target>, String> {
+        let Some(value) = setting(&settings.transcode_target) else {
+            return Ok(None);
+        };
+        let invalid = || {
+            format!(
+                "Invalid transcode_target '{}'; use e.g. \"h264/aac mp4\" \
+                 (video: h264, h265, vp9; audio: aac, opus, mp3; container: mp4, mkv, webm)",
+                value
+            )
+        };
+
+        let mut parts = value.split_whitespace();
+        let codecs = parts.next().ok_or_else(invalid)?;
+        let container_name = parts.next().unwrap_or("mp4");
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        let (video_name, audio_name) = codecs.split_once('/').ok_or_else(invalid)?;
+        let video = lookup(VIDEO_CODECS, video_name.replace("hevc", "h265").as_str())
+            .ok_or_else(invalid)?;
+        let audio = lookup(AUDIO_CODECS, audio_name).ok_or_else(invalid)?;
+        let container = CONTAINERS
+            .iter()
+            .copied()
+            .find(|c| *c == container_name)
+            .ok_or_else(invalid)?;
+        if container == "webm" && (video.0 != "vp9" || audio.0 != "opus") {
+            return Err(format!(
+                "transcode_target '{}': webm only holds vp9 video with opus audio",
+                value
+            ));
+        }
+
+        let crf = match setting(&settings.transcode_crf) {
+            Some(crf) => match crf.parse::<u32>() {
+                Ok(crf) if crf <= MAX_CRF => crf,
+                _ => {
+                    return Err(format!(
+                        "Invalid transcode_crf '{}'; use a number from 0 to {}",
+                        crf, MAX_CRF
+                    ))
+                }
+            },
+            None => DEFAULT_CRF,
+        };
+        let max_height = match setting(&settings.transcode_max_height) {
+            Some(height) => match height.parse::<u32>() {
+                Ok(height) if height > 0 => Some(height),
+                _ => {
+                    return Err(format!(
+                        "Invalid transcode_max_height '{}'; use a height in pixels, e.g. 1080",
+                        height
+                    ))
+                }
+            },
+            None => None,
+        };
+
+        Ok(Some(Target {
+            video,
+            audio,
+            container,
+            crf,
+            max_height,
+        }))
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!("{}/{} {}", self.video.0, self.audio.0, self.container);
+        if let Some(height) = self.max_height {
+            text.push_str(&format!(" up to {}p", height));
+        }
+        text
+    }
+
+    fn ffmpeg_args(&self, input: &Path, output: &Path) -> Vec<String> {
+        let mut args: Vec<String> = vec![
+            "-y".into(),
+            "-hide_banner".into(),
+            "-nostats".into(),
+            "-progress".into(),
+            "pipe:1".into(),
+            "-i".into(),
+            input.to_string_lossy().to_string(),
+            "-map".into(),
+            "0:v:0".into(),
+            "-map".into(),
+            "0:a?".into(),
+            "-c:v".into(),
+            self.video.1.into(),
+            "-crf".into(),
+            self.crf.to_string(),
+            "-pix_fmt".into(),
+            "yuv420p".into(),
+        ];
+        // libvpx only honours the CRF as a constant-quality target with no bitrate
+        if self.video.0 == "vp9" {
+            args.extend(["-b:v".into(), "0".into()]);
+        }
+        if let Some(height) = self.max_height {
+            args.extend(["-vf".into(), format!("scale=-2:'min({},ih)'", height)]);
+        }
+        args.extend(["-c:a".into(), self.audio.1.into()]);
+        if self.container == "mp4" {
+            args.extend(["-movflags".into(), "+faststart".into()]);
+        }
+        args.push(output.to_string_lossy().to_string());
+        args
+    }
+}
+
+// Check the transcode settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    Target::from_settings(settings).map(|_| ())
+}
+
+// ffprobe is installed beside ffmpeg
+fn ffprobe_for(ffmpeg: &str) -> String {
+    let name = if cfg!(target_os = "windows") {
+        "ffprobe.exe"
+    } else {
+        "ffprobe"
+    };
+    if ffmpeg == "ffmpeg" {
+        return "ffprobe".to_string();
+    }
+    Path::new(ffmpeg)
+        .with_file_name(name)
+        .to_string_lossy()
+        .to_string()
+}
+
+struct Probe {
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    height: Option<u64>,
+    duration: Option<f64>,
+}
+
+async fn probe(ffprobe: &str, path: &Path) -> Option<Probe> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "stream=codec_type,codec_name,height:format=duration",
+            "-of",
+            "json",
+        ])
+        .arg(path)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let info: JsonValue = serde_json::from_slice(&output.stdout).ok()?;
+    let streams = info.get("streams")?.as_array()?;
+    let stream = |kind: &str| {
+        streams
+            .iter()
+            .find(|s| s.get("codec_type").and_then(|t| t.as_str()) == Some(kind))
+    };
+    let codec = |kind: &str| {
+        stream(kind)
+            .and_then(|s| s.get("codec_name"))
+            .and_then(|c| c.as_str())
+            .map(str::to_string)
+    };
+    Some(Probe {
+        video_codec: codec("video"),
+        audio_codec: codec("audio"),
+        height: stream("video")
+            .and_then(|s| s.get("height"))
+            .and_then(|h| h.as_u64()),
+        duration: info
+            .get("format")
+            .and_then(|f| f.get("duration"))
+            .and_then(|d| d.as_str())
+            .and_then(|d| d.parse().ok()),
+    })
+}
+
+// Length of a video in seconds, if ffmpeg is installed and ffprobe can read the file
+pub async fn duration(settings: &AppSettings, video: &Path) -> Option<f64> {
+    let ffmpeg = tools::detect_ffmpeg(settings.ffmpeg_path.as_deref()).await;
+    let ffmpeg_path = ffmpeg.path.filter(|_| ffmpeg.found)?;
+    probe(&ffprobe_for(&ffmpeg_path), &long_paths::extended(video))
+        .await?
+        .duration
+}
+
+// Whether the file already is what the target asks for
+fn already_matches(target: &Target, path: &Path, probe: &Probe) -> bool {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    extension.as_deref() == Some(target.container)
+        && probe.video_codec.as_deref() == Some(target.video.2)
+        && probe
+            .audio_codec
+            .as_deref()
+            .map_or(true, |codec| codec == target.audio.2)
+        && match (target.max_height, probe.height) {
+            (Some(max), Some(height)) => height <= max as u64,
+            (Some(_), None) => false,
+            (None, _) => true,
+        }
+}
+
+// "out_time_us=12345678" (ffmpeg before 5.0 printed the same value as out_time_ms)
+fn progress_seconds(line: &str) -> Option<f64> {
+    let (key, value) = line.split_once('=')?;
+    if key != "out_time_us" && key != "out_time_ms" {
+        return None;
+    }
+    value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .map(|micros| micros / 1_000_000.0)
+}
+
+async fn set_status(app_state: &AppState, item_id: &str, message: ItemMessage) {
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "transcoding", Some(message))
+        .await
+    {
+        eprintln!("Error updating transcoding status of {}: {}", item_id, e);
+    }
+}
+
+// Transcode a finished download if the settings ask for it. Returns the new file's
+// path, None when nothing had to be done, or why ffmpeg failed.
+pub async fn run(
+    app_handle: &AppHandle,
+    item_id: &str,
+    video: &Path,
+    settings: &AppSettings,
+) -> Result<Option<String>, String> {
+    let Some(target) = Target::from_settings(settings)? else {
+        return Ok(None);
+    };
+    let app_state = app_handle.state::<AppState>();
+
+    let ffmpeg = tools::detect_ffmpeg(settings.ffmpeg_path.as_deref()).await;
+    let ffmpeg_path = match (ffmpeg.found, ffmpeg.path) {
+        (true, Some(path)) => path,
+        _ => return Err("ffmpeg not found".to_string()),
+    };
+    let input = long_paths::extended(video);
+    let probed = probe(&ffprobe_for(&ffmpeg_path), &input).await;
+    if let Some(probed) = &probed {
+        if already_matches(&target, video, probed) {
+            println!(
+                "Item {}: already {}, not transcoding",
+                item_id,
+                target.describe()
+            );
+            return Ok(None);
+        }
+    }
+    let duration = probed.and_then(|p| p.duration).filter(|d| *d > 0.0);
+
+    // The new file is written beside the download, about as big, before replacing it
+    let needed = fs::metadata(&input).map(|m| m.len()).unwrap_or(0)
+        + disk_space::min_free_bytes(settings).unwrap_or(0);
+    if let Ok(free) = disk_space::free_bytes(video.parent().unwrap_or(video)) {
+        if free < needed {
+            return Err(format!(
+                "Not enough disk space to transcode: {} MB needed, {} MB free",
+                needed / (1024 * 1024),
+                free / (1024 * 1024)
+            ));
+        }
+    }
+
+    let stem = video
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| item_id.to_string());
+    let final_path = video.with_file_name(format!("{}.{}", stem, target.container));
+    let work_path = video.with_file_name(format!("{}.transcoding.{}", stem, target.container));
+    let work = long_paths::extended(&work_path);
+
+    println!("Item {}: transcoding to {}", item_id, target.describe());
+    set_status(
+        &app_state,
+        item_id,
+        ItemMessage::new(messages::TRANSCODE_STARTING).with("target", target.describe()),
+    )
+    .await;
+
+    let cancel_guard = app_state.cancellations.register(item_id);
+    let job = app_state.jobs.start(
+        jobs::KIND_TRANSCODE,
+        Some(item_id),
+        cancel_guard.token().clone(),
+    );
+
+    let mut child = cancellation::in_own_group(&mut Command::new(&ffmpeg_path))
+        .args(target.ffmpeg_args(&input, &work))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run ffmpeg: {}", e))?;
+    if let Some(pid) = child.id() {
+        cancel_guard.track_process(pid);
+        performance::apply(pid, performance::profile(settings).priority).await;
+    }
+
+    let stderr_tail = Arc::new(Mutex::new(Vec::new()));
+    if let Some(stderr) = child.stderr.take() {
+        let stderr_tail = stderr_tail.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let mut tail = stderr_tail.lock().unwrap();
+                tail.push(line);
+                if tail.len() > STDERR_TAIL_LINES {
+                    tail.remove(0);
+                }
+            }
+        });
+    }
+
+    let stdout = child.stdout.take();
+    // Read -progress to the end even without a duration, so ffmpeg never writes to
+    // a closed pipe
+    let progress = async {
+        let Some(stdout) = stdout else {
+            return;
+        };
+        let mut lines = BufReader::new(stdout).lines();
+        let mut throttle = WriteThrottle::new();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let (Some(seconds), Some(duration)) = (progress_seconds(&line), duration) else {
+                continue;
+            };
+            let percent = ((seconds / duration) * 100.0).clamp(0.0, 100.0) as f32;
+            let message = ItemMessage::new(messages::TRANSCODE_PROGRESS)
+                .with("percent", (percent as f64 * 10.0).round() / 10.0);
+            job.update(Some(percent), Some(message.text()));
+            if throttle.should_write(percent) {
+                set_status(&app_state, item_id, message).await;
+            }
+        }
+    };
+
+    let status = tokio::select! {
+        (status, _) = async { tokio::join!(child.wait(), progress) } => status,
+        _ = cancel_guard.token().cancelled() => {
+            cancellation::kill_process_tree(&mut child).await;
+            let _ = fs::remove_file(&work);
+            return Err("Transcoding cancelled by user".to_string());
+        }
+    };
+
+    let succeeded = matches!(&status, Ok(status) if status.success()) && work.exists();
+    if !succeeded {
+        let _ = fs::remove_file(&work);
+        let tail = stderr_tail.lock().unwrap().join(" | ");
+        return Err(match status {
+            Err(e) => format!("ffmpeg did not finish: {}", e),
+            Ok(_) if tail.is_empty() => "ffmpeg exited with an error".to_string(),
+            Ok(_) => tail,
+        });
+    }
+
+    // The transcoded file takes the place of the download
+    fs::remove_file(&input).map_err(|e| format!("Failed to remove the original: {}", e))?;
+    fs::rename(&work, long_paths::extended(&final_path))
+        .map_err(|e| format!("Failed to move the transcoded file into place: {}", e))?;
+    println!("Item {}: transcoded to {}", item_id, final_path.display());
+    Ok(Some(final_path.to_string_lossy().to_string()))
+}
@@ -0,0 +1,1798 @@
+// Uploading finished downloads. perform_upload, run by the upload worker queue (see
+// upload_queue.rs), sends an item to its main target (Filemoon, archive.org, S3 or a
+// declared provider, see providers.rs) with retries, holds it while Filemoon is down
+// or the upload quota is used up, and then copies it to any mirrors.
+
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tauri::Manager;
+use tokio::time::sleep;
+use tokio_util::io::ReaderStream;
+
+use crate::db::{AppSettings, QueueItem};
+use crate::filemoon::{
+    self, FilemoonEncodingStatusResponse, FilemoonFileInfoResponse,
+    FilemoonGetUploadServerResponse, FilemoonUploadResponse,
+};
+use crate::messages::{self, ItemMessage};
+use crate::providers::{self, ProviderDefinition, StepError};
+use crate::queue_commands::sync_thumbnail;
+use crate::quota::{self, QuotaCheck};
+use crate::{
+    archive_org, bandwidth, http, jobs, long_paths, mirrors, provider_watch, s3, sanitize_filename,
+    shortener, titles, transcode, watchdog, AppState, Response,
+};
+
+// Persist a failed provider call; a failure here is only logged
+pub(crate) async fn record_provider_error(
+    app_state: &AppState,
+    item_id: Option<&str>,
+    endpoint: &str,
+    http_status: Option<u16>,
+    message: &str,
+    response_body: Option<&str>,
+) {
+    if let Err(e) = app_state
+        .db
+        .record_provider_error(
+            item_id,
+            endpoint,
+            http_status.map(i32::from),
+            message,
+            response_body,
+        )
+        .await
+    {
+        eprintln!("Failed to record provider error for {}: {}", endpoint, e);
+    }
+}
+
+// Store a short link for a freshly uploaded item if a shortener is configured.
+// Failures are logged only; the upload itself already succeeded.
+pub async fn create_short_url(
+    app_state: &AppState,
+    item_id: &str,
+    filecode: &str,
+    settings: &AppSettings,
+) {
+    let base_url = settings.shortener_url.as_deref();
+    let api_key = settings.shortener_api_key.as_deref();
+    if !shortener::is_configured(base_url, api_key) {
+        return;
+    }
+
+    let target = filemoon::player_url(filecode);
+    match shortener::shorten(base_url.unwrap(), api_key.unwrap(), &target).await {
+        Ok(short_url) => {
+            println!("Short link for item {}: {}", item_id, short_url);
+            if let Err(e) = app_state.db.set_short_url(item_id, &short_url).await {
+                eprintln!("Failed to store short link for item {}: {}", item_id, e);
+            }
+        }
+        Err(e) => eprintln!("Failed to create short link for item {}: {}", item_id, e),
+    }
+}
+
+// Hold an upload while Filemoon is down for maintenance instead of failing it.
+// provider_watch resumes held items once the API answers again.
+async fn hold_for_provider(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    item_id: &str,
+    detail: &str,
+) {
+    println!("Filemoon looks unavailable, holding item {}: {}", item_id, detail);
+    if let Err(e) = app_state
+        .db
+        .update_item_status(
+            item_id,
+            provider_watch::HELD_STATUS,
+            Some(ItemMessage::new(messages::UPLOAD_HELD_MAINTENANCE)),
+        )
+        .await
+    {
+        eprintln!("Error holding item {} for provider outage: {}", item_id, e);
+    }
+    provider_watch::report_outage(app_handle, &app_state.provider_watch, detail);
+}
+
+// Wait out the backoff before retrying a failed upload step, showing the retry on the
+// item. Returns false when the upload is cancelled during the wait.
+async fn wait_for_upload_retry(
+    app_state: &AppState,
+    job: &jobs::JobGuard<'_>,
+    cancel: &tokio_util::sync::CancellationToken,
+    item_id: &str,
+    step: &str,
+    attempt: u32,
+    error: &str,
+) -> bool {
+    let delay = filemoon::retry_delay(attempt);
+    let message = ItemMessage::new(messages::UPLOAD_RETRYING)
+        .with("step", step)
+        .with("attempt", attempt)
+        .with("max_attempts", filemoon::UPLOAD_MAX_ATTEMPTS)
+        .with("error", error)
+        .with("seconds", delay.as_secs());
+    println!("Item {}: {}", item_id, message.text());
+    job.update(None, Some(message.text()));
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "uploading", Some(message))
+        .await
+    {
+        eprintln!("Error updating status before upload retry: {}", e);
+    }
+
+    tokio::select! {
+        _ = sleep(delay) => true,
+        _ = cancel.cancelled() => {
+            println!("Upload cancelled for item {} while waiting to retry", item_id);
+            false
+        }
+    }
+}
+
+// Hold the item as "on_hold" instead of uploading if the upload would blow this
+// month's quota for `provider`; warns as the quota nears
+async fn hold_over_quota(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    settings: &AppSettings,
+    user_id: &str,
+    item_id: &str,
+    provider: &str,
+    upload_bytes: i64,
+) -> Result<(), String> {
+    match quota::check_upload(
+        app_state,
+        settings,
+        user_id,
+        item_id,
+        provider,
+        upload_bytes,
+    )
+    .await
+    {
+        Ok(QuotaCheck::Ok) => {}
+        Ok(QuotaCheck::Warn(warning)) => {
+            println!("{}", warning.message);
+            if let Err(e) = app_handle.emit_all("quota_warning", warning) {
+                eprintln!("Failed to emit quota_warning event: {}", e);
+            }
+        }
+        Ok(QuotaCheck::Exceeded(warning)) => {
+            println!("{}", warning.message);
+            if let Err(e) = app_state
+                .db
+                .update_item_status(
+                    item_id,
+                    quota::HELD_STATUS,
+                    Some(quota::exceeded_message(&warning)),
+                )
+                .await
+            {
+                eprintln!("Error updating status after quota hold: {}", e);
+            }
+            let message = warning.message.clone();
+            if let Err(e) = app_handle.emit_all("quota_warning", warning) {
+                eprintln!("Failed to emit quota_warning event: {}", e);
+            }
+            return Err(message);
+        }
+        Err(e) => eprintln!("Quota check failed, uploading anyway: {}", e),
+    }
+    Ok(())
+}
+
+// Runs a single upload; only called from the upload worker queue. The file goes to
+// the main upload target first and is then copied to any mirrors (see mirrors.rs).
+pub async fn perform_upload(
+    id: String,
+    user_id: String,
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+) -> Result<Response<String>, String> {
+    let settings = app_state
+        .db
+        .get_settings(&user_id)
+        .await
+        .map_err(|e| format!("Failed to retrieve settings: {}", e))?;
+    let (settings, mirrors_only) = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => (
+            mirrors::item_settings(&settings, &item),
+            mirrors::UPLOADED_ITEM_STATUSES.contains(&item.status.as_str()),
+        ),
+        Ok(None) => return Err(format!("Upload failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let main_target = mirrors::main_target(&settings);
+
+    let response = if mirrors_only {
+        Response {
+            success: true,
+            message: format!("Already uploaded to {}", main_target),
+            data: Some(id.clone()),
+        }
+    } else {
+        let outcome = upload_to_main_target(id.clone(), user_id, app_handle, app_state).await;
+        mirrors::record_main(app_state, &id, &main_target).await;
+        outcome?
+    };
+
+    let uploads = app_state.db.get_uploads(&id).await.unwrap_or_default();
+    let pending = mirrors::pending(&settings, &uploads);
+    if pending.is_empty() {
+        if mirrors_only {
+            return Err(format!("Item {} is already uploaded to every target.", id));
+        }
+        return Ok(response);
+    }
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Upload failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+    let failed = mirrors::upload(app_state, &item, &settings, &pending).await;
+    let message = if failed == 0 {
+        format!("{}; mirrored to {}", response.message, pending.join(", "))
+    } else {
+        format!(
+            "{}; {} of {} mirror upload(s) failed",
+            response.message,
+            failed,
+            pending.len()
+        )
+    };
+    Ok(Response {
+        message,
+        ..response
+    })
+}
+
+// Upload to the main upload target, which the item's status and links follow
+async fn upload_to_main_target(
+    id: String,
+    user_id: String,
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+) -> Result<Response<String>, String> {
+    let local_path_str: String;
+    let filename: String;
+    let settings_clone: AppSettings; // Clone settings to use outside lock
+    let item_id_clone = id.clone(); // Clone id
+
+    // Get initial data and mark as uploading
+    let item = match app_state.db.get_item_by_id(&id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return Err(format!("Upload failed: Item {} not found.", id)),
+        Err(e) => return Err(format!("Database error retrieving item: {}", e)),
+    };
+
+    settings_clone = match app_state.db.get_settings(&user_id).await {
+        Ok(settings) => mirrors::item_settings(&settings, &item),
+        Err(e) => return Err(format!("Failed to retrieve settings: {}", e)),
+    };
+
+    if item.locked == Some(true) {
+        return Err(format!(
+            "Item {} is locked. Unlock it before uploading.",
+            id
+        ));
+    }
+
+    // Only allow downloaded status for upload (or items held back by a quota)
+    if item.status != "downloaded" && item.status != quota::HELD_STATUS {
+        return Err(format!(
+            "Item {} is not in a downloaded state (status: {}). Cannot upload.",
+            id, item.status
+        ));
+    }
+
+    // Check if local_path exists
+    local_path_str = match &item.local_path {
+        Some(p) if !p.is_empty() => p.clone(),
+        Some(_) => return Err(format!("Upload failed: Local file path is empty for item.")),
+        None => {
+            return Err(format!(
+                "Upload failed: Local file path not found for item."
+            ))
+        }
+    };
+
+    let local_path_check = Path::new(&local_path_str); // Need Path for filename
+    filename = local_path_check
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown_file")
+        .to_string();
+
+    // Lets cancel_item abort the upload request while it is in flight
+    let cancel_guard = app_state.cancellations.register(&id);
+    let job = app_state.jobs.start(jobs::KIND_UPLOAD, Some(&id), cancel_guard.token().clone());
+
+    if let Err(e) = app_state
+        .db
+        .update_item_status(&id, "uploading", Some(ItemMessage::new(messages::UPLOAD_STARTING)))
+        .await
+    {
+        return Err(format!("Failed to update item status to uploading: {}", e));
+    }
+
+    // Check file existence
+    let local_path = long_paths::extended(Path::new(&local_path_str));
+    if !local_path.exists() {
+        // Print more diagnostics
+        println!("File path check failed: {} does not exist", local_path_str);
+
+        // Check if parent directory exists to provide better error information
+        if let Some(parent) = local_path.parent() {
+            if !parent.exists() {
+                println!("Parent directory {} does not exist", parent.display());
+            } else {
+                println!(
+                    "Parent directory {} exists, but file is missing",
+                    parent.display()
+                );
+            }
+        }
+
+        // Update status
+        if let Err(e) = app_state
+            .db
+            .update_item_status(
+                &item_id_clone,
+                "failed",
+                Some(
+                    ItemMessage::new(messages::UPLOAD_FILE_MISSING)
+                        .with("path", local_path_str.clone()),
+                ),
+            )
+            .await
+        {
+            eprintln!("Error updating status after file not found: {}", e);
+        }
+
+        return Err(format!(
+            "Upload failed: Local file does not exist at {}",
+            local_path_str
+        ));
+    } else {
+        println!("File path check passed: {} exists", local_path_str);
+    }
+
+    // Perform uploads outside lock
+    let mut success = false; // Track success status
+    let mut filecode = String::new(); // Initialize filecode for later use
+    let client = http::client();
+
+    // Providers declared in providers.json take Filemoon's place entirely
+    let main_target = mirrors::main_target(&settings_clone);
+    if let Some(definition) = app_state.providers.get(&main_target) {
+        return upload_to_declared_provider(
+            app_handle,
+            app_state,
+            &item,
+            &settings_clone,
+            definition,
+            cancel_guard.token(),
+            &job,
+        )
+        .await;
+    }
+
+    if main_target == archive_org::PROVIDER {
+        return upload_to_archive_org(
+            app_handle,
+            app_state,
+            &item,
+            &settings_clone,
+            cancel_guard.token(),
+            &job,
+        )
+        .await;
+    }
+    if main_target == s3::PROVIDER {
+        return upload_to_s3(
+            app_handle,
+            app_state,
+            &item,
+            &settings_clone,
+            cancel_guard.token(),
+            &job,
+        )
+        .await;
+    }
+
+    // --- Filemoon Upload Logic ---
+    let api_key = match settings_clone.filemoon_api_key.clone() {
+        Some(key) if !key.is_empty() => key,
+        _ => {
+            if let Err(e) = app_state
+                .db
+                .update_item_status(
+                    &item_id_clone,
+                    "failed",
+                    Some(ItemMessage::new(messages::UPLOAD_API_KEY_MISSING)),
+                )
+                .await
+            {
+                eprintln!("Error updating status after API key missing: {}", e);
+            }
+
+            return Err("Filemoon API key not configured".to_string());
+        }
+    };
+
+    let upload_bytes = fs::metadata(&local_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+
+    // On a shared account the same video may be there already; link to it instead
+    // of spending quota on a second copy
+    if settings_clone.reuse_existing_uploads.as_deref() == Some("true") {
+        let upload_name = sanitize_filename(&titles::upload_file_name(
+            item.title.as_deref(),
+            &item.url,
+            &filename,
+        ));
+        job.update(None, Some("Looking for an existing upload".to_string()));
+        let duration = transcode::duration(&settings_clone, Path::new(&local_path_str)).await;
+        match filemoon::find_existing_file(
+            &client,
+            &api_key,
+            &upload_name,
+            upload_bytes.max(0) as u64,
+            duration,
+        )
+        .await
+        {
+            Ok(Some(existing)) => {
+                return link_existing_upload(app_state, &item_id_clone, &existing, &settings_clone)
+                    .await
+            }
+            Ok(None) => {}
+            Err(e) => println!(
+                "Uploading item {} without deduplication: {}",
+                item_id_clone, e
+            ),
+        }
+    }
+
+    // Hold the item instead of uploading if it would blow this month's quota
+    hold_over_quota(
+        app_handle,
+        app_state,
+        &settings_clone,
+        &user_id,
+        &item_id_clone,
+        "filemoon",
+        upload_bytes,
+    )
+    .await?;
+
+    println!("Attempting to upload {} to Filemoon...", filename);
+
+    // --- Step 1: Get Upload Server URL ---
+    // Timeouts, dropped connections and 5xx answers are retried with backoff
+    job.update(None, Some("Requesting upload server".to_string()));
+    let upload_server_url: String;
+    let mut attempt = 1;
+    let server_result = loop {
+        let result = client
+            .get(filemoon::upload_server_endpoint())
+            .query(&[("key", &api_key)])
+            .send()
+            .await;
+        let transient_error = match &result {
+            Ok(response) if filemoon::is_transient_status(response.status().as_u16()) => {
+                Some(format!("HTTP {}", response.status()))
+            }
+            Err(e) if filemoon::is_transient_error(e) => Some(e.to_string()),
+            _ => None,
+        };
+        match transient_error {
+            Some(error) if attempt < filemoon::UPLOAD_MAX_ATTEMPTS => {
+                if !wait_for_upload_retry(
+                    app_state,
+                    &job,
+                    cancel_guard.token(),
+                    &item_id_clone,
+                    "GetServer",
+                    attempt,
+                    &error,
+                )
+                .await
+                {
+                    return Err("Upload cancelled by user".to_string());
+                }
+                attempt += 1;
+            }
+            _ => break result,
+        }
+    };
+    match server_result {
+        Ok(response) => {
+            let get_server_status = response.status();
+            match response.json::<FilemoonGetUploadServerResponse>().await {
+                Ok(resp_body) => {
+                    if filemoon::is_ok_response(get_server_status.as_u16(), resp_body.status)
+                        && !resp_body.result.is_empty()
+                    {
+                        upload_server_url = resp_body.result;
+                        println!("Got Filemoon upload server: {}", upload_server_url);
+                    } else {
+                        let failure = ItemMessage::new(messages::UPLOAD_SERVER_ERROR)
+                            .with("status", resp_body.status)
+                            .with("error", resp_body.msg.clone());
+                        let err_msg = failure.text();
+                        println!("{}", err_msg);
+                        record_provider_error(
+                            app_state,
+                            Some(&item_id_clone),
+                            "upload/server",
+                            Some(get_server_status.as_u16()),
+                            &err_msg,
+                            serde_json::to_string(&resp_body).ok().as_deref(),
+                        )
+                        .await;
+
+                        if filemoon::is_maintenance_response(
+                            get_server_status.as_u16(),
+                            resp_body.status,
+                            &resp_body.msg,
+                        ) {
+                            hold_for_provider(app_handle, app_state, &item_id_clone, &err_msg)
+                                .await;
+                        } else if let Err(e) = app_state
+                            .db
+                            .update_item_status(&item_id_clone, "failed", Some(failure))
+                            .await
+                        {
+                            eprintln!("Error updating status after API error: {}", e);
+                        }
+
+                        return Err(err_msg); // Stop here if we can't get upload server
+                    }
+                }
+                Err(e) => {
+                    let failure = ItemMessage::new(messages::UPLOAD_SERVER_PARSE_FAILED)
+                        .with("error", e.to_string());
+                    let err_msg = failure.text();
+                    println!("{}", err_msg);
+                    record_provider_error(
+                        app_state,
+                        Some(&item_id_clone),
+                        "upload/server",
+                        Some(get_server_status.as_u16()),
+                        &err_msg,
+                        None,
+                    )
+                    .await;
+
+                    if filemoon::is_maintenance_response(get_server_status.as_u16(), None, "") {
+                        hold_for_provider(app_handle, app_state, &item_id_clone, &err_msg).await;
+                    } else if let Err(db_e) = app_state
+                        .db
+                        .update_item_status(&item_id_clone, "failed", Some(failure))
+                        .await
+                    {
+                        eprintln!("Error updating status after parse error: {}", db_e);
+                    }
+
+                    return Err(err_msg);
+                }
+            }
+        }
+        Err(e) => {
+            let failure = ItemMessage::new(messages::UPLOAD_SERVER_REQUEST_FAILED)
+                .with("error", e.to_string());
+            let err_msg = failure.text();
+            println!("{}", err_msg);
+
+            if let Err(db_e) = app_state
+                .db
+                .update_item_status(&item_id_clone, "failed", Some(failure))
+                .await
+            {
+                eprintln!("Error updating status after request error: {}", db_e);
+            }
+
+            return Err(err_msg);
+        }
+    }
+
+    // --- Step 1b: Probe the server before a large upload ---
+    // A dead node otherwise only shows up once the whole file has been sent
+    let mut upload_server_url = upload_server_url;
+    if upload_bytes as u64 >= filemoon::PROBE_MIN_BYTES {
+        let mut servers_tried = 1;
+        while let Err(reason) = filemoon::probe_upload_server(&client, &upload_server_url).await {
+            let err_msg = format!("Filemoon upload server {} is {}", upload_server_url, reason);
+            println!("{}", err_msg);
+            record_provider_error(
+                app_state,
+                Some(&item_id_clone),
+                "upload/probe",
+                None,
+                &err_msg,
+                None,
+            )
+            .await;
+
+            let next_server = if servers_tried < filemoon::MAX_SERVER_PROBES {
+                filemoon::request_upload_server(&client, &api_key).await
+            } else {
+                Err(format!(
+                    "No healthy Filemoon upload server after {} tries",
+                    servers_tried
+                ))
+            };
+            match next_server {
+                Ok(url) => {
+                    servers_tried += 1;
+                    println!("Trying replacement Filemoon upload server: {}", url);
+                    upload_server_url = url;
+                }
+                Err(e) => {
+                    let failure = ItemMessage::new(messages::UPLOAD_NO_HEALTHY_SERVER)
+                        .with("error", e)
+                        .with("detail", err_msg);
+                    let err_msg = failure.text();
+                    if let Err(db_e) = app_state
+                        .db
+                        .update_item_status(&item_id_clone, "failed", Some(failure))
+                        .await
+                    {
+                        eprintln!("Error updating status after server probe: {}", db_e);
+                    }
+                    return Err(err_msg);
+                }
+            }
+        }
+    }
+
+    // --- Step 2: Upload to the Obtained Server URL ---
+    // Files are stored under their video id; Filemoon gets the item's title instead.
+    // Sanitize the filename before sending it to Filemoon
+    let sanitized_filename = sanitize_filename(&titles::upload_file_name(
+        item.title.as_deref(),
+        &item.url,
+        &filename,
+    ));
+    println!("Sanitized filename for upload: {}", sanitized_filename);
+
+    // POST to the URL obtained in Step 1; dropping the request future aborts the upload.
+    // A transient failure is retried with backoff on a freshly requested server. Filemoon
+    // takes the file as a single multipart POST with no way to resume a partial upload,
+    // so each retry sends the file from the start.
+    let upload_limit = watchdog::upload_limit(&settings_clone);
+    // The time limit covers all attempts together
+    let upload_deadline = watchdog::deadline(upload_limit);
+    tokio::pin!(upload_deadline);
+    let mut attempt = 1;
+    let (send_result, upload_started) = loop {
+        // Stream the file in chunks so memory use stays flat however large the video is.
+        // The length is given up front so the request carries a Content-Length.
+        let file = tokio::fs::File::open(&local_path)
+            .await
+            .map_err(|e| format!("Failed to open file: {}", e))?;
+        let file_stream = ReaderStream::with_capacity(file, filemoon::UPLOAD_CHUNK_BYTES);
+        let form = reqwest::multipart::Form::new()
+            .text("key", api_key.clone())
+            .part(
+                "file",
+                reqwest::multipart::Part::stream_with_length(
+                    reqwest::Body::wrap_stream(file_stream),
+                    upload_bytes.max(0) as u64,
+                )
+                .file_name(sanitized_filename.clone()),
+            );
+
+        // Log the upload details for debugging
+        job.update(None, Some(format!("Uploading {} MB", upload_bytes / (1024 * 1024))));
+        println!("Uploading to Filemoon URL: {}", upload_server_url);
+        println!("Streaming file data in {} KB chunks", filemoon::UPLOAD_CHUNK_BYTES / 1024);
+
+        let upload_started = std::time::Instant::now();
+        let result = tokio::select! {
+            result = client.post(&upload_server_url).multipart(form).send() => result,
+            _ = cancel_guard.token().cancelled() => {
+                println!("Upload cancelled for item {}", item_id_clone);
+                return Err("Upload cancelled by user".to_string());
+            }
+            _ = &mut upload_deadline => {
+                let failure = ItemMessage::new(messages::UPLOAD_TIMEOUT)
+                    .with("minutes", upload_limit.map_or(0, |limit| limit.as_secs() / 60));
+                let err_msg = failure.text();
+                println!("Item {}: {}", item_id_clone, err_msg);
+                if let Err(e) = app_state
+                    .db
+                    .update_item_status(&item_id_clone, "failed", Some(failure))
+                    .await
+                {
+                    eprintln!("Error updating status after upload timeout: {}", e);
+                }
+                return Err(err_msg);
+            }
+        };
+
+        let outcome = match result {
+            Ok(response) if filemoon::is_transient_status(response.status().as_u16()) => {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                let error = format!("HTTP {}", status);
+                record_provider_error(
+                    app_state,
+                    Some(&item_id_clone),
+                    "upload",
+                    Some(status.as_u16()),
+                    &format!("Filemoon upload attempt {} failed: {}", attempt, error),
+                    Some(&body),
+                )
+                .await;
+                Err(error)
+            }
+            Err(e) if filemoon::is_transient_error(&e) => Err(e.to_string()),
+            result => Ok(result),
+        };
+        let error = match outcome {
+            Ok(result) => break (result, upload_started),
+            Err(error) => error,
+        };
+        if attempt >= filemoon::UPLOAD_MAX_ATTEMPTS {
+            let failure = ItemMessage::new(messages::UPLOAD_REQUEST_FAILED).with(
+                "error",
+                format!("{} (after {} attempts)", error, attempt),
+            );
+            let err_msg = failure.text();
+            println!("{}", err_msg);
+            if let Err(db_e) = app_state
+                .db
+                .update_item_status(&item_id_clone, "failed", Some(failure))
+                .await
+            {
+                eprintln!("Error updating status after upload retries: {}", db_e);
+            }
+            return Err(err_msg);
+        }
+        if !wait_for_upload_retry(
+            app_state,
+            &job,
+            cancel_guard.token(),
+            &item_id_clone,
+            "Upload",
+            attempt,
+            &error,
+        )
+        .await
+        {
+            return Err("Upload cancelled by user".to_string());
+        }
+        // The server that failed may be the problem; keep it if no other is offered
+        match filemoon::request_upload_server(&client, &api_key).await {
+            Ok(url) => upload_server_url = url,
+            Err(e) => println!("Retrying on the same upload server: {}", e),
+        }
+        attempt += 1;
+    };
+
+    match send_result {
+        Ok(response) => {
+            // The file went over the wire whatever Filemoon made of it
+            app_state.throughput.record(
+                bandwidth::UPLOAD,
+                upload_bytes,
+                upload_started.elapsed(),
+            );
+            app_state.concurrency.record(
+                bandwidth::UPLOAD,
+                upload_bytes,
+                upload_started.elapsed(),
+            );
+            bandwidth::record(
+                app_state,
+                &user_id,
+                &item_id_clone,
+                bandwidth::UPLOAD,
+                upload_bytes,
+            )
+            .await;
+            let upload_status = response.status();
+            // Read the response body as text first for debugging
+            match response.text().await {
+                Ok(raw_text) => {
+                    // Now attempt to parse the raw text as JSON
+                    match serde_json::from_str::<FilemoonUploadResponse>(&raw_text) {
+                        Ok(resp_body) => {
+                            // Check using the parsed JSON
+                            if filemoon::is_ok_response(upload_status.as_u16(), resp_body.status)
+                                && resp_body
+                                    .files
+                                    .as_ref()
+                                    .and_then(|f| f.first())
+                                    .map_or(false, |f| !f.filecode.is_empty())
+                            {
+                                filecode = resp_body.files.unwrap().remove(0).filecode;
+                                println!("Filemoon upload successful! Filecode: {}", filecode);
+
+                                if let Err(e) = app_state
+                                    .db
+                                    .update_item_status(
+                                        &item_id_clone,
+                                        "uploaded",
+                                        Some(
+                                            ItemMessage::new(messages::UPLOAD_DONE)
+                                                .with("filecode", filecode.clone()),
+                                        ),
+                                    )
+                                    .await
+                                {
+                                    eprintln!(
+                                        "Error updating status after successful upload: {}",
+                                        e
+                                    );
+                                }
+
+                                // Update the item with the filecode
+                                let mut updated_item = app_state
+                                    .db
+                                    .get_item_by_id(&item_id_clone)
+                                    .await
+                                    .map_err(|e| format!("DB Error: {}", e))?
+                                    .unwrap();
+                                updated_item.filemoon_url = Some(filecode.clone());
+
+                                if let Err(e) = app_state.db.update_queue_item(&updated_item).await
+                                {
+                                    eprintln!("Failed to update Filemoon URL in DB: {}", e);
+                                }
+
+                                if let Err(e) = app_state
+                                    .db
+                                    .record_upload_usage(
+                                        &user_id,
+                                        &item_id_clone,
+                                        "filemoon",
+                                        upload_bytes,
+                                    )
+                                    .await
+                                {
+                                    eprintln!("Failed to record upload usage: {}", e);
+                                }
+
+                                create_short_url(
+                                    app_state,
+                                    &item_id_clone,
+                                    &filecode,
+                                    &settings_clone,
+                                )
+                                .await;
+
+                                success = true;
+                            } else {
+                                let failure = ItemMessage::new(messages::UPLOAD_API_ERROR)
+                                    .with("status", resp_body.status)
+                                    .with("error", resp_body.msg.clone())
+                                    .with("response", format!("{:?}", resp_body));
+                                let err_msg = failure.text();
+                                println!("{}", err_msg);
+                                record_provider_error(
+                                    app_state,
+                                    Some(&item_id_clone),
+                                    "upload",
+                                    Some(upload_status.as_u16()),
+                                    &err_msg,
+                                    Some(&raw_text),
+                                )
+                                .await;
+
+                                if filemoon::is_maintenance_response(
+                                    upload_status.as_u16(),
+                                    resp_body.status,
+                                    &resp_body.msg,
+                                ) {
+                                    hold_for_provider(
+                                        app_handle,
+                                        app_state,
+                                        &item_id_clone,
+                                        &err_msg,
+                                    )
+                                    .await;
+                                } else if let Err(e) = app_state
+                                    .db
+                                    .update_item_status(&item_id_clone, "failed", Some(failure))
+                                    .await
+                                {
+                                    eprintln!("Error updating status after API error: {}", e);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            // JSON parsing failed, use the raw text in the error message
+                            let failure = ItemMessage::new(messages::UPLOAD_PARSE_FAILED)
+                                .with("status", upload_status.to_string())
+                                .with("error", e.to_string())
+                                .with("body", raw_text.clone());
+                            let err_msg = failure.text();
+                            println!("{}", err_msg);
+                            record_provider_error(
+                                app_state,
+                                Some(&item_id_clone),
+                                "upload",
+                                Some(upload_status.as_u16()),
+                                &format!("Failed to parse Filemoon Upload JSON response: {}", e),
+                                Some(&raw_text),
+                            )
+                            .await;
+
+                            if filemoon::is_maintenance_response(
+                                upload_status.as_u16(),
+                                None,
+                                &raw_text,
+                            ) {
+                                hold_for_provider(app_handle, app_state, &item_id_clone, &err_msg)
+                                    .await;
+                            } else if let Err(db_e) = app_state
+                                .db
+                                .update_item_status(&item_id_clone, "failed", Some(failure))
+                                .await
+                            {
+                                eprintln!("Error updating status after parse error: {}", db_e);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Failed to even read the response body as text
+                    let failure = ItemMessage::new(messages::UPLOAD_READ_FAILED)
+                        .with("status", upload_status.to_string())
+                        .with("error", e.to_string());
+                    let err_msg = failure.text();
+                    println!("{}", err_msg);
+
+                    if let Err(db_e) = app_state
+                        .db
+                        .update_item_status(&item_id_clone, "failed", Some(failure))
+                        .await
+                    {
+                        eprintln!("Error updating status after response error: {}", db_e);
+                    }
+                }
+            }
+        }
+        Err(e) => {
+            let failure =
+                ItemMessage::new(messages::UPLOAD_REQUEST_FAILED).with("error", e.to_string());
+            let err_msg = failure.text();
+            println!("{}", err_msg);
+
+            if let Err(db_e) = app_state
+                .db
+                .update_item_status(&item_id_clone, "failed", Some(failure))
+                .await
+            {
+                eprintln!("Error updating status after request error: {}", db_e);
+            }
+        }
+    }
+
+    // Final result handling. With delete_after_upload the local file is kept until
+    // Filemoon confirms the upload; safe_delete removes it after that.
+    if success {
+        if settings_clone.delete_after_upload.as_deref() == Some("true") {
+            println!("Keeping local file {} until the upload is verified", local_path_str);
+        }
+        Ok(Response {
+            success: true,
+            message: format!("Upload to Filemoon successful (Filecode: {})", filecode),
+            data: Some(item_id_clone),
+        })
+    } else {
+        // If upload failed, return a generic error message since err_msg variable is used in multiple places
+        Err(format!(
+            "Upload failed. See the application logs for details."
+        ))
+    }
+}
+
+// Point the item at a file already on the Filemoon account instead of uploading it.
+// Nothing is sent, so no bandwidth or upload quota is recorded.
+async fn link_existing_upload(
+    app_state: &AppState,
+    item_id: &str,
+    filecode: &str,
+    settings: &AppSettings,
+) -> Result<Response<String>, String> {
+    println!(
+        "Item {} matches existing Filemoon file {}",
+        item_id, filecode
+    );
+    let mut item = app_state
+        .db
+        .get_item_by_id(item_id)
+        .await
+        .map_err(|e| format!("DB Error: {}", e))?
+        .ok_or_else(|| format!("Item {} not found.", item_id))?;
+    item.filemoon_url = Some(filecode.to_string());
+    if let Err(e) = app_state.db.update_queue_item(&item).await {
+        eprintln!("Failed to update Filemoon URL in DB: {}", e);
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(
+            item_id,
+            "uploaded",
+            Some(ItemMessage::new(messages::UPLOAD_LINKED_EXISTING).with("filecode", filecode)),
+        )
+        .await
+    {
+        eprintln!("Error updating status after linking existing upload: {}", e);
+    }
+    create_short_url(app_state, item_id, filecode, settings).await;
+
+    Ok(Response {
+        success: true,
+        message: format!("Linked to existing Filemoon file (Filecode: {})", filecode),
+        data: Some(item_id.to_string()),
+    })
+}
+
+async fn fail_declared_upload(
+    app_state: &AppState,
+    item_id: &str,
+    provider: &str,
+    error: &StepError,
+) -> String {
+    record_provider_error(
+        app_state,
+        Some(item_id),
+        &format!("{}/{}", provider, error.step),
+        error.http_status,
+        &error.message,
+        error.body.as_deref(),
+    )
+    .await;
+
+    let failure = ItemMessage::new(messages::UPLOAD_DECLARED_FAILED)
+        .with("provider", provider)
+        .with("error", error.message.clone());
+    let err_msg = failure.text();
+    println!("{}", err_msg);
+    if let Err(e) = app_state
+        .db
+        .update_item_status(item_id, "failed", Some(failure))
+        .await
+    {
+        eprintln!("Error updating status after {} upload: {}", provider, e);
+    }
+    err_msg
+}
+
+// Run `send` until it succeeds, retrying transient failures the way Filemoon
+// uploads are retried. Cancelling or running past the upload time limit ends it;
+// a failure that is final marks the item failed. Returns what `send` produced and
+// when its successful attempt started.
+async fn upload_with_retries<T, F, Fut>(
+    app_state: &AppState,
+    settings: &AppSettings,
+    job: &jobs::JobGuard<'_>,
+    cancel: &tokio_util::sync::CancellationToken,
+    item_id: &str,
+    provider: &str,
+    send: F,
+) -> Result<(T, std::time::Instant), String>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<T, StepError>>,
+{
+    let upload_limit = watchdog::upload_limit(settings);
+    let upload_deadline = watchdog::deadline(upload_limit);
+    tokio::pin!(upload_deadline);
+    let mut attempt = 1;
+    loop {
+        let upload_started = std::time::Instant::now();
+        let result = tokio::select! {
+            result = send() => result,
+            _ = cancel.cancelled() => {
+                println!("Upload cancelled for item {}", item_id);
+                return Err("Upload cancelled by user".to_string());
+            }
+            _ = &mut upload_deadline => {
+                let failure = ItemMessage::new(messages::UPLOAD_TIMEOUT)
+                    .with("minutes", upload_limit.map_or(0, |limit| limit.as_secs() / 60));
+                let err_msg = failure.text();
+                println!("Item {}: {}", item_id, err_msg);
+                if let Err(e) = app_state
+                    .db
+                    .update_item_status(item_id, "failed", Some(failure))
+                    .await
+                {
+                    eprintln!("Error updating status after upload timeout: {}", e);
+                }
+                return Err(err_msg);
+            }
+        };
+
+        let error = match result {
+            Ok(value) => return Ok((value, upload_started)),
+            Err(error) => error,
+        };
+        if !error.transient || attempt >= filemoon::UPLOAD_MAX_ATTEMPTS {
+            return Err(fail_declared_upload(app_state, item_id, provider, &error).await);
+        }
+        let step = format!("{} {}", provider, error.step);
+        if !wait_for_upload_retry(
+            app_state,
+            job,
+            cancel,
+            item_id,
+            &step,
+            attempt,
+            &error.message,
+        )
+        .await
+        {
+            return Err("Upload cancelled by user".to_string());
+        }
+        attempt += 1;
+    }
+}
+
+// Bookkeeping after an upload to a provider other than Filemoon: throughput,
+// bandwidth and quota usage, the link on the item, and the "uploaded" status
+async fn finish_provider_upload(
+    app_state: &AppState,
+    item_id: &str,
+    user_id: &str,
+    provider: &str,
+    url: &str,
+    upload_bytes: i64,
+    elapsed: Duration,
+) {
+    app_state
+        .throughput
+        .record(bandwidth::UPLOAD, upload_bytes, elapsed);
+    app_state
+        .concurrency
+        .record(bandwidth::UPLOAD, upload_bytes, elapsed);
+    bandwidth::record(app_state, user_id, item_id, bandwidth::UPLOAD, upload_bytes).await;
+
+    println!("Upload to {} successful: {}", provider, url);
+    // Recorded before the status change, so after-upload hooks see the link
+    if let Err(e) = app_state
+        .db
+        .set_provider_upload(item_id, provider, url)
+        .await
+    {
+        eprintln!("Failed to record {} upload in DB: {}", provider, e);
+    }
+    if let Err(e) = app_state
+        .db
+        .update_item_status(
+            item_id,
+            "uploaded",
+            Some(
+                ItemMessage::new(messages::UPLOAD_DECLARED_DONE)
+                    .with("provider", provider)
+                    .with("url", url),
+            ),
+        )
+        .await
+    {
+        eprintln!("Error updating status after successful upload: {}", e);
+    }
+    if let Err(e) = app_state
+        .db
+        .record_upload_usage(user_id, item_id, provider, upload_bytes)
+        .await
+    {
+        eprintln!("Failed to record upload usage: {}", e);
+    }
+}
+
+// Upload to a provider declared in providers.json (see providers.rs). Retries,
+// cancelling and the upload time limit work as they do for Filemoon.
+async fn upload_to_declared_provider(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    definition: &ProviderDefinition,
+    cancel: &tokio_util::sync::CancellationToken,
+    job: &jobs::JobGuard<'_>,
+) -> Result<Response<String>, String> {
+    let item_id = item.id.clone().unwrap_or_default();
+    let user_id = item.user_id.clone().unwrap_or_default();
+    let provider = definition.name.as_str();
+    // perform_upload has already checked the file is there
+    let local_path =
+        long_paths::extended(Path::new(item.local_path.as_deref().unwrap_or_default()));
+    let local_path = local_path.as_path();
+    let filename = local_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown_file");
+
+    let api_key = match definition.api_key() {
+        Ok(key) => key,
+        Err(e) => {
+            let error = StepError::new(providers::STEP_UPLOAD, e);
+            return Err(fail_declared_upload(app_state, &item_id, provider, &error).await);
+        }
+    };
+
+    let upload_bytes = fs::metadata(local_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+    hold_over_quota(
+        app_handle,
+        app_state,
+        settings,
+        &user_id,
+        &item_id,
+        provider,
+        upload_bytes,
+    )
+    .await?;
+
+    let file_name = sanitize_filename(&titles::upload_file_name(
+        item.title.as_deref(),
+        &item.url,
+        filename,
+    ));
+    println!("Attempting to upload {} to {}...", file_name, provider);
+
+    let client = http::client();
+    let (client, api_key, file_name) = (&client, &api_key, &file_name);
+    let (file_code, upload_started) = upload_with_retries(
+        app_state,
+        settings,
+        job,
+        cancel,
+        &item_id,
+        provider,
+        move || async move {
+            job.update(
+                None,
+                Some(format!(
+                    "Uploading {} MB to {}",
+                    upload_bytes / (1024 * 1024),
+                    provider
+                )),
+            );
+            let server = definition.request_server(client, api_key).await?;
+            definition
+                .send_file(
+                    client,
+                    server.as_deref(),
+                    api_key,
+                    local_path,
+                    file_name,
+                    upload_bytes.max(0) as u64,
+                )
+                .await
+        },
+    )
+    .await?;
+
+    let url = definition.link(&file_code);
+    finish_provider_upload(
+        app_state,
+        &item_id,
+        &user_id,
+        provider,
+        &url,
+        upload_bytes,
+        upload_started.elapsed(),
+    )
+    .await;
+
+    if definition.status.is_some() {
+        let watch_handle = app_handle.clone();
+        let watch_item_id = item_id.clone();
+        let watch_definition = definition.clone();
+        tokio::spawn(async move {
+            providers::watch(watch_handle, watch_item_id, watch_definition, file_code).await;
+        });
+    }
+
+    Ok(Response {
+        success: true,
+        message: format!("Upload to {} successful ({})", provider, url),
+        data: Some(item_id),
+    })
+}
+
+// Upload to the Internet Archive (see archive_org.rs)
+async fn upload_to_archive_org(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    cancel: &tokio_util::sync::CancellationToken,
+    job: &jobs::JobGuard<'_>,
+) -> Result<Response<String>, String> {
+    let item_id = item.id.clone().unwrap_or_default();
+    let user_id = item.user_id.clone().unwrap_or_default();
+    let provider = archive_org::PROVIDER;
+    // perform_upload has already checked the file is there
+    let local_path =
+        long_paths::extended(Path::new(item.local_path.as_deref().unwrap_or_default()));
+    let local_path = local_path.as_path();
+    let filename = local_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown_file");
+
+    let credentials = match archive_org::Credentials::from_settings(settings) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            let error = StepError::new(providers::STEP_UPLOAD, e);
+            return Err(fail_declared_upload(app_state, &item_id, provider, &error).await);
+        }
+    };
+
+    let upload_bytes = fs::metadata(local_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+    hold_over_quota(
+        app_handle,
+        app_state,
+        settings,
+        &user_id,
+        &item_id,
+        provider,
+        upload_bytes,
+    )
+    .await?;
+
+    let provenance = match app_state.db.get_provenance(&item_id).await {
+        Ok(record) => record,
+        Err(e) => {
+            eprintln!("Failed to load provenance for item {}: {}", item_id, e);
+            None
+        }
+    };
+    let metadata = archive_org::ItemMetadata::for_item(item, provenance.as_ref(), settings);
+    let identifier = archive_org::identifier(item);
+    let file_name = sanitize_filename(&titles::upload_file_name(
+        item.title.as_deref(),
+        &item.url,
+        filename,
+    ));
+    println!(
+        "Attempting to upload {} to archive.org item {}...",
+        file_name, identifier
+    );
+
+    let client = http::client();
+    let (client, credentials, metadata, identifier, file_name) =
+        (&client, &credentials, &metadata, &identifier, &file_name);
+    let ((), upload_started) = upload_with_retries(
+        app_state,
+        settings,
+        job,
+        cancel,
+        &item_id,
+        provider,
+        move || async move {
+            job.update(
+                None,
+                Some(format!(
+                    "Uploading {} MB to the Internet Archive",
+                    upload_bytes / (1024 * 1024)
+                )),
+            );
+            archive_org::upload(
+                client,
+                credentials,
+                identifier,
+                metadata,
+                local_path,
+                file_name,
+                upload_bytes.max(0) as u64,
+            )
+            .await
+        },
+    )
+    .await?;
+
+    let url = archive_org::details_url(identifier);
+    finish_provider_upload(
+        app_state,
+        &item_id,
+        &user_id,
+        provider,
+        &url,
+        upload_bytes,
+        upload_started.elapsed(),
+    )
+    .await;
+
+    Ok(Response {
+        success: true,
+        message: format!("Upload to the Internet Archive successful ({})", url),
+        data: Some(item_id),
+    })
+}
+
+// Upload to S3-compatible storage (see s3.rs)
+async fn upload_to_s3(
+    app_handle: &tauri::AppHandle,
+    app_state: &AppState,
+    item: &QueueItem,
+    settings: &AppSettings,
+    cancel: &tokio_util::sync::CancellationToken,
+    job: &jobs::JobGuard<'_>,
+) -> Result<Response<String>, String> {
+    let item_id = item.id.clone().unwrap_or_default();
+    let user_id = item.user_id.clone().unwrap_or_default();
+    let provider = s3::PROVIDER;
+    // perform_upload has already checked the file is there
+    let local_path =
+        long_paths::extended(Path::new(item.local_path.as_deref().unwrap_or_default()));
+    let local_path = local_path.as_path();
+    let filename = local_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("unknown_file");
+
+    let config = match s3::S3Config::from_settings(settings) {
+        Ok(config) => config,
+        Err(e) => {
+            let error = StepError::new(providers::STEP_UPLOAD, e);
+            return Err(fail_declared_upload(app_state, &item_id, provider, &error).await);
+        }
+    };
+
+    let upload_bytes = fs::metadata(local_path)
+        .map(|m| m.len() as i64)
+        .unwrap_or(0);
+    hold_over_quota(
+        app_handle,
+        app_state,
+        settings,
+        &user_id,
+        &item_id,
+        provider,
+        upload_bytes,
+    )
+    .await?;
+
+    let file_name = sanitize_filename(&titles::upload_file_name(
+        item.title.as_deref(),
+        &item.url,
+        filename,
+    ));
+    let key = format!("{}/{}", item_id, file_name);
+    println!("Attempting to upload {} to S3 as {}...", file_name, key);
+
+    let client = http::client();
+    let total_mb = upload_bytes / (1024 * 1024);
+    let progress = move |sent: u64| {
+        job.update(
+            Some(sent as f32 * 100.0 / upload_bytes.max(1) as f32),
+            Some(format!(
+                "Uploading to S3: {} of {} MB",
+                sent / (1024 * 1024),
+                total_mb
+            )),
+        );
+    };
+    let (client, config, key, progress) = (&client, &config, &key, &progress);
+    let ((), upload_started) = upload_with_retries(
+        app_state,
+        settings,
+        job,
+        cancel,
+        &item_id,
+        provider,
+        move || async move {
+            job.update(None, Some(format!("Uploading {} MB to S3", total_mb)));
+            s3::upload(
+                client,
+                config,
+                key,
+                local_path,
+                upload_bytes.max(0) as u64,
+                progress,
+            )
+            .await
+        },
+    )
+    .await?;
+
+    let url = config.object_url(key);
+    finish_provider_upload(
+        app_state,
+        &item_id,
+        &user_id,
+        provider,
+        &url,
+        upload_bytes,
+        upload_started.elapsed(),
+    )
+    .await;
+
+    Ok(Response {
+        success: true,
+        message: format!("Upload to S3 successful ({})", url),
+        data: Some(item_id),
+    })
+}
+
+// --- ADDED: Function to check Filemoon Encoding Status ---
+async fn check_filemoon_status(
+    item_id: &str,
+    filecode: &str,
+    api_key: &str,
+    app_handle: &tauri::AppHandle,
+) {
+    println!(
+        "Checking Filemoon status for item: {}, filecode: {}",
+        item_id, filecode
+    );
+    let client = http::client();
+    let url = format!("{}/encoding/status", filemoon::api_base());
+
+    match client
+        .get(&url)
+        .query(&[("key", api_key), ("file_code", filecode)])
+        .send()
+        .await
+    {
+        Ok(response) => {
+            let status = response.status();
+            match response.text().await {
+                // Read as text first
+                Ok(raw_text) => {
+                    // Now attempt to parse the raw text as JSON
+                    println!("========================================");
+                    println!("FILEMOON API RESPONSE DEBUG");
+                    println!("Item ID: {}", item_id);
+                    println!("Filecode: {}", filecode);
+                    println!("HTTP Status: {}", status);
+                    println!("Raw Response: {}", raw_text);
+                    println!("========================================");
+
+                    match serde_json::from_str::<FilemoonEncodingStatusResponse>(&raw_text) {
+                        Ok(resp_body) => {
+                            println!(
+                                "Parsed Response - API Status: {:?}, Message: {}, Result: {:?}",
+                                resp_body.status, resp_body.msg, resp_body.result
+                            );
+                            if filemoon::is_ok_response(status.as_u16(), resp_body.status) {
+                                if let Some(result) = resp_body.result {
+                                    let api_status = result.status.to_uppercase();
+                                    let progress =
+                                        result.progress.and_then(|p| p.parse::<i32>().ok());
+                                    let mut message = format!("Filemoon status: {}", api_status);
+                                    if let Some(p) = progress {
+                                        message.push_str(&format!(" ({}%)", p));
+                                    }
+
+                                    // Simplified status system - all uploaded files are considered uploaded
+                                    println!(
+                                        "Item {} already uploaded, ignoring encoding status",
+                                        item_id
+                                    );
+
+                                    // No status updates needed in simplified system
+                                } else {
+                                    println!("Item {} - API returned empty/null result, file already uploaded", item_id);
+                                }
+                            } else {
+                                eprintln!("Item {} Filemoon Status API Error (HTTP {}, API Status {:?}): {}. Full response: {:?}", item_id, status, resp_body.status, resp_body.msg, resp_body);
+                                record_provider_error(
+                                    &app_handle.state::<AppState>(),
+                                    Some(item_id),
+                                    "encoding/status",
+                                    Some(status.as_u16()),
+                                    &format!("Filemoon Status API Error: {}", resp_body.msg),
+                                    Some(&raw_text),
+                                )
+                                .await;
+                            }
+                        }
+                        Err(e) => {
+                            // JSON parsing failed
+                            eprintln!("Item {} Failed to parse Filemoon Status JSON response: {}. Raw Body: {}", item_id, e, raw_text);
+                            record_provider_error(
+                                &app_handle.state::<AppState>(),
+                                Some(item_id),
+                                "encoding/status",
+                                Some(status.as_u16()),
+                                &format!("Failed to parse Filemoon Status JSON response: {}", e),
+                                Some(&raw_text),
+                            )
+                            .await;
+                        }
+                    }
+                }
+                Err(e) => {
+                    // Failed to even read the response body as text
+                    eprintln!(
+                        "Item {} Failed to read Filemoon Status response body (HTTP {}): {}",
+                        item_id, status, e
+                    );
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("Item {} Filemoon Status request failed: {}", item_id, e);
+        }
+    }
+}
+// --- END ADDED ---
+
+// --- ADDED: Function to check Filemoon File Info API ---
+// Returns Ok(true) if file is ready (canplay=1), Ok(false) if checked but not ready, Err on API/parse failure.
+// A 2xx answer we can't make sense of (no entry for the file, no status, unreadable
+// JSON) counts as not ready yet rather than an error.
+async fn check_filemoon_file_info(
+    item_id: &str,
+    filecode: &str,
+    api_key: &str,
+    app_handle: &tauri::AppHandle,
+) -> Result<bool, String> {
+    println!(
+        "Checking Filemoon file/info for item: {}, filecode: {}",
+        item_id, filecode
+    );
+    let client = http::client();
+    let url = format!("{}/file/info", filemoon::api_base());
+
+    match client
+        .get(&url)
+        .query(&[("key", api_key), ("file_code", filecode)])
+        .send()
+        .await
+    {
+        Ok(response) => {
+            let status = response.status();
+            // Read body text first for better error reporting
+            match response.text().await {
+                Ok(raw_text) => {
+                    match serde_json::from_str::<FilemoonFileInfoResponse>(&raw_text) {
+                        Ok(resp_body) => {
+                            if filemoon::is_ok_response(status.as_u16(), resp_body.status) {
+                                if let Some(results) = resp_body.result {
+                                    if let Some(file_info) =
+                                        results.iter().find(|r| r.file_code == filecode)
+                                    {
+                                        if file_info.status == Some(200)
+                                            && file_info.canplay == Some(1)
+                                        {
+                                            println!("Item {} Filemoon file/info shows canplay=1. Marking as encoded.", item_id);
+                                            let state = app_handle.state::<AppState>();
+                                            if let Err(e) = state
+                                                .db
+                                                .update_item_encoding_details(
+                                                    item_id,
+                                                    "encoded",
+                                                    Some(100),
+                                                    Some(ItemMessage::new(
+                                                        messages::ENCODING_READY,
+                                                    )),
+                                                )
+                                                .await
+                                            {
+                                                eprintln!(
+                                                    "Error updating status to encoded: {}",
+                                                    e
+                                                );
+                                            }
+                                            sync_thumbnail(&state, item_id, api_key).await;
+                                            Ok(true) // File is ready
+                                        } else if file_info.status == Some(200) {
+                                            // File exists but not playable yet
+                                            println!("Item {} Filemoon file/info shows canplay!=1. Marking as encoding.", item_id);
+                                            // Update DB to encoding
+                                            let state = app_handle.state::<AppState>();
+                                            if let Err(e) = state
+                                                .db
+                                                .update_item_encoding_details(
+                                                    item_id,
+                                                    "encoding",
+                                                    None, // Progress unknown from file/info
+                                                    Some(
+                                                        ItemMessage::new(
+                                                            messages::ENCODING_IN_PROGRESS,
+                                                        )
+                                                        .with("canplay", file_info.canplay),
+                                                    ),
+                                                )
+                                                .await
+                                            {
+                                                eprintln!(
+                                                    "Error updating status to encoding: {}",
+                                                    e
+                                                );
+                                            }
+                                            Ok(false) // Checked, but not ready (status is now encoding)
+                                        } else {
+                                            // File status is not 200 (e.g., error, deleted?)
+                                            println!("Item {} Filemoon file/info status ({:?}): canplay={:?}. Not ready yet.", item_id, file_info.status, file_info.canplay);
+                                            Ok(false) // Checked, but not ready
+                                        }
+                                    } else {
+                                        println!("Item {} Filemoon file/info has no entry for filecode {} yet. Raw: {}", item_id, filecode, raw_text);
+                                        Ok(false) // Not listed yet; check again later
+                                    }
+                                } else {
+                                    println!("Item {} Filemoon file/info returned no result array. Raw: {}", item_id, raw_text);
+                                    Ok(false) // Nothing to go on yet; check again later
+                                }
+                            } else {
+                                let err_msg = format!("Filemoon file/info API Error (HTTP {}, API Status {:?}): {} for item {}. Raw: {}", status, resp_body.status, resp_body.msg, item_id, raw_text);
+                                eprintln!("{}", err_msg);
+                                record_provider_error(
+                                    &app_handle.state::<AppState>(),
+                                    Some(item_id),
+                                    "file/info",
+                                    Some(status.as_u16()),
+                                    &format!("Filemoon file/info API Error: {}", resp_body.msg),
+                                    Some(&raw_text),
+                                )
+                                .await;
+                                Err(err_msg)
+                            }
+                        }
+                        Err(e) => {
+                            let err_msg = format!("Failed to parse Filemoon file/info response for item {}: {}. Raw Body: {}", item_id, e, raw_text);
+                            eprintln!("{}", err_msg);
+                            record_provider_error(
+                                &app_handle.state::<AppState>(),
+                                Some(item_id),
+                                "file/info",
+                                Some(status.as_u16()),
+                                &format!("Failed to parse Filemoon file/info response: {}", e),
+                                Some(&raw_text),
+                            )
+                            .await;
+                            if status.is_success() {
+                                Ok(false) // Unreadable but not an error answer; check again later
+                            } else {
+                                Err(err_msg)
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    let err_msg = format!(
+                        "Failed to read Filemoon file/info response body for item {}: {}",
+                        item_id, e
+                    );
+                    eprintln!("{}", err_msg);
+                    Err(err_msg)
+                }
+            }
+        }
+        Err(e) => {
+            let err_msg = format!(
+                "Filemoon file/info request failed for item {}: {}",
+                item_id, e
+            );
+            eprintln!("{}", err_msg);
+            Err(err_msg)
+        }
+    }
+}
+// --- END ADDED ---
+
+// --- ADDED: Orchestrator function for checking Filemoon readiness ---
+async fn check_filemoon_readiness(
+    item_id: &str,
+    filecode: &str,
+    api_key: &str,
+    app_handle: &tauri::AppHandle,
+) {
+    println!(
+        "Checking Filemoon readiness for item {}, filecode: {} - Simplified: already uploaded",
+        item_id, filecode
+    );
+    // No more encoding status checks - file is already marked as uploaded
+}
+// --- END ADDED ---
@@ -0,0 +1,174 @@
+// Upload worker queue shared by manual and automatic upload triggers.
+// Every upload goes through here so ordering, concurrency limits and
+// rate limits apply the same way no matter who requested it.
+// A viewer-only instance gets a queue without a worker that refuses every upload,
+// since uploads run in the primary instance.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::Instant;
+
+use crate::upload::perform_upload;
+use crate::{bandwidth, AppState, Response};
+
+// Minimum spacing between the start of two uploads (provider rate limit)
+const MIN_UPLOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+pub type UploadOutcome = Result<Response<String>, String>;
+
+pub struct UploadJob {
+    item_id: String,
+    user_id: String,
+    reply: Option<oneshot::Sender<UploadOutcome>>,
+}
+
+#[derive(Clone)]
+pub struct UploadQueue {
+    // None in a viewer-only instance
+    sender: Option<mpsc::UnboundedSender<UploadJob>>,
+    // Item ids that are waiting for or currently running an upload
+    pending: Arc<Mutex<HashSet<String>>>,
+}
+
+impl UploadQueue {
+    // Create the queue and spawn its worker task
+    pub fn start(app_handle: AppHandle) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = UploadQueue {
+            sender: Some(sender),
+            pending: Arc::new(Mutex::new(HashSet::new())),
+        };
+
+        let worker_queue = queue.clone();
+        tokio::spawn(async move {
+            worker_queue.run_worker(app_handle, receiver).await;
+        });
+
+        queue
+    }
+
+    // The queue of a viewer-only instance: no worker, and every upload is refused
+    pub fn viewer() -> Self {
+        UploadQueue {
+            sender: None,
+            pending: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    // Queue an upload without waiting for it to finish (used by auto-upload)
+    pub fn enqueue(&self, item_id: String, user_id: String) -> Result<(), String> {
+        self.push(UploadJob {
+            item_id,
+            user_id,
+            reply: None,
+        })
+    }
+
+    // Queue an upload and wait for its result (used by the trigger_upload command)
+    pub async fn submit(&self, item_id: String, user_id: String) -> UploadOutcome {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.push(UploadJob {
+            item_id,
+            user_id,
+            reply: Some(reply_tx),
+        })?;
+
+        reply_rx.await.unwrap_or_else(|_| {
+            Err("Upload worker stopped before finishing the upload".to_string())
+        })
+    }
+
+    fn push(&self, job: UploadJob) -> Result<(), String> {
+        let sender = match &self.sender {
+            Some(sender) => sender,
+            None => {
+                return Err(
+                    "This is a viewer-only instance; uploads run in the main instance.".to_string(),
+                )
+            }
+        };
+        // Guard against the same item being queued twice (e.g. auto + manual trigger)
+        if !self.pending.lock().unwrap().insert(job.item_id.clone()) {
+            return Err(format!(
+                "Item {} is already queued for upload.",
+                job.item_id
+            ));
+        }
+
+        let item_id = job.item_id.clone();
+        if sender.send(job).is_err() {
+            self.pending.lock().unwrap().remove(&item_id);
+            return Err("Upload worker is not running".to_string());
+        }
+
+        println!("Item {} added to upload queue", item_id);
+        Ok(())
+    }
+
+    async fn run_worker(
+        &self,
+        app_handle: AppHandle,
+        mut receiver: mpsc::UnboundedReceiver<UploadJob>,
+    ) {
+        println!("Starting upload worker...");
+        let state = app_handle.state::<AppState>();
+        let mut last_start: Option<Instant> = None;
+
+        while let Some(job) = receiver.recv().await {
+            // One job is taken at a time, so jobs start in the order they were queued.
+            // How many run at once is up to concurrency.rs.
+            let slot = loop {
+                if let Some(slot) = state.concurrency.try_start(bandwidth::UPLOAD, &job.item_id) {
+                    break slot;
+                }
+                state.concurrency.upload_released().await;
+            };
+
+            if let Some(previous) = last_start {
+                let elapsed = previous.elapsed();
+                if elapsed < MIN_UPLOAD_INTERVAL {
+                    tokio::time::sleep(MIN_UPLOAD_INTERVAL - elapsed).await;
+                }
+            }
+            last_start = Some(Instant::now());
+
+            let handle = app_handle.clone();
+            let pending = self.pending.clone();
+            tokio::spawn(async move {
+                let UploadJob {
+                    item_id,
+                    user_id,
+                    reply,
+                } = job;
+
+                println!("Upload worker starting upload for item {}", item_id);
+                let state = handle.state::<AppState>();
+                let outcome = perform_upload(item_id.clone(), user_id, &handle, &state).await;
+
+                if outcome.is_err() {
+                    state.concurrency.record_failure(bandwidth::UPLOAD);
+                }
+                pending.lock().unwrap().remove(&item_id);
+                drop(slot);
+                // Downloads don't start while an upload is running
+                state.queue_wakeup.wake();
+
+                match reply {
+                    Some(reply) => {
+                        let _ = reply.send(outcome);
+                    }
+                    None => {
+                        if let Err(e) = outcome {
+                            eprintln!("Queued upload failed for {}: {}", item_id, e);
+                        }
+                    }
+                }
+            });
+        }
+
+        println!("Upload worker stopped");
+    }
+}
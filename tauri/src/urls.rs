@@ -0,0 +1,230 @@
+// URL normalization and canonical URL construction for supported sites.
+// Queue entries are stored in canonical form so the same video added through
+// different URL shapes (short links, mobile hosts, bare IDs) is deduplicated.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+lazy_static! {
+    static ref YOUTUBE_ID_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_-]{11}$").unwrap();
+    static ref NUMERIC_ID_REGEX: Regex = Regex::new(r"^\d{1,25}$").unwrap();
+    static ref DAILYMOTION_ID_REGEX: Regex = Regex::new(r"^[A-Za-z0-9]{5,10}$").unwrap();
+    static ref INSTAGRAM_ID_REGEX: Regex = Regex::new(r"^[A-Za-z0-9_-]{5,40}$").unwrap();
+    // youtu.be/<id>, youtube.com/shorts/<id>, youtube.com/live/<id>, m.youtube.com/watch?v=<id>
+    static ref YOUTUBE_URL_REGEX: Regex = Regex::new(
+        r"^https?://(?:www\.|m\.|music\.)?(?:youtu\.be/|youtube\.com/(?:shorts/|live/|embed/|watch\?(?:.*&)?v=))([A-Za-z0-9_-]{11})"
+    )
+    .unwrap();
+    // twitter.com, x.com, mobile hosts and embed-fixing mirrors (fxtwitter, vxtwitter, fixupx),
+    // with or without the user name; tracking parameters like ?s=20&t=... are dropped
+    static ref TWITTER_URL_REGEX: Regex = Regex::new(
+        r"^https?://(?:www\.|mobile\.)?(?:twitter|x|fxtwitter|vxtwitter|fixupx)\.com/(?:i/web/|i/|[A-Za-z0-9_]+/)status(?:es)?/(\d+)"
+    )
+    .unwrap();
+    // tiktok.com/@user/video/<id>, m.tiktok.com/v/<id>.html, tiktok.com/embed/v2/<id>.
+    // vm.tiktok.com short links need a redirect to resolve and are left to yt-dlp.
+    static ref TIKTOK_URL_REGEX: Regex = Regex::new(
+        r"^https?://(?:www\.|m\.)?tiktok\.com/(?:@([\w.-]+)/video/|v/|embed/(?:v2/)?)(\d+)"
+    )
+    .unwrap();
+    // Other sites' watch URLs, keyed by yt-dlp's extractor name (lowercased)
+    static ref SITE_VIDEO_URL_REGEXES: Vec<(&'static str, Regex)> = vec![
+        (
+            "facebook",
+            Regex::new(r"facebook\.com/(?:.*/videos/|reel/|watch/?\?(?:.*&)?v=)(\d+)").unwrap(),
+        ),
+        ("vimeo", Regex::new(r"vimeo\.com/(?:video/)?(\d+)").unwrap()),
+        ("dailymotion", Regex::new(r"dailymotion\.com/video/([A-Za-z0-9]+)").unwrap()),
+        ("twitter", Regex::new(r"(?:twitter|x)\.com/[^/]+/status/(\d+)").unwrap()),
+        ("instagram", Regex::new(r"instagram\.com/(?:reel|p|tv)/([A-Za-z0-9_-]+)").unwrap()),
+        ("twitchvod", Regex::new(r"twitch\.tv/videos/(\d+)").unwrap()),
+        ("tiktok", Regex::new(r"tiktok\.com/@[\w.-]*/video/(\d+)").unwrap()),
+    ];
+}
+
+// Sites that can be queued by bare video ID
+pub const SUPPORTED_ID_SITES: &[&str] = &[
+    "youtube",
+    "facebook",
+    "vimeo",
+    "dailymotion",
+    "twitter",
+    "instagram",
+    "twitch",
+    "tiktok",
+];
+
+// Build the canonical watch URL for a video ID on a known site
+pub fn canonical_url_for_id(site: &str, video_id: &str) -> Result<String, String> {
+    let video_id = video_id.trim();
+    let site = site.trim().to_lowercase();
+
+    let (valid, url) = match site.as_str() {
+        "youtube" | "yt" => (
+            YOUTUBE_ID_REGEX.is_match(video_id),
+            format!("https://www.youtube.com/watch?v={}", video_id),
+        ),
+        "facebook" | "fb" => (
+            NUMERIC_ID_REGEX.is_match(video_id),
+            format!("https://www.facebook.com/watch/?v={}", video_id),
+        ),
+        "vimeo" => (
+            NUMERIC_ID_REGEX.is_match(video_id),
+            format!("https://vimeo.com/{}", video_id),
+        ),
+        "dailymotion" => (
+            DAILYMOTION_ID_REGEX.is_match(video_id),
+            format!("https://www.dailymotion.com/video/{}", video_id),
+        ),
+        "twitter" | "x" => (
+            NUMERIC_ID_REGEX.is_match(video_id),
+            format!("https://x.com/i/status/{}", video_id),
+        ),
+        "instagram" => (
+            INSTAGRAM_ID_REGEX.is_match(video_id),
+            format!("https://www.instagram.com/reel/{}/", video_id),
+        ),
+        "twitch" => (
+            NUMERIC_ID_REGEX.is_match(video_id),
+            format!("https://www.twitch.tv/videos/{}", video_id),
+        ),
+        // yt-dlp accepts the video URL without the user name
+        "tiktok" => (
+            NUMERIC_ID_REGEX.is_match(video_id),
+            format!("https://www.tiktok.com/@/video/{}", video_id),
+        ),
+        _ => {
+            return Err(format!(
+                "Unsupported site '{}'. Supported sites: {}",
+                site,
+                SUPPORTED_ID_SITES.join(", ")
+            ))
+        }
+    };
+
+    if !valid {
+        return Err(format!(
+            "'{}' does not look like a valid {} video ID",
+            video_id, site
+        ));
+    }
+
+    Ok(url)
+}
+
+// Rewrite known alternate URL shapes to the canonical form; other URLs are only trimmed
+pub fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+
+    if let Some(id) = YOUTUBE_URL_REGEX
+        .captures(url)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str())
+    {
+        return format!("https://www.youtube.com/watch?v={}", id);
+    }
+
+    if let Some(caps) = TWITTER_URL_REGEX.captures(url) {
+        return format!("https://x.com/i/status/{}", &caps[1]);
+    }
+
+    if let Some(caps) = TIKTOK_URL_REGEX.captures(url) {
+        let user = caps.get(1).map_or("", |m| m.as_str());
+        return format!("https://www.tiktok.com/@{}/video/{}", user, &caps[2]);
+    }
+
+    url.to_string()
+}
+
+// "<site>:<video id>" for URLs of known sites, so the same video queued through
+// different URLs can be recognised. Site names follow yt-dlp's extractor names.
+pub fn video_key(url: &str) -> Option<String> {
+    let url = normalize_url(url);
+    if let Some(caps) = YOUTUBE_URL_REGEX.captures(&url) {
+        return Some(format!("youtube:{}", &caps[1]));
+    }
+    SITE_VIDEO_URL_REGEXES.iter().find_map(|(site, regex)| {
+        regex
+            .captures(&url)
+            .map(|caps| format!("{}:{}", site, &caps[1]))
+    })
+}
+
+// The site part of the URL's video key ("youtube"), and its host without "www."
+fn site_names(url: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Some(key) = video_key(url) {
+        if let Some((site, _)) = key.split_once(':') {
+            names.push(site.to_lowercase());
+        }
+    }
+    if let Ok(parsed) = reqwest::Url::parse(url) {
+        if let Some(host) = parsed.host_str() {
+            names.push(host.trim_start_matches("www.").to_lowercase());
+        }
+    }
+    names
+}
+
+// Whether the URL is from one of `sites`, given as site names ("youtube") or hosts;
+// "vimeo.com" also matches player.vimeo.com
+pub fn site_matches(url: &str, sites: &[String]) -> bool {
+    site_names(url).iter().any(|name| {
+        sites
+            .iter()
+            .any(|site| name == site || name.ends_with(&format!(".{}", site)))
+    })
+}
+
+// RFC 3986 percent-encoding: everything but unreserved characters (and '/' when
+// `keep_slashes`, for object paths) is escaped
+pub fn percent_encode(text: &str, keep_slashes: bool) -> String {
+    text.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric()
+                || matches!(b, b'-' | b'.' | b'_' | b'~')
+                || (keep_slashes && b == b'/')
+            {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn video_key_is_the_same_for_every_url_shape() {
+        let key = Some("youtube:dQw4w9WgXcQ".to_string());
+        assert_eq!(video_key("https://youtu.be/dQw4w9WgXcQ"), key);
+        assert_eq!(
+            video_key("https://www.youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=1"),
+            key
+        );
+        assert_eq!(video_key("https://www.youtube.com/shorts/dQw4w9WgXcQ"), key);
+
+        let key = Some("twitter:123".to_string());
+        assert_eq!(
+            video_key("https://twitter.com/someone/status/123?s=20"),
+            key
+        );
+        assert_eq!(video_key("https://x.com/i/status/123"), key);
+    }
+
+    #[test]
+    fn video_key_knows_other_sites() {
+        assert_eq!(
+            video_key("https://vimeo.com/76979871").as_deref(),
+            Some("vimeo:76979871")
+        );
+        assert_eq!(
+            video_key("https://www.tiktok.com/@someone/video/456").as_deref(),
+            Some("tiktok:456")
+        );
+        assert_eq!(video_key("https://example.com/video/1"), None);
+    }
+}
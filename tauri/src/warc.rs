@@ -0,0 +1,303 @@
+// Preservation copy of the context a video appeared in. With `capture_page` on, the
+// watch page HTML and the thumbnail are fetched once the download completes and
+// written as a WARC 1.1 file ("<video name>.warc.gz", one gzip member per record)
+// next to the video, so standard tools such as pywb or replayweb.page can open it.
+// Each fetch is stored as a request/response record pair holding the HTTP exchange,
+// with a SHA-256 block digest. The request record is the request as sent: every
+// header on it is set here, asking for an unencoded body. The response is stored as
+// the body was read, so its head is made to describe that body: the connection
+// undoes chunking, so Transfer-Encoding is dropped and Content-Length states the
+// stored length. A Content-Encoding the server applied anyway is left on, since the
+// body is kept as sent.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use reqwest::header::{HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::db::AppSettings;
+use crate::{http, long_paths, timestamps};
+
+pub const WARC_EXTENSION: &str = "warc.gz";
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+// Larger bodies are cut off and marked with WARC-Truncated
+const MAX_BODY_BYTES: usize = 20 * 1024 * 1024;
+const RESPONSE_CONTENT_TYPE: &str = "application/http;msgtype=response";
+const REQUEST_CONTENT_TYPE: &str = "application/http;msgtype=request";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+// Response headers that describe the transfer rather than the stored body
+const TRANSFER_HEADERS: [&str; 2] = ["transfer-encoding", "content-length"];
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PageCapture {
+    pub warc_path: String,
+    // URLs stored in the WARC
+    pub captured: Vec<String>,
+    // URLs that could not be fetched, with the reason
+    pub failed: Vec<String>,
+}
+
+struct Exchange {
+    uri: String,
+    fetched_at: DateTime<Utc>,
+    request: Vec<u8>,
+    response: Vec<u8>,
+    truncated: bool,
+}
+
+pub fn enabled(settings: &AppSettings) -> bool {
+    settings.capture_page.as_deref() == Some("true")
+}
+
+// "<dir>/<video stem>.warc.gz"
+pub fn warc_path_for(video_path: &Path) -> PathBuf {
+    let stem = video_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "page".to_string());
+    video_path.with_file_name(format!("{}.{}", stem, WARC_EXTENSION))
+}
+
+fn warc_date(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn base32(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut buffer = [0u8; 5];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = buffer.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64);
+        let symbols = (chunk.len() * 8 + 4) / 5;
+        for i in 0..8 {
+            if i < symbols {
+                let index = (bits >> (35 - i * 5)) & 0x1f;
+                out.push(BASE32_ALPHABET[index as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn sha256_label(block: &[u8]) -> String {
+    format!("sha256:{}", base32(&Sha256::digest(block)))
+}
+
+fn record_id() -> String {
+    format!("<urn:uuid:{}>", Uuid::new_v4())
+}
+
+// One WARC record compressed as its own gzip member
+fn record(headers: &[(&str, String)], block: &[u8]) -> Result<Vec<u8>, String> {
+    let mut raw = Vec::with_capacity(block.len() + 512);
+    raw.extend_from_slice(b"WARC/1.1\r\n");
+    for (name, value) in headers {
+        raw.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+    }
+    raw.extend_from_slice(format!("WARC-Block-Digest: {}\r\n", sha256_label(block)).as_bytes());
+    raw.extend_from_slice(format!("Content-Length: {}\r\n\r\n", block.len()).as_bytes());
+    raw.extend_from_slice(block);
+    raw.extend_from_slice(b"\r\n\r\n");
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(&raw)
+        .and_then(|_| encoder.finish())
+        .map_err(|e| format!("Failed to compress WARC record: {}", e))
+}
+
+fn write_headers<'a>(
+    head: &mut Vec<u8>,
+    headers: impl Iterator<Item = (&'a HeaderName, &'a HeaderValue)>,
+) {
+    for (name, value) in headers {
+        head.extend_from_slice(name.as_str().as_bytes());
+        head.extend_from_slice(b": ");
+        head.extend_from_slice(value.as_bytes());
+        head.extend_from_slice(b"\r\n");
+    }
+}
+
+// "GET /path?query HTTP/1.1" and the headers of `request`, with the Host header the
+// connection adds
+fn request_head(request: &reqwest::Request, version: reqwest::Version) -> Vec<u8> {
+    let url = request.url();
+    let mut target = url.path().to_string();
+    if let Some(query) = url.query() {
+        target.push('?');
+        target.push_str(query);
+    }
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+
+    let mut head = format!(
+        "{} {} {:?}\r\nHost: {}\r\n",
+        request.method(),
+        target,
+        version,
+        host
+    )
+    .into_bytes();
+    write_headers(&mut head, request.headers().iter());
+    head.extend_from_slice(b"\r\n");
+    head
+}
+
+async fn fetch(client: &reqwest::Client, uri: &str) -> Result<Exchange, String> {
+    let url = reqwest::Url::parse(uri).map_err(|e| format!("Invalid URL {}: {}", uri, e))?;
+    let request = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, http::USER_AGENT)
+        .header(reqwest::header::ACCEPT, "*/*")
+        .header(reqwest::header::ACCEPT_ENCODING, "identity")
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Invalid request: {}", e))?;
+    // Recorded before sending, as the request is consumed
+    let sent = request
+        .try_clone()
+        .ok_or_else(|| "Request can't be recorded".to_string())?;
+
+    let fetched_at = timestamps::now();
+    let response = client
+        .execute(request)
+        .await
+        .map_err(|e| format!("Request failed: {}", e))?;
+    let version = response.version();
+
+    let mut head = format!(
+        "{:?} {} {}\r\n",
+        version,
+        response.status().as_u16(),
+        response.status().canonical_reason().unwrap_or_default()
+    )
+    .into_bytes();
+    let headers = response.headers().iter();
+    write_headers(
+        &mut head,
+        headers.filter(|(name, _)| !TRANSFER_HEADERS.contains(&name.as_str())),
+    );
+
+    let mut body = response
+        .bytes()
+        .await
+        .map_err(|e| format!("Failed to read response: {}", e))?
+        .to_vec();
+    let truncated = body.len() > MAX_BODY_BYTES;
+    body.truncate(MAX_BODY_BYTES);
+    head.extend_from_slice(format!("content-length: {}\r\n\r\n", body.len()).as_bytes());
+    head.extend_from_slice(&body);
+
+    Ok(Exchange {
+        uri: uri.to_string(),
+        fetched_at,
+        request: request_head(&sent, version),
+        response: head,
+        truncated,
+    })
+}
+
+fn exchange_records(exchange: &Exchange, warcinfo_id: &str) -> Result<Vec<u8>, String> {
+    let date = warc_date(&exchange.fetched_at);
+    let response_id = record_id();
+
+    let mut response_headers = vec![
+        ("WARC-Type", "response".to_string()),
+        ("WARC-Record-ID", response_id.clone()),
+        ("WARC-Date", date.clone()),
+        ("WARC-Target-URI", exchange.uri.clone()),
+        ("WARC-Warcinfo-ID", warcinfo_id.to_string()),
+        ("Content-Type", RESPONSE_CONTENT_TYPE.to_string()),
+    ];
+    if exchange.truncated {
+        response_headers.push(("WARC-Truncated", "length".to_string()));
+    }
+    let mut out = record(&response_headers, &exchange.response)?;
+
+    out.extend(record(
+        &[
+            ("WARC-Type", "request".to_string()),
+            ("WARC-Record-ID", record_id()),
+            ("WARC-Date", date),
+            ("WARC-Target-URI", exchange.uri.clone()),
+            ("WARC-Warcinfo-ID", warcinfo_id.to_string()),
+            ("WARC-Concurrent-To", response_id),
+            ("Content-Type", REQUEST_CONTENT_TYPE.to_string()),
+        ],
+        &exchange.request,
+    )?);
+    Ok(out)
+}
+
+// Fetch the watch page (required) and thumbnail (best effort) into a WARC next to
+// `video_path`, replacing an earlier capture
+pub async fn capture(
+    page_url: &str,
+    thumbnail_url: Option<&str>,
+    video_path: &Path,
+) -> Result<PageCapture, String> {
+    // Not the provider client: its custom headers are meant for the upload providers.
+    // Nothing is set on the client, so each recorded request holds all its headers.
+    let client = reqwest::Client::builder()
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))?;
+
+    let mut exchanges = vec![fetch(&client, page_url)
+        .await
+        .map_err(|e| format!("Failed to capture {}: {}", page_url, e))?];
+    let mut failed = Vec::new();
+    if let Some(thumbnail) = thumbnail_url.filter(|t| t.starts_with("http")) {
+        match fetch(&client, thumbnail).await {
+            Ok(exchange) => exchanges.push(exchange),
+            Err(e) => failed.push(format!("{}: {}", thumbnail, e)),
+        }
+    }
+
+    let warc_path = warc_path_for(video_path);
+    let file_name = warc_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let warcinfo_id = record_id();
+    let fields = format!(
+        "software: {}\r\nformat: WARC File Format 1.1\r\n\
+         conformsTo: http://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n\
+         description: Watch page and thumbnail of {}\r\n",
+        http::USER_AGENT,
+        page_url
+    );
+    let mut bytes = record(
+        &[
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", warcinfo_id.clone()),
+            ("WARC-Date", warc_date(&timestamps::now())),
+            ("WARC-Filename", file_name),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ],
+        fields.as_bytes(),
+    )?;
+    for exchange in &exchanges {
+        bytes.extend(exchange_records(exchange, &warcinfo_id)?);
+    }
+
+    tokio::fs::write(long_paths::extended(&warc_path), bytes)
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", warc_path.display(), e))?;
+
+    Ok(PageCapture {
+        warc_path: warc_path.to_string_lossy().to_string(),
+        captured: exchanges.into_iter().map(|e| e.uri).collect(),
+        failed,
+    })
+}
@@ -0,0 +1,172 @@
+// Stage watchdogs. yt-dlp can hang without exiting (stuck Facebook downloads are
+// the usual case), and with a single download slot that blocks the whole queue.
+// The watchdog kills an attempt that shows no activity for too long or runs past a
+// wall-clock limit; the item is then re-queued with a growing backoff, and gives up
+// after a few stalled attempts. Uploads get a plain wall-clock limit.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::db::AppSettings;
+
+pub const DEFAULT_STALL_MINUTES: u64 = 10;
+pub const DEFAULT_TIMEOUT_MINUTES: u64 = 240;
+pub const DEFAULT_UPLOAD_TIMEOUT_MINUTES: u64 = 360;
+
+// Stalled attempts allowed before the item is marked failed
+pub const MAX_STALL_RETRIES: u32 = 3;
+const BACKOFF_BASE: Duration = Duration::from_secs(120);
+const CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    // Longest gap between output lines; None disables the check
+    pub stall: Option<Duration>,
+    // Longest total run time; None disables the check
+    pub timeout: Option<Duration>,
+}
+
+// "0" disables a limit; unset or unreadable values use the defaults
+fn minutes_setting(value: Option<&str>, default: u64) -> Option<Duration> {
+    let minutes = value
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .unwrap_or(default);
+    (minutes > 0).then(|| Duration::from_secs(minutes * 60))
+}
+
+impl Limits {
+    pub fn from_settings(settings: &AppSettings) -> Limits {
+        Limits {
+            stall: minutes_setting(
+                settings.download_stall_minutes.as_deref(),
+                DEFAULT_STALL_MINUTES,
+            ),
+            timeout: minutes_setting(
+                settings.download_timeout_minutes.as_deref(),
+                DEFAULT_TIMEOUT_MINUTES,
+            ),
+        }
+    }
+}
+
+// Longest a single upload may take before it is abandoned
+pub fn upload_limit(settings: &AppSettings) -> Option<Duration> {
+    minutes_setting(
+        settings.upload_timeout_minutes.as_deref(),
+        DEFAULT_UPLOAD_TIMEOUT_MINUTES,
+    )
+}
+
+// Resolves after `limit`, or never when there is no limit
+pub async fn deadline(limit: Option<Duration>) {
+    match limit {
+        Some(limit) => tokio::time::sleep(limit).await,
+        None => std::future::pending::<()>().await,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StallReason {
+    NoProgress(Duration),
+    TimedOut(Duration),
+}
+
+impl StallReason {
+    pub fn describe(&self) -> String {
+        match self {
+            StallReason::NoProgress(limit) => {
+                format!("no progress for {} min", limit.as_secs() / 60)
+            }
+            StallReason::TimedOut(limit) => {
+                format!("still running after {} min", limit.as_secs() / 60)
+            }
+        }
+    }
+}
+
+// Last time the download produced output; cloned into the stdout/stderr readers
+#[derive(Clone)]
+pub struct Activity(Arc<Mutex<Instant>>);
+
+impl Activity {
+    pub fn new() -> Self {
+        Activity(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    pub fn touch(&self) {
+        *self.0.lock().unwrap() = Instant::now();
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.0.lock().unwrap().elapsed()
+    }
+}
+
+// Resolves once the attempt has stalled or timed out; never resolves when both
+// limits are disabled. Meant to be raced against the child process in select!.
+pub async fn watch(limits: Limits, activity: Activity) -> StallReason {
+    let started = Instant::now();
+    if limits.stall.is_none() && limits.timeout.is_none() {
+        std::future::pending::<()>().await;
+    }
+
+    loop {
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        if let Some(timeout) = limits.timeout {
+            if started.elapsed() >= timeout {
+                return StallReason::TimedOut(timeout);
+            }
+        }
+        if let Some(stall) = limits.stall {
+            if activity.idle_for() >= stall {
+                return StallReason::NoProgress(stall);
+            }
+        }
+    }
+}
+
+// Stalled attempts per item and when each item may be picked up again. Kept in
+// memory like the extractor recovery state: a restart gives every item a fresh start.
+#[derive(Default)]
+pub struct StallBackoff {
+    items: Mutex<HashMap<String, (u32, Instant)>>,
+}
+
+impl StallBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Count a stalled attempt. Returns the attempt number and the delay before the
+    // next one, or None once the item has used all its retries.
+    pub fn record_stall(&self, item_id: &str) -> Option<(u32, Duration)> {
+        let mut items = self.items.lock().unwrap();
+        let attempts = items.get(item_id).map_or(0, |(attempts, _)| *attempts) + 1;
+        if attempts > MAX_STALL_RETRIES {
+            items.remove(item_id);
+            return None;
+        }
+
+        let delay = BACKOFF_BASE * 2u32.pow(attempts - 1);
+        items.insert(item_id.to_string(), (attempts, Instant::now() + delay));
+        Some((attempts, delay))
+    }
+
+    // Items still waiting out their backoff; the queue skips these
+    pub fn deferred_ids(&self) -> Vec<String> {
+        let now = Instant::now();
+        self.items
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, (_, until))| *until > now)
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    pub fn clear(&self, item_id: &str) {
+        self.items.lock().unwrap().remove(item_id);
+    }
+}
@@ -0,0 +1,317 @@
+// Lifecycle webhooks. With `webhook_url` set, every status an item enters (queued,
+// downloading, downloaded, uploading, uploaded, failed, ...) is POSTed there as JSON,
+// so automations such as n8n, Zapier or a Discord channel can follow the queue
+// without polling the database. Repeated writes of the same status (download
+// progress) send nothing. The body carries `content` and `text` with a one-line
+// summary, which is what Discord and Slack webhooks display.
+//
+// With `webhook_secret` set, each request is signed: X-PermaVid-Signature is
+// "sha256=" and the hex HMAC-SHA256 of the raw body under the secret. Requests are
+// sent one at a time in the order the changes happened; a failed one is retried
+// after RETRY_DELAYS, except for a 4xx answer other than 429.
+
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use crate::db::{AppSettings, StatusEvent};
+use crate::{filemoon, http, shares, timestamps, AppState};
+
+pub const EVENT_STATUS_CHANGED: &str = "item.status_changed";
+pub const EVENT_TEST: &str = "webhook.test";
+
+pub const SIGNATURE_HEADER: &str = "X-PermaVid-Signature";
+const EVENT_HEADER: &str = "X-PermaVid-Event";
+const DELIVERY_HEADER: &str = "X-PermaVid-Delivery";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const RETRY_DELAYS: &[Duration] = &[
+    Duration::from_secs(5),
+    Duration::from_secs(30),
+    Duration::from_secs(120),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: String,
+    pub delivery_id: String,
+    pub item_id: Option<String>,
+    pub user_id: String,
+    pub status: Option<String>,
+    // The status the item left; None for a new item or one not seen since startup
+    pub previous_status: Option<String>,
+    pub message: Option<String>,
+    pub message_code: Option<String>,
+    pub title: Option<String>,
+    pub url: Option<String>,
+    // Where the uploaded copy can be watched, once uploaded
+    pub upload_url: Option<String>,
+    pub summary: String,
+    // The summary again, for Discord (`content`) and Slack (`text`)
+    pub content: String,
+    pub text: String,
+    pub timestamp: String,
+}
+
+// Returned by test_webhook
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookTest {
+    pub status: u16,
+    pub signed: bool,
+}
+
+struct Delivery {
+    target: String,
+    secret: Option<String>,
+    payload: WebhookPayload,
+}
+
+fn setting(value: &Option<String>) -> Option<String> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string)
+}
+
+// Check the webhook settings before they are saved
+pub fn validate(settings: &AppSettings) -> Result<(), String> {
+    if let Some(target) = setting(&settings.webhook_url) {
+        if !(target.starts_with("https://") || target.starts_with("http://")) {
+            return Err(format!(
+                "Invalid webhook_url '{}'; use an http(s) URL",
+                target
+            ));
+        }
+        reqwest::Url::parse(&target)
+            .map_err(|e| format!("Invalid webhook_url '{}': {}", target, e))?;
+    } else if setting(&settings.webhook_secret).is_some() {
+        return Err("webhook_secret needs a webhook_url".to_string());
+    }
+    Ok(())
+}
+
+// "sha256=<hex>" over the raw body
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mac: String = shares::hmac_sha256(secret.as_bytes(), body)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("sha256={}", mac)
+}
+
+fn client() -> reqwest::Client {
+    // Not the provider client: its extra headers are meant for Filemoon only
+    reqwest::Client::builder()
+        .user_agent(http::USER_AGENT)
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .unwrap_or_default()
+}
+
+fn payload(event: &str, user_id: &str, summary: String) -> WebhookPayload {
+    WebhookPayload {
+        event: event.to_string(),
+        delivery_id: Uuid::new_v4().to_string(),
+        item_id: None,
+        user_id: user_id.to_string(),
+        status: None,
+        previous_status: None,
+        message: None,
+        message_code: None,
+        title: None,
+        url: None,
+        upload_url: None,
+        content: summary.clone(),
+        text: summary.clone(),
+        summary,
+        timestamp: timestamps::to_iso(&timestamps::now()),
+    }
+}
+
+// One attempt; Err carries whether trying again may help
+async fn send(client: &reqwest::Client, delivery: &Delivery) -> Result<StatusCode, (String, bool)> {
+    let body = serde_json::to_vec(&delivery.payload)
+        .map_err(|e| (format!("Failed to encode webhook payload: {}", e), false))?;
+    let mut request = client
+        .post(&delivery.target)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.payload.event)
+        .header(DELIVERY_HEADER, &delivery.payload.delivery_id);
+    if let Some(secret) = &delivery.secret {
+        request = request.header(SIGNATURE_HEADER, signature(secret, &body));
+    }
+
+    let response = request
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| (format!("Webhook request failed: {}", e), true))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status);
+    }
+    let retry = !status.is_client_error() || status == StatusCode::TOO_MANY_REQUESTS;
+    Err((format!("Webhook returned HTTP {}", status), retry))
+}
+
+// Send the queued deliveries in order, retrying each as RETRY_DELAYS allows
+async fn deliver(mut deliveries: mpsc::UnboundedReceiver<Delivery>) {
+    let client = client();
+    while let Some(delivery) = deliveries.recv().await {
+        let mut delays = RETRY_DELAYS.iter();
+        loop {
+            match send(&client, &delivery).await {
+                Ok(_) => break,
+                Err((e, retry)) => match delays.next().filter(|_| retry) {
+                    Some(delay) => {
+                        eprintln!(
+                            "Webhook for item {} failed, retrying in {}s: {}",
+                            delivery.payload.item_id.as_deref().unwrap_or_default(),
+                            delay.as_secs(),
+                            e
+                        );
+                        sleep(*delay).await;
+                    }
+                    None => {
+                        eprintln!(
+                            "Webhook for item {} ({}) dropped: {}",
+                            delivery.payload.item_id.as_deref().unwrap_or_default(),
+                            delivery.payload.status.as_deref().unwrap_or_default(),
+                            e
+                        );
+                        break;
+                    }
+                },
+            }
+        }
+    }
+}
+
+// Listen for status changes and post them to the owners' webhooks until the app exits
+pub async fn run(app_handle: AppHandle) {
+    println!("Starting webhooks...");
+    let mut events = app_handle.state::<AppState>().db.subscribe_status_events();
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(deliver(receiver));
+    // Last status sent per item, so repeated writes of the same status send nothing
+    let mut last_status: HashMap<String, String> = HashMap::new();
+
+    loop {
+        match events.recv().await {
+            Ok(event) => {
+                let previous = last_status.insert(event.item_id.clone(), event.status.clone());
+                if previous.as_deref() == Some(event.status.as_str()) {
+                    continue;
+                }
+                if let Some(delivery) = delivery_for(&app_handle, &event, previous).await {
+                    let _ = sender.send(delivery);
+                }
+            }
+            Err(RecvError::Lagged(missed)) => {
+                eprintln!(
+                    "Webhooks fell behind and skipped {} status event(s)",
+                    missed
+                );
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+
+    println!("Webhooks stopped");
+}
+
+async fn delivery_for(
+    app_handle: &AppHandle,
+    event: &StatusEvent,
+    previous_status: Option<String>,
+) -> Option<Delivery> {
+    let app_state = app_handle.state::<AppState>();
+    let item = match app_state.db.get_item_by_id(&event.item_id).await {
+        Ok(Some(item)) => item,
+        Ok(None) => return None,
+        Err(e) => {
+            eprintln!("Webhooks could not load item {}: {}", event.item_id, e);
+            return None;
+        }
+    };
+    let user_id = item
+        .user_id
+        .clone()
+        .unwrap_or_else(|| "local-user".to_string());
+    let settings = app_state
+        .db
+        .get_settings(&user_id)
+        .await
+        .unwrap_or_default();
+    let target = setting(&settings.webhook_url)?;
+
+    let name = item.title.clone().unwrap_or_else(|| item.url.clone());
+    let mut payload = payload(
+        EVENT_STATUS_CHANGED,
+        &user_id,
+        format!("{} is now {}", name, event.status),
+    );
+    payload.item_id = Some(event.item_id.clone());
+    payload.status = Some(event.status.clone());
+    payload.previous_status = previous_status;
+    payload.message = event.message.clone();
+    payload.message_code = event.message_code.clone();
+    payload.title = item.title.clone();
+    payload.url = Some(item.url.clone());
+    payload.upload_url = item
+        .filemoon_url
+        .as_deref()
+        .filter(|code| !code.is_empty())
+        .map(filemoon::player_url)
+        .or_else(|| item.upload_url.clone());
+
+    Some(Delivery {
+        target,
+        secret: setting(&settings.webhook_secret),
+        payload,
+    })
+}
+
+// Send a test event to the user's webhook right away, without retries
+pub async fn test(app_state: &AppState, user_id: &str) -> Result<WebhookTest, String> {
+    let settings = app_state
+        .db
+        .get_settings(user_id)
+        .await
+        .map_err(|e| format!("Failed to load settings: {}", e))?;
+    let target =
+        setting(&settings.webhook_url).ok_or_else(|| "No webhook_url configured".to_string())?;
+    let secret = setting(&settings.webhook_secret);
+    let delivery = Delivery {
+        target,
+        payload: payload(EVENT_TEST, user_id, "PermaVid webhook test".to_string()),
+        secret,
+    };
+
+    let status = send(&client(), &delivery).await.map_err(|(e, _)| e)?;
+    Ok(WebhookTest {
+        status: status.as_u16(),
+        signed: delivery.secret.is_some(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_the_body_with_hmac_sha256() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
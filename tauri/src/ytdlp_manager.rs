@@ -0,0 +1,360 @@
+// A yt-dlp the app keeps itself, so downloads don't depend on one being on PATH.
+// update_ytdlp fetches the release build for this OS from GitHub into bin/ under the
+// app data directory, checks it against the release's SHA2-256SUMS and only then
+// swaps it in. The executable downloads, probes and updates run is, in order:
+//
+//   - PERMAVID_YTDLP (tools::YTDLP_ENV), e.g. the end-to-end harness's fake
+//   - the ytdlp_path setting
+//   - the managed copy, once installed
+//   - yt-dlp on PATH
+//
+// When none of them runs at startup, the primary instance installs the managed copy.
+// After extractor failures the managed copy is updated the same way rather than with
+// `yt-dlp -U`, so every build it runs has been verified.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+use crate::db::AppSettings;
+use crate::{http, provenance, tools};
+
+const RELEASES_API: &str = "https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest";
+const DOWNLOAD_BASE: &str = "https://github.com/yt-dlp/yt-dlp/releases/download";
+const CHECKSUMS_ASSET: &str = "SHA2-256SUMS";
+const BIN_DIR_NAME: &str = "bin";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Where the running yt-dlp comes from
+pub const SOURCE_ENV: &str = "env";
+pub const SOURCE_SETTING: &str = "setting";
+pub const SOURCE_MANAGED: &str = "managed";
+pub const SOURCE_PATH: &str = "path";
+
+lazy_static! {
+    // bin/ under the app data directory, set at startup
+    static ref BIN_DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+    // The ytdlp_path setting
+    static ref OVERRIDE: RwLock<Option<String>> = RwLock::new(None);
+    // One install at a time
+    static ref INSTALL: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+// Returned by check_ytdlp_version and update_ytdlp
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct YtdlpVersion {
+    // The executable downloads run and where it comes from ("env", "setting",
+    // "managed" or "path")
+    pub binary: String,
+    pub source: String,
+    pub version: Option<String>,
+    pub managed_path: Option<String>,
+    pub managed_version: Option<String>,
+    // Newest release on GitHub, if it could be reached
+    pub latest_version: Option<String>,
+    // A newer release than the managed copy (or no managed copy yet)
+    pub update_available: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+// The release asset built for this OS and CPU
+fn asset_name() -> Option<&'static str> {
+    if cfg!(target_os = "windows") {
+        Some(if cfg!(target_arch = "x86") {
+            "yt-dlp_x86.exe"
+        } else {
+            "yt-dlp.exe"
+        })
+    } else if cfg!(target_os = "macos") {
+        Some("yt-dlp_macos")
+    } else if cfg!(target_os = "linux") {
+        if cfg!(target_arch = "x86_64") {
+            Some("yt-dlp_linux")
+        } else if cfg!(target_arch = "aarch64") {
+            Some("yt-dlp_linux_aarch64")
+        } else if cfg!(target_arch = "arm") {
+            Some("yt-dlp_linux_armv7l")
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+fn executable_name() -> &'static str {
+    if cfg!(target_os = "windows") {
+        "yt-dlp.exe"
+    } else {
+        "yt-dlp"
+    }
+}
+
+pub fn init(app_data_dir: Option<PathBuf>) {
+    *BIN_DIR.write().unwrap() = app_data_dir.map(|dir| dir.join(BIN_DIR_NAME));
+}
+
+// Apply the ytdlp_path setting
+pub fn configure(settings: &AppSettings) {
+    let configured = settings
+        .ytdlp_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(str::to_string);
+    *OVERRIDE.write().unwrap() = configured;
+}
+
+pub fn managed_path() -> Option<PathBuf> {
+    BIN_DIR
+        .read()
+        .unwrap()
+        .as_ref()
+        .map(|dir| dir.join(executable_name()))
+}
+
+// The yt-dlp to run and its source
+pub fn resolve() -> (String, &'static str) {
+    let from_env = std::env::var(tools::YTDLP_ENV)
+        .ok()
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty());
+    if let Some(path) = from_env {
+        return (path, SOURCE_ENV);
+    }
+    if let Some(path) = OVERRIDE.read().unwrap().clone() {
+        return (path, SOURCE_SETTING);
+    }
+    if let Some(path) = managed_path().filter(|path| path.is_file()) {
+        return (path.to_string_lossy().to_string(), SOURCE_MANAGED);
+    }
+    ("yt-dlp".to_string(), SOURCE_PATH)
+}
+
+pub fn is_managed() -> bool {
+    resolve().1 == SOURCE_MANAGED
+}
+
+fn client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .user_agent(http::USER_AGENT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build()
+        .map_err(|e| format!("Failed to build HTTP client: {}", e))
+}
+
+async fn latest_release(client: &reqwest::Client) -> Result<String, String> {
+    let release: Release = client
+        .get(RELEASES_API)
+        .header(reqwest::header::ACCEPT, "application/vnd.github+json")
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to look up the latest yt-dlp release: {}", e))?
+        .json()
+        .await
+        .map_err(|e| format!("Unreadable yt-dlp release info: {}", e))?;
+    Ok(release.tag_name)
+}
+
+// The checksum the release publishes for `asset`, as lowercase hex
+async fn expected_sha256(
+    client: &reqwest::Client,
+    tag: &str,
+    asset: &str,
+) -> Result<String, String> {
+    let sums = client
+        .get(format!("{}/{}/{}", DOWNLOAD_BASE, tag, CHECKSUMS_ASSET))
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| {
+            format!(
+                "Failed to download {} of yt-dlp {}: {}",
+                CHECKSUMS_ASSET, tag, e
+            )
+        })?
+        .text()
+        .await
+        .map_err(|e| {
+            format!(
+                "Failed to read {} of yt-dlp {}: {}",
+                CHECKSUMS_ASSET, tag, e
+            )
+        })?;
+    // Lines are "<hex digest>  <file name>"
+    sums.lines()
+        .find_map(|line| {
+            let (digest, name) = line.trim().split_once(char::is_whitespace)?;
+            (name.trim().trim_start_matches('*') == asset).then(|| digest.to_lowercase())
+        })
+        .ok_or_else(|| format!("{} of yt-dlp {} lists no {}", CHECKSUMS_ASSET, tag, asset))
+}
+
+async fn download(client: &reqwest::Client, url: &str, to: &Path) -> Result<(), String> {
+    let mut response = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+    let mut file = tokio::fs::File::create(to)
+        .await
+        .map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?
+    {
+        file.write_all(&chunk)
+            .await
+            .map_err(|e| format!("Failed to write {}: {}", to.display(), e))?;
+    }
+    file.flush()
+        .await
+        .map_err(|e| format!("Failed to write {}: {}", to.display(), e))
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+async fn managed_version() -> Option<String> {
+    let path = managed_path().filter(|path| path.is_file())?;
+    tools::probe_version(&path.to_string_lossy(), "--version").await
+}
+
+// Install the latest release as the managed copy, unless it already is. Returns the
+// version now installed.
+pub async fn install() -> Result<String, String> {
+    let _installing = INSTALL.lock().await;
+    let asset = asset_name().ok_or_else(|| {
+        "yt-dlp publishes no build for this platform; install it yourself and set ytdlp_path"
+            .to_string()
+    })?;
+    let target =
+        managed_path().ok_or_else(|| "Could not determine app data directory".to_string())?;
+    let client = client()?;
+    let tag = latest_release(&client).await?;
+    if managed_version().await.as_deref() == Some(tag.as_str()) {
+        return Ok(tag);
+    }
+    let expected = expected_sha256(&client, &tag, asset).await?;
+
+    if let Some(dir) = target.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    }
+    let partial = target.with_file_name(format!("{}.download", executable_name()));
+    let url = format!("{}/{}/{}", DOWNLOAD_BASE, tag, asset);
+    println!("Downloading yt-dlp {} from {}", tag, url);
+    let verified = async {
+        download(&client, &url, &partial).await?;
+        let path = partial.clone();
+        let (actual, _) = tokio::task::spawn_blocking(move || provenance::sha256_file(&path))
+            .await
+            .map_err(|e| format!("Checksum task failed: {}", e))?
+            .map_err(|e| format!("Failed to read {}: {}", partial.display(), e))?;
+        if actual != expected {
+            return Err(format!(
+                "Checksum mismatch for yt-dlp {} ({}): expected {}, got {}",
+                tag, asset, expected, actual
+            ));
+        }
+        make_executable(&partial)
+            .map_err(|e| format!("Failed to mark {} executable: {}", partial.display(), e))?;
+        // Windows won't replace an executable that is running
+        tokio::fs::rename(&partial, &target).await.map_err(|e| {
+            format!(
+                "Failed to replace {} (is a download running?): {}",
+                target.display(),
+                e
+            )
+        })
+    }
+    .await;
+    if let Err(e) = verified {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(e);
+    }
+
+    println!("Installed yt-dlp {} at {}", tag, target.display());
+    Ok(managed_version().await.unwrap_or(tag))
+}
+
+// What runs now, what is installed and what the latest release is
+pub async fn check() -> YtdlpVersion {
+    let (binary, source) = resolve();
+    let version = tools::probe_version(&binary, "--version").await;
+    let managed_version = managed_version().await;
+    let latest = match client() {
+        Ok(client) => latest_release(&client).await,
+        Err(e) => Err(e),
+    };
+
+    let message = if version.is_none() {
+        Some(match source {
+            SOURCE_ENV => format!("{} names '{}', which doesn't run", tools::YTDLP_ENV, binary),
+            SOURCE_SETTING => format!("Configured yt-dlp path '{}' doesn't run", binary),
+            _ => "yt-dlp not found; install it from Settings".to_string(),
+        })
+    } else if let Err(e) = &latest {
+        Some(e.clone())
+    } else if source != SOURCE_MANAGED && managed_version.is_some() {
+        Some(format!(
+            "The managed yt-dlp is installed but {} is used",
+            binary
+        ))
+    } else {
+        None
+    };
+    let latest_version = latest.ok();
+    let update_available = match (&latest_version, &managed_version) {
+        (Some(latest), Some(managed)) => latest != managed,
+        (Some(_), None) => true,
+        (None, _) => false,
+    };
+
+    YtdlpVersion {
+        binary,
+        source: source.to_string(),
+        version,
+        managed_path: managed_path().map(|path| path.to_string_lossy().to_string()),
+        managed_version,
+        latest_version,
+        update_available,
+        message,
+    }
+}
+
+// Install the managed copy when no yt-dlp runs at all
+pub async fn ensure_installed() {
+    let (binary, source) = resolve();
+    if source != SOURCE_PATH || tools::probe_version(&binary, "--version").await.is_some() {
+        return;
+    }
+    println!("yt-dlp not found; installing the managed copy");
+    if let Err(e) = install().await {
+        eprintln!("Failed to install yt-dlp: {}", e);
+    }
+}